@@ -0,0 +1,136 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{CniError, Result};
+
+// A network of a configuration directory, a .conflist or a single plugin of a
+// .conf/.json file, which is turned into a list of that plugin. Like the
+// kubelet, the files are taken in lexical order.
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NetworkConfigList {
+    pub name: String,
+    #[serde(rename = "cniVersion")]
+    pub cni_version: String,
+    // the plugin configurations as written, each with at least a "type"
+    pub plugins: Vec<Value>,
+}
+
+impl NetworkConfigList {
+    pub fn parse(path: &Path, bytes: &[u8]) -> Result<Self> {
+        let invalid = |reason: String| CniError::InvalidConfig {
+            path: path.to_path_buf(),
+            reason,
+        };
+        let value: Value = serde_json::from_slice(bytes).map_err(|e| invalid(e.to_string()))?;
+        let field = |name: &str| value.get(name).and_then(Value::as_str).map(str::to_string);
+        let name = field("name").ok_or_else(|| invalid("missing name".to_string()))?;
+        let cni_version = field("cniVersion").unwrap_or_else(|| "0.4.0".to_string());
+        let plugins = match value.get("plugins") {
+            Some(Value::Array(plugins)) => plugins.clone(),
+            Some(_) => return Err(invalid("plugins is not a list".to_string())),
+            // a single plugin
+            None => vec![value.clone()],
+        };
+        if plugins.is_empty() {
+            return Err(invalid("no plugins".to_string()));
+        }
+        if let Some(index) = plugins
+            .iter()
+            .position(|plugin| plugin.get("type").and_then(Value::as_str).is_none())
+        {
+            return Err(invalid(format!("plugin {} has no type", index)));
+        }
+        Ok(NetworkConfigList {
+            name,
+            cni_version,
+            plugins,
+        })
+    }
+
+    // the configuration handed to one plugin of the list
+    pub(crate) fn plugin_config(&self, plugin: &Value, prev_result: Option<&Value>) -> Value {
+        let mut config = plugin.clone();
+        config["name"] = Value::from(self.name.as_str());
+        config["cniVersion"] = Value::from(self.cni_version.as_str());
+        if let Some(prev_result) = prev_result {
+            config["prevResult"] = prev_result.clone();
+        }
+        config
+    }
+}
+
+fn is_config_file(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext == "conf" || ext == "conflist" || ext == "json")
+}
+
+// every network of a configuration directory, in lexical order of the files
+pub fn load_dir(dir: &Path) -> Result<Vec<NetworkConfigList>> {
+    let read = |path: &Path, source| CniError::Read {
+        path: path.to_path_buf(),
+        source,
+    };
+    let mut paths: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| is_config_file(p))
+            .collect(),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(read(dir, e)),
+    };
+    paths.sort();
+    paths
+        .iter()
+        .map(|path| {
+            let bytes = fs::read(path).map_err(|e| read(path, e))?;
+            NetworkConfigList::parse(path, &bytes)
+        })
+        .collect()
+}
+
+// the network pods are attached to, the first of the directory
+pub fn default_network(dir: &Path) -> Result<Option<NetworkConfigList>> {
+    Ok(load_dir(dir)?.into_iter().next())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("10-pods.conflist"),
+            r#"{"cniVersion": "1.0.0", "name": "pods", "plugins": [{"type": "bridge"}, {"type": "portmap"}]}"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("20-single.conf"),
+            r#"{"name": "single", "type": "macvlan"}"#,
+        )
+        .unwrap();
+        fs::write(dir.path().join("README"), "not a network").unwrap();
+
+        let networks = load_dir(dir.path()).unwrap();
+        assert_eq!(networks.len(), 2);
+        assert_eq!(networks[0].plugins.len(), 2);
+        assert_eq!(networks[1].cni_version, "0.4.0");
+        assert_eq!(default_network(dir.path()).unwrap().unwrap().name, "pods");
+
+        let config = networks[0].plugin_config(
+            &networks[0].plugins[1],
+            Some(&serde_json::json!({"ips": []})),
+        );
+        assert_eq!(config["name"], "pods");
+        assert!(config["prevResult"].is_object());
+
+        fs::write(dir.path().join("30-broken.conf"), r#"{"name": "broken"}"#).unwrap();
+        assert!(load_dir(dir.path()).is_err());
+        assert!(load_dir(&dir.path().join("missing")).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,91 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use serde_json::Value;
+
+use crate::{CniError, Result, RuntimeConf};
+
+// run one plugin with a command, returning what it printed on stdout
+pub(crate) fn invoke(
+    plugin_dirs: &[PathBuf],
+    command: &str,
+    config: &Value,
+    runtime: &RuntimeConf,
+) -> Result<Option<Value>> {
+    let plugin = config
+        .get("type")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_string();
+    let binary = plugin_dirs
+        .iter()
+        .map(|dir| dir.join(&plugin))
+        .find(|path| !plugin.contains('/') && path.is_file())
+        .ok_or_else(|| CniError::PluginNotFound {
+            plugin: plugin.clone(),
+            dirs: plugin_dirs
+                .iter()
+                .map(|dir| dir.display().to_string())
+                .collect::<Vec<_>>()
+                .join(":"),
+        })?;
+    let exec = |source| CniError::Exec {
+        plugin: plugin.clone(),
+        source,
+    };
+
+    let cni_args: Vec<String> = runtime
+        .args
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    let cni_path: Vec<String> = plugin_dirs
+        .iter()
+        .map(|dir| dir.display().to_string())
+        .collect();
+    let mut child = Command::new(&binary)
+        .env("CNI_COMMAND", command)
+        .env("CNI_CONTAINERID", &runtime.container_id)
+        .env("CNI_NETNS", &runtime.netns)
+        .env("CNI_IFNAME", &runtime.if_name)
+        .env("CNI_ARGS", cni_args.join(";"))
+        .env("CNI_PATH", cni_path.join(":"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(exec)?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(config.to_string().as_bytes())
+            .map_err(exec)?;
+    }
+    let output = child.wait_with_output().map_err(exec)?;
+
+    if !output.status.success() {
+        // plugins report errors as {"code": ..., "msg": ..., "details": ...}
+        let error: Value = serde_json::from_slice(&output.stdout).unwrap_or(Value::Null);
+        let mut message = error["msg"]
+            .as_str()
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("exited with {}", output.status));
+        if let Some(details) = error["details"]
+            .as_str()
+            .filter(|details| !details.is_empty())
+        {
+            message = format!("{}: {}", message, details);
+        }
+        return Err(CniError::Plugin {
+            plugin,
+            code: error["code"].as_u64().unwrap_or(0),
+            message,
+        });
+    }
+    if output.stdout.iter().all(u8::is_ascii_whitespace) {
+        return Ok(None);
+    }
+    serde_json::from_slice(&output.stdout)
+        .map(Some)
+        .map_err(|source| CniError::InvalidResult { plugin, source })
+}
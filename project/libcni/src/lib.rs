@@ -1,14 +1,211 @@
-pub fn add(left: u64, right: u64) -> u64 {
-    left + right
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+use serde_json::Value;
+
+// A client of the Container Network Interface: network configurations are
+// read from a directory like /etc/cni/net.d and their plugins are executed
+// from the plugin directories with the CNI_* environment, the configuration on
+// stdin and the result on stdout. ADD runs the plugins of a list in order,
+// each getting the result of the previous one, DEL runs them in reverse.
+
+mod config;
+mod exec;
+
+pub use config::{NetworkConfigList, default_network, load_dir};
+
+pub const DEFAULT_CONF_DIR: &str = "/etc/cni/net.d";
+pub const DEFAULT_PLUGIN_DIR: &str = "/opt/cni/bin";
+
+#[derive(Debug, thiserror::Error)]
+pub enum CniError {
+    #[error("failed to read {path}: {source}")]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("invalid network configuration {path}: {reason}")]
+    InvalidConfig { path: PathBuf, reason: String },
+    #[error("plugin {plugin} not found in {dirs}")]
+    PluginNotFound { plugin: String, dirs: String },
+    #[error("failed to execute plugin {plugin}: {source}")]
+    Exec {
+        plugin: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("plugin {plugin} failed: {message}")]
+    Plugin {
+        plugin: String,
+        code: u64,
+        message: String,
+    },
+    #[error("invalid result of plugin {plugin}: {source}")]
+    InvalidResult {
+        plugin: String,
+        #[source]
+        source: serde_json::Error,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, CniError>;
+
+// what the plugins are told about the container
+#[derive(Debug, Clone, Default)]
+pub struct RuntimeConf {
+    pub container_id: String,
+    // the network namespace, e.g. /proc/<pid>/ns/net
+    pub netns: PathBuf,
+    pub if_name: String,
+    // CNI_ARGS, e.g. K8S_POD_NAME
+    pub args: Vec<(String, String)>,
+}
+
+// the result of ADD, kept as written for the prevResult of DEL
+#[derive(Debug, Clone, PartialEq)]
+pub struct CniResult(pub Value);
+
+impl CniResult {
+    // the assigned addresses, of the ips of 0.3.0 and later or the ip4 and ip6 of older versions
+    pub fn ips(&self) -> Vec<IpAddr> {
+        let address = |value: &Value| {
+            value
+                .as_str()
+                .and_then(|cidr| cidr.split('/').next())
+                .and_then(|ip| ip.parse::<IpAddr>().ok())
+        };
+        if let Some(ips) = self.0.get("ips").and_then(Value::as_array) {
+            return ips
+                .iter()
+                .filter_map(|ip| ip.get("address").and_then(address))
+                .collect();
+        }
+        ["ip4", "ip6"]
+            .iter()
+            .filter_map(|key| {
+                self.0
+                    .get(*key)
+                    .and_then(|ip| ip.get("ip"))
+                    .and_then(address)
+            })
+            .collect()
+    }
+}
+
+pub struct CniConfig {
+    pub plugin_dirs: Vec<PathBuf>,
+}
+
+impl CniConfig {
+    pub fn new(plugin_dirs: Vec<PathBuf>) -> Self {
+        CniConfig { plugin_dirs }
+    }
+
+    // attach the container to the network. When a plugin fails the ones that
+    // ran before are not undone, that is up to a DEL of the caller
+    pub fn add_network_list(
+        &self,
+        network: &NetworkConfigList,
+        runtime: &RuntimeConf,
+    ) -> Result<CniResult> {
+        let mut result: Option<Value> = None;
+        for plugin in &network.plugins {
+            let config = network.plugin_config(plugin, result.as_ref());
+            if let Some(output) = exec::invoke(&self.plugin_dirs, "ADD", &config, runtime)? {
+                result = Some(output);
+            }
+        }
+        Ok(CniResult(
+            result.unwrap_or_else(|| Value::Object(Default::default())),
+        ))
+    }
+
+    // detach the container, with the result of its ADD when there was one
+    pub fn del_network_list(
+        &self,
+        network: &NetworkConfigList,
+        runtime: &RuntimeConf,
+        prev_result: Option<&CniResult>,
+    ) -> Result<()> {
+        for plugin in network.plugins.iter().rev() {
+            let config = network.plugin_config(plugin, prev_result.map(|result| &result.0));
+            exec::invoke(&self.plugin_dirs, "DEL", &config, runtime)?;
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
 
     #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+    fn test_add_and_del() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("calls");
+        // a plugin appending its command to the log and replying with an IP,
+        // or with the IP of the previous result
+        let script = format!(
+            "#!/bin/sh\nread config\necho \"$CNI_COMMAND $CNI_IFNAME $CNI_ARGS\" >> {}\n\
+             [ \"$CNI_COMMAND\" = ADD ] && echo '{{\"cniVersion\": \"1.0.0\", \"ips\": [{{\"address\": \"10.88.0.5/16\"}}]}}'\n\
+             exit 0\n",
+            log.display()
+        );
+        let plugin = dir.path().join("fake");
+        fs::write(&plugin, script).unwrap();
+        fs::set_permissions(&plugin, fs::Permissions::from_mode(0o755)).unwrap();
+        let failing = dir.path().join("failing");
+        fs::write(
+            &failing,
+            "#!/bin/sh\necho '{\"code\": 7, \"msg\": \"no addresses left\"}'\nexit 1\n",
+        )
+        .unwrap();
+        fs::set_permissions(&failing, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let network = NetworkConfigList::parse(
+            &dir.path().join("net.conflist"),
+            br#"{"cniVersion": "1.0.0", "name": "pods", "plugins": [{"type": "fake"}, {"type": "fake"}]}"#,
+        )
+        .unwrap();
+        let runtime = RuntimeConf {
+            container_id: "sandbox".to_string(),
+            netns: PathBuf::from("/proc/1/ns/net"),
+            if_name: "eth0".to_string(),
+            args: vec![("K8S_POD_NAME".to_string(), "web".to_string())],
+        };
+        let cni = CniConfig::new(vec![dir.path().to_path_buf()]);
+        let result = cni.add_network_list(&network, &runtime).unwrap();
+        assert_eq!(result.ips(), vec!["10.88.0.5".parse::<IpAddr>().unwrap()]);
+        cni.del_network_list(&network, &runtime, Some(&result))
+            .unwrap();
+        let calls = fs::read_to_string(&log).unwrap();
+        assert_eq!(
+            calls.lines().collect::<Vec<_>>(),
+            vec!["ADD eth0 K8S_POD_NAME=web"; 2]
+                .into_iter()
+                .chain(vec!["DEL eth0 K8S_POD_NAME=web"; 2])
+                .collect::<Vec<_>>()
+        );
+
+        let network = NetworkConfigList {
+            plugins: vec![serde_json::json!({"type": "failing"})],
+            ..network
+        };
+        let err = cni.add_network_list(&network, &runtime).unwrap_err();
+        assert!(matches!(err, CniError::Plugin { code: 7, .. }), "{}", err);
+        let network = NetworkConfigList {
+            plugins: vec![serde_json::json!({"type": "missing"})],
+            ..network
+        };
+        assert!(matches!(
+            cni.add_network_list(&network, &runtime),
+            Err(CniError::PluginNotFound { .. })
+        ));
+
+        let legacy = CniResult(serde_json::json!({"ip4": {"ip": "10.1.0.2/24"}}));
+        assert_eq!(legacy.ips(), vec!["10.1.0.2".parse::<IpAddr>().unwrap()]);
     }
 }
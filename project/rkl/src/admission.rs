@@ -0,0 +1,479 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use crate::describe;
+use crate::image::{self, ImageReference, auth, pull};
+use crate::rootpath;
+use crate::task::task::{ContainerSpec, EnvVar, PodTask, PullPolicy, Toleration};
+use crate::webhook::{Webhook, WebhookConfig};
+
+// Admission runs on every pod manifest before it is used. It applies the
+// namespace defaults of the platform configuration, e.g.
+//
+//   namespaces:
+//     team-a:
+//       runtimeClassName: gvisor
+//       imagePullPolicy: Always
+//       enforce: true
+//       tolerations:
+//         - key: dedicated
+//           value: team-a
+//       env:
+//         - name: HTTP_PROXY
+//           value: http://proxy.internal:3128
+//
+// Defaults only fill what the manifest leaves unset; with enforce the runtime
+// class and the pull policy replace the ones of the manifest too. Tolerations
+// and env vars are added unless the pod already has them.
+//
+// With --pin-image-digests the tags of the images are then resolved to the
+// digests they point to, nginx:1.27 becoming nginx:1.27@sha256:<digest>, so
+// that the pod runs the same image on every node and after every restart even
+// when the tag moves. The pinned images are recorded with the pod and shown by
+// `rkl describe pod`; a pod admitted again, e.g. when the daemon restarts one
+// of its containers, keeps the digests recorded.
+//
+// These are plugins of the admission pipeline, every plugin an
+// AdmissionPlugin that may change the pod (mutate) and refuse it (validate).
+// The plugins all mutate the pod in turn before any of them validates it, so
+// the pod is checked as it will run:
+//   NamespaceDefaults  the namespace defaults
+//   Sidecars           containers added to the pods of some namespaces or with
+//                      some labels, unless a container has the same name
+//   <mutating webhooks>  see webhook
+//   DigestPinning      with --pin-image-digests
+//   ImageRegistries    the images come from the allowedRegistries, a registry
+//                      or a registry and repository prefix
+//   <validating webhooks>
+// The plugins are configured by --admission-config, e.g.
+//
+//   allowedRegistries:
+//     - registry.internal
+//     - docker.io/library
+//   sidecars:
+//     - namespaces: [team-a]
+//       selector:
+//         app: web
+//       containers:
+//         - name: log-shipper
+//           image: registry.internal/fluent-bit:3
+//   webhooks:
+//     - name: policy.internal
+//       url: https://policy.internal/validate
+//       failurePolicy: Ignore
+//
+// A refused pod is never run, the error names the plugin refusing it.
+
+pub const DEFAULT_CONFIG_PATH: &str = "/etc/rk8s/namespace-defaults.yaml";
+pub const DEFAULT_PLUGIN_CONFIG_PATH: &str = "/etc/rk8s/admission.yaml";
+
+static CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+static PLUGIN_CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+static PIN_DIGESTS: OnceLock<bool> = OnceLock::new();
+
+// set once at startup from --namespace-defaults, --admission-config and --pin-image-digests
+pub fn init(config_path: PathBuf, plugin_config_path: PathBuf, pin_digests: bool) {
+    let _ = CONFIG_PATH.set(config_path);
+    let _ = PLUGIN_CONFIG_PATH.set(plugin_config_path);
+    let _ = PIN_DIGESTS.set(pin_digests);
+}
+
+pub trait AdmissionPlugin {
+    fn name(&self) -> &str;
+
+    fn mutate(&self, _task: &mut PodTask) -> Result<()> {
+        Ok(())
+    }
+
+    // an error refuses the pod
+    fn validate(&self, _task: &PodTask) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AdmissionConfig {
+    #[serde(default)]
+    pub namespaces: HashMap<String, NamespaceDefaults>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct NamespaceDefaults {
+    #[serde(rename = "runtimeClassName", default)]
+    pub runtime_class_name: Option<String>,
+    #[serde(rename = "imagePullPolicy", default)]
+    pub image_pull_policy: Option<PullPolicy>,
+    #[serde(default)]
+    pub enforce: bool,
+    #[serde(default)]
+    pub tolerations: Vec<Toleration>,
+    #[serde(default)]
+    pub env: Vec<EnvVar>,
+}
+
+impl AdmissionConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(AdmissionConfig::default());
+        }
+        let contents = fs::read_to_string(path)?;
+        serde_yaml::from_str(&contents).map_err(|e| anyhow!("Invalid namespace defaults {}: {}", path.display(), e))
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PluginConfig {
+    // any registry when empty
+    #[serde(default)]
+    pub allowed_registries: Vec<String>,
+    #[serde(default)]
+    pub sidecars: Vec<SidecarConfig>,
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SidecarConfig {
+    // every namespace when empty
+    #[serde(default)]
+    pub namespaces: Vec<String>,
+    // labels the pod has to have
+    #[serde(default)]
+    pub selector: HashMap<String, String>,
+    // container specs, parsed for every pod they are added to
+    pub containers: Vec<serde_yaml::Value>,
+}
+
+impl PluginConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(PluginConfig::default());
+        }
+        let contents = fs::read_to_string(path)?;
+        serde_yaml::from_str(&contents).map_err(|e| anyhow!("Invalid admission config {}: {}", path.display(), e))
+    }
+}
+
+struct NamespaceDefaulting(AdmissionConfig);
+
+impl AdmissionPlugin for NamespaceDefaulting {
+    fn name(&self) -> &str {
+        "NamespaceDefaults"
+    }
+
+    fn mutate(&self, task: &mut PodTask) -> Result<()> {
+        apply_namespace_defaults(task, &self.0);
+        Ok(())
+    }
+}
+
+struct Sidecars(Vec<SidecarConfig>);
+
+impl AdmissionPlugin for Sidecars {
+    fn name(&self) -> &str {
+        "Sidecars"
+    }
+
+    fn mutate(&self, task: &mut PodTask) -> Result<()> {
+        for sidecar in &self.0 {
+            let namespace = &task.metadata.namespace;
+            if !sidecar.namespaces.is_empty() && !sidecar.namespaces.contains(namespace) {
+                continue;
+            }
+            if sidecar.selector.iter().any(|(key, value)| task.metadata.labels.get(key) != Some(value)) {
+                continue;
+            }
+            for container in &sidecar.containers {
+                let container: ContainerSpec =
+                    serde_yaml::from_value(container.clone()).map_err(|e| anyhow!("invalid sidecar container: {}", e))?;
+                if !task.spec.containers.iter().any(|c| c.name == container.name) {
+                    task.spec.containers.push(container);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+struct DigestPinning;
+
+impl AdmissionPlugin for DigestPinning {
+    fn name(&self) -> &str {
+        "DigestPinning"
+    }
+
+    fn mutate(&self, task: &mut PodTask) -> Result<()> {
+        let root_path = rootpath::determine(None)?;
+        let pull_secrets: Vec<String> = task.spec.image_pull_secrets.iter().map(|s| s.name.clone()).collect();
+        let recorded = describe::load_spec(&root_path, &task.metadata.name);
+        pin_digests(task, recorded.as_ref(), |reference| {
+            let auth = auth::resolve(&root_path, &pull_secrets, &reference.registry)?;
+            pull::resolve_digest(reference, auth.as_ref())
+        })
+    }
+}
+
+struct ImageRegistries(Vec<String>);
+
+impl AdmissionPlugin for ImageRegistries {
+    fn name(&self) -> &str {
+        "ImageRegistries"
+    }
+
+    fn validate(&self, task: &PodTask) -> Result<()> {
+        if self.0.is_empty() {
+            return Ok(());
+        }
+        for container in task.spec.init_containers.iter().chain(&task.spec.containers) {
+            if image::is_bundle_path(&container.image) {
+                continue;
+            }
+            let reference = ImageReference::parse(&container.image)?;
+            let name = format!("{}/{}", reference.registry, reference.repository);
+            let allowed = self.0.iter().map(|allowed| allowed.trim_end_matches('/')).any(|allowed| {
+                name == allowed || name.starts_with(&format!("{}/", allowed))
+            });
+            if !allowed {
+                return Err(anyhow!(
+                    "image {} of container {} is not from an allowed registry: {}",
+                    container.image,
+                    container.name,
+                    self.0.join(", ")
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct Pipeline {
+    plugins: Vec<Box<dyn AdmissionPlugin>>,
+}
+
+impl Pipeline {
+    pub fn new(defaults: AdmissionConfig, config: PluginConfig, pin_digests: bool) -> Self {
+        let mut plugins: Vec<Box<dyn AdmissionPlugin>> = vec![Box::new(NamespaceDefaulting(defaults)), Box::new(Sidecars(config.sidecars))];
+        let (mutating, validating): (Vec<WebhookConfig>, Vec<WebhookConfig>) = config.webhooks.into_iter().partition(|webhook| webhook.mutating);
+        plugins.extend(mutating.into_iter().map(|webhook| Box::new(Webhook(webhook)) as Box<dyn AdmissionPlugin>));
+        if pin_digests {
+            plugins.push(Box::new(DigestPinning));
+        }
+        plugins.push(Box::new(ImageRegistries(config.allowed_registries)));
+        plugins.extend(validating.into_iter().map(|webhook| Box::new(Webhook(webhook)) as Box<dyn AdmissionPlugin>));
+        Pipeline { plugins }
+    }
+
+    pub fn admit(&self, task: &mut PodTask) -> Result<()> {
+        for plugin in &self.plugins {
+            plugin.mutate(task).map_err(|e| anyhow!("pod {} refused by admission plugin {}: {:#}", task.metadata.name, plugin.name(), e))?;
+        }
+        for plugin in &self.plugins {
+            plugin.validate(task).map_err(|e| anyhow!("pod {} refused by admission plugin {}: {:#}", task.metadata.name, plugin.name(), e))?;
+        }
+        Ok(())
+    }
+}
+
+// admit a pod with the configuration given at startup
+pub fn admit(task: &mut PodTask) -> Result<()> {
+    let path = CONFIG_PATH.get().map(PathBuf::as_path).unwrap_or(Path::new(DEFAULT_CONFIG_PATH));
+    let plugin_path = PLUGIN_CONFIG_PATH.get().map(PathBuf::as_path).unwrap_or(Path::new(DEFAULT_PLUGIN_CONFIG_PATH));
+    let pipeline = Pipeline::new(AdmissionConfig::load(path)?, PluginConfig::load(plugin_path)?, PIN_DIGESTS.get().copied().unwrap_or_default());
+    pipeline.admit(task)
+}
+
+// pin the images of the containers without a digest to the one recorded for
+// the same image of the pod, or else to the one resolve finds
+pub fn pin_digests(task: &mut PodTask, recorded: Option<&PodTask>, resolve: impl Fn(&ImageReference) -> Result<String>) -> Result<()> {
+    let recorded: Vec<ImageReference> = recorded
+        .into_iter()
+        .flat_map(|recorded| recorded.spec.init_containers.iter().chain(&recorded.spec.containers))
+        .filter_map(|container| ImageReference::parse(&container.image).ok())
+        .filter(|reference| reference.digest.is_some())
+        .collect();
+    let spec = &mut task.spec;
+    for container in spec.init_containers.iter_mut().chain(spec.containers.iter_mut()) {
+        if image::is_bundle_path(&container.image) {
+            continue;
+        }
+        let reference = ImageReference::parse(&container.image)?;
+        if reference.digest.is_some() {
+            continue;
+        }
+        let pinned = recorded.iter().find(|pinned| ImageReference { digest: None, ..(*pinned).clone() } == reference);
+        let digest = match pinned.and_then(|pinned| pinned.digest.clone()) {
+            Some(digest) => digest,
+            None => resolve(&reference).map_err(|e| anyhow!("Failed to pin the image {} of container {}: {:#}", container.image, container.name, e))?,
+        };
+        container.image = format!("{}@{}", container.image, digest);
+    }
+    Ok(())
+}
+
+pub fn apply_namespace_defaults(task: &mut PodTask, config: &AdmissionConfig) {
+    let Some(defaults) = config.namespaces.get(&task.metadata.namespace) else {
+        return;
+    };
+    let spec = &mut task.spec;
+
+    if defaults.runtime_class_name.is_some() && (defaults.enforce || spec.runtime_class_name.is_none()) {
+        spec.runtime_class_name = defaults.runtime_class_name.clone();
+    }
+    for toleration in &defaults.tolerations {
+        if !spec.tolerations.contains(toleration) {
+            spec.tolerations.push(toleration.clone());
+        }
+    }
+
+    for container in spec.init_containers.iter_mut().chain(spec.containers.iter_mut()) {
+        if defaults.image_pull_policy.is_some() && (defaults.enforce || container.image_pull_policy.is_none()) {
+            container.image_pull_policy = defaults.image_pull_policy;
+        }
+        for env in &defaults.env {
+            if !container.env.iter().any(|e| e.name == env.name) {
+                container.env.push(env.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POD: &str = r#"
+apiVersion: v1
+kind: Pod
+metadata:
+  name: web
+  namespace: team-a
+spec:
+  runtimeClassName: runc
+  containers:
+    - name: app
+      image: app:v1
+      env:
+        - name: HTTP_PROXY
+          value: http://own.proxy
+"#;
+
+    #[test]
+    fn test_namespace_defaults() {
+        let config: AdmissionConfig = serde_yaml::from_str(
+            r#"
+namespaces:
+  team-a:
+    runtimeClassName: gvisor
+    imagePullPolicy: Always
+    tolerations:
+      - key: dedicated
+        value: team-a
+    env:
+      - name: HTTP_PROXY
+        value: http://proxy.internal
+      - name: TEAM
+        value: a
+"#,
+        )
+        .unwrap();
+
+        let mut task: PodTask = serde_yaml::from_str(POD).unwrap();
+        apply_namespace_defaults(&mut task, &config);
+        // the manifest keeps its runtime class, the pull policy was unset
+        assert_eq!(task.spec.runtime_class_name.as_deref(), Some("runc"));
+        assert_eq!(task.spec.tolerations.len(), 1);
+        let container = &task.spec.containers[0];
+        assert_eq!(container.image_pull_policy, Some(PullPolicy::Always));
+        assert_eq!(container.env[0].value, "http://own.proxy");
+        assert_eq!(container.env[1].name, "TEAM");
+
+        // applying twice changes nothing
+        apply_namespace_defaults(&mut task, &config);
+        assert_eq!(task.spec.tolerations.len(), 1);
+        assert_eq!(task.spec.containers[0].env.len(), 2);
+    }
+
+    #[test]
+    fn test_enforced_defaults() {
+        let mut config = AdmissionConfig::default();
+        config.namespaces.insert(
+            "team-a".to_string(),
+            NamespaceDefaults {
+                runtime_class_name: Some("gvisor".to_string()),
+                enforce: true,
+                ..Default::default()
+            },
+        );
+        let mut task: PodTask = serde_yaml::from_str(POD).unwrap();
+        apply_namespace_defaults(&mut task, &config);
+        assert_eq!(task.spec.runtime_class_name.as_deref(), Some("gvisor"));
+
+        task.metadata.namespace = "team-b".to_string();
+        task.spec.runtime_class_name = None;
+        apply_namespace_defaults(&mut task, &config);
+        assert_eq!(task.spec.runtime_class_name, None);
+    }
+
+    #[test]
+    fn test_pin_digests() {
+        let digest = "sha256:0123456789abcdef";
+        let mut task: PodTask = serde_yaml::from_str(POD).unwrap();
+        pin_digests(&mut task, None, |reference| {
+            assert_eq!(reference.to_string(), "docker.io/library/app:v1");
+            Ok(digest.to_string())
+        })
+        .unwrap();
+        assert_eq!(task.spec.containers[0].image, "app:v1@sha256:0123456789abcdef");
+
+        // pinned images and the digests recorded for the pod are kept
+        let recorded: PodTask = serde_yaml::from_str(&serde_yaml::to_string(&task).unwrap()).unwrap();
+        pin_digests(&mut task, None, |_| Err(anyhow!("resolved again"))).unwrap();
+        let mut admitted_again: PodTask = serde_yaml::from_str(POD).unwrap();
+        pin_digests(&mut admitted_again, Some(&recorded), |_| Err(anyhow!("resolved again"))).unwrap();
+        assert_eq!(admitted_again.spec.containers[0].image, task.spec.containers[0].image);
+
+        let mut unreachable: PodTask = serde_yaml::from_str(POD).unwrap();
+        let e = pin_digests(&mut unreachable, None, |_| Err(anyhow!("registry unreachable"))).unwrap_err();
+        assert_eq!(e.to_string(), "Failed to pin the image app:v1 of container app: registry unreachable");
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let config: PluginConfig = serde_yaml::from_str(
+            r#"
+allowedRegistries:
+  - docker.io/library
+  - registry.internal/
+sidecars:
+  - namespaces: [team-a]
+    containers:
+      - name: log-shipper
+        image: registry.internal/fluent-bit:3
+  - namespaces: [team-b]
+    containers:
+      - name: proxy
+        image: envoy:v1
+"#,
+        )
+        .unwrap();
+        let pipeline = Pipeline::new(AdmissionConfig::default(), config, false);
+        let mut task: PodTask = serde_yaml::from_str(POD).unwrap();
+        pipeline.admit(&mut task).unwrap();
+        let names: Vec<&str> = task.spec.containers.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["app", "log-shipper"]);
+        // admitted again the sidecar isn't added twice
+        pipeline.admit(&mut task).unwrap();
+        assert_eq!(task.spec.containers.len(), 2);
+
+        task.spec.containers[0].image = "quay.io/org/app:v1".to_string();
+        let e = pipeline.admit(&mut task).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "pod web refused by admission plugin ImageRegistries: image quay.io/org/app:v1 of container app is not from an allowed registry: docker.io/library, registry.internal/"
+        );
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+use libcontainer::oci_spec::runtime::Spec;
+use serde_json::json;
+use crate::cli_commands::PodInfo;
+use crate::commands::load_container;
+use crate::rootpath;
+use crate::selector::Selector;
+use tracing::warn;
+
+// `rkl adopt` takes over the pods another CRI client (containerd, CRI-O) runs
+// in the same root directory: their sandboxes and containers are found through
+// the CRI annotations of their OCI specs, and every pod matching the selector is
+// recorded like a pod of rkl and written as a manifest of the daemon's control
+// API directory. The daemon then adopts the running containers as they are and
+// restarts them the way it restarts its own, from their bundle with the process
+// args and env they were created with.
+
+pub const MANAGED_BY: &str = "app.kubernetes.io/managed-by";
+// the CRI client the pod was adopted from
+pub const ADOPTED_FROM: &str = "rk8s.io/adopted-from";
+
+// where each runtime keeps the CRI metadata in the annotations
+struct CriKeys {
+    runtime: &'static str,
+    container_type: &'static str,
+    sandbox_id: &'static str,
+    pod_name: &'static str,
+    pod_namespace: &'static str,
+    // the pod labels as a JSON object, when the runtime records them
+    labels: Option<&'static str>,
+}
+
+const CRI_KEYS: [CriKeys; 2] = [
+    CriKeys {
+        runtime: "containerd",
+        container_type: "io.kubernetes.cri.container-type",
+        sandbox_id: "io.kubernetes.cri.sandbox-id",
+        pod_name: "io.kubernetes.cri.sandbox-name",
+        pod_namespace: "io.kubernetes.cri.sandbox-namespace",
+        labels: None,
+    },
+    CriKeys {
+        runtime: "cri-o",
+        container_type: "io.kubernetes.cri-o.ContainerType",
+        sandbox_id: "io.kubernetes.cri-o.SandboxID",
+        pod_name: "io.kubernetes.pod.name",
+        pod_namespace: "io.kubernetes.pod.namespace",
+        labels: Some("io.kubernetes.cri-o.Labels"),
+    },
+];
+
+// a container of the root directory that no pod of rkl owns
+#[derive(Debug, Clone)]
+struct Found {
+    id: String,
+    bundle: PathBuf,
+    annotations: HashMap<String, String>,
+    args: Vec<String>,
+    env: Vec<String>,
+}
+
+// a pod of another CRI client
+#[derive(Debug)]
+struct Candidate {
+    name: String,
+    namespace: String,
+    runtime: &'static str,
+    sandbox: Found,
+    labels: BTreeMap<String, String>,
+    containers: Vec<Found>,
+}
+
+fn cri_keys(annotations: &HashMap<String, String>) -> Option<&'static CriKeys> {
+    CRI_KEYS.iter().find(|keys| annotations.contains_key(keys.container_type))
+}
+
+// group the sandboxes and containers by pod, containers whose sandbox isn't
+// there are left alone
+fn candidates(found: Vec<Found>) -> Vec<Candidate> {
+    let mut pods: BTreeMap<String, Candidate> = BTreeMap::new();
+    let mut containers = Vec::new();
+    for container in found {
+        let Some(keys) = cri_keys(&container.annotations) else {
+            continue;
+        };
+        if container.annotations[keys.container_type] != "sandbox" {
+            containers.push((keys, container));
+            continue;
+        }
+        let annotation = |key: &str| container.annotations.get(key).cloned();
+        let Some(name) = annotation(keys.pod_name) else {
+            continue;
+        };
+        // the labels of the pod when the runtime has them, its annotations otherwise
+        let labels = keys
+            .labels
+            .and_then(|key| container.annotations.get(key))
+            .and_then(|labels| serde_json::from_str(labels).ok())
+            .unwrap_or_else(|| container.annotations.iter().map(|(k, v)| (k.clone(), v.clone())).collect());
+        let candidate = Candidate {
+            name,
+            namespace: annotation(keys.pod_namespace).unwrap_or_else(|| "default".to_string()),
+            runtime: keys.runtime,
+            sandbox: container.clone(),
+            labels,
+            containers: Vec::new(),
+        };
+        pods.insert(container.id.clone(), candidate);
+    }
+    for (keys, container) in containers {
+        if let Some(pod) = container.annotations.get(keys.sandbox_id).and_then(|id| pods.get_mut(id)) {
+            pod.containers.push(container);
+        }
+    }
+    pods.into_values().filter(|pod| !pod.containers.is_empty()).collect()
+}
+
+// the manifest of an adopted pod; the containers keep their ids as names since
+// container names are global to rkl
+fn manifest(candidate: &Candidate) -> Result<String> {
+    let mut labels: BTreeMap<String, String> = candidate.labels
+        .iter()
+        .filter(|(key, _)| !key.starts_with("io.kubernetes."))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    labels.insert(MANAGED_BY.to_string(), "rkl".to_string());
+    labels.insert("bundle".to_string(), candidate.sandbox.bundle.display().to_string());
+    let containers: Vec<serde_json::Value> = candidate.containers
+        .iter()
+        .map(|container| {
+            let env: Vec<serde_json::Value> = container.env
+                .iter()
+                .filter_map(|var| var.split_once('='))
+                .map(|(name, value)| json!({ "name": name, "value": value }))
+                .collect();
+            json!({
+                "name": container.id,
+                "image": container.bundle.display().to_string(),
+                "args": container.args,
+                "env": env,
+            })
+        })
+        .collect();
+    let pod = json!({
+        "apiVersion": "v1",
+        "kind": "Pod",
+        "metadata": {
+            "name": candidate.name,
+            "namespace": candidate.namespace,
+            "labels": labels,
+            "annotations": { ADOPTED_FROM: candidate.runtime },
+        },
+        "spec": {
+            "restartPolicy": "Always",
+            "containers": containers,
+        },
+    });
+    Ok(serde_yaml::to_string(&pod)?)
+}
+
+// the sandboxes and containers the pods of rkl are made of
+fn owned_containers(root_path: &Path) -> Result<HashSet<String>> {
+    let mut owned = HashSet::new();
+    let pods_dir = root_path.join("pods");
+    if !pods_dir.exists() {
+        return Ok(owned);
+    }
+    for entry in fs::read_dir(&pods_dir)? {
+        let name = entry?.file_name().to_string_lossy().to_string();
+        if let Ok(pod_info) = PodInfo::load(root_path, &name) {
+            owned.insert(pod_info.pod_sandbox_id);
+            owned.extend(pod_info.container_names);
+        }
+    }
+    Ok(owned)
+}
+
+fn discover(root_path: &Path) -> Result<Vec<Found>> {
+    let owned = owned_containers(root_path)?;
+    let mut found = Vec::new();
+    for entry in fs::read_dir(root_path)? {
+        let path = entry?.path();
+        let id = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        if owned.contains(&id) || !path.join("state.json").exists() {
+            continue;
+        }
+        let Ok(container) = load_container(root_path, &id) else {
+            continue;
+        };
+        let bundle = container.bundle().to_path_buf();
+        let Ok(spec) = Spec::load(bundle.join("config.json")) else {
+            continue;
+        };
+        let process = spec.process().as_ref();
+        found.push(Found {
+            id,
+            bundle,
+            annotations: spec.annotations().clone().unwrap_or_default(),
+            args: process.and_then(|p| p.args().clone()).unwrap_or_default(),
+            env: process.and_then(|p| p.env().clone()).unwrap_or_default(),
+        });
+    }
+    Ok(found)
+}
+
+// `rkl adopt`: hand the pods of other CRI clients matching the selector to the daemon
+pub fn adopt(selector: &Selector, dry_run: bool) -> Result<()> {
+    let root_path = rootpath::determine(None)?;
+    let manifest_dir = root_path.join("daemon").join("manifests");
+    let candidates: Vec<Candidate> = candidates(discover(&root_path)?)
+        .into_iter()
+        .filter(|pod| selector.matches(&pod.labels.clone().into_iter().collect()))
+        .collect();
+    if candidates.is_empty() {
+        println!("No pods of other CRI clients found");
+        return Ok(());
+    }
+    for candidate in candidates {
+        let containers: Vec<&str> = candidate.containers.iter().map(|c| c.id.as_str()).collect();
+        let description = format!("Pod {} of {} ({})", candidate.name, candidate.runtime, containers.join(", "));
+        if PodInfo::load(&root_path, &candidate.name).is_ok() {
+            warn!("Skipping {}: a Pod with that name already exists", description);
+            continue;
+        }
+        if dry_run {
+            println!("Would adopt {}", description);
+            continue;
+        }
+        let contents = manifest(&candidate)?;
+        let pod_info = PodInfo {
+            pod_sandbox_id: candidate.sandbox.id.clone(),
+            container_names: candidate.containers.iter().map(|c| c.id.clone()).collect(),
+            container_statuses: Vec::new(),
+        };
+        pod_info.save(&root_path, &candidate.name)?;
+        fs::create_dir_all(&manifest_dir)?;
+        fs::write(manifest_dir.join(format!("{}.yaml", candidate.name)), contents)?;
+        println!("Adopted {}", description);
+    }
+    println!("`rkl daemon` keeps the adopted pods running from {}", manifest_dir.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn found(id: &str, annotations: &[(&str, &str)]) -> Found {
+        Found {
+            id: id.to_string(),
+            bundle: PathBuf::from(format!("/bundles/{}", id)),
+            annotations: annotations.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+            args: vec!["nginx".to_string()],
+            env: vec!["PORT=80".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_candidates() {
+        let containers = vec![
+            found("abc", &[
+                ("io.kubernetes.cri-o.ContainerType", "sandbox"),
+                ("io.kubernetes.pod.name", "web"),
+                ("io.kubernetes.cri-o.Labels", r#"{"app":"web"}"#),
+            ]),
+            found("def", &[("io.kubernetes.cri-o.ContainerType", "container"), ("io.kubernetes.cri-o.SandboxID", "abc")]),
+            // no sandbox, and not a CRI container at all
+            found("ghi", &[("io.kubernetes.cri.container-type", "container"), ("io.kubernetes.cri.sandbox-id", "xyz")]),
+            found("jkl", &[]),
+        ];
+        let candidates = candidates(containers);
+        assert_eq!(candidates.len(), 1);
+        let pod = &candidates[0];
+        assert_eq!((pod.name.as_str(), pod.runtime, pod.containers.len()), ("web", "cri-o", 1));
+        let selector = Selector::parse("app=web").unwrap();
+        assert!(selector.matches(&pod.labels.clone().into_iter().collect()));
+
+        let manifest: serde_yaml::Value = serde_yaml::from_str(&manifest(pod).unwrap()).unwrap();
+        assert_eq!(manifest["metadata"]["labels"][MANAGED_BY].as_str(), Some("rkl"));
+        assert_eq!(manifest["metadata"]["labels"]["bundle"].as_str(), Some("/bundles/abc"));
+        assert_eq!(manifest["spec"]["containers"][0]["name"].as_str(), Some("def"));
+        assert_eq!(manifest["spec"]["containers"][0]["env"][0]["value"].as_str(), Some("80"));
+    }
+}
@@ -0,0 +1,295 @@
+use anyhow::{Result, anyhow};
+use serde_json::{Map, Value};
+
+// Declarative apply, like kubectl's client-side apply: the configuration a
+// pod was applied with is kept in its last-applied-configuration annotation
+// and `rkl apply -f` merges three ways, the new configuration over the live
+// manifest where fields that were in the last configuration but are no longer
+// are removed and fields the configuration never had, set by someone else,
+// are kept. Lists whose elements all have a name (containers, env, volumes,
+// ports...) are merged element by element, other lists are replaced. The
+// merged manifest goes to the node, which updates the pod in place when only
+// its labels or annotations changed, recreates the containers whose spec
+// changed when nothing else did and recreates the pod otherwise. `rkl diff -f`
+// shows what apply would change.
+
+pub const LAST_APPLIED: &str = "kubectl.kubernetes.io/last-applied-configuration";
+// lines of context around the changes of a diff
+const CONTEXT: usize = 3;
+
+fn parse(manifest: &str) -> Result<Value> {
+    let value: Value = serde_yaml::from_str(manifest)?;
+    if !value.is_object() {
+        return Err(anyhow!("expected a manifest object"));
+    }
+    Ok(value)
+}
+
+fn to_yaml(value: &Value) -> Result<String> {
+    Ok(serde_yaml::to_string(value)?)
+}
+
+fn annotations(value: &mut Value) -> Option<&mut Map<String, Value>> {
+    value.get_mut("metadata")?.get_mut("annotations")?.as_object_mut()
+}
+
+// the configuration of the manifest with its last-applied-configuration
+// annotation set to it
+fn configuration(manifest: &str) -> Result<Value> {
+    let mut configuration = parse(manifest)?;
+    if let Some(annotations) = annotations(&mut configuration) {
+        annotations.remove(LAST_APPLIED);
+        if annotations.is_empty()
+            && let Some(metadata) = configuration.get_mut("metadata").and_then(Value::as_object_mut)
+        {
+            metadata.remove("annotations");
+        }
+    }
+    let applied = Value::String(serde_json::to_string(&configuration)?);
+    let metadata = configuration
+        .as_object_mut()
+        .and_then(|fields| fields.entry("metadata").or_insert_with(|| Value::Object(Map::new())).as_object_mut())
+        .ok_or_else(|| anyhow!("metadata must be a map"))?;
+    let annotations = metadata
+        .entry("annotations")
+        .or_insert_with(|| Value::Object(Map::new()))
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("metadata.annotations must be a map"))?;
+    annotations.insert(LAST_APPLIED.to_string(), applied);
+    Ok(configuration)
+}
+
+fn last_applied(live: &Value) -> Option<Value> {
+    let applied = live.get("metadata")?.get("annotations")?.get(LAST_APPLIED)?.as_str()?;
+    serde_json::from_str(applied).ok()
+}
+
+// whether every element of the list has a name to merge it by
+pub fn named(elements: &[Value]) -> bool {
+    elements.iter().all(|element| element.get("name").is_some_and(Value::is_string))
+}
+
+fn find<'a>(elements: &'a [Value], name: &Value) -> Option<&'a Value> {
+    elements.iter().find(|element| element.get("name") == Some(name))
+}
+
+// merge the new configuration over the live value, removing what was only
+// in the last configuration
+fn merge(last: Option<&Value>, live: &Value, new: &Value) -> Value {
+    match (live, new) {
+        (Value::Object(live_fields), Value::Object(new_fields)) => {
+            let mut merged = live_fields.clone();
+            if let Some(Value::Object(last_fields)) = last {
+                for key in last_fields.keys().filter(|key| !new_fields.contains_key(*key)) {
+                    merged.remove(key);
+                }
+            }
+            for (key, value) in new_fields {
+                let value = match live_fields.get(key) {
+                    Some(live_value) => merge(last.and_then(|last| last.get(key)), live_value, value),
+                    None => value.clone(),
+                };
+                merged.insert(key.clone(), value);
+            }
+            Value::Object(merged)
+        }
+        (Value::Array(live_elements), Value::Array(new_elements)) if named(live_elements) && named(new_elements) => {
+            let last_elements = last.and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default();
+            let mut merged: Vec<Value> = new_elements
+                .iter()
+                .map(|element| {
+                    let name = &element["name"];
+                    match find(live_elements, name) {
+                        Some(live_element) => merge(find(last_elements, name), live_element, element),
+                        None => element.clone(),
+                    }
+                })
+                .collect();
+            // elements someone else added stay
+            merged.extend(
+                live_elements
+                    .iter()
+                    .filter(|element| find(new_elements, &element["name"]).is_none() && find(last_elements, &element["name"]).is_none())
+                    .cloned(),
+            );
+            Value::Array(merged)
+        }
+        _ => new.clone(),
+    }
+}
+
+// the manifest to apply and whether it differs from the live one, None when
+// there is no live pod yet
+pub fn apply(manifest: &str, live: Option<&str>) -> Result<(String, bool)> {
+    let configuration = configuration(manifest)?;
+    let Some(live) = live else {
+        return Ok((to_yaml(&configuration)?, true));
+    };
+    let live = parse(live)?;
+    let merged = merge(last_applied(&live).as_ref(), &live, &configuration);
+    let changed = merged != live;
+    Ok((to_yaml(&merged)?, changed))
+}
+
+// the manifest in the form apply writes it, for diffs
+pub fn normalize(manifest: &str) -> Result<String> {
+    to_yaml(&parse(manifest)?)
+}
+
+// the manifest without its labels and annotations, and without the specs of
+// its containers but their names when containers is false
+fn stripped(manifest: &str, containers: bool) -> Option<Value> {
+    let mut value = parse(manifest).ok()?;
+    if let Some(metadata) = value.get_mut("metadata").and_then(Value::as_object_mut) {
+        metadata.remove("labels");
+        metadata.remove("annotations");
+    }
+    if !containers && let Some(list) = value.get_mut("spec").and_then(|spec| spec.get_mut("containers")).and_then(Value::as_array_mut) {
+        for container in list.iter_mut() {
+            *container = container.get("name").cloned().unwrap_or_default();
+        }
+    }
+    Some(value)
+}
+
+// whether the manifests differ in more than the labels and annotations
+pub fn immutable_changed(old: &str, new: &str) -> bool {
+    match (stripped(old, true), stripped(new, true)) {
+        (Some(old), Some(new)) => old != new,
+        _ => true,
+    }
+}
+
+// whether the pods of the manifests differ in more than the labels and
+// annotations and the specs of their containers, which can be recreated one
+// by one in the sandbox of the pod as long as they keep their names
+pub fn pod_changed(old: &str, new: &str) -> bool {
+    match (stripped(old, false), stripped(new, false)) {
+        (Some(old), Some(new)) => old != new,
+        _ => true,
+    }
+}
+
+// a unified diff of the lines of two texts, empty when they are the same
+pub fn diff(old: &str, new: &str) -> Vec<String> {
+    let (a, b): (Vec<&str>, Vec<&str>) = (old.lines().collect(), new.lines().collect());
+    // lengths of the longest common subsequences of the suffixes
+    let mut lcs = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+        }
+    }
+    let mut ops: Vec<(char, &str)> = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            ops.push((' ', a[i]));
+            (i, j) = (i + 1, j + 1);
+        } else if j == b.len() || (i < a.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            ops.push(('-', a[i]));
+            i += 1;
+        } else {
+            ops.push(('+', b[j]));
+            j += 1;
+        }
+    }
+
+    let changed = |k: usize| ops[k].0 != ' ';
+    let mut lines = Vec::new();
+    let mut next = 0;
+    while let Some(first) = (next..ops.len()).find(|&k| changed(k)) {
+        // changes closer than twice the context share a hunk
+        let mut last = first;
+        while let Some(k) = (last + 1..ops.len().min(last + 2 * CONTEXT + 2)).find(|&k| changed(k)) {
+            last = k;
+        }
+        let (start, stop) = (first.saturating_sub(CONTEXT), (last + CONTEXT + 1).min(ops.len()));
+        let count = |range: std::ops::Range<usize>, side: char| ops[range].iter().filter(|(op, _)| *op == ' ' || *op == side).count();
+        let (old_start, new_start) = (count(0..start, '-'), count(0..start, '+'));
+        let (old_len, new_len) = (count(start..stop, '-'), count(start..stop, '+'));
+        lines.push(format!("@@ -{},{} +{},{} @@", old_start + 1, old_len, new_start + 1, new_len));
+        lines.extend(ops[start..stop].iter().map(|(op, line)| format!("{}{}", op, line)));
+        next = stop;
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POD: &str = r#"
+apiVersion: v1
+kind: Pod
+metadata:
+  name: web
+  labels:
+    app: web
+    tier: frontend
+spec:
+  containers:
+    - name: app
+      image: app:v1
+      env:
+        - name: MODE
+          value: fast
+"#;
+
+    #[test]
+    fn test_apply() {
+        let (created, changed) = apply(POD, None).unwrap();
+        assert!(changed);
+        assert!(created.contains(LAST_APPLIED));
+        assert!(!apply(POD, Some(&created)).unwrap().1);
+
+        // someone else adds a label and a container, the configuration drops
+        // a label and changes the env
+        let mut live = parse(&created).unwrap();
+        live["metadata"]["labels"]["team"] = "platform".into();
+        let sidecar = serde_json::json!({ "name": "sidecar", "image": "proxy:v1" });
+        live["spec"]["containers"].as_array_mut().unwrap().push(sidecar);
+        let configuration = POD.replace("    tier: frontend\n", "").replace("value: fast", "value: safe");
+        let (merged, changed) = apply(&configuration, Some(&to_yaml(&live).unwrap())).unwrap();
+        assert!(changed);
+        let merged = parse(&merged).unwrap();
+        let labels = &merged["metadata"]["labels"];
+        assert_eq!(labels["app"], "web");
+        assert_eq!(labels["team"], "platform");
+        assert!(labels.get("tier").is_none());
+        let containers = merged["spec"]["containers"].as_array().unwrap();
+        let names: Vec<&str> = containers.iter().map(|c| c["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["app", "sidecar"]);
+        assert_eq!(containers[0]["env"][0]["value"], "safe");
+
+        assert!(apply("- a\n- b\n", None).is_err());
+    }
+
+    #[test]
+    fn test_immutable_changed() {
+        let relabeled = POD.replace("tier: frontend", "tier: backend");
+        assert!(!immutable_changed(POD, &relabeled));
+        assert!(!immutable_changed(POD, &apply(POD, None).unwrap().0));
+        assert!(immutable_changed(POD, &POD.replace("app:v1", "app:v2")));
+        assert!(immutable_changed(POD, "not: [a manifest"));
+
+        assert!(!pod_changed(POD, &POD.replace("app:v1", "app:v2")));
+        assert!(pod_changed(POD, &POD.replace("name: app", "name: api")));
+        assert!(pod_changed(POD, &POD.replace("spec:\n", "spec:\n  hostNetwork: true\n")));
+        assert!(pod_changed(POD, "not: [a manifest"));
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\n";
+        let new = "a\nb\nC\nd\ne\nf\ng\nh\ni\nj\nk\nl\nm\n";
+        assert_eq!(
+            diff(old, new),
+            [
+                "@@ -1,6 +1,6 @@", " a", " b", "-c", "+C", " d", " e", " f",
+                "@@ -10,3 +10,4 @@", " j", " k", " l", "+m",
+            ]
+        );
+        assert!(diff(old, old).is_empty());
+    }
+}
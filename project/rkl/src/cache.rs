@@ -0,0 +1,358 @@
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use crate::image::ImageReference;
+use crate::rootpath;
+use tracing::warn;
+
+// Manifests can be given as a local path or as a remote source:
+//   https://host/pod.yaml[#sha256=<digest>]   fetched over http(s), revalidated with the ETag
+//   git+<repo url>//<path>[?ref=<rev>]         read from a cached clone of the repository
+//   oci://<image reference>[//<file>]         a file of an OCI artifact, e.g. pushed with
+//                                             `oras push`, copied with skopeo
+// Remote sources are cached under <root>/cache so that repeated runs are fast and
+// keep working offline once the cache is warm. The files of an artifact are its
+// layers, picked by their org.opencontainers.image.title, and every blob is
+// checked against its digest; an artifact pinned by digest never needs the network.
+// Repositories and revisions starting with '-' are refused, git would take them
+// for options.
+
+// the annotation oras names the files of an artifact with
+const TITLE_ANNOTATION: &str = "org.opencontainers.image.title";
+
+// read the manifest behind a path or a remote source
+pub fn read_manifest(source: &str) -> Result<String> {
+    if source.starts_with("http://") || source.starts_with("https://") {
+        return fetch_http(&cache_dir()?, source);
+    }
+    if let Some(git_source) = source.strip_prefix("git+") {
+        return fetch_git(&cache_dir()?, git_source);
+    }
+    if let Some(oci_source) = source.strip_prefix("oci://") {
+        return fetch_oci(&cache_dir()?, oci_source);
+    }
+
+    let mut file = File::open(source)?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+// remove every cached remote source
+pub fn clean() -> Result<()> {
+    let dir = cache_dir()?;
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    println!("Cache {} cleaned", dir.display());
+    Ok(())
+}
+
+fn cache_dir() -> Result<PathBuf> {
+    Ok(rootpath::determine(None)?.join("cache"))
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+// a cached http source: the body and a small "Key: value" metadata file next to it
+struct CacheEntry {
+    body_path: PathBuf,
+    meta_path: PathBuf,
+}
+
+impl CacheEntry {
+    fn new(cache_dir: &Path, url: &str) -> Self {
+        let dir = cache_dir.join("http").join(sha256_hex(url.as_bytes()));
+        CacheEntry {
+            body_path: dir.join("body"),
+            meta_path: dir.join("meta"),
+        }
+    }
+
+    fn etag(&self) -> Option<String> {
+        self.meta_value("ETag: ")
+    }
+
+    fn meta_value(&self, key: &str) -> Option<String> {
+        let meta = fs::read_to_string(&self.meta_path).ok()?;
+        meta.lines()
+            .find(|line| line.starts_with(key))
+            .map(|line| line.trim_start_matches(key).to_string())
+    }
+
+    // cached body, only returned if it still matches the digest recorded when it was stored
+    fn load(&self) -> Option<String> {
+        let body = fs::read(&self.body_path).ok()?;
+        if self.meta_value("SHA256: ")? != sha256_hex(&body) {
+            warn!("Ignoring corrupted cache entry {}", self.body_path.display());
+            return None;
+        }
+        String::from_utf8(body).ok()
+    }
+
+    fn store(&self, url: &str, etag: Option<&str>, body: &str) -> Result<()> {
+        let dir = self.body_path.parent().ok_or_else(|| anyhow!("invalid cache path"))?;
+        fs::create_dir_all(dir)?;
+        fs::write(&self.body_path, body)?;
+        let mut meta = File::create(&self.meta_path)?;
+        writeln!(meta, "URL: {}", url)?;
+        if let Some(etag) = etag {
+            writeln!(meta, "ETag: {}", etag)?;
+        }
+        writeln!(meta, "SHA256: {}", sha256_hex(body.as_bytes()))?;
+        Ok(())
+    }
+}
+
+fn fetch_http(cache_dir: &Path, source: &str) -> Result<String> {
+    // an optional "#sha256=<digest>" suffix pins the expected content
+    let (url, pinned_digest) = match source.split_once("#sha256=") {
+        Some((url, digest)) => (url, Some(digest.to_lowercase())),
+        None => (source, None),
+    };
+    let entry = CacheEntry::new(cache_dir, url);
+    let verify = |body: String| -> Result<String> {
+        if let Some(digest) = &pinned_digest {
+            let actual = sha256_hex(body.as_bytes());
+            if &actual != digest {
+                return Err(anyhow!("digest mismatch for {}: expected {}, got {}", url, digest, actual));
+            }
+        }
+        Ok(body)
+    };
+
+    // a pinned digest that is already cached never needs the network
+    if pinned_digest.is_some()
+        && let Some(body) = entry.load().and_then(|body| verify(body).ok())
+    {
+        return Ok(body);
+    }
+
+    let cached = entry.load();
+    let mut request = ureq::get(url);
+    if let (Some(etag), Some(_)) = (entry.etag(), &cached) {
+        request = request.set("If-None-Match", &etag);
+    }
+
+    match request.call() {
+        Ok(response) if response.status() == 304 => {
+            verify(cached.ok_or_else(|| anyhow!("cache entry for {} disappeared", url))?)
+        }
+        Ok(response) => {
+            let etag = response.header("ETag").map(|e| e.to_string());
+            let body = verify(response.into_string()?)?;
+            entry.store(url, etag.as_deref(), &body)?;
+            Ok(body)
+        }
+        // offline or the server is down: fall back to the cached copy if there is one
+        Err(ureq::Error::Transport(err)) => match cached {
+            Some(body) => {
+                warn!("Failed to fetch {} ({}), using cached copy", url, err);
+                verify(body)
+            }
+            None => Err(anyhow!("Failed to fetch {}: {}", url, err)),
+        },
+        Err(err) => Err(anyhow!("Failed to fetch {}: {}", url, err)),
+    }
+}
+
+fn fetch_git(cache_dir: &Path, source: &str) -> Result<String> {
+    let (source, rev) = match source.split_once("?ref=") {
+        Some((source, rev)) => (source, Some(rev)),
+        None => (source, None),
+    };
+    // the path inside the repository follows the first "//" after the scheme
+    let scheme_end = source.find("://").map(|i| i + 3).unwrap_or(0);
+    let split = source[scheme_end..]
+        .find("//")
+        .map(|i| i + scheme_end)
+        .ok_or_else(|| anyhow!("git source {} has no //<path> part", source))?;
+    let (repo, file_path) = (&source[..split], &source[split + 2..]);
+    if repo.starts_with('-') || rev.is_some_and(|rev| rev.starts_with('-')) {
+        return Err(anyhow!("invalid git source {}, the repository and ref can't start with '-'", source));
+    }
+
+    let repo_dir = cache_dir.join("git").join(sha256_hex(repo.as_bytes()));
+    if repo_dir.join(".git").exists() {
+        if let Err(err) = git(&repo_dir, &["fetch", "--quiet", "--tags", "origin"]) {
+            warn!("Failed to update {} ({}), using cached clone", repo, err);
+        }
+    } else {
+        fs::create_dir_all(&repo_dir)?;
+        let status = Command::new("git")
+            .args(["clone", "--quiet", "--", repo])
+            .arg(&repo_dir)
+            .status()?;
+        if !status.success() {
+            let _ = fs::remove_dir_all(&repo_dir);
+            return Err(anyhow!("Failed to clone {}", repo));
+        }
+    }
+
+    // branches are read from the remote so that a fetch is enough to pick up new commits
+    let candidates = match rev {
+        Some(rev) => vec![format!("origin/{}", rev), rev.to_string()],
+        None => vec!["origin/HEAD".to_string()],
+    };
+    for candidate in &candidates {
+        if let Ok(contents) = git(&repo_dir, &["show", &format!("{}:{}", candidate, file_path)]) {
+            return Ok(contents);
+        }
+    }
+    Err(anyhow!("{} not found in {} at {}", file_path, repo, candidates.join(" or ")))
+}
+
+fn fetch_oci(cache_dir: &Path, source: &str) -> Result<String> {
+    let (image, file) = match source.split_once("//") {
+        Some((image, file)) => (image, Some(file)),
+        None => (source, None),
+    };
+    let reference = ImageReference::parse(image)?;
+    let layout = cache_dir.join("oci").join(sha256_hex(reference.pull_name().as_bytes()));
+    let tag = reference.layout_tag();
+
+    // the content behind a digest never changes
+    if reference.digest.is_some()
+        && let Ok(contents) = read_artifact(&layout, &tag, reference.digest.as_deref(), file)
+    {
+        return Ok(contents);
+    }
+    fs::create_dir_all(&layout)?;
+    let output = Command::new("skopeo")
+        .args(["copy", "--quiet"])
+        .arg(format!("docker://{}", reference.pull_name()))
+        .arg(format!("oci:{}:{}", layout.display(), tag))
+        .output()
+        .map_err(|e| anyhow!("Failed to run skopeo to fetch {}: {}", reference, e));
+    let error = match output {
+        Ok(output) if output.status.success() => None,
+        Ok(output) => Some(anyhow!("{}", String::from_utf8_lossy(&output.stderr).trim())),
+        Err(e) => Some(e),
+    };
+    match (error, read_artifact(&layout, &tag, reference.digest.as_deref(), file)) {
+        (None, contents) => contents,
+        // offline or the registry is down: fall back to the cached copy if there is one
+        (Some(err), Ok(contents)) => {
+            warn!("Failed to fetch {} ({}), using cached copy", reference, err);
+            Ok(contents)
+        }
+        (Some(err), Err(_)) => Err(anyhow!("Failed to fetch {}: {}", reference, err)),
+    }
+}
+
+// a file of the artifact tagged in an OCI image layout, the only layer when no
+// file is given
+fn read_artifact(layout: &Path, tag: &str, digest: Option<&str>, file: Option<&str>) -> Result<String> {
+    let index: Value = serde_json::from_slice(&fs::read(layout.join("index.json"))?)?;
+    let manifest_digest = index["manifests"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|entry| entry["annotations"]["org.opencontainers.image.ref.name"].as_str() == Some(tag))
+        .and_then(|entry| entry["digest"].as_str())
+        .ok_or_else(|| anyhow!("{} is not in the cache", tag))?;
+    if digest.is_some_and(|digest| digest != manifest_digest) {
+        return Err(anyhow!("the cached manifest {} is not the pinned {}", manifest_digest, digest.unwrap_or_default()));
+    }
+    let manifest: Value = serde_json::from_slice(&read_blob(layout, manifest_digest)?)?;
+    let layers = manifest["layers"].as_array().map(Vec::as_slice).unwrap_or_default();
+    let layer = match file {
+        Some(file) => layers.iter().find(|layer| layer["annotations"][TITLE_ANNOTATION].as_str() == Some(file)),
+        None if layers.len() == 1 => layers.first(),
+        None => return Err(anyhow!("the artifact has {} files, name one with //<file>", layers.len())),
+    };
+    let layer_digest = layer
+        .and_then(|layer| layer["digest"].as_str())
+        .ok_or_else(|| anyhow!("the artifact has no file {}", file.unwrap_or_default()))?;
+    Ok(String::from_utf8(read_blob(layout, layer_digest)?)?)
+}
+
+// a blob of an OCI image layout, checked against its digest
+fn read_blob(layout: &Path, digest: &str) -> Result<Vec<u8>> {
+    let hex = digest.strip_prefix("sha256:").ok_or_else(|| anyhow!("unsupported digest {}", digest))?;
+    let blob = fs::read(layout.join("blobs").join("sha256").join(hex))?;
+    if sha256_hex(&blob) != hex {
+        return Err(anyhow!("the cached blob {} is corrupted", digest));
+    }
+    Ok(blob)
+}
+
+fn git(repo_dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git").arg("-C").arg(repo_dir).args(args).output()?;
+    if !output.status.success() {
+        return Err(anyhow!("git {}: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_entry_detects_corruption() {
+        let dir = tempfile::tempdir().unwrap();
+        let entry = CacheEntry::new(dir.path(), "https://example.com/pod.yaml");
+        entry.store("https://example.com/pod.yaml", Some("\"abc\""), "kind: Pod\n").unwrap();
+        assert_eq!(entry.load().as_deref(), Some("kind: Pod\n"));
+        assert_eq!(entry.etag().as_deref(), Some("\"abc\""));
+
+        fs::write(&entry.body_path, "kind: Evil\n").unwrap();
+        assert_eq!(entry.load(), None);
+    }
+
+    #[test]
+    fn test_pinned_digest_served_from_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = "http://127.0.0.1:1/pod.yaml";
+        CacheEntry::new(dir.path(), url).store(url, None, "kind: Pod\n").unwrap();
+
+        let source = format!("{}#sha256={}", url, sha256_hex(b"kind: Pod\n"));
+        assert_eq!(fetch_http(dir.path(), &source).unwrap(), "kind: Pod\n");
+
+        let wrong = format!("{}#sha256={}", url, sha256_hex(b"other"));
+        assert!(fetch_http(dir.path(), &wrong).is_err());
+    }
+
+    #[test]
+    fn test_read_artifact() {
+        let dir = tempfile::tempdir().unwrap();
+        let blobs = dir.path().join("blobs").join("sha256");
+        fs::create_dir_all(&blobs).unwrap();
+        let blob = |data: &[u8]| {
+            fs::write(blobs.join(sha256_hex(data)), data).unwrap();
+            format!("sha256:{}", sha256_hex(data))
+        };
+        let pod = blob(b"kind: Pod\n");
+        let service = blob(b"kind: Service\n");
+        let layer = |digest: &str, title: &str| serde_json::json!({ "digest": digest, "annotations": { TITLE_ANNOTATION: title } });
+        let manifest = serde_json::json!({ "layers": [layer(&pod, "pod.yaml"), layer(&service, "service.yaml")] });
+        let manifest = blob(manifest.to_string().as_bytes());
+        let index = serde_json::json!({ "manifests": [{ "digest": manifest, "annotations": { "org.opencontainers.image.ref.name": "v1" } }] });
+        fs::write(dir.path().join("index.json"), index.to_string()).unwrap();
+
+        assert_eq!(read_artifact(dir.path(), "v1", None, Some("service.yaml")).unwrap(), "kind: Service\n");
+        assert_eq!(read_artifact(dir.path(), "v1", Some(&manifest), Some("pod.yaml")).unwrap(), "kind: Pod\n");
+        assert!(read_artifact(dir.path(), "v1", None, None).is_err());
+        assert!(read_artifact(dir.path(), "v1", None, Some("missing.yaml")).is_err());
+        assert!(read_artifact(dir.path(), "v1", Some("sha256:other"), Some("pod.yaml")).is_err());
+        assert!(read_artifact(dir.path(), "v2", None, Some("pod.yaml")).is_err());
+
+        fs::write(blobs.join(sha256_hex(b"kind: Pod\n")), "kind: Evil\n").unwrap();
+        assert!(read_artifact(dir.path(), "v1", None, Some("pod.yaml")).is_err());
+    }
+
+    #[test]
+    fn test_git_source_options_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(fetch_git(dir.path(), "--upload-pack=touch /tmp/x//pod.yaml").is_err());
+        assert!(fetch_git(dir.path(), "https://example.com/repo.git//pod.yaml?ref=--output=/tmp/x").is_err());
+        assert!(!dir.path().join("git").exists());
+    }
+}
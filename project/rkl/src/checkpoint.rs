@@ -0,0 +1,463 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+use anyhow::{Result, anyhow};
+use base64::Engine;
+use libcontainer::container::CheckpointOptions;
+use libcontainer::oci_spec::runtime::Spec;
+use serde::{Deserialize, Serialize};
+use crate::cli_commands::{self, PodInfo};
+use crate::commands::load_container;
+use crate::configmap::{self, ConfigMap};
+use crate::cri::cri::{CheckpointContainerRequest, ContainerStatusRequest};
+use crate::cri::debug;
+use crate::describe;
+use crate::events;
+use crate::rootpath;
+use crate::runtime::{self, RuntimeBackend};
+use crate::secret::{self, Secret};
+use crate::task::task::{ContainerSpec, FailurePolicy, ObjectMeta, PodTask};
+use crate::values::Values;
+
+// `rkl checkpoint <pod>` saves the state of the running containers of a pod
+// with CRIU into an archive, `rkl restore <archive>` runs the pod again from
+// it, on this node or another one, the containers going on where they were:
+//
+//   checkpoint-web_default-1760000000.tar
+//     checkpoint.json       the pod, when, and the containers checkpointed
+//     pod.yaml              the manifest of the pod
+//     containers/<name>.tar the checkpoint of each container
+//     objects/<kind>-<name>.yaml the config maps and secrets of its volumes
+//
+// With a CRI runtime a container is checkpointed with CheckpointContainer and
+// its archive is the one of the runtime; CRI-O and containerd 2.0 restore a
+// container whose image is such an archive, which is how restore creates them.
+// With an OCI runtime or the built-in one the archive is the bundle of the
+// container with the CRIU images of `<runtime> checkpoint` or of libcontainer,
+// which the OCI backend restores with `<runtime> restore`. libcontainer doesn't
+// restore, so the pods of the built-in runtime are restored with
+// --runtime-endpoint runc or crun. Init containers aren't restored, they ran.
+// The config maps and secrets the volumes project are stored on restore when
+// the node doesn't have them, so the archive holds the secrets of the pod in
+// the clear and is to be kept like them.
+//
+// The daemon checkpoints and restores its pods over the control API, which is
+// how rks migrates a pod between nodes: the pod restored on the new node is
+// written as a manifest of the control API directory with the node it came from
+// in RESTORED_FROM, and the daemon adopts it as it runs.
+
+const METADATA: &str = "checkpoint.json";
+const MANIFEST: &str = "pod.yaml";
+const CONTAINERS: &str = "containers";
+const OBJECTS: &str = "objects";
+// the CRIU images inside the archive of a bundle
+pub const IMAGES: &str = "checkpoint";
+const VERSION: u32 = 1;
+// the node or archive a pod of the daemon was restored from
+pub const RESTORED_FROM: &str = "rk8s.io/restored-from";
+
+// what the archives of the containers are, only a runtime of the same kind restores them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    // the archives of a CRI runtime
+    Cri,
+    // bundles with CRIU images
+    Bundle,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ContainerCheckpoint {
+    pub name: String,
+    pub image: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Metadata {
+    pub version: u32,
+    pub pod: String,
+    pub namespace: String,
+    // seconds since the epoch
+    pub created: u64,
+    pub format: Format,
+    pub containers: Vec<ContainerCheckpoint>,
+}
+
+// the image of a container restored from a checkpoint is its archive
+pub fn is_archive(image: &str) -> bool {
+    image.ends_with(".tar") && Path::new(image).is_file()
+}
+
+fn tar(args: &[&str]) -> Result<()> {
+    let output = Command::new("tar").args(args).output().map_err(|e| anyhow!("Failed to run tar: {}", e))?;
+    if !output.status.success() {
+        return Err(anyhow!("tar {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(())
+}
+
+fn path_str(path: &Path) -> Result<&str> {
+    path.to_str().ok_or_else(|| anyhow!("{} is not UTF-8", path.display()))
+}
+
+// the metadata of an archive, without unpacking the rest
+pub fn read_metadata(archive: &Path) -> Result<Metadata> {
+    let output = Command::new("tar")
+        .args(["-xOf", path_str(archive)?, METADATA])
+        .output()
+        .map_err(|e| anyhow!("Failed to run tar: {}", e))?;
+    if !output.status.success() {
+        return Err(anyhow!("{} isn't a pod checkpoint", archive.display()));
+    }
+    let metadata: Metadata = serde_json::from_slice(&output.stdout)?;
+    if metadata.version != VERSION {
+        return Err(anyhow!("checkpoint version {} isn't supported, expected {}", metadata.version, VERSION));
+    }
+    Ok(metadata)
+}
+
+// archive a bundle, whose rootfs must be in it, with the CRIU images dump writes
+pub fn checkpoint_bundle(bundle: &Path, location: &Path, dump: impl FnOnce(&Path) -> Result<()>) -> Result<()> {
+    let spec = Spec::load(bundle.join("config.json")).map_err(|e| anyhow!("invalid bundle {}: {}", bundle.display(), e))?;
+    if spec.root().as_ref().is_none_or(|root| root.path() != Path::new("rootfs")) {
+        return Err(anyhow!("the rootfs of bundle {} isn't its rootfs directory, it can't be archived", bundle.display()));
+    }
+    let staging = PathBuf::from(format!("{}.d", location.display()));
+    fs::create_dir_all(staging.join(IMAGES))?;
+    let result = dump(&staging.join(IMAGES)).and_then(|()| {
+        tar(&["-cf", path_str(location)?, "-C", path_str(bundle)?, "config.json", "rootfs", "-C", path_str(&staging)?, IMAGES])
+    });
+    let _ = fs::remove_dir_all(&staging);
+    result
+}
+
+// unpack the archive of a bundle into dir, the CRIU images in dir/checkpoint
+pub fn unpack_bundle(archive: &Path, dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir)?;
+    tar(&["-xf", path_str(archive)?, "-C", path_str(dir)?])?;
+    if !dir.join("config.json").exists() || !dir.join(IMAGES).exists() {
+        return Err(anyhow!("{} isn't the checkpoint of a bundle", archive.display()));
+    }
+    Ok(())
+}
+
+// the spec of a restored bundle without the bind mounts of the sandbox it was
+// checkpointed in, the ones of the new sandbox are added again
+pub fn without_bind_mounts(mut spec: Spec) -> Spec {
+    let mounts = spec.mounts().clone().map(|mounts| mounts.into_iter().filter(|mount| mount.typ().as_deref() != Some("bind")).collect());
+    spec.set_mounts(mounts);
+    spec
+}
+
+// the container of libcontainer, left running
+fn checkpoint_builtin(root_path: &Path, container_id: &str, location: &Path) -> Result<()> {
+    let mut container = load_container(root_path, container_id)?;
+    let bundle = container.bundle().clone();
+    checkpoint_bundle(&bundle, location, |images| {
+        let options = CheckpointOptions {
+            ext_unix_sk: false,
+            file_locks: false,
+            image_path: images.to_path_buf(),
+            leave_running: true,
+            shell_job: false,
+            tcp_established: true,
+            work_path: None,
+        };
+        container.checkpoint(&options).map_err(|e| anyhow!("Failed to checkpoint container {}: {}", container_id, e))
+    })
+}
+
+// the name in the manifest of a container of the pod file, its id with the built-in runtime
+fn container_name(backend: Option<&dyn RuntimeBackend>, container_id: &str) -> String {
+    backend
+        .and_then(|backend| backend.container_status(ContainerStatusRequest { container_id: container_id.to_string(), verbose: false }).ok())
+        .and_then(|response| response.status)
+        .and_then(|status| status.metadata)
+        .map(|metadata| metadata.name)
+        .unwrap_or_else(|| container_id.to_string())
+}
+
+// checkpoint the containers of a pod into dir/containers
+fn checkpoint_containers(
+    backend: Option<&dyn RuntimeBackend>,
+    root_path: &Path,
+    pod_info: &PodInfo,
+    task: &PodTask,
+    dir: &Path,
+    timeout: Option<Duration>,
+) -> Result<Vec<ContainerCheckpoint>> {
+    fs::create_dir_all(dir.join(CONTAINERS))?;
+    let mut checkpoints = Vec::new();
+    for container_id in &pod_info.container_names {
+        let name = container_name(backend, container_id);
+        let Some(container) = task.spec.running_containers().find(|container| container.name == name) else {
+            continue;
+        };
+        let location = dir.join(CONTAINERS).join(format!("{}.tar", name));
+        let request = CheckpointContainerRequest {
+            container_id: container_id.clone(),
+            location: location.display().to_string(),
+            timeout: timeout.map(|timeout| timeout.as_secs() as i64).unwrap_or_default(),
+        };
+        debug::traced("CheckpointContainer", request, |request| match backend {
+            Some(backend) => backend.checkpoint_container(request),
+            None => checkpoint_builtin(root_path, container_id, &location).map(|()| Default::default()),
+        })
+        .map_err(|e| anyhow!("Failed to checkpoint container {}: {:#}", name, e))?;
+        checkpoints.push(ContainerCheckpoint { name, image: container.image.clone() });
+    }
+    if checkpoints.is_empty() {
+        return Err(anyhow!("pod {} has no containers to checkpoint", task.metadata.name));
+    }
+    Ok(checkpoints)
+}
+
+fn object_meta(name: &str, namespace: &str) -> ObjectMeta {
+    ObjectMeta { name: name.to_string(), namespace: namespace.to_string(), labels: Default::default(), annotations: Default::default() }
+}
+
+// write the config maps and secrets of the volumes of the pod into dir/objects
+// as manifests, the missing optional ones are left out
+fn save_objects(root_path: &Path, task: &PodTask, dir: &Path) -> Result<()> {
+    let dir = dir.join(OBJECTS);
+    fs::create_dir_all(&dir)?;
+    let encode = |value: &Vec<u8>| base64::engine::general_purpose::STANDARD.encode(value);
+    let namespace = &task.metadata.namespace;
+    for volume in &task.spec.volumes {
+        if let Some(source) = &volume.config_map
+            && let Ok(data) = configmap::load(root_path, &source.name)
+        {
+            let config_map = ConfigMap {
+                api_version: "v1".to_string(),
+                kind: configmap::KIND.to_string(),
+                metadata: object_meta(&source.name, namespace),
+                data: Default::default(),
+                binary_data: data.iter().map(|(key, value)| (key.clone(), encode(value))).collect(),
+            };
+            fs::write(dir.join(format!("configmap-{}.yaml", source.name)), serde_yaml::to_string(&config_map)?)?;
+        }
+        if let Some(source) = &volume.secret
+            && let Ok(stored) = secret::load(root_path, &source.secret_name)
+        {
+            let secret = Secret {
+                api_version: "v1".to_string(),
+                kind: secret::KIND.to_string(),
+                metadata: object_meta(&source.secret_name, namespace),
+                secret_type: stored.secret_type,
+                data: stored.data.iter().map(|(key, value)| (key.clone(), encode(value))).collect(),
+                string_data: Default::default(),
+            };
+            fs::write(dir.join(format!("secret-{}.yaml", source.secret_name)), serde_yaml::to_string(&secret)?)?;
+        }
+    }
+    Ok(())
+}
+
+// store the config maps and secrets of dir/objects this node doesn't have, the
+// ones it has are left as they are
+fn restore_objects(root_path: &Path, dir: &Path) -> Result<()> {
+    let Ok(entries) = fs::read_dir(dir.join(OBJECTS)) else {
+        return Ok(());
+    };
+    for entry in entries {
+        let path = entry?.path();
+        let contents = fs::read_to_string(&path)?;
+        let file_name = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        if file_name.starts_with("secret-") {
+            let secret = Secret::parse(&contents)?;
+            if !secret::exists(root_path, &secret.metadata.name) {
+                secret.save(root_path)?;
+            }
+        } else {
+            let config_map = ConfigMap::parse(&contents)?;
+            if configmap::load(root_path, &config_map.metadata.name).is_err() {
+                config_map.save(root_path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// `rkl checkpoint`, the archive written into output_dir
+pub fn checkpoint_pod(target: &str, output_dir: &Path, timeout: Option<Duration>) -> Result<()> {
+    let pod_name = target.strip_prefix("pod/").unwrap_or(target);
+    let archive = checkpoint(&rootpath::determine(None)?, pod_name, output_dir, timeout)?;
+    println!("Pod {} checkpointed to {}", pod_name, archive.display());
+    Ok(())
+}
+
+// checkpoint a pod into an archive of output_dir, whose path is returned
+pub fn checkpoint(root_path: &Path, pod_name: &str, output_dir: &Path, timeout: Option<Duration>) -> Result<PathBuf> {
+    let pod_info = PodInfo::load(root_path, pod_name)?;
+    let task = describe::load_spec(root_path, pod_name)
+        .ok_or_else(|| anyhow!("the manifest of pod {} isn't recorded, it couldn't be restored", pod_name))?;
+    let backend = runtime::backend()?;
+    let created = events::now();
+    let staging = root_path.join("checkpoints").join(format!(".{}-{}", pod_name, created));
+    let archive = output_dir.join(format!("checkpoint-{}_{}-{}.tar", pod_name, task.metadata.namespace, created));
+
+    let result = checkpoint_containers(backend.as_deref(), root_path, &pod_info, &task, &staging, timeout).and_then(|containers| {
+        let metadata = Metadata {
+            version: VERSION,
+            pod: pod_name.to_string(),
+            namespace: task.metadata.namespace.clone(),
+            created,
+            format: backend.as_ref().map_or(Format::Bundle, |backend| backend.checkpoint_format()),
+            containers,
+        };
+        fs::write(staging.join(METADATA), serde_json::to_string_pretty(&metadata)?)?;
+        fs::write(staging.join(MANIFEST), serde_yaml::to_string(&task)?)?;
+        save_objects(root_path, &task, &staging)?;
+        tar(&["-cf", path_str(&archive)?, "-C", path_str(&staging)?, METADATA, MANIFEST, CONTAINERS, OBJECTS])
+    });
+    let _ = fs::remove_dir_all(&staging);
+    result?;
+    Ok(archive)
+}
+
+// the manifest a pod is restored with: the containers and sidecars
+// checkpointed with their archive in dir as image, without the init containers
+// that ran to completion
+fn restored_task(mut task: PodTask, metadata: &Metadata, dir: &Path) -> Result<PodTask> {
+    let checkpointed = |container: &ContainerSpec| metadata.containers.iter().any(|checkpoint| checkpoint.name == container.name);
+    let spec = &mut task.spec;
+    spec.init_containers.retain(|container| container.is_sidecar() && checkpointed(container));
+    spec.sidecars.retain(checkpointed);
+    spec.containers.retain(checkpointed);
+    for container in spec.init_containers.iter_mut().chain(spec.sidecars.iter_mut()).chain(spec.containers.iter_mut()) {
+        let archive = dir.join(CONTAINERS).join(format!("{}.tar", container.name));
+        if !archive.is_file() {
+            return Err(anyhow!("the checkpoint of container {} is missing", container.name));
+        }
+        container.image = archive.display().to_string();
+    }
+    Ok(task)
+}
+
+// `rkl restore`
+pub fn restore_pod(archive: &Path) -> Result<()> {
+    let task = restore(archive)?;
+    println!("Pod {} restored from {}", task.metadata.name, archive.display());
+    Ok(())
+}
+
+// run the pod of an archive, returning the manifest it was checkpointed with
+pub fn restore(archive: &Path) -> Result<PodTask> {
+    let backend = runtime::backend()?.ok_or_else(|| {
+        anyhow!("the built-in runtime can't restore checkpoints, use --runtime-endpoint with CRI-O, containerd 2.0, runc or crun")
+    })?;
+    let root_path = rootpath::determine(None)?;
+    let name = archive.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    let dir = root_path.join("checkpoints").join(name);
+    if dir.exists() {
+        fs::remove_dir_all(&dir)?;
+    }
+    fs::create_dir_all(&dir)?;
+    let result = restore_from(backend.as_ref(), &root_path, archive, &dir);
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+fn restore_from(backend: &dyn RuntimeBackend, root_path: &Path, archive: &Path, dir: &Path) -> Result<PodTask> {
+    let metadata = read_metadata(archive)?;
+    tar(&["-xf", path_str(archive)?, "-C", path_str(dir)?])?;
+    if metadata.format != backend.checkpoint_format() {
+        return Err(anyhow!("the checkpoint of pod {} is in the {:?} format, which this runtime doesn't restore", metadata.pod, metadata.format));
+    }
+    if PodInfo::load(root_path, &metadata.pod).is_ok() {
+        return Err(anyhow!("Pod {} already exists", metadata.pod));
+    }
+    let read_task = || -> Result<PodTask> { Ok(serde_yaml::from_str(&fs::read_to_string(dir.join(MANIFEST))?)?) };
+    let restored = restored_task(read_task()?, &metadata, dir)?;
+    let manifest = dir.join("restored.yaml");
+    fs::write(&manifest, serde_yaml::to_string(&restored)?)?;
+    restore_objects(root_path, dir)?;
+
+    cli_commands::run_pod(path_str(&manifest)?, FailurePolicy::Rollback, &Values::default())?;
+    // recorded with the images it was checkpointed with, the archives are gone
+    let task = read_task()?;
+    describe::save_spec(root_path, &task)?;
+    Ok(task)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cri::cri::{ContainerConfig, ContainerMetadata, CreateContainerRequest, ImageSpec, PodSandboxConfig, PodSandboxMetadata, PullImageRequest, RunPodSandboxRequest, StartContainerRequest};
+    use crate::runtime::mock::MockRuntime;
+
+    const POD: &str = "
+apiVersion: v1
+kind: Pod
+metadata:
+  name: web
+spec:
+  initContainers:
+  - name: migrate
+    image: app:v1
+  sidecars:
+  - name: proxy
+    image: envoy:v1
+  containers:
+  - name: app
+    image: app:v1
+";
+
+    #[test]
+    fn test_checkpoint() {
+        let mock = MockRuntime::default();
+        let sandbox = PodSandboxConfig { metadata: Some(PodSandboxMetadata { name: "web".to_string(), ..Default::default() }), ..Default::default() };
+        mock.run_pod_sandbox(RunPodSandboxRequest { config: Some(sandbox), ..Default::default() }).unwrap();
+        for (name, image) in [("proxy", "envoy:v1"), ("app", "app:v1")] {
+            let image = Some(ImageSpec { image: image.to_string(), ..Default::default() });
+            mock.pull_image(PullImageRequest { image: image.clone(), ..Default::default() }).unwrap();
+            let config = ContainerConfig { metadata: Some(ContainerMetadata { name: name.to_string(), attempt: 0 }), image, ..Default::default() };
+            mock.create_container(CreateContainerRequest { pod_sandbox_id: "web".to_string(), config: Some(config), ..Default::default() }).unwrap();
+        }
+
+        let task: PodTask = serde_yaml::from_str(POD).unwrap();
+        let container_names = vec!["proxy".to_string(), "app".to_string()];
+        let pod_info = PodInfo { pod_sandbox_id: "web".to_string(), container_names, container_statuses: Vec::new() };
+        let dir = tempfile::tempdir().unwrap();
+        // only running containers are checkpointed
+        assert!(checkpoint_containers(Some(&mock), dir.path(), &pod_info, &task, dir.path(), None).is_err());
+        for container_id in &pod_info.container_names {
+            mock.start_container(StartContainerRequest { container_id: container_id.clone() }).unwrap();
+        }
+        let containers = checkpoint_containers(Some(&mock), dir.path(), &pod_info, &task, dir.path(), None).unwrap();
+        let checkpoint = |name: &str, image: &str| ContainerCheckpoint { name: name.to_string(), image: image.to_string() };
+        assert_eq!(containers, [checkpoint("proxy", "envoy:v1"), checkpoint("app", "app:v1")]);
+
+        let metadata = Metadata { version: VERSION, pod: "web".to_string(), namespace: "default".to_string(), created: 0, format: Format::Cri, containers };
+        let restored = restored_task(task, &metadata, dir.path()).unwrap();
+        assert!(restored.spec.init_containers.is_empty());
+        assert_eq!(Path::new(&restored.spec.sidecars[0].image), dir.path().join("containers/proxy.tar"));
+        let image = &restored.spec.containers[0].image;
+        assert_eq!(Path::new(image), dir.path().join("containers/app.tar"));
+        assert!(is_archive(image));
+        assert!(!is_archive("app:v1"));
+        assert_eq!(serde_json::to_value(Format::Bundle).unwrap(), "bundle");
+    }
+
+    #[test]
+    fn test_objects() {
+        let source = tempfile::tempdir().unwrap();
+        let config_map = "apiVersion: v1\nkind: ConfigMap\nmetadata:\n  name: settings\ndata:\n  level: debug\n";
+        ConfigMap::parse(config_map).unwrap().save(source.path()).unwrap();
+        let pod = format!("{}  volumes:\n  - name: settings\n    configMap:\n      name: settings\n  - name: missing\n    configMap:\n      name: missing\n      optional: true\n", POD);
+        let task: PodTask = serde_yaml::from_str(&pod).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        save_objects(source.path(), &task, dir.path()).unwrap();
+        assert!(dir.path().join("objects/configmap-settings.yaml").exists());
+        assert!(!dir.path().join("objects/configmap-missing.yaml").exists());
+
+        // stored on a node without it, a node's own is kept
+        let target = tempfile::tempdir().unwrap();
+        restore_objects(target.path(), dir.path()).unwrap();
+        assert_eq!(configmap::load(target.path(), "settings").unwrap(), configmap::load(source.path(), "settings").unwrap());
+        ConfigMap::parse(&config_map.replace("debug", "info")).unwrap().save(target.path()).unwrap();
+        restore_objects(target.path(), dir.path()).unwrap();
+        assert_eq!(configmap::load(target.path(), "settings").unwrap()["level"], b"info");
+    }
+}
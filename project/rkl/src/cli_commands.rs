@@ -1,17 +1,37 @@
 use std::fs::{self, File};
-use std::io::{Read, Write};
+use std::io::{self, IsTerminal, Read, Write};
+use std::net::TcpListener;
 use std::path::{Path,PathBuf};
+use std::thread;
+use std::time::Duration;
 use anyhow::{Result, anyhow};
 use liboci_cli::{Create, Start,State, Kill, Delete};
-use crate::commands::{create, start, state,kill,delete,load_container};
-use crate::task::task::TaskRunner;
+use crate::commands::{create, start, state,kill,delete,exec,load_container};
+use libcontainer::container::ContainerStatus as RuntimeStatus;
+use crate::task::{cni, cpumanager, ephemeral, logs, termination};
+use crate::task::task::{self, ContainerState, ContainerStatus, FailurePolicy, FailureStage, PodRunError, TaskRunner};
 use crate::rootpath;
+use crate::ratelimit;
+use crate::device;
+use crate::stream::{self, portforward};
+use crate::events::{self, EventRecorder};
+use crate::cri::cri::{AttachRequest, ExecSyncRequest, PodSandboxStatusRequest, PortForwardRequest, RemovePodSandboxRequest, StopPodSandboxRequest};
+use crate::runtime;
+use crate::cri::debug;
+use crate::stats::{self, ContainerStats};
+use tracing::{info, warn};
+use crate::logging;
+use crate::describe;
+use crate::values::{self, Values};
+use crate::selector::Selector;
+use crate::dryrun::{self, DryRun};
 
 // store infomation of pod
 #[derive(Debug)]
 pub struct PodInfo {
     pub pod_sandbox_id: String,
     pub container_names: Vec<String>,
+    pub container_statuses: Vec<ContainerStatus>,
 }
 
 impl PodInfo {
@@ -25,9 +45,17 @@ impl PodInfo {
 
         let mut pod_sandbox_id = None;
         let mut container_names = Vec::new();
+        let mut container_statuses = Vec::new();
+        let mut in_status_section = false;
         for line in contents.lines() {
             if line.starts_with("PodSandbox ID: ") {
                 pod_sandbox_id = Some(line.trim_start_matches("PodSandbox ID: ").to_string());
+            } else if line == "Container Status:" {
+                in_status_section = true;
+            } else if in_status_section {
+                if let Some(status) = parse_container_status(line) {
+                    container_statuses.push(status);
+                }
             } else if line.starts_with("- ") {
                 let container_name = line.trim_start_matches("- ").to_string();
                 container_names.push(container_name);
@@ -38,6 +66,7 @@ impl PodInfo {
         Ok(PodInfo {
             pod_sandbox_id,
             container_names,
+            container_statuses,
         })
     }
 
@@ -56,16 +85,45 @@ impl PodInfo {
         if pod_info_path.exists() {
             return Err(anyhow!("Pod {} already exists at {}", pod_name, pod_info_path.display()));
         }
+        self.write(&pod_info_path)
+    }
+
+    // replace the recorded status of a container of a recorded pod
+    pub fn update_status(root_path: &Path, pod_name: &str, status: ContainerStatus) -> Result<()> {
+        let mut pod_info = PodInfo::load(root_path, pod_name)?;
+        match pod_info.container_statuses.iter_mut().find(|s| s.name == status.name) {
+            Some(existing) => *existing = status,
+            None => pod_info.container_statuses.push(status),
+        }
+        pod_info.write(&root_path.join("pods").join(pod_name))
+    }
 
-        let mut file = File::create(&pod_info_path)?;
+    fn write(&self, pod_info_path: &Path) -> Result<()> {
+        let mut file = File::create(pod_info_path)?;
         writeln!(file, "PodSandbox ID: {}", self.pod_sandbox_id)?;
         writeln!(file, "Containers:")?;
         for container_name in &self.container_names {
             writeln!(file, "- {}", container_name)?;
         }
+        if !self.container_statuses.is_empty() {
+            writeln!(file, "Container Status:")?;
+            for status in &self.container_statuses {
+                writeln!(file, "{}: {}", status.name, status)?;
+            }
+        }
         Ok(())
     }
 
+    // the names of every recorded pod, sorted
+    pub fn names(root_path: &Path) -> Result<Vec<String>> {
+        let Ok(entries) = fs::read_dir(root_path.join("pods")) else {
+            return Ok(Vec::new());
+        };
+        let mut names: Vec<String> = entries.flatten().map(|entry| entry.file_name().to_string_lossy().into_owned()).collect();
+        names.sort();
+        Ok(names)
+    }
+
     pub fn delete(root_path: &Path, pod_name: &str) -> Result<()> {
         let pod_info_path = root_path.join("pods").join(format!("{}", pod_name));
         fs::remove_file(&pod_info_path)?;
@@ -73,14 +131,63 @@ impl PodInfo {
     }
 }
 
-pub fn run_pod(pod_yaml: &str) -> Result<(), anyhow::Error> {
-    let mut task_runner = TaskRunner::from_file(pod_yaml)?;
+// parse a "name: State[ (stage)][: reason]" line of the status section
+fn parse_container_status(line: &str) -> Option<ContainerStatus> {
+    let (name, rest) = line.split_once(": ")?;
+    let (state, reason) = match rest.split_once(": ") {
+        Some((state, reason)) => (state, Some(reason.to_string())),
+        None => (rest, None),
+    };
+    let state = match state {
+        "Created" => ContainerState::Created,
+        "Running" => ContainerState::Running,
+        "Failed (create)" => ContainerState::Failed(FailureStage::Create),
+        "Failed (start)" => ContainerState::Failed(FailureStage::Start),
+        "Failed (rollback)" => ContainerState::Failed(FailureStage::Rollback),
+        "Terminated" => ContainerState::Terminated(None),
+        state => {
+            let code = state.strip_prefix("Terminated (exit code ")?.strip_suffix(')')?;
+            ContainerState::Terminated(Some(code.parse().ok()?))
+        }
+    };
+    Some(ContainerStatus { name: name.to_string(), state, reason })
+}
+
+// a pod that failed without being rolled back is still recorded,
+// so that its status can be inspected and it can be deleted later
+fn save_partial_pod(err: anyhow::Error, task: &task::PodTask) -> anyhow::Error {
+    let pod_name = &task.metadata.name;
+    let Some(run_err) = err.downcast_ref::<PodRunError>() else {
+        return err;
+    };
+    if run_err.rolled_back {
+        return err;
+    }
+    let pod_info = PodInfo {
+        pod_sandbox_id: run_err.pod_sandbox_id.clone(),
+        container_names: run_err.created_containers.clone(),
+        container_statuses: run_err.container_statuses.clone(),
+    };
+    let saved = rootpath::determine(None)
+        .and_then(|root_path| pod_info.save(&root_path, pod_name).and_then(|()| describe::save_spec(&root_path, task)));
+    if let Err(save_err) = saved {
+        warn!("Failed to record partially started Pod {}: {}", pod_name, save_err);
+    } else {
+        info!("Pod {} recorded with failed containers", pod_name);
+    }
+    err
+}
+
+pub fn run_pod(pod_yaml: &str, policy: FailurePolicy, values: &Values) -> Result<(), anyhow::Error> {
+    let mut task_runner = TaskRunner::from_manifest(&values::read_manifest(pod_yaml, values)?)?;
     let pod_name = task_runner.task.metadata.name.clone();
-    let pod_sandbox_id = task_runner.run()?;
+    let pod_sandbox_id = task_runner
+        .run(policy)
+        .map_err(|e| save_partial_pod(e, &task_runner.task))?;
     println!("PodSandbox ID: {}", pod_sandbox_id);
 
-    let container_names: Vec<String> = task_runner.task.spec.containers
-        .iter()
+    let container_names: Vec<String> = task_runner.task.spec
+        .running_containers()
         .map(|c| c.name.clone())
         .collect();
 
@@ -88,50 +195,89 @@ pub fn run_pod(pod_yaml: &str) -> Result<(), anyhow::Error> {
     let pod_info = PodInfo {
         pod_sandbox_id,
         container_names,
+        container_statuses: task_runner.container_statuses.clone(),
     };
     pod_info.save(&root_path, &pod_name)?;
+    describe::save_spec(&root_path, &task_runner.task)?;
 
     println!("Pod {} created and started successfully", pod_name);
     Ok(())
 }
 
-pub fn create_pod(pod_yaml: &str) -> Result<(), anyhow::Error> {
-    let mut task_runner = TaskRunner::from_file(pod_yaml)?;
+pub fn create_pod(pod_yaml: &str, policy: FailurePolicy, values: &Values) -> Result<(), anyhow::Error> {
+    let mut task_runner = TaskRunner::from_manifest(&values::read_manifest(pod_yaml, values)?)?;
     let pod_name = task_runner.task.metadata.name.clone();
+    let span = logging::pod_span(&task_runner.task.metadata.namespace, &pod_name);
+    let _span = span.enter();
 
-    let pod_request = task_runner.build_run_pod_sandbox_request();
+    let pod_request = task_runner.build_run_pod_sandbox_request()?;
     let config = pod_request.config.as_ref().ok_or_else(|| anyhow!("PodSandbox config is required"))?;
     task_runner.sandbox_config = Some(config.clone());
     let pod_response = task_runner.run_pod_sandbox(pod_request)?;
     let pod_sandbox_id = pod_response.pod_sandbox_id;
 
-    let pause_pid = task_runner.pause_pid
-        .ok_or_else(|| anyhow!("Pause container PID not found for PodSandbox ID: {}", pod_sandbox_id))?;
-    println!("PodSandbox (Pause) created: {}, pid: {}\n", pod_sandbox_id, pause_pid);
+    match task_runner.pause_pid {
+        Some(pause_pid) => info!("PodSandbox (Pause) created: {}, pid: {}", pod_sandbox_id, pause_pid),
+        None if task_runner.backend.is_some() => info!("PodSandbox created: {}", pod_sandbox_id),
+        None => return Err(anyhow!("Pause container PID not found for PodSandbox ID: {}", pod_sandbox_id)),
+    }
 
-    let mut container_ids = Vec::new();
-    for container in &task_runner.task.spec.containers {
-        let create_request = task_runner.build_create_container_request(
-            &pod_sandbox_id,
-            container,
-        )?;
-        let create_response = task_runner.create_container(create_request)?;
-        container_ids.push(create_response.container_id.clone());
-        println!("Container created: {} (ID: {})", container.name, create_response.container_id);
+    let (container_ids, mut failures) = task_runner.create_containers(&pod_sandbox_id, policy)?;
+    if !failures.is_empty() {
+        let rolled_back = policy == FailurePolicy::Rollback;
+        if rolled_back {
+            failures.extend(task_runner.rollback(&pod_sandbox_id, &container_ids));
+        }
+        let err = PodRunError {
+            pod_name: pod_name.clone(),
+            pod_sandbox_id,
+            created_containers: container_ids,
+            container_statuses: task_runner.container_statuses.clone(),
+            failures,
+            rolled_back,
+        };
+        return Err(save_partial_pod(err.into(), &task_runner.task));
     }
 
     let root_path = rootpath::determine(None)?;
     let pod_info = PodInfo {
         pod_sandbox_id,
         container_names: container_ids,
+        container_statuses: task_runner.container_statuses.clone(),
     };
     pod_info.save(&root_path, &pod_name)?;
+    describe::save_spec(&root_path, &task_runner.task)?;
 
     println!("Pod {} created successfully", pod_name);
     Ok(())
 }
 
+// `rkl run|create --dry-run`: the CRI requests of the pod built and checked
+// without being sent, with server also the images checked with the runtime
+pub fn dry_run_pod(pod_yaml: &str, values: &Values, mode: DryRun, json: bool) -> Result<(), anyhow::Error> {
+    let mut task_runner = TaskRunner::from_manifest(&values::read_manifest(pod_yaml, values)?)?;
+    let pod_name = task_runner.task.metadata.name.clone();
+    let root_path = rootpath::determine(None)?;
+    if PodInfo::load(&root_path, &pod_name).is_ok() {
+        return Err(anyhow!("Pod {} already exists", pod_name));
+    }
+    let requests = task_runner.dry_run_requests()?;
+    if mode == DryRun::Server {
+        for image in task_runner.check_images()? {
+            info!("Image {} would be pulled", image);
+        }
+    }
+    if json {
+        dryrun::print_json(&requests);
+    } else {
+        println!("Pod {} created{}", pod_name, dryrun::suffix(Some(mode)));
+    }
+    Ok(())
+}
+
 pub fn start_pod(pod_name: &str) -> Result<(), anyhow::Error> {
+    let span = logging::pod_span("", pod_name);
+    let _span = span.enter();
     let root_path = rootpath::determine(None)?;
     let pod_info = PodInfo::load(&root_path, pod_name)?;
 
@@ -145,7 +291,7 @@ pub fn start_pod(pod_name: &str) -> Result<(), anyhow::Error> {
         };
         start::start(start_args, root_path.clone())
             .map_err(|e| anyhow!("Failed to start container {}: {}", container_name, e))?;
-        println!("Container started: {}", container_name);
+        info!("Container started: {}", container_name);
     }
 
     println!("Pod {} started successfully", pod_name);
@@ -153,23 +299,137 @@ pub fn start_pod(pod_name: &str) -> Result<(), anyhow::Error> {
 }
 
 pub fn delete_pod(pod_name: &str) -> Result<(), anyhow::Error> {
+    delete_pod_with_grace_period(pod_name, None)
+}
+
+// the pods whose labels match the selector, pods recorded without their spec
+// have no labels
+fn selected_pods(root_path: &Path, selector: &Selector) -> Result<Vec<String>> {
+    Ok(PodInfo::names(root_path)?
+        .into_iter()
+        .filter(|name| {
+            let labels = describe::load_spec(root_path, name).map(|task| task.metadata.labels).unwrap_or_default();
+            selector.matches(&labels)
+        })
+        .collect())
+}
+
+// `rkl delete -l <selector>`
+pub fn delete_selected(selector: &Selector, grace_period: Option<u64>, dry_run: Option<DryRun>, json: bool) -> Result<(), anyhow::Error> {
+    let root_path = rootpath::determine(None)?;
+    let pods = selected_pods(&root_path, selector)?;
+    if pods.is_empty() {
+        println!("No pods match {}", selector);
+    }
+    for pod_name in pods {
+        match dry_run {
+            Some(mode) => dry_run_delete(&pod_name, mode, json)?,
+            None => delete_pod_with_grace_period(&pod_name, grace_period)?,
+        }
+    }
+    Ok(())
+}
+
+// `rkl delete --dry-run`: the CRI requests that would remove the sandbox, with
+// server the runtime checked for still having it
+pub fn dry_run_delete(pod_name: &str, mode: DryRun, json: bool) -> Result<(), anyhow::Error> {
+    let root_path = rootpath::determine(None)?;
+    let pod_info = PodInfo::load(&root_path, pod_name)?;
+    let pod_sandbox_id = pod_info.pod_sandbox_id.clone();
+    if mode == DryRun::Server {
+        match runtime::backend()? {
+            Some(backend) => {
+                backend.pod_sandbox_status(PodSandboxStatusRequest { pod_sandbox_id: pod_sandbox_id.clone(), verbose: false })?;
+            }
+            None => {
+                load_container(&root_path, &pod_sandbox_id)?;
+            }
+        }
+    }
+    if json {
+        dryrun::print_json(&[
+            dryrun::Request::new("StopPodSandbox", &StopPodSandboxRequest { pod_sandbox_id: pod_sandbox_id.clone() }),
+            dryrun::Request::new("RemovePodSandbox", &RemovePodSandboxRequest { pod_sandbox_id }),
+        ]);
+    } else {
+        println!("Pod {} deleted{}", pod_name, dryrun::suffix(Some(mode)));
+    }
+    Ok(())
+}
+
+// grace_period overrides the terminationGracePeriodSeconds of the pod
+pub fn delete_pod_with_grace_period(pod_name: &str, grace_period: Option<u64>) -> Result<(), anyhow::Error> {
+    remove_pod(pod_name, grace_period)?;
+    println!("Pod {} deleted successfully", pod_name);
+    Ok(())
+}
+
+// stop the containers of the pod and remove it with everything recorded of it
+pub fn remove_pod(pod_name: &str, grace_period: Option<u64>) -> Result<(), anyhow::Error> {
+    let span = logging::pod_span("", pod_name);
+    let _span = span.enter();
     let root_path = rootpath::determine(None)?;
     let pod_info = PodInfo::load(&root_path, pod_name)?;
 
+    // the containers stop gracefully first, pods recorded without their spec
+    // are removed right away
+    let spec = describe::load_spec(&root_path, pod_name);
+    let ephemeral: Vec<String> =
+        spec.iter().flat_map(|task| &task.spec.ephemeral_containers).map(|container| container.name.clone()).collect();
+    if let Some(task) = spec {
+        let runner = TaskRunner {
+            task,
+            pause_pid: None,
+            sandbox_config: None,
+            container_statuses: Vec::new(),
+            network_status: None,
+            backend: runtime::backend()?,
+        };
+        let container_names: Vec<String> = pod_info.container_names.iter().chain(&ephemeral).cloned().collect();
+        runner.terminate(&pod_info.pod_sandbox_id, &container_names, grace_period);
+    }
+
+    if let Some(backend) = runtime::backend()? {
+        // the runtime removes the containers with their sandbox
+        let pod_sandbox_id = pod_info.pod_sandbox_id.clone();
+        backend.stop_pod_sandbox(StopPodSandboxRequest { pod_sandbox_id: pod_sandbox_id.clone() })?;
+        backend.remove_pod_sandbox(RemovePodSandboxRequest { pod_sandbox_id: pod_sandbox_id.clone() })?;
+        info!("PodSandbox deleted: {}", pod_sandbox_id);
+        // e.g. the termination logs of the containers
+        if let Err(err) = task::remove_sandbox_dir(&root_path, &pod_sandbox_id) {
+            warn!("Failed to remove files of PodSandbox {}: {}", pod_sandbox_id, err);
+        }
+        if let Err(err) = events::remove(&root_path, pod_name) {
+            warn!("Failed to remove events of Pod {}: {}", pod_name, err);
+        }
+        if let Err(err) = logs::remove_pod_logs(pod_name) {
+            warn!("Failed to remove logs of Pod {}: {}", pod_name, err);
+        }
+        if let Err(err) = describe::remove_spec(&root_path, pod_name) {
+            warn!("Failed to remove spec of Pod {}: {}", pod_name, err);
+        }
+        PodInfo::delete(&root_path, pod_name)?;
+        return Ok(());
+    }
+
     // delete all container
-    for container_name in &pod_info.container_names {
+    for container_name in pod_info.container_names.iter().chain(&ephemeral) {
         let delete_args = Delete {
             container_id: container_name.clone(),
             force: true, 
         };
         let root_path = rootpath::determine(None)?;
         if let Err(delete_err) = delete::delete(delete_args, root_path.clone()) {
-            eprintln!("Failed to delete container {}: {}", container_name, delete_err);
+            warn!("Failed to delete container {}: {}", container_name, delete_err);
         } else {
-            println!("Container deleted: {}", container_name);
+            info!("Container deleted: {}", container_name);
         }
     }
 
+    if let Err(err) = cni::teardown(&root_path, &pod_info.pod_sandbox_id) {
+        warn!("Failed to detach network of PodSandbox {}: {}", pod_info.pod_sandbox_id, err);
+    }
+
     // delete pause container
     let delete_args = Delete {
         container_id: pod_info.pod_sandbox_id.clone(),
@@ -177,14 +437,233 @@ pub fn delete_pod(pod_name: &str) -> Result<(), anyhow::Error> {
     };
     let root_path = rootpath::determine(None)?;
     if let Err(delete_err) = delete::delete(delete_args, root_path.clone()) {
-        eprintln!("Failed to delete PodSandbox {}: {}", pod_info.pod_sandbox_id, delete_err);
+        warn!("Failed to delete PodSandbox {}: {}", pod_info.pod_sandbox_id, delete_err);
     } else {
-        println!("PodSandbox deleted: {}", pod_info.pod_sandbox_id);
+        info!("PodSandbox deleted: {}", pod_info.pod_sandbox_id);
+    }
+
+    if let Err(err) = device::release_pod(&root_path, pod_name) {
+        warn!("Failed to release devices of Pod {}: {}", pod_name, err);
+    }
+    if let Err(err) = cpumanager::release_pod(&root_path, pod_name) {
+        warn!("Failed to release CPUs of Pod {}: {}", pod_name, err);
+    }
+    if let Err(err) = task::remove_sandbox_dir(&root_path, &pod_info.pod_sandbox_id) {
+        warn!("Failed to remove files of PodSandbox {}: {}", pod_info.pod_sandbox_id, err);
+    }
+    if let Err(err) = events::remove(&root_path, pod_name) {
+        warn!("Failed to remove events of Pod {}: {}", pod_name, err);
+    }
+    if let Err(err) = logs::remove_pod_logs(pod_name) {
+        warn!("Failed to remove logs of Pod {}: {}", pod_name, err);
+    }
+    if let Err(err) = describe::remove_spec(&root_path, pod_name) {
+        warn!("Failed to remove spec of Pod {}: {}", pod_name, err);
     }
 
     // delete pod file 
     PodInfo::delete(&root_path, pod_name)?;
-    println!("Pod {} deleted successfully", pod_name);
+    ratelimit::limiter().forget_pod(pod_name);
+    Ok(())
+}
+
+// the container of the pod by name, the error says which pod lacks it
+fn find_container<'a>(pod_info: &'a PodInfo, pod_name: &str, container: &str) -> Result<&'a String> {
+    pod_info.container_names
+        .iter()
+        .find(|c| c.as_str() == container)
+        .ok_or_else(|| anyhow!("Container {} not found in Pod {}", container, pod_name))
+}
+
+// recreate a container of the pod from the spec it was run with, like the
+// daemon does for its restart policy; only the built-in runtime can
+pub fn restart_container(pod_name: &str, container: &str) -> Result<(), anyhow::Error> {
+    let span = logging::pod_span("", pod_name);
+    let _span = span.enter();
+    let root_path = rootpath::determine(None)?;
+    let pod_info = PodInfo::load(&root_path, pod_name)?;
+    let container_name = find_container(&pod_info, pod_name, container)?;
+    let task = describe::load_spec(&root_path, pod_name)
+        .ok_or_else(|| anyhow!("Pod {} was recorded without its spec", pod_name))?;
+    let mut runner = TaskRunner {
+        task,
+        pause_pid: None,
+        sandbox_config: None,
+        container_statuses: Vec::new(),
+        network_status: None,
+        backend: runtime::backend()?,
+    };
+    runner.restart_container(&pod_info.pod_sandbox_id, container_name)?;
+    PodInfo::update_status(&root_path, pod_name, ContainerStatus::new(container_name, ContainerState::Running))
+}
+
+// run a command in a container of the pod and wait for it, the exit code with
+// what it wrote to stdout and stderr
+pub fn exec_in_container(pod_name: &str, container: &str, command: Vec<String>, timeout: Duration) -> Result<(i32, Vec<u8>)> {
+    let root_path = rootpath::determine(None)?;
+    let pod_info = PodInfo::load(&root_path, pod_name)?;
+    let container_id = find_container(&pod_info, pod_name, container)?.clone();
+    match runtime::backend()? {
+        Some(backend) => {
+            let request = ExecSyncRequest { container_id, cmd: command, timeout: timeout.as_secs() as i64 };
+            let response = debug::traced("ExecSync", request, |request| backend.exec_sync(request))?;
+            let mut output = response.stdout;
+            output.extend(response.stderr);
+            Ok((response.exit_code, output))
+        }
+        None => {
+            let (code, output) = exec::exec_output(&container_id, command, root_path, timeout)?;
+            // an exit code reaped by somebody else is taken for a success
+            Ok((code.unwrap_or(0), output))
+        }
+    }
+}
+
+// deliver a signal to the init process of one container of the pod,
+// or of every container when none is given
+pub fn kill_pod(target: &str, container: Option<&str>, signal: &str) -> Result<(), anyhow::Error> {
+    // accept both "pod/<name>" and "<name>"
+    let pod_name = target.strip_prefix("pod/").unwrap_or(target);
+    let root_path = rootpath::determine(None)?;
+    let pod_info = PodInfo::load(&root_path, pod_name)?;
+
+    let containers: Vec<&String> = match container {
+        Some(container) => {
+            let name = pod_info.container_names
+                .iter()
+                .find(|c| c.as_str() == container)
+                .ok_or_else(|| anyhow!("Container {} not found in Pod {}", container, pod_name))?;
+            vec![name]
+        }
+        None => pod_info.container_names.iter().collect(),
+    };
+
+    for container_name in containers {
+        let kill_args = Kill {
+            container_id: container_name.clone(),
+            signal: signal.to_string(),
+            all: false,
+        };
+        kill::kill(kill_args, root_path.clone())
+            .map_err(|e| anyhow!("Failed to send {} to container {}: {}", signal, container_name, e))?;
+        // other signals, e.g. SIGHUP to reload, don't stop the container
+        let stopping = ["TERM", "KILL", "INT", "QUIT", "15", "9", "2", "3"];
+        if stopping.contains(&signal.to_ascii_uppercase().trim_start_matches("SIG")) {
+            let message = format!("Stopping container {} with {}", container_name, signal);
+            EventRecorder::new(&root_path, pod_name).normal(events::KILLING, &message);
+        }
+        println!("Signal {} sent to container {}", signal, container_name);
+    }
+    Ok(())
+}
+
+pub fn attach_pod(target: &str, container: Option<&str>, stdin: bool, tty: bool) -> Result<(), anyhow::Error> {
+    let pod_name = target.strip_prefix("pod/").unwrap_or(target);
+    let root_path = rootpath::determine(None)?;
+    let pod_info = PodInfo::load(&root_path, pod_name)?;
+
+    // ephemeral containers can be attached to as well
+    let ephemeral = describe::load_spec(&root_path, pod_name).map(|task| task.spec.ephemeral_containers).unwrap_or_default();
+    let container_name = match container {
+        Some(container) => pod_info.container_names
+            .iter()
+            .chain(ephemeral.iter().map(|c| &c.name))
+            .find(|c| c.as_str() == container)
+            .ok_or_else(|| anyhow!("Container {} not found in Pod {}", container, pod_name))?,
+        None => pod_info.container_names
+            .first()
+            .ok_or_else(|| anyhow!("Pod {} has no containers", pod_name))?,
+    };
+
+    let request = AttachRequest {
+        container_id: container_name.clone(),
+        stdin,
+        tty,
+        stdout: true,
+        // a tty merges stderr into stdout
+        stderr: !tty,
+    };
+    let response = {
+        let _permit = ratelimit::limiter().acquire(pod_name);
+        debug::traced("Attach", &request, |request| stream::attach(&root_path, request))?
+    };
+    let connection = stream::connect(&response.url, &request)?;
+    if tty {
+        eprintln!("Attached to container {}, press ctrl-p ctrl-q to detach", container_name);
+    }
+    stream::client::run(connection, stdin, tty)
+}
+
+// `rkl debug`: run an ephemeral container in the pod and attach to it when
+// it was asked for stdin or a tty
+pub fn debug_pod(
+    target: &str,
+    image: &str,
+    container: Option<&str>,
+    target_container: Option<&str>,
+    stdin: bool,
+    tty: bool,
+    command: Vec<String>,
+) -> Result<(), anyhow::Error> {
+    let pod_name = target.strip_prefix("pod/").unwrap_or(target);
+    let span = logging::pod_span("", pod_name);
+    let _span = span.enter();
+    let root_path = rootpath::determine(None)?;
+    let pod_info = PodInfo::load(&root_path, pod_name)?;
+    let task = describe::load_spec(&root_path, pod_name)
+        .ok_or_else(|| anyhow!("Pod {} was recorded without its spec", pod_name))?;
+    let name = match container {
+        Some(name) => name.to_string(),
+        None => format!("debugger-{}", &uuid::Uuid::new_v4().simple().to_string()[..5]),
+    };
+    let spec = ephemeral::container(&name, image, command, target_container, stdin, tty)?;
+    let mut runner = TaskRunner {
+        task,
+        pause_pid: None,
+        sandbox_config: None,
+        container_statuses: Vec::new(),
+        network_status: None,
+        backend: runtime::backend()?,
+    };
+    runner.run_ephemeral_container(&pod_info.pod_sandbox_id, spec)?;
+    describe::save_spec(&root_path, &runner.task)?;
+    if !stdin && !tty {
+        println!("Ephemeral container {} started in Pod {}, attach with: rkl attach {} -c {} -it", name, pod_name, pod_name, name);
+        return Ok(());
+    }
+    attach_pod(pod_name, Some(&name), stdin, tty)
+}
+
+// forward local ports to ports of the pod until interrupted
+pub fn port_forward_pod(target: &str, port_specs: &[String], address: &str) -> Result<(), anyhow::Error> {
+    let pod_name = target.strip_prefix("pod/").unwrap_or(target);
+    let root_path = rootpath::determine(None)?;
+    let pod_info = PodInfo::load(&root_path, pod_name)?;
+
+    let ports = port_specs
+        .iter()
+        .map(|spec| portforward::parse_port_spec(spec))
+        .collect::<Result<Vec<_>>>()?;
+    let request = PortForwardRequest {
+        pod_sandbox_id: pod_info.pod_sandbox_id.clone(),
+        port: ports.iter().map(|(_, remote)| *remote as i32).collect(),
+    };
+    let response = {
+        let _permit = ratelimit::limiter().acquire(pod_name);
+        debug::traced("PortForward", &request, |request| portforward::port_forward(&root_path, request))?
+    };
+
+    let mut forwarders = Vec::new();
+    for (local, remote) in ports {
+        let listener = TcpListener::bind((address, local))
+            .map_err(|e| anyhow!("Failed to listen on {}:{}: {}", address, local, e))?;
+        println!("Forwarding from {} -> {}", listener.local_addr()?, remote);
+        let url = response.url.clone();
+        forwarders.push(thread::spawn(move || portforward::forward(listener, url, remote)));
+    }
+    for forwarder in forwarders {
+        let _ = forwarder.join();
+    }
     Ok(())
 }
 
@@ -195,12 +674,268 @@ pub fn state_pod(pod_name: &str) -> Result<(), anyhow::Error> {
     println!("Pod: {}", pod_name);
 
     println!("PodSandbox ID: {}", pod_info.pod_sandbox_id);
+    if let Some(ip) = cni::pod_ip(&root_path, &pod_info.pod_sandbox_id) {
+        println!("IP: {}", ip);
+    }
     state::state(State { container_id: pod_info.pod_sandbox_id.clone() }, root_path.clone());
-    
+    let pod_info = record_terminations(&root_path, pod_name, pod_info);
+
     println!("Containers:");
     for container_name in &pod_info.container_names {
         let container_state = state::state(State { container_id: container_name.clone() }, root_path.clone());
     }
 
+    if !pod_info.container_statuses.is_empty() {
+        println!("Container Status:");
+        for status in &pod_info.container_statuses {
+            println!("  {}: {}", status.name, status);
+        }
+    }
+
+    let events = events::list(&root_path, pod_name)?;
+    if !events.is_empty() {
+        for line in events::describe(&events, events::now()) {
+            println!("{}", line);
+        }
+    }
+
+    Ok(())
+}
+
+// record the termination message of the containers of the built-in runtime that
+// exited since, without `rkl daemon` nobody else notices; their exit code is unknown
+fn record_terminations(root_path: &Path, pod_name: &str, pod_info: PodInfo) -> PodInfo {
+    let Some(task) = describe::load_spec(root_path, pod_name) else {
+        return pod_info;
+    };
+    if runtime::backend().ok().flatten().is_some() {
+        return pod_info;
+    }
+    for container in task.spec.running_containers() {
+        let recorded = pod_info.container_statuses.iter().find(|status| status.name == container.name);
+        if recorded.is_some_and(|status| matches!(status.state, ContainerState::Terminated(_))) {
+            continue;
+        }
+        let stopped = load_container(root_path, &container.name).is_ok_and(|c| c.status() == RuntimeStatus::Stopped);
+        if !stopped {
+            continue;
+        }
+        let message = termination::message(root_path, pod_name, &pod_info.pod_sandbox_id, container, None);
+        let status = ContainerStatus::terminated(&container.name, None, message.as_deref());
+        if let Err(e) = PodInfo::update_status(root_path, pod_name, status) {
+            warn!("Failed to record the termination of container {}: {}", container.name, e);
+        }
+    }
+    PodInfo::load(root_path, pod_name).unwrap_or(pod_info)
+}
+
+// `rkl describe pod -l <selector>`, one pod after the other
+pub fn describe_selected(selector: &Selector) -> Result<(), anyhow::Error> {
+    let root_path = rootpath::determine(None)?;
+    let pods = selected_pods(&root_path, selector)?;
+    if pods.is_empty() {
+        println!("No pods match {}", selector);
+    }
+    for (i, pod_name) in pods.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        describe_pod(pod_name, false, Duration::ZERO)?;
+    }
     Ok(())
-}
\ No newline at end of file
+}
+
+pub fn describe_pod(target: &str, watch_usage: bool, interval: Duration) -> Result<(), anyhow::Error> {
+    let pod_name = target.strip_prefix("pod/").unwrap_or(target);
+    let root_path = rootpath::determine(None)?;
+    let pod_info = PodInfo::load(&root_path, pod_name)?;
+
+    let task = describe::load_spec(&root_path, pod_name);
+    let ip = cni::pod_ip(&root_path, &pod_info.pod_sandbox_id).map(|ip| ip.to_string());
+    let backend = runtime::backend()?;
+    let report = match backend.as_deref() {
+        Some(backend) => describe::pod_report(pod_name, &pod_info, task.as_ref(), ip.as_deref(), &describe::CriState(backend)),
+        None => describe::pod_report(pod_name, &pod_info, task.as_ref(), ip.as_deref(), &describe::BuiltinState(&root_path)),
+    };
+    for line in report {
+        println!("{}", line);
+    }
+    for line in events::describe(&events::list(&root_path, pod_name)?, events::now()) {
+        println!("{}", line);
+    }
+    if !watch_usage {
+        return Ok(());
+    }
+
+    // redraw the section in place on a terminal, append it otherwise
+    let redraw = io::stdout().is_terminal();
+    let sample = |name: &String| stats::collect(&root_path, name).ok();
+    let mut earlier: Vec<Option<ContainerStats>> = pod_info.container_names.iter().map(sample).collect();
+    let mut drawn = 0;
+    // the first rates come sooner than the interval
+    thread::sleep(interval.min(Duration::from_secs(1)));
+    loop {
+        if PodInfo::load(&root_path, pod_name).is_err() {
+            println!("Pod {} was deleted", pod_name);
+            return Ok(());
+        }
+        let later: Vec<Option<ContainerStats>> = pod_info.container_names.iter().map(sample).collect();
+        let mut lines = vec![format!("Resources (every {:?}, ctrl-c to stop):", interval)];
+        for ((name, earlier), later) in pod_info.container_names.iter().zip(&earlier).zip(&later) {
+            lines.push(format!("  {}:", name));
+            match (earlier, later) {
+                (Some(earlier), Some(later)) => lines.extend(usage_lines(earlier, later)),
+                _ => lines.push("    not running".to_string()),
+            }
+        }
+        let mut stdout = io::stdout().lock();
+        if redraw && drawn > 0 {
+            // back to the start of the previous section and clear it
+            write!(stdout, "\x1b[{}A\x1b[J", drawn)?;
+        }
+        for line in &lines {
+            writeln!(stdout, "{}", line)?;
+        }
+        stdout.flush()?;
+        drawn = lines.len();
+        drop(stdout);
+
+        earlier = later;
+        thread::sleep(interval);
+    }
+}
+
+// `rkl top pod` and `rkl top container`: the usage of every pod, of one or of the
+// ones matching a selector, and per container with containers, from the CRI
+// stats of the runtime
+pub fn top(
+    pod: Option<&str>,
+    selector: Option<&Selector>,
+    containers: bool,
+    watch: bool,
+    interval: Duration,
+) -> Result<(), anyhow::Error> {
+    let root_path = rootpath::determine(None)?;
+    let pods: Vec<(String, PodInfo)> = match pod {
+        Some(target) => {
+            let pod_name = target.strip_prefix("pod/").unwrap_or(target);
+            vec![(pod_name.to_string(), PodInfo::load(&root_path, pod_name)?)]
+        }
+        None => selected_pods(&root_path, selector.unwrap_or(&Selector::default()))?
+            .into_iter()
+            .filter_map(|name| PodInfo::load(&root_path, &name).ok().map(|info| (name, info)))
+            .collect(),
+    };
+    let backend = runtime::backend()?;
+    let mut sampler = stats::UsageSampler::default();
+    let mut sample = || -> Vec<(String, Vec<(String, stats::Usage)>)> {
+        pods.iter()
+            .map(|(name, info)| {
+                let usages = sampler.sample(&root_path, backend.as_deref(), &info.pod_sandbox_id, &info.container_names);
+                (name.clone(), usages)
+            })
+            .collect()
+    };
+    // the cpu is a rate between two samples
+    sample();
+    thread::sleep(interval.min(Duration::from_secs(1)));
+    let redraw = watch && io::stdout().is_terminal();
+    loop {
+        let mut lines = Vec::new();
+        let row = |name: &str, usage: &stats::Usage| {
+            format!(
+                "{:<16}{:<12}{:<16}{}",
+                name,
+                format!("{}m", usage.cpu_millis),
+                stats::format_bytes(usage.memory_bytes),
+                stats::format_bytes(usage.fs_bytes)
+            )
+        };
+        for (pod_name, usages) in sample() {
+            if containers {
+                for (container, usage) in &usages {
+                    lines.push(format!("{:<24}{}", pod_name, row(container, usage)));
+                }
+            } else {
+                let mut total = stats::Usage::default();
+                for (_, usage) in usages {
+                    total += usage;
+                }
+                lines.push(row(&pod_name, &total));
+            }
+        }
+        let header = row_header(containers);
+        let mut stdout = io::stdout().lock();
+        if redraw {
+            // clear the screen and start at the top
+            write!(stdout, "\x1b[H\x1b[2J")?;
+        }
+        writeln!(stdout, "{}", header)?;
+        for line in &lines {
+            writeln!(stdout, "{}", line)?;
+        }
+        stdout.flush()?;
+        drop(stdout);
+        if !watch {
+            return Ok(());
+        }
+        thread::sleep(interval);
+    }
+}
+
+fn row_header(containers: bool) -> String {
+    let header = format!("{:<16}{:<12}{:<16}{}", "NAME", "CPU(cores)", "MEMORY(bytes)", "FS(bytes)");
+    if containers { format!("{:<24}{}", "POD", header) } else { header }
+}
+
+// the cpu and memory lines of a container, current against limit
+fn usage_lines(earlier: &ContainerStats, later: &ContainerStats) -> Vec<String> {
+    const WIDTH: usize = 20;
+    let cpu = later.cpu_millis(earlier);
+    let throttled = later.throttled_percent(earlier);
+    let cpu = match later.cpu_limit {
+        Some(limit) => format!("{} {}m/{}m, throttled {}%", stats::bar(cpu, limit, WIDTH), cpu, limit, throttled),
+        None => format!("{}m (no limit)", cpu),
+    };
+    let memory = match later.memory_limit {
+        Some(limit) => format!(
+            "{} {}/{}",
+            stats::bar(later.memory_usage, limit, WIDTH),
+            stats::format_bytes(later.memory_usage),
+            stats::format_bytes(limit)
+        ),
+        None => format!("{} (no limit)", stats::format_bytes(later.memory_usage)),
+    };
+    vec![format!("    cpu:     {}", cpu), format!("    memory:  {}", memory)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pod_info_status_roundtrip() {
+        let root = tempfile::tempdir().unwrap();
+        let pod_info = PodInfo {
+            pod_sandbox_id: "pod1".to_string(),
+            container_names: vec!["c1".to_string()],
+            container_statuses: vec![
+                ContainerStatus::new("c1", ContainerState::Running),
+                ContainerStatus::failed("c2", FailureStage::Create, &anyhow!("Bundle directory does not exist")),
+                ContainerStatus::terminated("c3", Some(137), Some("OOMKilled:\n  out of memory")),
+                ContainerStatus::terminated("c4", None, None),
+            ],
+        };
+        pod_info.save(root.path(), "pod1").unwrap();
+
+        let loaded = PodInfo::load(root.path(), "pod1").unwrap();
+        assert_eq!(loaded.pod_sandbox_id, "pod1");
+        assert_eq!(loaded.container_names, vec!["c1".to_string()]);
+        assert_eq!(loaded.container_statuses, pod_info.container_statuses);
+        assert_eq!(loaded.container_statuses[2].to_string(), "Terminated (exit code 137): OOMKilled: out of memory");
+
+        PodInfo::update_status(root.path(), "pod1", ContainerStatus::new("c3", ContainerState::Running)).unwrap();
+        let loaded = PodInfo::load(root.path(), "pod1").unwrap();
+        assert_eq!(loaded.container_statuses[2], ContainerStatus::new("c3", ContainerState::Running));
+    }
+}
@@ -0,0 +1,844 @@
+use std::fs;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tonic::Code;
+use tonic::codegen::InterceptedService;
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::service::Interceptor;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint};
+use crate::daemon::scheduler::scheduler_client::SchedulerClient;
+use crate::daemon::scheduler::{
+    CordonNodeRequest, CreateNamespaceRequest, DeleteNamespaceRequest, DeletePodRequest, EvictPodRequest, GetPodRequest,
+    GetPodResponse, ListNamespacesRequest, ListNodesRequest, MigratePodRequest, SchedulePodRequest,
+};
+use crate::logging::Propagate;
+use crate::apply;
+use crate::dryrun::{self, DryRun};
+use crate::kustomize;
+use crate::printer::{Column, Listing, OutputArgs};
+use crate::selector::{FieldSelector, Selector};
+use crate::task::task::{PodTask, default_namespace};
+use crate::strict;
+use crate::validate;
+use crate::values::Values;
+
+// The clusters rkl manages, each an rks scheduler, as contexts of the context
+// configuration:
+//
+//   current: edge-1
+//   contexts:
+//     - name: edge-1
+//       server: http://10.0.0.1:7500
+//       groups: [edge]
+//     - name: edge-2
+//       server: http://10.0.1.1:7500
+//       groups: [edge]
+//       token_file: /etc/rk8s/edge-2.token
+//     - name: core
+//       server: https://10.0.2.1:7500
+//       ca_file: /etc/rk8s/core-ca.pem
+//
+// which `rkl context` edits, the token of token_file is sent to an rks that
+// requires one, see rks --token-file, and the certificate of an rks served over
+// https is verified with the CA of ca_file, see rks --tls-cert. --context picks the clusters of `rkl
+// get`, `rkl apply` and `rkl delete`: a context, a group, a comma separated
+// list of them or all, the current context without it. A command goes to every cluster at
+// once and reports what each of them answered, one that fails or doesn't
+// answer in time doesn't hold back the others but fails the command.
+// Pods are of a namespace of the cluster, `rkl create namespace` adds one to
+// every selected cluster and `rkl get pods -n` lists the pods of one.
+// `rkl get` builds a v1 object of everything it lists, from what rks and the
+// nodes report, for --field-selector to match and -o to print instead of the
+// table, see printer. `rkl migrate` has rks move a pod to another node of its
+// cluster with the state of its containers, see checkpoint. `rkl cordon` stops
+// rks from scheduling onto a node and `rkl drain` also evicts its pods, but
+// those of the daemon sets, onto the other nodes: each is scheduled elsewhere
+// before it is deleted from the node, which stops it within its grace period,
+// and the drain waits for the node to no longer report them. An eviction the
+// pod disruption budgets of rks refuse is retried until they allow it.
+
+pub const DEFAULT_CONFIG_PATH: &str = "/etc/rk8s/contexts.yaml";
+const ALL: &str = "all";
+const TIMEOUT: Duration = Duration::from_secs(10);
+// a migration checkpoints, transfers and restores the containers of a pod
+const MIGRATE_TIMEOUT: Duration = Duration::from_secs(600);
+// an eviction creates the pod on another node and deletes it from its own
+const EVICT_TIMEOUT: Duration = Duration::from_secs(60);
+// how often a drain checks whether the node still runs the evicted pods
+const DRAIN_POLL: Duration = Duration::from_secs(2);
+// the label rks gives the pods of its daemon sets
+const DAEMONSET_NAME: &str = "daemonset-name";
+
+static CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+static SELECTION: OnceLock<Option<String>> = OnceLock::new();
+
+// set once at startup from --context-config and --context
+pub fn init(config_path: PathBuf, selection: Option<String>) {
+    let _ = CONFIG_PATH.set(config_path);
+    let _ = SELECTION.set(selection);
+}
+
+fn config_path() -> &'static Path {
+    CONFIG_PATH.get().map(PathBuf::as_path).unwrap_or(Path::new(DEFAULT_CONFIG_PATH))
+}
+
+// whether clusters were picked explicitly with --context
+pub fn targeted() -> bool {
+    SELECTION.get().is_some_and(Option::is_some)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Context {
+    pub name: String,
+    // url of the rks scheduler
+    pub server: String,
+    #[serde(default)]
+    pub groups: Vec<String>,
+    // bearer token for rks, kept in its own file rather than in the configuration
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_file: Option<PathBuf>,
+    // CA certificate of an rks served over https
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ca_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ContextConfig {
+    #[serde(default)]
+    pub current: Option<String>,
+    #[serde(default)]
+    pub contexts: Vec<Context>,
+}
+
+impl ContextConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(ContextConfig::default());
+        }
+        let contents = fs::read_to_string(path)?;
+        serde_yaml::from_str(&contents).map_err(|e| anyhow!("Invalid context configuration {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_yaml::to_string(self)?)?;
+        Ok(())
+    }
+
+    // the contexts of a selection, in the order of the configuration
+    pub fn resolve(&self, selection: Option<&str>) -> Result<Vec<Context>> {
+        let selection = match selection {
+            Some(selection) => selection,
+            None => self.current.as_deref().ok_or_else(|| {
+                anyhow!("No current context, set one with `rkl context use` or pass --context")
+            })?,
+        };
+        let mut selected: Vec<&Context> = Vec::new();
+        for term in selection.split(',').map(str::trim).filter(|term| !term.is_empty()) {
+            let matching: Vec<&Context> = self.contexts
+                .iter()
+                .filter(|context| term == ALL || context.name == term || context.groups.iter().any(|group| group == term))
+                .collect();
+            if matching.is_empty() {
+                return Err(anyhow!("No context or group named {}", term));
+            }
+            selected.extend(matching);
+        }
+        let mut contexts: Vec<Context> = Vec::new();
+        for context in self.contexts.iter().filter(|context| selected.contains(context)) {
+            contexts.push(context.clone());
+        }
+        if contexts.is_empty() {
+            return Err(anyhow!("No context selected"));
+        }
+        Ok(contexts)
+    }
+}
+
+// the trace context and the token of the context with every request
+#[derive(Clone)]
+struct Credentials {
+    propagate: Propagate,
+    authorization: Option<MetadataValue<Ascii>>,
+}
+
+impl Credentials {
+    fn new(context: &Context, propagate: Propagate) -> Result<Self> {
+        let authorization = match &context.token_file {
+            Some(path) => {
+                let token = fs::read_to_string(path).map_err(|e| anyhow!("Failed to read token file {}: {}", path.display(), e))?;
+                let value = format!("Bearer {}", token.trim()).parse().map_err(|_| anyhow!("Invalid token in {}", path.display()))?;
+                Some(value)
+            }
+            None => None,
+        };
+        Ok(Credentials { propagate, authorization })
+    }
+}
+
+impl Interceptor for Credentials {
+    fn call(&mut self, request: tonic::Request<()>) -> Result<tonic::Request<()>, tonic::Status> {
+        let mut request = self.propagate.call(request)?;
+        if let Some(authorization) = &self.authorization {
+            request.metadata_mut().insert("authorization", authorization.clone());
+        }
+        Ok(request)
+    }
+}
+
+type Client = SchedulerClient<InterceptedService<Channel, Credentials>>;
+
+async fn connect(server: &str, ca_file: Option<&Path>, credentials: Credentials, timeout: Duration) -> Result<Client> {
+    let mut endpoint = Endpoint::from_shared(server.to_string()).map_err(|e| anyhow!("Invalid server {}: {}", server, e))?;
+    if let Some(path) = ca_file {
+        let ca = fs::read(path).map_err(|e| anyhow!("Failed to read CA file {}: {}", path.display(), e))?;
+        endpoint = endpoint
+            .tls_config(ClientTlsConfig::new().ca_certificate(Certificate::from_pem(ca)))
+            .map_err(|e| anyhow!("Invalid CA file {}: {}", path.display(), e))?;
+    }
+    let channel = endpoint
+        .connect_timeout(TIMEOUT)
+        .timeout(timeout)
+        .connect()
+        .await
+        .map_err(|e| anyhow!("Failed to connect to {}: {}", server, e))?;
+    Ok(SchedulerClient::with_interceptor(channel, credentials))
+}
+
+// run the call against every selected cluster at the same time, the results
+// by context name in the order of the configuration
+fn fan_out<T, F, Fut>(call: F) -> Result<Vec<(String, Result<T>)>>
+where
+    T: Send + 'static,
+    F: Fn(Client) -> Fut,
+    Fut: Future<Output = Result<T>> + Send + 'static,
+{
+    fan_out_with(TIMEOUT, call)
+}
+
+// fan_out for calls that may take longer than TIMEOUT
+fn fan_out_with<T, F, Fut>(timeout: Duration, call: F) -> Result<Vec<(String, Result<T>)>>
+where
+    T: Send + 'static,
+    F: Fn(Client) -> Fut,
+    Fut: Future<Output = Result<T>> + Send + 'static,
+{
+    let selection = SELECTION.get().cloned().flatten();
+    let contexts = ContextConfig::load(config_path())?.resolve(selection.as_deref())?;
+    let runtime = tokio::runtime::Runtime::new()?;
+    // the calls run on other threads, outside of the span of the command
+    let propagate = Propagate::current();
+    Ok(runtime.block_on(async {
+        let connections: Vec<_> = contexts
+            .iter()
+            .map(|context| {
+                let (server, ca_file) = (context.server.clone(), context.ca_file.clone());
+                let credentials = Credentials::new(context, propagate.clone());
+                tokio::spawn(async move { connect(&server, ca_file.as_deref(), credentials?, timeout).await })
+            })
+            .collect();
+        let mut tasks = Vec::new();
+        for connection in connections {
+            let call = connection.await.map_err(|e| anyhow!("{}", e)).and_then(|client| client).map(&call);
+            tasks.push(tokio::spawn(async move { call?.await }));
+        }
+        let mut results = Vec::new();
+        for (context, task) in contexts.iter().zip(tasks) {
+            let result = task.await.map_err(|e| anyhow!("{}", e)).and_then(|result| result);
+            results.push((context.name.clone(), result));
+        }
+        results
+    }))
+}
+
+// print every failure, failing when there was one
+fn report_failures<T>(results: &[(String, Result<T>)]) -> Result<()> {
+    let failed: Vec<&String> = results
+        .iter()
+        .filter_map(|(context, result)| result.as_ref().err().map(|e| (context, e)))
+        .map(|(context, e)| {
+            eprintln!("{}: {}", context, e);
+            context
+        })
+        .collect();
+    if failed.is_empty() {
+        return Ok(());
+    }
+    let failed: Vec<&str> = failed.into_iter().map(String::as_str).collect();
+    Err(anyhow!("Failed on {} of {} context(s): {}", failed.len(), results.len(), failed.join(", ")))
+}
+
+// a row of the table and the object it was made from
+type Listed = Vec<(Vec<String>, Value)>;
+
+// print what a get listed without the objects the field selector doesn't
+// match, with the context first when there are several
+fn print_listed(mut listing: Listing, results: Vec<(String, Result<Listed>)>, fields: &FieldSelector, output: &OutputArgs) -> Result<()> {
+    let with_context = results.len() > 1;
+    if with_context {
+        listing.columns.insert(0, Column { name: "CONTEXT".to_string(), wide: false });
+    }
+    for (context, result) in &results {
+        for (row, object) in result.iter().flatten().filter(|(_, object)| fields.matches(object)) {
+            let mut cells = row.clone();
+            if with_context {
+                cells.insert(0, context.clone());
+            }
+            listing.push(cells, object.clone());
+        }
+    }
+    output.print(listing)?;
+    report_failures(&results)
+}
+
+pub fn get_nodes(fields: &FieldSelector, output: &OutputArgs) -> Result<()> {
+    let results = fan_out(|mut client| async move {
+        let nodes = client.list_nodes(ListNodesRequest {}).await.map_err(|e| anyhow!("{}", e.message()))?.into_inner().nodes;
+        let listed = nodes
+            .into_iter()
+            .map(|status| {
+                let node = status.node.unwrap_or_default();
+                let capacity = node.capacity.unwrap_or_default();
+                let allocated = status.allocated.unwrap_or_default();
+                let ready = if status.ready { "Ready" } else { "NotReady" };
+                let mut labels: Vec<String> = node.labels.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+                labels.sort();
+                let object = json!({
+                    "apiVersion": "v1",
+                    "kind": "Node",
+                    "metadata": { "name": node.name, "labels": node.labels },
+                    "spec": {
+                        "unschedulable": node.unschedulable,
+                        "taints": node.taints.iter().map(|taint| json!({ "key": taint.key, "value": taint.value, "effect": taint.effect })).collect::<Vec<_>>(),
+                    },
+                    "status": {
+                        "phase": ready,
+                        "capacity": { "cpu": format!("{}m", capacity.cpu_millis), "memory": capacity.memory_bytes.to_string(), "pods": capacity.pods.to_string() },
+                        "allocated": { "cpu": format!("{}m", allocated.cpu_millis), "memory": allocated.memory_bytes.to_string(), "pods": allocated.pods.to_string() },
+                    },
+                });
+                let status = if node.unschedulable { format!("{},SchedulingDisabled", ready) } else { ready.to_string() };
+                let row = vec![
+                    node.name,
+                    status,
+                    format!("{}/{}", allocated.cpu_millis, capacity.cpu_millis),
+                    format!("{}Mi/{}Mi", allocated.memory_bytes >> 20, capacity.memory_bytes >> 20),
+                    format!("{}/{}", allocated.pods, capacity.pods),
+                    labels.join(","),
+                ];
+                (row, object)
+            })
+            .collect();
+        Ok(listed)
+    })?;
+    let listing = Listing::new(&["NAME", "STATUS", "CPU(m)", "MEMORY", "PODS"]).with_wide(&["LABELS"]);
+    print_listed(listing, results, fields, output)
+}
+
+// the pods of a namespace, of all of them when None
+pub fn get_pods(namespace: Option<&str>, selector: &Selector, fields: &FieldSelector, output: &OutputArgs) -> Result<()> {
+    let namespace = namespace.map(str::to_string);
+    let all = namespace.is_none();
+    let selector = selector.clone();
+    let results = fan_out(move |mut client| {
+        let (namespace, selector) = (namespace.clone(), selector.clone());
+        async move {
+            let nodes = client.list_nodes(ListNodesRequest {}).await.map_err(|e| anyhow!("{}", e.message()))?.into_inner().nodes;
+            let mut listed = Vec::new();
+            for status in nodes {
+                let node = status.node.unwrap_or_default();
+                for pod in status.pods {
+                    // scheduled before namespaces were reported
+                    let pod_namespace = status.namespaces.get(&pod).cloned().unwrap_or_else(default_namespace);
+                    let labels = status.labels.get(&pod).map(|labels| labels.labels.clone()).unwrap_or_default();
+                    if !selector.matches(&labels) || namespace.as_ref().is_some_and(|namespace| *namespace != pod_namespace) {
+                        continue;
+                    }
+                    // dispatched but not reported by the node yet
+                    let state = node.pod_states.get(&pod).cloned().unwrap_or_default();
+                    let phase = if state.phase.is_empty() { "Pending".to_string() } else { state.phase };
+                    let object = json!({
+                        "apiVersion": "v1",
+                        "kind": "Pod",
+                        "metadata": { "name": pod, "namespace": pod_namespace, "labels": labels },
+                        "spec": { "nodeName": node.name },
+                        "status": { "phase": phase, "podIP": state.pod_ip },
+                    });
+                    let mut row = vec![pod, phase, node.name.clone(), state.pod_ip];
+                    if all {
+                        row.insert(0, pod_namespace);
+                    }
+                    listed.push((row, object));
+                }
+            }
+            Ok(listed)
+        }
+    })?;
+    let header: &[&str] = if all { &["NAMESPACE", "NAME", "STATUS", "NODE"] } else { &["NAME", "STATUS", "NODE"] };
+    print_listed(Listing::new(header).with_wide(&["IP"]), results, fields, output)
+}
+
+pub fn get_namespaces(fields: &FieldSelector, output: &OutputArgs) -> Result<()> {
+    let results = fan_out(|mut client| async move {
+        let names = client
+            .list_namespaces(ListNamespacesRequest {})
+            .await
+            .map_err(|e| anyhow!("{}", e.message()))?
+            .into_inner()
+            .names;
+        let listed = names
+            .into_iter()
+            .map(|name| {
+                let object = json!({ "apiVersion": "v1", "kind": "Namespace", "metadata": { "name": name }, "status": { "phase": "Active" } });
+                (vec![name], object)
+            })
+            .collect();
+        Ok(listed)
+    })?;
+    print_listed(Listing::new(&["NAME"]), results, fields, output)
+}
+
+pub fn create_namespace(name: &str) -> Result<()> {
+    let namespace = name.to_string();
+    let results = fan_out(move |mut client| {
+        let name = namespace.clone();
+        async move {
+            client.create_namespace(CreateNamespaceRequest { name }).await.map_err(|e| anyhow!("{}", e.message()))?;
+            Ok(())
+        }
+    })?;
+    for (context, result) in &results {
+        if result.is_ok() {
+            println!("{}: namespace {} created", context, name);
+        }
+    }
+    report_failures(&results)
+}
+
+pub fn delete_namespace(name: &str) -> Result<()> {
+    let namespace = name.to_string();
+    let results = fan_out(move |mut client| {
+        let name = namespace.clone();
+        async move {
+            client.delete_namespace(DeleteNamespaceRequest { name }).await.map_err(|e| anyhow!("{}", e.message()))?;
+            Ok(())
+        }
+    })?;
+    for (context, result) in &results {
+        if result.is_ok() {
+            println!("{}: namespace {} deleted", context, name);
+        }
+    }
+    report_failures(&results)
+}
+
+// the manifest of a pod as scheduled and its node, None when it isn't
+async fn live_pod(client: &mut Client, name: &str, namespace: &str) -> Result<Option<(String, String)>> {
+    let request = GetPodRequest { name: name.to_string(), namespace: namespace.to_string() };
+    match client.get_pod(request).await {
+        Ok(response) => {
+            let GetPodResponse { manifest, node, .. } = response.into_inner();
+            Ok(Some((manifest, node)))
+        }
+        Err(status) if status.code() == Code::NotFound => Ok(None),
+        Err(status) => Err(anyhow!("{}", status.message())),
+    }
+}
+
+// a pod manifest to apply with its name and namespace
+pub struct Manifest {
+    manifest: String,
+    name: String,
+    namespace: String,
+}
+
+fn pod_manifest(manifest: String, source: &str) -> Result<Manifest> {
+    validate::check(&manifest, source)?;
+    let task: PodTask = strict::from_yaml(&manifest).map_err(|e| anyhow!("Invalid manifest {}: {}", source, e))?;
+    if task.kind != "Pod" {
+        return Err(anyhow!("{}: only pods can be applied, not {}", source, task.kind));
+    }
+    Ok(Manifest { manifest, name: task.metadata.name, namespace: task.metadata.namespace })
+}
+
+// the manifests of -f, rendered with the values, or of the kustomization of -k
+pub fn read_manifests(pod_yaml: Option<&str>, kustomization: Option<&str>, values: &Values) -> Result<Vec<Manifest>> {
+    if let Some(dir) = kustomization {
+        return kustomize::build(Path::new(dir))?
+            .iter()
+            .map(|resource| pod_manifest(serde_yaml::to_string(resource)?, dir))
+            .collect();
+    }
+    let pod_yaml = pod_yaml.ok_or_else(|| anyhow!("Either -f or -k is required"))?;
+    let manifest = fs::read_to_string(pod_yaml).map_err(|e| anyhow!("Failed to read {}: {}", pod_yaml, e))?;
+    Ok(vec![pod_manifest(values.render(&manifest)?, pod_yaml)?])
+}
+
+// run the command for every manifest, failing when it failed for any of them
+fn for_each(manifests: &[Manifest], command: impl Fn(&Manifest) -> Result<()>) -> Result<()> {
+    let mut failed = 0;
+    for manifest in manifests {
+        if let Err(e) = command(manifest) {
+            eprintln!("pod/{}: {}", manifest.name, e);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        return Err(anyhow!("Failed for {} of {} pod(s)", failed, manifests.len()));
+    }
+    Ok(())
+}
+
+// schedule the pods where they don't exist yet, update them with the
+// three-way merge of apply where they do; a client dry run stops at the
+// merge, -o json printing the merged manifests, a server dry run has rks
+// check where the pods would go
+pub fn apply(manifests: &[Manifest], dry_run: Option<DryRun>, json: bool) -> Result<()> {
+    for_each(manifests, |manifest| apply_one(manifest, dry_run, json))
+}
+
+fn apply_one(manifest: &Manifest, dry_run: Option<DryRun>, json: bool) -> Result<()> {
+    let Manifest { manifest, name, namespace } = manifest;
+    let suffix = dryrun::suffix(dry_run);
+    let results = fan_out(move |mut client| {
+        let (manifest, name, namespace) = (manifest.clone(), name.clone(), namespace.clone());
+        async move {
+            let live = live_pod(&mut client, &name, &namespace).await?;
+            let (applied, changed) = apply::apply(&manifest, live.as_ref().map(|(live, _)| live.as_str()))?;
+            if let Some((_, node)) = &live
+                && !changed
+            {
+                return Ok((format!("pod/{} unchanged on node {}{}", name, node, suffix), None));
+            }
+            let update = live.is_some();
+            let action = if update { "configured" } else { "created" };
+            if dry_run == Some(DryRun::Client) {
+                let merged: Value = serde_yaml::from_str(&applied)?;
+                let node = live.map(|(_, node)| format!(" on node {}", node)).unwrap_or_default();
+                return Ok((format!("pod/{} {}{}{}", name, action, node, suffix), Some(merged)));
+            }
+            let request = SchedulePodRequest { manifest: applied, update, dry_run: dry_run.is_some() };
+            let response = client.schedule_pod(request).await.map_err(|e| anyhow!("{}", e.message()))?;
+            Ok((format!("pod/{} {} on node {}{}", name, action, response.into_inner().node, suffix), None))
+        }
+    })?;
+    for (context, result) in &results {
+        match result {
+            Ok((_, Some(merged))) if json => println!("{}", serde_json::to_string_pretty(merged)?),
+            Ok((message, _)) => println!("{}: {}", context, message),
+            Err(_) => {}
+        }
+    }
+    report_failures(&results)
+}
+
+// what apply would change on every cluster, as unified diffs of the manifests
+pub fn diff(manifests: &[Manifest]) -> Result<()> {
+    for_each(manifests, diff_one)
+}
+
+fn diff_one(manifest: &Manifest) -> Result<()> {
+    let Manifest { manifest, name, namespace } = manifest;
+    let target = format!("{}/pod/{}", namespace, name);
+    let results = fan_out(move |mut client| {
+        let (manifest, name, namespace) = (manifest.clone(), name.clone(), namespace.clone());
+        async move {
+            let live = live_pod(&mut client, &name, &namespace).await?.map(|(live, _)| live);
+            let (applied, _) = apply::apply(&manifest, live.as_deref())?;
+            let live = live.as_deref().map(apply::normalize).transpose()?.unwrap_or_default();
+            Ok(apply::diff(&live, &applied))
+        }
+    })?;
+    for (context, result) in &results {
+        let Ok(lines) = result else {
+            continue;
+        };
+        if lines.is_empty() {
+            continue;
+        }
+        println!("--- {}/{} (live)", context, target);
+        println!("+++ {}/{} (applied)", context, target);
+        for line in lines {
+            println!("{}", line);
+        }
+    }
+    report_failures(&results)
+}
+
+// a client dry run only looks the pod up, a server dry run has rks check it
+pub fn delete(pod_name: &str, namespace: &str, dry_run: Option<DryRun>) -> Result<()> {
+    let (name, namespace) = (pod_name.to_string(), namespace.to_string());
+    let results = fan_out(move |mut client| {
+        let (name, namespace) = (name.clone(), namespace.clone());
+        async move {
+            if dry_run == Some(DryRun::Client) {
+                let live = live_pod(&mut client, &name, &namespace).await?;
+                return live.map(|(_, node)| node).ok_or_else(|| anyhow!("pod {} is not scheduled in namespace {}", name, namespace));
+            }
+            let request = DeletePodRequest { name, namespace, dry_run: dry_run.is_some() };
+            let response = client.delete_pod(request).await.map_err(|e| anyhow!("{}", e.message()))?;
+            Ok(response.into_inner().node)
+        }
+    })?;
+    for (context, result) in &results {
+        if let Ok(node) = result {
+            println!("{}: Pod {} deleted from node {}{}", context, pod_name, node, dryrun::suffix(dry_run));
+        }
+    }
+    report_failures(&results)
+}
+
+// `rkl migrate`: the clusters that don't schedule the pod fail like for delete
+pub fn migrate(pod_name: &str, namespace: &str, node: &str, timeout: Option<Duration>) -> Result<()> {
+    let name = pod_name.strip_prefix("pod/").unwrap_or(pod_name).to_string();
+    let (namespace, target) = (namespace.to_string(), node.to_string());
+    let timeout_secs = timeout.map_or(0, |timeout| timeout.as_secs() as i64);
+    let results = fan_out_with(MIGRATE_TIMEOUT, move |mut client| {
+        let request = MigratePodRequest { name: name.clone(), namespace: namespace.clone(), node: target.clone(), timeout: timeout_secs };
+        async move {
+            let response = client.migrate_pod(request).await.map_err(|e| anyhow!("{}", e.message()))?;
+            Ok(response.into_inner().source)
+        }
+    })?;
+    for (context, result) in &results {
+        if let Ok(source) = result {
+            println!("{}: Pod {} migrated from node {} to node {}", context, pod_name, source, node);
+        }
+    }
+    report_failures(&results)
+}
+
+// `rkl cordon` and `rkl uncordon`, the clusters without the node fail
+pub fn cordon(node: &str, unschedulable: bool) -> Result<()> {
+    let name = node.strip_prefix("node/").unwrap_or(node).to_string();
+    let results = fan_out(move |mut client| {
+        let request = CordonNodeRequest { name: name.clone(), unschedulable };
+        async move {
+            client.cordon_node(request).await.map_err(|e| anyhow!("{}", e.message()))?;
+            Ok(())
+        }
+    })?;
+    let done = if unschedulable { "cordoned" } else { "uncordoned" };
+    for (context, result) in &results {
+        if result.is_ok() {
+            println!("{}: node/{} {}", context, node.strip_prefix("node/").unwrap_or(node), done);
+        }
+    }
+    report_failures(&results)
+}
+
+// the pods rks scheduled onto the node with their namespace and labels, and
+// the pods the node reports it runs
+async fn node_pods(client: &mut Client, node: &str) -> Result<(Vec<(String, String, bool)>, Vec<String>)> {
+    let nodes = client.list_nodes(ListNodesRequest {}).await.map_err(|e| anyhow!("{}", e.message()))?.into_inner().nodes;
+    let status = nodes
+        .into_iter()
+        .find(|status| status.node.as_ref().is_some_and(|n| n.name == node))
+        .ok_or_else(|| anyhow!("node {} is not registered", node))?;
+    let scheduled = status
+        .pods
+        .iter()
+        .map(|pod| {
+            let namespace = status.namespaces.get(pod).cloned().unwrap_or_else(default_namespace);
+            let daemon = status.labels.get(pod).is_some_and(|labels| labels.labels.contains_key(DAEMONSET_NAME));
+            (pod.clone(), namespace, daemon)
+        })
+        .collect();
+    Ok((scheduled, status.node.map(|node| node.pods).unwrap_or_default()))
+}
+
+// `rkl drain`: cordon the node, evict its pods and wait up to timeout for
+// the node to stop them, printing every pod as it goes
+pub fn drain(node: &str, timeout: Duration) -> Result<()> {
+    let name = node.strip_prefix("node/").unwrap_or(node).to_string();
+    let results = fan_out_with(EVICT_TIMEOUT, move |mut client| {
+        let name = name.clone();
+        async move {
+            let request = CordonNodeRequest { name: name.clone(), unschedulable: true };
+            client.cordon_node(request).await.map_err(|e| anyhow!("{}", e.message()))?;
+            println!("node/{} cordoned", name);
+            let (scheduled, _) = node_pods(&mut client, &name).await?;
+            // the pods to evict and whether their disruption budget blocked them
+            let mut pending = Vec::new();
+            for (pod, namespace, daemon) in scheduled {
+                if daemon {
+                    println!("Ignoring pod/{} of a daemon set", pod);
+                } else {
+                    pending.push((pod, namespace, false));
+                }
+            }
+            let mut evicted = Vec::new();
+            let mut failed = Vec::new();
+            let deadline = Instant::now() + timeout;
+            loop {
+                let mut blocked = Vec::new();
+                for (pod, namespace, warned) in pending {
+                    match client.evict_pod(EvictPodRequest { name: pod.clone(), namespace: namespace.clone() }).await {
+                        Ok(response) => {
+                            println!("Evicting pod/{} onto node {}", pod, response.into_inner().node);
+                            evicted.push(pod);
+                        }
+                        // the budget allows it once the pods evicted before are healthy again
+                        Err(e) if e.code() == Code::ResourceExhausted => {
+                            if !warned {
+                                println!("Cannot evict pod/{} yet, retrying: {}", pod, e.message());
+                            }
+                            blocked.push((pod, namespace, true));
+                        }
+                        Err(e) => {
+                            eprintln!("Failed to evict pod/{}: {}", pod, e.message());
+                            failed.push(pod);
+                        }
+                    }
+                }
+                pending = blocked;
+                if !evicted.is_empty() {
+                    let (_, running) = node_pods(&mut client, &name).await?;
+                    evicted.retain(|pod| {
+                        let stopped = !running.contains(pod);
+                        if stopped {
+                            println!("pod/{} evicted", pod);
+                        }
+                        !stopped
+                    });
+                }
+                if pending.is_empty() && evicted.is_empty() {
+                    break;
+                }
+                if Instant::now() >= deadline {
+                    let left: Vec<String> = pending.into_iter().map(|(pod, _, _)| pod).chain(evicted).collect();
+                    return Err(anyhow!("node {} still runs {} after {:?}", name, left.join(", "), timeout));
+                }
+                tokio::time::sleep(DRAIN_POLL).await;
+            }
+            if !failed.is_empty() {
+                return Err(anyhow!("Failed to evict {} from node {}", failed.join(", "), name));
+            }
+            Ok(())
+        }
+    })?;
+    for (context, result) in &results {
+        if result.is_ok() {
+            println!("{}: node/{} drained", context, node.strip_prefix("node/").unwrap_or(node));
+        }
+    }
+    report_failures(&results)
+}
+
+// delete the pods of the namespace matching the selector, from every cluster
+// of the context
+pub fn delete_selected(selector: &Selector, namespace: &str, dry_run: Option<DryRun>) -> Result<()> {
+    let (selected, namespace) = (selector.clone(), namespace.to_string());
+    let results = fan_out(move |mut client| {
+        let (selector, namespace) = (selected.clone(), namespace.clone());
+        async move {
+            let nodes = client.list_nodes(ListNodesRequest {}).await.map_err(|e| anyhow!("{}", e.message()))?.into_inner().nodes;
+            let mut deleted = Vec::new();
+            for status in nodes {
+                for pod in status.pods {
+                    let pod_namespace = status.namespaces.get(&pod).cloned().unwrap_or_else(default_namespace);
+                    let labels = status.labels.get(&pod).map(|labels| labels.labels.clone()).unwrap_or_default();
+                    if pod_namespace != namespace || !selector.matches(&labels) {
+                        continue;
+                    }
+                    if dry_run == Some(DryRun::Client) {
+                        let node = status.node.as_ref().map(|node| node.name.clone()).unwrap_or_default();
+                        deleted.push((pod, node));
+                        continue;
+                    }
+                    let request = DeletePodRequest { name: pod.clone(), namespace: namespace.clone(), dry_run: dry_run.is_some() };
+                    let node = client.delete_pod(request).await.map_err(|e| anyhow!("{}", e.message()))?.into_inner().node;
+                    deleted.push((pod, node));
+                }
+            }
+            Ok(deleted)
+        }
+    })?;
+    for (context, result) in &results {
+        match result {
+            Ok(deleted) if deleted.is_empty() => println!("{}: No pods match {}", context, selector),
+            Ok(deleted) => {
+                for (pod, node) in deleted {
+                    println!("{}: Pod {} deleted from node {}{}", context, pod, node, dryrun::suffix(dry_run));
+                }
+            }
+            Err(_) => {}
+        }
+    }
+    report_failures(&results)
+}
+
+pub fn list_contexts(output: &OutputArgs) -> Result<()> {
+    let config = ContextConfig::load(config_path())?;
+    let mut listing = Listing::new(&["CURRENT", "NAME", "SERVER", "GROUPS"]);
+    for context in &config.contexts {
+        let current = config.current.as_deref() == Some(context.name.as_str());
+        let object = json!({ "kind": "Context", "metadata": { "name": context.name }, "server": context.server, "groups": context.groups, "current": current });
+        let row = vec![if current { "*" } else { "" }.to_string(), context.name.clone(), context.server.clone(), context.groups.join(",")];
+        listing.push(row, object);
+    }
+    output.print(listing)
+}
+
+// make a context the current one
+pub fn use_context(name: &str) -> Result<()> {
+    let path = config_path();
+    let mut config = ContextConfig::load(path)?;
+    if !config.contexts.iter().any(|context| context.name == name) {
+        return Err(anyhow!("No context named {}", name));
+    }
+    config.current = Some(name.to_string());
+    config.save(path)?;
+    println!("Switched to context {}", name);
+    Ok(())
+}
+
+// add a context or change its server, groups, token and CA
+pub fn set_context(name: &str, server: &str, groups: &[String], token_file: Option<PathBuf>, ca_file: Option<PathBuf>) -> Result<()> {
+    if name == ALL || name.contains(',') {
+        return Err(anyhow!("{} can't be used as a context name", name));
+    }
+    let path = config_path();
+    let mut config = ContextConfig::load(path)?;
+    let context = Context { name: name.to_string(), server: server.to_string(), groups: groups.to_vec(), token_file, ca_file };
+    match config.contexts.iter_mut().find(|context| context.name == name) {
+        Some(existing) => *existing = context,
+        None => config.contexts.push(context),
+    }
+    if config.current.is_none() {
+        config.current = Some(name.to_string());
+    }
+    config.save(path)?;
+    println!("Context {} set", name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let config: ContextConfig = serde_yaml::from_str(
+            "current: hq\ncontexts:\n  - {name: hq, server: 'http://hq:7500'}\n  - {name: edge-1, server: 'http://e1:7500', groups: [edge]}\n  - {name: edge-2, server: 'http://e2:7500', groups: [edge]}\n",
+        )
+        .unwrap();
+        let names = |selection: Option<&str>| -> Vec<String> {
+            config.resolve(selection).unwrap().into_iter().map(|context| context.name).collect()
+        };
+        assert_eq!(names(None), vec!["hq"]);
+        assert_eq!(names(Some("edge")), vec!["edge-1", "edge-2"]);
+        assert_eq!(names(Some("all")), vec!["hq", "edge-1", "edge-2"]);
+        // in the order of the configuration, once each
+        assert_eq!(names(Some("edge-2,hq,edge")), vec!["hq", "edge-1", "edge-2"]);
+        assert!(config.resolve(Some("lab")).is_err());
+        assert!(ContextConfig::default().resolve(None).is_err());
+    }
+}
@@ -1,4 +1,5 @@
 //! Handles the creation of a new container
+use std::os::fd::OwnedFd;
 use std::path::PathBuf;
 
 use anyhow::Result;
@@ -7,18 +8,40 @@ use libcontainer::syscall::syscall::SyscallType;
 use liboci_cli::Create;
 use libcontainer::workload::{Executor, ExecutorError, ExecutorValidationError};
 
+// stdio of the container init process, the ones of the current process when unset
+#[derive(Default)]
+pub struct ContainerStdio {
+    pub stdin: Option<OwnedFd>,
+    pub stdout: Option<OwnedFd>,
+    pub stderr: Option<OwnedFd>,
+}
+
 // One thing to note is that in the end, container is just another process in Linux
 // it has specific/different control group, namespace, using which program executing in it
 // can be given impression that is is running on a complete system, but on the system which
 // it is running, it is just another process, and has attributes such as pid, file descriptors, etc.
 // associated with it like any other process.
 pub fn create(args: Create, root_path: PathBuf, systemd_cgroup: bool) -> Result<()> {
-    ContainerBuilder::new(args.container_id.clone(), SyscallType::default())
+    create_with_stdio(args, root_path, systemd_cgroup, ContainerStdio::default())
+}
+
+pub fn create_with_stdio(args: Create, root_path: PathBuf, systemd_cgroup: bool, stdio: ContainerStdio) -> Result<()> {
+    let mut builder = ContainerBuilder::new(args.container_id.clone(), SyscallType::default())
         .with_executor(libcontainer::workload::default::DefaultExecutor{})
         .with_pid_file(args.pid_file.as_ref())?
         .with_console_socket(args.console_socket.as_ref())
         .with_root_path(root_path)?
-        .with_preserved_fds(args.preserve_fds)
+        .with_preserved_fds(args.preserve_fds);
+    if let Some(stdin) = stdio.stdin {
+        builder = builder.with_stdin(stdin);
+    }
+    if let Some(stdout) = stdio.stdout {
+        builder = builder.with_stdout(stdout);
+    }
+    if let Some(stderr) = stdio.stderr {
+        builder = builder.with_stderr(stderr);
+    }
+    builder
         .validate_id()?
         .as_init(&args.bundle)
         .with_systemd(systemd_cgroup)
@@ -0,0 +1,72 @@
+//! Contains functionality of exec container command
+use std::fs::File;
+use std::io::Read;
+use std::os::fd::OwnedFd;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use libcontainer::container::builder::ContainerBuilder;
+use libcontainer::syscall::syscall::SyscallType;
+use libcontainer::workload::default::DefaultExecutor;
+use nix::errno::Errno;
+use nix::sys::signal::{self, Signal};
+use nix::sys::wait::{self, WaitPidFlag, WaitStatus};
+use nix::unistd::{self, Pid};
+
+// run a command in a running container and wait for it, killing it once the
+// timeout is over. The output is dropped. The exit code is None when somebody
+// else reaped the process, e.g. the reaper of `rkl daemon`
+pub fn exec(container_id: &str, command: Vec<String>, root_path: PathBuf, timeout: Duration) -> Result<Option<i32>> {
+    let null = || -> Result<OwnedFd> { Ok(File::options().read(true).write(true).open("/dev/null")?.into()) };
+    let pid = spawn(container_id, command, root_path, null()?, null()?)?;
+    wait_for(container_id, pid, timeout)
+}
+
+// like exec, with what the command wrote to stdout and stderr
+pub fn exec_output(container_id: &str, command: Vec<String>, root_path: PathBuf, timeout: Duration) -> Result<(Option<i32>, Vec<u8>)> {
+    let (read, write) = unistd::pipe()?;
+    let pid = spawn(container_id, command, root_path, write.try_clone()?, write)?;
+    // read while it runs, it can write more than the pipe holds
+    let reader = thread::spawn(move || {
+        let mut output = Vec::new();
+        File::from(read).read_to_end(&mut output).map(|_| output)
+    });
+    let code = wait_for(container_id, pid, timeout)?;
+    let output = reader.join().map_err(|_| anyhow!("Failed to read the output of exec in container {}", container_id))??;
+    Ok((code, output))
+}
+
+fn spawn(container_id: &str, command: Vec<String>, root_path: PathBuf, stdout: OwnedFd, stderr: OwnedFd) -> Result<Pid> {
+    let stdin: OwnedFd = File::open("/dev/null")?.into();
+    let pid = ContainerBuilder::new(container_id.to_string(), SyscallType::default())
+        .with_executor(DefaultExecutor {})
+        .with_root_path(root_path)?
+        .with_stdin(stdin)
+        .with_stdout(stdout)
+        .with_stderr(stderr)
+        .validate_id()?
+        .as_tenant()
+        .with_container_args(command)
+        .build()?;
+    Ok(pid)
+}
+
+fn wait_for(container_id: &str, pid: Pid, timeout: Duration) -> Result<Option<i32>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        match wait::waitpid(pid, Some(WaitPidFlag::WNOHANG)) {
+            Ok(WaitStatus::Exited(_, code)) => return Ok(Some(code)),
+            Ok(WaitStatus::Signaled(_, signal, _)) => return Ok(Some(128 + signal as i32)),
+            Err(Errno::ECHILD) => return Ok(None),
+            Err(e) => return Err(anyhow!("Failed to wait for exec in container {}: {}", container_id, e)),
+            Ok(_) if Instant::now() >= deadline => {
+                let _ = signal::kill(pid, Signal::SIGKILL);
+                let _ = wait::waitpid(pid, None);
+                return Err(anyhow!("command timed out after {}s", timeout.as_secs()));
+            }
+            Ok(_) => thread::sleep(Duration::from_millis(50)),
+        }
+    }
+}
@@ -10,6 +10,7 @@ pub mod start;
 pub mod delete;
 pub mod state;
 pub mod kill;
+pub mod exec;
 
 
 fn construct_container_root<P: AsRef<Path>>(root_path: P, container_id: &str) -> Result<PathBuf> {
@@ -40,7 +41,7 @@ fn container_exists<P: AsRef<Path>>(root_path: P, container_id: &str) -> Result<
     Ok(container_root.exists())
 }
 
-fn create_cgroup_manager<P: AsRef<Path>>(
+pub fn create_cgroup_manager<P: AsRef<Path>>(
     root_path: P,
     container_id: &str,
 ) -> Result<AnyCgroupManager> {
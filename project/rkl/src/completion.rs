@@ -0,0 +1,59 @@
+use std::ffi::OsStr;
+use std::io;
+use anyhow::{Result, anyhow};
+use clap_complete::engine::CompletionCandidate;
+use clap_complete::env::Shells;
+use crate::cli_commands::PodInfo;
+use crate::rootpath;
+
+// Completion of rkl in bash, zsh and fish: the commands and flags come from
+// the clap definitions, pod names from the pods recorded on this node.
+// `rkl completion bash` prints the script registering it, e.g. in ~/.bashrc:
+//
+//   source <(rkl completion bash)
+//
+// For every completion the script runs rkl again with COMPLETE=<shell> set,
+// which main hands to clap_complete before parsing anything.
+
+pub const SHELLS: [&str; 3] = ["bash", "zsh", "fish"];
+
+// the pod names starting with what was typed, keeping a pod/ in front of it
+fn matching(names: Vec<String>, current: &str) -> Vec<String> {
+    let (prefix, typed) = match current.strip_prefix("pod/") {
+        Some(typed) => ("pod/", typed),
+        None => ("", current),
+    };
+    names.into_iter().filter(|name| name.starts_with(typed)).map(|name| format!("{}{}", prefix, name)).collect()
+}
+
+// completes the pod name arguments
+pub fn pods(current: &OsStr) -> Vec<CompletionCandidate> {
+    let Some(current) = current.to_str() else {
+        return Vec::new();
+    };
+    let names = rootpath::determine(None).and_then(|root_path| PodInfo::names(&root_path)).unwrap_or_default();
+    matching(names, current).into_iter().map(CompletionCandidate::new).collect()
+}
+
+// `rkl completion`
+pub fn print_script(shell: &str) -> Result<()> {
+    let shells = Shells::builtins();
+    let completer = shells.completer(shell).ok_or_else(|| anyhow!("unsupported shell {}", shell))?;
+    let bin = std::env::current_exe().map_or_else(|_| "rkl".to_string(), |path| path.display().to_string());
+    completer.write_registration("COMPLETE", "rkl", "rkl", &bin, &mut io::stdout())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matching() {
+        let names = || vec!["cache".to_string(), "web-0".to_string(), "web-1".to_string()];
+        assert_eq!(matching(names(), "web"), ["web-0", "web-1"]);
+        assert_eq!(matching(names(), "pod/c"), ["pod/cache"]);
+        assert_eq!(matching(names(), ""), ["cache", "web-0", "web-1"]);
+        assert!(matching(names(), "db").is_empty());
+    }
+}
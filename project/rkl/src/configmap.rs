@@ -0,0 +1,373 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Component, Path, PathBuf};
+use anyhow::{Result, anyhow};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use crate::printer::{Listing, OutputArgs};
+use crate::rootpath;
+use crate::strict;
+use crate::task::task::ObjectMeta;
+
+// ConfigMaps hold configuration for pods as keys and values. Like the pull
+// secrets they are kept per node, every key a file of <root>/configmaps/<name>,
+// and stored with `rkl configmap apply` or as a manifest of `rkl daemon`.
+// Pods use them through
+//
+//   envFrom:
+//     - configMapRef:
+//         name: settings
+//       prefix: APP_
+//
+// which adds every key that is a valid env var name to the environment, and
+// through configMap volumes, whose keys (or items) are written to a directory
+// of the sandbox when a container mounting them is created and mounted
+// read-only into it.
+
+pub const KIND: &str = "ConfigMap";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConfigMap {
+    #[serde(rename = "apiVersion")]
+    pub api_version: String,
+    pub kind: String,
+    pub metadata: ObjectMeta,
+    #[serde(default)]
+    pub data: BTreeMap<String, String>,
+    // base64 encoded values
+    #[serde(rename = "binaryData", default)]
+    pub binary_data: BTreeMap<String, String>,
+}
+
+// simulate Kubernetes ConfigMapEnvSource
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigMapEnvSource {
+    pub name: String,
+    // a missing config map is skipped instead of failing the container
+    #[serde(default)]
+    pub optional: bool,
+}
+
+// simulate Kubernetes ConfigMapVolumeSource
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigMapVolumeSource {
+    pub name: String,
+    // the keys to project and where, every key as a file of its name when empty
+    #[serde(default)]
+    pub items: Vec<KeyToPath>,
+    #[serde(rename = "defaultMode", default = "default_mode")]
+    pub default_mode: u32,
+    #[serde(default)]
+    pub optional: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyToPath {
+    pub key: String,
+    pub path: String,
+    #[serde(default)]
+    pub mode: Option<u32>,
+}
+
+pub fn default_mode() -> u32 {
+    0o644
+}
+
+pub fn valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && key.len() <= 253
+        && key != "."
+        && key != ".."
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+// a DNS subdomain, like the names of Kubernetes objects
+pub fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 253
+        && name.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && name.ends_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '.')
+}
+
+fn valid_env_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+}
+
+pub fn store_dir(root_path: &Path, name: &str) -> PathBuf {
+    root_path.join("configmaps").join(name)
+}
+
+impl ConfigMap {
+    pub fn parse(contents: &str) -> Result<Self> {
+        let config_map: ConfigMap = strict::from_yaml(contents)?;
+        if config_map.kind != KIND || config_map.api_version != "v1" {
+            return Err(anyhow!("expected apiVersion v1 and kind ConfigMap"));
+        }
+        if !valid_name(&config_map.metadata.name) {
+            return Err(anyhow!("invalid ConfigMap name {:?}", config_map.metadata.name));
+        }
+        for key in config_map.data.keys().chain(config_map.binary_data.keys()) {
+            if !valid_key(key) {
+                return Err(anyhow!("ConfigMap {}: invalid key {:?}", config_map.metadata.name, key));
+            }
+            if config_map.data.contains_key(key) && config_map.binary_data.contains_key(key) {
+                return Err(anyhow!("ConfigMap {}: key {} is in both data and binaryData", config_map.metadata.name, key));
+            }
+        }
+        config_map.entries()?;
+        Ok(config_map)
+    }
+
+    // every key with its value, the binary ones decoded
+    fn entries(&self) -> Result<BTreeMap<String, Vec<u8>>> {
+        let mut entries: BTreeMap<String, Vec<u8>> =
+            self.data.iter().map(|(key, value)| (key.clone(), value.clone().into_bytes())).collect();
+        for (key, value) in &self.binary_data {
+            let decoded = base64::engine::general_purpose::STANDARD
+                .decode(value)
+                .map_err(|e| anyhow!("ConfigMap {}: binaryData {} is not base64: {}", self.metadata.name, key, e))?;
+            entries.insert(key.clone(), decoded);
+        }
+        Ok(entries)
+    }
+
+    // replace the stored config map of the same name, returns whether it changed
+    pub fn save(&self, root_path: &Path) -> Result<bool> {
+        let entries = self.entries()?;
+        let dir = store_dir(root_path, &self.metadata.name);
+        if load(root_path, &self.metadata.name).ok().as_ref() == Some(&entries) {
+            return Ok(false);
+        }
+        // written next to the store and renamed so that readers never see half of it
+        let staging = root_path.join("configmaps").join(format!(".{}", self.metadata.name));
+        if staging.exists() {
+            fs::remove_dir_all(&staging)?;
+        }
+        fs::create_dir_all(&staging)?;
+        for (key, value) in &entries {
+            fs::write(staging.join(key), value)?;
+        }
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        fs::rename(&staging, &dir)?;
+        Ok(true)
+    }
+}
+
+// the keys and values of a stored config map
+pub fn load(root_path: &Path, name: &str) -> Result<BTreeMap<String, Vec<u8>>> {
+    if !valid_name(name) {
+        return Err(anyhow!("invalid ConfigMap name {:?}", name));
+    }
+    let dir = store_dir(root_path, name);
+    let entries = fs::read_dir(&dir).map_err(|_| anyhow!("ConfigMap {} not found", name))?;
+    let mut data = BTreeMap::new();
+    for entry in entries {
+        let entry = entry?;
+        data.insert(entry.file_name().to_string_lossy().to_string(), fs::read(entry.path())?);
+    }
+    Ok(data)
+}
+
+pub fn remove(root_path: &Path, name: &str) -> Result<()> {
+    let dir = store_dir(root_path, name);
+    if !dir.exists() {
+        return Err(anyhow!("ConfigMap {} not found", name));
+    }
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+// the names of the stored config maps
+pub fn list(root_path: &Path) -> Result<Vec<String>> {
+    let dir = root_path.join("configmaps");
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut names: Vec<String> = fs::read_dir(&dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir() && !entry.file_name().to_string_lossy().starts_with('.'))
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+// the env vars of an envFrom configMapRef, keys that aren't valid names are left out
+pub fn env(root_path: &Path, source: &ConfigMapEnvSource, prefix: &str) -> Result<Vec<(String, String)>> {
+    let data = match load(root_path, &source.name) {
+        Ok(data) => data,
+        Err(_) if source.optional => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    Ok(data
+        .into_iter()
+        .map(|(key, value)| (format!("{}{}", prefix, key), String::from_utf8_lossy(&value).to_string()))
+        .filter(|(name, _)| valid_env_name(name))
+        .collect())
+}
+
+// write the keys of a configMap volume into dir
+pub fn project(root_path: &Path, source: &ConfigMapVolumeSource, dir: &Path) -> Result<()> {
+    let data = match load(root_path, &source.name) {
+        Ok(data) => data,
+        Err(_) if source.optional => BTreeMap::new(),
+        Err(e) => return Err(e),
+    };
+    let object = format!("ConfigMap {}", source.name);
+    write_keys(&object, &data, &source.items, source.default_mode, source.optional, dir)
+}
+
+// write the keys of a config map or secret into the directory of a volume,
+// as the items or every key as a file of its name when there are none. The
+// files are rewritten in place, the directory may already be mounted into
+// other containers of the pod
+pub fn write_keys(
+    object: &str,
+    data: &BTreeMap<String, Vec<u8>>,
+    items: &[KeyToPath],
+    default_mode: u32,
+    optional: bool,
+    dir: &Path,
+) -> Result<()> {
+    let items: Vec<KeyToPath> = if items.is_empty() {
+        data.keys().map(|key| KeyToPath { key: key.clone(), path: key.clone(), mode: None }).collect()
+    } else {
+        items.to_vec()
+    };
+    fs::create_dir_all(dir)?;
+    let mut written = HashSet::new();
+    for item in &items {
+        let path = Path::new(&item.path);
+        if item.path.is_empty() || !path.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(anyhow!("{}: invalid path {:?} of key {}", object, item.path, item.key));
+        }
+        let Some(value) = data.get(&item.key) else {
+            if optional {
+                continue;
+            }
+            return Err(anyhow!("{} has no key {}", object, item.key));
+        };
+        let file = dir.join(path);
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&file, value)?;
+        fs::set_permissions(&file, fs::Permissions::from_mode(item.mode.unwrap_or(default_mode)))?;
+        if let Some(Component::Normal(top)) = path.components().next() {
+            written.insert(top.to_os_string());
+        }
+    }
+    // keys removed since the last projection
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !written.contains(&entry.file_name()) {
+            if entry.path().is_dir() {
+                fs::remove_dir_all(entry.path())?;
+            } else {
+                fs::remove_file(entry.path())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// `rkl configmap apply`
+pub fn apply(path: &str) -> Result<()> {
+    let contents = fs::read_to_string(path).map_err(|e| anyhow!("Failed to read {}: {}", path, e))?;
+    let config_map = ConfigMap::parse(&contents).map_err(|e| anyhow!("Invalid ConfigMap {}: {}", path, e))?;
+    let root_path = rootpath::determine(None)?;
+    if config_map.save(&root_path)? {
+        println!("ConfigMap {} configured", config_map.metadata.name);
+    } else {
+        println!("ConfigMap {} unchanged", config_map.metadata.name);
+    }
+    Ok(())
+}
+
+// `rkl configmap delete`
+pub fn delete(name: &str) -> Result<()> {
+    let root_path = rootpath::determine(None)?;
+    remove(&root_path, name)?;
+    println!("ConfigMap {} deleted", name);
+    Ok(())
+}
+
+// `rkl configmap list`
+pub fn print_list(output: &OutputArgs) -> Result<()> {
+    let root_path = rootpath::determine(None)?;
+    let mut listing = Listing::new(&["NAME", "DATA"]);
+    for name in list(&root_path)? {
+        let data = load(&root_path, &name)?;
+        // the values that aren't text go to binaryData, like they came
+        let (text, binary): (BTreeMap<_, _>, BTreeMap<_, _>) = data.iter().partition(|(_, value)| std::str::from_utf8(value).is_ok());
+        let object = json!({
+            "apiVersion": "v1",
+            "kind": "ConfigMap",
+            "metadata": { "name": name },
+            "data": text.into_iter().map(|(key, value)| (key.clone(), String::from_utf8_lossy(value).into_owned())).collect::<BTreeMap<_, _>>(),
+            "binaryData": binary.into_iter().map(|(key, value)| (key.clone(), base64::engine::general_purpose::STANDARD.encode(value))).collect::<BTreeMap<_, _>>(),
+        });
+        listing.push(vec![name, data.len().to_string()], object);
+    }
+    output.print(listing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG_MAP: &str = "
+apiVersion: v1
+kind: ConfigMap
+metadata:
+  name: settings
+data:
+  log.level: debug
+  nginx.conf: |
+    worker_processes 1;
+  1st: one
+binaryData:
+  blob: aGVsbG8=
+";
+
+    #[test]
+    fn test_store_and_project() {
+        let root = tempfile::tempdir().unwrap();
+        let config_map = ConfigMap::parse(CONFIG_MAP).unwrap();
+        assert!(config_map.save(root.path()).unwrap());
+        assert!(!config_map.save(root.path()).unwrap());
+        assert_eq!(list(root.path()).unwrap(), vec!["settings"]);
+        assert_eq!(load(root.path(), "settings").unwrap()["blob"], b"hello");
+
+        // 1st isn't a valid name, APP_1st is
+        let source = ConfigMapEnvSource { name: "settings".to_string(), optional: false };
+        let names: Vec<String> = env(root.path(), &source, "").unwrap().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["blob", "log.level", "nginx.conf"]);
+        assert_eq!(env(root.path(), &source, "APP_").unwrap().len(), 4);
+        let missing = ConfigMapEnvSource { name: "missing".to_string(), optional: true };
+        assert!(env(root.path(), &missing, "").unwrap().is_empty());
+
+        let dir = root.path().join("volume");
+        let mut volume = ConfigMapVolumeSource { name: "settings".to_string(), items: Vec::new(), default_mode: 0o600, optional: false };
+        project(root.path(), &volume, &dir).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("nginx.conf")).unwrap(), "worker_processes 1;\n");
+        assert_eq!(fs::metadata(dir.join("blob")).unwrap().permissions().mode() & 0o777, 0o600);
+
+        volume.items = vec![KeyToPath { key: "nginx.conf".to_string(), path: "conf.d/default.conf".to_string(), mode: None }];
+        project(root.path(), &volume, &dir).unwrap();
+        assert!(dir.join("conf.d/default.conf").exists());
+        assert!(!dir.join("blob").exists());
+        volume.items[0].path = "../escape".to_string();
+        assert!(project(root.path(), &volume, &dir).is_err());
+
+        assert!(ConfigMap::parse(&CONFIG_MAP.replace("log.level", "log/level")).is_err());
+        assert!(ConfigMap::parse(&CONFIG_MAP.replace("aGVsbG8=", "not base64!")).is_err());
+    }
+}
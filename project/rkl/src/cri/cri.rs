@@ -1,6 +1,7 @@
 // This file is @generated by prost-build.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct VersionRequest {
     /// Version of the kubelet runtime API.
     #[prost(string, tag = "1")]
@@ -8,6 +9,7 @@ pub struct VersionRequest {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct VersionResponse {
     /// Version of the kubelet runtime API.
     #[prost(string, tag = "1")]
@@ -27,6 +29,7 @@ pub struct VersionResponse {
 /// DNSConfig specifies the DNS servers and search domains of a sandbox.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct DnsConfig {
     /// List of DNS servers of the cluster.
     #[prost(string, repeated, tag = "1")]
@@ -42,6 +45,7 @@ pub struct DnsConfig {
 /// PortMapping specifies the port mapping configurations of a sandbox.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct PortMapping {
     /// Protocol of the port mapping.
     #[prost(enumeration = "Protocol", tag = "1")]
@@ -68,6 +72,7 @@ pub struct PortMapping {
 /// Mount specifies a host volume to mount into a container.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct Mount {
     /// Path of the mount within the container.
     #[prost(string, tag = "1")]
@@ -118,6 +123,7 @@ pub struct Mount {
 /// IDMapping describes host to container ID mappings for a pod sandbox.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct IdMapping {
     /// HostId is the id on the host.
     #[prost(uint32, tag = "1")]
@@ -132,6 +138,7 @@ pub struct IdMapping {
 /// UserNamespace describes the intended user namespace configuration for a pod sandbox.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct UserNamespace {
     /// Mode is the NamespaceMode for this UserNamespace.
     /// Note: NamespaceMode for UserNamespace currently supports only POD and NODE, not CONTAINER OR TARGET.
@@ -147,6 +154,7 @@ pub struct UserNamespace {
 /// NamespaceOption provides options for Linux namespaces.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct NamespaceOption {
     /// Network namespace for this container/sandbox.
     /// Note: There is currently no way to set CONTAINER scoped network in the Kubernetes API.
@@ -180,6 +188,7 @@ pub struct NamespaceOption {
 /// Int64Value is the wrapper of int64.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct Int64Value {
     /// The value.
     #[prost(int64, tag = "1")]
@@ -192,6 +201,7 @@ pub struct Int64Value {
 ///     process.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct LinuxSandboxSecurityContext {
     /// Configurations for the sandbox's namespaces.
     /// This will be used only if the PodSandbox uses namespace for isolation.
@@ -246,6 +256,7 @@ pub struct LinuxSandboxSecurityContext {
 /// A security profile which can be used for sandboxes and containers.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct SecurityProfile {
     /// Indicator which `ProfileType` should be applied.
     #[prost(enumeration = "security_profile::ProfileType", tag = "1")]
@@ -271,6 +282,7 @@ pub mod security_profile {
         Ord,
         ::prost::Enumeration
     )]
+    #[derive(serde::Serialize)]
     #[repr(i32)]
     pub enum ProfileType {
         /// The container runtime default profile should be used.
@@ -307,6 +319,7 @@ pub mod security_profile {
 /// host platforms and Linux-based containers.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct LinuxPodSandboxConfig {
     /// Parent cgroup of the PodSandbox.
     /// The cgroupfs style syntax will be used, but the container runtime can
@@ -335,6 +348,7 @@ pub struct LinuxPodSandboxConfig {
 /// the runtime can construct a unique PodSandboxName based on the metadata.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct PodSandboxMetadata {
     /// Pod name of the sandbox. Same as the pod name in the Pod ObjectMeta.
     #[prost(string, tag = "1")]
@@ -353,6 +367,7 @@ pub struct PodSandboxMetadata {
 /// sandbox.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct PodSandboxConfig {
     /// Metadata of the sandbox. This information will uniquely identify the
     /// sandbox, and the runtime should leverage this to ensure correct
@@ -418,6 +433,7 @@ pub struct PodSandboxConfig {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct RunPodSandboxRequest {
     /// Configuration for creating a PodSandbox.
     #[prost(message, optional, tag = "1")]
@@ -432,6 +448,7 @@ pub struct RunPodSandboxRequest {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct RunPodSandboxResponse {
     /// ID of the PodSandbox to run.
     #[prost(string, tag = "1")]
@@ -439,6 +456,7 @@ pub struct RunPodSandboxResponse {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct StopPodSandboxRequest {
     /// ID of the PodSandbox to stop.
     #[prost(string, tag = "1")]
@@ -446,9 +464,11 @@ pub struct StopPodSandboxRequest {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct StopPodSandboxResponse {}
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct RemovePodSandboxRequest {
     /// ID of the PodSandbox to remove.
     #[prost(string, tag = "1")]
@@ -456,9 +476,11 @@ pub struct RemovePodSandboxRequest {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct RemovePodSandboxResponse {}
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct PodSandboxStatusRequest {
     /// ID of the PodSandbox for which to retrieve status.
     #[prost(string, tag = "1")]
@@ -470,6 +492,7 @@ pub struct PodSandboxStatusRequest {
 /// PodIP represents an ip of a Pod
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct PodIp {
     /// an ip is a string representation of an IPv4 or an IPv6
     #[prost(string, tag = "1")]
@@ -479,6 +502,7 @@ pub struct PodIp {
 /// Currently ignored for pods sharing the host networking namespace.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct PodSandboxNetworkStatus {
     /// IP address of the PodSandbox.
     #[prost(string, tag = "1")]
@@ -490,6 +514,7 @@ pub struct PodSandboxNetworkStatus {
 /// Namespace contains paths to the namespaces.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct Namespace {
     /// Namespace options for Linux namespaces.
     #[prost(message, optional, tag = "2")]
@@ -498,6 +523,7 @@ pub struct Namespace {
 /// LinuxSandboxStatus contains status specific to Linux sandboxes.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct LinuxPodSandboxStatus {
     /// Paths to the sandbox's namespaces.
     #[prost(message, optional, tag = "1")]
@@ -506,6 +532,7 @@ pub struct LinuxPodSandboxStatus {
 /// PodSandboxStatus contains the status of the PodSandbox.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct PodSandboxStatus {
     /// ID of the sandbox.
     #[prost(string, tag = "1")]
@@ -547,6 +574,7 @@ pub struct PodSandboxStatus {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct PodSandboxStatusResponse {
     /// Status of the PodSandbox.
     #[prost(message, optional, tag = "1")]
@@ -570,6 +598,7 @@ pub struct PodSandboxStatusResponse {
 /// PodSandboxStateValue is the wrapper of PodSandboxState.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct PodSandboxStateValue {
     /// State of the sandbox.
     #[prost(enumeration = "PodSandboxState", tag = "1")]
@@ -579,6 +608,7 @@ pub struct PodSandboxStateValue {
 /// All those fields are combined with 'AND'
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct PodSandboxFilter {
     /// ID of the sandbox.
     #[prost(string, tag = "1")]
@@ -597,6 +627,7 @@ pub struct PodSandboxFilter {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ListPodSandboxRequest {
     /// PodSandboxFilter to filter a list of PodSandboxes.
     #[prost(message, optional, tag = "1")]
@@ -605,6 +636,7 @@ pub struct ListPodSandboxRequest {
 /// PodSandbox contains minimal information about a sandbox.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct PodSandbox {
     /// ID of the PodSandbox.
     #[prost(string, tag = "1")]
@@ -639,6 +671,7 @@ pub struct PodSandbox {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ListPodSandboxResponse {
     /// List of PodSandboxes.
     #[prost(message, repeated, tag = "1")]
@@ -646,6 +679,7 @@ pub struct ListPodSandboxResponse {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct PodSandboxStatsRequest {
     /// ID of the pod sandbox for which to retrieve stats.
     #[prost(string, tag = "1")]
@@ -653,6 +687,7 @@ pub struct PodSandboxStatsRequest {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct PodSandboxStatsResponse {
     #[prost(message, optional, tag = "1")]
     pub stats: ::core::option::Option<PodSandboxStats>,
@@ -661,6 +696,7 @@ pub struct PodSandboxStatsResponse {
 /// All those fields are combined with 'AND'.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct PodSandboxStatsFilter {
     /// ID of the pod sandbox.
     #[prost(string, tag = "1")]
@@ -676,6 +712,7 @@ pub struct PodSandboxStatsFilter {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ListPodSandboxStatsRequest {
     /// Filter for the list request.
     #[prost(message, optional, tag = "1")]
@@ -683,6 +720,7 @@ pub struct ListPodSandboxStatsRequest {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ListPodSandboxStatsResponse {
     /// Stats of the pod sandbox.
     #[prost(message, repeated, tag = "1")]
@@ -691,6 +729,7 @@ pub struct ListPodSandboxStatsResponse {
 /// PodSandboxAttributes provides basic information of the pod sandbox.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct PodSandboxAttributes {
     /// ID of the pod sandbox.
     #[prost(string, tag = "1")]
@@ -718,6 +757,7 @@ pub struct PodSandboxAttributes {
 /// The linux or windows field will be populated depending on the platform.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct PodSandboxStats {
     /// Information of the pod.
     #[prost(message, optional, tag = "1")]
@@ -732,6 +772,7 @@ pub struct PodSandboxStats {
 /// LinuxPodSandboxStats provides the resource usage statistics for a pod sandbox on linux.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct LinuxPodSandboxStats {
     /// CPU usage gathered for the pod sandbox.
     #[prost(message, optional, tag = "1")]
@@ -752,6 +793,7 @@ pub struct LinuxPodSandboxStats {
 /// WindowsPodSandboxStats provides the resource usage statistics for a pod sandbox on windows
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct WindowsPodSandboxStats {
     /// CPU usage gathered for the pod sandbox.
     #[prost(message, optional, tag = "1")]
@@ -772,6 +814,7 @@ pub struct WindowsPodSandboxStats {
 /// NetworkUsage contains data about network resources.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct NetworkUsage {
     /// Timestamp in nanoseconds at which the information were collected. Must be > 0.
     #[prost(int64, tag = "1")]
@@ -786,6 +829,7 @@ pub struct NetworkUsage {
 /// WindowsNetworkUsage contains data about network resources specific to Windows.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct WindowsNetworkUsage {
     /// Timestamp in nanoseconds at which the information were collected. Must be > 0.
     #[prost(int64, tag = "1")]
@@ -800,6 +844,7 @@ pub struct WindowsNetworkUsage {
 /// NetworkInterfaceUsage contains resource value data about a network interface.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct NetworkInterfaceUsage {
     /// The name of the network interface.
     #[prost(string, tag = "1")]
@@ -820,6 +865,7 @@ pub struct NetworkInterfaceUsage {
 /// WindowsNetworkInterfaceUsage contains resource value data about a network interface specific for Windows.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct WindowsNetworkInterfaceUsage {
     /// The name of the network interface.
     #[prost(string, tag = "1")]
@@ -840,6 +886,7 @@ pub struct WindowsNetworkInterfaceUsage {
 /// ProcessUsage are stats pertaining to processes.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ProcessUsage {
     /// Timestamp in nanoseconds at which the information were collected. Must be > 0.
     #[prost(int64, tag = "1")]
@@ -851,6 +898,7 @@ pub struct ProcessUsage {
 /// WindowsProcessUsage are stats pertaining to processes specific to Windows.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct WindowsProcessUsage {
     /// Timestamp in nanoseconds at which the information were collected. Must be > 0.
     #[prost(int64, tag = "1")]
@@ -862,6 +910,7 @@ pub struct WindowsProcessUsage {
 /// ImageSpec is an internal representation of an image.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ImageSpec {
     /// Container's Image field (e.g. imageID or imageDigest).
     #[prost(string, tag = "1")]
@@ -886,6 +935,7 @@ pub struct ImageSpec {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct KeyValue {
     #[prost(string, tag = "1")]
     pub key: ::prost::alloc::string::String,
@@ -896,6 +946,7 @@ pub struct KeyValue {
 /// resources.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct LinuxContainerResources {
     /// CPU CFS (Completely Fair Scheduler) period. Default: 0 (not specified).
     #[prost(int64, tag = "1")]
@@ -937,6 +988,7 @@ pub struct LinuxContainerResources {
 /// For example, `PageSize=1GB`, `Limit=1073741824` means setting `1073741824` bytes to hugetlb.1GB.limit_in_bytes.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct HugepageLimit {
     /// The value of PageSize has the format <size><unit-prefix>B (2MB, 1GB),
     /// and must match the <hugepagesize> of the corresponding control file found in `hugetlb.<hugepagesize>.limit_in_bytes`.
@@ -950,6 +1002,7 @@ pub struct HugepageLimit {
 /// SELinuxOption are the labels to be applied to the container.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct SeLinuxOption {
     #[prost(string, tag = "1")]
     pub user: ::prost::alloc::string::String,
@@ -970,6 +1023,7 @@ pub struct SeLinuxOption {
 /// permitted, inheritable, effective, bounding and ambient sets.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct Capability {
     /// List of capabilities to add.
     #[prost(string, repeated, tag = "1")]
@@ -986,6 +1040,7 @@ pub struct Capability {
 /// LinuxContainerSecurityContext holds linux security configuration that will be applied to a container.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct LinuxContainerSecurityContext {
     /// Capabilities to add or drop.
     #[prost(message, optional, tag = "1")]
@@ -1084,6 +1139,7 @@ pub struct LinuxContainerSecurityContext {
 /// Linux-based containers.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct LinuxContainerConfig {
     /// Resources specification for the container.
     #[prost(message, optional, tag = "1")]
@@ -1094,6 +1150,7 @@ pub struct LinuxContainerConfig {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct LinuxContainerUser {
     /// uid is the primary uid initially attached to the first process in the container
     #[prost(int64, tag = "1")]
@@ -1108,6 +1165,7 @@ pub struct LinuxContainerUser {
 /// WindowsNamespaceOption provides options for Windows namespaces.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct WindowsNamespaceOption {
     /// Network namespace for this container/sandbox.
     /// Namespaces currently set by the kubelet: POD, NODE
@@ -1119,6 +1177,7 @@ pub struct WindowsNamespaceOption {
 /// These settings will only apply to the sandbox container.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct WindowsSandboxSecurityContext {
     /// User name to run the container process as. If specified, the user MUST
     /// exist in the container image and be resolved there by the runtime;
@@ -1139,6 +1198,7 @@ pub struct WindowsSandboxSecurityContext {
 /// host platforms and Windows-based containers.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct WindowsPodSandboxConfig {
     /// WindowsSandboxSecurityContext holds sandbox security attributes.
     #[prost(message, optional, tag = "1")]
@@ -1147,6 +1207,7 @@ pub struct WindowsPodSandboxConfig {
 /// WindowsContainerSecurityContext holds windows security configuration that will be applied to a container.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct WindowsContainerSecurityContext {
     /// User name to run the container process as. If specified, the user MUST
     /// exist in the container image and be resolved there by the runtime;
@@ -1164,6 +1225,7 @@ pub struct WindowsContainerSecurityContext {
 /// Windows-based containers.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct WindowsContainerConfig {
     /// Resources specification for the container.
     #[prost(message, optional, tag = "1")]
@@ -1176,6 +1238,7 @@ pub struct WindowsContainerConfig {
 /// resources.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct WindowsContainerResources {
     /// CPU shares (relative weight vs. other containers). Default: 0 (not specified).
     #[prost(int64, tag = "1")]
@@ -1201,6 +1264,7 @@ pub struct WindowsContainerResources {
 /// <https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/miniport/ns-miniport-_group_affinity>
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct WindowsCpuGroupAffinity {
     /// CPU mask relative to this CPU group.
     #[prost(uint64, tag = "1")]
@@ -1217,6 +1281,7 @@ pub struct WindowsCpuGroupAffinity {
 /// within a sandbox for the entire lifetime of the sandbox.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ContainerMetadata {
     /// Name of the container. Same as the container name in the PodSpec.
     #[prost(string, tag = "1")]
@@ -1228,6 +1293,7 @@ pub struct ContainerMetadata {
 /// Device specifies a host device to mount into a container.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct Device {
     /// Path of the device within the container.
     #[prost(string, tag = "1")]
@@ -1245,6 +1311,7 @@ pub struct Device {
 /// CDIDevice specifies a CDI device information.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct CdiDevice {
     /// Fully qualified CDI device name
     /// for example: vendor.com/gpu=gpudevice1
@@ -1257,6 +1324,7 @@ pub struct CdiDevice {
 /// container.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ContainerConfig {
     /// Metadata of the container. This information will uniquely identify the
     /// container, and the runtime should leverage this to ensure correct
@@ -1338,6 +1406,7 @@ pub struct ContainerConfig {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct CreateContainerRequest {
     /// ID of the PodSandbox in which the container should be created.
     #[prost(string, tag = "1")]
@@ -1354,6 +1423,7 @@ pub struct CreateContainerRequest {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct CreateContainerResponse {
     /// ID of the created container.
     #[prost(string, tag = "1")]
@@ -1361,6 +1431,7 @@ pub struct CreateContainerResponse {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct StartContainerRequest {
     /// ID of the container to start.
     #[prost(string, tag = "1")]
@@ -1368,9 +1439,11 @@ pub struct StartContainerRequest {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct StartContainerResponse {}
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct StopContainerRequest {
     /// ID of the container to stop.
     #[prost(string, tag = "1")]
@@ -1382,9 +1455,11 @@ pub struct StopContainerRequest {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct StopContainerResponse {}
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct RemoveContainerRequest {
     /// ID of the container to remove.
     #[prost(string, tag = "1")]
@@ -1392,10 +1467,12 @@ pub struct RemoveContainerRequest {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct RemoveContainerResponse {}
 /// ContainerStateValue is the wrapper of ContainerState.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ContainerStateValue {
     /// State of the container.
     #[prost(enumeration = "ContainerState", tag = "1")]
@@ -1405,6 +1482,7 @@ pub struct ContainerStateValue {
 /// All those fields are combined with 'AND'
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ContainerFilter {
     /// ID of the container.
     #[prost(string, tag = "1")]
@@ -1426,6 +1504,7 @@ pub struct ContainerFilter {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ListContainersRequest {
     #[prost(message, optional, tag = "1")]
     pub filter: ::core::option::Option<ContainerFilter>,
@@ -1434,6 +1513,7 @@ pub struct ListContainersRequest {
 /// state of the container.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct Container {
     /// ID of the container, used by the container runtime to identify
     /// a container.
@@ -1486,6 +1566,7 @@ pub struct Container {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ListContainersResponse {
     /// List of containers.
     #[prost(message, repeated, tag = "1")]
@@ -1493,6 +1574,7 @@ pub struct ListContainersResponse {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ContainerStatusRequest {
     /// ID of the container for which to retrieve status.
     #[prost(string, tag = "1")]
@@ -1504,6 +1586,7 @@ pub struct ContainerStatusRequest {
 /// ContainerStatus represents the status of a container.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ContainerStatus {
     /// ID of the container.
     #[prost(string, tag = "1")]
@@ -1579,6 +1662,7 @@ pub struct ContainerStatus {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ContainerStatusResponse {
     /// Status of the container.
     #[prost(message, optional, tag = "1")]
@@ -1596,6 +1680,7 @@ pub struct ContainerStatusResponse {
 /// ContainerResources holds resource limits configuration for a container.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ContainerResources {
     /// Resource limits configuration specific to Linux container.
     #[prost(message, optional, tag = "1")]
@@ -1606,6 +1691,7 @@ pub struct ContainerResources {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ContainerUser {
     /// User identities initially attached to first process in the Linux container.
     /// Note that the actual running identity can be changed if the process has enough privilege to do so.
@@ -1614,6 +1700,7 @@ pub struct ContainerUser {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct UpdateContainerResourcesRequest {
     /// ID of the container to update.
     #[prost(string, tag = "1")]
@@ -1635,9 +1722,11 @@ pub struct UpdateContainerResourcesRequest {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct UpdateContainerResourcesResponse {}
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ExecSyncRequest {
     /// ID of the container.
     #[prost(string, tag = "1")]
@@ -1651,6 +1740,7 @@ pub struct ExecSyncRequest {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ExecSyncResponse {
     /// Captured command stdout output.
     /// The runtime should cap the output of this response to 16MB.
@@ -1672,6 +1762,7 @@ pub struct ExecSyncResponse {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ExecRequest {
     /// ID of the container in which to execute the command.
     #[prost(string, tag = "1")]
@@ -1700,6 +1791,7 @@ pub struct ExecRequest {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ExecResponse {
     /// Fully qualified URL of the exec streaming server.
     #[prost(string, tag = "1")]
@@ -1707,6 +1799,7 @@ pub struct ExecResponse {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct AttachRequest {
     /// ID of the container to which to attach.
     #[prost(string, tag = "1")]
@@ -1733,6 +1826,7 @@ pub struct AttachRequest {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct AttachResponse {
     /// Fully qualified URL of the attach streaming server.
     #[prost(string, tag = "1")]
@@ -1740,6 +1834,7 @@ pub struct AttachResponse {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct PortForwardRequest {
     /// ID of the container to which to forward the port.
     #[prost(string, tag = "1")]
@@ -1750,6 +1845,7 @@ pub struct PortForwardRequest {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct PortForwardResponse {
     /// Fully qualified URL of the port-forward streaming server.
     #[prost(string, tag = "1")]
@@ -1757,6 +1853,7 @@ pub struct PortForwardResponse {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ImageFilter {
     /// Spec of the image.
     #[prost(message, optional, tag = "1")]
@@ -1764,6 +1861,7 @@ pub struct ImageFilter {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ListImagesRequest {
     /// Filter to list images.
     #[prost(message, optional, tag = "1")]
@@ -1772,6 +1870,7 @@ pub struct ListImagesRequest {
 /// Basic information about a container image.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct Image {
     /// ID of the image.
     #[prost(string, tag = "1")]
@@ -1805,6 +1904,7 @@ pub struct Image {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ListImagesResponse {
     /// List of images.
     #[prost(message, repeated, tag = "1")]
@@ -1812,6 +1912,7 @@ pub struct ListImagesResponse {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ImageStatusRequest {
     /// Spec of the image.
     #[prost(message, optional, tag = "1")]
@@ -1822,6 +1923,7 @@ pub struct ImageStatusRequest {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ImageStatusResponse {
     /// Status of the image.
     #[prost(message, optional, tag = "1")]
@@ -1839,6 +1941,7 @@ pub struct ImageStatusResponse {
 /// AuthConfig contains authorization information for connecting to a registry.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct AuthConfig {
     #[prost(string, tag = "1")]
     pub username: ::prost::alloc::string::String,
@@ -1858,6 +1961,7 @@ pub struct AuthConfig {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct PullImageRequest {
     /// Spec of the image.
     #[prost(message, optional, tag = "1")]
@@ -1871,6 +1975,7 @@ pub struct PullImageRequest {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct PullImageResponse {
     /// Reference to the image in use. For most runtimes, this should be an
     /// image ID or digest.
@@ -1879,6 +1984,7 @@ pub struct PullImageResponse {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct RemoveImageRequest {
     /// Spec of the image to remove.
     #[prost(message, optional, tag = "1")]
@@ -1886,9 +1992,11 @@ pub struct RemoveImageRequest {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct RemoveImageResponse {}
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct NetworkConfig {
     /// CIDR to use for pod IP addresses. If the CIDR is empty, runtimes
     /// should omit it.
@@ -1897,18 +2005,21 @@ pub struct NetworkConfig {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct RuntimeConfig {
     #[prost(message, optional, tag = "1")]
     pub network_config: ::core::option::Option<NetworkConfig>,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct UpdateRuntimeConfigRequest {
     #[prost(message, optional, tag = "1")]
     pub runtime_config: ::core::option::Option<RuntimeConfig>,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct UpdateRuntimeConfigResponse {}
 /// RuntimeCondition contains condition information for the runtime.
 /// There are 2 kinds of runtime conditions:
@@ -1925,6 +2036,7 @@ pub struct UpdateRuntimeConfigResponse {}
 /// them understand the status of the system.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct RuntimeCondition {
     /// Type of runtime condition.
     #[prost(string, tag = "1")]
@@ -1942,6 +2054,7 @@ pub struct RuntimeCondition {
 /// RuntimeStatus is information about the current status of the runtime.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct RuntimeStatus {
     /// List of current observed runtime conditions.
     #[prost(message, repeated, tag = "1")]
@@ -1949,6 +2062,7 @@ pub struct RuntimeStatus {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct StatusRequest {
     /// Verbose indicates whether to return extra information about the runtime.
     #[prost(bool, tag = "1")]
@@ -1957,6 +2071,7 @@ pub struct StatusRequest {
 /// RuntimeHandlerFeatures is a set of features implemented by the runtime handler.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct RuntimeHandlerFeatures {
     /// recursive_read_only_mounts is set to true if the runtime handler supports
     /// recursive read-only mounts.
@@ -1971,6 +2086,7 @@ pub struct RuntimeHandlerFeatures {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct RuntimeHandler {
     /// Name must be unique in StatusResponse.
     /// An empty string denotes the default handler.
@@ -1985,6 +2101,7 @@ pub struct RuntimeHandler {
 /// independent of runtime handlers.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct RuntimeFeatures {
     /// supplemental_groups_policy is set to true if the runtime supports SupplementalGroupsPolicy and ContainerUser.
     #[prost(bool, tag = "1")]
@@ -1992,6 +2109,7 @@ pub struct RuntimeFeatures {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct StatusResponse {
     /// Status of the Runtime.
     #[prost(message, optional, tag = "1")]
@@ -2015,10 +2133,12 @@ pub struct StatusResponse {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ImageFsInfoRequest {}
 /// UInt64Value is the wrapper of uint64.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct UInt64Value {
     /// The value.
     #[prost(uint64, tag = "1")]
@@ -2027,6 +2147,7 @@ pub struct UInt64Value {
 /// FilesystemIdentifier uniquely identify the filesystem.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct FilesystemIdentifier {
     /// Mountpoint of a filesystem.
     #[prost(string, tag = "1")]
@@ -2035,6 +2156,7 @@ pub struct FilesystemIdentifier {
 /// FilesystemUsage provides the filesystem usage information.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct FilesystemUsage {
     /// Timestamp in nanoseconds at which the information were collected. Must be > 0.
     #[prost(int64, tag = "1")]
@@ -2056,6 +2178,7 @@ pub struct FilesystemUsage {
 /// WindowsFilesystemUsage provides the filesystem usage information specific to Windows.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct WindowsFilesystemUsage {
     /// Timestamp in nanoseconds at which the information were collected. Must be > 0.
     #[prost(int64, tag = "1")]
@@ -2071,6 +2194,7 @@ pub struct WindowsFilesystemUsage {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ImageFsInfoResponse {
     /// Information of image filesystem(s).
     #[prost(message, repeated, tag = "1")]
@@ -2084,6 +2208,7 @@ pub struct ImageFsInfoResponse {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ContainerStatsRequest {
     /// ID of the container for which to retrieve stats.
     #[prost(string, tag = "1")]
@@ -2091,6 +2216,7 @@ pub struct ContainerStatsRequest {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ContainerStatsResponse {
     /// Stats of the container.
     #[prost(message, optional, tag = "1")]
@@ -2098,6 +2224,7 @@ pub struct ContainerStatsResponse {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ListContainerStatsRequest {
     /// Filter for the list request.
     #[prost(message, optional, tag = "1")]
@@ -2107,6 +2234,7 @@ pub struct ListContainerStatsRequest {
 /// All those fields are combined with 'AND'
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ContainerStatsFilter {
     /// ID of the container.
     #[prost(string, tag = "1")]
@@ -2125,6 +2253,7 @@ pub struct ContainerStatsFilter {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ListContainerStatsResponse {
     /// Stats of the container.
     #[prost(message, repeated, tag = "1")]
@@ -2133,6 +2262,7 @@ pub struct ListContainerStatsResponse {
 /// ContainerAttributes provides basic information of the container.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ContainerAttributes {
     /// ID of the container.
     #[prost(string, tag = "1")]
@@ -2159,6 +2289,7 @@ pub struct ContainerAttributes {
 /// ContainerStats provides the resource usage statistics for a container.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ContainerStats {
     /// Information of the container.
     #[prost(message, optional, tag = "1")]
@@ -2179,6 +2310,7 @@ pub struct ContainerStats {
 /// WindowsContainerStats provides the resource usage statistics for a container specific for Windows
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct WindowsContainerStats {
     /// Information of the container.
     #[prost(message, optional, tag = "1")]
@@ -2196,6 +2328,7 @@ pub struct WindowsContainerStats {
 /// CpuUsage provides the CPU usage information.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct CpuUsage {
     /// Timestamp in nanoseconds at which the information were collected. Must be > 0.
     #[prost(int64, tag = "1")]
@@ -2211,6 +2344,7 @@ pub struct CpuUsage {
 /// WindowsCpuUsage provides the CPU usage information specific to Windows
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct WindowsCpuUsage {
     /// Timestamp in nanoseconds at which the information were collected. Must be > 0.
     #[prost(int64, tag = "1")]
@@ -2226,6 +2360,7 @@ pub struct WindowsCpuUsage {
 /// MemoryUsage provides the memory usage information.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct MemoryUsage {
     /// Timestamp in nanoseconds at which the information were collected. Must be > 0.
     #[prost(int64, tag = "1")]
@@ -2251,6 +2386,7 @@ pub struct MemoryUsage {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct SwapUsage {
     /// Timestamp in nanoseconds at which the information were collected. Must be > 0.
     #[prost(int64, tag = "1")]
@@ -2265,6 +2401,7 @@ pub struct SwapUsage {
 /// WindowsMemoryUsage provides the memory usage information specific to Windows
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct WindowsMemoryUsage {
     /// Timestamp in nanoseconds at which the information were collected. Must be > 0.
     #[prost(int64, tag = "1")]
@@ -2284,6 +2421,7 @@ pub struct WindowsMemoryUsage {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ReopenContainerLogRequest {
     /// ID of the container for which to reopen the log.
     #[prost(string, tag = "1")]
@@ -2291,9 +2429,11 @@ pub struct ReopenContainerLogRequest {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ReopenContainerLogResponse {}
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct CheckpointContainerRequest {
     /// ID of the container to be checkpointed.
     #[prost(string, tag = "1")]
@@ -2309,12 +2449,15 @@ pub struct CheckpointContainerRequest {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct CheckpointContainerResponse {}
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct GetEventsRequest {}
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ContainerEventResponse {
     /// ID of the container
     #[prost(string, tag = "1")]
@@ -2334,15 +2477,18 @@ pub struct ContainerEventResponse {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ListMetricDescriptorsRequest {}
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ListMetricDescriptorsResponse {
     #[prost(message, repeated, tag = "1")]
     pub descriptors: ::prost::alloc::vec::Vec<MetricDescriptor>,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct MetricDescriptor {
     /// The name field will be used as a unique identifier of this MetricDescriptor,
     /// and be used in conjunction with the Metric structure to populate the full Metric.
@@ -2359,15 +2505,18 @@ pub struct MetricDescriptor {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ListPodSandboxMetricsRequest {}
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ListPodSandboxMetricsResponse {
     #[prost(message, repeated, tag = "1")]
     pub pod_metrics: ::prost::alloc::vec::Vec<PodSandboxMetrics>,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct PodSandboxMetrics {
     #[prost(string, tag = "1")]
     pub pod_sandbox_id: ::prost::alloc::string::String,
@@ -2378,6 +2527,7 @@ pub struct PodSandboxMetrics {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct ContainerMetrics {
     #[prost(string, tag = "1")]
     pub container_id: ::prost::alloc::string::String,
@@ -2386,6 +2536,7 @@ pub struct ContainerMetrics {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct Metric {
     /// Name must match a name previously returned in a MetricDescriptors call,
     /// otherwise, it will be ignored.
@@ -2407,9 +2558,11 @@ pub struct Metric {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct RuntimeConfigRequest {}
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct RuntimeConfigResponse {
     /// Configuration information for Linux-based runtimes. This field contains
     /// global runtime configuration options that are not specific to runtime
@@ -2419,6 +2572,7 @@ pub struct RuntimeConfigResponse {
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
+#[derive(serde::Serialize)]
 pub struct LinuxRuntimeConfiguration {
     /// Cgroup driver to use
     /// Note: this field should not change for the lifecycle of the Kubelet,
@@ -2431,6 +2585,7 @@ pub struct LinuxRuntimeConfiguration {
     pub cgroup_driver: i32,
 }
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[derive(serde::Serialize)]
 #[repr(i32)]
 pub enum Protocol {
     Tcp = 0,
@@ -2460,6 +2615,7 @@ impl Protocol {
     }
 }
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[derive(serde::Serialize)]
 #[repr(i32)]
 pub enum MountPropagation {
     /// No mount propagation ("rprivate" in Linux terminology).
@@ -2498,6 +2654,7 @@ impl MountPropagation {
 /// of the namespaces (Network, PID, IPC) in NamespaceOption. Runtimes should
 /// map these modes as appropriate for the technology underlying the runtime.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[derive(serde::Serialize)]
 #[repr(i32)]
 pub enum NamespaceMode {
     /// A POD namespace is common to all containers in a pod.
@@ -2547,6 +2704,7 @@ impl NamespaceMode {
 /// SupplementalGroupsPolicy defines how supplemental groups
 /// of the first container processes are calculated.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[derive(serde::Serialize)]
 #[repr(i32)]
 pub enum SupplementalGroupsPolicy {
     /// Merge means that the container's provided SupplementalGroups
@@ -2580,6 +2738,7 @@ impl SupplementalGroupsPolicy {
     }
 }
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[derive(serde::Serialize)]
 #[repr(i32)]
 pub enum PodSandboxState {
     SandboxReady = 0,
@@ -2606,6 +2765,7 @@ impl PodSandboxState {
     }
 }
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[derive(serde::Serialize)]
 #[repr(i32)]
 pub enum ContainerState {
     ContainerCreated = 0,
@@ -2638,6 +2798,7 @@ impl ContainerState {
     }
 }
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[derive(serde::Serialize)]
 #[repr(i32)]
 pub enum ContainerEventType {
     /// Container created
@@ -2674,6 +2835,7 @@ impl ContainerEventType {
     }
 }
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[derive(serde::Serialize)]
 #[repr(i32)]
 pub enum MetricType {
     Counter = 0,
@@ -2700,6 +2862,7 @@ impl MetricType {
     }
 }
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[derive(serde::Serialize)]
 #[repr(i32)]
 pub enum CgroupDriver {
     Systemd = 0,
@@ -0,0 +1,153 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::{Value, json};
+use crate::metrics;
+use crate::rootpath;
+use crate::secret;
+use tracing::{info, warn};
+
+// --debug-cri dumps every CRI request and response made by this run of rkl as
+// pretty JSON to <root>/debug/cri-<unix time>-<pid>.log, for bug reports about
+// failing sandbox and container creation. Registry credentials, the values of
+// Secrets and of environment variables that look like secrets are redacted.
+
+static ENABLED: OnceLock<bool> = OnceLock::new();
+static LOG: OnceLock<Option<Mutex<File>>> = OnceLock::new();
+
+const REDACTED: &str = "<redacted>";
+// fields of AuthConfig
+const SECRET_FIELDS: [&str; 4] = ["password", "auth", "identity_token", "registry_token"];
+const SECRET_ENV_HINTS: [&str; 6] = ["SECRET", "PASSWORD", "PASSWD", "TOKEN", "CREDENTIAL", "KEY"];
+
+pub fn init(enabled: bool) {
+    let _ = ENABLED.set(enabled);
+}
+
+fn log() -> Option<&'static Mutex<File>> {
+    if !ENABLED.get().copied().unwrap_or(false) {
+        return None;
+    }
+    LOG.get_or_init(|| {
+        let opened = rootpath::determine(None).and_then(|root_path| {
+            let dir = root_path.join("debug");
+            fs::create_dir_all(&dir)?;
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+            let path = dir.join(format!("cri-{}-{}.log", now, std::process::id()));
+            let file = File::create(&path)?;
+            info!("Logging CRI calls to {}", path.display());
+            Ok(file)
+        });
+        match opened {
+            Ok(file) => Some(Mutex::new(file)),
+            Err(e) => {
+                warn!("Failed to open the CRI debug log: {}", e);
+                None
+            }
+        }
+    })
+    .as_ref()
+}
+
+// run a CRI call, recording its request and response when --debug-cri is set
+// and its latency in the metrics
+pub fn traced<Req, Resp>(method: &str, request: Req, call: impl FnOnce(Req) -> Result<Resp>) -> Result<Resp>
+where
+    Req: Serialize,
+    Resp: Serialize,
+{
+    let _span = tracing::info_span!("cri", method).entered();
+    let Some(log) = log() else {
+        let started = Instant::now();
+        let result = call(request);
+        metrics::CRI_REQUEST_DURATION.observe_with(method, started.elapsed());
+        return result;
+    };
+    let mut entry = json!({
+        "method": method,
+        "time": SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64()).unwrap_or(0.0),
+        "request": redact(serde_json::to_value(&request).unwrap_or(Value::Null)),
+    });
+    let started = Instant::now();
+    let result = call(request);
+    metrics::CRI_REQUEST_DURATION.observe_with(method, started.elapsed());
+    entry["durationMs"] = json!(started.elapsed().as_millis() as u64);
+    match &result {
+        Ok(response) => entry["response"] = redact(serde_json::to_value(response).unwrap_or(Value::Null)),
+        Err(e) => entry["error"] = json!(secret::scrub(&e.to_string())),
+    }
+    if let Ok(pretty) = serde_json::to_string_pretty(&entry) {
+        let _ = writeln!(log.lock().unwrap(), "{}", pretty);
+    }
+    result
+}
+
+fn secret_env(name: &str) -> bool {
+    let name = name.to_ascii_uppercase();
+    SECRET_ENV_HINTS.iter().any(|hint| name.contains(hint))
+}
+
+pub fn redact(mut value: Value) -> Value {
+    match &mut value {
+        Value::Object(fields) => {
+            for (name, field) in fields.iter_mut() {
+                if SECRET_FIELDS.contains(&name.as_str()) && field.as_str().is_some_and(|s| !s.is_empty()) {
+                    *field = json!(REDACTED);
+                } else if name == "envs"
+                    && let Value::Array(envs) = field
+                {
+                    for env in envs.iter_mut() {
+                        if env["key"].as_str().is_some_and(secret_env) || env["value"].as_str().is_some_and(secret::is_revealed) {
+                            env["value"] = json!(REDACTED);
+                        }
+                    }
+                } else {
+                    *field = redact(field.take());
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items.iter_mut() {
+                *item = redact(item.take());
+            }
+        }
+        _ => {}
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cri::cri::{AuthConfig, ContainerConfig, KeyValue, PullImageRequest};
+
+    #[test]
+    fn test_redact() {
+        let request = PullImageRequest {
+            auth: Some(AuthConfig {
+                username: "ops".to_string(),
+                password: "s3cret".to_string(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let value = redact(serde_json::to_value(&request).unwrap());
+        assert_eq!(value["auth"]["username"], "ops");
+        assert_eq!(value["auth"]["password"], REDACTED);
+        assert_eq!(value["auth"]["identity_token"], "");
+
+        let config = ContainerConfig {
+            envs: vec![
+                KeyValue { key: "DB_PASSWORD".to_string(), value: "hunter2".to_string() },
+                KeyValue { key: "PORT".to_string(), value: "8080".to_string() },
+            ],
+            ..Default::default()
+        };
+        let value = redact(serde_json::to_value(&config).unwrap());
+        assert_eq!(value["envs"][0]["value"], REDACTED);
+        assert_eq!(value["envs"][1]["value"], "8080");
+    }
+}
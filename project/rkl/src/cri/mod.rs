@@ -1 +1,2 @@
 pub mod cri;
+pub mod debug;
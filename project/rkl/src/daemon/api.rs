@@ -0,0 +1,179 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::thread;
+use anyhow::{Result, anyhow};
+use crate::daemon::auth::{self, AuthError, Verb};
+use crate::daemon::remote::{Control, ControlError};
+use crate::daemon::sync::PodStatus;
+use tracing::warn;
+
+// Local status API of the daemon, plain HTTP/1.1 with JSON bodies on a unix socket:
+//   GET /healthz          "ok"
+//   GET /pods             status of every pod
+//   GET /pods/<name>      status of one pod
+//   POST /pods            create a pod from the YAML manifest of the body
+//   DELETE /pods/<name>   delete a pod created with POST
+// e.g. curl --unix-socket /run/youki/rkl.sock http://localhost/pods
+// With a token file requests need an "Authorization: Bearer <token>" header.
+
+// largest manifest accepted by POST /pods
+const MAX_BODY: usize = 1 << 20;
+
+pub fn serve(socket: &Path, control: Control) -> Result<()> {
+    if socket.exists() {
+        fs::remove_file(socket)?;
+    }
+    if let Some(dir) = socket.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let listener = UnixListener::bind(socket)
+        .map_err(|e| anyhow!("Failed to listen on {}: {}", socket.display(), e))?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let control = control.clone();
+            thread::spawn(move || {
+                if let Err(e) = handle(stream, &control) {
+                    warn!("Status API request failed: {}", e);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+fn handle(stream: UnixStream, control: &Control) -> Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // only the length of the body and the token are needed from the headers
+    let mut content_length = 0;
+    let mut token = None;
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().map_err(|_| anyhow!("invalid Content-Length {}", value.trim()))?;
+            } else if name.eq_ignore_ascii_case("authorization") {
+                token = auth::bearer_token(value).map(str::to_string);
+            }
+        }
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+    let verb = if method == "GET" { Verb::Read } else { Verb::Write };
+    // health checks don't need a token
+    let health_check = method == "GET" && path.trim_end_matches('/') == "/healthz";
+    if !health_check
+        && let Err(e) = control.authorize(token.as_deref(), verb)
+    {
+        let code = match e {
+            AuthError::Unauthenticated => 401,
+            AuthError::Forbidden(_) => 403,
+        };
+        return write_response(stream, code, &error_body(&e.to_string()));
+    }
+    let (code, body) = match method {
+        "GET" => respond(path, &control.pods()),
+        "POST" | "DELETE" if content_length > MAX_BODY => (413, error_body("manifest too large")),
+        "POST" if path.trim_end_matches('/') == "/pods" => {
+            let mut manifest = vec![0; content_length];
+            reader.read_exact(&mut manifest)?;
+            match control.create_pod(&String::from_utf8_lossy(&manifest)) {
+                Ok(name) => (201, serde_json::json!({ "name": name }).to_string()),
+                Err(e) => control_error(e),
+            }
+        }
+        "DELETE" => match path.trim_end_matches('/').strip_prefix("/pods/") {
+            Some(name) => match control.delete_pod(name, None) {
+                Ok(()) => (200, serde_json::json!({ "name": name }).to_string()),
+                Err(e) => control_error(e),
+            },
+            None => (404, error_body("not found")),
+        },
+        "POST" => (404, error_body("not found")),
+        _ => (405, error_body("method not allowed")),
+    };
+    write_response(stream, code, &body)
+}
+
+fn error_body(message: &str) -> String {
+    serde_json::json!({ "error": message }).to_string()
+}
+
+fn control_error(e: ControlError) -> (u16, String) {
+    let code = match e {
+        ControlError::InvalidManifest(_) => 400,
+        ControlError::NotFound(_) => 404,
+        ControlError::AlreadyExists(_) | ControlError::StaticPod(_) => 409,
+        ControlError::Failed(_) => 500,
+    };
+    (code, error_body(&e.to_string()))
+}
+
+// status code and body of a GET request
+fn respond(path: &str, pods: &[PodStatus]) -> (u16, String) {
+    let path = path.split('?').next().unwrap_or_default();
+    match path.trim_end_matches('/') {
+        "/healthz" => (200, "\"ok\"".to_string()),
+        "/pods" => (200, serde_json::to_string(pods).unwrap_or_default()),
+        other => match other.strip_prefix("/pods/") {
+            Some(name) => match pods.iter().find(|pod| pod.name == name) {
+                Some(pod) => (200, serde_json::to_string(pod).unwrap_or_default()),
+                None => (404, error_body(&format!("pod {} not found", name))),
+            },
+            None => (404, error_body("not found")),
+        },
+    }
+}
+
+fn write_response(mut stream: UnixStream, code: u16, body: &str) -> Result<()> {
+    let reason = match code {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        _ => "Internal Server Error",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        code,
+        reason,
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_respond() {
+        let pods = vec![PodStatus {
+            name: "web".to_string(),
+            manifest: "/etc/rk8s/manifests/web.yaml".to_string(),
+            sandbox_id: Some("web".to_string()),
+            pod_ip: Some("10.88.0.2".to_string()),
+            phase: "Running".to_string(),
+            last_error: None,
+            containers: Vec::new(),
+        }];
+        assert_eq!(respond("/healthz", &pods).0, 200);
+        let (code, body) = respond("/pods/web", &pods);
+        assert_eq!(code, 200);
+        assert!(body.contains("\"phase\":\"Running\""));
+        assert_eq!(respond("/pods/db", &pods).0, 404);
+        assert_eq!(respond("/nodes", &pods).0, 404);
+    }
+}
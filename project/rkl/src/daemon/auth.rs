@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use anyhow::{Result, anyhow};
+
+// Access control of the daemon APIs. With --token-file every request needs a
+// bearer token of the file, one "<token> <user> <role>" line per token:
+//   admin   everything
+//   view    get, list and logs only, e.g. for dashboards and on-call tooling
+// --read-only rejects every request that would change workloads, whatever the
+// role of the token.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Admin,
+    View,
+}
+
+// what a request does to the workloads of the node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verb {
+    Read,
+    Write,
+}
+
+#[derive(Debug)]
+pub enum AuthError {
+    Unauthenticated,
+    Forbidden(String),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::Unauthenticated => write!(f, "a valid bearer token is required"),
+            AuthError::Forbidden(reason) => write!(f, "forbidden: {}", reason),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Authorizer {
+    read_only: bool,
+    // user and role by token, no authentication when unset
+    tokens: Option<HashMap<String, (String, Role)>>,
+}
+
+impl Authorizer {
+    pub fn new(read_only: bool, token_file: Option<&Path>) -> Result<Self> {
+        let tokens = token_file.map(parse_token_file).transpose()?;
+        Ok(Authorizer { read_only, tokens })
+    }
+
+    // role of a token of the token file
+    pub fn role(&self, token: &str) -> Option<Role> {
+        self.tokens.as_ref()?.get(token).map(|(_, role)| *role)
+    }
+
+    pub fn authorize(&self, token: Option<&str>, verb: Verb) -> Result<(), AuthError> {
+        if let Some(tokens) = &self.tokens {
+            let (user, role) = token.and_then(|token| tokens.get(token)).ok_or(AuthError::Unauthenticated)?;
+            if verb == Verb::Write && *role == Role::View {
+                return Err(AuthError::Forbidden(format!("{} has a view-only token", user)));
+            }
+        }
+        if verb == Verb::Write && self.read_only {
+            return Err(AuthError::Forbidden("the daemon API is read-only".to_string()));
+        }
+        Ok(())
+    }
+}
+
+// the token of an "Authorization: Bearer <token>" header value
+pub fn bearer_token(header: &str) -> Option<&str> {
+    let (scheme, token) = header.trim().split_once(' ')?;
+    scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+}
+
+fn parse_token_file(path: &Path) -> Result<HashMap<String, (String, Role)>> {
+    let contents = fs::read_to_string(path).map_err(|e| anyhow!("Failed to read token file {}: {}", path.display(), e))?;
+    let mut tokens = HashMap::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [token, user, role] = fields[..] else {
+            return Err(anyhow!("{}:{}: expected \"<token> <user> <role>\"", path.display(), number + 1));
+        };
+        let role = match role {
+            "admin" => Role::Admin,
+            "view" => Role::View,
+            other => return Err(anyhow!("{}:{}: unknown role {}", path.display(), number + 1, other)),
+        };
+        tokens.insert(token.to_string(), (user.to_string(), role));
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_authorize() {
+        let dir = tempfile::tempdir().unwrap();
+        let token_file = dir.path().join("tokens");
+        fs::write(&token_file, "# node tokens\ns3cret ops admin\nlook dashboard view\n").unwrap();
+
+        let authorizer = Authorizer::new(false, Some(&token_file)).unwrap();
+        assert!(authorizer.authorize(Some("s3cret"), Verb::Write).is_ok());
+        assert!(authorizer.authorize(Some("look"), Verb::Read).is_ok());
+        assert!(matches!(authorizer.authorize(Some("look"), Verb::Write), Err(AuthError::Forbidden(_))));
+        assert!(matches!(authorizer.authorize(None, Verb::Read), Err(AuthError::Unauthenticated)));
+        assert!(matches!(authorizer.authorize(Some("guess"), Verb::Read), Err(AuthError::Unauthenticated)));
+
+        // read-only applies to admins too, and without tokens
+        let authorizer = Authorizer::new(true, Some(&token_file)).unwrap();
+        assert!(matches!(authorizer.authorize(Some("s3cret"), Verb::Write), Err(AuthError::Forbidden(_))));
+        let authorizer = Authorizer::new(true, None).unwrap();
+        assert!(authorizer.authorize(None, Verb::Read).is_ok());
+        assert!(authorizer.authorize(None, Verb::Write).is_err());
+
+        fs::write(&token_file, "s3cret ops root\n").unwrap();
+        assert!(Authorizer::new(false, Some(&token_file)).is_err());
+        assert_eq!(bearer_token("Bearer s3cret"), Some("s3cret"));
+        assert_eq!(bearer_token("Basic b3Bz"), None);
+    }
+}
@@ -0,0 +1,154 @@
+use anyhow::{Result, anyhow};
+
+// Cron schedules of CronJobs: the five fields minute, hour, day of month,
+// month and day of week, each *, a number, a range a-b or a list of them, with
+// an optional /step, months and days also by name, or one of the @hourly,
+// @daily, @weekly, @monthly and @yearly macros. Like cron, when both the day of
+// month and the day of week are restricted either of them matches. Times are
+// in UTC.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // whether the day of month and week fields are *
+    any_day: bool,
+    any_weekday: bool,
+}
+
+const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+// the values of a field as a bit set
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64> {
+    let value = |text: &str| -> Result<u32> {
+        let lower = text.to_ascii_lowercase();
+        if let Some(index) = names.iter().position(|name| *name == lower) {
+            return Ok(index as u32 + min);
+        }
+        let value: u32 = text.parse().map_err(|_| anyhow!("invalid value {:?}", text))?;
+        if value < min || value > max {
+            return Err(anyhow!("{} is out of range {}-{}", value, min, max));
+        }
+        Ok(value)
+    };
+    let mut bits = 0;
+    for term in field.split(',') {
+        let (range, step) = match term.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|step| *step > 0).ok_or_else(|| anyhow!("invalid step {:?}", step))?),
+            None => (term, 1),
+        };
+        let (first, last) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((first, last)) => (value(first)?, value(last)?),
+                // a/n runs from a to the end
+                None if step > 1 => (value(range)?, max),
+                None => (value(range)?, value(range)?),
+            },
+        };
+        if first > last {
+            return Err(anyhow!("invalid range {:?}", range));
+        }
+        for v in (first..=last).step_by(step as usize) {
+            bits |= 1 << v;
+        }
+    }
+    Ok(bits)
+}
+
+impl Schedule {
+    pub fn parse(expression: &str) -> Result<Self> {
+        let expression = match expression.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            expression => expression,
+        };
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(anyhow!("invalid schedule {:?}: expected 5 fields", expression));
+        };
+        let invalid = |field: &str, e: anyhow::Error| anyhow!("invalid schedule {:?}: {} field: {}", expression, field, e);
+        // 7 is sunday as well
+        let weekdays = parse_field(weekday, 0, 7, &WEEKDAYS).map_err(|e| invalid("day of week", e))?;
+        Ok(Schedule {
+            minutes: parse_field(minute, 0, 59, &[]).map_err(|e| invalid("minute", e))?,
+            hours: parse_field(hour, 0, 23, &[]).map_err(|e| invalid("hour", e))?,
+            days: parse_field(day, 1, 31, &[]).map_err(|e| invalid("day of month", e))?,
+            months: parse_field(month, 1, 12, &MONTHS).map_err(|e| invalid("month", e))?,
+            weekdays: (weekdays | weekdays >> 7) & 0x7f,
+            any_day: day.starts_with('*'),
+            any_weekday: weekday.starts_with('*'),
+        })
+    }
+
+    // whether the schedule fires at the minute starting at the unix time
+    pub fn matches(&self, time: u64) -> bool {
+        let minutes = time / 60;
+        let (minute, hour) = (minutes % 60, minutes / 60 % 24);
+        let days = minutes / (60 * 24);
+        let (_, month, day) = civil_from_days(days);
+        // 1970-01-01 was a thursday
+        let weekday = (days + 4) % 7;
+        let day_matches = match (self.any_day, self.any_weekday) {
+            (false, false) => self.days & 1 << day != 0 || self.weekdays & 1 << weekday != 0,
+            _ => self.days & 1 << day != 0 && self.weekdays & 1 << weekday != 0,
+        };
+        self.minutes & 1 << minute != 0 && self.hours & 1 << hour != 0 && self.months & 1 << month != 0 && day_matches
+    }
+
+    // the latest time the schedule fired after `after` and up to `until`,
+    // looking back at most a year
+    pub fn latest(&self, after: u64, until: u64) -> Option<u64> {
+        let first = (after / 60 + 1).max((until / 60).saturating_sub(366 * 24 * 60));
+        (first..=until / 60).rev().map(|minute| minute * 60).find(|time| self.matches(*time))
+    }
+}
+
+// year, month and day of a number of days since 1970-01-01
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedule() {
+        // 2024-03-15 12:30 UTC, a friday
+        let friday = 1_710_505_800;
+        assert_eq!(civil_from_days(friday / 86_400), (2024, 3, 15));
+        assert!(Schedule::parse("30 12 * * *").unwrap().matches(friday));
+        assert!(Schedule::parse("*/15 9-17 * * mon-fri").unwrap().matches(friday));
+        assert!(!Schedule::parse("*/15 9-17 * * sat,sun").unwrap().matches(friday));
+        // either the day of month or the day of week
+        assert!(Schedule::parse("30 12 1 * 5").unwrap().matches(friday));
+        assert!(Schedule::parse("30 12 15 mar 7").unwrap().matches(friday));
+        assert!(!Schedule::parse("@daily").unwrap().matches(friday));
+
+        let hourly = Schedule::parse("@hourly").unwrap();
+        assert_eq!(hourly.latest(friday - 7200, friday), Some(friday - 1800));
+        assert_eq!(hourly.latest(friday - 1800, friday), None);
+
+        assert!(Schedule::parse("* * *").is_err());
+        assert!(Schedule::parse("60 * * * *").is_err());
+        assert!(Schedule::parse("*/0 * * * *").is_err());
+        assert!(Schedule::parse("5-1 * * * *").is_err());
+    }
+}
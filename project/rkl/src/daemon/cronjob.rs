@@ -0,0 +1,322 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use crate::daemon::cron::Schedule;
+use crate::daemon::job::{ConditionType, Job, JobController};
+use crate::events::{self, EventType};
+use crate::rootpath;
+use crate::task::task::ObjectMeta;
+use tracing::{error, info};
+
+// A CronJob of a manifest directory creates a Job of its jobTemplate every time
+// its schedule fires, named <cronjob>-<minutes since the epoch>, which the Job
+// controller then runs. concurrencyPolicy decides what happens while a Job of
+// the CronJob is still running: Allow runs the next one next to it, Forbid
+// skips it and Replace deletes the running one first. Only the last
+// successfulJobsHistoryLimit and failedJobsHistoryLimit finished Jobs are kept.
+// A daemon that wasn't running when the schedule fired creates the Job it
+// missed last, unless startingDeadlineSeconds passed since. The Jobs are kept
+// in <root>/daemon/cronjobs/<name>.json.
+
+pub const KIND: &str = "CronJob";
+const CRONJOB_NAME: &str = "cronjob-name";
+
+#[derive(Debug, Deserialize)]
+pub struct CronJob {
+    #[serde(rename = "apiVersion")]
+    pub api_version: String,
+    pub kind: String,
+    pub metadata: ObjectMeta,
+    pub spec: CronJobSpec,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CronJobSpec {
+    pub schedule: String,
+    #[serde(rename = "concurrencyPolicy", default)]
+    pub concurrency_policy: ConcurrencyPolicy,
+    #[serde(default)]
+    pub suspend: bool,
+    #[serde(rename = "startingDeadlineSeconds", default)]
+    pub starting_deadline_seconds: Option<u64>,
+    #[serde(rename = "successfulJobsHistoryLimit", default = "default_successful_limit")]
+    pub successful_jobs_history_limit: usize,
+    #[serde(rename = "failedJobsHistoryLimit", default = "default_failed_limit")]
+    pub failed_jobs_history_limit: usize,
+    // kept as written, the jobs are made of it
+    #[serde(rename = "jobTemplate")]
+    pub job_template: Value,
+}
+
+fn default_successful_limit() -> usize {
+    3
+}
+
+fn default_failed_limit() -> usize {
+    1
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum ConcurrencyPolicy {
+    #[default]
+    Allow,
+    Forbid,
+    Replace,
+}
+
+impl CronJob {
+    pub fn parse(contents: &str) -> Result<Self> {
+        let cronjob: CronJob = serde_yaml::from_str(contents)?;
+        if cronjob.kind != KIND || cronjob.api_version != "batch/v1" {
+            return Err(anyhow!("expected apiVersion batch/v1 and kind CronJob"));
+        }
+        Schedule::parse(&cronjob.spec.schedule)?;
+        Job::parse(&cronjob.job_manifest(&cronjob.metadata.name)?).map_err(|e| anyhow!("invalid jobTemplate: {}", e))?;
+        Ok(cronjob)
+    }
+
+    // the manifest of a job of the template
+    fn job_manifest(&self, name: &str) -> Result<String> {
+        let mut metadata = self.spec.job_template.get("metadata").cloned().unwrap_or(Value::Null);
+        metadata["name"] = Value::from(name);
+        metadata["namespace"] = Value::from(self.metadata.namespace.as_str());
+        metadata["labels"][CRONJOB_NAME] = Value::from(self.metadata.name.as_str());
+        let spec = self.spec.job_template.get("spec").cloned().ok_or_else(|| anyhow!("the job template has no spec"))?;
+        let mut job = Mapping::new();
+        job.insert("apiVersion".into(), "batch/v1".into());
+        job.insert("kind".into(), "Job".into());
+        job.insert("metadata".into(), metadata);
+        job.insert("spec".into(), spec);
+        Ok(serde_yaml::to_string(&job)?)
+    }
+}
+
+// what the controller keeps of a cronjob
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CronJobState {
+    manifest: PathBuf,
+    contents: String,
+    // unix time the cronjob was first seen, nothing before it is missed
+    created: u64,
+    #[serde(rename = "lastScheduleTime")]
+    last_schedule_time: Option<u64>,
+    // the jobs by name in the order they were created
+    jobs: Vec<String>,
+}
+
+fn state_dir(root_path: &Path) -> PathBuf {
+    root_path.join("daemon").join("cronjobs")
+}
+
+fn state_path(root_path: &Path, name: &str) -> PathBuf {
+    state_dir(root_path).join(format!("{}.json", name))
+}
+
+// the state of every cronjob of the manifest directories
+pub struct CronJobController {
+    root_path: PathBuf,
+    states: HashMap<String, CronJobState>,
+}
+
+impl CronJobController {
+    pub fn load(root_path: &Path) -> Result<Self> {
+        let mut states = HashMap::new();
+        let dir = state_dir(root_path);
+        if dir.exists() {
+            for entry in fs::read_dir(&dir)? {
+                let path = entry?.path();
+                let Some(name) = path.file_stem().filter(|_| path.extension().is_some_and(|ext| ext == "json")) else {
+                    continue;
+                };
+                let state: CronJobState = serde_json::from_str(&fs::read_to_string(&path)?)
+                    .map_err(|e| anyhow!("invalid cronjob state {}: {}", path.display(), e))?;
+                states.insert(name.to_string_lossy().to_string(), state);
+            }
+        }
+        Ok(CronJobController { root_path: root_path.to_path_buf(), states })
+    }
+
+    // the manifest every cronjob was last reconciled from, by name
+    pub fn applied(&self) -> impl Iterator<Item = (&String, &Path, &String)> {
+        self.states.iter().map(|(name, state)| (name, state.manifest.as_path(), &state.contents))
+    }
+
+    // whether the job belongs to a cronjob
+    pub fn owns(&self, job_name: &str) -> bool {
+        self.states.values().any(|state| state.jobs.iter().any(|job| job == job_name))
+    }
+
+    // create the jobs of the cronjobs, given as name, manifest and contents,
+    // whose schedule fired by now and return the manifests of the jobs they
+    // should have as manifest of their cronjob, job name and job manifest
+    pub fn reconcile(
+        &mut self,
+        cronjobs: Vec<(String, PathBuf, String)>,
+        jobs: &JobController,
+        now: u64,
+    ) -> Vec<(PathBuf, String, String)> {
+        let names: HashSet<&String> = cronjobs.iter().map(|(name, _, _)| name).collect();
+        let removed: Vec<String> = self.states.keys().filter(|name| !names.contains(name)).cloned().collect();
+        for name in removed {
+            info!("Manifest of CronJob {} removed, deleting its jobs", name);
+            self.states.remove(&name);
+            let _ = fs::remove_file(state_path(&self.root_path, &name));
+        }
+
+        let mut manifests = Vec::new();
+        for (name, manifest, contents) in cronjobs {
+            match self.reconcile_cronjob(&name, manifest.clone(), contents, jobs, now) {
+                Ok(job_manifests) => manifests.extend(job_manifests.into_iter().map(|(job, contents)| (manifest.clone(), job, contents))),
+                Err(e) => error!("Failed to reconcile CronJob {}: {}", name, e),
+            }
+        }
+        manifests
+    }
+
+    fn reconcile_cronjob(
+        &mut self,
+        name: &str,
+        manifest: PathBuf,
+        contents: String,
+        jobs: &JobController,
+        now: u64,
+    ) -> Result<Vec<(String, String)>> {
+        let cronjob = CronJob::parse(&contents)?;
+        let schedule = Schedule::parse(&cronjob.spec.schedule)?;
+        let state = self.states.entry(name.to_string()).or_insert_with(|| CronJobState { created: now, ..CronJobState::default() });
+        let before = serde_json::to_string(state)?;
+        state.manifest = manifest;
+        state.contents = contents;
+
+        let after = state.last_schedule_time.unwrap_or(state.created);
+        let scheduled = schedule.latest(after, now).filter(|_| !cronjob.spec.suspend);
+        if let Some(time) = scheduled {
+            state.last_schedule_time = Some(time);
+            let active: Vec<String> = state.jobs.iter().filter(|job| jobs.condition(job).is_none()).cloned().collect();
+            let late = cronjob.spec.starting_deadline_seconds.is_some_and(|deadline| now - time > deadline);
+            if late {
+                let message = format!("Missed the scheduled time to start a job: {}", time);
+                let _ = events::record(&self.root_path, name, EventType::Warning, events::MISS_SCHEDULE, &message);
+            } else if !active.is_empty() && cronjob.spec.concurrency_policy == ConcurrencyPolicy::Forbid {
+                let message = "Not starting job because prior execution is running and concurrency policy is Forbid";
+                let _ = events::record(&self.root_path, name, EventType::Normal, events::JOB_ALREADY_ACTIVE, message);
+            } else {
+                if cronjob.spec.concurrency_policy == ConcurrencyPolicy::Replace {
+                    for job in active {
+                        let message = format!("Deleted job {}", job);
+                        let _ = events::record(&self.root_path, name, EventType::Normal, events::SUCCESSFUL_DELETE, &message);
+                        state.jobs.retain(|j| *j != job);
+                    }
+                }
+                let job = format!("{}-{}", name, time / 60);
+                let message = format!("Created job {}", job);
+                let _ = events::record(&self.root_path, name, EventType::Normal, events::SUCCESSFUL_CREATE, &message);
+                state.jobs.push(job);
+            }
+        }
+
+        // forget the oldest finished jobs past the history limits
+        for (kind, limit) in [
+            (ConditionType::Complete, cronjob.spec.successful_jobs_history_limit),
+            (ConditionType::Failed, cronjob.spec.failed_jobs_history_limit),
+        ] {
+            let finished: Vec<String> = state.jobs.iter().filter(|job| jobs.condition(job) == Some(kind)).cloned().collect();
+            for job in &finished[..finished.len().saturating_sub(limit)] {
+                state.jobs.retain(|j| j != job);
+            }
+        }
+
+        let mut manifests = Vec::new();
+        for job in &state.jobs {
+            manifests.push((job.clone(), cronjob.job_manifest(job)?));
+        }
+
+        let after = serde_json::to_string(state)?;
+        if after != before {
+            let path = state_path(&self.root_path, name);
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(&path, after)?;
+        }
+        Ok(manifests)
+    }
+}
+
+// print the status of a cronjob of the local daemon
+pub fn status(name: &str) -> Result<()> {
+    let root_path = rootpath::determine(None)?;
+    let contents = fs::read_to_string(state_path(&root_path, name)).map_err(|_| anyhow!("CronJob {} not found", name))?;
+    let state: CronJobState = serde_json::from_str(&contents)?;
+    let cronjob = CronJob::parse(&state.contents)?;
+    let jobs = JobController::load(&root_path)?;
+    println!("Name:               {}", name);
+    println!("Schedule:           {}", cronjob.spec.schedule);
+    println!("Concurrency Policy: {:?}", cronjob.spec.concurrency_policy);
+    println!("Suspend:            {}", cronjob.spec.suspend);
+    match state.last_schedule_time {
+        Some(time) => println!("Last Schedule Time: {}", time),
+        None => println!("Last Schedule Time: <none>"),
+    }
+    if !state.jobs.is_empty() {
+        println!("{:<32} STATUS", "JOB");
+        for job in &state.jobs {
+            let status = match jobs.condition(job) {
+                Some(ConditionType::Complete) => "Complete",
+                Some(ConditionType::Failed) => "Failed",
+                None => "Running",
+            };
+            println!("{:<32} {}", job, status);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CRONJOB: &str = "
+apiVersion: batch/v1
+kind: CronJob
+metadata:
+  name: backup
+spec:
+  schedule: '*/5 * * * *'
+  concurrencyPolicy: Forbid
+  jobTemplate:
+    spec:
+      template:
+        spec:
+          restartPolicy: OnFailure
+          containers:
+            - name: backup
+              image: backup:v1
+";
+
+    #[test]
+    fn test_concurrency_policy() {
+        let root = tempfile::tempdir().unwrap();
+        let mut controller = CronJobController::load(root.path()).unwrap();
+        let jobs = JobController::load(root.path()).unwrap();
+        let step = |controller: &mut CronJobController, contents: &str, now: u64| -> Vec<String> {
+            let cronjobs = vec![("backup".to_string(), PathBuf::from("backup.yaml"), contents.to_string())];
+            controller.reconcile(cronjobs, &jobs, now).into_iter().map(|(_, job, _)| job).collect()
+        };
+        let start = 1_710_505_800;
+        assert!(step(&mut controller, CRONJOB, start).is_empty());
+        assert_eq!(step(&mut controller, CRONJOB, start + 300), vec![format!("backup-{}", start / 60 + 5)]);
+        // the first job is still running
+        assert_eq!(step(&mut controller, CRONJOB, start + 600).len(), 1);
+
+        let replace = CRONJOB.replace("Forbid", "Replace");
+        assert_eq!(step(&mut controller, &replace, start + 900), vec![format!("backup-{}", start / 60 + 15)]);
+        let allow = CRONJOB.replace("Forbid", "Allow");
+        assert_eq!(step(&mut controller, &allow, start + 1200).len(), 2);
+        assert!(CronJob::parse(&CRONJOB.replace("*/5", "70")).is_err());
+        assert!(CronJob::parse(&CRONJOB.replace("OnFailure", "Always")).is_err());
+    }
+}
@@ -0,0 +1,490 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use crate::daemon::template::{self, PodTemplate};
+use crate::events::{self, EventType};
+use crate::node;
+use crate::rootpath;
+use crate::selector::LabelSelector;
+use crate::task::task::{ObjectMeta, RestartPolicy};
+use tracing::{error, info};
+
+// A Deployment of a manifest directory keeps `replicas` pods of its template
+// running, named <deployment>-<template hash>-<suffix>. A changed template is
+// rolled out by the strategy: RollingUpdate replaces the pods a few at a time
+// while at most maxSurge pods more than replicas exist and at most
+// maxUnavailable of them aren't running, Recreate deletes every old pod first.
+// Each template is a revision of the rollout history, kept with the pods and
+// the manifest in <root>/daemon/deployments/<name>.json so that a restarted
+// daemon carries on where it stopped. The pods themselves are run by the
+// PodManager like the static ones.
+
+pub const KIND: &str = "Deployment";
+const POD_TEMPLATE_HASH: &str = "pod-template-hash";
+const CHANGE_CAUSE: &str = "kubernetes.io/change-cause";
+
+#[derive(Debug, Deserialize)]
+pub struct Deployment {
+    #[serde(rename = "apiVersion")]
+    pub api_version: String,
+    pub kind: String,
+    pub metadata: ObjectMeta,
+    pub spec: DeploymentSpec,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeploymentSpec {
+    #[serde(default = "default_replicas")]
+    pub replicas: u32,
+    #[serde(default)]
+    pub selector: LabelSelector,
+    // kept as written, the pods are made of it
+    pub template: Value,
+    #[serde(default)]
+    pub strategy: DeploymentStrategy,
+    #[serde(rename = "revisionHistoryLimit", default = "default_revision_history_limit")]
+    pub revision_history_limit: usize,
+}
+
+fn default_replicas() -> u32 {
+    1
+}
+
+fn default_revision_history_limit() -> usize {
+    10
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct DeploymentStrategy {
+    #[serde(rename = "type", default)]
+    pub kind: StrategyType,
+    #[serde(rename = "rollingUpdate", default)]
+    pub rolling_update: RollingUpdate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum StrategyType {
+    #[default]
+    RollingUpdate,
+    Recreate,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RollingUpdate {
+    #[serde(rename = "maxSurge", default = "default_max")]
+    pub max_surge: IntOrPercent,
+    #[serde(rename = "maxUnavailable", default = "default_max")]
+    pub max_unavailable: IntOrPercent,
+}
+
+impl Default for RollingUpdate {
+    fn default() -> Self {
+        RollingUpdate { max_surge: default_max(), max_unavailable: default_max() }
+    }
+}
+
+fn default_max() -> IntOrPercent {
+    IntOrPercent::Percent("25%".to_string())
+}
+
+// a number of pods, or a percentage of the replicas such as "25%"
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum IntOrPercent {
+    Int(u32),
+    Percent(String),
+}
+
+impl IntOrPercent {
+    // the number of pods it is out of replicas, a percentage rounded up or down
+    fn scaled(&self, replicas: u32, round_up: bool) -> Result<u32> {
+        match self {
+            IntOrPercent::Int(count) => Ok(*count),
+            IntOrPercent::Percent(text) => {
+                let percent: u32 = text
+                    .strip_suffix('%')
+                    .and_then(|percent| percent.parse().ok())
+                    .ok_or_else(|| anyhow!("invalid value {:?}, expected a number or a percentage like 25%", text))?;
+                let scaled = u64::from(replicas) * u64::from(percent);
+                let scaled = if round_up { scaled.div_ceil(100) } else { scaled / 100 };
+                Ok(scaled as u32)
+            }
+        }
+    }
+}
+
+impl Deployment {
+    pub fn parse(contents: &str) -> Result<Self> {
+        let deployment: Deployment = serde_yaml::from_str(contents)?;
+        if deployment.kind != KIND || deployment.api_version != "apps/v1" {
+            return Err(anyhow!("expected apiVersion apps/v1 and kind Deployment"));
+        }
+        let template = PodTemplate::parse(&deployment.spec.template)?;
+        // the pods are replaced whenever they exit, like Kubernetes requires
+        if template.spec.restart_policy != RestartPolicy::Always {
+            return Err(anyhow!("the restartPolicy of the pod template must be Always"));
+        }
+        let unmatched = deployment.spec.selector.selector()?.unmatched(&template.metadata.labels);
+        if !unmatched.is_empty() {
+            return Err(anyhow!("selector does not match the template labels ({})", unmatched.join(", ")));
+        }
+        let rolling_update = &deployment.spec.strategy.rolling_update;
+        let max_surge = rolling_update.max_surge.scaled(100, true)?;
+        let max_unavailable = rolling_update.max_unavailable.scaled(100, false)?;
+        if deployment.spec.strategy.kind == StrategyType::RollingUpdate && max_surge == 0 && max_unavailable == 0 {
+            return Err(anyhow!("maxSurge and maxUnavailable can't both be 0"));
+        }
+        Ok(deployment)
+    }
+
+    // the revision the pods are of, a hash of the template
+    pub fn template_hash(&self) -> String {
+        template::hash(&self.spec.template)
+    }
+}
+
+// a template of the rollout history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Revision {
+    pub revision: u64,
+    pub hash: String,
+    #[serde(rename = "changeCause")]
+    pub change_cause: Option<String>,
+    // unix time the template was first rolled out
+    pub created: u64,
+    template: Value,
+}
+
+// what the controller keeps of a deployment
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct DeploymentState {
+    manifest: PathBuf,
+    contents: String,
+    // the pods by name, with the hash of the template they were made of
+    pods: BTreeMap<String, String>,
+    history: Vec<Revision>,
+}
+
+impl DeploymentState {
+    // make the template the latest revision, an earlier one comes back with a new number
+    fn record_revision(&mut self, deployment: &Deployment, hash: &str) {
+        if self.history.last().is_some_and(|revision| revision.hash == hash) {
+            return;
+        }
+        let revision = self.history.last().map_or(1, |revision| revision.revision + 1);
+        self.history.retain(|revision| revision.hash != hash);
+        self.history.push(Revision {
+            revision,
+            hash: hash.to_string(),
+            change_cause: deployment.metadata.annotations.get(CHANGE_CAUSE).cloned(),
+            created: node::unix_now(),
+            template: deployment.spec.template.clone(),
+        });
+    }
+
+    // forget the oldest revisions past the limit that no pod is of anymore
+    fn trim_history(&mut self, limit: usize) {
+        let current = self.history.len().saturating_sub(1);
+        let in_use: HashSet<&String> = self.pods.values().collect();
+        let mut unused: Vec<usize> = (0..current).filter(|&i| !in_use.contains(&self.history[i].hash)).collect();
+        unused.truncate(unused.len().saturating_sub(limit));
+        let mut index = 0;
+        self.history.retain(|_| {
+            index += 1;
+            !unused.contains(&(index - 1))
+        });
+    }
+}
+
+fn state_dir(root_path: &Path) -> PathBuf {
+    root_path.join("daemon").join("deployments")
+}
+
+fn state_path(root_path: &Path, name: &str) -> PathBuf {
+    state_dir(root_path).join(format!("{}.json", name))
+}
+
+// a pod of a deployment as the rollout sees it
+struct PodView<'a> {
+    name: &'a str,
+    current: bool,
+    available: bool,
+}
+
+// the pods to delete and the number of pods of the current template to create
+// for the pods of a deployment to move towards its spec in one step
+fn plan(replicas: u32, strategy: &DeploymentStrategy, pods: &[PodView]) -> Result<(Vec<String>, usize)> {
+    let target = replicas as usize;
+    let (mut new, mut old): (Vec<&PodView>, Vec<&PodView>) = pods.iter().partition(|pod| pod.current);
+    // the pods that aren't running go first
+    new.sort_by_key(|pod| (pod.available, pod.name));
+    old.sort_by_key(|pod| (pod.available, pod.name));
+    let mut delete: Vec<String> = Vec::new();
+    if new.len() > target {
+        delete.extend(new.drain(..new.len() - target).map(|pod| pod.name.to_string()));
+    }
+
+    if strategy.kind == StrategyType::Recreate {
+        if !old.is_empty() {
+            delete.extend(old.iter().map(|pod| pod.name.to_string()));
+            return Ok((delete, 0));
+        }
+        return Ok((delete, target - new.len()));
+    }
+
+    let max_surge = strategy.rolling_update.max_surge.scaled(replicas, true)? as usize;
+    let max_unavailable = strategy.rolling_update.max_unavailable.scaled(replicas, false)? as usize;
+    let total = new.len() + old.len();
+    let create = (target + max_surge).saturating_sub(total).min(target - new.len());
+
+    // like the Kubernetes deployment controller: old pods that aren't
+    // running are deleted first, the running ones as long as enough pods stay
+    let min_available = target.saturating_sub(max_unavailable);
+    let new_unavailable = new.iter().filter(|pod| !pod.available).count();
+    let available = pods.iter().filter(|pod| pod.available && !delete.iter().any(|name| name == pod.name)).count();
+    let mut budget = total.saturating_sub(min_available + new_unavailable);
+    let mut removable = available.saturating_sub(min_available);
+    for pod in old {
+        if budget == 0 || pod.available && removable == 0 {
+            break;
+        }
+        if pod.available {
+            removable -= 1;
+        }
+        budget -= 1;
+        delete.push(pod.name.to_string());
+    }
+    Ok((delete, create))
+}
+
+// the rollout state of every deployment of the manifest directories
+pub struct DeploymentController {
+    root_path: PathBuf,
+    states: HashMap<String, DeploymentState>,
+}
+
+impl DeploymentController {
+    pub fn load(root_path: &Path) -> Result<Self> {
+        let mut states = HashMap::new();
+        let dir = state_dir(root_path);
+        if dir.exists() {
+            for entry in fs::read_dir(&dir)? {
+                let path = entry?.path();
+                let Some(name) = path.file_stem().filter(|_| path.extension().is_some_and(|ext| ext == "json")) else {
+                    continue;
+                };
+                let state: DeploymentState = serde_json::from_str(&fs::read_to_string(&path)?)
+                    .map_err(|e| anyhow!("invalid deployment state {}: {}", path.display(), e))?;
+                states.insert(name.to_string_lossy().to_string(), state);
+            }
+        }
+        Ok(DeploymentController { root_path: root_path.to_path_buf(), states })
+    }
+
+    // the manifest every deployment was last reconciled from, by name
+    pub fn applied(&self) -> impl Iterator<Item = (&String, &Path, &String)> {
+        self.states.iter().map(|(name, state)| (name, state.manifest.as_path(), &state.contents))
+    }
+
+    // whether the pod belongs to a deployment
+    pub fn owns(&self, pod_name: &str) -> bool {
+        self.states.values().any(|state| state.pods.contains_key(pod_name))
+    }
+
+    // take a step of the rollout of every deployment, given as name, manifest
+    // and contents, and return the manifests of the pods they should have
+    // right now as manifest of their deployment, pod name and pod manifest
+    pub fn reconcile(
+        &mut self,
+        deployments: Vec<(String, PathBuf, String)>,
+        available: &HashSet<String>,
+    ) -> Vec<(PathBuf, String, String)> {
+        let names: HashSet<&String> = deployments.iter().map(|(name, _, _)| name).collect();
+        let removed: Vec<String> = self.states.keys().filter(|name| !names.contains(name)).cloned().collect();
+        for name in removed {
+            info!("Manifest of Deployment {} removed, deleting its pods", name);
+            self.states.remove(&name);
+            let _ = fs::remove_file(state_path(&self.root_path, &name));
+        }
+
+        let mut pods = Vec::new();
+        for (name, manifest, contents) in deployments {
+            match self.reconcile_deployment(&name, manifest.clone(), contents, available) {
+                Ok(manifests) => pods.extend(manifests.into_iter().map(|(pod, contents)| (manifest.clone(), pod, contents))),
+                Err(e) => error!("Failed to reconcile Deployment {}: {}", name, e),
+            }
+        }
+        pods
+    }
+
+    fn reconcile_deployment(
+        &mut self,
+        name: &str,
+        manifest: PathBuf,
+        contents: String,
+        available: &HashSet<String>,
+    ) -> Result<Vec<(String, String)>> {
+        let deployment = Deployment::parse(&contents)?;
+        let hash = deployment.template_hash();
+        let state = self.states.entry(name.to_string()).or_default();
+        let before = serde_json::to_string(state)?;
+        state.manifest = manifest;
+        state.contents = contents;
+        state.record_revision(&deployment, &hash);
+
+        let views: Vec<PodView> = state.pods
+            .iter()
+            .map(|(pod, pod_hash)| PodView { name: pod, current: *pod_hash == hash, available: available.contains(pod) })
+            .collect();
+        let (delete, create) = plan(deployment.spec.replicas, &deployment.spec.strategy, &views)?;
+        let revision_of = |state: &DeploymentState, pod_hash: &str| {
+            state.history.iter().find(|revision| revision.hash == pod_hash).map_or(0, |revision| revision.revision)
+        };
+        for pod in delete {
+            if let Some(pod_hash) = state.pods.remove(&pod) {
+                let message = format!("Deleted Pod {} of revision {}", pod, revision_of(state, &pod_hash));
+                let _ = events::record(&self.root_path, name, EventType::Normal, events::SCALING_REPLICA_SET, &message);
+            }
+        }
+        for _ in 0..create {
+            let pod = template::pod_name(&format!("{}-{}", name, hash));
+            let message = format!("Created Pod {} of revision {}", pod, revision_of(state, &hash));
+            let _ = events::record(&self.root_path, name, EventType::Normal, events::SCALING_REPLICA_SET, &message);
+            state.pods.insert(pod, hash.clone());
+        }
+        state.trim_history(deployment.spec.revision_history_limit);
+
+        let mut manifests = Vec::new();
+        for (pod, pod_hash) in &state.pods {
+            let template = state.history
+                .iter()
+                .find(|revision| revision.hash == *pod_hash)
+                .map(|revision| &revision.template)
+                .ok_or_else(|| anyhow!("revision {} of Pod {} is not in the history", pod_hash, pod))?;
+            let labels = [(POD_TEMPLATE_HASH, pod_hash.as_str())];
+            manifests.push((pod.clone(), template::pod_manifest(template, &deployment.metadata.namespace, pod, &labels)?));
+        }
+
+        let after = serde_json::to_string(state)?;
+        if after != before {
+            let path = state_path(&self.root_path, name);
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(&path, after)?;
+        }
+        Ok(manifests)
+    }
+}
+
+// print the rollout history of a deployment of the local daemon
+pub fn history(name: &str) -> Result<()> {
+    let root_path = rootpath::determine(None)?;
+    let path = state_path(&root_path, name);
+    let contents = fs::read_to_string(&path).map_err(|_| anyhow!("Deployment {} not found", name))?;
+    let state: DeploymentState = serde_json::from_str(&contents)?;
+    println!("deployment/{}", name);
+    println!("{:<10} {:<12} {:<6} CHANGE-CAUSE", "REVISION", "HASH", "PODS");
+    for revision in &state.history {
+        let pods = state.pods.values().filter(|hash| **hash == revision.hash).count();
+        let change_cause = revision.change_cause.as_deref().unwrap_or("<none>");
+        println!("{:<10} {:<12} {:<6} {}", revision.revision, revision.hash, pods, change_cause);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEPLOYMENT: &str = "
+apiVersion: apps/v1
+kind: Deployment
+metadata:
+  name: web
+spec:
+  replicas: 3
+  selector:
+    matchLabels:
+      app: web
+  strategy:
+    rollingUpdate:
+      maxSurge: 1
+      maxUnavailable: 0
+  template:
+    metadata:
+      labels:
+        app: web
+    spec:
+      containers:
+        - name: app
+          image: app:v1
+";
+
+    // run the rollout until it settles, creating every pod available
+    fn roll_out(controller: &mut DeploymentController, contents: &str) -> Vec<(String, String)> {
+        let mut available = HashSet::new();
+        let mut steps = 0;
+        loop {
+            let pods = controller.reconcile(vec![("web".to_string(), PathBuf::from("web.yaml"), contents.to_string())], &available);
+            let names: HashSet<String> = pods.iter().map(|(_, pod, _)| pod.clone()).collect();
+            // never more than replicas + maxSurge pods, never less than replicas running
+            assert!(names.len() <= 4);
+            assert!(steps == 0 || names.intersection(&available).count() >= 3);
+            if names == available {
+                return pods.into_iter().map(|(_, pod, contents)| (pod, contents)).collect();
+            }
+            available = names;
+            steps += 1;
+            assert!(steps < 20, "the rollout doesn't settle");
+        }
+    }
+
+    #[test]
+    fn test_rolling_update() {
+        let root = tempfile::tempdir().unwrap();
+        let mut controller = DeploymentController::load(root.path()).unwrap();
+        let pods = roll_out(&mut controller, DEPLOYMENT);
+        assert_eq!(pods.len(), 3);
+        let (pod, contents) = &pods[0];
+        let manifest: serde_yaml::Value = serde_yaml::from_str(contents).unwrap();
+        assert_eq!(manifest["metadata"]["name"].as_str(), Some(pod.as_str()));
+        assert_eq!(manifest["metadata"]["labels"]["app"].as_str(), Some("web"));
+        let suffix = pod.rsplit('-').next().unwrap();
+        assert_eq!(manifest["spec"]["containers"][0]["name"].as_str(), Some(format!("app-{}", suffix).as_str()));
+
+        let updated = DEPLOYMENT.replace("app:v1", "app:v2");
+        let pods = roll_out(&mut controller, &updated);
+        assert!(pods.iter().all(|(_, contents)| contents.contains("app:v2")));
+        let state = &controller.states["web"];
+        let revisions: Vec<u64> = state.history.iter().map(|revision| revision.revision).collect();
+        assert_eq!(revisions, vec![1, 2]);
+
+        // a restarted daemon picks up the rollout
+        let controller = DeploymentController::load(root.path()).unwrap();
+        assert!(controller.owns(&pods[0].0));
+    }
+
+    #[test]
+    fn test_plan() {
+        let pod = |name: &'static str, current: bool, available: bool| PodView { name, current, available };
+        let strategy: DeploymentStrategy = serde_yaml::from_str("rollingUpdate: {maxSurge: 0, maxUnavailable: 1}").unwrap();
+        let pods = [pod("a", false, true), pod("b", false, true), pod("c", false, false)];
+        // the broken pod goes first, one running pod may be unavailable
+        assert_eq!(plan(3, &strategy, &pods).unwrap(), (vec!["c".to_string()], 0));
+        assert_eq!(plan(3, &strategy, &pods[..2]).unwrap(), (vec![], 1));
+
+        let recreate: DeploymentStrategy = serde_yaml::from_str("type: Recreate").unwrap();
+        assert_eq!(plan(2, &recreate, &pods).unwrap().1, 0);
+        assert_eq!(plan(2, &recreate, &[pod("a", true, true)]).unwrap(), (vec![], 1));
+        assert_eq!(IntOrPercent::Percent("25%".to_string()).scaled(3, true).unwrap(), 1);
+        assert_eq!(IntOrPercent::Percent("25%".to_string()).scaled(3, false).unwrap(), 0);
+
+        let invalid = DEPLOYMENT.replace("maxSurge: 1", "maxSurge: 0%");
+        assert!(Deployment::parse(&invalid).is_err());
+        assert!(Deployment::parse(&DEPLOYMENT.replace("app: web\n  strategy", "app: db\n  strategy")).is_err());
+    }
+}
@@ -0,0 +1,343 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::mpsc::{self, TrySendError};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
+use anyhow::{Result, anyhow};
+use crate::daemon::endpoints::PodView;
+use crate::daemon::service::ServiceState;
+use crate::task::dns as resolv;
+use tracing::warn;
+
+// The DNS server of the pods, listening on UDP port 53 of --cluster-dns, which
+// the sandboxes of ClusterFirst pods get as nameserver (see task/dns.rs). It
+// answers A queries of <service>.<namespace>.svc.<domain> with the cluster IP
+// of the service and of <pod>.<namespace>.pod.<domain> with the IP of the pod,
+// the names are updated by every sync. Names of the cluster domain that aren't
+// known are NXDOMAIN, the others are forwarded to the nameservers of the node
+// by FORWARD_WORKERS threads; queries that find FORWARD_QUEUE others waiting
+// are dropped, the resolvers of the pods ask again. Packets that are responses
+// themselves, or too short to be a query, are dropped so that two servers
+// can't be made to answer each other forever.
+
+const TYPE_A: u16 = 1;
+const CLASS_IN: u16 = 1;
+// kept short, services and pods come and go with every sync
+const TTL: u32 = 5;
+const FORWARD_TIMEOUT: Duration = Duration::from_secs(2);
+const FORWARD_WORKERS: usize = 16;
+const FORWARD_QUEUE: usize = 256;
+
+const FLAG_RESPONSE: u16 = 0x8000;
+const FLAG_AUTHORITATIVE: u16 = 0x0400;
+const FLAG_RECURSION_DESIRED: u16 = 0x0100;
+const FLAG_RECURSION_AVAILABLE: u16 = 0x0080;
+const RCODE_FORMAT_ERROR: u16 = 1;
+const RCODE_SERVER_FAILURE: u16 = 2;
+const RCODE_NAME_ERROR: u16 = 3;
+const RCODE_NOT_IMPLEMENTED: u16 = 4;
+
+// the names of the services and pods, without the cluster domain
+#[derive(Default)]
+pub struct Records {
+    names: RwLock<HashMap<String, Ipv4Addr>>,
+}
+
+impl Records {
+    pub fn update<'a>(&self, services: impl Iterator<Item = &'a ServiceState>, pods: &[PodView]) {
+        let mut names = HashMap::new();
+        for service in services {
+            names.insert(format!("{}.{}.svc", service.name, service.namespace), service.cluster_ip);
+        }
+        for pod in pods {
+            if let Some(ip) = pod.ip {
+                names.insert(format!("{}.{}.pod", pod.name, pod.namespace), ip);
+            }
+        }
+        *self.names.write().unwrap() = names;
+    }
+}
+
+enum Lookup {
+    Found(Ipv4Addr),
+    NotFound,
+    // not a name of the cluster
+    Forward,
+}
+
+fn lookup(records: &Records, domain: &str, name: &str) -> Lookup {
+    let name = name.trim_end_matches('.').to_ascii_lowercase();
+    let short = match name.strip_suffix(domain) {
+        Some("") => return Lookup::NotFound,
+        Some(rest) => match rest.strip_suffix('.') {
+            Some(short) => short.to_string(),
+            None => return Lookup::Forward,
+        },
+        // <service>.<namespace>.svc also works without the domain
+        None if name.ends_with(".svc") || name.ends_with(".pod") => name,
+        None => return Lookup::Forward,
+    };
+    match records.names.read().unwrap().get(&short) {
+        Some(ip) => Lookup::Found(*ip),
+        None => Lookup::NotFound,
+    }
+}
+
+struct Question {
+    id: u16,
+    flags: u16,
+    name: String,
+    qtype: u16,
+    qclass: u16,
+    // where the question ends in the query
+    end: usize,
+}
+
+fn parse_question(query: &[u8]) -> Result<Question> {
+    let field = |at: usize| -> Result<u16> {
+        query.get(at..at + 2).map(|b| u16::from_be_bytes([b[0], b[1]])).ok_or_else(|| anyhow!("truncated query"))
+    };
+    let (id, flags, count) = (field(0)?, field(2)?, field(4)?);
+    if count != 1 {
+        return Err(anyhow!("expected one question, got {}", count));
+    }
+    let mut labels = Vec::new();
+    let mut at = 12;
+    loop {
+        let len = *query.get(at).ok_or_else(|| anyhow!("truncated query"))? as usize;
+        at += 1;
+        if len == 0 {
+            break;
+        }
+        // questions are never compressed
+        if len > 63 {
+            return Err(anyhow!("invalid label length {}", len));
+        }
+        let label = query.get(at..at + len).ok_or_else(|| anyhow!("truncated query"))?;
+        labels.push(String::from_utf8_lossy(label).into_owned());
+        at += len;
+    }
+    Ok(Question { id, flags, name: labels.join("."), qtype: field(at)?, qclass: field(at + 2)?, end: at + 4 })
+}
+
+fn response(query: &[u8], question: &Question, rcode: u16, answer: Option<Ipv4Addr>) -> Vec<u8> {
+    let flags = FLAG_RESPONSE
+        | FLAG_AUTHORITATIVE
+        | (question.flags & FLAG_RECURSION_DESIRED)
+        | FLAG_RECURSION_AVAILABLE
+        | rcode;
+    let mut packet = Vec::with_capacity(question.end + 16);
+    for field in [question.id, flags, 1, answer.is_some() as u16, 0, 0] {
+        packet.extend_from_slice(&field.to_be_bytes());
+    }
+    packet.extend_from_slice(&query[12..question.end]);
+    if let Some(ip) = answer {
+        // the name is the one of the question, at offset 12
+        packet.extend_from_slice(&0xc00cu16.to_be_bytes());
+        packet.extend_from_slice(&TYPE_A.to_be_bytes());
+        packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+        packet.extend_from_slice(&TTL.to_be_bytes());
+        packet.extend_from_slice(&4u16.to_be_bytes());
+        packet.extend_from_slice(&ip.octets());
+    }
+    packet
+}
+
+// an error response to a query that can't be parsed, if it has a header at all
+fn error_response(query: &[u8], rcode: u16) -> Option<Vec<u8>> {
+    let header = query.get(..12)?;
+    let mut packet = header.to_vec();
+    let flags = FLAG_RESPONSE | (u16::from_be_bytes([header[2], header[3]]) & FLAG_RECURSION_DESIRED) | rcode;
+    packet[2..4].copy_from_slice(&flags.to_be_bytes());
+    // no question, answer, authority or additional records
+    packet[4..12].fill(0);
+    Some(packet)
+}
+
+#[derive(Debug, PartialEq)]
+enum Reply {
+    Answer(Vec<u8>),
+    Forward,
+    // not a query, nothing is sent back
+    Drop,
+}
+
+// what to do with a packet received
+fn answer(records: &Records, domain: &str, query: &[u8]) -> Reply {
+    let Some(header) = query.get(..12) else {
+        return Reply::Drop;
+    };
+    if u16::from_be_bytes([header[2], header[3]]) & FLAG_RESPONSE != 0 {
+        return Reply::Drop;
+    }
+    let error = |rcode| error_response(query, rcode).map_or(Reply::Drop, Reply::Answer);
+    let question = match parse_question(query) {
+        Ok(question) => question,
+        Err(_) => return error(RCODE_FORMAT_ERROR),
+    };
+    // only standard queries
+    if (question.flags >> 11) & 0xf != 0 {
+        return error(RCODE_NOT_IMPLEMENTED);
+    }
+    match lookup(records, domain, &question.name) {
+        Lookup::Forward => Reply::Forward,
+        Lookup::NotFound => Reply::Answer(response(query, &question, RCODE_NAME_ERROR, None)),
+        // the name exists, but only with an A record
+        Lookup::Found(_) if question.qtype != TYPE_A || question.qclass != CLASS_IN => {
+            Reply::Answer(response(query, &question, 0, None))
+        }
+        Lookup::Found(ip) => Reply::Answer(response(query, &question, 0, Some(ip))),
+    }
+}
+
+// the response of the first nameserver of the node that answers
+fn forward(query: &[u8], upstreams: &[SocketAddr]) -> Result<Vec<u8>> {
+    let mut last_error = anyhow!("the node has no nameservers");
+    for upstream in upstreams {
+        let bind: SocketAddr = if upstream.is_ipv4() { ([0, 0, 0, 0], 0).into() } else { ([0u16; 8], 0).into() };
+        let result = UdpSocket::bind(bind).and_then(|socket| {
+            socket.set_read_timeout(Some(FORWARD_TIMEOUT))?;
+            socket.send_to(query, upstream)?;
+            let mut buf = [0u8; 4096];
+            let (len, _) = socket.recv_from(&mut buf)?;
+            Ok(buf[..len].to_vec())
+        });
+        match result {
+            Ok(reply) => return Ok(reply),
+            Err(e) => last_error = anyhow!("{}: {}", upstream, e),
+        }
+    }
+    Err(last_error)
+}
+
+pub fn serve(address: Ipv4Addr, domain: &str, records: Arc<Records>) -> Result<()> {
+    let socket = UdpSocket::bind((address, 53)).map_err(|e| anyhow!("Failed to listen on {}:53: {}", address, e))?;
+    // the cluster DNS itself may be in the node's resolv.conf
+    let upstreams: Vec<SocketAddr> = resolv::host_dns_config()?
+        .servers
+        .iter()
+        .filter_map(|server| server.parse::<IpAddr>().ok())
+        .filter(|ip| *ip != IpAddr::V4(address))
+        .map(|ip| SocketAddr::new(ip, 53))
+        .collect();
+    let upstreams = Arc::new(upstreams);
+    let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+
+    let (queue, queued) = mpsc::sync_channel::<(Vec<u8>, SocketAddr)>(FORWARD_QUEUE);
+    let queued = Arc::new(Mutex::new(queued));
+    for _ in 0..FORWARD_WORKERS {
+        let (socket, upstreams, queued) = (socket.try_clone()?, upstreams.clone(), queued.clone());
+        thread::spawn(move || {
+            loop {
+                // the lock is only held while waiting, not while forwarding
+                let next = queued.lock().unwrap_or_else(|e| e.into_inner()).recv();
+                let Ok((query, client)) = next else {
+                    return;
+                };
+                let reply = forward(&query, &upstreams).unwrap_or_else(|e| {
+                    warn!("Failed to forward a DNS query: {}", e);
+                    error_response(&query, RCODE_SERVER_FAILURE).unwrap_or_default()
+                });
+                let _ = socket.send_to(&reply, client);
+            }
+        });
+    }
+
+    thread::spawn(move || {
+        let mut buf = [0u8; 512];
+        let mut dropping = false;
+        loop {
+            let (len, client) = match socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(e) => {
+                    warn!("Failed to receive a DNS query: {}", e);
+                    continue;
+                }
+            };
+            let query = buf[..len].to_vec();
+            match answer(&records, &domain, &query) {
+                Reply::Answer(reply) => {
+                    let _ = socket.send_to(&reply, client);
+                }
+                Reply::Drop => {}
+                Reply::Forward => match queue.try_send((query, client)) {
+                    Ok(()) => dropping = false,
+                    // only the first of a burst is worth a warning
+                    Err(TrySendError::Full(_)) if !dropping => {
+                        warn!("Dropping DNS queries, {} are waiting to be forwarded", FORWARD_QUEUE);
+                        dropping = true;
+                    }
+                    Err(_) => {}
+                },
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{BTreeMap, HashMap};
+
+    fn query(name: &str, qtype: u16) -> Vec<u8> {
+        let mut packet = vec![0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        for label in name.split('.') {
+            packet.push(label.len() as u8);
+            packet.extend_from_slice(label.as_bytes());
+        }
+        packet.push(0);
+        packet.extend_from_slice(&qtype.to_be_bytes());
+        packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+        packet
+    }
+
+    #[test]
+    fn test_answer() {
+        let records = Records::default();
+        let service = ServiceState {
+            name: "web".to_string(),
+            namespace: "default".to_string(),
+            cluster_ip: Ipv4Addr::new(10, 96, 0, 2),
+            ports: Vec::new(),
+            selector: BTreeMap::new(),
+        };
+        let pod = PodView {
+            name: "web-1".to_string(),
+            namespace: "default".to_string(),
+            labels: HashMap::new(),
+            ip: Some(Ipv4Addr::new(10, 88, 0, 2)),
+            ready: false,
+        };
+        records.update([&service].into_iter(), &[pod]);
+
+        let domain = "cluster.local";
+        let reply = |query: &[u8]| match answer(&records, domain, query) {
+            Reply::Answer(reply) => reply,
+            other => panic!("expected an answer, got {:?}", other),
+        };
+        let web = reply(&query("web.default.svc.cluster.local", TYPE_A));
+        assert_eq!(&web[..4], &[0x12, 0x34, 0x85, 0x80]);
+        assert_eq!(&web[6..8], &[0, 1]);
+        assert_eq!(&web[web.len() - 4..], &[10, 96, 0, 2]);
+        let pod = reply(&query("Web-1.default.pod", TYPE_A));
+        assert_eq!(&pod[pod.len() - 4..], &[10, 88, 0, 2]);
+
+        // an AAAA query of a known name has no answers, an unknown name doesn't exist
+        let aaaa = reply(&query("web.default.svc", 28));
+        assert_eq!((aaaa[3] & 0xf, &aaaa[6..8]), (0, &[0u8, 0][..]));
+        let unknown = reply(&query("api.default.svc.cluster.local", TYPE_A));
+        assert_eq!(unknown[3] & 0xf, RCODE_NAME_ERROR as u8);
+        assert_eq!(answer(&records, domain, &query("example.com", TYPE_A)), Reply::Forward);
+        assert_eq!(answer(&records, domain, &query("notcluster.local", TYPE_A)), Reply::Forward);
+        assert_eq!(answer(&records, domain, &[0x12, 0x34, 0x01, 0x00, 0, 1]), Reply::Drop);
+
+        // responses are never answered, not even our own ones sent back to us
+        assert_eq!(answer(&records, domain, &web), Reply::Drop);
+        let mut malformed = query("example.com", TYPE_A);
+        malformed[2] |= 0x80;
+        malformed.truncate(14);
+        assert_eq!(answer(&records, domain, &malformed), Reply::Drop);
+    }
+}
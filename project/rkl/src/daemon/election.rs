@@ -0,0 +1,107 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use anyhow::{Result, anyhow};
+use store::lease::{Elector, Leadership};
+use crate::daemon::SyncTrigger;
+use tracing::{info, warn};
+
+// Leader election of the workload controllers, for nodes that all have the
+// same Deployment, Job and CronJob manifests: with --leader-elect-store every
+// daemon is a candidate for the lease --leader-elect-lease of the cluster
+// state store and only the holder runs the controllers. The other nodes keep
+// the workloads applied without running pods of them, so that exactly one node
+// does. A thread renews the lease every third of --leader-elect-lease-duration.
+// A leader that can't renew stops its controllers, deleting their pods, once
+// its leadership is over, which is before any other node can take the lease
+// (see the store's lease.rs). Pods, config maps, secrets and services run on
+// every node as usual.
+
+pub const DEFAULT_LEASE: &str = "rkl-controllers";
+pub const DEFAULT_LEASE_DURATION: &str = "15s";
+
+#[derive(Debug, Clone)]
+pub struct ElectionConfig {
+    // url of the store, see store::open
+    pub store: String,
+    pub lease: String,
+    pub lease_duration: Duration,
+}
+
+// whether this node runs the controllers, shared with the sync loop
+#[derive(Debug, Default)]
+pub struct LeaderState {
+    leadership: Mutex<Option<Leadership>>,
+}
+
+impl LeaderState {
+    pub fn leading(&self, now: Instant) -> bool {
+        self.leadership.lock().unwrap().is_some_and(|leadership| leadership.valid(now))
+    }
+
+    // the fencing token of the current leadership
+    pub fn token(&self, now: Instant) -> Option<u64> {
+        self.leadership.lock().unwrap().filter(|leadership| leadership.valid(now)).map(|leadership| leadership.token)
+    }
+
+    // a failed round keeps the leadership until its deadline
+    fn update(&self, round: Result<Option<Leadership>>) {
+        let mut leadership = self.leadership.lock().unwrap();
+        match round {
+            Ok(next) => *leadership = next,
+            Err(e) => warn!("Failed to renew the lease of the controllers: {}", e),
+        }
+    }
+}
+
+// run for the lease as identity, syncing right away whenever this node starts
+// or stops leading
+pub fn start(config: ElectionConfig, identity: &str, trigger: Arc<SyncTrigger>) -> Result<Arc<LeaderState>> {
+    if config.lease_duration < Duration::from_secs(3) {
+        return Err(anyhow!("--leader-elect-lease-duration must be at least 3s"));
+    }
+    let store = store::open(&config.store)?;
+    let state = Arc::new(LeaderState::default());
+    let mut elector = Elector::new(&config.lease, identity, config.lease_duration);
+    let retry = config.lease_duration / 3;
+    let shared = state.clone();
+    thread::spawn(move || {
+        let mut leading = false;
+        loop {
+            let now = Instant::now();
+            shared.update(elector.try_acquire_or_renew(store.as_ref(), now));
+            let token = shared.token(Instant::now());
+            if token.is_some() != leading {
+                leading = token.is_some();
+                match token {
+                    Some(token) => info!("Acquired the lease {} (token {}), running the controllers", config.lease, token),
+                    None => info!("Lost the lease {}, stopping the controllers", config.lease),
+                }
+                trigger.notify();
+            }
+            thread::sleep(retry);
+        }
+    });
+    Ok(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_leader_state() {
+        let state = LeaderState::default();
+        let now = Instant::now();
+        assert!(!state.leading(now));
+        state.update(Ok(Some(Leadership { token: 3, deadline: now + Duration::from_secs(15) })));
+        assert!(state.leading(now));
+        assert_eq!(state.token(now), Some(3));
+        // a failed renewal leads until the deadline
+        state.update(Err(anyhow!("store unreachable")));
+        assert!(state.leading(now + Duration::from_secs(10)));
+        assert!(!state.leading(now + Duration::from_secs(15)));
+        state.update(Ok(None));
+        assert_eq!(state.token(now), None);
+    }
+}
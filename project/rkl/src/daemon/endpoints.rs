@@ -0,0 +1,180 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use crate::daemon::service::ServiceState;
+use tracing::warn;
+
+// The endpoints controller keeps, for every service, the pods it selects with
+// their IPs: the ready ones as addresses, the others as notReadyAddresses.
+// A pod is ready when all of its containers run and passed their readiness
+// probes (see task/probe.rs). The lists are kept in
+// <root>/daemon/endpoints/<service>.json, the proxy only routes to the
+// addresses.
+
+// a pod a service can route to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Endpoint {
+    pub pod: String,
+    pub ip: Ipv4Addr,
+}
+
+// simulate Kubernetes Endpoints, of one service
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Endpoints {
+    pub service: String,
+    #[serde(default)]
+    pub addresses: Vec<Endpoint>,
+    #[serde(rename = "notReadyAddresses", default)]
+    pub not_ready_addresses: Vec<Endpoint>,
+}
+
+// a pod of the node as the endpoints controller sees it
+pub struct PodView {
+    pub name: String,
+    pub namespace: String,
+    pub labels: HashMap<String, String>,
+    pub ip: Option<Ipv4Addr>,
+    pub ready: bool,
+}
+
+fn state_dir(root_path: &Path) -> PathBuf {
+    root_path.join("daemon").join("endpoints")
+}
+
+fn state_path(root_path: &Path, service: &str) -> PathBuf {
+    state_dir(root_path).join(format!("{}.json", service))
+}
+
+pub fn load_all(root_path: &Path) -> Result<BTreeMap<String, Endpoints>> {
+    let mut all = BTreeMap::new();
+    let dir = state_dir(root_path);
+    if !dir.exists() {
+        return Ok(all);
+    }
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+        let endpoints: Endpoints = serde_json::from_str(&fs::read_to_string(&path)?)
+            .map_err(|e| anyhow!("invalid endpoints {}: {}", path.display(), e))?;
+        all.insert(endpoints.service.clone(), endpoints);
+    }
+    Ok(all)
+}
+
+pub struct EndpointsController {
+    root_path: PathBuf,
+    endpoints: BTreeMap<String, Endpoints>,
+}
+
+impl EndpointsController {
+    pub fn load(root_path: &Path) -> Result<Self> {
+        Ok(EndpointsController { root_path: root_path.to_path_buf(), endpoints: load_all(root_path)? })
+    }
+
+    // the ready endpoints of a service
+    pub fn addresses(&self, service: &str) -> &[Endpoint] {
+        self.endpoints.get(service).map_or(&[], |endpoints| endpoints.addresses.as_slice())
+    }
+
+    // bring the endpoints of every service up to date with the pods,
+    // returning whether the ready ones of any service changed
+    pub fn reconcile(&mut self, services: &[&ServiceState], pods: &[PodView]) -> bool {
+        let names: HashSet<&String> = services.iter().map(|service| &service.name).collect();
+        let removed: Vec<String> = self.endpoints.keys().filter(|name| !names.contains(name)).cloned().collect();
+        let mut changed = !removed.is_empty();
+        for name in removed {
+            self.endpoints.remove(&name);
+            let _ = fs::remove_file(state_path(&self.root_path, &name));
+        }
+
+        for service in services {
+            let mut endpoints = Endpoints { service: service.name.clone(), ..Default::default() };
+            for pod in pods.iter().filter(|pod| service.selects(&pod.namespace, &pod.labels)) {
+                // a pod without IP can't be routed to, ready or not
+                let Some(ip) = pod.ip else {
+                    continue;
+                };
+                let endpoint = Endpoint { pod: pod.name.clone(), ip };
+                if pod.ready {
+                    endpoints.addresses.push(endpoint);
+                } else {
+                    endpoints.not_ready_addresses.push(endpoint);
+                }
+            }
+            endpoints.addresses.sort_by(|a, b| a.pod.cmp(&b.pod));
+            endpoints.not_ready_addresses.sort_by(|a, b| a.pod.cmp(&b.pod));
+            let previous = self.endpoints.get(&service.name);
+            if previous == Some(&endpoints) {
+                continue;
+            }
+            changed |= previous.is_none_or(|previous| previous.addresses != endpoints.addresses);
+            if let Err(e) = self.save(&endpoints) {
+                warn!("Failed to record the endpoints of Service {}: {}", service.name, e);
+            }
+            self.endpoints.insert(service.name.clone(), endpoints);
+        }
+        changed
+    }
+
+    fn save(&self, endpoints: &Endpoints) -> Result<()> {
+        fs::create_dir_all(state_dir(&self.root_path))?;
+        fs::write(state_path(&self.root_path, &endpoints.service), serde_json::to_string_pretty(endpoints)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reconcile() {
+        let root = tempfile::tempdir().unwrap();
+        let service = ServiceState {
+            name: "web".to_string(),
+            namespace: "default".to_string(),
+            cluster_ip: Ipv4Addr::new(10, 96, 0, 2),
+            ports: Vec::new(),
+            selector: BTreeMap::from([("app".to_string(), "web".to_string())]),
+        };
+        let pod = |name: &str, app: &str, ip: Option<Ipv4Addr>, ready: bool| PodView {
+            name: name.to_string(),
+            namespace: "default".to_string(),
+            labels: HashMap::from([("app".to_string(), app.to_string())]),
+            ip,
+            ready,
+        };
+        let mut pods = vec![
+            pod("web-2", "web", Some(Ipv4Addr::new(10, 88, 0, 3)), true),
+            pod("web-1", "web", Some(Ipv4Addr::new(10, 88, 0, 2)), true),
+            pod("web-3", "web", None, true),
+            pod("web-4", "web", Some(Ipv4Addr::new(10, 88, 0, 5)), false),
+            pod("db", "db", Some(Ipv4Addr::new(10, 88, 0, 4)), true),
+        ];
+        let mut controller = EndpointsController::load(root.path()).unwrap();
+        assert!(controller.reconcile(&[&service], &pods));
+        let pods_of = |endpoints: &[Endpoint]| endpoints.iter().map(|e| e.pod.clone()).collect::<Vec<_>>();
+        assert_eq!(pods_of(controller.addresses("web")), vec!["web-1", "web-2"]);
+        assert_eq!(pods_of(&controller.endpoints["web"].not_ready_addresses), vec!["web-4"]);
+
+        // a pod becoming ready changes what is routed, a pod becoming unknown to the
+        // service while not ready doesn't
+        pods[3].ready = true;
+        assert!(controller.reconcile(&[&service], &pods));
+        pods.remove(3);
+        assert!(controller.reconcile(&[&service], &pods));
+        pods.push(pod("web-5", "web", Some(Ipv4Addr::new(10, 88, 0, 6)), false));
+        assert!(!controller.reconcile(&[&service], &pods));
+
+        // kept across restarts, deleted with the service
+        let mut controller = EndpointsController::load(root.path()).unwrap();
+        assert_eq!(pods_of(&controller.endpoints["web"].not_ready_addresses), vec!["web-5"]);
+        assert!(controller.reconcile(&[], &pods));
+        assert!(!state_path(root.path(), "web").exists());
+    }
+}
@@ -0,0 +1,200 @@
+use std::cmp::Reverse;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use anyhow::{Result, anyhow};
+use nix::sys::statvfs::statvfs;
+use crate::quantity::Quantity;
+use crate::task::qos::QosClass;
+
+// The eviction manager of the daemon, like the kubelet's hard eviction: after
+// every sync the available memory of the node (MemAvailable) and disk (the
+// filesystem of the rkl root, where the rootfs of the containers are) are
+// compared with --eviction-hard, e.g. "memory.available<100Mi,nodefs.available<10%".
+// Under pressure one pod is evicted per sync: BestEffort pods before Burstable
+// ones before Guaranteed ones and within a class the one using the most over
+// its requests. An evicted pod is deleted, with an Evicted event and its status
+// saying why, and is only run again once the pressure is gone: while the node
+// is under memory pressure BestEffort pods are rejected, under disk pressure
+// every pod is.
+
+pub const DEFAULT_EVICTION_HARD: &str = "memory.available<100Mi,nodefs.available<10%";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Threshold {
+    Bytes(u64),
+    // of the capacity
+    Percent(f64),
+}
+
+impl Threshold {
+    fn parse(text: &str) -> Result<Self> {
+        match text.strip_suffix('%') {
+            Some(percent) => {
+                let percent: f64 = percent.parse().map_err(|_| anyhow!("invalid percentage {}", text))?;
+                if !(0.0..=100.0).contains(&percent) {
+                    return Err(anyhow!("percentage {} out of range", text));
+                }
+                Ok(Threshold::Percent(percent))
+            }
+            None => {
+                let bytes = Quantity::parse(text)?.value();
+                u64::try_from(bytes).map(Threshold::Bytes).map_err(|_| anyhow!("negative quantity {}", text))
+            }
+        }
+    }
+
+    fn crossed(&self, available: u64, capacity: u64) -> bool {
+        match self {
+            Threshold::Bytes(bytes) => available < *bytes,
+            Threshold::Percent(percent) => (available as f64) < capacity as f64 * percent / 100.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Thresholds {
+    pub memory_available: Option<Threshold>,
+    pub nodefs_available: Option<Threshold>,
+}
+
+pub fn parse_thresholds(text: &str) -> Result<Thresholds> {
+    let mut thresholds = Thresholds::default();
+    for rule in text.split(',').map(str::trim).filter(|rule| !rule.is_empty()) {
+        let (signal, value) = rule
+            .split_once('<')
+            .ok_or_else(|| anyhow!("expected <signal><<quantity>, got {}", rule))?;
+        let threshold = Some(Threshold::parse(value.trim())?);
+        match signal.trim() {
+            "memory.available" => thresholds.memory_available = threshold,
+            "nodefs.available" => thresholds.nodefs_available = threshold,
+            other => return Err(anyhow!("unknown eviction signal {}", other)),
+        }
+    }
+    Ok(thresholds)
+}
+
+// for clap
+pub fn parse_thresholds_arg(text: &str) -> Result<Thresholds, String> {
+    parse_thresholds(text).map_err(|e| e.to_string())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pressure {
+    Memory,
+    Disk,
+}
+
+impl fmt::Display for Pressure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pressure::Memory => write!(f, "MemoryPressure"),
+            Pressure::Disk => write!(f, "DiskPressure"),
+        }
+    }
+}
+
+impl Pressure {
+    // the resource the node is low on
+    pub fn resource(&self) -> &'static str {
+        match self {
+            Pressure::Memory => "memory",
+            Pressure::Disk => "ephemeral-storage",
+        }
+    }
+
+    // whether a pod of the class is admitted while the node is under the pressure
+    pub fn admits(&self, class: QosClass) -> bool {
+        match self {
+            Pressure::Memory => class != QosClass::BestEffort,
+            Pressure::Disk => false,
+        }
+    }
+}
+
+// MemTotal and MemAvailable of /proc/meminfo, in bytes
+fn memory() -> Result<(u64, u64)> {
+    let meminfo = fs::read_to_string("/proc/meminfo")?;
+    let field = |name: &str| {
+        meminfo
+            .lines()
+            .find_map(|line| line.strip_prefix(name))
+            .and_then(|kb| kb.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+            .map(|kb| kb * 1024)
+            .ok_or_else(|| anyhow!("{} not found in /proc/meminfo", name))
+    };
+    Ok((field("MemTotal:")?, field("MemAvailable:")?))
+}
+
+// the pressures the node is under
+pub fn pressures(thresholds: &Thresholds, root_path: &Path) -> Result<Vec<Pressure>> {
+    let mut pressures = Vec::new();
+    if let Some(threshold) = &thresholds.memory_available {
+        let (total, available) = memory()?;
+        if threshold.crossed(available, total) {
+            pressures.push(Pressure::Memory);
+        }
+    }
+    if let Some(threshold) = &thresholds.nodefs_available {
+        let stat = statvfs(root_path)?;
+        let fragment = stat.fragment_size() as u64;
+        let (capacity, available) = (stat.blocks() as u64 * fragment, stat.blocks_available() as u64 * fragment);
+        if threshold.crossed(available, capacity) {
+            pressures.push(Pressure::Disk);
+        }
+    }
+    Ok(pressures)
+}
+
+// a running pod that could be evicted, with the usage and the requests of the
+// resource the node is low on
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub name: String,
+    pub class: QosClass,
+    pub usage: u64,
+    pub request: u64,
+}
+
+// the pod to evict first
+pub fn choose(candidates: &[Candidate]) -> Option<&Candidate> {
+    let rank = |class: QosClass| match class {
+        QosClass::BestEffort => 0,
+        QosClass::Burstable => 1,
+        QosClass::Guaranteed => 2,
+    };
+    candidates.iter().min_by_key(|c| (rank(c.class), Reverse(c.usage as i128 - c.request as i128), c.name.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_thresholds() {
+        let thresholds = parse_thresholds(DEFAULT_EVICTION_HARD).unwrap();
+        assert_eq!(thresholds.memory_available, Some(Threshold::Bytes(100 * 1024 * 1024)));
+        assert_eq!(thresholds.nodefs_available, Some(Threshold::Percent(10.0)));
+        assert!(Threshold::Percent(10.0).crossed(99, 1000));
+        assert!(!Threshold::Bytes(100).crossed(100, 1000));
+
+        assert!(parse_thresholds("memory.available>1Gi").is_err());
+        assert!(parse_thresholds("imagefs.available<10%").is_err());
+        assert!(parse_thresholds("nodefs.available<110%").is_err());
+        assert_eq!(parse_thresholds("").unwrap(), Thresholds::default());
+    }
+
+    #[test]
+    fn test_choose() {
+        let candidate = |name: &str, class, usage, request| Candidate { name: name.to_string(), class, usage, request };
+        let mut candidates = vec![
+            candidate("guaranteed", QosClass::Guaranteed, 900, 100),
+            candidate("small", QosClass::Burstable, 300, 200),
+            candidate("large", QosClass::Burstable, 800, 200),
+        ];
+        assert_eq!(choose(&candidates).unwrap().name, "large");
+        candidates.push(candidate("best-effort", QosClass::BestEffort, 10, 0));
+        assert_eq!(choose(&candidates).unwrap().name, "best-effort");
+        assert!(choose(&[]).is_none());
+    }
+}
@@ -0,0 +1,77 @@
+use std::fs;
+use std::path::Path;
+
+// .rklignore of a manifest directory: one glob pattern per line matched against
+// file names, with * and ? wildcards, # comments and ! to re-include files an
+// earlier pattern excluded, like .gitignore. Hidden files are always ignored,
+// editors and `rkl daemon` itself keep their temporary files there.
+
+pub const IGNORE_FILE: &str = ".rklignore";
+
+#[derive(Debug, Default)]
+pub struct IgnoreRules {
+    // pattern and whether it re-includes
+    rules: Vec<(String, bool)>,
+}
+
+impl IgnoreRules {
+    pub fn parse(contents: &str) -> Self {
+        let rules = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| match line.strip_prefix('!') {
+                Some(pattern) => (pattern.to_string(), true),
+                None => (line.to_string(), false),
+            })
+            .collect();
+        IgnoreRules { rules }
+    }
+
+    // the rules of dir, none when it has no .rklignore
+    pub fn load(dir: &Path) -> std::io::Result<Self> {
+        match fs::read_to_string(dir.join(IGNORE_FILE)) {
+            Ok(contents) => Ok(Self::parse(&contents)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn is_ignored(&self, file_name: &str) -> bool {
+        if file_name.starts_with('.') {
+            return true;
+        }
+        // the last matching pattern decides
+        self.rules
+            .iter()
+            .rev()
+            .find(|(pattern, _)| glob_match(pattern.as_bytes(), file_name.as_bytes()))
+            .is_some_and(|(_, include)| !include)
+    }
+}
+
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some(b'*'), _) => glob_match(&pattern[1..], name) || (!name.is_empty() && glob_match(pattern, &name[1..])),
+        (Some(b'?'), Some(_)) => glob_match(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => glob_match(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ignore_rules() {
+        let rules = IgnoreRules::parse("# drafts\n*.draft.yaml\nwip-*\n!wip-keep.yaml\n");
+        assert!(rules.is_ignored("web.draft.yaml"));
+        assert!(rules.is_ignored("wip-db.yaml"));
+        assert!(!rules.is_ignored("wip-keep.yaml"));
+        assert!(!rules.is_ignored("web.yaml"));
+        assert!(rules.is_ignored(".web.yaml.swp"));
+        assert!(IgnoreRules::parse("web?.yaml").is_ignored("web1.yaml"));
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use anyhow::{Result, anyhow};
+use nix::sys::statvfs::statvfs;
+use crate::cri::cri::{Image, ImageFsInfoRequest, ImageSpec, ListImagesRequest, RemoveImageRequest};
+use crate::image::{self, ImageReference};
+use crate::runtime::RuntimeBackend;
+use crate::stats::format_bytes;
+use tracing::{info, warn};
+
+// The image garbage collection of the daemon, like the kubelet's: after every
+// sync the usage of the filesystem the images are on, the one of the CRI
+// runtime or of the image store of rkl, is compared with
+// --image-gc-high-threshold. Above it the images no pod of the daemon uses are
+// removed, the least recently used first, until the usage is down to
+// --image-gc-low-threshold. Images are only removed once they have been seen
+// for --image-minimum-gc-age, so that one pulled for a pod about to start isn't.
+// When they were last used is kept in memory, after a restart of the daemon
+// every image counts as unused since then.
+
+pub const DEFAULT_HIGH_THRESHOLD: u8 = 85;
+pub const DEFAULT_LOW_THRESHOLD: u8 = 80;
+pub const DEFAULT_MINIMUM_AGE: &str = "2m";
+
+// percentages of the capacity of the image filesystem
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageGcPolicy {
+    pub high_threshold: u8,
+    pub low_threshold: u8,
+    pub minimum_age: Duration,
+}
+
+impl ImageGcPolicy {
+    pub fn validate(&self) -> Result<()> {
+        if self.high_threshold > 100 {
+            return Err(anyhow!("--image-gc-high-threshold {} is over 100", self.high_threshold));
+        }
+        if self.low_threshold > self.high_threshold {
+            return Err(anyhow!(
+                "--image-gc-low-threshold {} is over --image-gc-high-threshold {}",
+                self.low_threshold,
+                self.high_threshold
+            ));
+        }
+        Ok(())
+    }
+
+    // the bytes to free on a filesystem, 0 while it is under the high threshold;
+    // at 100 images are never collected
+    fn to_free(&self, capacity: u64, available: u64) -> u64 {
+        let used = capacity.saturating_sub(available) as u128;
+        let capacity = capacity as u128;
+        if self.high_threshold >= 100 || capacity == 0 || used * 100 < capacity * self.high_threshold as u128 {
+            return 0;
+        }
+        used.saturating_sub(capacity * self.low_threshold as u128 / 100) as u64
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Record {
+    first_detected: Instant,
+    last_used: Option<Instant>,
+}
+
+pub struct ImageGc {
+    policy: ImageGcPolicy,
+    // by image id
+    records: HashMap<String, Record>,
+}
+
+// an image name the way the runtimes list it, e.g. docker.io/library/nginx:1.27,
+// or by its digest alone when pinned
+fn normalize(image: &str) -> String {
+    ImageReference::parse(image).map(|reference| reference.pull_name()).unwrap_or_else(|_| image.to_string())
+}
+
+fn used_by(image: &Image, in_use: &HashSet<String>) -> bool {
+    in_use.contains(&image.id) || image.repo_tags.iter().chain(&image.repo_digests).any(|name| in_use.contains(&normalize(name)))
+}
+
+impl ImageGc {
+    pub fn new(policy: ImageGcPolicy) -> Self {
+        ImageGc { policy, records: HashMap::new() }
+    }
+
+    // remember the images of the node and which are used now, forgetting the removed ones
+    fn detect(&mut self, images: &[Image], in_use: &HashSet<String>, now: Instant) {
+        let ids: HashSet<&str> = images.iter().map(|image| image.id.as_str()).collect();
+        self.records.retain(|id, _| ids.contains(id.as_str()));
+        for image in images {
+            let record = self.records.entry(image.id.clone()).or_insert(Record { first_detected: now, last_used: None });
+            if used_by(image, in_use) {
+                record.last_used = Some(now);
+            }
+        }
+    }
+
+    // the images to remove to free the bytes, least recently used first
+    fn choose<'i>(&self, images: &'i [Image], in_use: &HashSet<String>, bytes: u64, now: Instant) -> (Vec<&'i Image>, u64) {
+        let mut candidates: Vec<(&Image, &Record)> = images
+            .iter()
+            .filter(|image| !used_by(image, in_use))
+            .filter_map(|image| self.records.get(&image.id).map(|record| (image, record)))
+            .filter(|(_, record)| now.duration_since(record.first_detected) >= self.policy.minimum_age)
+            .collect();
+        candidates.sort_by_key(|(image, record)| (record.last_used, record.first_detected, image.id.clone()));
+        let mut chosen = Vec::new();
+        let mut freed = 0;
+        for (image, _) in candidates {
+            if freed >= bytes {
+                break;
+            }
+            freed += image.size;
+            chosen.push(image);
+        }
+        (chosen, freed)
+    }
+
+    // the filesystem the images are on
+    fn mountpoint(backend: Option<&dyn RuntimeBackend>, root_path: &Path) -> Result<PathBuf> {
+        let Some(backend) = backend else {
+            let store = image::image_store_dir(root_path);
+            return Ok(if store.exists() { store } else { root_path.to_path_buf() });
+        };
+        let response = backend.image_fs_info(ImageFsInfoRequest {})?;
+        response
+            .image_filesystems
+            .iter()
+            .filter_map(|usage| usage.fs_id.as_ref())
+            .map(|id| PathBuf::from(&id.mountpoint))
+            .next()
+            .ok_or_else(|| anyhow!("the runtime reports no image filesystem"))
+    }
+
+    fn list(backend: Option<&dyn RuntimeBackend>, root_path: &Path) -> Result<Vec<Image>> {
+        match backend {
+            Some(backend) => Ok(backend.list_images(ListImagesRequest::default())?.images),
+            None => Ok(image::list_images(root_path, None)),
+        }
+    }
+
+    fn remove(backend: Option<&dyn RuntimeBackend>, root_path: &Path, id: &str) -> Result<()> {
+        match backend {
+            Some(backend) => {
+                backend.remove_image(RemoveImageRequest { image: Some(ImageSpec { image: id.to_string(), ..Default::default() }) })?;
+                Ok(())
+            }
+            None => image::remove_image(root_path, id),
+        }
+    }
+
+    // one pass, with the images the pods of the daemon are made of
+    pub fn collect(&mut self, backend: Option<&dyn RuntimeBackend>, root_path: &Path, in_use: &[String]) {
+        if let Err(e) = self.try_collect(backend, root_path, in_use) {
+            warn!("Image garbage collection failed: {}", e);
+        }
+    }
+
+    fn try_collect(&mut self, backend: Option<&dyn RuntimeBackend>, root_path: &Path, in_use: &[String]) -> Result<()> {
+        let now = Instant::now();
+        let in_use: HashSet<String> = in_use.iter().map(|image| normalize(image)).collect();
+        let images = Self::list(backend, root_path)?;
+        self.detect(&images, &in_use, now);
+
+        let mountpoint = Self::mountpoint(backend, root_path)?;
+        let stat = statvfs(&mountpoint)?;
+        let fragment = stat.fragment_size() as u64;
+        let (capacity, available) = (stat.blocks() as u64 * fragment, stat.blocks_available() as u64 * fragment);
+        let bytes = self.policy.to_free(capacity, available);
+        if bytes == 0 {
+            return Ok(());
+        }
+        info!(
+            "Image filesystem {} is {}% used, over the high threshold of {}%, freeing {}",
+            mountpoint.display(),
+            capacity.saturating_sub(available) as u128 * 100 / capacity as u128,
+            self.policy.high_threshold,
+            format_bytes(bytes)
+        );
+        let (chosen, _) = self.choose(&images, &in_use, bytes, now);
+        let mut freed = 0;
+        for image in chosen {
+            let name = image.repo_tags.first().unwrap_or(&image.id);
+            match Self::remove(backend, root_path, &image.id) {
+                Ok(()) => {
+                    info!("Removed unused image {} ({})", name, format_bytes(image.size));
+                    freed += image.size;
+                    self.records.remove(&image.id);
+                }
+                Err(e) => warn!("Failed to remove unused image {}: {}", name, e),
+            }
+        }
+        if freed < bytes {
+            return Err(anyhow!("only {} of the {} needed could be freed, the other images are in use or too recent", format_bytes(freed), format_bytes(bytes)));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_gc() {
+        let policy = ImageGcPolicy { high_threshold: 85, low_threshold: 80, minimum_age: Duration::from_secs(120) };
+        assert!(policy.validate().is_ok());
+        assert!(ImageGcPolicy { low_threshold: 90, ..policy.clone() }.validate().is_err());
+        assert_eq!(policy.to_free(1000, 200), 0);
+        assert_eq!(policy.to_free(1000, 100), 100);
+        assert_eq!(ImageGcPolicy { high_threshold: 100, ..policy.clone() }.to_free(1000, 0), 0);
+
+        let image = |id: &str, tag: &str, size| Image { id: id.to_string(), repo_tags: vec![tag.to_string()], size, ..Default::default() };
+        let images = vec![
+            image("sha256:a", "docker.io/library/nginx:1.27", 300),
+            image("sha256:b", "docker.io/library/busybox:1.36", 200),
+            image("sha256:c", "docker.io/library/redis:7", 100),
+            image("sha256:d", "docker.io/library/alpine:3.20", 50),
+        ];
+        let mut gc = ImageGc::new(policy);
+        let start = Instant::now();
+        // redis was used after busybox, nginx is used now
+        gc.detect(&images[..3], &["docker.io/library/busybox:1.36".to_string()].into(), start);
+        gc.detect(&images[..3], &["docker.io/library/redis:7".to_string()].into(), start + Duration::from_secs(60));
+        let later = start + Duration::from_secs(600);
+        let in_use: HashSet<String> = [normalize("nginx:1.27")].into();
+        gc.detect(&images, &in_use, later);
+
+        // alpine was only seen now, nginx is in use
+        let (chosen, freed) = gc.choose(&images, &in_use, 250, later);
+        assert_eq!(chosen.iter().map(|image| image.id.as_str()).collect::<Vec<_>>(), ["sha256:b", "sha256:c"]);
+        assert_eq!(freed, 300);
+        let (chosen, _) = gc.choose(&images, &in_use, 1, later);
+        assert_eq!(chosen.len(), 1);
+        // pinned images are in use by their digest
+        let pinned = Image { repo_digests: vec!["docker.io/library/busybox@sha256:b0".to_string()], ..images[1].clone() };
+        assert!(used_by(&pinned, &[normalize("busybox:1.36@sha256:b0")].into()));
+
+        // removed images are forgotten
+        gc.detect(&images[..1], &in_use, later);
+        assert_eq!(gc.records.len(), 1);
+    }
+}
@@ -0,0 +1,381 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+use crate::daemon::template::{self, PodTemplate};
+use crate::events::{self, EventType};
+use crate::node;
+use crate::rootpath;
+use crate::task::task::{ObjectMeta, RestartPolicy};
+use tracing::{error, info};
+
+// A Job of a manifest directory runs pods of its template to completion:
+// up to parallelism pods at a time until completions of them succeeded. A
+// pod fails when its containers exit with a non-zero code, then another one
+// is created in its place, each restart of a container of an OnFailure pod
+// counts as a failure too. Past backoffLimit failures the Job fails, and once
+// it is complete or failed its running pods are deleted; the finished pods
+// stay so that their logs can be read. The pods and their phases are kept in
+// <root>/daemon/jobs/<name>.json.
+
+pub const KIND: &str = "Job";
+const JOB_NAME: &str = "job-name";
+
+#[derive(Debug, Deserialize)]
+pub struct Job {
+    #[serde(rename = "apiVersion")]
+    pub api_version: String,
+    pub kind: String,
+    pub metadata: ObjectMeta,
+    pub spec: JobSpec,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct JobSpec {
+    #[serde(default = "default_one")]
+    pub completions: u32,
+    #[serde(default = "default_one")]
+    pub parallelism: u32,
+    #[serde(rename = "backoffLimit", default = "default_backoff_limit")]
+    pub backoff_limit: u32,
+    // kept as written, the pods are made of it
+    pub template: Value,
+}
+
+fn default_one() -> u32 {
+    1
+}
+
+fn default_backoff_limit() -> u32 {
+    6
+}
+
+impl Job {
+    pub fn parse(contents: &str) -> Result<Self> {
+        let job: Job = serde_yaml::from_str(contents)?;
+        if job.kind != KIND || job.api_version != "batch/v1" {
+            return Err(anyhow!("expected apiVersion batch/v1 and kind Job"));
+        }
+        let template = PodTemplate::parse(&job.spec.template)?;
+        if template.spec.restart_policy == RestartPolicy::Always {
+            return Err(anyhow!("the restartPolicy of the pod template must be Never or OnFailure"));
+        }
+        if job.spec.completions == 0 {
+            return Err(anyhow!("completions must be at least 1"));
+        }
+        Ok(job)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConditionType {
+    Complete,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobCondition {
+    #[serde(rename = "type")]
+    pub kind: ConditionType,
+    pub reason: String,
+    pub message: String,
+}
+
+// a pod of a job with the phase it was last seen in
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PodRecord {
+    pub phase: String,
+    pub restarts: u32,
+}
+
+impl PodRecord {
+    fn finished(&self) -> bool {
+        self.phase == "Succeeded" || self.phase == "Failed"
+    }
+}
+
+// what the controller keeps of a job
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct JobState {
+    manifest: PathBuf,
+    contents: String,
+    // hash of the template the pods are made of
+    hash: String,
+    pods: BTreeMap<String, PodRecord>,
+    condition: Option<JobCondition>,
+    // unix times
+    #[serde(rename = "startTime")]
+    start_time: u64,
+    #[serde(rename = "completionTime")]
+    completion_time: Option<u64>,
+}
+
+impl JobState {
+    fn succeeded(&self) -> usize {
+        self.pods.values().filter(|pod| pod.phase == "Succeeded").count()
+    }
+
+    fn failed(&self) -> u32 {
+        let failed = self.pods.values().filter(|pod| pod.phase == "Failed").count() as u32;
+        failed + self.pods.values().map(|pod| pod.restarts).sum::<u32>()
+    }
+
+    fn active(&self) -> usize {
+        self.pods.values().filter(|pod| !pod.finished()).count()
+    }
+}
+
+fn state_dir(root_path: &Path) -> PathBuf {
+    root_path.join("daemon").join("jobs")
+}
+
+fn state_path(root_path: &Path, name: &str) -> PathBuf {
+    state_dir(root_path).join(format!("{}.json", name))
+}
+
+// the state of every job of the manifest directories
+pub struct JobController {
+    root_path: PathBuf,
+    states: HashMap<String, JobState>,
+}
+
+impl JobController {
+    pub fn load(root_path: &Path) -> Result<Self> {
+        let mut states = HashMap::new();
+        let dir = state_dir(root_path);
+        if dir.exists() {
+            for entry in fs::read_dir(&dir)? {
+                let path = entry?.path();
+                let Some(name) = path.file_stem().filter(|_| path.extension().is_some_and(|ext| ext == "json")) else {
+                    continue;
+                };
+                let state: JobState = serde_json::from_str(&fs::read_to_string(&path)?)
+                    .map_err(|e| anyhow!("invalid job state {}: {}", path.display(), e))?;
+                states.insert(name.to_string_lossy().to_string(), state);
+            }
+        }
+        Ok(JobController { root_path: root_path.to_path_buf(), states })
+    }
+
+    // the manifest every job was last reconciled from, by name
+    pub fn applied(&self) -> impl Iterator<Item = (&String, &Path, &String)> {
+        self.states.iter().map(|(name, state)| (name, state.manifest.as_path(), &state.contents))
+    }
+
+    // whether the pod belongs to a job
+    pub fn owns(&self, pod_name: &str) -> bool {
+        self.states.values().any(|state| state.pods.contains_key(pod_name))
+    }
+
+    // whether the job completed or failed, None while it runs
+    pub fn condition(&self, name: &str) -> Option<ConditionType> {
+        self.states.get(name).and_then(|state| state.condition.as_ref()).map(|condition| condition.kind)
+    }
+
+    // count the finished pods of every job, given as name, manifest and
+    // contents, and return the manifests of the pods they should have right
+    // now as manifest of their job, pod name and pod manifest; pods gives the
+    // phase and container restarts of the pods the daemon runs
+    pub fn reconcile(
+        &mut self,
+        jobs: Vec<(String, PathBuf, String)>,
+        pods: &HashMap<String, PodRecord>,
+    ) -> Vec<(PathBuf, String, String)> {
+        let names: HashSet<&String> = jobs.iter().map(|(name, _, _)| name).collect();
+        let removed: Vec<String> = self.states.keys().filter(|name| !names.contains(name)).cloned().collect();
+        for name in removed {
+            info!("Manifest of Job {} removed, deleting its pods", name);
+            self.states.remove(&name);
+            let _ = fs::remove_file(state_path(&self.root_path, &name));
+        }
+
+        let mut manifests = Vec::new();
+        for (name, manifest, contents) in jobs {
+            match self.reconcile_job(&name, manifest.clone(), contents, pods) {
+                Ok(job_pods) => manifests.extend(job_pods.into_iter().map(|(pod, contents)| (manifest.clone(), pod, contents))),
+                Err(e) => error!("Failed to reconcile Job {}: {}", name, e),
+            }
+        }
+        manifests
+    }
+
+    fn reconcile_job(
+        &mut self,
+        name: &str,
+        manifest: PathBuf,
+        contents: String,
+        pods: &HashMap<String, PodRecord>,
+    ) -> Result<Vec<(String, String)>> {
+        let job = Job::parse(&contents)?;
+        let hash = template::hash(&job.spec.template);
+        let state = self.states.entry(name.to_string()).or_default();
+        let before = serde_json::to_string(state)?;
+        if state.hash != hash {
+            // a job runs a single template, another one starts it over
+            if !state.hash.is_empty() {
+                info!("Template of Job {} changed, starting it over", name);
+            }
+            *state = JobState { hash: hash.clone(), start_time: node::unix_now(), ..JobState::default() };
+        }
+        state.manifest = manifest;
+        state.contents = contents;
+
+        // a finished pod stays finished even once the daemon forgets it
+        for (pod, record) in state.pods.iter_mut() {
+            if let Some(current) = pods.get(pod).filter(|_| !record.finished()) {
+                *record = current.clone();
+            }
+        }
+
+        if state.condition.is_none() {
+            let failed = state.failed();
+            if failed > job.spec.backoff_limit {
+                let message = format!("Job has reached the specified backoff limit ({} failures)", failed);
+                let _ = events::record(&self.root_path, name, EventType::Warning, events::BACKOFF_LIMIT_EXCEEDED, &message);
+                state.condition = Some(JobCondition {
+                    kind: ConditionType::Failed,
+                    reason: events::BACKOFF_LIMIT_EXCEEDED.to_string(),
+                    message,
+                });
+            } else if state.succeeded() >= job.spec.completions as usize {
+                let _ = events::record(&self.root_path, name, EventType::Normal, events::COMPLETED, "Job completed");
+                state.condition = Some(JobCondition {
+                    kind: ConditionType::Complete,
+                    reason: events::COMPLETED.to_string(),
+                    message: "Job completed".to_string(),
+                });
+            }
+            if state.condition.is_some() {
+                state.completion_time = Some(node::unix_now());
+            }
+        }
+
+        if state.condition.is_some() {
+            state.pods.retain(|_, pod| pod.finished());
+        } else {
+            let remaining = (job.spec.completions as usize).saturating_sub(state.succeeded());
+            let wanted = remaining.min(job.spec.parallelism as usize);
+            for _ in state.active()..wanted {
+                let pod = template::pod_name(name);
+                let message = format!("Created pod: {}", pod);
+                let _ = events::record(&self.root_path, name, EventType::Normal, events::SUCCESSFUL_CREATE, &message);
+                state.pods.insert(pod, PodRecord { phase: "Pending".to_string(), restarts: 0 });
+            }
+        }
+
+        let mut manifests = Vec::new();
+        for pod in state.pods.keys() {
+            let labels = [(JOB_NAME, name)];
+            manifests.push((pod.clone(), template::pod_manifest(&job.spec.template, &job.metadata.namespace, pod, &labels)?));
+        }
+
+        let after = serde_json::to_string(state)?;
+        if after != before {
+            let path = state_path(&self.root_path, name);
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(&path, after)?;
+        }
+        Ok(manifests)
+    }
+}
+
+// print the status of a job of the local daemon
+pub fn status(name: &str) -> Result<()> {
+    let root_path = rootpath::determine(None)?;
+    let contents = fs::read_to_string(state_path(&root_path, name)).map_err(|_| anyhow!("Job {} not found", name))?;
+    let state: JobState = serde_json::from_str(&contents)?;
+    let job = Job::parse(&state.contents)?;
+    let status = match &state.condition {
+        Some(condition) if condition.kind == ConditionType::Complete => "Complete".to_string(),
+        Some(condition) => format!("Failed ({}: {})", condition.reason, condition.message),
+        None => "Running".to_string(),
+    };
+    println!("Name:        {}", name);
+    println!("Status:      {}", status);
+    println!("Completions: {}/{}", state.succeeded(), job.spec.completions);
+    println!("Parallelism: {}", job.spec.parallelism);
+    println!(
+        "Pods:        {} Active / {} Succeeded / {} Failed (backoffLimit {})",
+        state.active(),
+        state.succeeded(),
+        state.failed(),
+        job.spec.backoff_limit
+    );
+    if let Some(completion_time) = state.completion_time {
+        println!("Duration:    {}s", completion_time.saturating_sub(state.start_time));
+    }
+    if !state.pods.is_empty() {
+        println!("{:<24} {:<10} RESTARTS", "POD", "PHASE");
+        for (pod, record) in &state.pods {
+            println!("{:<24} {:<10} {}", pod, record.phase, record.restarts);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JOB: &str = "
+apiVersion: batch/v1
+kind: Job
+metadata:
+  name: pi
+spec:
+  completions: 3
+  parallelism: 2
+  backoffLimit: 1
+  template:
+    spec:
+      restartPolicy: Never
+      containers:
+        - name: pi
+          image: perl:5
+";
+
+    fn record(phase: &str) -> PodRecord {
+        PodRecord { phase: phase.to_string(), restarts: 0 }
+    }
+
+    fn step(controller: &mut JobController, pods: &HashMap<String, PodRecord>) -> Vec<String> {
+        let jobs = vec![("pi".to_string(), PathBuf::from("pi.yaml"), JOB.to_string())];
+        controller.reconcile(jobs, pods).into_iter().map(|(_, pod, _)| pod).collect()
+    }
+
+    #[test]
+    fn test_completions_and_backoff() {
+        let root = tempfile::tempdir().unwrap();
+        let mut controller = JobController::load(root.path()).unwrap();
+        let pods = step(&mut controller, &HashMap::new());
+        assert_eq!(pods.len(), 2);
+
+        // the first pod succeeds, only one more is needed next to the running one
+        let mut phases: HashMap<String, PodRecord> = HashMap::from([(pods[0].clone(), record("Succeeded")), (pods[1].clone(), record("Running"))]);
+        let pods = step(&mut controller, &phases);
+        assert_eq!(pods.len(), 3);
+        let state = &controller.states["pi"];
+        assert_eq!((state.succeeded(), state.active()), (1, 2));
+
+        // two failures are past the backoff limit of 1, the running pod goes
+        for pod in &pods {
+            phases.entry(pod.clone()).or_insert_with(|| record("Failed"));
+        }
+        let failed = pods.iter().find(|pod| phases[*pod].phase == "Running").unwrap().clone();
+        phases.insert(failed, PodRecord { phase: "Running".to_string(), restarts: 1 });
+        let pods = step(&mut controller, &phases);
+        let state = &controller.states["pi"];
+        assert_eq!(state.condition.as_ref().map(|c| c.kind), Some(ConditionType::Failed));
+        assert_eq!(pods.len(), 2);
+        assert_eq!(state.active(), 0);
+
+        // a restarted daemon keeps the finished job
+        let controller = JobController::load(root.path()).unwrap();
+        assert!(controller.owns(&pods[0]));
+        assert!(Job::parse(&JOB.replace("Never", "Always")).is_err());
+    }
+}
@@ -0,0 +1,143 @@
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::{Result, anyhow};
+use crate::cri::cri::ReopenContainerLogRequest;
+use crate::runtime::RuntimeBackend;
+use crate::task::logs::LogConfig;
+use tracing::warn;
+
+// Rotation of the container logs (see task/logs.rs), run by every sync. A log
+// bigger than the max size is renamed to <log>.<unix time> and the runtime is
+// asked to reopen it, like the kubelet does. The built-in runtime, and CRI
+// runtimes failing to reopen it, leave the container writing to the renamed
+// file, so it is renamed back, copied and truncated instead. The oldest rotated
+// files go beyond the max files.
+
+pub struct LogManager {
+    config: LogConfig,
+}
+
+impl LogManager {
+    pub fn new(config: LogConfig) -> Self {
+        LogManager { config }
+    }
+
+    // rotate the logs of every container under the pod log directory
+    pub fn rotate_all(&self, backend: Option<&dyn RuntimeBackend>) {
+        let Ok(pods) = fs::read_dir(&self.config.pod_log_dir) else {
+            return;
+        };
+        for container_dir in pods.flatten().filter_map(|pod| fs::read_dir(pod.path()).ok()).flatten().flatten() {
+            // the container id is its name
+            let container_id = container_dir.file_name().to_string_lossy().into_owned();
+            for log in current_logs(&container_dir.path()) {
+                if let Err(e) = self.rotate(&log, &container_id, backend) {
+                    warn!("Failed to rotate log {}: {}", log.display(), e);
+                }
+            }
+        }
+    }
+
+    fn rotate(&self, log: &Path, container_id: &str, backend: Option<&dyn RuntimeBackend>) -> Result<()> {
+        let size = fs::metadata(log)?.len();
+        if size as i64 > self.config.max_size.value() {
+            let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+            let rotated = PathBuf::from(format!("{}.{}", log.display(), timestamp));
+            rotate_file(log, &rotated, container_id, backend)?;
+        }
+        self.remove_oldest(log)
+    }
+
+    // keep max_files - 1 rotated files next to the current one, at least the last
+    fn remove_oldest(&self, log: &Path) -> Result<()> {
+        let mut rotated = rotated_logs(log)?;
+        let keep = self.config.max_files.saturating_sub(1).max(1);
+        if rotated.len() <= keep {
+            return Ok(());
+        }
+        rotated.sort_by_key(|(timestamp, _)| *timestamp);
+        for (_, path) in &rotated[..rotated.len() - keep] {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+fn rotate_file(log: &Path, rotated: &Path, container_id: &str, backend: Option<&dyn RuntimeBackend>) -> Result<()> {
+    if let Some(backend) = backend {
+        fs::rename(log, rotated)?;
+        let request = ReopenContainerLogRequest { container_id: container_id.to_string() };
+        match backend.reopen_container_log(request) {
+            Ok(_) => return Ok(()),
+            Err(e) => {
+                warn!("Failed to reopen log of container {}, truncating it: {}", container_id, e);
+                fs::rename(rotated, log)?;
+            }
+        }
+    }
+    // the container appends, it goes on at the start of the truncated file
+    fs::copy(log, rotated)?;
+    OpenOptions::new()
+        .write(true)
+        .open(log)
+        .and_then(|file| file.set_len(0))
+        .map_err(|e| anyhow!("Failed to truncate: {}", e))
+}
+
+// the logs the containers write to, <restart>.log
+fn current_logs(container_dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(container_dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "log"))
+        .collect()
+}
+
+fn rotated_logs(log: &Path) -> io::Result<Vec<(u64, PathBuf)>> {
+    let prefix = format!("{}.", log.file_name().unwrap_or_default().to_string_lossy());
+    let mut rotated = Vec::new();
+    for entry in fs::read_dir(log.parent().unwrap_or(Path::new(".")))?.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if let Some(timestamp) = name.strip_prefix(&prefix).and_then(|suffix| suffix.parse().ok()) {
+            rotated.push((timestamp, entry.path()));
+        }
+    }
+    Ok(rotated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quantity::Quantity;
+
+    #[test]
+    fn test_rotate() {
+        let dir = tempfile::tempdir().unwrap();
+        let container_dir = dir.path().join("default_web_1234").join("nginx");
+        fs::create_dir_all(&container_dir).unwrap();
+        let log = container_dir.join("0.log");
+        fs::write(&log, "x".repeat(2048)).unwrap();
+        for timestamp in [100, 200, 300] {
+            fs::write(container_dir.join(format!("0.log.{}", timestamp)), "old").unwrap();
+        }
+        let manager = LogManager::new(LogConfig {
+            pod_log_dir: dir.path().to_path_buf(),
+            max_size: Quantity::parse("1Ki").unwrap(),
+            max_files: 3,
+        });
+        // without a runtime to reopen it the log is copied and truncated
+        manager.rotate_all(None);
+        assert_eq!(fs::metadata(&log).unwrap().len(), 0);
+        let mut rotated: Vec<_> = rotated_logs(&log).unwrap().into_iter().map(|(timestamp, _)| timestamp).collect();
+        rotated.sort();
+        assert_eq!(rotated.len(), 2);
+        assert_eq!(rotated[0], 300);
+        let (_, newest) = rotated_logs(&log).unwrap().into_iter().max_by_key(|(timestamp, _)| *timestamp).unwrap();
+        assert_eq!(fs::read_to_string(newest).unwrap().len(), 2048);
+    }
+}
@@ -0,0 +1,59 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::thread;
+use anyhow::{Result, anyhow};
+use crate::metrics;
+use tracing::warn;
+
+// --metrics-listen serves the metrics of the daemon (see metrics.rs) for
+// Prometheus to scrape, plain HTTP/1.1:
+//   GET /metrics   every metric in the text exposition format
+// e.g. curl http://127.0.0.1:10255/metrics
+
+pub fn serve(addr: SocketAddr) -> Result<()> {
+    let listener = TcpListener::bind(addr).map_err(|e| anyhow!("Failed to listen on {}: {}", addr, e))?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            thread::spawn(move || {
+                if let Err(e) = handle(stream) {
+                    warn!("Metrics request failed: {}", e);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+fn handle(stream: TcpStream) -> Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+    let (status, body) = respond(method, path);
+    write_response(stream, status, &body)
+}
+
+fn respond(method: &str, path: &str) -> (&'static str, String) {
+    let path = path.split('?').next().unwrap_or_default();
+    match (method, path.trim_end_matches('/')) {
+        ("GET", "/metrics") => ("200 OK", metrics::render()),
+        ("GET", _) => ("404 Not Found", "not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "method not allowed\n".to_string()),
+    }
+}
+
+fn write_response(mut stream: TcpStream, status: &str, body: &str) -> Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
@@ -0,0 +1,262 @@
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+use anyhow::{Result, anyhow};
+use crate::daemon::proxy::ProxyMode;
+use crate::node;
+use crate::task;
+use crate::task::bridge::Ipv4Cidr;
+use crate::rootpath;
+use crate::runtime;
+use tonic::transport::{Certificate, ClientTlsConfig};
+use tracing::{info, warn};
+
+pub mod sync;
+pub mod api;
+pub mod control;
+pub mod remote;
+pub mod scheduler;
+pub mod register;
+pub mod auth;
+pub mod ignore;
+pub mod template;
+pub mod deployment;
+pub mod job;
+pub mod cron;
+pub mod cronjob;
+pub mod service;
+pub mod endpoints;
+pub mod proxy;
+pub mod dns;
+pub mod logs;
+pub mod metrics;
+pub mod resources;
+pub mod eviction;
+pub mod imagegc;
+pub mod preemption;
+pub mod watch;
+pub mod election;
+
+// `rkl daemon` is a minimal kubelet: every manifest of the static pod directory
+// is run as a pod, a sync loop keeps the pods as the manifests describe them and
+// restarts exited containers per restartPolicy, and the status of the pods is
+// served as JSON on a local unix socket. A directory is applied as a whole: while
+// one of its manifests is invalid none of its changes are, files matching its
+// .rklignore are skipped and pods whose manifest is gone are deleted. Pods can
+// also be created and deleted remotely over the gRPC control API, their manifests
+// are kept under <root>/daemon/manifests and synced like the static ones. The
+// directories are watched, a change is synced right away, see watch. With a
+// scheduler the node registers with rks, which places pods onto it through that API.
+// Pods whose nodeSelector doesn't match the labels of the node or that don't
+// tolerate its taints are rejected, NoExecute taints also evict running pods, and
+// so are pods whose requests don't fit in what the node has left, see resources.
+// When the node runs low on memory or disk pods are evicted, see eviction, and
+// unused images are removed when their filesystem fills up, see imagegc.
+// Pods are admitted in the order of their priority and one that doesn't fit
+// preempts running pods of lower priority, see preemption. PriorityClass
+// manifests are stored like ConfigMaps.
+// A Deployment manifest is run as the replicas of its pod template, which are
+// rolled out again one step per sync whenever the template changes, a Job
+// manifest runs pods of its template until enough of them succeeded and a
+// CronJob manifest creates such Jobs on its schedule; with --leader-elect-store
+// only one of the nodes with the same workloads runs them, see election. A
+// Service manifest gets a cluster IP that the proxy balances over the ready
+// pods it selects, pods being ready once their containers passed their
+// readiness probes. With
+// --cluster-dns the daemon also serves the DNS names of the services and pods.
+// After every sync the container logs are rotated. With --metrics-listen the
+// metrics of the pods, restarts and CRI calls are served for Prometheus.
+
+pub struct DaemonConfig {
+    pub manifest_dir: PathBuf,
+    pub sync_interval: Duration,
+    // <root>/rkl.sock when unset
+    pub api_socket: Option<PathBuf>,
+    // no gRPC control API when unset
+    pub grpc_listen: Option<SocketAddr>,
+    // certificate and private key files of the control API, plaintext when unset
+    pub tls: Option<(PathBuf, PathBuf)>,
+    // url of the rks scheduler to register with, requires grpc_listen
+    pub scheduler: Option<String>,
+    // CA certificate of an rks served over https
+    pub scheduler_ca_file: Option<PathBuf>,
+    // the hostname when unset
+    pub node_name: Option<String>,
+    // url of the control API given to the scheduler, http(s)://<grpc_listen> when unset
+    pub advertise_address: Option<String>,
+    // file of the admin token of the token file the scheduler creates pods
+    // with, kept off the command line, $RKL_SCHEDULER_TOKEN when unset
+    pub scheduler_token_file: Option<PathBuf>,
+    // file of the node token of rks the node registers with, none when unset
+    pub bootstrap_token_file: Option<PathBuf>,
+    // the node is NotReady for the scheduler once it didn't renew its lease for this long
+    pub node_lease_duration: Duration,
+    // reject every API request that would change workloads
+    pub read_only: bool,
+    // bearer tokens of the APIs, no authentication when unset
+    pub token_file: Option<PathBuf>,
+    // the cluster IPs of the services are allocated from it
+    pub service_cidr: Ipv4Cidr,
+    pub proxy_mode: ProxyMode,
+    // no metrics endpoint when unset
+    pub metrics_listen: Option<SocketAddr>,
+    // pods are rejected once their requests exceed this times the allocatable resources
+    pub overcommit_ratio: f64,
+    // pods are evicted when the available memory or disk falls below these
+    pub eviction_hard: eviction::Thresholds,
+    // unused images are removed when their filesystem is used over its thresholds
+    pub image_gc: imagegc::ImageGcPolicy,
+    // the controllers run on every node when unset
+    pub leader_election: Option<election::ElectionConfig>,
+}
+
+// the scheduler token when there's no --scheduler-token-file
+const SCHEDULER_TOKEN_ENV: &str = "RKL_SCHEDULER_TOKEN";
+
+// lets the APIs run the next sync right away instead of at the next interval
+#[derive(Default)]
+pub struct SyncTrigger {
+    pending: Mutex<bool>,
+    condvar: Condvar,
+}
+
+impl SyncTrigger {
+    pub fn notify(&self) {
+        *self.pending.lock().unwrap() = true;
+        self.condvar.notify_one();
+    }
+
+    // wait for a notification or the timeout, whichever comes first
+    pub fn wait(&self, timeout: Duration) {
+        let pending = self.pending.lock().unwrap();
+        let (mut pending, _) = self.condvar.wait_timeout_while(pending, timeout, |pending| !*pending).unwrap();
+        *pending = false;
+    }
+}
+
+// the token the scheduler creates pods with, from the file or the environment
+fn scheduler_token(file: Option<&Path>) -> Result<Option<String>> {
+    let token = match file {
+        Some(file) => Some(
+            fs::read_to_string(file).map_err(|e| anyhow!("failed to read the scheduler token {}: {}", file.display(), e))?,
+        ),
+        None => std::env::var(SCHEDULER_TOKEN_ENV).ok(),
+    };
+    Ok(token.map(|token| token.trim().to_string()).filter(|token| !token.is_empty()))
+}
+
+pub fn run(config: DaemonConfig) -> Result<()> {
+    let root_path = rootpath::determine(None)?;
+    config.image_gc.validate()?;
+    // orphaned container processes are reparented to the daemon,
+    // which is how it learns the exit codes of the containers
+    nix::sys::prctl::set_child_subreaper(true)?;
+
+    let status = Arc::new(Mutex::new(Vec::new()));
+    let trigger = Arc::new(SyncTrigger::default());
+    let authorizer = Arc::new(auth::Authorizer::new(config.read_only, config.token_file.as_deref())?);
+    let control = remote::Control::new(&root_path, status.clone(), trigger.clone(), authorizer.clone());
+    let node_name = config.node_name.unwrap_or_else(node::hostname);
+    let api_socket = config.api_socket.unwrap_or_else(|| root_path.join("rkl.sock"));
+    api::serve(&api_socket, control.clone())?;
+    if let Some(addr) = config.grpc_listen {
+        // the control API runs any pod it is given
+        if config.token_file.is_none() && !addr.ip().is_loopback() {
+            return Err(anyhow!("--grpc-listen on {} requires --token-file", addr));
+        }
+        if config.tls.is_none() && !addr.ip().is_loopback() {
+            warn!("The control API on {} is served without TLS, its tokens travel in plaintext", addr);
+        }
+        remote::serve(addr, control.clone(), config.tls.as_ref())?;
+        info!("rkl daemon control API on {}", addr);
+    }
+    if let Some(addr) = config.metrics_listen {
+        metrics::serve(addr)?;
+        info!("rkl daemon metrics on http://{}/metrics", addr);
+    }
+    if let Some(scheduler) = config.scheduler {
+        let addr = config.grpc_listen.ok_or_else(|| anyhow!("--scheduler requires --grpc-listen"))?;
+        let address = match config.advertise_address {
+            Some(address) => address,
+            None if addr.ip().is_unspecified() => {
+                return Err(anyhow!("--advertise-address is required when listening on {}", addr));
+            }
+            None => format!("{}://{}", if config.tls.is_some() { "https" } else { "http" }, addr),
+        };
+        // the scheduler has to be able to create pods on the node
+        if config.read_only {
+            return Err(anyhow!("--scheduler can't be used with --read-only"));
+        }
+        let scheduler_token = scheduler_token(config.scheduler_token_file.as_deref())?;
+        if config.token_file.is_some()
+            && scheduler_token.as_deref().and_then(|token| authorizer.role(token)) != Some(auth::Role::Admin)
+        {
+            return Err(anyhow!("the scheduler token must be an admin token of the token file"));
+        }
+        let registration = register::Registration {
+            scheduler,
+            node_name: node_name.clone(),
+            address,
+            token: scheduler_token.unwrap_or_default(),
+            bootstrap_token: config
+                .bootstrap_token_file
+                .map(|file| {
+                    fs::read_to_string(&file)
+                        .map(|token| token.trim().to_string())
+                        .map_err(|e| anyhow!("failed to read the bootstrap token {}: {}", file.display(), e))
+                })
+                .transpose()?,
+            lease_duration: config.node_lease_duration,
+            scheduler_tls: config
+                .scheduler_ca_file
+                .map(|file| {
+                    fs::read(&file)
+                        .map(|ca| ClientTlsConfig::new().ca_certificate(Certificate::from_pem(ca)))
+                        .map_err(|e| anyhow!("failed to read the scheduler CA {}: {}", file.display(), e))
+                })
+                .transpose()?,
+        };
+        register::start(registration, control.clone())?;
+    }
+
+    let manifest_dirs = vec![config.manifest_dir.clone(), control.manifest_dir().to_path_buf()];
+    let services = service::ServiceController::load(&root_path, config.service_cidr, config.proxy_mode)?;
+    let records = match task::dns::cluster_dns() {
+        Some((address, domain)) => {
+            // pods reach the node on the gateway of the bridge, which may not be there yet
+            task::bridge::ensure_gateway(address)?;
+            let records = Arc::new(dns::Records::default());
+            dns::serve(address, domain, records.clone())?;
+            info!("rkl daemon DNS of {} on {}:53", domain, address);
+            Some(records)
+        }
+        None => None,
+    };
+    let limits = sync::NodeLimits { overcommit_ratio: config.overcommit_ratio, eviction_hard: config.eviction_hard };
+    if let Err(e) = watch::start(manifest_dirs.clone(), trigger.clone()) {
+        warn!("Manifest changes are only synced every {:?}: {}", config.sync_interval, e);
+    }
+    let mut manager = sync::PodManager::load(&root_path, &node_name, manifest_dirs, services, records, status, limits)?;
+    if let Some(election) = config.leader_election {
+        let lease = election.lease.clone();
+        manager.set_leader(election::start(election, &node_name, trigger.clone())?);
+        info!("rkl daemon running the controllers while it holds the lease {}", lease);
+    }
+    info!(
+        "rkl daemon syncing {} every {:?}, status on {}",
+        config.manifest_dir.display(),
+        config.sync_interval,
+        api_socket.display()
+    );
+    let logs = logs::LogManager::new(task::logs::config());
+    let mut image_gc = imagegc::ImageGc::new(config.image_gc);
+    loop {
+        manager.sync();
+        let backend = runtime::backend().ok().flatten();
+        logs.rotate_all(backend.as_deref());
+        image_gc.collect(backend.as_deref(), &root_path, &manager.images());
+        trigger.wait(config.sync_interval);
+    }
+}
@@ -0,0 +1,77 @@
+use std::cmp::Reverse;
+use crate::daemon::resources::{self, Resources};
+
+// Preemption of the daemon, like the kube-scheduler's on a single node: when
+// the requests of a pod don't fit, the running pods of lower priority are
+// taken off the node one after the other, lowest priority first, until it
+// does. The ones that turn out not to be in the way are spared again,
+// highest priority first, so that as few and as unimportant pods as possible
+// are deleted. A preempted pod gets a Preempted event and is run again once
+// it fits, see priority for where the priorities come from.
+
+// a running pod that could make room
+#[derive(Debug, Clone)]
+pub struct Victim {
+    pub name: String,
+    pub priority: i32,
+    pub requests: Resources,
+}
+
+// the pods to delete for the requested resources to fit, None when even
+// deleting all of them wouldn't do. `used` are the requests of the running
+// pods that can't be preempted
+pub fn victims(
+    allocatable: Resources,
+    used: Resources,
+    requested: Resources,
+    candidates: &[Victim],
+    overcommit_ratio: f64,
+) -> Option<Vec<&Victim>> {
+    if resources::insufficient(allocatable, used, requested, overcommit_ratio).is_some() {
+        return None;
+    }
+    let mut spared: Vec<&Victim> = candidates.iter().collect();
+    spared.sort_by_key(|victim| (Reverse(victim.priority), victim.name.clone()));
+    let mut used = used;
+    let mut victims = Vec::new();
+    for victim in spared {
+        let mut with = used;
+        with += victim.requests;
+        if resources::insufficient(allocatable, with, requested, overcommit_ratio).is_none() {
+            used = with;
+        } else {
+            victims.push(victim);
+        }
+    }
+    Some(victims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn victim(name: &str, priority: i32, cpu_millis: i64) -> Victim {
+        Victim { name: name.to_string(), priority, requests: Resources { cpu_millis, memory_bytes: 0 } }
+    }
+
+    #[test]
+    fn test_victims() {
+        let allocatable = Resources { cpu_millis: 4000, memory_bytes: 1 << 30 };
+        let used = Resources { cpu_millis: 1000, memory_bytes: 0 };
+        let requested = Resources { cpu_millis: 2000, memory_bytes: 0 };
+        let candidates = [victim("batch", 0, 1000), victim("report", 100, 500), victim("cache", 10, 500)];
+
+        // report and cache are spared, batch alone makes room
+        let names: Vec<&str> = victims(allocatable, used, requested, &candidates, 1.0)
+            .unwrap()
+            .iter()
+            .map(|victim| victim.name.as_str())
+            .collect();
+        assert_eq!(names, ["batch"]);
+
+        // nothing to delete when the pod fits anyway
+        assert!(victims(allocatable, used, requested, &[], 1.0).unwrap().is_empty());
+        // the pods that can't be preempted already take too much
+        assert!(victims(allocatable, Resources { cpu_millis: 3000, memory_bytes: 0 }, requested, &candidates, 1.0).is_none());
+    }
+}
@@ -0,0 +1,304 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{ErrorKind, Write};
+use std::net::{SocketAddr, SocketAddrV4, TcpListener, TcpStream};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use anyhow::{Result, anyhow};
+use sha2::{Digest, Sha256};
+use crate::daemon::endpoints::Endpoint;
+use crate::daemon::service::ServiceState;
+use crate::stream::portforward;
+use crate::task::bridge;
+use tracing::{info, warn};
+
+// The proxy makes the cluster IPs of the services reach their endpoints. In
+// iptables mode the nat table gets a RKL-SERVICES chain, jumped to from
+// PREROUTING and OUTPUT, with a chain per service port that picks one of the
+// endpoint chains at random, each of which DNATs to its pod. The chains are
+// replaced as a whole with iptables-restore whenever the endpoints change.
+// Without iptables the userspace mode assigns the cluster IPs to lo, labeled
+// lo:rkl to tell them from the others, and accepts the connections to every TCP port itself, relaying
+// each one to the next endpoint that accepts it, round robin.
+
+const SERVICES_CHAIN: &str = "RKL-SERVICES";
+const SERVICE_CHAIN_PREFIX: &str = "RKL-SVC-";
+const ENDPOINT_CHAIN_PREFIX: &str = "RKL-SEP-";
+const ADDRESS_LABEL: &str = "lo:rkl";
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(100);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ProxyMode {
+    // iptables when it is installed, userspace otherwise
+    #[default]
+    Auto,
+    Iptables,
+    Userspace,
+    // only keep track of the endpoints, e.g. for another proxy
+    None,
+}
+
+impl fmt::Display for ProxyMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProxyMode::Auto => write!(f, "auto"),
+            ProxyMode::Iptables => write!(f, "iptables"),
+            ProxyMode::Userspace => write!(f, "userspace"),
+            ProxyMode::None => write!(f, "none"),
+        }
+    }
+}
+
+// a port the userspace proxy accepts connections on
+struct Listener {
+    endpoints: Arc<Mutex<Vec<SocketAddrV4>>>,
+    stop: Arc<AtomicBool>,
+}
+
+pub struct Proxy {
+    mode: ProxyMode,
+    listeners: HashMap<SocketAddrV4, Listener>,
+    // the cluster IPs assigned to lo
+    addresses: HashSet<std::net::Ipv4Addr>,
+}
+
+impl Proxy {
+    pub fn new(mode: ProxyMode) -> Result<Self> {
+        let mode = match mode {
+            ProxyMode::Auto if Command::new("iptables-restore").arg("--version").output().is_ok_and(|o| o.status.success()) => {
+                ProxyMode::Iptables
+            }
+            ProxyMode::Auto => ProxyMode::Userspace,
+            mode => mode,
+        };
+        if mode != ProxyMode::None {
+            info!("Service proxy in {} mode", mode);
+        }
+        Ok(Proxy { mode, listeners: HashMap::new(), addresses: HashSet::new() })
+    }
+
+    // route the cluster IPs of the services to their ready endpoints, and no others
+    pub fn apply(&mut self, services: &[(&ServiceState, &[Endpoint])]) -> Result<()> {
+        match self.mode {
+            ProxyMode::Iptables => apply_iptables(services),
+            ProxyMode::Userspace => self.apply_userspace(services),
+            _ => Ok(()),
+        }
+    }
+
+    fn apply_userspace(&mut self, services: &[(&ServiceState, &[Endpoint])]) -> Result<()> {
+        let mut wanted: HashMap<SocketAddrV4, Vec<SocketAddrV4>> = HashMap::new();
+        for (service, endpoints) in services {
+            for port in &service.ports {
+                if port.protocol != "TCP" {
+                    warn!("Port {}/{} of Service {} is only proxied in iptables mode", port.port, port.protocol, service.name);
+                    continue;
+                }
+                let endpoints = endpoints.iter().map(|endpoint| SocketAddrV4::new(endpoint.ip, port.target())).collect();
+                wanted.insert(SocketAddrV4::new(service.cluster_ip, port.port), endpoints);
+            }
+        }
+
+        if self.addresses.is_empty() {
+            // left behind by an earlier daemon
+            bridge::ip(&["addr", "flush", "dev", "lo", "label", ADDRESS_LABEL])?;
+        }
+
+        self.listeners.retain(|address, listener| {
+            let keep = wanted.contains_key(address);
+            if !keep {
+                listener.stop.store(true, Ordering::Relaxed);
+            }
+            keep
+        });
+        let ips: HashSet<std::net::Ipv4Addr> = wanted.keys().map(|address| *address.ip()).collect();
+        for ip in self.addresses.difference(&ips) {
+            bridge::ip(&["addr", "del", &format!("{}/32", ip), "dev", "lo"])?;
+        }
+        for ip in ips.difference(&self.addresses) {
+            bridge::ip(&["addr", "replace", &format!("{}/32", ip), "dev", "lo", "label", ADDRESS_LABEL])?;
+        }
+        self.addresses = ips;
+
+        for (address, endpoints) in wanted {
+            if let Some(listener) = self.listeners.get(&address) {
+                *listener.endpoints.lock().unwrap() = endpoints;
+                continue;
+            }
+            let socket = TcpListener::bind(address).map_err(|e| anyhow!("Failed to listen on {}: {}", address, e))?;
+            socket.set_nonblocking(true)?;
+            let listener = Listener { endpoints: Arc::new(Mutex::new(endpoints)), stop: Arc::new(AtomicBool::new(false)) };
+            let (endpoints, stop) = (listener.endpoints.clone(), listener.stop.clone());
+            thread::spawn(move || accept(socket, endpoints, stop));
+            self.listeners.insert(address, listener);
+        }
+        Ok(())
+    }
+}
+
+fn accept(socket: TcpListener, endpoints: Arc<Mutex<Vec<SocketAddrV4>>>, stop: Arc<AtomicBool>) {
+    let next = AtomicUsize::new(0);
+    while !stop.load(Ordering::Relaxed) {
+        let client = match socket.accept() {
+            Ok((client, _)) => client,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                thread::sleep(ACCEPT_INTERVAL);
+                continue;
+            }
+            Err(e) => {
+                warn!("Failed to accept a connection to {:?}: {}", socket.local_addr(), e);
+                thread::sleep(ACCEPT_INTERVAL);
+                continue;
+            }
+        };
+        let endpoints = endpoints.lock().unwrap().clone();
+        let start = next.fetch_add(1, Ordering::Relaxed);
+        thread::spawn(move || {
+            let _ = client.set_nonblocking(false);
+            // the first endpoint of the turn that accepts the connection gets it
+            let backend = (0..endpoints.len())
+                .map(|i| endpoints[(start + i) % endpoints.len()])
+                .find_map(|endpoint| TcpStream::connect_timeout(&SocketAddr::V4(endpoint), CONNECT_TIMEOUT).ok());
+            match backend {
+                Some(backend) => {
+                    let _ = portforward::relay(client, backend);
+                }
+                None => {
+                    let _ = client.shutdown(std::net::Shutdown::Both);
+                }
+            }
+        });
+    }
+}
+
+fn chain_name(prefix: &str, key: &str) -> String {
+    let hash = format!("{:X}", Sha256::digest(key.as_bytes()));
+    format!("{}{}", prefix, &hash[..16])
+}
+
+// the iptables-restore input replacing the chains of the proxy, the chains of
+// earlier services that are gone are deleted
+fn iptables_rules(services: &[(&ServiceState, &[Endpoint])], existing: &[String]) -> String {
+    let mut chains = vec![SERVICES_CHAIN.to_string()];
+    let mut rules = Vec::new();
+    for (service, endpoints) in services {
+        for port in &service.ports {
+            let protocol = port.protocol.to_lowercase();
+            let key = format!("{}/{}:{}/{}", service.namespace, service.name, port.port, protocol);
+            let service_chain = chain_name(SERVICE_CHAIN_PREFIX, &key);
+            if endpoints.is_empty() {
+                continue;
+            }
+            rules.push(format!(
+                "-A {} -d {}/32 -p {} -m {} --dport {} -m comment --comment \"{}\" -j {}",
+                SERVICES_CHAIN, service.cluster_ip, protocol, protocol, port.port, key, service_chain
+            ));
+            chains.push(service_chain.clone());
+            let count = endpoints.len();
+            for (i, endpoint) in endpoints.iter().enumerate() {
+                let endpoint_chain = chain_name(ENDPOINT_CHAIN_PREFIX, &format!("{}/{}", key, endpoint.ip));
+                // each endpoint is picked with the same probability in the end
+                let pick = if i + 1 < count {
+                    format!("-m statistic --mode random --probability {:.5} ", 1.0 / (count - i) as f64)
+                } else {
+                    String::new()
+                };
+                rules.push(format!("-A {} {}-j {}", service_chain, pick, endpoint_chain));
+                rules.push(format!(
+                    "-A {} -p {} -m {} -j DNAT --to-destination {}:{}",
+                    endpoint_chain, protocol, protocol, endpoint.ip, port.target()
+                ));
+                chains.push(endpoint_chain);
+            }
+        }
+    }
+    let stale: Vec<&String> = existing.iter().filter(|chain| !chains.contains(chain)).collect();
+
+    let mut input = String::from("*nat\n");
+    for chain in chains.iter().chain(stale.iter().copied()) {
+        input.push_str(&format!(":{} - [0:0]\n", chain));
+    }
+    for rule in rules {
+        input.push_str(&rule);
+        input.push('\n');
+    }
+    for chain in stale {
+        input.push_str(&format!("-X {}\n", chain));
+    }
+    input.push_str("COMMIT\n");
+    input
+}
+
+fn apply_iptables(services: &[(&ServiceState, &[Endpoint])]) -> Result<()> {
+    let output = Command::new("iptables").args(["-t", "nat", "-S"]).output()?;
+    let existing: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.strip_prefix("-N "))
+        .filter(|chain| chain.starts_with(SERVICE_CHAIN_PREFIX) || chain.starts_with(ENDPOINT_CHAIN_PREFIX))
+        .map(str::to_string)
+        .collect();
+    let input = iptables_rules(services, &existing);
+
+    let mut child = Command::new("iptables-restore")
+        .arg("--noflush")
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow!("Failed to run iptables-restore: {}", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(anyhow!("iptables-restore failed: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+
+    for chain in ["PREROUTING", "OUTPUT"] {
+        let jump = |action: &str| bridge::run(Command::new("iptables").args(["-t", "nat", action, chain, "-j", SERVICES_CHAIN]));
+        if jump("-C").is_err() {
+            jump("-I")?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::daemon::service::ServicePort;
+    use std::collections::BTreeMap;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_iptables_rules() {
+        let service = ServiceState {
+            name: "web".to_string(),
+            namespace: "default".to_string(),
+            cluster_ip: Ipv4Addr::new(10, 96, 0, 2),
+            ports: vec![ServicePort { name: String::new(), protocol: "TCP".to_string(), port: 80, target_port: Some(8080) }],
+            selector: BTreeMap::new(),
+        };
+        let endpoints = vec![
+            Endpoint { pod: "web-1".to_string(), ip: Ipv4Addr::new(10, 88, 0, 2) },
+            Endpoint { pod: "web-2".to_string(), ip: Ipv4Addr::new(10, 88, 0, 3) },
+        ];
+        let stale = "RKL-SVC-0000000000000000".to_string();
+        let input = iptables_rules(&[(&service, &endpoints)], std::slice::from_ref(&stale));
+        let lines: Vec<&str> = input.lines().collect();
+        assert_eq!(lines.first(), Some(&"*nat"));
+        assert_eq!(lines.last(), Some(&"COMMIT"));
+        assert!(lines.contains(&":RKL-SERVICES - [0:0]"));
+        assert!(lines.iter().any(|line| line.starts_with("-A RKL-SERVICES -d 10.96.0.2/32 -p tcp -m tcp --dport 80")));
+        assert_eq!(lines.iter().filter(|line| line.contains("--probability 0.50000")).count(), 1);
+        assert!(lines.iter().any(|line| line.ends_with("-j DNAT --to-destination 10.88.0.3:8080")));
+        // flushed by its declaration, then deleted
+        assert!(lines.contains(&":RKL-SVC-0000000000000000 - [0:0]"));
+        assert!(lines.contains(&"-X RKL-SVC-0000000000000000"));
+
+        assert!(!iptables_rules(&[(&service, &[])], &[]).contains("-A "));
+    }
+}
@@ -0,0 +1,181 @@
+use std::thread;
+use std::time::Duration;
+use anyhow::{Result, anyhow};
+use crate::daemon::remote::Control;
+use crate::daemon::resources;
+use crate::node;
+use crate::daemon::scheduler::scheduler_client::SchedulerClient;
+use crate::daemon::scheduler::{Node, PodState, RegisterNodeRequest, RenewNodeLeaseRequest, Resources, Taint};
+use tonic::transport::{ClientTlsConfig, Endpoint};
+use tracing::field::Empty;
+use tracing::{info, info_span, warn};
+use crate::logging::{self, Propagate};
+use crate::cli_commands::PodInfo;
+use crate::runtime;
+use crate::stats::{Usage, UsageSampler};
+use crate::rootpath;
+
+// With --scheduler the daemon registers its node with rks and keeps doing so
+// every REGISTER_INTERVAL: rks only schedules onto nodes that registered
+// recently and dispatches the pods to the control API at the advertised address.
+// Between registrations the daemon renews the lease of its node every quarter
+// of --node-lease-duration, a heartbeat rks marks the node NotReady without;
+// once it stayed NotReady for long its pods are scheduled onto other nodes and
+// deleted from it when it is back. Both carry the bootstrap token of the node,
+// which rks binds the node to.
+
+const REGISTER_INTERVAL: Duration = Duration::from_secs(10);
+pub const DEFAULT_NODE_LEASE_DURATION: &str = "40s";
+// the kubelet's default
+const MAX_PODS: i64 = 110;
+
+pub struct Registration {
+    pub scheduler: String,
+    pub node_name: String,
+    // url of the control API as reachable by the scheduler
+    pub address: String,
+    // bearer token for the control API, empty without a token file
+    pub token: String,
+    // bearer token of the node for rks, see rks --token-file
+    pub bootstrap_token: Option<String>,
+    // verifies the certificate of an rks served over https, see rks --tls-cert
+    pub scheduler_tls: Option<ClientTlsConfig>,
+    // how long the node is ready for without renewing its lease
+    pub lease_duration: Duration,
+}
+
+// what the pods of the node use, summed over their containers
+fn usage(control: &Control, sampler: &mut UsageSampler) -> Result<Resources> {
+    let root_path = rootpath::determine(None)?;
+    let backend = runtime::backend()?;
+    let mut total = Usage::default();
+    for pod in control.pods() {
+        let Ok(info) = PodInfo::load(&root_path, &pod.name) else {
+            continue;
+        };
+        for (_, usage) in sampler.sample(&root_path, backend.as_deref(), &info.pod_sandbox_id, &info.container_names) {
+            total += usage;
+        }
+    }
+    Ok(Resources { cpu_millis: total.cpu_millis as i64, memory_bytes: total.memory_bytes as i64, pods: 0 })
+}
+
+fn node(registration: &Registration, control: &Control, sampler: &mut UsageSampler) -> Result<Node> {
+    let cpus = thread::available_parallelism().map(|n| n.get()).unwrap_or(1) as i64;
+    let labels = node::labels(&registration.node_name)?.into_iter().collect();
+    Ok(Node {
+        name: registration.node_name.clone(),
+        address: registration.address.clone(),
+        capacity: Some(Resources {
+            cpu_millis: cpus * 1000,
+            memory_bytes: resources::memory_total()?,
+            pods: MAX_PODS,
+        }),
+        labels,
+        taints: node::taints()?
+            .into_iter()
+            .map(|taint| Taint { key: taint.key, value: taint.value, effect: taint.effect })
+            .collect(),
+        pods: control.pods().into_iter().map(|pod| pod.name).collect(),
+        pod_states: control
+            .pods()
+            .into_iter()
+            .map(|pod| (pod.name, PodState { phase: pod.phase, pod_ip: pod.pod_ip.unwrap_or_default() }))
+            .collect(),
+        token: registration.token.clone(),
+        usage: Some(usage(control, sampler)?),
+        // rks keeps what rkl cordon set
+        unschedulable: false,
+    })
+}
+
+// a request to rks with the bootstrap token, if any
+fn authenticated<T>(message: T, bootstrap_token: Option<&str>) -> Result<tonic::Request<T>> {
+    let mut request = tonic::Request::new(message);
+    if let Some(token) = bootstrap_token {
+        let authorization = format!("Bearer {}", token).parse().map_err(|_| anyhow!("invalid bootstrap token"))?;
+        request.metadata_mut().insert("authorization", authorization);
+    }
+    Ok(request)
+}
+
+// the connection to rks, over https with the CA of --scheduler-ca-file
+fn endpoint(scheduler: &str, tls: Option<&ClientTlsConfig>) -> Result<Endpoint> {
+    let endpoint = Endpoint::from_shared(scheduler.to_string())?;
+    Ok(match tls {
+        Some(tls) => endpoint.tls_config(tls.clone())?,
+        None => endpoint,
+    })
+}
+
+// renew the lease of the node in the background, a node that isn't registered
+// yet is refused until it is
+fn heartbeat(registration: &Registration) -> Result<()> {
+    let (scheduler, node_name, duration) = (registration.scheduler.clone(), registration.node_name.clone(), registration.lease_duration);
+    let (bootstrap_token, tls) = (registration.bootstrap_token.clone(), registration.scheduler_tls.clone());
+    let runtime = tokio::runtime::Runtime::new()?;
+    thread::spawn(move || {
+        let mut renewing = true;
+        loop {
+            let result = runtime.block_on(async {
+                let mut client = SchedulerClient::new(endpoint(&scheduler, tls.as_ref())?.connect().await?);
+                let request = RenewNodeLeaseRequest { node_name: node_name.clone(), lease_duration_seconds: duration.as_secs() as i64 };
+                client.renew_node_lease(authenticated(request, bootstrap_token.as_deref())?).await?;
+                Ok::<(), anyhow::Error>(())
+            });
+            match result {
+                Ok(()) => renewing = true,
+                // registering warns as well, only the first failure is worth it
+                Err(e) if renewing => {
+                    warn!("Failed to renew the lease of node {} with {}: {}", node_name, scheduler, e);
+                    renewing = false;
+                }
+                Err(_) => {}
+            }
+            thread::sleep(duration / 4);
+        }
+    });
+    Ok(())
+}
+
+// register the node in the background for as long as the daemon runs
+pub fn start(registration: Registration, control: Control) -> Result<()> {
+    if registration.lease_duration < Duration::from_secs(4) {
+        return Err(anyhow!("--node-lease-duration must be at least 4s"));
+    }
+    heartbeat(&registration)?;
+    let runtime = tokio::runtime::Runtime::new()?;
+    thread::spawn(move || {
+        let mut registered = false;
+        // the cpu usage is a rate between two registrations
+        let mut sampler = UsageSampler::default();
+        loop {
+            let span = logging::root_span(info_span!("register", scheduler = %registration.scheduler, trace_id = Empty));
+            let entered = span.enter();
+            let result = node(&registration, &control, &mut sampler).and_then(|node| {
+                let propagate = Propagate::current();
+                runtime.block_on(async {
+                    let channel = endpoint(&registration.scheduler, registration.scheduler_tls.as_ref())?.connect().await?;
+                    let mut client = SchedulerClient::with_interceptor(channel, propagate);
+                    let request = RegisterNodeRequest { node: Some(node) };
+                    client.register_node(authenticated(request, registration.bootstrap_token.as_deref())?).await?;
+                    Ok::<(), anyhow::Error>(())
+                })
+            });
+            match result {
+                Ok(()) if !registered => {
+                    info!("Node {} registered with {}", registration.node_name, registration.scheduler);
+                    registered = true;
+                }
+                Ok(()) => {}
+                Err(e) => {
+                    warn!("Failed to register node with {}: {}", registration.scheduler, e);
+                    registered = false;
+                }
+            }
+            drop(entered);
+            thread::sleep(REGISTER_INTERVAL);
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,493 @@
+use std::fmt;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use anyhow::{Result, anyhow};
+use tonic::transport::{Identity, Server, ServerTlsConfig};
+use tonic::transport::server::TcpIncoming;
+use tonic::codegen::tokio_stream::{self, Iter};
+use tonic::{Request, Response, Status, Streaming};
+use crate::daemon::SyncTrigger;
+use crate::daemon::auth::{AuthError, Authorizer, Verb};
+use crate::daemon::control::pod_service_server::{PodService, PodServiceServer};
+use crate::daemon::control::{
+    CheckpointPodChunk, CheckpointPodRequest, ContainerStatus, CreatePodRequest, CreatePodResponse, DeletePodRequest,
+    DeletePodResponse, ListPodsRequest, ListPodsResponse, PodLogsRequest, PodLogsResponse, PodStatusRequest,
+    PodStatusResponse, RestorePodChunk, RestorePodResponse,
+};
+use crate::daemon::control;
+use crate::daemon::sync::PodStatus;
+use crate::checkpoint;
+use crate::task;
+use crate::task::task::PodTask;
+use tracing::{error, info};
+use crate::logging;
+
+// Remote pod management of the daemon, served over gRPC (proto/control.proto)
+// and as JSON on the local status socket. Created pods are regular manifests in
+// <root>/daemon/manifests: the sync loop runs and restarts them like static pods
+// and they survive restarts of the daemon. Only those pods can be deleted
+// remotely, static pods belong to whoever manages the static pod directory.
+// Both APIs check every request with the Authorizer, see auth. With
+// --tls-cert-file the gRPC API is served over TLS.
+//
+// Those pods can also be checkpointed into an archive and restored from one,
+// see checkpoint, which is how rks migrates them between nodes. The archives
+// are streamed in chunks of CHECKPOINT_CHUNK_SIZE, so that every message stays
+// within the default limits of gRPC, and are refused past MAX_CHECKPOINT_SIZE.
+
+const CHECKPOINT_CHUNK_SIZE: usize = 1 << 20;
+pub const MAX_CHECKPOINT_SIZE: usize = u32::MAX as usize;
+
+#[derive(Debug)]
+pub enum ControlError {
+    InvalidManifest(String),
+    AlreadyExists(String),
+    NotFound(String),
+    StaticPod(String),
+    Failed(anyhow::Error),
+}
+
+impl fmt::Display for ControlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControlError::InvalidManifest(e) => write!(f, "invalid pod manifest: {}", e),
+            ControlError::AlreadyExists(name) => write!(f, "pod {} already exists", name),
+            ControlError::NotFound(name) => write!(f, "pod {} not found", name),
+            ControlError::StaticPod(manifest) => {
+                write!(f, "pod is a static pod of {}, only its manifest manages it", manifest)
+            }
+            ControlError::Failed(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<std::io::Error> for ControlError {
+    fn from(e: std::io::Error) -> Self {
+        ControlError::Failed(e.into())
+    }
+}
+
+impl From<ControlError> for Status {
+    fn from(e: ControlError) -> Self {
+        let message = e.to_string();
+        match e {
+            ControlError::InvalidManifest(_) => Status::invalid_argument(message),
+            ControlError::AlreadyExists(_) => Status::already_exists(message),
+            ControlError::NotFound(_) => Status::not_found(message),
+            ControlError::StaticPod(_) => Status::failed_precondition(message),
+            ControlError::Failed(_) => Status::internal(message),
+        }
+    }
+}
+
+impl From<AuthError> for Status {
+    fn from(e: AuthError) -> Self {
+        match e {
+            AuthError::Unauthenticated => Status::unauthenticated(e.to_string()),
+            AuthError::Forbidden(_) => Status::permission_denied(e.to_string()),
+        }
+    }
+}
+
+// pod names end up in file names, only DNS labels/subdomains are accepted
+fn valid_pod_name(name: &str) -> bool {
+    let alphanumeric = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit();
+    !name.is_empty()
+        && name.len() <= 253
+        && name.chars().all(|c| alphanumeric(c) || c == '-' || c == '.')
+        && name.starts_with(alphanumeric)
+        && name.ends_with(alphanumeric)
+}
+
+// handle on the daemon shared by the control APIs
+#[derive(Clone)]
+pub struct Control {
+    root_path: PathBuf,
+    manifest_dir: PathBuf,
+    status: Arc<Mutex<Vec<PodStatus>>>,
+    trigger: Arc<SyncTrigger>,
+    authorizer: Arc<Authorizer>,
+}
+
+impl Control {
+    pub fn new(
+        root_path: &Path,
+        status: Arc<Mutex<Vec<PodStatus>>>,
+        trigger: Arc<SyncTrigger>,
+        authorizer: Arc<Authorizer>,
+    ) -> Self {
+        Control {
+            root_path: root_path.to_path_buf(),
+            manifest_dir: root_path.join("daemon").join("manifests"),
+            status,
+            trigger,
+            authorizer,
+        }
+    }
+
+    pub fn authorize(&self, token: Option<&str>, verb: Verb) -> Result<(), AuthError> {
+        self.authorizer.authorize(token, verb)
+    }
+
+    // directory of the manifests of the pods created remotely
+    pub fn manifest_dir(&self) -> &Path {
+        &self.manifest_dir
+    }
+
+    pub fn pods(&self) -> Vec<PodStatus> {
+        self.status.lock().unwrap().clone()
+    }
+
+    pub fn pod(&self, name: &str) -> Option<PodStatus> {
+        self.status.lock().unwrap().iter().find(|pod| pod.name == name).cloned()
+    }
+
+    // accept a pod manifest, the pod is created by the next sync
+    pub fn create_pod(&self, manifest: &str) -> Result<String, ControlError> {
+        let task: PodTask = serde_yaml::from_str(manifest).map_err(|e| ControlError::InvalidManifest(e.to_string()))?;
+        let name = task.metadata.name;
+        if !valid_pod_name(&name) {
+            return Err(ControlError::InvalidManifest(format!("invalid pod name {:?}", name)));
+        }
+        let path = self.manifest_dir.join(format!("{}.yaml", name));
+        if let Some(pod) = self.pod(&name)
+            && Path::new(&pod.manifest) != path
+        {
+            return Err(ControlError::AlreadyExists(name));
+        }
+        if path.exists() {
+            // retrying a create that went through is fine
+            if fs::read_to_string(&path)? == manifest {
+                return Ok(name);
+            }
+            return Err(ControlError::AlreadyExists(name));
+        }
+        self.write_manifest(&name, manifest)?;
+        Ok(name)
+    }
+
+    // replace the manifest of a pod created remotely, the next sync updates
+    // the pod or recreates it
+    pub fn replace_pod(&self, manifest: &str) -> Result<String, ControlError> {
+        let task: PodTask = serde_yaml::from_str(manifest).map_err(|e| ControlError::InvalidManifest(e.to_string()))?;
+        let name = task.metadata.name;
+        if !valid_pod_name(&name) {
+            return Err(ControlError::InvalidManifest(format!("invalid pod name {:?}", name)));
+        }
+        if !self.manifest_dir.join(format!("{}.yaml", name)).exists() {
+            return match self.pod(&name) {
+                Some(pod) => Err(ControlError::StaticPod(pod.manifest)),
+                None => Err(ControlError::NotFound(name)),
+            };
+        }
+        self.write_manifest(&name, manifest)?;
+        Ok(name)
+    }
+
+    fn write_manifest(&self, name: &str, manifest: &str) -> Result<(), ControlError> {
+        fs::create_dir_all(&self.manifest_dir)?;
+        // the sync loop must never read a partially written manifest
+        let tmp = self.manifest_dir.join(format!(".{}.yaml.tmp", name));
+        fs::write(&tmp, manifest)?;
+        fs::rename(&tmp, self.manifest_dir.join(format!("{}.yaml", name)))?;
+        self.trigger.notify();
+        Ok(())
+    }
+
+    // the error for a pod that wasn't created remotely
+    fn not_created(&self, name: &str) -> ControlError {
+        match self.pod(name) {
+            Some(pod) => ControlError::StaticPod(pod.manifest),
+            None => ControlError::NotFound(name.to_string()),
+        }
+    }
+
+    // remove the manifest of a pod created remotely, the next sync deletes the
+    // pod; with a namespace a pod of another namespace is not found
+    pub fn delete_pod(&self, name: &str, namespace: Option<&str>) -> Result<(), ControlError> {
+        let path = self.manifest_dir.join(format!("{}.yaml", name));
+        if valid_pod_name(name) && path.exists() {
+            if let Some(namespace) = namespace {
+                let task: PodTask = serde_yaml::from_str(&fs::read_to_string(&path)?).map_err(|e| ControlError::InvalidManifest(e.to_string()))?;
+                if task.metadata.namespace != namespace {
+                    return Err(ControlError::NotFound(name.to_string()));
+                }
+            }
+            fs::remove_file(&path)?;
+            self.trigger.notify();
+            return Ok(());
+        }
+        Err(self.not_created(name))
+    }
+
+    // the checkpoint archive of a pod created remotely, which keeps running
+    pub fn checkpoint_pod(&self, name: &str, timeout: Option<Duration>) -> Result<Vec<u8>, ControlError> {
+        if !valid_pod_name(name) || !self.manifest_dir.join(format!("{}.yaml", name)).exists() {
+            return Err(self.not_created(name));
+        }
+        let dir = self.root_path.join("checkpoints");
+        fs::create_dir_all(&dir)?;
+        let archive = checkpoint::checkpoint(&self.root_path, name, &dir, timeout).map_err(ControlError::Failed)?;
+        let contents = fs::read(&archive);
+        let _ = fs::remove_file(&archive);
+        Ok(contents?)
+    }
+
+    // run the pod of a checkpoint archive and manage it like the pods created
+    // remotely, its manifest recording the node it comes from
+    pub fn restore_pod(&self, archive: &[u8], source: &str) -> Result<String, ControlError> {
+        let dir = self.root_path.join("checkpoints");
+        fs::create_dir_all(&dir)?;
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_nanos()).unwrap_or_default();
+        let path = dir.join(format!("restore-{}.tar", nanos));
+        fs::write(&path, archive)?;
+        let result = self.restore_from(&path, source);
+        let _ = fs::remove_file(&path);
+        result
+    }
+
+    fn restore_from(&self, archive: &Path, source: &str) -> Result<String, ControlError> {
+        let name = checkpoint::read_metadata(archive).map_err(|e| ControlError::InvalidManifest(e.to_string()))?.pod;
+        if !valid_pod_name(&name) {
+            return Err(ControlError::InvalidManifest(format!("invalid pod name {:?}", name)));
+        }
+        if self.pod(&name).is_some() || self.manifest_dir.join(format!("{}.yaml", name)).exists() {
+            return Err(ControlError::AlreadyExists(name));
+        }
+        let mut task = checkpoint::restore(archive).map_err(ControlError::Failed)?;
+        task.metadata.annotations.insert(checkpoint::RESTORED_FROM.to_string(), source.to_string());
+        let manifest = serde_yaml::to_string(&task).map_err(|e| ControlError::Failed(e.into()))?;
+        self.write_manifest(&name, &manifest)?;
+        Ok(name)
+    }
+}
+
+impl From<PodStatus> for control::PodStatus {
+    fn from(pod: PodStatus) -> Self {
+        control::PodStatus {
+            name: pod.name,
+            manifest: pod.manifest,
+            sandbox_id: pod.sandbox_id.unwrap_or_default(),
+            pod_ip: pod.pod_ip.unwrap_or_default(),
+            phase: pod.phase,
+            last_error: pod.last_error.unwrap_or_default(),
+            containers: pod.containers
+                .into_iter()
+                .map(|c| ContainerStatus {
+                    name: c.name,
+                    state: c.state,
+                    restart_count: c.restart_count,
+                    last_exit_code: c.last_exit_code,
+                })
+                .collect(),
+        }
+    }
+}
+
+// bearer token of the authorization metadata of a request
+fn request_token<T>(request: &Request<T>) -> Option<&str> {
+    let header = request.metadata().get("authorization")?.to_str().ok()?;
+    crate::daemon::auth::bearer_token(header)
+}
+
+#[tonic::async_trait]
+impl PodService for Control {
+    async fn create_pod(&self, request: Request<CreatePodRequest>) -> Result<Response<CreatePodResponse>, Status> {
+        let span = logging::request_span("CreatePod", &request);
+        let _span = span.enter();
+        self.authorize(request_token(&request), Verb::Write)?;
+        let CreatePodRequest { manifest, replace } = request.into_inner();
+        if replace {
+            let name = Control::replace_pod(self, &manifest)?;
+            info!("Pod {} replaced over the control API", name);
+            return Ok(Response::new(CreatePodResponse { name }));
+        }
+        let name = Control::create_pod(self, &manifest)?;
+        info!("Pod {} created over the control API", name);
+        Ok(Response::new(CreatePodResponse { name }))
+    }
+
+    async fn delete_pod(&self, request: Request<DeletePodRequest>) -> Result<Response<DeletePodResponse>, Status> {
+        let span = logging::request_span("DeletePod", &request);
+        let _span = span.enter();
+        self.authorize(request_token(&request), Verb::Write)?;
+        let DeletePodRequest { name, namespace } = request.into_inner();
+        Control::delete_pod(self, &name, Some(namespace.as_str()).filter(|namespace| !namespace.is_empty()))?;
+        info!("Pod {} deleted over the control API", name);
+        Ok(Response::new(DeletePodResponse {}))
+    }
+
+    async fn list_pods(&self, request: Request<ListPodsRequest>) -> Result<Response<ListPodsResponse>, Status> {
+        let span = logging::request_span("ListPods", &request);
+        let _span = span.enter();
+        self.authorize(request_token(&request), Verb::Read)?;
+        Ok(Response::new(ListPodsResponse {
+            pods: self.pods().into_iter().map(Into::into).collect(),
+        }))
+    }
+
+    async fn pod_status(&self, request: Request<PodStatusRequest>) -> Result<Response<PodStatusResponse>, Status> {
+        let span = logging::request_span("PodStatus", &request);
+        let _span = span.enter();
+        self.authorize(request_token(&request), Verb::Read)?;
+        let name = request.into_inner().name;
+        let pod = self.pod(&name).ok_or(ControlError::NotFound(name))?;
+        Ok(Response::new(PodStatusResponse { status: Some(pod.into()) }))
+    }
+
+    async fn pod_logs(&self, request: Request<PodLogsRequest>) -> Result<Response<PodLogsResponse>, Status> {
+        let span = logging::request_span("PodLogs", &request);
+        let _span = span.enter();
+        self.authorize(request_token(&request), Verb::Read)?;
+        let request = request.into_inner();
+        if self.pod(&request.name).is_none() {
+            return Err(ControlError::NotFound(request.name).into());
+        }
+        let logs = task::logs::read_container_log(&request.name, &request.container)
+            .map_err(|e| Status::failed_precondition(e.to_string()))?;
+        Ok(Response::new(PodLogsResponse { logs }))
+    }
+
+    // the runtimes are called synchronously, off the threads of the server
+    type CheckpointPodStream = Iter<std::vec::IntoIter<Result<CheckpointPodChunk, Status>>>;
+
+    #[allow(clippy::result_large_err)]
+    async fn checkpoint_pod(&self, request: Request<CheckpointPodRequest>) -> Result<Response<Self::CheckpointPodStream>, Status> {
+        let span = logging::request_span("CheckpointPod", &request);
+        let token = request_token(&request).map(str::to_string);
+        let control = self.clone();
+        let CheckpointPodRequest { name, timeout } = request.into_inner();
+        let timeout = (timeout > 0).then(|| Duration::from_secs(timeout as u64));
+        let archive = tokio::task::spawn_blocking(move || -> Result<Vec<u8>, Status> {
+            let _span = span.enter();
+            control.authorize(token.as_deref(), Verb::Write)?;
+            let archive = Control::checkpoint_pod(&control, &name, timeout)?;
+            info!("Pod {} checkpointed over the control API", name);
+            Ok(archive)
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))??;
+        let chunks: Vec<_> = archive.chunks(CHECKPOINT_CHUNK_SIZE).map(|data| Ok(CheckpointPodChunk { data: data.to_vec() })).collect();
+        Ok(Response::new(tokio_stream::iter(chunks)))
+    }
+
+    #[allow(clippy::result_large_err)]
+    async fn restore_pod(&self, request: Request<Streaming<RestorePodChunk>>) -> Result<Response<RestorePodResponse>, Status> {
+        let span = logging::request_span("RestorePod", &request);
+        // before anything of the archive is received
+        self.authorize(request_token(&request), Verb::Write)?;
+        let control = self.clone();
+        let mut chunks = request.into_inner();
+        let (mut archive, mut source) = (Vec::new(), String::new());
+        while let Some(chunk) = chunks.message().await? {
+            if archive.len() + chunk.data.len() > MAX_CHECKPOINT_SIZE {
+                return Err(Status::resource_exhausted(format!("checkpoint archives are limited to {} bytes", MAX_CHECKPOINT_SIZE)));
+            }
+            archive.extend_from_slice(&chunk.data);
+            if source.is_empty() {
+                source = chunk.source;
+            }
+        }
+        let name = tokio::task::spawn_blocking(move || -> Result<String, Status> {
+            let _span = span.enter();
+            let name = Control::restore_pod(&control, &archive, &source)?;
+            info!("Pod {} restored over the control API from {}", name, source);
+            Ok(name)
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))??;
+        Ok(Response::new(RestorePodResponse { name }))
+    }
+}
+
+// serve the gRPC control API on its own runtime, failing right away if the
+// address can't be bound
+pub fn serve(addr: SocketAddr, control: Control, tls: Option<&(PathBuf, PathBuf)>) -> Result<()> {
+    let mut builder = Server::builder();
+    if let Some((cert, key)) = tls {
+        let read = |path: &PathBuf| fs::read(path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e));
+        let identity = Identity::from_pem(read(cert)?, read(key)?);
+        builder = builder
+            .tls_config(ServerTlsConfig::new().identity(identity))
+            .map_err(|e| anyhow!("Invalid TLS certificate {}: {}", cert.display(), e))?;
+    }
+    let runtime = tokio::runtime::Runtime::new()?;
+    let incoming = runtime
+        .block_on(async { TcpIncoming::new(addr, true, None) })
+        .map_err(|e| anyhow!("Failed to listen on {}: {}", addr, e))?;
+    thread::spawn(move || {
+        let server = builder.add_service(PodServiceServer::new(control)).serve_with_incoming(incoming);
+        if let Err(e) = runtime.block_on(server) {
+            error!("Control API stopped: {}", e);
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POD: &str = r#"
+apiVersion: v1
+kind: Pod
+metadata:
+  name: web
+spec:
+  containers:
+    - name: web-app
+      image: app:v1
+"#;
+
+    #[test]
+    fn test_create_and_delete_pod() {
+        let root = tempfile::tempdir().unwrap();
+        let status = Arc::new(Mutex::new(Vec::new()));
+        let control = Control::new(
+            root.path(),
+            status.clone(),
+            Arc::new(SyncTrigger::default()),
+            Arc::new(Authorizer::default()),
+        );
+
+        assert_eq!(control.create_pod(POD).unwrap(), "web");
+        assert!(control.manifest_dir().join("web.yaml").exists());
+        // the same manifest again is accepted, a different one isn't
+        assert!(control.create_pod(POD).is_ok());
+        let changed = POD.replace("app:v1", "app:v2");
+        assert!(matches!(control.create_pod(&changed), Err(ControlError::AlreadyExists(_))));
+        assert_eq!(control.replace_pod(&changed).unwrap(), "web");
+        assert_eq!(fs::read_to_string(control.manifest_dir().join("web.yaml")).unwrap(), changed);
+        assert!(matches!(control.create_pod("kind: Pod"), Err(ControlError::InvalidManifest(_))));
+        let bad_name = POD.replace("name: web\n", "name: ../web\n");
+        assert!(matches!(control.create_pod(&bad_name), Err(ControlError::InvalidManifest(_))));
+
+        // rks deletes the pod of a namespace, not a pod of the same name of another
+        assert!(matches!(control.delete_pod("web", Some("team-a")), Err(ControlError::NotFound(_))));
+        control.delete_pod("web", Some("default")).unwrap();
+        assert!(!control.manifest_dir().join("web.yaml").exists());
+        assert!(matches!(control.delete_pod("web", None), Err(ControlError::NotFound(_))));
+        assert!(matches!(control.replace_pod(POD), Err(ControlError::NotFound(_))));
+
+        // static pods can't be deleted remotely
+        status.lock().unwrap().push(PodStatus {
+            name: "db".to_string(),
+            manifest: "/etc/rk8s/manifests/db.yaml".to_string(),
+            sandbox_id: None,
+            pod_ip: None,
+            phase: "Pending".to_string(),
+            last_error: None,
+            containers: Vec::new(),
+        });
+        assert!(matches!(control.delete_pod("db", None), Err(ControlError::StaticPod(_))));
+        assert!(matches!(control.checkpoint_pod("db", None), Err(ControlError::StaticPod(_))));
+        assert!(matches!(control.checkpoint_pod("web", None), Err(ControlError::NotFound(_))));
+        assert!(matches!(control.restore_pod(b"not an archive", "node-a"), Err(ControlError::InvalidManifest(_))));
+        let db = POD.replace("name: web\n", "name: db\n");
+        assert!(matches!(control.create_pod(&db), Err(ControlError::AlreadyExists(_))));
+    }
+}
@@ -0,0 +1,168 @@
+use std::fs;
+use std::ops::AddAssign;
+use std::path::Path;
+use std::thread;
+use anyhow::{Result, anyhow};
+use crate::events;
+use crate::quantity::Quantity;
+use crate::task::qos;
+use crate::task::task::PodSpec;
+
+// Node resource accounting for the admission of pods, like the kubelet's: the
+// allocatable cpu and memory of the node are what it has, capped by the cgroup
+// limits the daemon runs under, and a pod is only admitted while its requests
+// and those of the pods already running stay within --overcommit-ratio times
+// that. Requests default to limits, pods without requests always fit.
+
+pub const DEFAULT_OVERCOMMIT_RATIO: f64 = 1.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Resources {
+    pub cpu_millis: i64,
+    pub memory_bytes: i64,
+}
+
+impl AddAssign for Resources {
+    fn add_assign(&mut self, other: Resources) {
+        self.cpu_millis += other.cpu_millis;
+        self.memory_bytes += other.memory_bytes;
+    }
+}
+
+pub fn memory_total() -> Result<i64> {
+    let meminfo = fs::read_to_string("/proc/meminfo")?;
+    meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("MemTotal:"))
+        .and_then(|kb| kb.trim().trim_end_matches("kB").trim().parse::<i64>().ok())
+        .map(|kb| kb * 1024)
+        .ok_or_else(|| anyhow!("MemTotal not found in /proc/meminfo"))
+}
+
+// the memory limit of the cgroup of the daemon, from memory.max on cgroup v2
+// and memory.limit_in_bytes on v1; None when unlimited
+fn cgroup_memory_limit() -> Option<i64> {
+    let cgroups = fs::read_to_string("/proc/self/cgroup").ok()?;
+    let root = Path::new("/sys/fs/cgroup");
+    let limit_file = cgroups.lines().find_map(|line| {
+        let mut fields = line.splitn(3, ':');
+        let (_, controllers, path) = (fields.next()?, fields.next()?, fields.next()?);
+        let path = path.trim_start_matches('/');
+        if controllers.is_empty() {
+            Some(root.join(path).join("memory.max"))
+        } else if controllers.split(',').any(|c| c == "memory") {
+            Some(root.join("memory").join(path).join("memory.limit_in_bytes"))
+        } else {
+            None
+        }
+    })?;
+    // "max" on v2, a huge number on v1
+    fs::read_to_string(limit_file).ok()?.trim().parse::<i64>().ok().filter(|limit| *limit < i64::MAX / 2)
+}
+
+// the cpus the daemon may use already account for its cgroup cpu quota
+pub fn allocatable() -> Result<Resources> {
+    let cpus = thread::available_parallelism().map(|n| n.get()).unwrap_or(1) as i64;
+    let memory = memory_total()?;
+    Ok(Resources {
+        cpu_millis: cpus * 1000,
+        memory_bytes: cgroup_memory_limit().map_or(memory, |limit| limit.min(memory)),
+    })
+}
+
+// what the pod needs: its containers and sidecars run together, each other
+// init container alone
+pub fn requests(spec: &PodSpec) -> Resources {
+    let request = |resource: &str, value: fn(&Quantity) -> i64| {
+        let sum: i64 = spec.running_containers().filter_map(|c| qos::request(c, resource)).map(value).sum();
+        let init = spec.init_containers.iter().filter(|c| !c.is_sidecar()).filter_map(|c| qos::request(c, resource)).map(value).max();
+        sum.max(init.unwrap_or(0))
+    };
+    Resources {
+        cpu_millis: request("cpu", Quantity::milli_value),
+        memory_bytes: request("memory", Quantity::value),
+    }
+}
+
+// why the requests of the pod don't fit next to those in use, as the reason
+// and message of the event
+pub fn insufficient(
+    allocatable: Resources,
+    used: Resources,
+    requested: Resources,
+    overcommit_ratio: f64,
+) -> Option<(&'static str, String)> {
+    let checks = [
+        ("cpu", events::OUT_OF_CPU, requested.cpu_millis, used.cpu_millis, allocatable.cpu_millis, "m"),
+        ("memory", events::OUT_OF_MEMORY, requested.memory_bytes, used.memory_bytes, allocatable.memory_bytes, ""),
+    ];
+    for (resource, reason, requested, used, allocatable, unit) in checks {
+        let limit = (allocatable as f64 * overcommit_ratio) as i64;
+        if requested > 0 && used + requested > limit {
+            let message = format!(
+                "insufficient {}: requested {}{}, {}{} of {}{} in use",
+                resource, requested, unit, used, unit, limit, unit
+            );
+            return Some((reason, message));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::task::PodTask;
+
+    #[test]
+    fn test_requests() {
+        let task: PodTask = serde_yaml::from_str(
+            r#"
+apiVersion: v1
+kind: Pod
+metadata:
+  name: resources-pod
+spec:
+  init_containers:
+    - name: migrate
+      image: /bundle/migrate
+      resources:
+        requests:
+          memory: 1Gi
+  containers:
+    - name: web
+      image: /bundle/web
+      resources:
+        limits:
+          cpu: 500m
+          memory: 256Mi
+    - name: sidecar
+      image: /bundle/sidecar
+      resources:
+        requests:
+          cpu: 250m
+          memory: 128Mi
+"#,
+        )
+        .unwrap();
+        // the limits of web count as its requests, the init container needs the most memory
+        assert_eq!(requests(&task.spec), Resources { cpu_millis: 750, memory_bytes: 1 << 30 });
+    }
+
+    #[test]
+    fn test_insufficient() {
+        let allocatable = Resources { cpu_millis: 2000, memory_bytes: 1 << 30 };
+        let used = Resources { cpu_millis: 1500, memory_bytes: 1 << 29 };
+        let fits = Resources { cpu_millis: 500, memory_bytes: 1 << 29 };
+        assert!(insufficient(allocatable, used, fits, 1.0).is_none());
+
+        let (reason, message) = insufficient(allocatable, used, Resources { cpu_millis: 600, memory_bytes: 0 }, 1.0).unwrap();
+        assert_eq!(reason, events::OUT_OF_CPU);
+        assert_eq!(message, "insufficient cpu: requested 600m, 1500m of 2000m in use");
+        // overcommitting lets it in
+        assert!(insufficient(allocatable, used, Resources { cpu_millis: 600, memory_bytes: 0 }, 1.5).is_none());
+
+        let (reason, _) = insufficient(allocatable, used, Resources { cpu_millis: 0, memory_bytes: 1 << 30 }, 1.0).unwrap();
+        assert_eq!(reason, events::OUT_OF_MEMORY);
+    }
+}
@@ -0,0 +1,322 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use crate::daemon::endpoints::{self, EndpointsController, PodView};
+use crate::daemon::proxy::{Proxy, ProxyMode};
+use crate::printer::{Listing, OutputArgs};
+use crate::rootpath;
+use crate::selector::Selector;
+use crate::task::bridge::Ipv4Cidr;
+use crate::task::task::ObjectMeta;
+use tracing::{info, warn};
+
+// A Service of a manifest directory gets a virtual IP of the service CIDR,
+// its cluster IP, that stays the same for as long as the manifest exists.
+// Connections to a port of the cluster IP are balanced over the ready pods of
+// the namespace whose labels match the selector, its endpoints (see
+// endpoints.rs), by the proxy (see proxy.rs), which is updated whenever those
+// pods come and go. Services are kept in <root>/daemon/services/<name>.json,
+// so that a restarted daemon hands out the same cluster IPs.
+
+pub const KIND: &str = "Service";
+pub const DEFAULT_SERVICE_CIDR: &str = "10.96.0.0/16";
+
+// simulate Kubernetes Service
+#[derive(Debug, Deserialize)]
+pub struct Service {
+    #[serde(rename = "apiVersion")]
+    pub api_version: String,
+    pub kind: String,
+    pub metadata: ObjectMeta,
+    pub spec: ServiceSpec,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ServiceSpec {
+    // a service without selector has no endpoints
+    #[serde(default)]
+    pub selector: HashMap<String, String>,
+    pub ports: Vec<ServicePort>,
+    // a fixed cluster IP of the service CIDR, one is allocated when unset
+    #[serde(rename = "clusterIP", default)]
+    pub cluster_ip: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServicePort {
+    #[serde(default)]
+    pub name: String,
+    // TCP or UDP
+    #[serde(default = "default_protocol")]
+    pub protocol: String,
+    pub port: u16,
+    // the port of the pods, the same as port when unset
+    #[serde(rename = "targetPort", default)]
+    pub target_port: Option<u16>,
+}
+
+fn default_protocol() -> String {
+    "TCP".to_string()
+}
+
+impl ServicePort {
+    pub fn target(&self) -> u16 {
+        self.target_port.unwrap_or(self.port)
+    }
+}
+
+impl Service {
+    pub fn parse(contents: &str) -> Result<Self> {
+        let service: Service = serde_yaml::from_str(contents)?;
+        if service.kind != KIND || service.api_version != "v1" {
+            return Err(anyhow!("expected apiVersion v1 and kind Service"));
+        }
+        if service.spec.ports.is_empty() {
+            return Err(anyhow!("Service {} has no ports", service.metadata.name));
+        }
+        let mut seen = HashSet::new();
+        for port in &service.spec.ports {
+            if port.protocol != "TCP" && port.protocol != "UDP" {
+                return Err(anyhow!("port {}: unsupported protocol {}", port.port, port.protocol));
+            }
+            if port.port == 0 || port.target_port == Some(0) {
+                return Err(anyhow!("port {}: ports must be between 1 and 65535", port.port));
+            }
+            if !seen.insert((port.port, port.protocol.as_str())) {
+                return Err(anyhow!("port {}/{} is defined twice", port.port, port.protocol));
+            }
+        }
+        if let Some(ip) = &service.spec.cluster_ip {
+            ip.parse::<Ipv4Addr>().map_err(|_| anyhow!("invalid clusterIP {}", ip))?;
+        }
+        Ok(service)
+    }
+}
+
+// what the controller keeps of a service
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServiceState {
+    pub name: String,
+    pub namespace: String,
+    #[serde(rename = "clusterIP")]
+    pub cluster_ip: Ipv4Addr,
+    pub ports: Vec<ServicePort>,
+    pub selector: BTreeMap<String, String>,
+}
+
+impl ServiceState {
+    // whether the pod of the namespace with the labels is one of the service
+    pub fn selects(&self, namespace: &str, labels: &HashMap<String, String>) -> bool {
+        !self.selector.is_empty() && namespace == self.namespace && Selector::from_labels(&self.selector).matches(labels)
+    }
+}
+
+fn state_dir(root_path: &Path) -> PathBuf {
+    root_path.join("daemon").join("services")
+}
+
+fn state_path(root_path: &Path, name: &str) -> PathBuf {
+    state_dir(root_path).join(format!("{}.json", name))
+}
+
+fn load_states(root_path: &Path) -> Result<BTreeMap<String, ServiceState>> {
+    let mut states = BTreeMap::new();
+    let dir = state_dir(root_path);
+    if !dir.exists() {
+        return Ok(states);
+    }
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if path.extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+        let state: ServiceState = serde_json::from_str(&fs::read_to_string(&path)?)
+            .map_err(|e| anyhow!("invalid service state {}: {}", path.display(), e))?;
+        states.insert(state.name.clone(), state);
+    }
+    Ok(states)
+}
+
+pub struct ServiceController {
+    root_path: PathBuf,
+    cidr: Ipv4Cidr,
+    states: BTreeMap<String, ServiceState>,
+    endpoints: EndpointsController,
+    proxy: Proxy,
+    // the proxy has to be given the services again, e.g. after a restart
+    stale: bool,
+}
+
+impl ServiceController {
+    pub fn load(root_path: &Path, cidr: Ipv4Cidr, mode: ProxyMode) -> Result<Self> {
+        Ok(ServiceController {
+            root_path: root_path.to_path_buf(),
+            cidr,
+            states: load_states(root_path)?,
+            endpoints: EndpointsController::load(root_path)?,
+            proxy: Proxy::new(mode)?,
+            stale: true,
+        })
+    }
+
+    // take the services of the manifests, given as name and contents,
+    // allocating the cluster IPs of the new ones
+    pub fn update(&mut self, services: Vec<(String, String)>) {
+        let names: HashSet<&String> = services.iter().map(|(name, _)| name).collect();
+        let removed: Vec<String> = self.states.keys().filter(|name| !names.contains(name)).cloned().collect();
+        for name in removed {
+            info!("Manifest of Service {} removed, deleting it", name);
+            self.states.remove(&name);
+            let _ = fs::remove_file(state_path(&self.root_path, &name));
+            self.stale = true;
+        }
+        for (name, contents) in services {
+            if let Err(e) = self.update_service(&name, &contents) {
+                warn!("Failed to update Service {}: {}", name, e);
+            }
+        }
+    }
+
+    fn update_service(&mut self, name: &str, contents: &str) -> Result<()> {
+        let service = Service::parse(contents)?;
+        let previous = self.states.get(name);
+        let requested = service.spec.cluster_ip.as_deref().map(|ip| ip.parse::<Ipv4Addr>()).transpose()?;
+        let cluster_ip = match (requested, previous) {
+            (Some(ip), Some(previous)) if previous.cluster_ip == ip => ip,
+            (None, Some(previous)) => previous.cluster_ip,
+            (requested, _) => self.allocate(name, requested)?,
+        };
+        let state = ServiceState {
+            name: name.to_string(),
+            namespace: service.metadata.namespace,
+            cluster_ip,
+            ports: service.spec.ports,
+            selector: service.spec.selector.into_iter().collect(),
+        };
+        if previous == Some(&state) {
+            return Ok(());
+        }
+        if previous.is_none() {
+            info!("Service {} got cluster IP {}", name, cluster_ip);
+        }
+        self.save(&state)?;
+        self.states.insert(name.to_string(), state);
+        self.stale = true;
+        Ok(())
+    }
+
+    // a free cluster IP, the requested one if it is
+    fn allocate(&self, name: &str, requested: Option<Ipv4Addr>) -> Result<Ipv4Addr> {
+        let in_use: HashSet<Ipv4Addr> =
+            self.states.values().filter(|state| state.name != name).map(|state| state.cluster_ip).collect();
+        if let Some(ip) = requested {
+            if !self.cidr.contains(ip) {
+                return Err(anyhow!("clusterIP {} is not in the service CIDR {}", ip, self.cidr));
+            }
+            if in_use.contains(&ip) {
+                return Err(anyhow!("clusterIP {} is already allocated", ip));
+            }
+            return Ok(ip);
+        }
+        let (first, last) = self.cidr.hosts();
+        (first..=last)
+            .map(Ipv4Addr::from)
+            .find(|ip| !in_use.contains(ip))
+            .ok_or_else(|| anyhow!("no cluster IPs left in {}", self.cidr))
+    }
+
+    fn save(&self, state: &ServiceState) -> Result<()> {
+        fs::create_dir_all(state_dir(&self.root_path))?;
+        fs::write(state_path(&self.root_path, &state.name), serde_json::to_string_pretty(state)?)?;
+        Ok(())
+    }
+
+    pub fn states(&self) -> impl Iterator<Item = &ServiceState> {
+        self.states.values()
+    }
+
+    // route every service to the ready pods it selects right now
+    pub fn sync_endpoints(&mut self, pods: &[PodView]) {
+        let services: Vec<&ServiceState> = self.states.values().collect();
+        let changed = self.endpoints.reconcile(&services, pods);
+        if !changed && !self.stale {
+            return;
+        }
+        let routes: Vec<_> = services.iter().map(|service| (*service, self.endpoints.addresses(&service.name))).collect();
+        match self.proxy.apply(&routes) {
+            Ok(()) => self.stale = false,
+            Err(e) => warn!("Failed to update the service proxy: {}", e),
+        }
+    }
+}
+
+pub fn print_list(output: &OutputArgs) -> Result<()> {
+    let root_path = rootpath::determine(None)?;
+    let mut listing = Listing::new(&["NAME", "CLUSTER-IP", "PORTS", "ENDPOINTS"]).with_wide(&["SELECTOR"]);
+    let all = endpoints::load_all(&root_path)?;
+    for state in load_states(&root_path)?.values() {
+        let ports: Vec<String> = state.ports.iter().map(|port| format!("{}/{}", port.port, port.protocol)).collect();
+        let (ready, not_ready) = all
+            .get(&state.name)
+            .map_or((Vec::new(), 0), |e| (e.addresses.iter().map(|a| a.ip.to_string()).collect(), e.not_ready_addresses.len()));
+        let mut endpoints = if ready.is_empty() { "<none>".to_string() } else { ready.join(",") };
+        if not_ready > 0 {
+            endpoints = format!("{} ({} not ready)", endpoints, not_ready);
+        }
+        let selector: Vec<String> = state.selector.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+        let object = json!({
+            "apiVersion": "v1",
+            "kind": "Service",
+            "metadata": { "name": state.name, "namespace": state.namespace },
+            "spec": { "clusterIP": state.cluster_ip.to_string(), "ports": state.ports, "selector": state.selector },
+        });
+        let row = vec![state.name.clone(), state.cluster_ip.to_string(), ports.join(","), endpoints, selector.join(",")];
+        listing.push(row, object);
+    }
+    output.print(listing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVICE: &str = "
+apiVersion: v1
+kind: Service
+metadata:
+  name: web
+spec:
+  selector:
+    app: web
+  ports:
+  - port: 80
+    targetPort: 8080
+";
+
+    #[test]
+    fn test_cluster_ips() {
+        let root = tempfile::tempdir().unwrap();
+        let cidr: Ipv4Cidr = "10.96.0.0/24".parse().unwrap();
+        let mut controller = ServiceController::load(root.path(), cidr, ProxyMode::None).unwrap();
+        controller.update(vec![("web".to_string(), SERVICE.to_string())]);
+        assert_eq!(controller.states["web"].cluster_ip, Ipv4Addr::new(10, 96, 0, 2));
+        assert_eq!(controller.states["web"].ports[0].target(), 8080);
+
+        // the cluster IP survives a restart, another service gets the next one
+        let mut controller = ServiceController::load(root.path(), cidr, ProxyMode::None).unwrap();
+        let api = SERVICE.replace("name: web", "name: api");
+        controller.update(vec![("web".to_string(), SERVICE.to_string()), ("api".to_string(), api.clone())]);
+        assert_eq!(controller.states["web"].cluster_ip, Ipv4Addr::new(10, 96, 0, 2));
+        assert_eq!(controller.states["api"].cluster_ip, Ipv4Addr::new(10, 96, 0, 3));
+        let taken = api.replace("spec:\n", "spec:\n  clusterIP: 10.96.0.3\n").replace("name: api", "name: db");
+        controller.update(vec![("web".to_string(), SERVICE.to_string()), ("api".to_string(), api.clone()), ("db".to_string(), taken)]);
+        assert!(!controller.states.contains_key("db"));
+        controller.update(vec![("api".to_string(), api)]);
+        assert!(!state_path(root.path(), "web").exists());
+        assert!(Service::parse(&SERVICE.replace("port: 80", "port: 0")).is_err());
+    }
+}
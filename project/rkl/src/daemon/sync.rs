@@ -0,0 +1,1438 @@
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use anyhow::{Result, anyhow};
+use libcontainer::container::ContainerStatus as RuntimeStatus;
+use nix::errno::Errno;
+use nix::sys::wait::{self, WaitPidFlag, WaitStatus};
+use nix::unistd::Pid;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use crate::adopt;
+use crate::apply;
+use crate::checkpoint;
+use crate::describe;
+use crate::values::Values;
+use crate::cli_commands::{self, PodInfo};
+use crate::configmap::{self, ConfigMap};
+use crate::secret::{self, Secret};
+use crate::priority::{self, PriorityClass};
+use crate::commands::load_container;
+use crate::events::{self, EventType};
+use crate::daemon::deployment::{self, Deployment, DeploymentController};
+use crate::daemon::job::{self, Job, JobController, PodRecord};
+use crate::daemon::cronjob::{self, CronJob, CronJobController};
+use crate::daemon::ignore::IgnoreRules;
+use crate::daemon::dns::Records;
+use crate::daemon::endpoints::PodView;
+use crate::daemon::election::LeaderState;
+use crate::daemon::eviction::{self, Candidate, Pressure, Thresholds};
+use crate::daemon::preemption::{self, Victim};
+use crate::daemon::resources;
+use crate::daemon::service::{self, Service, ServiceController};
+use crate::identity;
+use crate::node;
+use crate::runtime;
+use crate::stats::UsageSampler;
+use crate::task::{cni, qos, termination};
+use crate::task::probe::Readiness;
+use crate::task::task::{self, ContainerState, ContainerStatus, FailurePolicy, PodTask, RestartPolicy, TaskRunner};
+use tracing::field::Empty;
+use tracing::{debug, error, info, info_span, warn};
+use crate::logging;
+use crate::metrics;
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(10);
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+// a container running this long without exiting gets its backoff reset
+const BACKOFF_RESET: Duration = Duration::from_secs(600);
+// how long a manifest isn't written to before its directory is applied, less
+// than the debounce of watch so that the sync it runs sees the manifest settled
+pub const SETTLE: Duration = Duration::from_millis(200);
+
+// exponential delay between two attempts, like the kubelet's CrashLoopBackOff
+#[derive(Debug, Clone, Default)]
+pub struct Backoff {
+    delay: Duration,
+    next_attempt: Option<Instant>,
+}
+
+impl Backoff {
+    pub fn ready(&self, now: Instant) -> bool {
+        self.next_attempt.is_none_or(|next| now >= next)
+    }
+
+    pub fn fail(&mut self, now: Instant) {
+        self.delay = if self.delay.is_zero() { INITIAL_BACKOFF } else { (self.delay * 2).min(MAX_BACKOFF) };
+        self.next_attempt = Some(now + self.delay);
+    }
+
+    pub fn reset(&mut self) {
+        *self = Backoff::default();
+    }
+}
+
+#[derive(Debug, Default)]
+struct ContainerRecord {
+    pid: Option<i32>,
+    restart_count: u32,
+    last_exit_code: Option<i32>,
+    started_at: Option<Instant>,
+    backoff: Backoff,
+    readiness: Readiness,
+}
+
+// a pod run from a manifest of the static pod directory
+#[derive(Debug)]
+struct StaticPod {
+    manifest: PathBuf,
+    // sha256 of the manifest the pod was created from
+    hash: String,
+    restart_policy: RestartPolicy,
+    containers: HashMap<String, ContainerRecord>,
+    created: bool,
+    last_error: Option<String>,
+    backoff: Backoff,
+}
+
+// what the pods of the node may use, see resources and eviction
+#[derive(Debug, Clone)]
+pub struct NodeLimits {
+    // how far the requests of the pods may exceed the allocatable resources
+    pub overcommit_ratio: f64,
+    pub eviction_hard: Thresholds,
+}
+
+impl Default for NodeLimits {
+    fn default() -> Self {
+        NodeLimits { overcommit_ratio: resources::DEFAULT_OVERCOMMIT_RATIO, eviction_hard: Thresholds::default() }
+    }
+}
+
+// status of the static pods as served by the daemon API
+#[derive(Debug, Clone, Serialize)]
+pub struct PodStatus {
+    pub name: String,
+    pub manifest: String,
+    #[serde(rename = "sandboxId")]
+    pub sandbox_id: Option<String>,
+    #[serde(rename = "podIP")]
+    pub pod_ip: Option<String>,
+    pub phase: String,
+    #[serde(rename = "lastError")]
+    pub last_error: Option<String>,
+    pub containers: Vec<ContainerStatusView>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ContainerStatusView {
+    pub name: String,
+    pub state: String,
+    #[serde(rename = "restartCount")]
+    pub restart_count: u32,
+    #[serde(rename = "lastExitCode")]
+    pub last_exit_code: Option<i32>,
+}
+
+// a manifest of a manifest directory
+#[derive(Debug, Clone)]
+struct Desired {
+    manifest: PathBuf,
+    contents: String,
+    hash: String,
+    restart_policy: RestartPolicy,
+    kind: Kind,
+}
+
+// what a manifest defines, the workloads are expanded into their pods
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Pod,
+    Deployment,
+    Job,
+    CronJob,
+    ConfigMap,
+    Secret,
+    PriorityClass,
+    Service,
+}
+
+impl Desired {
+    fn parse(manifest: PathBuf, contents: String) -> Result<(String, Self)> {
+        let kind = serde_yaml::from_str::<serde_yaml::Value>(&contents)?
+            .get("kind")
+            .and_then(|kind| kind.as_str().map(str::to_string));
+        let (name, restart_policy, kind) = match kind.as_deref() {
+            Some(deployment::KIND) => {
+                let deployment = Deployment::parse(&contents)?;
+                (deployment.metadata.name, RestartPolicy::Always, Kind::Deployment)
+            }
+            Some(job::KIND) => {
+                let job = Job::parse(&contents)?;
+                (job.metadata.name, RestartPolicy::Never, Kind::Job)
+            }
+            Some(cronjob::KIND) => {
+                let cronjob = CronJob::parse(&contents)?;
+                (cronjob.metadata.name, RestartPolicy::Never, Kind::CronJob)
+            }
+            Some(configmap::KIND) => {
+                let config_map = ConfigMap::parse(&contents)?;
+                (config_map.metadata.name, RestartPolicy::Never, Kind::ConfigMap)
+            }
+            Some(secret::KIND) => {
+                let secret = Secret::parse(&contents)?;
+                (secret.metadata.name, RestartPolicy::Never, Kind::Secret)
+            }
+            // cluster-wide like in Kubernetes, a class may share the name of a pod
+            Some(priority::KIND) => {
+                let class = PriorityClass::parse(&contents)?;
+                (format!("{}/{}", priority::KIND, class.metadata.name), RestartPolicy::Never, Kind::PriorityClass)
+            }
+            // a service usually has the name of the workload it selects
+            Some(service::KIND) => {
+                let service = Service::parse(&contents)?;
+                (format!("{}/{}", service::KIND, service.metadata.name), RestartPolicy::Never, Kind::Service)
+            }
+            _ => {
+                let task: PodTask = serde_yaml::from_str(&contents)?;
+                task.spec.validate_sidecars()?;
+                task.spec.validate_hostname()?;
+                task.spec.validate_os()?;
+                for container in task.spec.running_containers() {
+                    if let Some(probe) = &container.readiness_probe {
+                        probe.validate().map_err(|e| anyhow!("readinessProbe of container {}: {}", container.name, e))?;
+                    }
+                    if let Some(post_start) = container.post_start() {
+                        post_start.validate().map_err(|e| anyhow!("postStart hook of container {}: {}", container.name, e))?;
+                    }
+                    if let Some(pre_stop) = container.pre_stop() {
+                        pre_stop.validate().map_err(|e| anyhow!("preStop hook of container {}: {}", container.name, e))?;
+                    }
+                }
+                (task.metadata.name, task.spec.restart_policy, Kind::Pod)
+            }
+        };
+        let desired = Desired { manifest, hash: manifest_hash(&contents), contents, restart_policy, kind };
+        Ok((name, desired))
+    }
+}
+
+pub struct PodManager {
+    root_path: PathBuf,
+    // the pods have to match the labels of this node
+    node_name: String,
+    // the static pod directory and the one of the pods created over the control API
+    manifest_dirs: Vec<PathBuf>,
+    // manifests of the last valid state of every directory, by pod name
+    applied: HashMap<PathBuf, HashMap<String, Desired>>,
+    // why the current state of a directory isn't applied
+    dir_errors: HashMap<PathBuf, String>,
+    pods: HashMap<String, StaticPod>,
+    deployments: DeploymentController,
+    jobs: JobController,
+    cronjobs: CronJobController,
+    services: ServiceController,
+    // the names of the cluster DNS, when the daemon serves it
+    dns: Option<Arc<Records>>,
+    // the config maps and secrets stored from manifests as <kind>/<name>,
+    // deleted with their manifest
+    objects: HashSet<String>,
+    // exit codes of reaped processes by pid
+    exits: HashMap<i32, i32>,
+    status: Arc<Mutex<Vec<PodStatus>>>,
+    limits: NodeLimits,
+    // what the node was under at the last sync
+    pressures: Vec<Pressure>,
+    sampler: UsageSampler,
+    // the workloads only run while this node leads when set, see election
+    leader: Option<Arc<LeaderState>>,
+}
+
+fn manifest_hash(contents: &str) -> String {
+    format!("{:x}", Sha256::digest(contents.as_bytes()))
+}
+
+// static pods owned by the daemon are recorded as "<name> <hash> <manifest>"
+// lines in <root>/daemon/static-pods, so that a restarted daemon adopts them
+fn static_pods_path(root_path: &Path) -> PathBuf {
+    root_path.join("daemon").join("static-pods")
+}
+
+// the config maps and secrets of the manifests as lines of <root>/daemon/objects
+fn objects_path(root_path: &Path) -> PathBuf {
+    root_path.join("daemon").join("objects")
+}
+
+// pods run from a copy of the manifest applied last, so that a restart or
+// re-creation never picks up an edit of the directory that wasn't applied
+fn applied_manifest_path(root_path: &Path, name: &str) -> PathBuf {
+    root_path.join("daemon").join("applied").join(format!("{}.yaml", name))
+}
+
+fn applied_task(root_path: &Path, name: &str) -> Result<PodTask> {
+    let contents = fs::read_to_string(applied_manifest_path(root_path, name))?;
+    Ok(serde_yaml::from_str(&contents)?)
+}
+
+impl PodManager {
+    pub fn load(
+        root_path: &Path,
+        node_name: &str,
+        manifest_dirs: Vec<PathBuf>,
+        services: ServiceController,
+        dns: Option<Arc<Records>>,
+        status: Arc<Mutex<Vec<PodStatus>>>,
+        limits: NodeLimits,
+    ) -> Result<Self> {
+        let mut manager = PodManager {
+            root_path: root_path.to_path_buf(),
+            node_name: node_name.to_string(),
+            manifest_dirs,
+            applied: HashMap::new(),
+            dir_errors: HashMap::new(),
+            pods: HashMap::new(),
+            deployments: DeploymentController::load(root_path)?,
+            jobs: JobController::load(root_path)?,
+            cronjobs: CronJobController::load(root_path)?,
+            services,
+            dns,
+            objects: fs::read_to_string(objects_path(root_path))
+                .map(|contents| contents.lines().map(str::to_string).collect())
+                .unwrap_or_default(),
+            exits: HashMap::new(),
+            status,
+            limits,
+            pressures: Vec::new(),
+            sampler: UsageSampler::default(),
+            leader: None,
+        };
+        // a workload stays applied like a pod, its pods are made of it again
+        // the jobs of cronjobs are made of their cronjob again
+        let workloads: Vec<(String, PathBuf, String)> = manager.deployments
+            .applied()
+            .chain(manager.jobs.applied().filter(|(name, _, _)| !manager.cronjobs.owns(name)))
+            .chain(manager.cronjobs.applied())
+            .map(|(name, manifest, contents)| (name.clone(), manifest.to_path_buf(), contents.clone()))
+            .collect();
+        for (name, manifest, contents) in workloads {
+            let (Ok((_, desired)), Some(dir)) = (Desired::parse(manifest.clone(), contents), manifest.parent()) else {
+                continue;
+            };
+            manager.applied.entry(dir.to_path_buf()).or_default().insert(name, desired);
+        }
+        let path = static_pods_path(root_path);
+        if path.exists() {
+            for line in fs::read_to_string(&path)?.lines() {
+                let mut fields = line.splitn(3, ' ');
+                let (Some(name), Some(hash), Some(manifest)) = (fields.next(), fields.next(), fields.next()) else {
+                    continue;
+                };
+                // pods deleted behind the daemon's back are simply created again
+                let Ok(pod_info) = PodInfo::load(root_path, name) else {
+                    continue;
+                };
+                let mut pod = StaticPod {
+                    manifest: PathBuf::from(manifest),
+                    hash: hash.to_string(),
+                    restart_policy: RestartPolicy::default(),
+                    containers: HashMap::new(),
+                    created: true,
+                    last_error: None,
+                    backoff: Backoff::default(),
+                };
+                manager.record_containers(&mut pod, &pod_info);
+                // what the pod runs stays applied until its directory is valid again
+                let applied = fs::read_to_string(applied_manifest_path(root_path, name))
+                    .map_err(anyhow::Error::from)
+                    .and_then(|contents| Desired::parse(pod.manifest.clone(), contents));
+                if let (Ok((_, desired)), Some(dir)) = (applied, pod.manifest.parent()) {
+                    pod.restart_policy = desired.restart_policy;
+                    if !manager.deployments.owns(name) && !manager.jobs.owns(name) {
+                        manager.applied.entry(dir.to_path_buf()).or_default().insert(name.to_string(), desired);
+                    }
+                }
+                manager.pods.insert(name.to_string(), pod);
+            }
+        }
+        Ok(manager)
+    }
+
+    pub fn set_leader(&mut self, leader: Arc<LeaderState>) {
+        self.leader = Some(leader);
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = static_pods_path(&self.root_path);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let contents: String = self.pods
+            .iter()
+            .filter(|(_, pod)| pod.created)
+            .map(|(name, pod)| format!("{} {} {}\n", name, pod.hash, pod.manifest.display()))
+            .collect();
+        fs::write(&path, contents)?;
+        Ok(())
+    }
+
+    fn record_containers(&self, pod: &mut StaticPod, pod_info: &PodInfo) {
+        for name in &pod_info.container_names {
+            let record = pod.containers.entry(name.clone()).or_default();
+            record.pid = load_container(&self.root_path, name).ok().and_then(|c| c.pid()).map(|p| p.as_raw());
+            record.started_at = Some(Instant::now());
+        }
+    }
+
+    // one pass of the sync loop
+    pub fn sync(&mut self) {
+        let span = logging::root_span(info_span!("sync", trace_id = Empty));
+        let _span = span.enter();
+        self.reap();
+        let desired = self.scan_manifests();
+        let desired = self.expand_workloads(desired);
+
+        let removed: Vec<String> = self.pods.keys().filter(|name| !desired.contains_key(*name)).cloned().collect();
+        for name in removed {
+            info!("Manifest of static Pod {} removed, deleting it", name);
+            if self.pods[&name].created
+                && let Err(e) = cli_commands::delete_pod(&name)
+            {
+                warn!("Failed to delete static Pod {}: {}", name, e);
+                continue;
+            }
+            self.pods.remove(&name);
+            let _ = fs::remove_file(applied_manifest_path(&self.root_path, &name));
+        }
+
+        // pods of higher priority are admitted first
+        let mut desired: Vec<(String, Desired)> = desired.into_iter().collect();
+        desired.sort_by_cached_key(|(name, desired)| (Reverse(self.priority(&desired.contents)), name.clone()));
+        for (name, desired) in desired {
+            let changed = self.pods.get(&name).is_none_or(|pod| pod.hash != desired.hash);
+            if changed {
+                self.replace_pod(&name, desired);
+            } else {
+                if let Some(pod) = self.pods.get_mut(&name) {
+                    pod.restart_policy = desired.restart_policy;
+                }
+                self.check_pod(&name);
+            }
+        }
+        self.evict_pods();
+        self.evict_for_pressure();
+        self.probe_pods();
+        let pods = self.pod_views();
+        self.services.sync_endpoints(&pods);
+        if let Some(dns) = &self.dns {
+            dns.update(self.services.states(), &pods);
+        }
+        // forget the exit codes of processes that aren't containers, e.g. io shims
+        let pids: Vec<i32> = self.pods.values().flat_map(|p| p.containers.values()).filter_map(|c| c.pid).collect();
+        self.exits.retain(|pid, _| pids.contains(pid));
+
+        match identity::rotate_all(&self.root_path) {
+            Ok(rotated) => {
+                for sandbox in rotated {
+                    info!("Rotated SVID of PodSandbox {}", sandbox);
+                }
+            }
+            Err(e) => warn!("Failed to rotate SVIDs: {}", e),
+        }
+        if let Err(e) = self.save() {
+            warn!("Failed to record static pods: {}", e);
+        }
+        self.publish();
+    }
+
+    // collect the exit codes of the containers that were reparented to the daemon
+    fn reap(&mut self) {
+        loop {
+            match wait::waitpid(Pid::from_raw(-1), Some(WaitPidFlag::WNOHANG)) {
+                Ok(WaitStatus::Exited(pid, code)) => {
+                    self.exits.insert(pid.as_raw(), code);
+                }
+                Ok(WaitStatus::Signaled(pid, signal, _)) => {
+                    self.exits.insert(pid.as_raw(), 128 + signal as i32);
+                }
+                Ok(WaitStatus::StillAlive) | Err(Errno::ECHILD) => return,
+                Ok(_) => {}
+                Err(e) => {
+                    warn!("Failed to reap exited processes: {}", e);
+                    return;
+                }
+            }
+        }
+    }
+
+    // the manifests to run by pod name: the current state of every valid
+    // directory and the last applied one of the others
+    fn scan_manifests(&mut self) -> HashMap<String, Desired> {
+        for dir in self.manifest_dirs.clone() {
+            // applied once written, see watch
+            if let Some(path) = unsettled(&dir, SystemTime::now()) {
+                debug!("Not applying the changes of {} yet: {} is being written", dir.display(), path.display());
+                continue;
+            }
+            match scan_dir(&dir) {
+                Ok(manifests) => {
+                    if self.dir_errors.remove(&dir).is_some() {
+                        info!("Manifest directory {} is valid again, applying its changes", dir.display());
+                    }
+                    self.applied.insert(dir, manifests);
+                }
+                Err(e) => {
+                    // a directory is applied all or nothing, report each new problem once
+                    let message = e.to_string();
+                    if self.dir_errors.get(&dir) != Some(&message) {
+                        warn!("Not applying the changes of {}: {}", dir.display(), message);
+                        self.dir_errors.insert(dir, message);
+                    }
+                }
+            }
+        }
+
+        let mut desired: HashMap<String, Desired> = HashMap::new();
+        for dir in &self.manifest_dirs {
+            let Some(manifests) = self.applied.get(dir) else {
+                continue;
+            };
+            let mut names: Vec<&String> = manifests.keys().collect();
+            names.sort();
+            for name in names {
+                let manifest = &manifests[name];
+                if let Some(other) = desired.get(name) {
+                    let (path, other) = (manifest.manifest.display(), other.manifest.display());
+                    warn!("Ignoring {}: Pod {} is already defined by {}", path, name, other);
+                    continue;
+                }
+                desired.insert(name.clone(), manifest.clone());
+            }
+        }
+        desired
+    }
+
+    // replace the workloads by the pods they should have right now: the
+    // deployments at this step of their rollout, the jobs until they finish,
+    // including the ones the cronjobs created by now. The config maps and
+    // secrets are stored on the way, before the pods using them are created
+    fn expand_workloads(&mut self, desired: HashMap<String, Desired>) -> HashMap<String, Desired> {
+        let mut deployments = Vec::new();
+        let mut jobs = Vec::new();
+        let mut cronjobs = Vec::new();
+        let mut objects = Vec::new();
+        let mut services = Vec::new();
+        let mut pods = HashMap::new();
+        for (name, desired) in desired {
+            match desired.kind {
+                Kind::Pod => {
+                    pods.insert(name, desired);
+                }
+                Kind::Deployment => deployments.push((name, desired.manifest, desired.contents)),
+                Kind::Job => jobs.push((name, desired.manifest, desired.contents)),
+                Kind::CronJob => cronjobs.push((name, desired.manifest, desired.contents)),
+                Kind::ConfigMap | Kind::Secret => objects.push((desired.kind, name, desired.contents)),
+                Kind::PriorityClass => {
+                    let name = name.strip_prefix(&format!("{}/", priority::KIND)).unwrap_or(&name).to_string();
+                    objects.push((desired.kind, name, desired.contents));
+                }
+                Kind::Service => {
+                    let name = name.strip_prefix(&format!("{}/", service::KIND)).unwrap_or(&name).to_string();
+                    services.push((name, desired.contents));
+                }
+            }
+        }
+        self.store_objects(objects);
+        self.services.update(services);
+        let mut desired = pods;
+        // a standby node keeps the workloads without their pods
+        if self.leader.as_ref().is_some_and(|leader| !leader.leading(Instant::now())) {
+            if !deployments.is_empty() || !jobs.is_empty() || !cronjobs.is_empty() {
+                debug!("Not leading, the workloads run on another node");
+            }
+            return desired;
+        }
+
+        let available: HashSet<String> = self.pods
+            .iter()
+            .filter(|(name, _)| self.pod_available(name))
+            .map(|(name, _)| name.clone())
+            .collect();
+        let records: HashMap<String, PodRecord> = self.pods
+            .iter()
+            .map(|(name, pod)| {
+                let status = self.pod_status(name, pod);
+                let restarts = status.containers.iter().map(|c| c.restart_count).sum();
+                (name.clone(), PodRecord { phase: status.phase, restarts })
+            })
+            .collect();
+        let names: HashSet<String> = jobs.iter().map(|(name, _, _)| name.clone()).collect();
+        for (manifest, name, contents) in self.cronjobs.reconcile(cronjobs, &self.jobs, node::unix_now()) {
+            if names.contains(&name) {
+                warn!("Ignoring Job {} of {}: it is already defined by another manifest", name, manifest.display());
+                continue;
+            }
+            jobs.push((name, manifest, contents));
+        }
+        let mut workload_pods = self.deployments.reconcile(deployments, &available);
+        workload_pods.extend(self.jobs.reconcile(jobs, &records));
+        for (manifest, name, contents) in workload_pods {
+            if let Some(other) = desired.get(&name) {
+                warn!("Ignoring Pod {} of {}: it is already defined by {}", name, manifest.display(), other.manifest.display());
+                continue;
+            }
+            match Desired::parse(manifest, contents) {
+                Ok((_, pod)) => {
+                    desired.insert(name, pod);
+                }
+                Err(e) => warn!("Invalid manifest of Pod {}: {}", name, e),
+            }
+        }
+        desired
+    }
+
+    fn store_objects(&mut self, objects: Vec<(Kind, String, String)>) {
+        let mut stored = HashSet::new();
+        for (kind, name, contents) in objects {
+            let (kind_name, saved) = match kind {
+                Kind::Secret => (secret::KIND, Secret::parse(&contents).and_then(|secret| secret.save(&self.root_path))),
+                Kind::PriorityClass => {
+                    (priority::KIND, PriorityClass::parse(&contents).and_then(|class| class.save(&self.root_path)))
+                }
+                _ => (configmap::KIND, ConfigMap::parse(&contents).and_then(|config_map| config_map.save(&self.root_path))),
+            };
+            match saved {
+                Ok(true) => info!("{} {} stored", kind_name, name),
+                Ok(false) => {}
+                Err(e) => warn!("Failed to store {} {}: {}", kind_name, name, e),
+            }
+            stored.insert(format!("{}/{}", kind_name, name));
+        }
+        let mut removed: Vec<&String> = self.objects.difference(&stored).collect();
+        removed.sort();
+        for object in removed {
+            let Some((kind_name, name)) = object.split_once('/') else {
+                continue;
+            };
+            info!("Manifest of {} {} removed, deleting it", kind_name, name);
+            let removed = match kind_name {
+                secret::KIND => secret::remove(&self.root_path, name),
+                priority::KIND => priority::remove(&self.root_path, name),
+                _ => configmap::remove(&self.root_path, name),
+            };
+            if let Err(e) = removed {
+                warn!("Failed to delete {} {}: {}", kind_name, name, e);
+            }
+        }
+        if stored != self.objects {
+            let mut lines: Vec<&String> = stored.iter().collect();
+            lines.sort();
+            let contents: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+            let path = objects_path(&self.root_path);
+            let written = path.parent().map_or(Ok(()), fs::create_dir_all).and_then(|_| fs::write(&path, contents));
+            if let Err(e) = written {
+                warn!("Failed to record the config maps and secrets: {}", e);
+            }
+            self.objects = stored;
+        }
+    }
+
+    // run the readiness probes that are due of the running containers
+    fn probe_pods(&mut self) {
+        let now = Instant::now();
+        let names: Vec<String> = self.pods.iter().filter(|(_, pod)| pod.created).map(|(name, _)| name.clone()).collect();
+        for name in names {
+            let (Ok(task), Ok(pod_info)) = (applied_task(&self.root_path, &name), PodInfo::load(&self.root_path, &name)) else {
+                continue;
+            };
+            let ip = if task.spec.host_network {
+                Some(IpAddr::V4(Ipv4Addr::LOCALHOST))
+            } else {
+                cni::pod_ip(&self.root_path, &pod_info.pod_sandbox_id).and_then(|ip| ip.parse().ok())
+            };
+            let Some(pod) = self.pods.get_mut(&name) else {
+                continue;
+            };
+            for container in task.spec.running_containers() {
+                let running = load_container(&self.root_path, &container.name)
+                    .is_ok_and(|c| c.status() == RuntimeStatus::Running);
+                let Some(record) = pod.containers.get_mut(&container.name) else {
+                    continue;
+                };
+                if !running {
+                    record.readiness = Readiness::default();
+                    continue;
+                }
+                let Some(probe) = &container.readiness_probe else {
+                    record.readiness.ready = true;
+                    continue;
+                };
+                let (Some(ip), Some(started)) = (ip, record.started_at) else {
+                    continue;
+                };
+                if !record.readiness.due(probe, started, now) || !record.readiness.record(probe, probe.run(ip), now) {
+                    continue;
+                }
+                if record.readiness.ready {
+                    info!("Container {} of static Pod {} is ready", container.name, name);
+                } else {
+                    let reason = record.readiness.reason.clone().unwrap_or_default();
+                    let message = format!("Readiness probe of container {} failed: {}", container.name, reason);
+                    let _ = events::record(&self.root_path, &name, EventType::Warning, events::UNHEALTHY, &message);
+                }
+            }
+        }
+    }
+
+    // the created pods with their labels, IPs and readiness, for the endpoints
+    // of the services
+    fn pod_views(&self) -> Vec<PodView> {
+        let mut views = Vec::new();
+        for (name, pod) in self.pods.iter().filter(|(_, pod)| pod.created) {
+            let (Ok(task), Ok(pod_info)) = (applied_task(&self.root_path, name), PodInfo::load(&self.root_path, name)) else {
+                continue;
+            };
+            let ready = self.pod_available(name)
+                && task.spec.running_containers().all(|container| {
+                    pod.containers.get(&container.name).is_some_and(|record| record.readiness.ready)
+                });
+            views.push(PodView {
+                name: name.clone(),
+                namespace: task.metadata.namespace,
+                labels: task.metadata.labels,
+                ip: cni::pod_ip(&self.root_path, &pod_info.pod_sandbox_id).and_then(|ip| ip.parse().ok()),
+                ready,
+            });
+        }
+        views
+    }
+
+    // the images of the containers of every pod, which image garbage collection keeps
+    pub fn images(&self) -> Vec<String> {
+        self.pods
+            .keys()
+            .filter_map(|name| applied_task(&self.root_path, name).ok())
+            .flat_map(|task| task.spec.init_containers.into_iter().chain(task.spec.sidecars).chain(task.spec.containers).map(|container| container.image))
+            .collect()
+    }
+
+    // whether every container of the pod is running
+    fn pod_available(&self, name: &str) -> bool {
+        let Some(pod) = self.pods.get(name) else {
+            return false;
+        };
+        pod.created
+            && !pod.containers.is_empty()
+            && pod.containers.keys().all(|container| {
+                load_container(&self.root_path, container).is_ok_and(|c| c.status() == RuntimeStatus::Running)
+            })
+    }
+
+    // the containers to recreate for the new manifest of a created pod, those
+    // whose hash changed, when nothing else but the labels and annotations of
+    // the pod changed; None when the whole pod has to be recreated. Only the
+    // built-in runtime recreates single containers.
+    fn changed_containers(&self, name: &str, contents: &str) -> Option<Vec<String>> {
+        let applied = fs::read_to_string(applied_manifest_path(&self.root_path, name)).ok()?;
+        if !apply::immutable_changed(&applied, contents) {
+            return Some(Vec::new());
+        }
+        if apply::pod_changed(&applied, contents) {
+            return None;
+        }
+        // the hashes are of the specs once admitted, like the containers were created
+        let (old, new) = (TaskRunner::from_manifest(&applied).ok()?, TaskRunner::from_manifest(contents).ok()?);
+        let backend = new.backend.as_deref();
+        let changed: Vec<String> = old.task.spec
+            .running_containers()
+            .zip(new.task.spec.running_containers())
+            .filter(|(previous, container)| {
+                let running = task::running_container_hash(backend, &self.root_path, &previous.name);
+                running.unwrap_or_else(|| task::container_hash(previous)) != task::container_hash(container)
+            })
+            .map(|(_, container)| container.name.clone())
+            .collect();
+        if !changed.is_empty() && new.backend.is_some() {
+            return None;
+        }
+        Some(changed)
+    }
+
+    // update a created pod in place for its new manifest, recreating the
+    // containers whose spec changed in its sandbox
+    fn update_pod(&self, name: &str, pod: &mut StaticPod, contents: &str, changed: &[String]) {
+        let applied = applied_manifest_path(&self.root_path, name);
+        let pod_info = if changed.is_empty() {
+            info!("Metadata of static Pod {} changed, updating it in place", name);
+            None
+        } else {
+            let previous = fs::read_to_string(&applied).unwrap_or_default();
+            info!(
+                "Containers {} of static Pod {} changed, recreating them:\n{}",
+                changed.join(", "),
+                name,
+                apply::diff(&previous, contents).join("\n")
+            );
+            // the containers stop the way they were created, e.g. with their preStop hook
+            let pod_info = PodInfo::load(&self.root_path, name).ok();
+            if let (Some(pod_info), Ok(runner)) = (&pod_info, TaskRunner::from_manifest(&previous)) {
+                runner.terminate(&pod_info.pod_sandbox_id, changed, None);
+            }
+            pod_info
+        };
+        if let Err(e) = fs::write(&applied, contents) {
+            warn!("Failed to record the manifest of static Pod {}: {}", name, e);
+        }
+        // recreated containers are recorded with the images they were pinned to
+        let task = match pod_info {
+            Some(_) => TaskRunner::from_manifest(contents).map(|runner| runner.task),
+            None => serde_yaml::from_str::<PodTask>(contents).map_err(anyhow::Error::from),
+        };
+        if let Ok(task) = task
+            && let Err(e) = describe::save_spec(&self.root_path, &task)
+        {
+            warn!("Failed to record the spec of static Pod {}: {}", name, e);
+        }
+        let Some(pod_info) = pod_info else {
+            return;
+        };
+        let now = Instant::now();
+        for container_name in changed {
+            let result = TaskRunner::from_file(&applied.display().to_string())
+                .and_then(|mut runner| runner.restart_container(&pod_info.pod_sandbox_id, container_name));
+            // like the kubelet, a container recreated for its spec counts as restarted
+            let record = pod.containers.entry(container_name.clone()).or_default();
+            record.restart_count += 1;
+            record.readiness = Readiness::default();
+            record.pid = None;
+            match result {
+                Ok(()) => {
+                    let status = ContainerStatus::new(container_name, ContainerState::Running);
+                    if let Err(e) = PodInfo::update_status(&self.root_path, name, status) {
+                        warn!("Failed to record the status of container {}: {}", container_name, e);
+                    }
+                    record.started_at = Some(now);
+                    record.backoff.reset();
+                    record.pid = load_container(&self.root_path, container_name).ok().and_then(|c| c.pid()).map(|p| p.as_raw());
+                    info!("Container {} of static Pod {} recreated", container_name, name);
+                }
+                Err(e) => {
+                    warn!("Failed to recreate container {} of static Pod {}: {}", container_name, name, e);
+                    record.backoff.fail(now);
+                    pod.last_error = Some(e.to_string());
+                }
+            }
+        }
+    }
+
+    // create the pod of a new or changed manifest, replacing the previous one
+    fn replace_pod(&mut self, name: &str, desired: Desired) {
+        let Desired { manifest, contents, hash, restart_policy, .. } = desired;
+        let changed = match self.pods.get(name) {
+            Some(pod) if pod.created && pod.hash != hash => self.changed_containers(name, &contents),
+            _ => None,
+        };
+        let pod = match (self.pods.remove(name), changed) {
+            (Some(pod), _) if pod.hash == hash => pod,
+            // only the labels or annotations or the specs of some containers
+            // changed, e.g. by `rkl apply`
+            (Some(mut pod), Some(changed)) => {
+                self.update_pod(name, &mut pod, &contents, &changed);
+                pod.hash = hash;
+                pod.manifest = manifest;
+                pod.restart_policy = restart_policy;
+                self.pods.insert(name.to_string(), pod);
+                return;
+            }
+            (Some(mut pod), None) => {
+                if pod.created {
+                    let applied = fs::read_to_string(applied_manifest_path(&self.root_path, name)).unwrap_or_default();
+                    info!("Manifest of static Pod {} changed, recreating it:\n{}", name, apply::diff(&applied, &contents).join("\n"));
+                    if let Err(e) = cli_commands::delete_pod(name) {
+                        warn!("Failed to delete static Pod {}: {}", name, e);
+                        pod.last_error = Some(e.to_string());
+                        self.pods.insert(name.to_string(), pod);
+                        return;
+                    }
+                }
+                StaticPod { manifest, hash, restart_policy, ..Self::pending_pod() }
+            }
+            (None, _) => match PodInfo::load(&self.root_path, name) {
+                // recorded by `rkl adopt` or restored over the control API, the
+                // pod keeps running as it is
+                Ok(pod_info) if adopted(&contents) => {
+                    info!("Adopting Pod {} and its containers", name);
+                    let mut pod = StaticPod { manifest, hash, restart_policy, created: true, ..Self::pending_pod() };
+                    self.record_containers(&mut pod, &pod_info);
+                    let applied = applied_manifest_path(&self.root_path, name);
+                    let written = applied.parent().map_or(Ok(()), fs::create_dir_all).and_then(|_| fs::write(&applied, contents));
+                    if let Err(e) = written {
+                        warn!("Failed to record the manifest of static Pod {}: {}", name, e);
+                    }
+                    self.pods.insert(name.to_string(), pod);
+                    return;
+                }
+                Ok(_) => {
+                    warn!("Ignoring static Pod {}: a Pod with that name already exists", name);
+                    return;
+                }
+                Err(_) => StaticPod { manifest, hash, restart_policy, ..Self::pending_pod() },
+            },
+        };
+
+        let applied = applied_manifest_path(&self.root_path, name);
+        let written = applied.parent().map_or(Ok(()), fs::create_dir_all).and_then(|_| fs::write(&applied, contents));
+        if let Err(e) = written {
+            warn!("Failed to record the manifest of static Pod {}: {}", name, e);
+        }
+        self.start_pod(name, pod);
+    }
+
+    // run a pod from its applied manifest, unless it is backing off
+    fn start_pod(&mut self, name: &str, mut pod: StaticPod) {
+        let now = Instant::now();
+        if !pod.backoff.ready(now) {
+            self.pods.insert(name.to_string(), pod);
+            return;
+        }
+        let mut rejection = self.rejection(name);
+        if let Ok(Some((reason, _))) = &rejection
+            && [events::OUT_OF_CPU, events::OUT_OF_MEMORY].contains(reason)
+            && self.preempt(name)
+        {
+            rejection = self.rejection(name);
+        }
+        if let Ok(Some((reason, message))) = rejection {
+            warn!("Pod {} was rejected: {}", name, message);
+            let _ = events::record(&self.root_path, name, EventType::Warning, reason, &message);
+            pod.last_error = Some(format!("Pod was rejected: {}", message));
+            pod.backoff.fail(now);
+            self.pods.insert(name.to_string(), pod);
+            return;
+        }
+        let applied = applied_manifest_path(&self.root_path, name);
+        match cli_commands::run_pod(&applied.display().to_string(), FailurePolicy::Rollback, &Values::default())
+            .and_then(|_| PodInfo::load(&self.root_path, name))
+        {
+            Ok(pod_info) => {
+                pod.created = true;
+                pod.last_error = None;
+                pod.backoff.reset();
+                self.record_containers(&mut pod, &pod_info);
+            }
+            Err(e) => {
+                error!("Failed to run static Pod {}: {}", name, e);
+                pod.last_error = Some(e.to_string());
+                pod.backoff.fail(now);
+            }
+        }
+        self.pods.insert(name.to_string(), pod);
+    }
+
+    // why the pod can't run on this node, as the reason and message of the
+    // event; the node configuration is read every time so that `rkl node label`
+    // and `rkl node taint` apply without restarting the daemon
+    fn rejection(&self, name: &str) -> Result<Option<(&'static str, String)>> {
+        let task = applied_task(&self.root_path, name)?;
+        let labels = node::labels(&self.node_name)?;
+        let unmatched = node::unmatched_selector(&labels, &task.spec.effective_node_selector());
+        if !unmatched.is_empty() {
+            let message = format!("node didn't match Pod's node selector ({})", unmatched.join(", "));
+            return Ok(Some((events::NODE_AFFINITY, message)));
+        }
+        if let Err(e) = priority::resolve(&self.root_path, &task.spec) {
+            return Ok(Some((events::FAILED, e.to_string())));
+        }
+        if let Some(pressure) = self.pressures.iter().find(|pressure| !pressure.admits(qos::qos_class(&task.spec))) {
+            let message = format!("the node had condition: [{}]", pressure);
+            return Ok(Some((events::EVICTED, message)));
+        }
+        let taints = node::taints()?;
+        // a NoExecute taint tolerated for a while only admits the pod until it expires
+        let evicted = node::eviction(&taints, &task.spec.tolerations).filter(|(at, _)| *at <= node::unix_now());
+        let untolerated = node::untolerated(&taints, &task.spec.tolerations, &[node::NO_SCHEDULE]);
+        if let Some(taint) = evicted.map(|(_, taint)| taint).or(untolerated.first().copied()) {
+            let message = format!("node had untolerated taint {{{}}}", taint);
+            return Ok(Some((events::TAINT_TOLERATION, message)));
+        }
+        let requested = resources::requests(&task.spec);
+        if requested == resources::Resources::default() {
+            return Ok(None);
+        }
+        Ok(resources::insufficient(resources::allocatable()?, self.requested_resources(name), requested, self.limits.overcommit_ratio))
+    }
+
+    // the requests of the pods running on the node, but the one being admitted
+    fn requested_resources(&self, except: &str) -> resources::Resources {
+        let mut used = resources::Resources::default();
+        for (name, pod) in &self.pods {
+            if pod.created
+                && name != except
+                && let Ok(task) = applied_task(&self.root_path, name)
+            {
+                used += resources::requests(&task.spec);
+            }
+        }
+        used
+    }
+
+    // the priority of a pod manifest, 0 when it can't be resolved
+    fn priority(&self, contents: &str) -> i32 {
+        serde_yaml::from_str::<PodTask>(contents)
+            .ok()
+            .and_then(|task| priority::resolve(&self.root_path, &task.spec).ok())
+            .map_or(0, |(priority, _)| priority)
+    }
+
+    // delete running pods of lower priority to make room for the pod, see
+    // preemption; returns whether any was deleted
+    fn preempt(&mut self, name: &str) -> bool {
+        let Ok(task) = applied_task(&self.root_path, name) else {
+            return false;
+        };
+        let Ok((priority, true)) = priority::resolve(&self.root_path, &task.spec) else {
+            return false;
+        };
+        let mut used = resources::Resources::default();
+        let mut candidates = Vec::new();
+        for (other, pod) in &self.pods {
+            if !pod.created || other == name {
+                continue;
+            }
+            let Ok(task) = applied_task(&self.root_path, other) else {
+                continue;
+            };
+            let requests = resources::requests(&task.spec);
+            let other_priority = priority::resolve(&self.root_path, &task.spec).map_or(0, |(priority, _)| priority);
+            if other_priority < priority {
+                candidates.push(Victim { name: other.clone(), priority: other_priority, requests });
+            } else {
+                used += requests;
+            }
+        }
+        let Ok(allocatable) = resources::allocatable() else {
+            return false;
+        };
+        let requested = resources::requests(&task.spec);
+        let Some(victims) = preemption::victims(allocatable, used, requested, &candidates, self.limits.overcommit_ratio) else {
+            return false;
+        };
+        let mut preempted = false;
+        for victim in victims {
+            let message = format!("Preempted by Pod {} on node {}", name, self.node_name);
+            info!("Pod {} (priority {}): {}", victim.name, victim.priority, message);
+            if let Err(e) = cli_commands::delete_pod(&victim.name) {
+                warn!("Failed to preempt Pod {}: {}", victim.name, e);
+                continue;
+            }
+            let _ = events::record(&self.root_path, &victim.name, EventType::Normal, events::PREEMPTED, &message);
+            if let Some(pod) = self.pods.get_mut(&victim.name) {
+                pod.created = false;
+                pod.containers.clear();
+                pod.last_error = Some(format!("Pod was preempted by Pod {}", name));
+                pod.backoff.fail(Instant::now());
+            }
+            preempted = true;
+        }
+        preempted
+    }
+
+    // delete the running pods that don't tolerate a NoExecute taint of the node
+    // (anymore), they are created again once the taint is gone
+    fn evict_pods(&mut self) {
+        let taints = match node::taints() {
+            Ok(taints) => taints,
+            Err(e) => {
+                warn!("Failed to read the node taints: {}", e);
+                return;
+            }
+        };
+        if !taints.iter().any(|taint| taint.effect == node::NO_EXECUTE) {
+            return;
+        }
+        let now = node::unix_now();
+        let mut names: Vec<String> = self.pods.iter().filter(|(_, pod)| pod.created).map(|(name, _)| name.clone()).collect();
+        names.sort();
+        for name in names {
+            let Ok(task) = applied_task(&self.root_path, &name) else {
+                continue;
+            };
+            let Some((at, taint)) = node::eviction(&taints, &task.spec.tolerations) else {
+                continue;
+            };
+            if now < at {
+                continue;
+            }
+            let message = format!("Marking for deletion Pod {}: node has taint {{{}}}", name, taint);
+            info!("{}", message);
+            let _ = events::record(&self.root_path, &name, EventType::Normal, events::TAINT_MANAGER_EVICTION, &message);
+            if let Err(e) = cli_commands::delete_pod(&name) {
+                warn!("Failed to evict Pod {}: {}", name, e);
+                continue;
+            }
+            if let Some(pod) = self.pods.get_mut(&name) {
+                pod.created = false;
+                pod.containers.clear();
+                pod.last_error = Some(format!("Pod was evicted: node had taint {{{}}}", taint));
+            }
+        }
+    }
+
+    // evict a pod when the node is low on memory or disk, see eviction
+    fn evict_for_pressure(&mut self) {
+        self.pressures = match eviction::pressures(&self.limits.eviction_hard, &self.root_path) {
+            Ok(pressures) => pressures,
+            Err(e) => {
+                warn!("Failed to check the node for pressure: {}", e);
+                return;
+            }
+        };
+        let Some(pressure) = self.pressures.first().copied() else {
+            return;
+        };
+        let backend = runtime::backend().ok().flatten();
+        let mut candidates = Vec::new();
+        let mut names: Vec<String> = self.pods.iter().filter(|(_, pod)| pod.created).map(|(name, _)| name.clone()).collect();
+        names.sort();
+        for name in names {
+            let (Ok(task), Ok(info)) = (applied_task(&self.root_path, &name), PodInfo::load(&self.root_path, &name)) else {
+                continue;
+            };
+            let usages = self.sampler.sample(&self.root_path, backend.as_deref(), &info.pod_sandbox_id, &info.container_names);
+            let usage = usages
+                .iter()
+                .map(|(_, usage)| if pressure == Pressure::Memory { usage.memory_bytes } else { usage.fs_bytes })
+                .sum();
+            let request = match pressure {
+                Pressure::Memory => resources::requests(&task.spec).memory_bytes.max(0) as u64,
+                Pressure::Disk => 0,
+            };
+            candidates.push(Candidate { name, class: qos::qos_class(&task.spec), usage, request });
+        }
+        let Some(candidate) = eviction::choose(&candidates).cloned() else {
+            return;
+        };
+
+        let message = format!(
+            "The node was low on resource: {}. Pod {} ({}) was using {} bytes, which exceeds its request of {}.",
+            pressure.resource(), candidate.name, candidate.class, candidate.usage, candidate.request
+        );
+        warn!("Evicting Pod {}: {}", candidate.name, message);
+        let _ = events::record(&self.root_path, &candidate.name, EventType::Warning, events::EVICTED, &message);
+        if let Err(e) = cli_commands::delete_pod(&candidate.name) {
+            warn!("Failed to evict Pod {}: {}", candidate.name, e);
+            return;
+        }
+        if let Some(pod) = self.pods.get_mut(&candidate.name) {
+            pod.created = false;
+            pod.containers.clear();
+            pod.last_error = Some(format!("Pod was evicted: the node was low on {}", pressure.resource()));
+            pod.backoff.fail(Instant::now());
+        }
+    }
+
+    fn pending_pod() -> StaticPod {
+        StaticPod {
+            manifest: PathBuf::new(),
+            hash: String::new(),
+            restart_policy: RestartPolicy::default(),
+            containers: HashMap::new(),
+            created: false,
+            last_error: None,
+            backoff: Backoff::default(),
+        }
+    }
+
+    // restart the exited containers of a pod, or the whole pod when its sandbox is gone
+    fn check_pod(&mut self, name: &str) {
+        let Some(mut pod) = self.pods.remove(name) else {
+            return;
+        };
+        if !pod.created {
+            return self.start_pod(name, pod);
+        }
+
+        let pod_info = match PodInfo::load(&self.root_path, name) {
+            Ok(pod_info) => pod_info,
+            Err(e) => {
+                pod.last_error = Some(e.to_string());
+                pod.created = false;
+                self.pods.insert(name.to_string(), pod);
+                return;
+            }
+        };
+        let sandbox_running = load_container(&self.root_path, &pod_info.pod_sandbox_id)
+            .is_ok_and(|sandbox| sandbox.status() == RuntimeStatus::Running);
+        if !sandbox_running {
+            let message = "Pod sandbox changed, it will be killed and re-created.";
+            let _ = events::record(&self.root_path, name, EventType::Normal, events::SANDBOX_CHANGED, message);
+            if let Err(e) = cli_commands::delete_pod(name) {
+                warn!("Failed to delete static Pod {}: {}", name, e);
+            }
+            pod.created = false;
+            pod.containers.clear();
+            return self.start_pod(name, pod);
+        }
+
+        let now = Instant::now();
+        // sidecars are restarted whatever the restart policy of the pod
+        let sidecars: Vec<String> = applied_task(&self.root_path, name)
+            .map(|task| task.spec.sidecars().map(|sidecar| sidecar.name.clone()).collect())
+            .unwrap_or_default();
+        for container_name in &pod_info.container_names {
+            let status = load_container(&self.root_path, container_name).map(|c| c.status());
+            let record = pod.containers.entry(container_name.clone()).or_default();
+            if matches!(status, Ok(RuntimeStatus::Running)) {
+                if record.started_at.is_some_and(|started| now.duration_since(started) >= BACKOFF_RESET) {
+                    record.backoff.reset();
+                }
+                continue;
+            }
+            if matches!(status, Ok(RuntimeStatus::Created) | Ok(RuntimeStatus::Creating) | Ok(RuntimeStatus::Paused)) {
+                continue;
+            }
+
+            // stopped, or removed behind the daemon's back
+            if let Some(code) = record.pid.and_then(|pid| self.exits.remove(&pid)) {
+                record.last_exit_code = Some(code);
+                self.record_termination(name, &pod_info.pod_sandbox_id, container_name, code);
+            }
+            record.pid = None;
+            let restart_policy = if sidecars.contains(container_name) { RestartPolicy::Always } else { pod.restart_policy };
+            if !restart_policy.should_restart(record.last_exit_code) || !record.backoff.ready(now) {
+                continue;
+            }
+
+            let applied = applied_manifest_path(&self.root_path, name);
+            let result = TaskRunner::from_file(&applied.display().to_string())
+                .and_then(|mut runner| runner.restart_container(&pod_info.pod_sandbox_id, container_name));
+            match result {
+                Ok(()) => {
+                    // the termination stays as the last state of the container
+                    let last_state = PodInfo::load(&self.root_path, name)
+                        .ok()
+                        .and_then(|info| info.container_statuses.into_iter().find(|s| &s.name == container_name))
+                        .filter(|status| matches!(status.state, ContainerState::Terminated(_)));
+                    let mut status = ContainerStatus::new(container_name, ContainerState::Running);
+                    status.reason = last_state.map(|last| format!("last state {}", last));
+                    if let Err(e) = PodInfo::update_status(&self.root_path, name, status) {
+                        warn!("Failed to record the status of container {}: {}", container_name, e);
+                    }
+                    record.restart_count += 1;
+                    metrics::CONTAINER_RESTARTS.inc();
+                    record.started_at = Some(now);
+                    record.readiness = Readiness::default();
+                    record.pid = load_container(&self.root_path, container_name)
+                        .ok()
+                        .and_then(|c| c.pid())
+                        .map(|p| p.as_raw());
+                    // the next exit of the container waits before it is restarted
+                    record.backoff.fail(now);
+                    info!("Container {} of static Pod {} restarted ({} restarts)", container_name, name, record.restart_count);
+                }
+                Err(e) => {
+                    let message = format!("Back-off restarting failed container {}: {}", container_name, e);
+                    let _ = events::record(&self.root_path, name, EventType::Warning, events::BACK_OFF, &message);
+                    record.backoff.fail(now);
+                }
+            }
+        }
+        self.pods.insert(name.to_string(), pod);
+    }
+
+    // record why a container of a static pod exited in its pod file
+    fn record_termination(&self, name: &str, pod_sandbox_id: &str, container_name: &str, exit_code: i32) {
+        let Ok(task) = applied_task(&self.root_path, name) else {
+            return;
+        };
+        let Some(container) = task.spec.container(container_name) else {
+            return;
+        };
+        let message = termination::message(&self.root_path, name, pod_sandbox_id, container, Some(exit_code));
+        if let Some(message) = &message {
+            info!("Container {} of static Pod {} exited with {}: {}", container_name, name, exit_code, message);
+        }
+        let status = ContainerStatus::terminated(container_name, Some(exit_code), message.as_deref());
+        if let Err(e) = PodInfo::update_status(&self.root_path, name, status) {
+            warn!("Failed to record the termination of container {}: {}", container_name, e);
+        }
+    }
+
+    fn publish(&self) {
+        let mut statuses: Vec<PodStatus> = self.pods
+            .iter()
+            .map(|(name, pod)| self.pod_status(name, pod))
+            .collect();
+        statuses.sort_by(|a, b| a.name.cmp(&b.name));
+        *self.status.lock().unwrap() = statuses;
+    }
+
+    fn pod_status(&self, name: &str, pod: &StaticPod) -> PodStatus {
+        let sandbox_id = pod.created
+            .then(|| PodInfo::load(&self.root_path, name).ok().map(|info| info.pod_sandbox_id))
+            .flatten();
+        let mut containers: Vec<ContainerStatusView> = pod.containers
+            .iter()
+            .map(|(container_name, record)| ContainerStatusView {
+                name: container_name.clone(),
+                state: load_container(&self.root_path, container_name)
+                    .map(|c| c.status().to_string())
+                    .unwrap_or_else(|_| "Unknown".to_string()),
+                restart_count: record.restart_count,
+                last_exit_code: record.last_exit_code,
+            })
+            .collect();
+        containers.sort_by(|a, b| a.name.cmp(&b.name));
+
+        PodStatus {
+            name: name.to_string(),
+            manifest: pod.manifest.display().to_string(),
+            pod_ip: sandbox_id.as_deref().and_then(|id| cni::pod_ip(&self.root_path, id)),
+            sandbox_id,
+            phase: pod_phase(pod.created, pod.restart_policy, &containers).to_string(),
+            last_error: pod.last_error.clone(),
+            containers,
+        }
+    }
+}
+
+fn adopted(contents: &str) -> bool {
+    serde_yaml::from_str::<PodTask>(contents).is_ok_and(|task| {
+        [adopt::ADOPTED_FROM, checkpoint::RESTORED_FROM].iter().any(|key| task.metadata.annotations.contains_key(*key))
+    })
+}
+
+// every manifest of a directory, failing when one of them is invalid
+// manifests are yaml files, hidden ones are the temporary files of editors and
+// of writers that rename them into place
+pub fn is_manifest_name(name: &str) -> bool {
+    !name.starts_with('.') && (name.ends_with(".yaml") || name.ends_with(".yml"))
+}
+
+// a manifest of the directory written less than SETTLE ago, which may still be
+// written to
+fn unsettled(dir: &Path, now: SystemTime) -> Option<PathBuf> {
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| is_manifest_name(&entry.file_name().to_string_lossy()))
+        .find(|entry| {
+            let modified = entry.metadata().and_then(|metadata| metadata.modified());
+            modified.is_ok_and(|modified| now.duration_since(modified).is_ok_and(|age| age < SETTLE))
+        })
+        .map(|entry| entry.path())
+}
+
+fn scan_dir(dir: &Path) -> Result<HashMap<String, Desired>> {
+    let mut manifests: HashMap<String, Desired> = HashMap::new();
+    if !dir.exists() {
+        return Ok(manifests);
+    }
+    let rules = IgnoreRules::load(dir)?;
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.file_name().is_some_and(|name| is_manifest_name(&name.to_string_lossy()) && !rules.is_ignored(&name.to_string_lossy()))
+        })
+        .collect();
+    paths.sort();
+    for path in paths {
+        let contents = fs::read_to_string(&path).map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+        let (name, desired) =
+            Desired::parse(path.clone(), contents).map_err(|e| anyhow!("invalid manifest {}: {}", path.display(), e))?;
+        if let Some(other) = manifests.get(&name) {
+            return Err(anyhow!("{} is defined by both {} and {}", name, other.manifest.display(), path.display()));
+        }
+        manifests.insert(name, desired);
+    }
+    Ok(manifests)
+}
+
+fn pod_phase(created: bool, restart_policy: RestartPolicy, containers: &[ContainerStatusView]) -> &'static str {
+    if !created || containers.is_empty() {
+        return "Pending";
+    }
+    // containers waiting to be restarted keep the pod running
+    if containers.iter().any(|c| c.state == "Running" || restart_policy.should_restart(c.last_exit_code)) {
+        return "Running";
+    }
+    if containers.iter().all(|c| c.last_exit_code == Some(0)) {
+        return "Succeeded";
+    }
+    "Failed"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::daemon::proxy::ProxyMode;
+
+    #[test]
+    fn test_backoff_doubles_up_to_max() {
+        let now = Instant::now();
+        let mut backoff = Backoff::default();
+        assert!(backoff.ready(now));
+
+        backoff.fail(now);
+        assert!(!backoff.ready(now));
+        assert!(backoff.ready(now + INITIAL_BACKOFF));
+        for _ in 0..10 {
+            backoff.fail(now);
+        }
+        assert_eq!(backoff.delay, MAX_BACKOFF);
+
+        backoff.reset();
+        assert!(backoff.ready(now));
+    }
+
+    #[test]
+    fn test_restart_policy() {
+        assert!(RestartPolicy::Always.should_restart(Some(0)));
+        assert!(RestartPolicy::OnFailure.should_restart(Some(1)));
+        assert!(RestartPolicy::OnFailure.should_restart(None));
+        assert!(!RestartPolicy::OnFailure.should_restart(Some(0)));
+        assert!(!RestartPolicy::Never.should_restart(Some(1)));
+    }
+
+    #[test]
+    fn test_directory_applied_all_or_nothing() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("manifests");
+        fs::create_dir_all(&dir).unwrap();
+        let pod = |name: &str, image: &str| {
+            format!("apiVersion: v1\nkind: Pod\nmetadata:\n  name: {}\nspec:\n  containers:\n    - name: {}-app\n      image: {}\n", name, name, image)
+        };
+        fs::write(dir.join("web.yaml"), pod("web", "app:v1")).unwrap();
+        fs::write(dir.join("notes.draft.yaml"), "not a pod").unwrap();
+        fs::write(dir.join(".rklignore"), "*.draft.yaml\n").unwrap();
+
+        let status = Arc::new(Mutex::new(Vec::new()));
+        let services = ServiceController::load(root.path(), service::DEFAULT_SERVICE_CIDR.parse().unwrap(), ProxyMode::None).unwrap();
+        let mut manager = PodManager::load(root.path(), "node-1", vec![dir.clone()], services, None, status, NodeLimits::default()).unwrap();
+        std::thread::sleep(SETTLE);
+        let desired = manager.scan_manifests();
+        assert_eq!(desired.len(), 1);
+        let applied_hash = desired["web"].hash.clone();
+
+        // an invalid manifest holds back every change of the directory
+        fs::write(dir.join("web.yaml"), pod("web", "app:v2")).unwrap();
+        fs::write(dir.join("db.yaml"), "kind: [").unwrap();
+        std::thread::sleep(SETTLE);
+        let desired = manager.scan_manifests();
+        assert_eq!(desired.len(), 1);
+        assert_eq!(desired["web"].hash, applied_hash);
+
+        // nor is one being written, or a hidden one
+        fs::write(dir.join("db.yaml"), pod("db", "db:v1")).unwrap();
+        fs::write(dir.join(".web.yaml"), "kind: [").unwrap();
+        let desired = manager.scan_manifests();
+        assert_eq!(desired.len(), 1);
+        std::thread::sleep(SETTLE);
+        let desired = manager.scan_manifests();
+        assert_eq!(desired.len(), 2);
+        assert_ne!(desired["web"].hash, applied_hash);
+    }
+}
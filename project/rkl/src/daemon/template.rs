@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use serde_yaml::{Mapping, Value};
+use sha2::{Digest, Sha256};
+use crate::task::task::PodSpec;
+
+// Pod templates of the workloads of the daemon (Deployments, Jobs) and the pod
+// manifests made of them. A template is kept as written so that the pods get
+// exactly the fields it has, only the part the daemon looks at is parsed.
+
+#[derive(Deserialize)]
+pub struct PodTemplate {
+    #[serde(default)]
+    pub metadata: TemplateMeta,
+    pub spec: PodSpec,
+}
+
+#[derive(Default, Deserialize)]
+pub struct TemplateMeta {
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+}
+
+impl PodTemplate {
+    pub fn parse(template: &Value) -> Result<Self> {
+        let parsed: PodTemplate =
+            serde_yaml::from_value(template.clone()).map_err(|e| anyhow!("invalid pod template: {}", e))?;
+        if parsed.spec.containers.is_empty() {
+            return Err(anyhow!("the pod template has no containers"));
+        }
+        Ok(parsed)
+    }
+}
+
+// a short hash identifying the template
+pub fn hash(template: &Value) -> String {
+    let template = serde_yaml::to_string(template).unwrap_or_default();
+    format!("{:x}", Sha256::digest(template.as_bytes()))[..10].to_string()
+}
+
+// <prefix>-<5 random characters>, like the pods of a ReplicaSet or Job
+pub fn pod_name(prefix: &str) -> String {
+    format!("{}-{}", prefix, &uuid::Uuid::new_v4().simple().to_string()[..5])
+}
+
+// the manifest of a pod of the template with the extra labels; container
+// names are global, so each pod gets its own ones suffixed like the pod name
+pub fn pod_manifest(template: &Value, namespace: &str, name: &str, labels: &[(&str, &str)]) -> Result<String> {
+    let suffix = name.rsplit('-').next().unwrap_or(name);
+    let mut metadata = template.get("metadata").cloned().unwrap_or(Value::Null);
+    metadata["name"] = Value::from(name);
+    metadata["namespace"] = Value::from(namespace);
+    for (key, value) in labels {
+        metadata["labels"][*key] = Value::from(*value);
+    }
+    let mut spec = template.get("spec").cloned().ok_or_else(|| anyhow!("the pod template has no spec"))?;
+    for key in ["containers", "init_containers", "initContainers"] {
+        let Some(containers) = spec.get_mut(key).and_then(Value::as_sequence_mut) else {
+            continue;
+        };
+        for container in containers {
+            if let Some(container_name) = container.get("name").and_then(Value::as_str) {
+                container["name"] = Value::from(format!("{}-{}", container_name, suffix));
+            }
+        }
+    }
+    let mut pod = Mapping::new();
+    pod.insert("apiVersion".into(), "v1".into());
+    pod.insert("kind".into(), "Pod".into());
+    pod.insert("metadata".into(), metadata);
+    pod.insert("spec".into(), spec);
+    Ok(serde_yaml::to_string(&pod)?)
+}
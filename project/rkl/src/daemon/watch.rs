@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use anyhow::{Result, anyhow};
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, InotifyEvent, WatchDescriptor};
+use crate::daemon::SyncTrigger;
+use crate::daemon::ignore::IGNORE_FILE;
+use crate::daemon::sync;
+use tracing::{debug, info, warn};
+
+// Hot reload of the manifest directories: they are watched with inotify and
+// the sync loop runs as soon as a manifest is written, moved in or out or
+// removed, instead of at the next --sync-interval. New manifests then create
+// their pods, changed ones recreate them (see sync.rs) and removed ones delete
+// them. Changes are debounced, the sync runs once the directories were quiet
+// for DEBOUNCE, so that a burst of them (a `cp` of several manifests, an editor
+// writing a backup before the file) is applied at once. Only finished writes
+// count: a manifest is seen when it is closed or renamed into the directory, and
+// the sync leaves a directory as it was while one of its manifests was written
+// less than sync::SETTLE ago, for writers that keep their file open. A watched
+// directory that is removed is watched again once it is back. The sync still
+// runs every --sync-interval, for filesystems inotify doesn't see changes of.
+
+pub const DEBOUNCE: Duration = Duration::from_millis(300);
+// how often a removed directory is looked for
+const RETRY: Duration = Duration::from_secs(5);
+
+fn flags() -> AddWatchFlags {
+    AddWatchFlags::IN_CLOSE_WRITE
+        | AddWatchFlags::IN_MOVED_TO
+        | AddWatchFlags::IN_MOVED_FROM
+        | AddWatchFlags::IN_DELETE
+        | AddWatchFlags::IN_DELETE_SELF
+        | AddWatchFlags::IN_MOVE_SELF
+}
+
+// whether an event changes what the sync reads of a directory
+fn relevant(mask: AddWatchFlags, name: Option<&OsStr>) -> bool {
+    if mask.intersects(AddWatchFlags::IN_DELETE_SELF | AddWatchFlags::IN_MOVE_SELF | AddWatchFlags::IN_IGNORED) {
+        return true;
+    }
+    name.and_then(OsStr::to_str).is_some_and(|name| name == IGNORE_FILE || sync::is_manifest_name(name))
+}
+
+// what the watcher thread hears from the reader thread
+enum Change {
+    // a manifest changed
+    Manifest,
+    // the directory of the watch is gone
+    Gone(WatchDescriptor),
+}
+
+fn changes(event: &InotifyEvent) -> Vec<Change> {
+    let mut changes = Vec::new();
+    if relevant(event.mask, event.name.as_deref()) {
+        changes.push(Change::Manifest);
+    }
+    if event.mask.contains(AddWatchFlags::IN_IGNORED) {
+        changes.push(Change::Gone(event.wd));
+    }
+    changes
+}
+
+// when to sync after a burst of changes
+#[derive(Debug, Default)]
+struct Debouncer {
+    last_change: Option<Instant>,
+}
+
+impl Debouncer {
+    fn change(&mut self, now: Instant) {
+        self.last_change = Some(now);
+    }
+
+    // how long until the sync is due, None while nothing changed
+    fn remaining(&self, now: Instant) -> Option<Duration> {
+        self.last_change.map(|last| DEBOUNCE.saturating_sub(now.duration_since(last)))
+    }
+
+    // whether the sync is due, forgetting the changes when it is
+    fn due(&mut self, now: Instant) -> bool {
+        if self.remaining(now) == Some(Duration::ZERO) {
+            self.last_change = None;
+            return true;
+        }
+        false
+    }
+}
+
+struct Watcher {
+    inotify: Arc<Inotify>,
+    dirs: Vec<PathBuf>,
+    watches: HashMap<WatchDescriptor, PathBuf>,
+}
+
+impl Watcher {
+    // watch the directories that aren't, returning whether one was added
+    fn add_missing(&mut self) -> bool {
+        let mut added = false;
+        for dir in &self.dirs {
+            if self.watches.values().any(|watched| watched == dir) || !dir.is_dir() {
+                continue;
+            }
+            match self.inotify.add_watch(dir, flags()) {
+                Ok(wd) => {
+                    debug!("Watching manifest directory {}", dir.display());
+                    self.watches.insert(wd, dir.clone());
+                    added = true;
+                }
+                Err(e) => warn!("Failed to watch manifest directory {}: {}", dir.display(), e),
+            }
+        }
+        added
+    }
+}
+
+// watch the manifest directories, notifying the trigger after every burst of changes
+pub fn start(dirs: Vec<PathBuf>, trigger: Arc<SyncTrigger>) -> Result<()> {
+    let inotify = Arc::new(Inotify::init(InitFlags::IN_CLOEXEC).map_err(|e| anyhow!("Failed to initialize inotify: {}", e))?);
+    for dir in &dirs {
+        // the directory of the control API only appears with its first pod
+        if let Err(e) = fs::create_dir_all(dir) {
+            warn!("Failed to create manifest directory {}: {}", dir.display(), e);
+        }
+    }
+    let mut watcher = Watcher { inotify: inotify.clone(), dirs, watches: HashMap::new() };
+    watcher.add_missing();
+    info!("Watching {} manifest directories for changes", watcher.watches.len());
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        loop {
+            match inotify.read_events() {
+                Ok(events) => {
+                    for change in events.iter().flat_map(changes) {
+                        if sender.send(change).is_err() {
+                            return;
+                        }
+                    }
+                }
+                Err(e) => {
+                    warn!("Failed to read the changes of the manifest directories: {}", e);
+                    thread::sleep(RETRY);
+                }
+            }
+        }
+    });
+    thread::spawn(move || {
+        let mut debouncer = Debouncer::default();
+        loop {
+            let now = Instant::now();
+            let timeout = match debouncer.remaining(now) {
+                Some(remaining) => remaining,
+                None if watcher.watches.len() < watcher.dirs.len() => RETRY,
+                None => Duration::MAX,
+            };
+            match receiver.recv_timeout(timeout) {
+                Ok(Change::Manifest) => debouncer.change(Instant::now()),
+                Ok(Change::Gone(wd)) => {
+                    if let Some(dir) = watcher.watches.remove(&wd) {
+                        warn!("Manifest directory {} is gone, watching for it to come back", dir.display());
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+            // a directory that is back may hold new manifests
+            if watcher.watches.len() < watcher.dirs.len() && watcher.add_missing() {
+                debouncer.change(Instant::now());
+            }
+            if debouncer.due(Instant::now()) {
+                debug!("Manifest directories changed, syncing");
+                trigger.notify();
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watch() {
+        assert!(relevant(AddWatchFlags::IN_CLOSE_WRITE, Some(OsStr::new("web.yaml"))));
+        assert!(relevant(AddWatchFlags::IN_DELETE, Some(OsStr::new(IGNORE_FILE))));
+        assert!(!relevant(AddWatchFlags::IN_CLOSE_WRITE, Some(OsStr::new(".web.yaml.swp"))));
+        assert!(!relevant(AddWatchFlags::IN_MOVED_TO, Some(OsStr::new(".web.yaml"))));
+        assert!(relevant(AddWatchFlags::IN_DELETE_SELF, None));
+
+        let mut debouncer = Debouncer::default();
+        let now = Instant::now();
+        assert!(!debouncer.due(now));
+        debouncer.change(now);
+        debouncer.change(now + DEBOUNCE / 2);
+        assert!(!debouncer.due(now + DEBOUNCE));
+        assert!(debouncer.due(now + DEBOUNCE * 2));
+        assert_eq!(debouncer.remaining(now + DEBOUNCE * 2), None);
+
+        // a manifest written into a watched directory runs the sync
+        let dir = tempfile::tempdir().unwrap();
+        let trigger = Arc::new(SyncTrigger::default());
+        start(vec![dir.path().to_path_buf()], trigger.clone()).unwrap();
+        fs::write(dir.path().join("web.yaml"), "kind: Pod\n").unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !*trigger.pending.lock().unwrap() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert!(*trigger.pending.lock().unwrap());
+    }
+}
@@ -0,0 +1,449 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use anyhow::Result;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Paragraph, Row, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+use crate::cli_commands::{self, PodInfo};
+use crate::describe::{BuiltinState, CriState, StateSource};
+use crate::rootpath;
+use crate::runtime::{self, RuntimeBackend};
+use crate::stats::{self, Usage, UsageSampler};
+use crate::task::logs;
+
+// `rkl dashboard`: the pods of this node in a terminal UI, with the containers
+// of the selected pod, the log of the selected container and what they use,
+// refreshed every --interval from where `rkl state`, `rkl top` and the logs
+// read them. The keys:
+//
+//   up/down or k/j   select a pod or a container   tab   switch between them
+//   d                delete the pod, y confirms     r     restart the container
+//   e                run a command in the container, its output replaces the log
+//   q or esc         quit
+//
+// Deleting, restarting and exec go through the functions of `rkl delete`, the
+// restart policy of the daemon and ExecSync. The logs of rkl itself still go
+// to stderr, redirect it (2>dashboard.log) to keep them off the screen.
+
+// the lines of a log or an output shown
+const LOG_LINES: usize = 200;
+const EXEC_TIMEOUT: Duration = Duration::from_secs(30);
+const KEYS: &str = "up/down select  tab switch  d delete  r restart  e exec  q quit";
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct ContainerView {
+    name: String,
+    state: String,
+    usage: Usage,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct PodView {
+    name: String,
+    state: String,
+    containers: Vec<ContainerView>,
+}
+
+impl PodView {
+    fn usage(&self) -> Usage {
+        let mut total = Usage::default();
+        for container in &self.containers {
+            total += container.usage;
+        }
+        total
+    }
+
+    fn ready(&self) -> String {
+        let running = self.containers.iter().filter(|container| container.state == "Running").count();
+        format!("{}/{}", running, self.containers.len())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum Focus {
+    #[default]
+    Pods,
+    Containers,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+enum Mode {
+    #[default]
+    Normal,
+    ConfirmDelete,
+    // the command typed so far
+    Command(String),
+}
+
+// what a key asks for besides moving around
+#[derive(Debug, Clone, PartialEq)]
+enum Action {
+    Quit,
+    Delete(String),
+    Restart(String, String),
+    Exec(String, String, Vec<String>),
+}
+
+impl Action {
+    fn progress(&self) -> String {
+        match self {
+            Action::Quit => String::new(),
+            Action::Delete(pod) => format!("deleting pod {}...", pod),
+            Action::Restart(pod, container) => format!("restarting container {} of pod {}...", container, pod),
+            Action::Exec(_, container, command) => format!("running {} in container {}...", command.join(" "), container),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct App {
+    pods: Vec<PodView>,
+    pod: usize,
+    container: usize,
+    focus: Focus,
+    mode: Mode,
+    log: Vec<String>,
+    // the output of the last exec, shown instead of the log until the
+    // selection changes
+    output: Option<(String, Vec<String>)>,
+    message: String,
+}
+
+impl App {
+    fn selected_pod(&self) -> Option<&PodView> {
+        self.pods.get(self.pod)
+    }
+
+    fn selected_container(&self) -> Option<&ContainerView> {
+        self.selected_pod().and_then(|pod| pod.containers.get(self.container))
+    }
+
+    fn selected_names(&self) -> Option<(String, String)> {
+        Some((self.selected_pod()?.name.clone(), self.selected_container()?.name.clone()))
+    }
+
+    // the selection stays on its pod when pods come and go
+    fn update(&mut self, pods: Vec<PodView>) {
+        let selected = self.selected_pod().map(|pod| pod.name.clone());
+        self.pods = pods;
+        let last = self.pods.len().saturating_sub(1);
+        self.pod = selected.and_then(|name| self.pods.iter().position(|pod| pod.name == name)).unwrap_or(self.pod.min(last));
+        let containers = self.selected_pod().map_or(0, |pod| pod.containers.len());
+        self.container = self.container.min(containers.saturating_sub(1));
+    }
+
+    fn select(&mut self, down: bool) {
+        let (index, count) = match self.focus {
+            Focus::Pods => (&mut self.pod, self.pods.len()),
+            Focus::Containers => (&mut self.container, self.pods.get(self.pod).map_or(0, |pod| pod.containers.len())),
+        };
+        let next = if down { (*index + 1).min(count.saturating_sub(1)) } else { index.saturating_sub(1) };
+        if next == *index {
+            return;
+        }
+        *index = next;
+        if self.focus == Focus::Pods {
+            self.container = 0;
+        }
+        self.output = None;
+    }
+
+    fn on_key(&mut self, key: KeyEvent) -> Option<Action> {
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            return Some(Action::Quit);
+        }
+        match std::mem::take(&mut self.mode) {
+            Mode::ConfirmDelete => {
+                if key.code == KeyCode::Char('y') {
+                    return self.selected_pod().map(|pod| Action::Delete(pod.name.clone()));
+                }
+                self.message = "delete cancelled".to_string();
+                None
+            }
+            Mode::Command(mut input) => match key.code {
+                KeyCode::Esc => None,
+                KeyCode::Enter => {
+                    let command: Vec<String> = input.split_whitespace().map(str::to_string).collect();
+                    if command.is_empty() {
+                        return None;
+                    }
+                    self.selected_names().map(|(pod, container)| Action::Exec(pod, container, command))
+                }
+                code => {
+                    match code {
+                        KeyCode::Backspace => {
+                            input.pop();
+                        }
+                        KeyCode::Char(c) => input.push(c),
+                        _ => {}
+                    }
+                    self.mode = Mode::Command(input);
+                    None
+                }
+            },
+            Mode::Normal => {
+                self.message.clear();
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Some(Action::Quit),
+                    KeyCode::Up | KeyCode::Char('k') => self.select(false),
+                    KeyCode::Down | KeyCode::Char('j') => self.select(true),
+                    KeyCode::Tab => {
+                        self.focus = if self.focus == Focus::Pods { Focus::Containers } else { Focus::Pods };
+                    }
+                    KeyCode::Char('d') => {
+                        if let Some(name) = self.selected_pod().map(|pod| pod.name.clone()) {
+                            self.message = format!("delete pod {}? y to confirm", name);
+                            self.mode = Mode::ConfirmDelete;
+                        }
+                    }
+                    KeyCode::Char('r') => return self.selected_names().map(|(pod, container)| Action::Restart(pod, container)),
+                    KeyCode::Char('e') if self.selected_container().is_some() => self.mode = Mode::Command(String::new()),
+                    _ => {}
+                }
+                None
+            }
+        }
+    }
+}
+
+// the last lines of a text
+fn tail(text: &str, lines: usize) -> Vec<String> {
+    let all: Vec<&str> = text.lines().collect();
+    all[all.len().saturating_sub(lines)..].iter().map(|line| line.to_string()).collect()
+}
+
+// reads what the dashboard shows
+struct Source {
+    root_path: PathBuf,
+    backend: Option<Arc<dyn RuntimeBackend>>,
+    sampler: UsageSampler,
+}
+
+impl Source {
+    fn pods(&mut self) -> Vec<PodView> {
+        let Source { root_path, backend, sampler } = self;
+        let root_path = root_path.as_path();
+        let builtin = BuiltinState(root_path);
+        let cri = backend.as_deref().map(CriState);
+        let state: &dyn StateSource = match &cri {
+            Some(cri) => cri,
+            None => &builtin,
+        };
+        PodInfo::names(root_path)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|name| {
+                let info = PodInfo::load(root_path, &name).ok()?;
+                let usages = sampler.sample(root_path, backend.as_deref(), &info.pod_sandbox_id, &info.container_names);
+                let containers = info
+                    .container_names
+                    .iter()
+                    .map(|container| ContainerView {
+                        name: container.clone(),
+                        state: state.container_state(container),
+                        usage: usages.iter().find(|(name, _)| name == container).map(|(_, usage)| *usage).unwrap_or_default(),
+                    })
+                    .collect();
+                Some(PodView { state: state.sandbox_state(&info.pod_sandbox_id), name, containers })
+            })
+            .collect()
+    }
+}
+
+fn log(pod: &str, container: &str) -> Vec<String> {
+    match logs::read_container_log(pod, container) {
+        Ok(log) => tail(&String::from_utf8_lossy(&log), LOG_LINES),
+        Err(e) => vec![e.to_string()],
+    }
+}
+
+fn perform(app: &mut App, action: Action) {
+    app.message = match action {
+        Action::Quit => return,
+        Action::Delete(pod) => match cli_commands::remove_pod(&pod, None) {
+            Ok(()) => format!("pod {} deleted", pod),
+            Err(e) => format!("failed to delete pod {}: {}", pod, e),
+        },
+        Action::Restart(pod, container) => match cli_commands::restart_container(&pod, &container) {
+            Ok(()) => format!("container {} of pod {} restarted", container, pod),
+            Err(e) => format!("failed to restart container {}: {}", container, e),
+        },
+        Action::Exec(pod, container, command) => {
+            match cli_commands::exec_in_container(&pod, &container, command.clone(), EXEC_TIMEOUT) {
+                Ok((code, output)) => {
+                    let title = format!("{} in {} (exit code {})", command.join(" "), container, code);
+                    app.output = Some((title, tail(&String::from_utf8_lossy(&output), LOG_LINES)));
+                    String::new()
+                }
+                Err(e) => format!("failed to run {} in container {}: {}", command.join(" "), container, e),
+            }
+        }
+    };
+}
+
+fn block(title: String, focused: bool) -> Block<'static> {
+    let style = if focused { Style::new().fg(Color::Cyan) } else { Style::new() };
+    Block::bordered().title(title).border_style(style)
+}
+
+fn draw(frame: &mut Frame, app: &App) {
+    let [pods_area, middle, footer] = Layout::vertical([Constraint::Percentage(40), Constraint::Fill(1), Constraint::Length(1)]).areas(frame.area());
+    let [containers_area, log_area] = Layout::horizontal([Constraint::Percentage(45), Constraint::Fill(1)]).areas(middle);
+    let bold = Style::new().add_modifier(Modifier::BOLD);
+    let highlight = Style::new().add_modifier(Modifier::REVERSED);
+
+    let rows = app.pods.iter().map(|pod| {
+        let usage = pod.usage();
+        Row::new([pod.name.clone(), pod.state.clone(), pod.ready(), format!("{}m", usage.cpu_millis), stats::format_bytes(usage.memory_bytes)])
+    });
+    let widths = [Constraint::Fill(1), Constraint::Length(12), Constraint::Length(6), Constraint::Length(8), Constraint::Length(10)];
+    let pods = Table::new(rows, widths)
+        .header(Row::new(["NAME", "STATE", "READY", "CPU", "MEMORY"]).style(bold))
+        .block(block(format!("Pods ({})", app.pods.len()), app.focus == Focus::Pods))
+        .row_highlight_style(highlight);
+    let mut state = TableState::default().with_selected(app.selected_pod().map(|_| app.pod));
+    frame.render_stateful_widget(pods, pods_area, &mut state);
+
+    let containers: &[ContainerView] = app.selected_pod().map_or(&[][..], |pod| pod.containers.as_slice());
+    let rows = containers.iter().map(|container| {
+        let usage = container.usage;
+        Row::new([
+            container.name.clone(),
+            container.state.clone(),
+            format!("{}m", usage.cpu_millis),
+            stats::format_bytes(usage.memory_bytes),
+            stats::format_bytes(usage.fs_bytes),
+        ])
+    });
+    let widths = [Constraint::Fill(1), Constraint::Length(12), Constraint::Length(8), Constraint::Length(10), Constraint::Length(10)];
+    let title = app.selected_pod().map_or("Containers".to_string(), |pod| format!("Containers of {}", pod.name));
+    let table = Table::new(rows, widths)
+        .header(Row::new(["NAME", "STATE", "CPU", "MEMORY", "FS"]).style(bold))
+        .block(block(title, app.focus == Focus::Containers))
+        .row_highlight_style(highlight);
+    let mut state = TableState::default().with_selected(app.selected_container().map(|_| app.container));
+    frame.render_stateful_widget(table, containers_area, &mut state);
+
+    let (title, lines) = match (&app.output, app.selected_names()) {
+        (Some((title, output)), _) => (title.clone(), output),
+        (None, Some((pod, container))) => (format!("Log of {}/{}", pod, container), &app.log),
+        (None, None) => ("Log".to_string(), &app.log),
+    };
+    // the end of the log, like tail -f
+    let scroll = lines.len().saturating_sub(log_area.height.saturating_sub(2) as usize);
+    let log = Paragraph::new(lines.iter().map(|line| Line::raw(line.as_str())).collect::<Vec<_>>())
+        .block(block(title, false))
+        .scroll((scroll.min(u16::MAX as usize) as u16, 0));
+    frame.render_widget(log, log_area);
+
+    let status = match &app.mode {
+        Mode::Command(input) => format!("exec> {}", input),
+        _ if !app.message.is_empty() => app.message.clone(),
+        _ => KEYS.to_string(),
+    };
+    frame.render_widget(Paragraph::new(status), footer);
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, source: &mut Source, interval: Duration) -> Result<()> {
+    let mut app = App::default();
+    let mut refreshed: Option<Instant> = None;
+    loop {
+        if refreshed.is_none_or(|at| at.elapsed() >= interval) {
+            app.update(source.pods());
+            app.log = app.selected_names().map(|(pod, container)| log(&pod, &container)).unwrap_or_default();
+            refreshed = Some(Instant::now());
+        }
+        terminal.draw(|frame| draw(frame, &app))?;
+        let waited = refreshed.map_or(Duration::ZERO, |at| at.elapsed());
+        if !event::poll(interval.saturating_sub(waited))? {
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        let selection = (app.pod, app.container);
+        match app.on_key(key) {
+            None => {}
+            Some(Action::Quit) => return Ok(()),
+            Some(action) => {
+                app.message = action.progress();
+                terminal.draw(|frame| draw(frame, &app))?;
+                perform(&mut app, action);
+                refreshed = None;
+            }
+        }
+        // the log of the newly selected container right away
+        if (app.pod, app.container) != selection {
+            refreshed = None;
+        }
+    }
+}
+
+pub fn run(interval: Duration) -> Result<()> {
+    let mut source = Source { root_path: rootpath::determine(None)?, backend: runtime::backend()?, sampler: UsageSampler::default() };
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &mut source, interval);
+    ratatui::restore();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn pod(name: &str, containers: &[&str]) -> PodView {
+        let containers = containers
+            .iter()
+            .map(|name| ContainerView { name: name.to_string(), state: "Running".to_string(), ..Default::default() })
+            .collect();
+        PodView { name: name.to_string(), state: "Ready".to_string(), containers }
+    }
+
+    #[test]
+    fn test_keys() {
+        let mut app = App::default();
+        app.update(vec![pod("db", &["postgres"]), pod("web", &["app", "proxy"])]);
+        assert_eq!(app.on_key(key(KeyCode::Char('j'))), None);
+        assert_eq!(app.on_key(key(KeyCode::Tab)), None);
+        app.on_key(key(KeyCode::Down));
+        app.on_key(key(KeyCode::Down));
+        assert_eq!(app.selected_names(), Some(("web".to_string(), "proxy".to_string())));
+        assert_eq!(app.selected_pod().unwrap().ready(), "2/2");
+
+        // the selection follows its pod
+        app.update(vec![pod("api", &["server"]), pod("db", &["postgres"]), pod("web", &["app", "proxy"])]);
+        assert_eq!(app.selected_names(), Some(("web".to_string(), "proxy".to_string())));
+        app.update(vec![pod("api", &["server"])]);
+        assert_eq!(app.selected_names(), Some(("api".to_string(), "server".to_string())));
+
+        assert_eq!(app.on_key(key(KeyCode::Char('r'))), Some(Action::Restart("api".to_string(), "server".to_string())));
+        app.on_key(key(KeyCode::Char('e')));
+        for c in "cat /etc/hosts".chars() {
+            app.on_key(key(KeyCode::Char(c)));
+        }
+        app.on_key(key(KeyCode::Backspace));
+        let command = vec!["cat".to_string(), "/etc/host".to_string()];
+        assert_eq!(app.on_key(key(KeyCode::Enter)), Some(Action::Exec("api".to_string(), "server".to_string(), command)));
+
+        // delete only once confirmed
+        app.on_key(key(KeyCode::Char('d')));
+        assert_eq!(app.on_key(key(KeyCode::Char('n'))), None);
+        assert_eq!(app.mode, Mode::Normal);
+        app.on_key(key(KeyCode::Char('d')));
+        assert_eq!(app.on_key(key(KeyCode::Char('y'))), Some(Action::Delete("api".to_string())));
+        assert_eq!(app.on_key(key(KeyCode::Char('q'))), Some(Action::Quit));
+
+        assert_eq!(tail("a\nb\nc\n", 2), ["b", "c"]);
+    }
+}
@@ -0,0 +1,347 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+use crate::cli_commands::PodInfo;
+use crate::commands::load_container;
+use crate::cri::cri::{ContainerState, ContainerStatusRequest, PodSandboxState, PodSandboxStatusRequest};
+use crate::image::ImageReference;
+use crate::quantity::Quantity;
+use crate::runtime::RuntimeBackend;
+use crate::task::qos;
+use crate::task::task::{ContainerSpec, EnvVar, EnvVarSource, PodTask, Volume};
+
+// The report of `rkl describe pod`, like the one of kubectl describe: the spec
+// the pod was run with, kept in <root>/specs/<pod name>.yaml when it is run,
+// next to the state of its sandbox and containers as the runtime reports it
+// right now, the IP of the pod and the status rkl recorded. The events and the
+// resource usage are added by cli_commands::describe_pod. Pods run before the
+// spec was kept only show what is known about them.
+
+fn spec_path(root_path: &Path, pod_name: &str) -> PathBuf {
+    root_path.join("specs").join(format!("{}.yaml", pod_name))
+}
+
+pub fn save_spec(root_path: &Path, task: &PodTask) -> Result<()> {
+    let path = spec_path(root_path, &task.metadata.name);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_yaml::to_string(task)?)?;
+    Ok(())
+}
+
+pub fn load_spec(root_path: &Path, pod_name: &str) -> Option<PodTask> {
+    let contents = fs::read_to_string(spec_path(root_path, pod_name)).ok()?;
+    serde_yaml::from_str(&contents).ok()
+}
+
+pub fn remove_spec(root_path: &Path, pod_name: &str) -> Result<()> {
+    let path = spec_path(root_path, pod_name);
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+// the live state of a sandbox or container
+pub trait StateSource {
+    fn sandbox_state(&self, sandbox_id: &str) -> String;
+    fn container_state(&self, container_id: &str) -> String;
+}
+
+// the state of the containers of the built-in runtime
+pub struct BuiltinState<'a>(pub &'a Path);
+
+impl BuiltinState<'_> {
+    fn state(&self, id: &str) -> String {
+        match load_container(self.0, id) {
+            Ok(container) => container.status().to_string(),
+            Err(_) => "Unknown".to_string(),
+        }
+    }
+}
+
+impl StateSource for BuiltinState<'_> {
+    fn sandbox_state(&self, sandbox_id: &str) -> String {
+        self.state(sandbox_id)
+    }
+
+    fn container_state(&self, container_id: &str) -> String {
+        self.state(container_id)
+    }
+}
+
+// the state as a CRI runtime reports it
+pub struct CriState<'a>(pub &'a dyn RuntimeBackend);
+
+impl StateSource for CriState<'_> {
+    fn sandbox_state(&self, sandbox_id: &str) -> String {
+        let request = PodSandboxStatusRequest { pod_sandbox_id: sandbox_id.to_string(), verbose: false };
+        match self.0.pod_sandbox_status(request).map(|response| response.status) {
+            Ok(Some(status)) => match PodSandboxState::try_from(status.state) {
+                Ok(PodSandboxState::SandboxReady) => "Ready".to_string(),
+                _ => "NotReady".to_string(),
+            },
+            _ => "Unknown".to_string(),
+        }
+    }
+
+    fn container_state(&self, container_id: &str) -> String {
+        let request = ContainerStatusRequest { container_id: container_id.to_string(), verbose: false };
+        let Ok(Some(status)) = self.0.container_status(request).map(|response| response.status) else {
+            return "Unknown".to_string();
+        };
+        match ContainerState::try_from(status.state) {
+            Ok(ContainerState::ContainerCreated) => "Created".to_string(),
+            Ok(ContainerState::ContainerRunning) => "Running".to_string(),
+            Ok(ContainerState::ContainerExited) if status.reason.is_empty() => format!("Exited ({})", status.exit_code),
+            Ok(ContainerState::ContainerExited) => format!("Exited ({}): {}", status.exit_code, status.reason),
+            _ => "Unknown".to_string(),
+        }
+    }
+}
+
+fn map_lines(lines: &mut Vec<String>, title: &str, map: &HashMap<String, String>) {
+    if map.is_empty() {
+        lines.push(format!("{:<16}<none>", title));
+        return;
+    }
+    let mut entries: Vec<_> = map.iter().collect();
+    entries.sort();
+    for (i, (key, value)) in entries.into_iter().enumerate() {
+        let title = if i == 0 { title } else { "" };
+        lines.push(format!("{:<16}{}={}", title, key, value));
+    }
+}
+
+fn quantity_lines(lines: &mut Vec<String>, title: &str, quantities: &HashMap<String, Quantity>) {
+    if quantities.is_empty() {
+        return;
+    }
+    lines.push(format!("    {}:", title));
+    let mut entries: Vec<_> = quantities.iter().collect();
+    entries.sort_by_key(|(name, _)| name.as_str());
+    for (name, quantity) in entries {
+        lines.push(format!("      {}:  {}", name, quantity));
+    }
+}
+
+// the value of an env var, the references to secrets and fields instead of their values
+fn env_value(env: &EnvVar) -> String {
+    match &env.value_from {
+        Some(EnvVarSource { secret_key_ref: Some(selector), .. }) => {
+            format!("<set to the key '{}' in secret '{}'>", selector.key, selector.name)
+        }
+        Some(EnvVarSource { field_ref: Some(field), .. }) => format!("({})", field.field_path),
+        _ => env.value.clone(),
+    }
+}
+
+fn container_lines(lines: &mut Vec<String>, container: &ContainerSpec) {
+    lines.push(format!("    Image:        {}", container.image));
+    // pinned at admission or given by digest
+    if let Ok(reference) = ImageReference::parse(&container.image)
+        && reference.digest.is_some()
+    {
+        lines.push(format!("    Image ID:     {}", reference.pull_name()));
+    }
+    if !container.ports.is_empty() {
+        let ports: Vec<String> = container
+            .ports
+            .iter()
+            .map(|port| match port.host_port {
+                0 => format!("{}/{}", port.container_port, port.protocol),
+                host_port => format!("{}/{} (host {})", port.container_port, port.protocol, host_port),
+            })
+            .collect();
+        lines.push(format!("    Ports:        {}", ports.join(", ")));
+    }
+    if !container.args.is_empty() {
+        lines.push(format!("    Args:         {}", container.args.join(" ")));
+    }
+    quantity_lines(lines, "Limits", &container.resources.limits);
+    quantity_lines(lines, "Requests", &container.resources.requests);
+    if !container.env.is_empty() || !container.env_from.is_empty() {
+        lines.push("    Environment:".to_string());
+        for source in &container.env_from {
+            if let Some(config_map) = &source.config_map_ref {
+                lines.push(format!("      ConfigMap {}  prefix '{}'", config_map.name, source.prefix));
+            }
+        }
+        for env in &container.env {
+            lines.push(format!("      {}:  {}", env.name, env_value(env)));
+        }
+    }
+    if !container.volume_mounts.is_empty() {
+        lines.push("    Mounts:".to_string());
+        for mount in &container.volume_mounts {
+            lines.push(format!("      {} from {} (ro)", mount.mount_path, mount.name));
+        }
+    }
+}
+
+fn volume_lines(lines: &mut Vec<String>, volume: &Volume) {
+    lines.push(format!("  {}:", volume.name));
+    if let Some(config_map) = &volume.config_map {
+        lines.push("    Type:  ConfigMap".to_string());
+        lines.push(format!("    Name:  {}", config_map.name));
+    } else if let Some(secret) = &volume.secret {
+        lines.push("    Type:  Secret".to_string());
+        lines.push(format!("    Name:  {}", secret.secret_name));
+    } else if volume.downward_api.is_some() {
+        lines.push("    Type:  DownwardAPI".to_string());
+    }
+}
+
+// every section of the report but the events and the usage
+pub fn pod_report(pod_name: &str, pod_info: &PodInfo, task: Option<&PodTask>, ip: Option<&str>, state: &dyn StateSource) -> Vec<String> {
+    let mut lines = vec![format!("Name:           {}", pod_name)];
+    if let Some(task) = task {
+        lines.push(format!("Namespace:      {}", task.metadata.namespace));
+        map_lines(&mut lines, "Labels:", &task.metadata.labels);
+        map_lines(&mut lines, "Annotations:", &task.metadata.annotations);
+    }
+    let sandbox_id = &pod_info.pod_sandbox_id;
+    lines.push(format!("Sandbox:        {} ({})", sandbox_id, state.sandbox_state(sandbox_id)));
+    lines.push(format!("IP:             {}", ip.unwrap_or("<none>")));
+    if let Some(task) = task {
+        lines.push(format!("Restart:        {:?}", task.spec.restart_policy));
+        lines.push(format!("QoS Class:      {}", qos::qos_class(&task.spec)));
+        if let Some(class) = &task.spec.priority_class_name {
+            lines.push(format!("Priority Class: {}", class));
+        } else if let Some(priority) = task.spec.priority {
+            lines.push(format!("Priority:       {}", priority));
+        }
+        if let Some(class) = &task.spec.runtime_class_name {
+            lines.push(format!("Runtime:        {}", class));
+        }
+        if task.spec.host_network {
+            lines.push("Network:        host".to_string());
+        }
+    }
+
+    lines.push("Containers:".to_string());
+    for container_name in &pod_info.container_names {
+        lines.push(format!("  {}:", container_name));
+        lines.push(format!("    State:        {}", state.container_state(container_name)));
+        if let Some(status) = pod_info.container_statuses.iter().find(|s| &s.name == container_name) {
+            lines.push(format!("    Status:       {}", status));
+        }
+        let spec = task.and_then(|task| task.spec.container(container_name));
+        if let Some(container) = spec {
+            container_lines(&mut lines, container);
+        }
+    }
+
+    if let Some(task) = task {
+        if task.spec.volumes.is_empty() {
+            lines.push("Volumes:        <none>".to_string());
+        } else {
+            lines.push("Volumes:".to_string());
+            for volume in &task.spec.volumes {
+                volume_lines(&mut lines, volume);
+            }
+        }
+        map_lines(&mut lines, "Node-Selectors:", &task.spec.node_selector);
+        if task.spec.tolerations.is_empty() {
+            lines.push(format!("{:<16}<none>", "Tolerations:"));
+        }
+        for (i, toleration) in task.spec.tolerations.iter().enumerate() {
+            let title = if i == 0 { "Tolerations:" } else { "" };
+            let operator = toleration.operator.as_deref().unwrap_or("Equal");
+            let value = if operator == "Exists" { String::new() } else { format!("={}", toleration.value) };
+            let effect = if toleration.effect.is_empty() { String::new() } else { format!(":{}", toleration.effect) };
+            lines.push(format!("{:<16}{}{}{} op={}", title, toleration.key, value, effect, operator));
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::task::ContainerStatus;
+
+    struct Fixed;
+
+    impl StateSource for Fixed {
+        fn sandbox_state(&self, _sandbox_id: &str) -> String {
+            "Ready".to_string()
+        }
+
+        fn container_state(&self, _container_id: &str) -> String {
+            "Running".to_string()
+        }
+    }
+
+    #[test]
+    fn test_pod_report() {
+        let task: PodTask = serde_yaml::from_str(
+            "
+apiVersion: v1
+kind: Pod
+metadata:
+  name: web
+  labels:
+    app: web
+spec:
+  containers:
+  - name: nginx
+    image: nginx:1.27
+    ports:
+    - containerPort: 80
+    resources:
+      limits:
+        memory: 128Mi
+        cpu: 500m
+    env:
+    - name: MODE
+      value: prod
+    - name: PASSWORD
+      valueFrom:
+        secretKeyRef:
+          name: db
+          key: password
+    volumeMounts:
+    - name: config
+      mountPath: /etc/nginx/conf.d
+  volumes:
+  - name: config
+    configMap:
+      name: nginx-config
+",
+        )
+        .unwrap();
+        let root = tempfile::tempdir().unwrap();
+        save_spec(root.path(), &task).unwrap();
+        let task = load_spec(root.path(), "web").unwrap();
+        let pod_info = PodInfo {
+            pod_sandbox_id: "web".to_string(),
+            container_names: vec!["nginx".to_string()],
+            container_statuses: vec![ContainerStatus::new("nginx", crate::task::task::ContainerState::Running)],
+        };
+        let report = pod_report("web", &pod_info, Some(&task), Some("10.88.0.2"), &Fixed).join("\n");
+        for expected in [
+            "Namespace:      default",
+            "Labels:         app=web",
+            "Sandbox:        web (Ready)",
+            "IP:             10.88.0.2",
+            "    State:        Running",
+            "    Ports:        80/TCP",
+            "    Limits:\n      cpu:  500m\n      memory:  128Mi",
+            "      PASSWORD:  <set to the key 'password' in secret 'db'>",
+            "      /etc/nginx/conf.d from config (ro)",
+            "  config:\n    Type:  ConfigMap\n    Name:  nginx-config",
+        ] {
+            assert!(report.contains(expected), "{} not in\n{}", expected, report);
+        }
+
+        // without a spec only what is known about the pod
+        let report = pod_report("web", &pod_info, None, None, &Fixed).join("\n");
+        assert!(report.contains("IP:             <none>"));
+        assert!(!report.contains("Image:"));
+        remove_spec(root.path(), "web").unwrap();
+        assert!(load_spec(root.path(), "web").is_none());
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use anyhow::Result;
+use crate::device::{ContainerAllocation, Device, DevicePlugin, TopologyHintProvider};
+use crate::task::cpumanager::parse_cpuset;
+
+pub const RESOURCE_NAME: &str = "nvidia.com/gpu";
+// environment variable read by the NVIDIA container toolkit
+pub const VISIBLE_DEVICES_ENV: &str = "NVIDIA_VISIBLE_DEVICES";
+// driver nodes every GPU container needs besides its /dev/nvidia<minor>
+const CONTROL_DEVICES: [&str; 3] = ["/dev/nvidiactl", "/dev/nvidia-uvm", "/dev/nvidia-uvm-tools"];
+
+pub struct NvidiaGpuPlugin;
+
+impl DevicePlugin for NvidiaGpuPlugin {
+    fn resource_name(&self) -> &str {
+        RESOURCE_NAME
+    }
+
+    fn discover(&self) -> Result<Vec<Device>> {
+        discover()
+    }
+
+    fn topology_providers(&self) -> Vec<Box<dyn TopologyHintProvider>> {
+        match NvLinkProvider::from_nvidia_smi() {
+            Some(nvlink) => vec![Box::new(nvlink)],
+            None => Vec::new(),
+        }
+    }
+
+    fn allocation(&self, devices: &[Device]) -> ContainerAllocation {
+        let ids: Vec<&str> = devices.iter().map(|d| d.id.as_str()).collect();
+        let mut device_paths: Vec<String> = ids.iter().map(|id| format!("/dev/nvidia{}", id)).collect();
+        device_paths.extend(
+            CONTROL_DEVICES
+                .iter()
+                .filter(|path| Path::new(path).exists())
+                .map(|path| path.to_string()),
+        );
+        ContainerAllocation {
+            envs: vec![(VISIBLE_DEVICES_ENV.to_string(), ids.join(","))],
+            device_paths,
+        }
+    }
+}
+
+// find the NVIDIA GPUs of the node. The PCIe location comes from the driver's
+// /proc entries and the NUMA node and local CPUs from sysfs
+pub fn discover() -> Result<Vec<Device>> {
+    let mut devices = Vec::new();
+    let gpus_dir = Path::new("/proc/driver/nvidia/gpus");
+    if gpus_dir.exists() {
+        for entry in fs::read_dir(gpus_dir)? {
+            let entry = entry?;
+            let bus_id = entry.file_name().to_string_lossy().to_lowercase();
+            let information = fs::read_to_string(entry.path().join("information")).unwrap_or_default();
+            let Some(minor) = information
+                .lines()
+                .find_map(|line| line.strip_prefix("Device Minor:"))
+                .map(|minor| minor.trim().to_string())
+            else {
+                continue;
+            };
+            let pci_dir = Path::new("/sys/bus/pci/devices").join(&bus_id);
+            devices.push(Device {
+                id: minor,
+                resource: RESOURCE_NAME.to_string(),
+                numa_node: fs::read_to_string(pci_dir.join("numa_node"))
+                    .ok()
+                    .and_then(|node| node.trim().parse().ok()),
+                local_cpus: fs::read_to_string(pci_dir.join("local_cpulist"))
+                    .ok()
+                    .and_then(|list| parse_cpuset(&list).ok())
+                    .unwrap_or_default(),
+                pci_bus_id: Some(bus_id),
+            });
+        }
+    } else if Path::new("/dev").exists() {
+        // no driver information, fall back to the device nodes without topology
+        for entry in fs::read_dir("/dev")? {
+            let name = entry?.file_name().to_string_lossy().to_string();
+            if let Some(minor) = name.strip_prefix("nvidia").filter(|m| m.chars().all(|c| c.is_ascii_digit()) && !m.is_empty()) {
+                devices.push(Device {
+                    id: minor.to_string(),
+                    resource: RESOURCE_NAME.to_string(),
+                    pci_bus_id: None,
+                    numa_node: None,
+                    local_cpus: Vec::new(),
+                });
+            }
+        }
+    }
+    devices.sort_by_key(|d| d.id.parse::<u32>().unwrap_or(u32::MAX));
+    Ok(devices)
+}
+
+// prefers GPUs connected to each other with NVLink, weighted by the number of links
+pub struct NvLinkProvider {
+    // (gpu, gpu) -> number of NVLinks between them
+    links: HashMap<(String, String), u32>,
+}
+
+impl NvLinkProvider {
+    pub fn from_nvidia_smi() -> Option<Self> {
+        let output = Command::new("nvidia-smi").args(["topo", "-m"]).output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        Some(Self::parse_topology(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    // parse the matrix printed by `nvidia-smi topo -m`, where NV<n> marks n NVLinks
+    pub fn parse_topology(matrix: &str) -> Self {
+        let mut links = HashMap::new();
+        let mut lines = matrix.lines().filter(|l| !l.trim().is_empty());
+        let header: Vec<String> = match lines.next() {
+            Some(header) => header
+                .split_whitespace()
+                .take_while(|column| column.starts_with("GPU"))
+                .map(|column| column.trim_start_matches("GPU").to_string())
+                .collect(),
+            None => return NvLinkProvider { links },
+        };
+
+        for line in lines {
+            let mut fields = line.split_whitespace();
+            let Some(row) = fields.next().and_then(|f| f.strip_prefix("GPU")) else {
+                continue;
+            };
+            for (column, field) in header.iter().zip(fields) {
+                if let Some(count) = field.strip_prefix("NV").and_then(|n| n.parse::<u32>().ok()) {
+                    links.insert((row.to_string(), column.clone()), count);
+                }
+            }
+        }
+        NvLinkProvider { links }
+    }
+}
+
+impl TopologyHintProvider for NvLinkProvider {
+    fn score(&self, devices: &[&Device], _pinned_cpus: &[u32]) -> i64 {
+        let mut score = 0;
+        for (i, a) in devices.iter().enumerate() {
+            for b in &devices[i + 1..] {
+                let links = self.links.get(&(a.id.clone(), b.id.clone())).copied().unwrap_or(0);
+                score += i64::from(links) * 10;
+            }
+        }
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nvlink_topology() {
+        let provider = NvLinkProvider::parse_topology(
+            "\tGPU0\tGPU1\tGPU2\tCPU Affinity\tNUMA Affinity\n\
+             GPU0\t X \tNV2\tSYS\t0-15\t0\n\
+             GPU1\tNV2\t X \tSYS\t0-15\t0\n\
+             GPU2\tSYS\tSYS\t X \t16-31\t1\n",
+        );
+        let gpu = |id: &str| Device {
+            id: id.to_string(),
+            resource: RESOURCE_NAME.to_string(),
+            pci_bus_id: None,
+            numa_node: None,
+            local_cpus: Vec::new(),
+        };
+        let (gpu0, gpu1, gpu2) = (gpu("0"), gpu("1"), gpu("2"));
+        assert_eq!(provider.score(&[&gpu0, &gpu1], &[]), 20);
+        assert_eq!(provider.score(&[&gpu0, &gpu2], &[]), 0);
+    }
+
+    #[test]
+    fn test_gpu_allocation() {
+        let gpu = |id: &str| Device {
+            id: id.to_string(),
+            resource: RESOURCE_NAME.to_string(),
+            pci_bus_id: None,
+            numa_node: None,
+            local_cpus: Vec::new(),
+        };
+        let allocation = NvidiaGpuPlugin.allocation(&[gpu("1"), gpu("3")]);
+        assert_eq!(allocation.envs, vec![(VISIBLE_DEVICES_ENV.to_string(), "1,3".to_string())]);
+        assert_eq!(&allocation.device_paths[..2], &["/dev/nvidia1".to_string(), "/dev/nvidia3".to_string()]);
+    }
+}
@@ -0,0 +1,141 @@
+use std::fs;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+use anyhow::{Result, anyhow};
+use libcontainer::oci_spec::runtime::{
+    LinuxDeviceBuilder, LinuxDeviceCgroupBuilder, LinuxDeviceType, LinuxResourcesBuilder, Spec,
+};
+use serde::{Deserialize, Serialize};
+use crate::cri::cri::Device;
+
+// like docker run --device
+pub const DEFAULT_PERMISSIONS: &str = "rwm";
+
+// a device node of the node passed through to a container, e.g. /dev/fuse
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeviceMapping {
+    #[serde(rename = "hostPath")]
+    pub host_path: String,
+    // empty keeps the host path
+    #[serde(rename = "containerPath", default)]
+    pub container_path: String,
+    // cgroup access of the container: r, w and/or m(knod)
+    #[serde(default = "default_permissions")]
+    pub permissions: String,
+}
+
+fn default_permissions() -> String {
+    DEFAULT_PERMISSIONS.to_string()
+}
+
+// the CRI device of a mapping, failing when the host has no such device
+pub fn cri_device(mapping: &DeviceMapping) -> Result<Device> {
+    if mapping.permissions.is_empty() || !mapping.permissions.chars().all(|c| "rwm".contains(c)) {
+        return Err(anyhow!("device {}: permissions must be made of r, w and m", mapping.host_path));
+    }
+    let container_path = if mapping.container_path.is_empty() { &mapping.host_path } else { &mapping.container_path };
+    if !container_path.starts_with('/') {
+        return Err(anyhow!("device {}: containerPath must be an absolute path", mapping.host_path));
+    }
+    device_node(Path::new(&mapping.host_path))?;
+    Ok(Device {
+        container_path: container_path.clone(),
+        host_path: mapping.host_path.clone(),
+        permissions: mapping.permissions.clone(),
+    })
+}
+
+// add the devices to the spec and allow them in the device cgroup
+pub fn add_to_spec(spec: &mut Spec, devices: &[Device]) -> Result<()> {
+    if devices.is_empty() {
+        return Ok(());
+    }
+    let mut linux = spec.linux().clone().unwrap_or_default();
+    let mut nodes = linux.devices().clone().unwrap_or_default();
+    let mut resources = linux.resources().clone().unwrap_or_else(|| LinuxResourcesBuilder::default().build().unwrap());
+    let mut rules = resources.devices().clone().unwrap_or_default();
+    for device in devices {
+        let (typ, major, minor, mode) = device_node(Path::new(&device.host_path))?;
+        nodes.retain(|node| node.path() != Path::new(&device.container_path));
+        nodes.push(
+            LinuxDeviceBuilder::default()
+                .path(PathBuf::from(&device.container_path))
+                .typ(typ)
+                .major(major)
+                .minor(minor)
+                .file_mode(mode)
+                .build()?,
+        );
+        rules.push(
+            LinuxDeviceCgroupBuilder::default()
+                .allow(true)
+                .typ(typ)
+                .major(major)
+                .minor(minor)
+                .access(device.permissions.clone())
+                .build()?,
+        );
+    }
+    resources.set_devices(Some(rules));
+    linux.set_resources(Some(resources));
+    linux.set_devices(Some(nodes));
+    spec.set_linux(Some(linux));
+    Ok(())
+}
+
+// type, major, minor and permission bits of a character or block device
+fn device_node(path: &Path) -> Result<(LinuxDeviceType, i64, i64, u32)> {
+    let metadata = fs::metadata(path).map_err(|e| anyhow!("device {}: {}", path.display(), e))?;
+    let typ = if metadata.file_type().is_char_device() {
+        LinuxDeviceType::C
+    } else if metadata.file_type().is_block_device() {
+        LinuxDeviceType::B
+    } else {
+        return Err(anyhow!("device {}: not a character or block device", path.display()));
+    };
+    // the glibc encoding of dev_t
+    let rdev = metadata.rdev();
+    let major = ((rdev >> 8) & 0xfff) | ((rdev >> 32) & !0xfff);
+    let minor = (rdev & 0xff) | ((rdev >> 12) & !0xff);
+    Ok((typ, major as i64, minor as i64, metadata.mode() & 0o7777))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(host_path: &str, container_path: &str, permissions: &str) -> DeviceMapping {
+        DeviceMapping {
+            host_path: host_path.to_string(),
+            container_path: container_path.to_string(),
+            permissions: permissions.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_cri_device() {
+        let device = cri_device(&mapping("/dev/null", "", "rw")).unwrap();
+        assert_eq!(device.container_path, "/dev/null");
+        assert_eq!(device.permissions, "rw");
+
+        assert!(cri_device(&mapping("/dev/rk8s-missing", "", "rwm")).is_err());
+        assert!(cri_device(&mapping("/dev/null", "", "rx")).is_err());
+        assert!(cri_device(&mapping("/dev/null", "dev/null", "rwm")).is_err());
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let err = cri_device(&mapping(file.path().to_str().unwrap(), "/dev/fake", "rwm")).unwrap_err();
+        assert!(err.to_string().contains("not a character or block device"));
+    }
+
+    #[test]
+    fn test_add_to_spec() {
+        let mut spec = Spec::default();
+        let device = cri_device(&mapping("/dev/null", "/dev/sink", "rwm")).unwrap();
+        add_to_spec(&mut spec, &[device]).unwrap();
+
+        let linux = spec.linux().as_ref().unwrap();
+        let node = linux.devices().as_ref().unwrap().iter().find(|d| d.path() == Path::new("/dev/sink")).unwrap();
+        assert_eq!((node.typ(), node.major(), node.minor()), (LinuxDeviceType::C, 1, 3));
+        let rule = linux.resources().as_ref().unwrap().devices().as_ref().unwrap().last().unwrap();
+        assert_eq!((rule.major(), rule.minor(), rule.access().as_deref()), (Some(1), Some(3), Some("rwm")));
+    }
+}
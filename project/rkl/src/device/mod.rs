@@ -0,0 +1,255 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{Result, anyhow};
+
+pub mod gpu;
+pub mod host;
+
+// a device that can be handed to a container as an extended resource
+#[derive(Debug, Clone, PartialEq)]
+pub struct Device {
+    // index of the device for its plugin, e.g. "0" for /dev/nvidia0
+    pub id: String,
+    // extended resource name, e.g. nvidia.com/gpu
+    pub resource: String,
+    pub pci_bus_id: Option<String>,
+    pub numa_node: Option<u32>,
+    // CPUs attached to the same PCIe root as the device
+    pub local_cpus: Vec<u32>,
+}
+
+// what a container is given for the devices allocated to it
+#[derive(Debug, Default, PartialEq)]
+pub struct ContainerAllocation {
+    pub envs: Vec<(String, String)>,
+    // device nodes of the host, passed through at the same path
+    pub device_paths: Vec<String>,
+}
+
+// discovers the devices of one extended resource and tells how a container
+// uses the ones it is allocated
+pub trait DevicePlugin {
+    fn resource_name(&self) -> &str;
+    fn discover(&self) -> Result<Vec<Device>>;
+    // locality signals specific to the devices, on top of the PCIe one
+    fn topology_providers(&self) -> Vec<Box<dyn TopologyHintProvider>> {
+        Vec::new()
+    }
+    fn allocation(&self, devices: &[Device]) -> ContainerAllocation;
+}
+
+// the built-in plugins
+pub fn plugins() -> Vec<Box<dyn DevicePlugin>> {
+    vec![Box::new(gpu::NvidiaGpuPlugin)]
+}
+
+pub fn plugin(resource: &str) -> Option<Box<dyn DevicePlugin>> {
+    plugins().into_iter().find(|plugin| plugin.resource_name() == resource)
+}
+
+// scores a set of devices that could be allocated together, higher is better.
+// providers are summed so that several locality signals can be combined
+pub trait TopologyHintProvider {
+    fn score(&self, devices: &[&Device], pinned_cpus: &[u32]) -> i64;
+}
+
+// prefers devices whose PCIe root is local to the pinned CPUs, and
+// devices on the same NUMA node when the container isn't pinned
+pub struct PcieLocalityProvider;
+
+impl TopologyHintProvider for PcieLocalityProvider {
+    fn score(&self, devices: &[&Device], pinned_cpus: &[u32]) -> i64 {
+        if !pinned_cpus.is_empty() {
+            return devices
+                .iter()
+                .map(|d| pinned_cpus.iter().filter(|cpu| d.local_cpus.contains(cpu)).count() as i64)
+                .sum();
+        }
+        let nodes: HashSet<_> = devices.iter().filter_map(|d| d.numa_node).collect();
+        if nodes.len() <= 1 { 1 } else { 0 }
+    }
+}
+
+// an allocation is recorded as "<resource> <device id> <owner>" in <root>/devices,
+// the owner being "<pod>/<container>"
+#[derive(Debug, Clone, PartialEq)]
+struct Allocation {
+    resource: String,
+    device_id: String,
+    owner: String,
+}
+
+pub struct DeviceManager {
+    devices: Vec<Device>,
+    providers: Vec<Box<dyn TopologyHintProvider>>,
+    state_path: PathBuf,
+}
+
+impl DeviceManager {
+    pub fn new(root_path: &Path, devices: Vec<Device>, providers: Vec<Box<dyn TopologyHintProvider>>) -> Self {
+        DeviceManager {
+            devices,
+            providers,
+            state_path: root_path.join("devices"),
+        }
+    }
+
+    // discover the devices of the node with a plugin
+    pub fn discover(root_path: &Path, plugin: &dyn DevicePlugin) -> Result<Self> {
+        let devices = plugin.discover()?;
+        let mut providers: Vec<Box<dyn TopologyHintProvider>> = vec![Box::new(PcieLocalityProvider)];
+        providers.extend(plugin.topology_providers());
+        Ok(Self::new(root_path, devices, providers))
+    }
+
+    // allocate count devices of the resource to owner, choosing the free
+    // combination the topology hint providers like best
+    pub fn allocate(&self, resource: &str, count: usize, owner: &str, pinned_cpus: &[u32]) -> Result<Vec<Device>> {
+        let mut allocations = self.load()?;
+        let free: Vec<&Device> = self.devices
+            .iter()
+            .filter(|d| d.resource == resource)
+            .filter(|d| !allocations.iter().any(|a| a.resource == resource && a.device_id == d.id))
+            .collect();
+        if free.len() < count {
+            return Err(anyhow!(
+                "insufficient {}: {} requested by {}, {} available",
+                resource, count, owner, free.len()
+            ));
+        }
+
+        let chosen = self.best_combination(&free, count, pinned_cpus);
+        for device in &chosen {
+            allocations.push(Allocation {
+                resource: resource.to_string(),
+                device_id: device.id.clone(),
+                owner: owner.to_string(),
+            });
+        }
+        self.save(&allocations)?;
+        Ok(chosen.into_iter().cloned().collect())
+    }
+
+    // release every device whose owner starts with the prefix, e.g. "<pod>/"
+    pub fn release(&self, owner_prefix: &str) -> Result<()> {
+        let mut allocations = self.load()?;
+        allocations.retain(|a| !a.owner.starts_with(owner_prefix));
+        self.save(&allocations)
+    }
+
+    fn best_combination<'a>(&self, free: &[&'a Device], count: usize, pinned_cpus: &[u32]) -> Vec<&'a Device> {
+        let mut best: Option<(i64, Vec<&Device>)> = None;
+        let mut current = Vec::with_capacity(count);
+        self.visit_combinations(free, count, 0, &mut current, &mut |combination| {
+            let score: i64 = self.providers.iter().map(|p| p.score(combination, pinned_cpus)).sum();
+            if best.as_ref().is_none_or(|(best_score, _)| score > *best_score) {
+                best = Some((score, combination.to_vec()));
+            }
+        });
+        best.map(|(_, devices)| devices).unwrap_or_default()
+    }
+
+    fn visit_combinations<'a>(
+        &self,
+        free: &[&'a Device],
+        count: usize,
+        start: usize,
+        current: &mut Vec<&'a Device>,
+        visit: &mut dyn FnMut(&[&'a Device]),
+    ) {
+        if current.len() == count {
+            visit(current);
+            return;
+        }
+        for i in start..free.len() {
+            current.push(free[i]);
+            self.visit_combinations(free, count, i + 1, current, visit);
+            current.pop();
+        }
+    }
+
+    fn load(&self) -> Result<Vec<Allocation>> {
+        if !self.state_path.exists() {
+            return Ok(Vec::new());
+        }
+        let contents = fs::read_to_string(&self.state_path)?;
+        Ok(contents
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split_whitespace();
+                Some(Allocation {
+                    resource: fields.next()?.to_string(),
+                    device_id: fields.next()?.to_string(),
+                    owner: fields.next()?.to_string(),
+                })
+            })
+            .collect())
+    }
+
+    fn save(&self, allocations: &[Allocation]) -> Result<()> {
+        let contents: String = allocations
+            .iter()
+            .map(|a| format!("{} {} {}\n", a.resource, a.device_id, a.owner))
+            .collect();
+        fs::write(&self.state_path, contents)?;
+        Ok(())
+    }
+}
+
+// release the devices of every container of the pod
+pub fn release_pod(root_path: &Path, pod_name: &str) -> Result<()> {
+    DeviceManager::new(root_path, Vec::new(), Vec::new()).release(&format!("{}/", pod_name))
+}
+
+// release the devices of one container, e.g. before it is recreated
+pub fn release_container(root_path: &Path, pod_name: &str, container_name: &str) -> Result<()> {
+    let manager = DeviceManager::new(root_path, Vec::new(), Vec::new());
+    let owner = format!("{}/{}", pod_name, container_name);
+    let mut allocations = manager.load()?;
+    allocations.retain(|a| a.owner != owner);
+    manager.save(&allocations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gpu(id: &str, numa_node: u32, local_cpus: Vec<u32>) -> Device {
+        Device {
+            id: id.to_string(),
+            resource: gpu::RESOURCE_NAME.to_string(),
+            pci_bus_id: None,
+            numa_node: Some(numa_node),
+            local_cpus,
+        }
+    }
+
+    #[test]
+    fn test_allocate_prefers_local_devices() {
+        let root = tempfile::tempdir().unwrap();
+        let devices = vec![
+            gpu("0", 0, vec![0, 1]),
+            gpu("1", 1, vec![2, 3]),
+            gpu("2", 1, vec![2, 3]),
+        ];
+        let manager = DeviceManager::new(root.path(), devices, vec![Box::new(PcieLocalityProvider)]);
+
+        // not pinned: two devices of the same NUMA node are preferred
+        let chosen = manager.allocate(gpu::RESOURCE_NAME, 2, "pod/a", &[]).unwrap();
+        let ids: Vec<_> = chosen.iter().map(|d| d.id.as_str()).collect();
+        assert_eq!(ids, vec!["1", "2"]);
+
+        manager.release("pod/").unwrap();
+        // pinned to CPU 2: the devices local to it win
+        let chosen = manager.allocate(gpu::RESOURCE_NAME, 1, "pod/b", &[2]).unwrap();
+        assert_eq!(chosen[0].id, "1");
+        let chosen = manager.allocate(gpu::RESOURCE_NAME, 2, "pod/c", &[]).unwrap();
+        let ids: Vec<_> = chosen.iter().map(|d| d.id.as_str()).collect();
+        assert_eq!(ids, vec!["0", "2"]);
+
+        assert!(manager.allocate(gpu::RESOURCE_NAME, 1, "other/d", &[]).is_err());
+        manager.release("pod/").unwrap();
+        assert!(manager.allocate(gpu::RESOURCE_NAME, 3, "other/d", &[]).is_ok());
+    }
+}
@@ -0,0 +1,73 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::{Value, json};
+use crate::cri::debug;
+
+// --dry-run of run, create, delete and apply: nothing is changed, the command
+// says what it would do. With client the requests are built and checked
+// without being sent: the CRI requests of a pod (-o json prints them, redacted
+// like --debug-cri), the three-way merge of apply against the live pod. With
+// server the runtime or the clusters are asked as well where they can check
+// without changing anything: the CRI runtime whether it is ready and has the
+// images, rks whether the pod can be scheduled and where it would go, or
+// whether the pod to delete exists.
+
+// the id of the sandbox the containers of a dry run would be created in
+pub const SANDBOX_ID: &str = "<dry-run>";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DryRun {
+    Client,
+    Server,
+}
+
+// appended to what the command says it did
+pub fn suffix(mode: Option<DryRun>) -> &'static str {
+    match mode {
+        None => "",
+        Some(DryRun::Client) => " (dry run)",
+        Some(DryRun::Server) => " (server dry run)",
+    }
+}
+
+// a request a dry run didn't send
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub method: &'static str,
+    pub request: Value,
+}
+
+impl Request {
+    pub fn new(method: &'static str, request: &impl Serialize) -> Self {
+        Request { method, request: debug::redact(serde_json::to_value(request).unwrap_or(Value::Null)) }
+    }
+}
+
+// the requests as a JSON list of {method, request}
+pub fn print_json(requests: &[Request]) {
+    let requests: Vec<Value> = requests.iter().map(|r| json!({ "method": r.method, "request": r.request })).collect();
+    if let Ok(pretty) = serde_json::to_string_pretty(&requests) {
+        println!("{}", pretty);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cri::cri::{AuthConfig, ImageSpec, PullImageRequest};
+
+    #[test]
+    fn test_request() {
+        let pull = PullImageRequest {
+            image: Some(ImageSpec { image: "registry.local/web:v1".to_string(), ..Default::default() }),
+            auth: Some(AuthConfig { username: "ci".to_string(), password: "hunter2".to_string(), ..Default::default() }),
+            ..Default::default()
+        };
+        let request = Request::new("PullImage", &pull);
+        assert_eq!(request.request["image"]["image"], "registry.local/web:v1");
+        assert_eq!(request.request["auth"]["username"], "ci");
+        assert_ne!(request.request["auth"]["password"], "hunter2");
+        assert_eq!(suffix(None), "");
+        assert_eq!(suffix(Some(DryRun::Server)), " (server dry run)");
+    }
+}
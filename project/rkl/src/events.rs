@@ -0,0 +1,237 @@
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::Result;
+use crate::rootpath;
+use crate::secret;
+use tracing::warn;
+
+// Kubernetes-style events of a pod, one "<unix time> <type> <reason> <message>"
+// line each in <root>/events/<pod name>. The lifecycle of the pod, from pulling
+// its images to killing its containers, is recorded by the EventRecorder of its
+// TaskRunner, the daemon and its controllers add their own reasons, and
+// `rkl describe pod` shows them like kubectl does.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventType {
+    Normal,
+    Warning,
+}
+
+impl fmt::Display for EventType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EventType::Normal => write!(f, "Normal"),
+            EventType::Warning => write!(f, "Warning"),
+        }
+    }
+}
+
+pub const PULLED: &str = "Pulled";
+pub const CREATED: &str = "Created";
+pub const STARTED: &str = "Started";
+pub const FAILED: &str = "Failed";
+pub const KILLING: &str = "Killing";
+pub const FAILED_POST_START_HOOK: &str = "FailedPostStartHook";
+pub const FAILED_PRE_STOP_HOOK: &str = "FailedPreStopHook";
+pub const FAILED_CREATE_POD_SANDBOX: &str = "FailedCreatePodSandBox";
+pub const SANDBOX_CHANGED: &str = "SandboxChanged";
+pub const BACK_OFF: &str = "BackOff";
+pub const UNHEALTHY: &str = "Unhealthy";
+pub const NODE_AFFINITY: &str = "NodeAffinity";
+pub const TAINT_TOLERATION: &str = "TaintToleration";
+pub const TAINT_MANAGER_EVICTION: &str = "TaintManagerEviction";
+pub const OUT_OF_CPU: &str = "OutOfcpu";
+pub const OUT_OF_MEMORY: &str = "OutOfmemory";
+pub const EVICTED: &str = "Evicted";
+pub const PREEMPTED: &str = "Preempted";
+pub const SCALING_REPLICA_SET: &str = "ScalingReplicaSet";
+pub const SUCCESSFUL_CREATE: &str = "SuccessfulCreate";
+pub const COMPLETED: &str = "Completed";
+pub const BACKOFF_LIMIT_EXCEEDED: &str = "BackoffLimitExceeded";
+pub const SUCCESSFUL_DELETE: &str = "SuccessfulDelete";
+pub const JOB_ALREADY_ACTIVE: &str = "JobAlreadyActive";
+pub const MISS_SCHEDULE: &str = "MissSchedule";
+
+// the timestamps of the events are unix times
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+fn events_path(root_path: &Path, pod_name: &str) -> PathBuf {
+    root_path.join("events").join(pod_name)
+}
+
+pub fn record(root_path: &Path, pod_name: &str, event_type: EventType, reason: &str, message: &str) -> Result<()> {
+    let path = events_path(root_path, pod_name);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let timestamp = now();
+    // events are single lines, without the values of secrets
+    let message = secret::scrub(message).replace('\n', " ");
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+    writeln!(file, "{} {} {} {}", timestamp, event_type, reason, message)?;
+    if event_type == EventType::Warning {
+        warn!("{} {} pod/{}: {}", event_type, reason, pod_name, message);
+    }
+    Ok(())
+}
+
+// records the events of one pod; events are best effort, failing to record
+// one is only logged
+pub struct EventRecorder {
+    // None when the root of rkl couldn't be determined
+    root_path: Option<PathBuf>,
+    pod_name: String,
+}
+
+impl EventRecorder {
+    pub fn new(root_path: &Path, pod_name: &str) -> Self {
+        EventRecorder { root_path: Some(root_path.to_path_buf()), pod_name: pod_name.to_string() }
+    }
+
+    pub fn for_pod(pod_name: &str) -> Self {
+        EventRecorder { root_path: rootpath::determine(None).ok(), pod_name: pod_name.to_string() }
+    }
+
+    pub fn normal(&self, reason: &str, message: &str) {
+        self.event(EventType::Normal, reason, message);
+    }
+
+    pub fn warning(&self, reason: &str, message: &str) {
+        self.event(EventType::Warning, reason, message);
+    }
+
+    fn event(&self, event_type: EventType, reason: &str, message: &str) {
+        let Some(root_path) = &self.root_path else {
+            return;
+        };
+        if let Err(e) = record(root_path, &self.pod_name, event_type, reason, message) {
+            warn!("Failed to record event {} of Pod {}: {}", reason, self.pod_name, e);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub timestamp: u64,
+    pub event_type: String,
+    pub reason: String,
+    pub message: String,
+    // repeats of the same event are shown once, like kubectl does
+    pub count: u32,
+    pub first_timestamp: u64,
+}
+
+impl Event {
+    fn parse(line: &str) -> Option<Self> {
+        let mut parts = line.splitn(4, ' ');
+        let timestamp = parts.next()?.parse().ok()?;
+        let (event_type, reason) = (parts.next()?.to_string(), parts.next()?.to_string());
+        let message = parts.next().unwrap_or_default().to_string();
+        Some(Event { timestamp, event_type, reason, message, count: 1, first_timestamp: timestamp })
+    }
+}
+
+// the events of the pod, oldest first, repeats folded into the last of them
+pub fn list(root_path: &Path, pod_name: &str) -> Result<Vec<Event>> {
+    let path = events_path(root_path, pod_name);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let mut events: Vec<Event> = Vec::new();
+    for event in fs::read_to_string(&path)?.lines().filter_map(Event::parse) {
+        match events.iter_mut().find(|e| e.event_type == event.event_type && e.reason == event.reason && e.message == event.message) {
+            Some(earlier) => {
+                earlier.count += 1;
+                earlier.timestamp = event.timestamp;
+            }
+            None => events.push(event),
+        }
+    }
+    events.sort_by_key(|event| event.timestamp);
+    Ok(events)
+}
+
+// a duration the way kubectl shows ages, e.g. 45s, 12m, 3h or 5d
+pub fn age(seconds: u64) -> String {
+    match seconds {
+        0..120 => format!("{}s", seconds),
+        120..7200 => format!("{}m", seconds / 60),
+        7200..172800 => format!("{}h", seconds / 3600),
+        _ => format!("{}d", seconds / 86400),
+    }
+}
+
+// the Events section of `rkl describe pod`
+pub fn describe(events: &[Event], now: u64) -> Vec<String> {
+    if events.is_empty() {
+        return vec!["Events:  <none>".to_string()];
+    }
+    let ages: Vec<String> = events
+        .iter()
+        .map(|event| {
+            let age = age(now.saturating_sub(event.timestamp));
+            match event.count {
+                1 => age,
+                count => format!("{} (x{} over {})", age, count, self::age(now.saturating_sub(event.first_timestamp))),
+            }
+        })
+        .collect();
+    let reason_width = events.iter().map(|event| event.reason.len()).max().unwrap_or(0).max("Reason".len());
+    let age_width = ages.iter().map(String::len).max().unwrap_or(0).max("----".len());
+    let row = |event_type: &str, reason: &str, age: &str, message: &str| {
+        format!("  {:<7}  {:<reason_width$}  {:<age_width$}  {}", event_type, reason, age, message)
+    };
+    let mut lines = vec![
+        "Events:".to_string(),
+        row("Type", "Reason", "Age", "Message"),
+        row("----", "------", "----", "-------"),
+    ];
+    for (event, age) in events.iter().zip(&ages) {
+        lines.push(row(&event.event_type, &event.reason, age, &event.message));
+    }
+    lines
+}
+
+pub fn remove(root_path: &Path, pod_name: &str) -> Result<()> {
+    let path = events_path(root_path, pod_name);
+    if path.exists() {
+        fs::remove_file(&path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = events_path(dir.path(), "web");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(
+            &path,
+            "1000 Normal Pulled Successfully pulled image nginx\n\
+             1001 Normal Created Created container nginx\n\
+             1002 Warning BackOff Back-off restarting failed container nginx\n\
+             1100 Warning BackOff Back-off restarting failed container nginx\n\
+             1400 Warning BackOff Back-off restarting failed container nginx\n",
+        )
+        .unwrap();
+        let events = list(dir.path(), "web").unwrap();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[2].count, 3);
+
+        let lines = describe(&events, 1600);
+        assert_eq!(lines[1], "  Type     Reason   Age              Message");
+        assert_eq!(lines[2], "  ----     ------   ----             -------");
+        assert_eq!(lines[3], "  Normal   Pulled   10m              Successfully pulled image nginx");
+        assert_eq!(lines[5], "  Warning  BackOff  3m (x3 over 9m)  Back-off restarting failed container nginx");
+        assert_eq!(describe(&[], 0), vec!["Events:  <none>"]);
+    }
+}
@@ -0,0 +1,201 @@
+use std::collections::BTreeMap;
+use anyhow::{Result, anyhow};
+use crate::validate::{self, Schema};
+
+// `rkl explain pod.spec.containers`: what rkl supports of a kind or of a field
+// of it, from the schemas manifests are validated against, like kubectl's:
+//
+//   KIND:     Pod
+//   FIELD:    containers <[]Container>
+//
+//   DESCRIPTION:
+//       The containers of the pod, started in order once the init containers
+//       succeeded.
+//
+//   FIELDS:
+//     args	<[]string>
+//       The command and its arguments.
+//
+// The fields of a list or a map are the fields of its elements, e.g.
+// pod.spec.containers.env.valueFrom; kinds can be written as pod, pods or Pod.
+
+// where descriptions wrap
+const WIDTH: usize = 80;
+
+struct Explainer {
+    definitions: BTreeMap<String, Schema>,
+}
+
+impl Explainer {
+    fn resolve<'s>(&'s self, schema: &'s Schema) -> &'s Schema {
+        match &schema.reference {
+            Some(reference) => self.definitions.get(reference.trim_start_matches("#/definitions/")).unwrap_or(schema),
+            None => schema,
+        }
+    }
+
+    // the schema of what has the fields, the elements of lists and maps
+    fn element<'s>(&'s self, schema: &'s Schema) -> &'s Schema {
+        let schema = self.resolve(schema);
+        match (&schema.items, &schema.additional_properties) {
+            (Some(items), _) => self.element(items),
+            (None, Some(values)) if schema.properties.is_empty() => self.element(values),
+            _ => schema,
+        }
+    }
+
+    // the description of the field, or else of what it refers to or holds
+    fn description<'s>(&'s self, schema: &'s Schema) -> &'s str {
+        [schema, self.resolve(schema), self.element(schema)]
+            .into_iter()
+            .map(|schema| schema.description.as_str())
+            .find(|description| !description.is_empty())
+            .unwrap_or_default()
+    }
+}
+
+fn type_name(schema: &Schema) -> String {
+    if let Some(reference) = &schema.reference {
+        return reference.trim_start_matches("#/definitions/").to_string();
+    }
+    if schema.int_or_string {
+        return "int-or-string".to_string();
+    }
+    if let Some(items) = &schema.items {
+        return format!("[]{}", type_name(items));
+    }
+    if let Some(values) = &schema.additional_properties
+        && schema.properties.is_empty()
+    {
+        return format!("map[string]{}", type_name(values));
+    }
+    match schema.value_type.as_deref() {
+        Some("object") | None => "Object".to_string(),
+        Some(other) => other.to_string(),
+    }
+}
+
+// the words of the text as lines of at most WIDTH, indented
+fn wrap(text: &str, indent: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && indent + line.len() + 1 + word.len() > WIDTH {
+            lines.push(format!("{:indent$}{}", "", line, indent = indent));
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(format!("{:indent$}{}", "", line, indent = indent));
+    }
+    lines
+}
+
+// the kind of a name as written on the command line
+fn kind(name: &str) -> Option<&'static str> {
+    validate::kinds()
+        .into_iter()
+        .find(|kind| name.eq_ignore_ascii_case(kind) || name.eq_ignore_ascii_case(&format!("{}s", kind)))
+}
+
+fn lines(path: &str) -> Result<Vec<String>> {
+    let mut names = path.split('.');
+    let kind_name = names.next().unwrap_or_default();
+    let kind = kind(kind_name)
+        .ok_or_else(|| anyhow!("unknown kind {}, expected one of {}", kind_name, validate::kinds().join(", ")))?;
+    let (root, definitions) = validate::schema(kind).ok_or_else(|| anyhow!("no schema of {}", kind))?;
+    let explainer = Explainer { definitions };
+
+    let mut schema = &root;
+    let mut field = None;
+    let mut walked = kind_name.to_string();
+    for name in names {
+        schema = explainer
+            .element(schema)
+            .properties
+            .get(name)
+            .ok_or_else(|| anyhow!("field {} of {} doesn't exist", name, walked))?;
+        walked = format!("{}.{}", walked, name);
+        field = Some(name);
+    }
+
+    let mut lines = vec![format!("KIND:     {}", kind)];
+    if let Some(name) = field {
+        lines.push(format!("FIELD:    {} <{}>", name, type_name(schema)));
+    }
+    lines.push(String::new());
+    lines.push("DESCRIPTION:".to_string());
+    match explainer.description(schema) {
+        "" => lines.push("    <empty>".to_string()),
+        description => lines.extend(wrap(description, 4)),
+    }
+    let element = explainer.element(schema);
+    if !element.allowed.is_empty() {
+        lines.push(String::new());
+        lines.push("ENUM:".to_string());
+        for value in &element.allowed {
+            match value.as_str() {
+                Some(value) => lines.push(format!("    {}", value)),
+                None => lines.push(format!("    {}", value)),
+            }
+        }
+    }
+    if !element.properties.is_empty() {
+        lines.push(String::new());
+        lines.push("FIELDS:".to_string());
+        for (name, field) in &element.properties {
+            let mut line = format!("  {}\t<{}>", name, type_name(field));
+            if element.required.contains(name) {
+                line.push_str(" -required-");
+            }
+            if explainer.resolve(field).deprecated {
+                line.push_str(" -deprecated-");
+            }
+            lines.push(line);
+            lines.extend(wrap(explainer.description(field), 4));
+            lines.push(String::new());
+        }
+        lines.pop();
+    }
+    Ok(lines)
+}
+
+// `rkl explain`
+pub fn explain(path: &str) -> Result<()> {
+    for line in lines(path)? {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_explain() {
+        let containers = lines("pods.spec.containers").unwrap();
+        assert_eq!(containers[..3], ["KIND:     Pod", "FIELD:    containers <[]Container>", ""]);
+        assert!(containers.contains(&"  image\t<string> -required-".to_string()));
+        assert!(containers.contains(&"  env\t<[]Object>".to_string()));
+        assert!(containers.iter().all(|line| line.len() <= WIDTH));
+
+        let field_ref = lines("Pod.spec.containers.env.valueFrom.fieldRef").unwrap();
+        assert_eq!(field_ref[1], "FIELD:    fieldRef <ObjectFieldSelector>");
+        assert!(field_ref.contains(&"  fieldPath\t<string> -required-".to_string()));
+
+        let labels = lines("pod.metadata.labels").unwrap();
+        assert_eq!(labels[1], "FIELD:    labels <map[string]string>");
+        let policy = lines("pod.spec.restartPolicy").unwrap();
+        assert_eq!(policy[policy.len() - 3..], ["    Always", "    OnFailure", "    Never"]);
+        assert!(lines("pod.spec").unwrap().contains(&"  serviceAccount\t<string> -deprecated-".to_string()));
+        assert_eq!(lines("configmap").unwrap()[0], "KIND:     ConfigMap");
+
+        assert!(lines("pod.spec.containers.command").is_err());
+        assert!(lines("deployment").is_err());
+    }
+}
@@ -0,0 +1,353 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use anyhow::{Result, anyhow};
+use openssl::asn1::{Asn1Integer, Asn1Time};
+use openssl::bn::{BigNum, MsbOption};
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private};
+use openssl::x509::extension::{
+    AuthorityKeyIdentifier, BasicConstraints, ExtendedKeyUsage, KeyUsage, SubjectAlternativeName,
+    SubjectKeyIdentifier,
+};
+use openssl::x509::{X509, X509Builder, X509NameBuilder};
+use crate::task::task::sandbox_dir;
+use tracing::info;
+
+// Workload identity: with --spiffe-trust-domain every pod gets an X.509 SVID,
+// a certificate for the SPIFFE ID
+//
+//   spiffe://<trust domain>/ns/<namespace>/sa/<serviceAccountName>
+//
+// projected read-only into all its containers at /var/run/secrets/spiffe.io:
+//   svid.pem      certificate of the pod
+//   svid_key.pem  its private key
+//   bundle.pem    trust bundle, the CA certificates of the trust domain
+//
+// The CA of the trust domain is <root>/identity/ca.{crt,key}, generated on first
+// use; nodes sharing a trust domain should be given the same CA files. SVIDs are
+// short lived and reissued by `rkl daemon` once half of their lifetime has passed.
+// The files are replaced atomically, so workloads can simply reload them. The
+// directory is 0700 and the files 0600, owned by the user the pod runs as.
+
+pub const MOUNT_PATH: &str = "/var/run/secrets/spiffe.io";
+pub const DEFAULT_SVID_TTL: &str = "1h";
+
+const SVID_CERT: &str = "svid.pem";
+const SVID_KEY: &str = "svid_key.pem";
+const BUNDLE: &str = "bundle.pem";
+const CA_TTL_DAYS: u32 = 3650;
+// tolerate clocks of peers that are a little behind
+const CLOCK_SKEW: i64 = 60;
+
+#[derive(Debug, Clone)]
+pub struct IdentityConfig {
+    pub trust_domain: String,
+    pub svid_ttl: Duration,
+}
+
+static CONFIG: OnceLock<Option<IdentityConfig>> = OnceLock::new();
+
+// set once at startup from --spiffe-trust-domain and --spiffe-svid-ttl
+pub fn init(trust_domain: Option<String>, svid_ttl: Duration) -> Result<()> {
+    let config = match trust_domain {
+        Some(trust_domain) => {
+            validate_trust_domain(&trust_domain)?;
+            Some(IdentityConfig { trust_domain, svid_ttl })
+        }
+        None => None,
+    };
+    let _ = CONFIG.set(config);
+    Ok(())
+}
+
+// None when workload identity is disabled
+pub fn config() -> Option<&'static IdentityConfig> {
+    CONFIG.get().and_then(Option::as_ref)
+}
+
+fn validate_trust_domain(trust_domain: &str) -> Result<()> {
+    let valid = !trust_domain.is_empty()
+        && trust_domain.len() <= 255
+        && trust_domain
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '.' | '-' | '_'));
+    if !valid {
+        return Err(anyhow!("invalid SPIFFE trust domain {:?}", trust_domain));
+    }
+    Ok(())
+}
+
+pub fn spiffe_id(trust_domain: &str, namespace: &str, service_account: &str) -> String {
+    format!("spiffe://{}/ns/{}/sa/{}", trust_domain, namespace, service_account)
+}
+
+// directory of the SVID files of a sandbox, bind mounted at MOUNT_PATH
+pub fn svid_dir(root_path: &Path, pod_sandbox_id: &str) -> PathBuf {
+    sandbox_dir(root_path, pod_sandbox_id).join("svid")
+}
+
+fn ca_dir(root_path: &Path) -> PathBuf {
+    root_path.join("identity")
+}
+
+fn now_unix() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or_default()
+}
+
+fn new_key() -> Result<PKey<Private>> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+    Ok(PKey::from_ec_key(EcKey::generate(&group)?)?)
+}
+
+fn random_serial() -> Result<Asn1Integer> {
+    let mut serial = BigNum::new()?;
+    serial.rand(127, MsbOption::MAYBE_ZERO, false)?;
+    Ok(serial.to_asn1_integer()?)
+}
+
+// write a file through a temporary one so that readers never see a partial
+// file, the temporary file has its mode and owner before anything is written
+fn write_atomic(path: &Path, contents: &[u8], mode: u32, owner: Option<(u32, u32)>) -> Result<()> {
+    let file_name = path.file_name().ok_or_else(|| anyhow!("invalid path {}", path.display()))?;
+    let tmp = path.with_file_name(format!(".{}.tmp", file_name.to_string_lossy()));
+    // a leftover of an interrupted write, create_new doesn't follow it
+    let _ = fs::remove_file(&tmp);
+    let mut file = OpenOptions::new().write(true).create_new(true).mode(mode).open(&tmp)?;
+    if let Some((uid, gid)) = owner {
+        std::os::unix::fs::fchown(&file, Some(uid), Some(gid))?;
+    }
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+pub struct CertificateAuthority {
+    cert: X509,
+    key: PKey<Private>,
+}
+
+impl CertificateAuthority {
+    // load the CA of the trust domain, creating it on first use
+    pub fn load_or_create(root_path: &Path, trust_domain: &str) -> Result<Self> {
+        let dir = ca_dir(root_path);
+        let (cert_path, key_path) = (dir.join("ca.crt"), dir.join("ca.key"));
+        if cert_path.exists() && key_path.exists() {
+            let cert = X509::from_pem(&fs::read(&cert_path)?)
+                .map_err(|e| anyhow!("Failed to load {}: {}", cert_path.display(), e))?;
+            let key = PKey::private_key_from_pem(&fs::read(&key_path)?)
+                .map_err(|e| anyhow!("Failed to load {}: {}", key_path.display(), e))?;
+            return Ok(CertificateAuthority { cert, key });
+        }
+
+        let ca = Self::generate(trust_domain)?;
+        fs::create_dir_all(&dir)?;
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))?;
+        write_atomic(&key_path, &ca.key.private_key_to_pem_pkcs8()?, 0o600, None)?;
+        write_atomic(&cert_path, &ca.cert.to_pem()?, 0o644, None)?;
+        info!("Created SPIFFE CA of trust domain {} in {}", trust_domain, dir.display());
+        Ok(ca)
+    }
+
+    pub fn generate(trust_domain: &str) -> Result<Self> {
+        let key = new_key()?;
+        let mut name = X509NameBuilder::new()?;
+        name.append_entry_by_nid(Nid::ORGANIZATIONNAME, "rk8s")?;
+        name.append_entry_by_nid(Nid::COMMONNAME, &format!("SPIFFE CA {}", trust_domain))?;
+        let name = name.build();
+
+        let mut builder = X509Builder::new()?;
+        builder.set_version(2)?;
+        let serial = random_serial()?;
+        builder.set_serial_number(&serial)?;
+        builder.set_subject_name(&name)?;
+        builder.set_issuer_name(&name)?;
+        builder.set_pubkey(&key)?;
+        let not_before = Asn1Time::from_unix(now_unix() - CLOCK_SKEW)?;
+        let not_after = Asn1Time::days_from_now(CA_TTL_DAYS)?;
+        builder.set_not_before(&not_before)?;
+        builder.set_not_after(&not_after)?;
+        builder.append_extension(BasicConstraints::new().critical().ca().build()?)?;
+        builder.append_extension(KeyUsage::new().critical().key_cert_sign().crl_sign().build()?)?;
+        let subject_key_id = SubjectKeyIdentifier::new().build(&builder.x509v3_context(None, None))?;
+        builder.append_extension(subject_key_id)?;
+        let trust_domain_id = SubjectAlternativeName::new()
+            .uri(&format!("spiffe://{}", trust_domain))
+            .build(&builder.x509v3_context(None, None))?;
+        builder.append_extension(trust_domain_id)?;
+        builder.sign(&key, MessageDigest::sha256())?;
+        Ok(CertificateAuthority { cert: builder.build(), key })
+    }
+
+    // issue an X.509 SVID for a SPIFFE ID, returning the certificate and its key
+    pub fn issue(&self, spiffe_id: &str, ttl: Duration) -> Result<(X509, PKey<Private>)> {
+        let key = new_key()?;
+        let mut name = X509NameBuilder::new()?;
+        name.append_entry_by_nid(Nid::ORGANIZATIONNAME, "rk8s")?;
+        let name = name.build();
+
+        let now = now_unix();
+        let mut builder = X509Builder::new()?;
+        builder.set_version(2)?;
+        let serial = random_serial()?;
+        builder.set_serial_number(&serial)?;
+        builder.set_subject_name(&name)?;
+        builder.set_issuer_name(self.cert.subject_name())?;
+        builder.set_pubkey(&key)?;
+        let not_before = Asn1Time::from_unix(now - CLOCK_SKEW)?;
+        let not_after = Asn1Time::from_unix(now + ttl.as_secs() as i64)?;
+        builder.set_not_before(&not_before)?;
+        builder.set_not_after(&not_after)?;
+        builder.append_extension(BasicConstraints::new().critical().build()?)?;
+        builder.append_extension(KeyUsage::new().critical().digital_signature().key_agreement().build()?)?;
+        builder.append_extension(ExtendedKeyUsage::new().server_auth().client_auth().build()?)?;
+        let context = builder.x509v3_context(Some(&self.cert), None);
+        let subject_key_id = SubjectKeyIdentifier::new().build(&context)?;
+        let authority_key_id = AuthorityKeyIdentifier::new().keyid(false).build(&context)?;
+        let san = SubjectAlternativeName::new().uri(spiffe_id).build(&context)?;
+        builder.append_extension(subject_key_id)?;
+        builder.append_extension(authority_key_id)?;
+        builder.append_extension(san)?;
+        builder.sign(&self.key, MessageDigest::sha256())?;
+        Ok((builder.build(), key))
+    }
+
+    // write a fresh SVID and the trust bundle into dir, readable by the uid and
+    // gid of the owner only
+    fn write_svid(&self, dir: &Path, spiffe_id: &str, ttl: Duration, owner: (u32, u32)) -> Result<()> {
+        let (cert, key) = self.issue(spiffe_id, ttl)?;
+        fs::create_dir_all(dir)?;
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+        std::os::unix::fs::chown(dir, Some(owner.0), Some(owner.1))?;
+        write_atomic(&dir.join(BUNDLE), &self.cert.to_pem()?, 0o600, Some(owner))?;
+        write_atomic(&dir.join(SVID_KEY), &key.private_key_to_pem_pkcs8()?, 0o600, Some(owner))?;
+        write_atomic(&dir.join(SVID_CERT), &cert.to_pem()?, 0o600, Some(owner))?;
+        Ok(())
+    }
+
+    // whether the SVID has to be reissued: past half of its lifetime or not
+    // signed by this CA anymore
+    fn needs_rotation(&self, cert: &X509, now: i64) -> Result<bool> {
+        let public_key = self.cert.public_key()?;
+        if !cert.verify(&public_key).unwrap_or(false) {
+            return Ok(true);
+        }
+        let epoch = Asn1Time::from_unix(0)?;
+        let seconds = |time: &openssl::asn1::Asn1TimeRef| -> Result<i64> {
+            let diff = epoch.diff(time)?;
+            Ok(diff.days as i64 * 86400 + diff.secs as i64)
+        };
+        let (not_before, not_after) = (seconds(cert.not_before())?, seconds(cert.not_after())?);
+        Ok(now >= not_before + (not_after - not_before) / 2)
+    }
+}
+
+// SPIFFE ID of an issued SVID
+fn svid_spiffe_id(cert: &X509) -> Option<String> {
+    cert.subject_alt_names()?
+        .iter()
+        .find_map(|name| name.uri().filter(|uri| uri.starts_with("spiffe://")).map(str::to_string))
+}
+
+// make sure the sandbox has an SVID for its pod, owned by the uid and gid the
+// pod runs as, returning the directory to mount
+pub fn ensure_svid(
+    root_path: &Path,
+    pod_sandbox_id: &str,
+    namespace: &str,
+    service_account: &str,
+    owner: (u32, u32),
+) -> Result<PathBuf> {
+    let config = config().ok_or_else(|| anyhow!("workload identity is disabled"))?;
+    let dir = svid_dir(root_path, pod_sandbox_id);
+    let id = spiffe_id(&config.trust_domain, namespace, service_account);
+    let ca = CertificateAuthority::load_or_create(root_path, &config.trust_domain)?;
+    // a restarted container keeps the SVID of its pod while it is valid
+    if let Some(current) = fs::read(dir.join(SVID_CERT)).ok().and_then(|pem| X509::from_pem(&pem).ok())
+        && svid_spiffe_id(&current).as_deref() == Some(id.as_str())
+        && !ca.needs_rotation(&current, now_unix())?
+    {
+        return Ok(dir);
+    }
+    ca.write_svid(&dir, &id, config.svid_ttl, owner)
+        .map_err(|e| anyhow!("Failed to issue SVID {}: {}", id, e))?;
+    Ok(dir)
+}
+
+// reissue the SVIDs of every sandbox that are due, returning the rotated sandboxes
+pub fn rotate_all(root_path: &Path) -> Result<Vec<String>> {
+    let Some(config) = config() else {
+        return Ok(Vec::new());
+    };
+    let sandboxes = root_path.join("sandboxes");
+    if !sandboxes.exists() {
+        return Ok(Vec::new());
+    }
+    let ca = CertificateAuthority::load_or_create(root_path, &config.trust_domain)?;
+    let now = now_unix();
+    let mut rotated = Vec::new();
+    for entry in fs::read_dir(&sandboxes)?.flatten() {
+        let dir = entry.path().join("svid");
+        let Ok(pem) = fs::read(dir.join(SVID_CERT)) else {
+            continue;
+        };
+        let cert = X509::from_pem(&pem)?;
+        let Some(id) = svid_spiffe_id(&cert) else {
+            continue;
+        };
+        if ca.needs_rotation(&cert, now)? {
+            // the owner the SVID was first issued to
+            let metadata = fs::metadata(&dir)?;
+            ca.write_svid(&dir, &id, config.svid_ttl, (metadata.uid(), metadata.gid()))?;
+            rotated.push(entry.file_name().to_string_lossy().to_string());
+        }
+    }
+    Ok(rotated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::stack::Stack;
+    use openssl::x509::X509StoreContext;
+    use openssl::x509::store::X509StoreBuilder;
+
+    #[test]
+    fn test_issue_svid() {
+        let ca = CertificateAuthority::generate("rk8s.local").unwrap();
+        let id = spiffe_id("rk8s.local", "default", "web");
+        assert_eq!(id, "spiffe://rk8s.local/ns/default/sa/web");
+        let (cert, key) = ca.issue(&id, Duration::from_secs(3600)).unwrap();
+        assert_eq!(svid_spiffe_id(&cert).as_deref(), Some(id.as_str()));
+        assert!(cert.public_key().unwrap().public_eq(&key));
+
+        // the SVID chains up to the trust bundle
+        let mut store = X509StoreBuilder::new().unwrap();
+        store.add_cert(ca.cert.clone()).unwrap();
+        let store = store.build();
+        let mut context = X509StoreContext::new().unwrap();
+        let chain = Stack::new().unwrap();
+        assert!(context.init(&store, &cert, &chain, |c| c.verify_cert()).unwrap());
+
+        let now = now_unix();
+        assert!(!ca.needs_rotation(&cert, now).unwrap());
+        assert!(ca.needs_rotation(&cert, now + 1800).unwrap());
+        // an SVID of another CA is replaced right away
+        let other = CertificateAuthority::generate("rk8s.local").unwrap();
+        assert!(other.needs_rotation(&cert, now).unwrap());
+    }
+
+    #[test]
+    fn test_trust_domain() {
+        assert!(validate_trust_domain("rk8s.local").is_ok());
+        assert!(validate_trust_domain("").is_err());
+        assert!(validate_trust_domain("Example.org").is_err());
+        assert!(validate_trust_domain("rk8s.local/ns").is_err());
+    }
+}
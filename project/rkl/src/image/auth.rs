@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use anyhow::{Result, anyhow};
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+use crate::cri::cri::AuthConfig;
+use crate::image::DEFAULT_REGISTRY;
+use crate::secret;
+
+// The credentials of a pull reach skopeo and cosign in a registry config of
+// their own, never on their command lines where every user of the node sees
+// them: a 0600 config.json in a 0700 directory, removed once the command is
+// done. skopeo takes it as --authfile and cosign through $DOCKER_CONFIG. An
+// identity token is an OAuth refresh token, which both exchange for an access
+// token; a registry token is used as the access token as is, by cosign only.
+
+const AUTH_FILE_NAME: &str = "config.json";
+// the key Docker Hub credentials are stored under by docker login
+const DOCKER_HUB_KEY: &str = "https://index.docker.io/v1/";
+
+// docker-style config.json, only the parts needed to authenticate pulls
+#[derive(Debug, Default, Deserialize)]
+pub struct DockerConfig {
+    #[serde(default)]
+    pub auths: HashMap<String, DockerAuthEntry>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct DockerAuthEntry {
+    // base64 of "username:password"
+    #[serde(default)]
+    pub auth: Option<String>,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(rename = "identitytoken", default)]
+    pub identity_token: Option<String>,
+    #[serde(rename = "registrytoken", default)]
+    pub registry_token: Option<String>,
+}
+
+impl DockerConfig {
+    pub fn parse(contents: &str) -> Result<Self> {
+        Ok(serde_json::from_str(contents)?)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read registry credentials {}: {}", path.display(), e))?;
+        Self::parse(&contents).map_err(|e| anyhow!("Invalid registry credentials {}: {}", path.display(), e))
+    }
+
+    // credentials for a registry, e.g. "docker.io" or "registry.example.com:5000"
+    pub fn auth_for(&self, registry: &str) -> Option<AuthConfig> {
+        let (key, entry) = self.auths.iter().find(|(key, _)| normalize_registry(key) == registry)?;
+        let mut auth = AuthConfig {
+            server_address: key.clone(),
+            identity_token: entry.identity_token.clone().unwrap_or_default(),
+            registry_token: entry.registry_token.clone().unwrap_or_default(),
+            ..Default::default()
+        };
+        match (&entry.username, &entry.password, &entry.auth) {
+            (Some(username), Some(password), _) => {
+                auth.username = username.clone();
+                auth.password = password.clone();
+            }
+            (_, _, Some(encoded)) => {
+                let decoded = base64::engine::general_purpose::STANDARD.decode(encoded).ok()?;
+                let decoded = String::from_utf8(decoded).ok()?;
+                let (username, password) = decoded.split_once(':')?;
+                auth.username = username.to_string();
+                auth.password = password.to_string();
+                auth.auth = encoded.clone();
+            }
+            _ => {}
+        }
+        Some(auth)
+    }
+}
+
+// config.json keys can be full URLs ("https://index.docker.io/v1/"), compare on the host only
+pub fn normalize_registry(key: &str) -> String {
+    let host = key.trim_start_matches("https://").trim_start_matches("http://");
+    let host = host.split('/').next().unwrap_or(host);
+    match host {
+        "index.docker.io" | "registry-1.docker.io" | "docker.io" => "docker.io".to_string(),
+        _ => host.to_string(),
+    }
+}
+
+// the credentials of a pull for a registry, removed when dropped
+pub struct AuthFile {
+    dir: PathBuf,
+}
+
+impl AuthFile {
+    pub fn new(registry: &str, auth: &AuthConfig) -> Result<Self> {
+        let dir = env::temp_dir().join(format!("rkl-auth-{}", uuid::Uuid::new_v4()));
+        DirBuilder::new().mode(0o700).create(&dir)?;
+        let auth_file = AuthFile { dir };
+        let mut file = OpenOptions::new().write(true).create_new(true).mode(0o600).open(auth_file.path())?;
+        file.write_all(&serde_json::to_vec(&auth_json(registry, auth))?)?;
+        Ok(auth_file)
+    }
+
+    // the file for skopeo --authfile
+    pub fn path(&self) -> PathBuf {
+        self.dir.join(AUTH_FILE_NAME)
+    }
+
+    // the directory for $DOCKER_CONFIG
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+impl Drop for AuthFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn auth_json(registry: &str, auth: &AuthConfig) -> Value {
+    let mut entry = Map::new();
+    if !auth.username.is_empty() {
+        let encoded = base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", auth.username, auth.password));
+        entry.insert("auth".to_string(), json!(encoded));
+    }
+    if !auth.identity_token.is_empty() {
+        entry.insert("identitytoken".to_string(), json!(auth.identity_token));
+    }
+    if !auth.registry_token.is_empty() {
+        entry.insert("registrytoken".to_string(), json!(auth.registry_token));
+    }
+    let mut auths = Map::new();
+    if registry == DEFAULT_REGISTRY {
+        auths.insert(DOCKER_HUB_KEY.to_string(), Value::Object(entry.clone()));
+    }
+    auths.insert(registry.to_string(), Value::Object(entry));
+    json!({ "auths": auths })
+}
+
+// credentials of the user running rkl: $DOCKER_CONFIG/config.json or ~/.docker/config.json
+pub fn default_config_path() -> Option<PathBuf> {
+    if let Ok(dir) = env::var("DOCKER_CONFIG") {
+        return Some(PathBuf::from(dir).join("config.json"));
+    }
+    env::var("HOME").ok().map(|home| PathBuf::from(home).join(".docker/config.json"))
+}
+
+// an imagePullSecret is read from <root>/secrets/<name>/.dockerconfigjson,
+// the key Kubernetes uses for kubernetes.io/dockerconfigjson secrets
+pub fn pull_secret_path(root_path: &Path, name: &str) -> PathBuf {
+    root_path.join("secrets").join(name).join(secret::DOCKER_CONFIG_JSON_KEY)
+}
+
+// the plain file of a pull secret, or else the Secret of that name
+fn load_pull_secret(root_path: &Path, name: &str) -> Result<DockerConfig> {
+    let path = pull_secret_path(root_path, name);
+    if path.exists() || !secret::exists(root_path, name) {
+        return DockerConfig::load(&path);
+    }
+    let stored = secret::load(root_path, name)?;
+    if stored.secret_type != secret::DOCKER_CONFIG_JSON {
+        return Err(anyhow!("Secret {} is of type {}, not {}", name, stored.secret_type, secret::DOCKER_CONFIG_JSON));
+    }
+    let contents = stored.data
+        .get(secret::DOCKER_CONFIG_JSON_KEY)
+        .map(|value| String::from_utf8_lossy(value).to_string())
+        .ok_or_else(|| anyhow!("Secret {} has no {} key", name, secret::DOCKER_CONFIG_JSON_KEY))?;
+    DockerConfig::parse(&contents).map_err(|e| anyhow!("Invalid registry credentials of Secret {}: {}", name, e))
+}
+
+// find the credentials for the registry: the pod's pull secrets first, in order,
+// then the default docker config
+pub fn resolve(root_path: &Path, pull_secrets: &[String], registry: &str) -> Result<Option<AuthConfig>> {
+    for name in pull_secrets {
+        let config = load_pull_secret(root_path, name)?;
+        if let Some(auth) = config.auth_for(registry) {
+            return Ok(Some(auth));
+        }
+    }
+    if let Some(path) = default_config_path().filter(|p| p.exists()) {
+        return Ok(DockerConfig::load(&path)?.auth_for(registry));
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auth_for_registry() {
+        let config = DockerConfig::parse(
+            r#"{"auths": {
+                "https://index.docker.io/v1/": {"auth": "dXNlcjpwYXNz"},
+                "registry.example.com:5000": {"username": "bot", "password": "secret"}
+            }}"#,
+        )
+        .unwrap();
+
+        let auth = config.auth_for("docker.io").unwrap();
+        assert_eq!(auth.username, "user");
+        assert_eq!(auth.password, "pass");
+
+        let auth = config.auth_for("registry.example.com:5000").unwrap();
+        assert_eq!(auth.username, "bot");
+        assert!(config.auth_for("quay.io").is_none());
+    }
+
+    #[test]
+    fn test_auth_file() {
+        let auth = AuthConfig {
+            username: "user".to_string(),
+            password: "pass".to_string(),
+            identity_token: "refresh".to_string(),
+            ..Default::default()
+        };
+        let auth_file = AuthFile::new("docker.io", &auth).unwrap();
+        let path = auth_file.path();
+        let config = DockerConfig::load(&path).unwrap();
+        let entry = config.auth_for("docker.io").unwrap();
+        assert_eq!((entry.username.as_str(), entry.password.as_str()), ("user", "pass"));
+        assert_eq!(entry.identity_token, "refresh");
+        assert!(config.auths.contains_key(DOCKER_HUB_KEY));
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        assert_eq!(fs::metadata(auth_file.dir()).unwrap().permissions().mode() & 0o777, 0o700);
+        drop(auth_file);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_resolve_pull_secrets_first() {
+        let root = tempfile::tempdir().unwrap();
+        let path = pull_secret_path(root.path(), "regcred");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, r#"{"auths": {"quay.io": {"username": "a", "password": "b"}}}"#).unwrap();
+
+        let auth = resolve(root.path(), &["regcred".to_string()], "quay.io").unwrap().unwrap();
+        assert_eq!(auth.username, "a");
+        assert!(resolve(root.path(), &["missing".to_string()], "quay.io").is_err());
+    }
+}
@@ -0,0 +1,183 @@
+use std::io::{self, IsTerminal};
+use std::time::Duration;
+use anyhow::{Result, anyhow};
+use serde_json::json;
+use crate::cri::cri::{
+    FilesystemUsage, Image, ImageFilter, ImageFsInfoRequest, ImageFsInfoResponse, ImageSpec, ImageStatusRequest, ListImagesRequest,
+    ListImagesResponse, PullImageRequest, RemoveImageRequest, RemoveImageResponse,
+};
+use crate::cri::debug;
+use crate::events;
+use crate::image::{self, ImageReference, auth};
+use crate::image::pull::{self, PullOptions};
+use crate::printer::{Listing, OutputArgs};
+use crate::rootpath;
+use crate::runtime;
+use crate::stats::format_bytes;
+
+// `rkl image`: the images of the node, for operators to manage them without
+// crictl. With a CRI runtime they are those of its ImageService, otherwise
+// those of the image store of rkl, which the calls are served from the same
+// way. Pulls show how far they got (see pull.rs).
+
+fn spec(image: &str) -> Option<ImageSpec> {
+    Some(ImageSpec { image: image.to_string(), ..Default::default() })
+}
+
+fn list_images(filter: Option<&str>) -> Result<Vec<Image>> {
+    let backend = runtime::backend()?;
+    let request = ListImagesRequest { filter: filter.map(|image| ImageFilter { image: spec(image) }) };
+    let response = debug::traced("ListImages", request, |request| match &backend {
+        Some(backend) => backend.list_images(request),
+        None => Ok(ListImagesResponse { images: image::list_images(&rootpath::determine(None)?, filter) }),
+    })?;
+    Ok(response.images)
+}
+
+// the repository and tag of a repo tag or digest, e.g. docker.io/library/busybox:1.36
+fn split_repo(repo: &str) -> (&str, &str) {
+    if let Some((name, _)) = repo.split_once('@') {
+        return (name, "<none>");
+    }
+    match repo.rfind(':') {
+        Some(i) if !repo[i..].contains('/') => (&repo[..i], &repo[i + 1..]),
+        _ => (repo, "<none>"),
+    }
+}
+
+// the id of an image the way crictl shortens it
+fn short_id(id: &str) -> &str {
+    let id = id.trim_start_matches("sha256:");
+    &id[..id.len().min(13)]
+}
+
+// `rkl image ls`
+pub fn print_list(filter: Option<&str>, output: &OutputArgs) -> Result<()> {
+    let mut listing = Listing::new(&["IMAGE", "TAG", "IMAGE ID", "SIZE"]).with_wide(&["DIGEST"]);
+    for image in list_images(filter)? {
+        let digest = image.repo_digests.first().and_then(|repo| repo.split_once('@')).map_or("<none>", |(_, digest)| digest);
+        let repos = if image.repo_tags.is_empty() { image.repo_digests.iter().take(1).collect() } else { image.repo_tags.iter().collect::<Vec<_>>() };
+        for repo in repos {
+            let (name, tag) = split_repo(repo);
+            let object = json!({
+                "kind": "Image",
+                "metadata": { "name": repo },
+                "id": image.id,
+                "repoTags": image.repo_tags,
+                "repoDigests": image.repo_digests,
+                "size": image.size,
+            });
+            let cells = vec![name.to_string(), tag.to_string(), short_id(&image.id).to_string(), format_bytes(image.size), digest.to_string()];
+            listing.push(cells, object);
+        }
+    }
+    output.print(listing)
+}
+
+// `rkl image pull`, with the credentials of the pull secrets for its registry;
+// how far it got is shown on stderr while it is a terminal
+pub fn pull(image: &str, pull_secrets: &[String], timeout: Option<Duration>) -> Result<()> {
+    let reference = ImageReference::parse(image)?;
+    let root_path = rootpath::determine(None)?;
+    let auth = auth::resolve(&root_path, pull_secrets, &reference.registry)?;
+    let request = PullImageRequest { image: spec(image), auth, sandbox_config: None };
+    let options = PullOptions { timeout: timeout.or_else(pull::default_timeout), ..Default::default() };
+    let backend = runtime::backend()?;
+    let terminal = io::stderr().is_terminal();
+    eprintln!("Pulling {}", reference);
+    let response = debug::traced("PullImage", request, |request| {
+        pull::pull(backend.as_deref(), &root_path, &request, &options, |progress| {
+            if terminal {
+                eprint!("\r\x1b[2K{} in {}", progress, events::age(progress.elapsed.as_secs()));
+            }
+        })
+    });
+    if terminal {
+        eprint!("\r\x1b[2K");
+    }
+    let response = response?;
+    println!("Image {} pulled", response.image_ref);
+    Ok(())
+}
+
+fn remove_image(image: &str) -> Result<()> {
+    let backend = runtime::backend()?;
+    let request = RemoveImageRequest { image: spec(image) };
+    debug::traced("RemoveImage", request, |request| match &backend {
+        Some(backend) => {
+            // the CRI runtimes succeed removing an image that isn't there
+            let status = backend.image_status(ImageStatusRequest { image: request.image.clone(), verbose: false })?;
+            if status.image.is_none() {
+                return Err(anyhow!("image {} not found", image));
+            }
+            backend.remove_image(request)
+        }
+        None => {
+            image::remove_image(&rootpath::determine(None)?, image)?;
+            Ok(RemoveImageResponse {})
+        }
+    })?;
+    Ok(())
+}
+
+// `rkl image rm`, every image even when one fails
+pub fn remove(images: &[String]) -> Result<()> {
+    let mut failed = Vec::new();
+    for image in images {
+        match remove_image(image) {
+            Ok(()) => println!("Image {} removed", image),
+            Err(e) => {
+                eprintln!("{}: {}", image, e);
+                failed.push(image.as_str());
+            }
+        }
+    }
+    if failed.is_empty() {
+        return Ok(());
+    }
+    Err(anyhow!("Failed to remove {} of {} image(s): {}", failed.len(), images.len(), failed.join(", ")))
+}
+
+fn fs_info_rows(kind: &str, filesystems: &[FilesystemUsage], listing: &mut Listing) {
+    for usage in filesystems {
+        let mountpoint = usage.fs_id.as_ref().map(|id| id.mountpoint.clone()).unwrap_or_default();
+        let used = usage.used_bytes.as_ref().map(|used| used.value).unwrap_or_default();
+        let inodes = usage.inodes_used.as_ref().map(|inodes| inodes.value).unwrap_or_default();
+        let object = json!({
+            "kind": "Filesystem",
+            "metadata": { "name": mountpoint },
+            "type": kind,
+            "usedBytes": used,
+            "inodesUsed": inodes,
+        });
+        listing.push(vec![kind.to_string(), mountpoint, format_bytes(used), inodes.to_string()], object);
+    }
+}
+
+// `rkl image fsinfo`: the filesystems of the images, and of the writable
+// layers of the containers when the runtime keeps them apart
+pub fn print_fs_info(output: &OutputArgs) -> Result<()> {
+    let backend = runtime::backend()?;
+    let response = debug::traced("ImageFsInfo", ImageFsInfoRequest {}, |request| match &backend {
+        Some(backend) => backend.image_fs_info(request),
+        None => Ok(ImageFsInfoResponse { image_filesystems: vec![image::fs_info(&rootpath::determine(None)?)], ..Default::default() }),
+    })?;
+    let mut listing = Listing::new(&["TYPE", "MOUNTPOINT", "USED", "INODES"]);
+    fs_info_rows("image", &response.image_filesystems, &mut listing);
+    fs_info_rows("container", &response.container_filesystems, &mut listing);
+    output.print(listing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_repo() {
+        assert_eq!(split_repo("docker.io/library/busybox:1.36"), ("docker.io/library/busybox", "1.36"));
+        assert_eq!(split_repo("registry.local:5000/web"), ("registry.local:5000/web", "<none>"));
+        assert_eq!(split_repo("quay.io/org/app@sha256:abcd"), ("quay.io/org/app", "<none>"));
+        assert_eq!(short_id("sha256:0123456789abcdef0123"), "0123456789abc");
+        assert_eq!(short_id("nginx:1.27"), "nginx:1.27");
+    }
+}
@@ -0,0 +1,492 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+use crate::cri::cri::{FilesystemIdentifier, FilesystemUsage, Image, PullImageRequest, PullImageResponse, UInt64Value};
+use crate::stats;
+use self::pull::Cancel;
+
+pub mod auth;
+pub mod manage;
+pub mod platform;
+pub mod pull;
+pub mod verify;
+
+// An image is either the path of an OCI bundle on the node, which is used as is,
+// or a registry reference. References are pulled with skopeo into an OCI image
+// layout under <root>/images and unpacked into a bundle with umoci. There is a
+// layout per repository, <root>/images/<registry>/<repository>, each pulled
+// tag or digest a manifest of its index.json.
+
+pub const DEFAULT_REGISTRY: &str = "docker.io";
+
+pub fn is_bundle_path(image: &str) -> bool {
+    image.starts_with('/') || image.starts_with("./") || image.starts_with("../")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageReference {
+    pub registry: String,
+    pub repository: String,
+    pub tag: Option<String>,
+    pub digest: Option<String>,
+}
+
+impl ImageReference {
+    // parse references like busybox, quay.io/org/app:v1 or app@sha256:<digest>
+    pub fn parse(image: &str) -> Result<Self> {
+        if image.is_empty() {
+            return Err(anyhow!("empty image reference"));
+        }
+        let (name, digest) = match image.split_once('@') {
+            Some((name, digest)) => (name, Some(digest.to_string())),
+            None => (image, None),
+        };
+        if let Some(digest) = &digest
+            && !is_digest(digest)
+        {
+            return Err(anyhow!("invalid digest {} of image {}, expected <algorithm>:<hex> like sha256:<digest>", digest, image));
+        }
+        // a ':' after the last '/' separates the tag, one before it is a registry port
+        let (name, tag) = match name.rfind(':') {
+            Some(i) if !name[i..].contains('/') => (&name[..i], Some(name[i + 1..].to_string())),
+            _ => (name, None),
+        };
+
+        let (registry, repository) = match name.split_once('/') {
+            Some((first, rest)) if first.contains('.') || first.contains(':') || first == "localhost" => {
+                (first.to_string(), rest.to_string())
+            }
+            _ => (DEFAULT_REGISTRY.to_string(), name.to_string()),
+        };
+        let repository = if registry == DEFAULT_REGISTRY && !repository.contains('/') {
+            format!("library/{}", repository)
+        } else {
+            repository
+        };
+        let tag = if tag.is_none() && digest.is_none() { Some("latest".to_string()) } else { tag };
+
+        Ok(ImageReference { registry, repository, tag, digest })
+    }
+
+    // name of the image inside its OCI layout, digests can't be layout tags as is
+    pub fn layout_tag(&self) -> String {
+        match (&self.digest, &self.tag) {
+            (Some(digest), _) => digest.replace(':', "-"),
+            (None, Some(tag)) => tag.clone(),
+            (None, None) => "latest".to_string(),
+        }
+    }
+
+    // the reference registries are pulled from by, the digest alone when there
+    // is one: skopeo refuses a tag next to it, which is only there for people
+    pub fn pull_name(&self) -> String {
+        match &self.digest {
+            Some(digest) => format!("{}/{}@{}", self.registry, self.repository, digest),
+            None => self.to_string(),
+        }
+    }
+
+    // the same image pinned to a digest, keeping the tag
+    pub fn pinned(&self, digest: &str) -> ImageReference {
+        ImageReference { digest: Some(digest.to_string()), ..self.clone() }
+    }
+}
+
+fn is_digest(digest: &str) -> bool {
+    digest
+        .split_once(':')
+        .is_some_and(|(algorithm, hex)| !algorithm.is_empty() && !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+impl fmt::Display for ImageReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.registry, self.repository)?;
+        if let Some(tag) = &self.tag {
+            write!(f, ":{}", tag)?;
+        }
+        if let Some(digest) = &self.digest {
+            write!(f, "@{}", digest)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn image_store_dir(root_path: &Path) -> PathBuf {
+    root_path.join("images")
+}
+
+// OCI image layout holding the tags of one repository
+fn layout_dir(root_path: &Path, reference: &ImageReference) -> PathBuf {
+    image_store_dir(root_path).join(&reference.registry).join(&reference.repository)
+}
+
+// pull the image of the request into the local image store
+pub fn pull_image(root_path: &Path, request: &PullImageRequest) -> Result<PullImageResponse> {
+    pull_image_cancellable(root_path, request, &Cancel::default())
+}
+
+// pull_image, killing skopeo once cancelled
+pub fn pull_image_cancellable(root_path: &Path, request: &PullImageRequest, cancel: &Cancel) -> Result<PullImageResponse> {
+    let image = request.image.as_ref().ok_or_else(|| anyhow!("image is required"))?;
+    let reference = ImageReference::parse(&image.image)?;
+    let layout = layout_dir(root_path, &reference);
+    fs::create_dir_all(&layout)?;
+
+    let mut command = Command::new("skopeo");
+    command.arg("copy").arg("--quiet");
+    // kept until skopeo is done
+    let auth_file = request.auth.as_ref().map(|auth| auth::AuthFile::new(&reference.registry, auth)).transpose()?;
+    if let Some(auth_file) = &auth_file {
+        command.arg("--authfile").arg(auth_file.path());
+    }
+    command
+        .arg(format!("docker://{}", reference.pull_name()))
+        .arg(format!("oci:{}:{}", layout.display(), reference.layout_tag()))
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+    let mut child = command
+        .spawn()
+        .map_err(|e| anyhow!("Failed to run skopeo to pull {}: {}", reference, e))?;
+    // read aside so that skopeo can't block on a full stderr while it is waited for
+    let stderr = child.stderr.take();
+    let errors = thread::spawn(move || {
+        let mut errors = String::new();
+        if let Some(mut stderr) = stderr {
+            let _ = stderr.read_to_string(&mut errors);
+        }
+        errors
+    });
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break Some(status);
+        }
+        if cancel.is_cancelled() {
+            let _ = child.kill();
+            let _ = child.wait();
+            break None;
+        }
+        thread::sleep(pull::CANCEL_POLL);
+    };
+    let errors = errors.join().unwrap_or_default();
+    match status {
+        None => Err(anyhow!("Pull of {} cancelled", reference)),
+        Some(status) if !status.success() => Err(anyhow!("Failed to pull {}: {}", reference, errors.trim())),
+        Some(_) => Ok(PullImageResponse { image_ref: reference.to_string() }),
+    }
+}
+
+pub fn is_present(root_path: &Path, image: &str) -> bool {
+    let Ok(reference) = ImageReference::parse(image) else {
+        return false;
+    };
+    layout_tags(&layout_dir(root_path, &reference)).contains(&reference.layout_tag())
+}
+
+// tags recorded in the index.json of an OCI image layout
+fn layout_tags(layout: &Path) -> Vec<String> {
+    let Ok(index) = fs::read_to_string(layout.join("index.json")) else {
+        return Vec::new();
+    };
+    let Ok(index) = serde_json::from_str::<serde_json::Value>(&index) else {
+        return Vec::new();
+    };
+    index["manifests"]
+        .as_array()
+        .map(|manifests| {
+            manifests
+                .iter()
+                .filter_map(|m| m["annotations"]["org.opencontainers.image.ref.name"].as_str())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// a manifest of the index.json of a layout
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    layout: PathBuf,
+    reference: ImageReference,
+    // the digest of the image config, the id of the image like in the CRI runtimes
+    id: String,
+    manifest_digest: String,
+    size: u64,
+}
+
+fn blob_path(layout: &Path, digest: &str) -> PathBuf {
+    let (algorithm, hex) = digest.split_once(':').unwrap_or(("sha256", digest));
+    layout.join("blobs").join(algorithm).join(hex)
+}
+
+fn read_json(path: &Path) -> Option<Value> {
+    serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+// the config and layer digests of a manifest of a layout with their total size
+fn manifest_blobs(layout: &Path, manifest_digest: &str) -> (Vec<String>, u64) {
+    read_json(&blob_path(layout, manifest_digest)).map(|manifest| descriptors(&manifest)).unwrap_or_default()
+}
+
+// the config and layer digests of a manifest with their total size
+fn descriptors(manifest: &Value) -> (Vec<String>, u64) {
+    let descriptors = std::iter::once(&manifest["config"]).chain(manifest["layers"].as_array().into_iter().flatten());
+    let mut digests = Vec::new();
+    let mut size = 0;
+    for descriptor in descriptors {
+        if let Some(digest) = descriptor["digest"].as_str() {
+            digests.push(digest.to_string());
+            size += descriptor["size"].as_u64().unwrap_or_default();
+        }
+    }
+    (digests, size)
+}
+
+// the manifests of a layout
+fn layout_entries(layout: &Path, registry: &str, repository: &str) -> Vec<Entry> {
+    let Some(index) = read_json(&layout.join("index.json")) else {
+        return Vec::new();
+    };
+    let mut entries = Vec::new();
+    for manifest in index["manifests"].as_array().into_iter().flatten() {
+        let (Some(tag), Some(manifest_digest)) = (manifest["annotations"]["org.opencontainers.image.ref.name"].as_str(), manifest["digest"].as_str()) else {
+            continue;
+        };
+        // digests are layout tags with a - for the :, see layout_tag
+        let (tag, digest) = match tag.split_once('-') {
+            Some((algorithm, hex)) if algorithm == "sha256" && hex.len() == 64 => (None, Some(format!("{}:{}", algorithm, hex))),
+            _ => (Some(tag.to_string()), None),
+        };
+        let (blobs, size) = manifest_blobs(layout, manifest_digest);
+        entries.push(Entry {
+            layout: layout.to_path_buf(),
+            reference: ImageReference { registry: registry.to_string(), repository: repository.to_string(), tag, digest },
+            id: blobs.first().cloned().unwrap_or_else(|| manifest_digest.to_string()),
+            manifest_digest: manifest_digest.to_string(),
+            size: size + manifest["size"].as_u64().unwrap_or_default(),
+        });
+    }
+    entries
+}
+
+// the manifests of every layout of the store
+fn entries(root_path: &Path) -> Vec<Entry> {
+    let store = image_store_dir(root_path);
+    let mut entries = Vec::new();
+    let mut dirs = vec![store.clone()];
+    while let Some(dir) = dirs.pop() {
+        if dir.join("index.json").is_file() {
+            let relative = dir.strip_prefix(&store).unwrap_or(&dir).to_string_lossy().into_owned();
+            if let Some((registry, repository)) = relative.split_once('/') {
+                entries.extend(layout_entries(&dir, registry, repository));
+            }
+            continue;
+        }
+        let Ok(children) = fs::read_dir(&dir) else {
+            continue;
+        };
+        dirs.extend(children.flatten().map(|child| child.path()).filter(|path| path.is_dir()));
+    }
+    entries
+}
+
+// whether an image given by reference or id, with or without sha256:, is the one of an entry
+fn matches(entry: &Entry, image: &str) -> bool {
+    let id = entry.id.trim_start_matches("sha256:");
+    if !image.is_empty() && (image == entry.id || image == id) {
+        return true;
+    }
+    let Ok(reference) = ImageReference::parse(image) else {
+        return false;
+    };
+    // by digest the tag doesn't matter, the digest is the one pulled or of the manifest
+    match &reference.digest {
+        Some(digest) => {
+            reference.registry == entry.reference.registry
+                && reference.repository == entry.reference.repository
+                && (entry.reference.digest.as_ref() == Some(digest) || entry.manifest_digest == *digest)
+        }
+        None => reference == entry.reference,
+    }
+}
+
+// the images of the store as the CRI lists them, the tags and digests of an
+// image id together; only those of an image reference or id when given
+pub fn list_images(root_path: &Path, image: Option<&str>) -> Vec<Image> {
+    let mut images: BTreeMap<String, Image> = BTreeMap::new();
+    for entry in entries(root_path) {
+        if image.is_some_and(|image| !matches(&entry, image)) {
+            continue;
+        }
+        let name = format!("{}/{}", entry.reference.registry, entry.reference.repository);
+        let image = images.entry(entry.id.clone()).or_insert_with(|| Image { id: entry.id.clone(), size: entry.size, ..Default::default() });
+        if let Some(tag) = &entry.reference.tag {
+            image.repo_tags.push(format!("{}:{}", name, tag));
+        }
+        // the digest pulled by is the one of the index for images of several platforms
+        for digest in [Some(&entry.manifest_digest), entry.reference.digest.as_ref()].into_iter().flatten() {
+            let digest = format!("{}@{}", name, digest);
+            if !image.repo_digests.contains(&digest) {
+                image.repo_digests.push(digest);
+            }
+        }
+    }
+    images.into_values().collect()
+}
+
+// remove an image given by reference or id from the store, with the blobs
+// no other tag of its repository uses
+pub fn remove_image(root_path: &Path, image: &str) -> Result<()> {
+    let removed: Vec<Entry> = entries(root_path).into_iter().filter(|entry| matches(entry, image)).collect();
+    if removed.is_empty() {
+        return Err(anyhow!("image {} not found", image));
+    }
+    let layouts: HashSet<&Path> = removed.iter().map(|entry| entry.layout.as_path()).collect();
+    for layout in layouts {
+        let tags: HashSet<String> = removed.iter().filter(|entry| entry.layout == layout).map(|entry| entry.reference.layout_tag()).collect();
+        let mut index = read_json(&layout.join("index.json")).ok_or_else(|| anyhow!("invalid image layout {}", layout.display()))?;
+        if let Some(manifests) = index["manifests"].as_array_mut() {
+            manifests.retain(|manifest| {
+                manifest["annotations"]["org.opencontainers.image.ref.name"].as_str().is_none_or(|tag| !tags.contains(tag))
+            });
+            if manifests.is_empty() {
+                fs::remove_dir_all(layout)?;
+                continue;
+            }
+        }
+        fs::write(layout.join("index.json"), serde_json::to_vec(&index)?)?;
+        collect_garbage(layout, &index);
+    }
+    Ok(())
+}
+
+// remove the blobs no manifest of the index refers to anymore
+fn collect_garbage(layout: &Path, index: &Value) {
+    let mut used = HashSet::new();
+    for manifest in index["manifests"].as_array().into_iter().flatten() {
+        if let Some(digest) = manifest["digest"].as_str() {
+            used.extend(manifest_blobs(layout, digest).0);
+            used.insert(digest.to_string());
+        }
+    }
+    let used: HashSet<PathBuf> = used.iter().map(|digest| blob_path(layout, digest)).collect();
+    let Ok(algorithms) = fs::read_dir(layout.join("blobs")) else {
+        return;
+    };
+    for blob in algorithms.flatten().filter_map(|algorithm| fs::read_dir(algorithm.path()).ok()).flatten().flatten() {
+        if !used.contains(&blob.path()) {
+            let _ = fs::remove_file(blob.path());
+        }
+    }
+}
+
+// the usage of the filesystem of the store, by the store
+pub fn fs_info(root_path: &Path) -> FilesystemUsage {
+    let store = image_store_dir(root_path);
+    let (used_bytes, inodes_used) = stats::disk_usage(&store);
+    FilesystemUsage {
+        timestamp: stats::now_nanos(),
+        fs_id: Some(FilesystemIdentifier { mountpoint: store.display().to_string() }),
+        used_bytes: Some(UInt64Value { value: used_bytes }),
+        inodes_used: Some(UInt64Value { value: inodes_used }),
+    }
+}
+
+// unpack a pulled image into a fresh bundle directory
+pub fn unpack(root_path: &Path, image: &str, bundle_dir: &Path) -> Result<()> {
+    let reference = ImageReference::parse(image)?;
+    if bundle_dir.exists() {
+        fs::remove_dir_all(bundle_dir)?;
+    }
+    if let Some(parent) = bundle_dir.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let output = Command::new("umoci")
+        .arg("unpack")
+        .arg("--image")
+        .arg(format!("{}:{}", layout_dir(root_path, &reference).display(), reference.layout_tag()))
+        .arg(bundle_dir)
+        .output()
+        .map_err(|e| anyhow!("Failed to run umoci to unpack {}: {}", reference, e))?;
+    if !output.status.success() {
+        return Err(anyhow!("Failed to unpack {}: {}", reference, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_image_reference() {
+        let reference = ImageReference::parse("busybox").unwrap();
+        assert_eq!(reference.to_string(), "docker.io/library/busybox:latest");
+
+        let reference = ImageReference::parse("registry.example.com:5000/team/app:v1").unwrap();
+        assert_eq!(reference.registry, "registry.example.com:5000");
+        assert_eq!(reference.repository, "team/app");
+        assert_eq!(reference.tag.as_deref(), Some("v1"));
+
+        let reference = ImageReference::parse("quay.io/org/app@sha256:abcd").unwrap();
+        assert_eq!(reference.tag, None);
+        assert_eq!(reference.digest.as_deref(), Some("sha256:abcd"));
+        assert_eq!(reference.layout_tag(), "sha256-abcd");
+        assert_eq!(reference.pull_name(), "quay.io/org/app@sha256:abcd");
+
+        let reference = ImageReference::parse("nginx:1.27@sha256:ef01").unwrap();
+        assert_eq!(reference.to_string(), "docker.io/library/nginx:1.27@sha256:ef01");
+        assert_eq!(reference.pull_name(), "docker.io/library/nginx@sha256:ef01");
+        assert_eq!(ImageReference::parse("nginx:1.27").unwrap().pinned("sha256:ef01"), reference);
+        assert!(ImageReference::parse("nginx@sha256").is_err());
+        assert!(ImageReference::parse("nginx@sha256:xyz").is_err());
+    }
+
+    #[test]
+    fn test_store() {
+        use sha2::Digest;
+        let root = tempfile::tempdir().unwrap();
+        let layout = image_store_dir(root.path()).join("docker.io/library/busybox");
+        let blob = |content: &str| {
+            let digest = format!("sha256:{:x}", sha2::Sha256::digest(content));
+            let path = blob_path(&layout, &digest);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+            digest
+        };
+        let manifest = |config: &str, layer: &str| {
+            let (config, layer) = (blob(config), blob(layer));
+            blob(&serde_json::json!({ "config": { "digest": config, "size": 10 }, "layers": [{ "digest": layer, "size": 100 }] }).to_string())
+        };
+        let (old, new) = (manifest("old config", "old layer"), manifest("new config", "new layer"));
+        let entry = |digest: &str, tag: &str| serde_json::json!({ "digest": digest, "size": 1, "annotations": { "org.opencontainers.image.ref.name": tag } });
+        let index = serde_json::json!({ "manifests": [entry(&old, "1.36"), entry(&new, "latest"), entry(&new, "1.37")] });
+        fs::write(layout.join("index.json"), index.to_string()).unwrap();
+
+        let images = list_images(root.path(), None);
+        assert_eq!(images.len(), 2);
+        let latest = images.iter().find(|image| image.repo_tags.contains(&"docker.io/library/busybox:latest".to_string())).unwrap();
+        assert_eq!(latest.repo_tags, ["docker.io/library/busybox:latest", "docker.io/library/busybox:1.37"]);
+        assert_eq!(latest.repo_digests, [format!("docker.io/library/busybox@{}", new)]);
+        assert_eq!(latest.size, 111);
+        assert_eq!(list_images(root.path(), Some("busybox:1.36")).len(), 1);
+        assert_eq!(list_images(root.path(), Some(latest.id.trim_start_matches("sha256:")))[0].id, latest.id);
+        // by digest whatever the tag
+        assert_eq!(list_images(root.path(), Some(&format!("busybox:1.36@{}", new)))[0].id, latest.id);
+
+        remove_image(root.path(), "busybox:1.36").unwrap();
+        assert!(!blob_path(&layout, &old).exists());
+        assert!(blob_path(&layout, &new).exists());
+        assert!(is_present(root.path(), "busybox:1.37"));
+        assert!(remove_image(root.path(), "busybox:1.36").is_err());
+        remove_image(root.path(), &latest.id).unwrap();
+        assert!(!layout.exists());
+        assert!(list_images(root.path(), None).is_empty());
+        assert!(fs_info(root.path()).fs_id.unwrap().mountpoint.ends_with("images"));
+    }
+}
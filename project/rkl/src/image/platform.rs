@@ -0,0 +1,198 @@
+use std::fmt;
+use std::fs;
+use std::sync::OnceLock;
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+use crate::cri::cri::AuthConfig;
+use crate::image::ImageReference;
+use crate::image::pull;
+
+// The platform of the node the way image indexes name it, e.g. linux/amd64
+// or linux/arm/v7, which is also where the kubernetes.io/os and
+// kubernetes.io/arch labels of the node come from (see node.rs). Images of
+// several platforms are pulled by the manifest of the node's platform: the
+// same os and architecture, with the node's variant or none, and for arm the
+// newest variant the node runs, a v7 node also runs v6 images. Before an
+// image is pulled its index is checked for a manifest of the node's platform,
+// or the config of an image of a single platform for that platform, so that
+// such an image is refused up front instead of failing with an exec format
+// error once it runs. An image that can't be inspected is left to the pull.
+// The CRI runtimes of Windows nodes pick the manifest of their own platform.
+
+// image indexes list attestations under this os and architecture
+const UNKNOWN: &str = "unknown";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Platform {
+    pub os: String,
+    pub architecture: String,
+    pub variant: Option<String>,
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.os, self.architecture)?;
+        if let Some(variant) = &self.variant {
+            write!(f, "/{}", variant)?;
+        }
+        Ok(())
+    }
+}
+
+// the architecture of this node as named by Go and therefore Kubernetes and
+// image platforms
+pub fn architecture() -> &'static str {
+    match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "x86" => "386",
+        "aarch64" => "arm64",
+        "powerpc64" if cfg!(target_endian = "little") => "ppc64le",
+        "powerpc64" => "ppc64",
+        "loongarch64" => "loong64",
+        arch => arch,
+    }
+}
+
+// v7 is 7
+fn arm_version(variant: &str) -> Option<u32> {
+    variant.strip_prefix('v')?.parse().ok()
+}
+
+// the variant of the architecture, the arm version of the CPU for arm
+fn variant(architecture: &str) -> Option<String> {
+    match architecture {
+        "arm64" => Some("v8".to_string()),
+        "arm" => {
+            let cpuinfo = fs::read_to_string("/proc/cpuinfo").ok()?;
+            let version = cpuinfo.lines().find_map(|line| line.strip_prefix("CPU architecture"))?;
+            let version: u32 = version.trim_start_matches([' ', '\t', ':']).chars().take_while(char::is_ascii_digit).collect::<String>().parse().ok()?;
+            // an armv8 CPU runs 32-bit arm as v7
+            Some(format!("v{}", version.min(7)))
+        }
+        _ => None,
+    }
+}
+
+// the platform of this node
+pub fn node() -> &'static Platform {
+    static NODE: OnceLock<Platform> = OnceLock::new();
+    NODE.get_or_init(|| Platform { os: "linux".to_string(), architecture: architecture().to_string(), variant: variant(architecture()) })
+}
+
+impl Platform {
+    // the platform of an index entry or of an image config
+    pub fn from_json(value: &Value) -> Option<Platform> {
+        Some(Platform {
+            os: value["os"].as_str()?.to_string(),
+            architecture: value["architecture"].as_str()?.to_string(),
+            variant: value["variant"].as_str().filter(|variant| !variant.is_empty()).map(str::to_string),
+        })
+    }
+
+    // whether an image of the other platform runs on this one
+    pub fn runs(&self, other: &Platform) -> bool {
+        if self.os != other.os || self.architecture != other.architecture {
+            return false;
+        }
+        match (&self.variant, &other.variant) {
+            (_, None) => true,
+            (Some(own), Some(theirs)) if self.architecture == "arm" => {
+                matches!((arm_version(own), arm_version(theirs)), (Some(own), Some(theirs)) if theirs <= own)
+            }
+            (own, Some(theirs)) => own.as_ref() == Some(theirs),
+        }
+    }
+}
+
+// the digest of the manifest of an index for the platform, the one with the
+// closest variant
+pub fn select<'a>(index: &'a Value, platform: &Platform) -> Option<&'a str> {
+    let rank = |candidate: &Platform| candidate.variant.as_deref().map_or(0, |variant| arm_version(variant).unwrap_or(u32::MAX));
+    index["manifests"]
+        .as_array()?
+        .iter()
+        .filter_map(|entry| Some((Platform::from_json(&entry["platform"])?, entry["digest"].as_str()?)))
+        .filter(|(candidate, _)| platform.runs(candidate))
+        .min_by_key(|(candidate, _)| std::cmp::Reverse(rank(candidate)))
+        .map(|(_, digest)| digest)
+}
+
+// the platforms of the manifests of an index, without the attestations
+pub fn platforms(index: &Value) -> Vec<String> {
+    index["manifests"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| Platform::from_json(&entry["platform"]))
+        .filter(|platform| platform.os != UNKNOWN)
+        .map(|platform| platform.to_string())
+        .collect()
+}
+
+// why an image of the raw manifest, and of the config for a single platform,
+// doesn't run on the platform, None when it does
+fn mismatch(manifest: &Value, config: impl FnOnce() -> Option<Value>, platform: &Platform) -> Option<String> {
+    if manifest["manifests"].is_array() {
+        if select(manifest, platform).is_some() {
+            return None;
+        }
+        let available = platforms(manifest);
+        let available = if available.is_empty() { "none".to_string() } else { available.join(", ") };
+        return Some(format!("has no manifest for {}, only for {}", platform, available));
+    }
+    let image = Platform::from_json(&config()?)?;
+    (!platform.runs(&image)).then(|| format!("is built for {}, not {}", image, platform))
+}
+
+// refuse an image without a manifest for the platform before it is pulled
+pub fn check(reference: &ImageReference, auth: Option<&AuthConfig>, platform: &Platform) -> Result<()> {
+    let image = reference.pull_name();
+    let Some(manifest) = pull::inspect(&image, auth).ok().and_then(|raw| serde_json::from_slice::<Value>(&raw).ok()) else {
+        return Ok(());
+    };
+    let config = || pull::inspect_config(&image, auth).ok().and_then(|raw| serde_json::from_slice::<Value>(&raw).ok());
+    match mismatch(&manifest, config, platform) {
+        Some(reason) => Err(anyhow!("image {} {}", reference, reason)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_select() {
+        let platform = |os: &str, architecture: &str, variant: Option<&str>| Platform {
+            os: os.to_string(),
+            architecture: architecture.to_string(),
+            variant: variant.map(str::to_string),
+        };
+        let entry = |digest: &str, platform: Value| json!({ "digest": digest, "platform": platform });
+        let index = json!({ "manifests": [
+            entry("sha256:amd64", json!({ "os": "linux", "architecture": "amd64" })),
+            entry("sha256:armv6", json!({ "os": "linux", "architecture": "arm", "variant": "v6" })),
+            entry("sha256:armv7", json!({ "os": "linux", "architecture": "arm", "variant": "v7" })),
+            entry("sha256:arm64", json!({ "os": "linux", "architecture": "arm64", "variant": "v8" })),
+            entry("sha256:attestation", json!({ "os": "unknown", "architecture": "unknown" })),
+        ]});
+        assert_eq!(select(&index, &platform("linux", "amd64", None)), Some("sha256:amd64"));
+        assert_eq!(select(&index, &platform("linux", "arm", Some("v7"))), Some("sha256:armv7"));
+        assert_eq!(select(&index, &platform("linux", "arm", Some("v6"))), Some("sha256:armv6"));
+        assert_eq!(select(&index, &platform("linux", "arm64", Some("v8"))), Some("sha256:arm64"));
+        assert_eq!(select(&index, &platform("windows", "amd64", None)), None);
+
+        let s390x = platform("linux", "s390x", None);
+        assert_eq!(
+            mismatch(&index, || None, &s390x).unwrap(),
+            "has no manifest for linux/s390x, only for linux/amd64, linux/arm/v6, linux/arm/v7, linux/arm64/v8"
+        );
+        // a single manifest goes by its config
+        let manifest = json!({ "config": { "digest": "sha256:c0" }, "layers": [] });
+        let config = || Some(json!({ "os": "linux", "architecture": "amd64" }));
+        assert_eq!(mismatch(&manifest, config, &s390x).unwrap(), "is built for linux/amd64, not linux/s390x");
+        assert!(mismatch(&manifest, config, &platform("linux", "amd64", None)).is_none());
+        assert!(mismatch(&manifest, || None, &s390x).is_none());
+    }
+}
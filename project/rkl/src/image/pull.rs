@@ -0,0 +1,291 @@
+use std::fmt;
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use crate::cri::cri::{AuthConfig, ImageFsInfoRequest, PullImageRequest, PullImageResponse};
+use crate::events;
+use crate::image::{self, ImageReference, platform, verify};
+use crate::runtime::RuntimeBackend;
+use crate::stats::{self, format_bytes};
+
+// Pulls that say how far they got, give up after --image-pull-timeout and can
+// be cancelled. The CRI PullImage is a single call without progress, so while
+// it runs the bytes pulled are measured: the growth of the image filesystem
+// the runtime reports with ImageFsInfo, which containerd only refreshes every
+// few seconds, or of the layout the image store pulls into. For the store the
+// manifest is looked up first, which gives the total to pull. A pull that fails
+// says how far it got, from which registry and of which manifest. With
+// --cosign-public-key images are only pulled once their signature is verified
+// (see verify.rs), and images without a manifest for the platform of the node
+// aren't pulled at all (see platform.rs).
+//
+// Cancelling a pull drops the PullImage call, which the CRI runtimes stop
+// pulling on, or kills skopeo for the store; interrupting `rkl image pull`
+// does the same.
+
+// how often the bytes pulled are measured
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+// how often cancellation is checked
+pub const CANCEL_POLL: Duration = Duration::from_millis(100);
+
+static TIMEOUT: OnceLock<Option<Duration>> = OnceLock::new();
+
+// set once at startup from --image-pull-timeout
+pub fn init(timeout: Option<Duration>) {
+    let _ = TIMEOUT.set(timeout);
+}
+
+pub fn default_timeout() -> Option<Duration> {
+    TIMEOUT.get().copied().flatten()
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Cancel(Arc<AtomicBool>);
+
+impl Cancel {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PullOptions {
+    pub timeout: Option<Duration>,
+    pub cancel: Cancel,
+}
+
+impl Default for PullOptions {
+    fn default() -> Self {
+        PullOptions { timeout: default_timeout(), cancel: Cancel::default() }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    pub bytes: u64,
+    // when the manifest is known
+    pub total: Option<u64>,
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", format_bytes(self.bytes))?;
+        if let Some(total) = self.total.filter(|total| *total > 0) {
+            let percent = self.bytes.min(total) as u128 * 100 / total as u128;
+            write!(f, " of {} ({}%)", format_bytes(total), percent)?;
+        }
+        Ok(())
+    }
+}
+
+// the manifest an image is pulled by, for this platform
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub digest: String,
+    // of the config and the layers
+    pub size: u64,
+}
+
+// a raw manifest, or the digest of the manifest of this platform of an index
+fn parse_manifest(raw: &[u8]) -> Result<std::result::Result<Manifest, String>> {
+    let manifest: Value = serde_json::from_slice(raw)?;
+    if manifest["manifests"].is_array() {
+        let digest = platform::select(&manifest, platform::node()).ok_or_else(|| anyhow!("the index has no manifest for {}", platform::node()))?;
+        return Ok(Err(digest.to_string()));
+    }
+    let (_, size) = image::descriptors(&manifest);
+    Ok(Ok(Manifest { digest: format!("sha256:{:x}", Sha256::digest(raw)), size }))
+}
+
+// the raw manifest of an image as the registry has it
+pub fn inspect(image: &str, auth: Option<&AuthConfig>) -> Result<Vec<u8>> {
+    skopeo_inspect(image, auth, "--raw")
+}
+
+// the config of an image, of the node's platform for an index
+pub fn inspect_config(image: &str, auth: Option<&AuthConfig>) -> Result<Vec<u8>> {
+    skopeo_inspect(image, auth, "--config")
+}
+
+fn skopeo_inspect(image: &str, auth: Option<&AuthConfig>, what: &str) -> Result<Vec<u8>> {
+    let mut command = Command::new("skopeo");
+    command.arg("inspect").arg(what);
+    let auth_file = auth.map(|auth| image::auth::AuthFile::new(&ImageReference::parse(image)?.registry, auth)).transpose()?;
+    if let Some(auth_file) = &auth_file {
+        command.arg("--authfile").arg(auth_file.path());
+    }
+    let output = command
+        .arg(format!("docker://{}", image))
+        .output()
+        .map_err(|e| anyhow!("Failed to run skopeo to inspect {}: {}", image, e))?;
+    if !output.status.success() {
+        return Err(anyhow!("Failed to inspect {}: {}", image, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(output.stdout)
+}
+
+// the manifest the registry has for an image
+pub fn remote_manifest(reference: &ImageReference, auth: Option<&AuthConfig>) -> Result<Manifest> {
+    match parse_manifest(&inspect(&reference.pull_name(), auth)?)? {
+        Ok(manifest) => Ok(manifest),
+        Err(digest) => {
+            let image = format!("{}/{}@{}", reference.registry, reference.repository, digest);
+            parse_manifest(&inspect(&image, auth)?)?.map_err(|_| anyhow!("the manifest of {} is an index", image))
+        }
+    }
+}
+
+// the digest a tag points to now, of the index for images of several platforms
+// as the registries show it
+pub fn resolve_digest(reference: &ImageReference, auth: Option<&AuthConfig>) -> Result<String> {
+    Ok(format!("sha256:{:x}", Sha256::digest(inspect(&reference.pull_name(), auth)?)))
+}
+
+// bytes used on the image filesystems of a runtime
+fn image_fs_used(backend: &dyn RuntimeBackend) -> u64 {
+    backend
+        .image_fs_info(ImageFsInfoRequest {})
+        .map(|response| response.image_filesystems.iter().filter_map(|usage| usage.used_bytes.as_ref()).map(|used| used.value).sum())
+        .unwrap_or_default()
+}
+
+// pull an image into the runtime, or the image store without one, calling
+// on_progress every POLL_INTERVAL until it is done
+pub fn pull(
+    backend: Option<&dyn RuntimeBackend>,
+    root_path: &Path,
+    request: &PullImageRequest,
+    options: &PullOptions,
+    mut on_progress: impl FnMut(&Progress),
+) -> Result<PullImageResponse> {
+    let image = request.image.as_ref().map(|image| image.image.as_str()).unwrap_or_default();
+    let reference = ImageReference::parse(image)?;
+    if verify::enabled() {
+        verify::verify(&reference, request.auth.as_ref()).map_err(|e| anyhow!("Pull of {} refused: {:#}", reference, e))?;
+    }
+    // the runtimes of Windows nodes pick the manifest of their own platform
+    if request.sandbox_config.as_ref().is_none_or(|config| config.windows.is_none()) {
+        platform::check(&reference, request.auth.as_ref(), platform::node()).map_err(|e| anyhow!("Pull of {} refused: {:#}", reference, e))?;
+    }
+    // the runtimes resolve the manifest themselves
+    let manifest = match backend {
+        Some(_) => None,
+        None => remote_manifest(&reference, request.auth.as_ref()).ok(),
+    };
+    let layout = image::layout_dir(root_path, &reference);
+    let measure = || match backend {
+        Some(backend) => image_fs_used(backend),
+        None => stats::disk_usage(&layout).0,
+    };
+    let baseline = measure();
+    let started = Instant::now();
+    let deadline = options.timeout.map(|timeout| started + timeout);
+    let cancel = &options.cancel;
+    let mut progress = Progress { total: manifest.as_ref().map(|manifest| manifest.size), ..Default::default() };
+    let mut timed_out = false;
+
+    let result = thread::scope(|scope| {
+        let worker = scope.spawn(|| match backend {
+            Some(backend) => backend.pull_image_cancellable(request.clone(), cancel),
+            None => image::pull_image_cancellable(root_path, request, cancel),
+        });
+        let mut measured = started;
+        while !worker.is_finished() {
+            thread::sleep(CANCEL_POLL);
+            if !timed_out && deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                timed_out = true;
+                cancel.cancel();
+            }
+            if measured.elapsed() >= POLL_INTERVAL {
+                measured = Instant::now();
+                progress.bytes = measure().saturating_sub(baseline);
+                progress.elapsed = started.elapsed();
+                on_progress(&progress);
+            }
+        }
+        worker.join().unwrap_or_else(|_| Err(anyhow!("the pull of {} panicked", reference)))
+    });
+    progress.bytes = measure().saturating_sub(baseline);
+    progress.elapsed = started.elapsed();
+
+    result.map_err(|e| {
+        let outcome = match (timed_out, cancel.is_cancelled()) {
+            (true, _) => "timed out",
+            (false, true) => "was cancelled",
+            (false, false) => "failed",
+        };
+        failure(&reference, outcome, &progress, manifest.as_ref(), &e)
+    })
+}
+
+// how far a pull got before it failed, and of what
+fn failure(reference: &ImageReference, outcome: &str, progress: &Progress, manifest: Option<&Manifest>, e: &anyhow::Error) -> anyhow::Error {
+    anyhow!(
+        "Pull of {} {} after {} with {} pulled (registry {}, repository {}, manifest {}): {:#}",
+        reference,
+        outcome,
+        events::age(progress.elapsed.as_secs()),
+        progress,
+        reference.registry,
+        reference.repository,
+        manifest.map_or("unknown", |manifest| manifest.digest.as_str()),
+        e
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::mock::MockRuntime;
+
+    #[test]
+    fn test_manifest() {
+        let raw = br#"{"config": {"digest": "sha256:c0", "size": 1000}, "layers": [{"digest": "sha256:l0", "size": 3000}]}"#;
+        let manifest = parse_manifest(raw).unwrap().unwrap();
+        assert_eq!(manifest.size, 4000);
+        assert_eq!(manifest.digest, format!("sha256:{:x}", Sha256::digest(raw)));
+
+        let index = format!(
+            r#"{{"manifests": [{{"digest": "sha256:other", "platform": {{"os": "linux", "architecture": "s390x"}}}}, {{"digest": "sha256:this", "platform": {{"os": "linux", "architecture": "{}"}}}}]}}"#,
+            platform::node().architecture
+        );
+        assert_eq!(parse_manifest(index.as_bytes()).unwrap(), Err("sha256:this".to_string()));
+        assert!(parse_manifest(br#"{"manifests": []}"#).is_err());
+
+        let progress = Progress { bytes: 1024 * 1024, total: Some(4 * 1024 * 1024), elapsed: Duration::ZERO };
+        assert_eq!(progress.to_string(), "1.0Mi of 4.0Mi (25%)");
+        assert_eq!(Progress { total: None, ..progress }.to_string(), "1.0Mi");
+    }
+
+    #[test]
+    fn test_pull() {
+        let mock = MockRuntime::default();
+        let request = PullImageRequest {
+            image: Some(crate::cri::cri::ImageSpec { image: "nginx:1.27".to_string(), ..Default::default() }),
+            ..Default::default()
+        };
+        let response = pull(Some(&mock), Path::new("/nonexistent"), &request, &PullOptions::default(), |_| {}).unwrap();
+        assert_eq!(response.image_ref, "nginx:1.27");
+
+        let reference = ImageReference::parse("nginx:1.27").unwrap();
+        let progress = Progress { bytes: 2048, total: Some(8192), elapsed: Duration::from_secs(90) };
+        let manifest = Manifest { digest: "sha256:abcd".to_string(), size: 8192 };
+        let e = failure(&reference, "timed out", &progress, Some(&manifest), &anyhow!("Pull of nginx cancelled"));
+        assert_eq!(
+            e.to_string(),
+            "Pull of docker.io/library/nginx:1.27 timed out after 90s with 2.0Ki of 8.0Ki (25%) pulled \
+             (registry docker.io, repository library/nginx, manifest sha256:abcd): Pull of nginx cancelled"
+        );
+    }
+}
@@ -0,0 +1,72 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+use anyhow::{Result, anyhow};
+use crate::cri::cri::AuthConfig;
+use crate::image::ImageReference;
+use crate::image::auth::AuthFile;
+use crate::image::pull;
+
+// Verification of the cosign signatures of images before they are pulled. With
+// --cosign-public-key every image pulled, by `rkl image pull` or for a pod,
+// must be signed by one of the keys given, which `cosign verify` checks; the
+// pull is refused otherwise. Images of a tag are verified by the digest the tag
+// points to at that moment, so with --pin-image-digests the image verified is
+// exactly the one pulled. Without keys nothing is verified.
+
+static PUBLIC_KEYS: OnceLock<Vec<PathBuf>> = OnceLock::new();
+
+// set once at startup from --cosign-public-key
+pub fn init(public_keys: Vec<PathBuf>) {
+    let _ = PUBLIC_KEYS.set(public_keys);
+}
+
+fn public_keys() -> &'static [PathBuf] {
+    PUBLIC_KEYS.get().map(Vec::as_slice).unwrap_or_default()
+}
+
+pub fn enabled() -> bool {
+    !public_keys().is_empty()
+}
+
+// the credentials of the pull are in $DOCKER_CONFIG, see auth
+fn args(public_key: &Path, image: &str) -> Vec<String> {
+    vec!["verify".to_string(), format!("--key={}", public_key.display()), image.to_string()]
+}
+
+// verify an image against the configured keys, returning the digest verified
+pub fn verify(reference: &ImageReference, auth: Option<&AuthConfig>) -> Result<String> {
+    let digest = match &reference.digest {
+        Some(digest) => digest.clone(),
+        None => pull::resolve_digest(reference, auth)?,
+    };
+    let image = reference.pinned(&digest).pull_name();
+    let auth_file = auth.map(|auth| AuthFile::new(&reference.registry, auth)).transpose()?;
+    let mut failures = Vec::new();
+    for public_key in public_keys() {
+        let mut command = Command::new("cosign");
+        if let Some(auth_file) = &auth_file {
+            command.env("DOCKER_CONFIG", auth_file.dir());
+        }
+        let output = command
+            .args(args(public_key, &image))
+            .output()
+            .map_err(|e| anyhow!("Failed to run cosign to verify {}: {}", image, e))?;
+        if output.status.success() {
+            return Ok(digest);
+        }
+        failures.push(format!("{}: {}", public_key.display(), String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Err(anyhow!("no signature of {} by the configured keys ({})", image, failures.join("; ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_args() {
+        let image = "docker.io/library/nginx@sha256:ef01";
+        assert_eq!(args(Path::new("/etc/rk8s/cosign.pub"), image), ["verify", "--key=/etc/rk8s/cosign.pub", image]);
+    }
+}
@@ -0,0 +1,198 @@
+use anyhow::{Result, anyhow};
+use serde_json::Value;
+
+// The JSONPath templates of `rkl get -o jsonpath=...`, the subset kubectl's
+// are mostly written in:
+//
+//   {.metadata.name}{"\t"}{.status.podIP}
+//
+// text outside of braces is printed as is, {"..."} prints a string with \n and
+// \t escapes and every other expression selects from the object: .field or
+// ['field'] a field, [n] an element (negative from the end), [*] or .* every
+// element or field and ..field the field at any depth, $ the object itself.
+// Several selected values are separated by spaces, strings print without
+// quotes and other values as JSON. Like kubectl's default fields that are
+// missing print nothing.
+
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Child(String),
+    Index(i64),
+    Wildcard,
+    Descend(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Text(String),
+    Path(Vec<Step>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("\\n", "\n").replace("\\t", "\t").replace("\\\"", "\"")
+}
+
+// the name of a field, up to the next step
+fn field_name(path: &str) -> (&str, &str) {
+    let end = path.find(['.', '[']).unwrap_or(path.len());
+    path.split_at(end)
+}
+
+fn parse_path(expression: &str) -> Result<Vec<Step>> {
+    let mut steps = Vec::new();
+    let mut rest = expression.strip_prefix('$').unwrap_or(expression);
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("..") {
+            let (name, after) = field_name(after);
+            if name.is_empty() {
+                return Err(anyhow!("missing field name after .. in {}", expression));
+            }
+            steps.push(Step::Descend(name.to_string()));
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('.') {
+            let (name, after) = field_name(after);
+            match name {
+                // {.} is the object itself
+                "" => {}
+                "*" => steps.push(Step::Wildcard),
+                name => steps.push(Step::Child(name.to_string())),
+            }
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let (inner, after) = after.split_once(']').ok_or_else(|| anyhow!("missing ] in {}", expression))?;
+            let inner = inner.trim();
+            let quoted = inner
+                .strip_prefix('\'')
+                .and_then(|inner| inner.strip_suffix('\''))
+                .or_else(|| inner.strip_prefix('"').and_then(|inner| inner.strip_suffix('"')));
+            steps.push(match quoted {
+                Some(name) => Step::Child(name.to_string()),
+                None if inner == "*" => Step::Wildcard,
+                None => Step::Index(inner.parse().map_err(|_| anyhow!("unsupported subscript [{}] in {}", inner, expression))?),
+            });
+            rest = after;
+        } else {
+            return Err(anyhow!("unexpected {:?} in {}", rest, expression));
+        }
+    }
+    Ok(steps)
+}
+
+impl Template {
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut rest = text;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                segments.push(Segment::Text(rest[..start].to_string()));
+            }
+            let (expression, after) = rest[start + 1..]
+                .split_once('}')
+                .ok_or_else(|| anyhow!("unclosed {{ in template {}", text))?;
+            let expression = expression.trim();
+            let literal = expression.strip_prefix('"').and_then(|literal| literal.strip_suffix('"'));
+            segments.push(match literal {
+                Some(literal) => Segment::Text(unescape(literal)),
+                None if expression.is_empty() => return Err(anyhow!("empty expression in template {}", text)),
+                None => Segment::Path(parse_path(expression).map_err(|e| anyhow!("invalid template {}: {}", text, e))?),
+            });
+            rest = after;
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest.to_string()));
+        }
+        Ok(Template { segments })
+    }
+
+    pub fn render(&self, object: &Value) -> String {
+        let mut out = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => out.push_str(text),
+                Segment::Path(steps) => {
+                    let values: Vec<String> = select(object, steps).into_iter().map(format_value).collect();
+                    out.push_str(&values.join(" "));
+                }
+            }
+        }
+        out
+    }
+}
+
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+// every value of the object or below it with the field
+fn descend<'a>(value: &'a Value, name: &str, found: &mut Vec<&'a Value>) {
+    if let Some(field) = value.get(name) {
+        found.push(field);
+    }
+    match value {
+        Value::Object(fields) => fields.values().for_each(|field| descend(field, name, found)),
+        Value::Array(elements) => elements.iter().for_each(|element| descend(element, name, found)),
+        _ => {}
+    }
+}
+
+fn select<'a>(object: &'a Value, steps: &[Step]) -> Vec<&'a Value> {
+    let mut current = vec![object];
+    for step in steps {
+        let mut next = Vec::new();
+        for value in current {
+            match step {
+                Step::Child(name) => next.extend(value.as_object().and_then(|fields| fields.get(name))),
+                Step::Index(index) => {
+                    let elements = value.as_array().map(Vec::as_slice).unwrap_or_default();
+                    let index = if *index < 0 { elements.len() as i64 + index } else { *index };
+                    next.extend(usize::try_from(index).ok().and_then(|index| elements.get(index)));
+                }
+                Step::Wildcard => match value {
+                    Value::Array(elements) => next.extend(elements),
+                    Value::Object(fields) => next.extend(fields.values()),
+                    _ => {}
+                },
+                Step::Descend(name) => descend(value, name, &mut next),
+            }
+        }
+        current = next;
+    }
+    current
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_render() {
+        let pod = json!({
+            "metadata": {"name": "web", "labels": {"app.kubernetes.io/name": "web"}},
+            "spec": {"containers": [{"name": "app", "ports": [{"containerPort": 80}]}, {"name": "sidecar"}]},
+            "status": {"phase": "Running", "podIP": "10.1.0.5"},
+        });
+        let render = |text: &str| Template::parse(text).unwrap().render(&pod);
+        assert_eq!(render("{.status.podIP}"), "10.1.0.5");
+        assert_eq!(render("{$.metadata.name}{\"\\t\"}{.status.phase}"), "web\tRunning");
+        assert_eq!(render("{.spec.containers[*].name}"), "app sidecar");
+        assert_eq!(render("{.spec.containers[-1].name}"), "sidecar");
+        assert_eq!(render("{.metadata.labels['app.kubernetes.io/name']}"), "web");
+        assert_eq!(render("{..containerPort}"), "80");
+        assert_eq!(render("ip={.status.hostIP}."), "ip=.");
+        assert_eq!(render("{.spec.containers[0].ports}"), "[{\"containerPort\":80}]");
+
+        assert!(Template::parse("{.status.podIP").is_err());
+        assert!(Template::parse("{}").is_err());
+        assert!(Template::parse("{.spec.containers[?(@.name)]}").is_err());
+        assert!(Template::parse("{status}").is_err());
+    }
+}
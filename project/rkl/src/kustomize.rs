@@ -0,0 +1,482 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use crate::apply;
+use crate::strict;
+
+// Kustomize-style overlays: a directory with a kustomization.yaml composes the
+// manifests of its resources, files or directories with a kustomization of
+// their own (bases), and changes them for a variant, e.g. an environment:
+//
+//   resources: [../base]
+//   namePrefix: staging-
+//   namespace: staging
+//   commonLabels: {env: staging}
+//   commonAnnotations: {owner: web-team}
+//   patchesStrategicMerge: [replicas.yaml]
+//   patchesJson6902:
+//     - target: {kind: Pod, name: web}
+//       path: image.yaml
+//
+// Strategic merge patches are partial manifests of the same kind and name
+// merged over the resource: maps key by key with null removing a key, lists
+// of named elements element by element with `$patch: delete` removing one,
+// other lists replaced. JSON 6902 patches are lists of add, remove, replace,
+// move, copy and test operations on JSON pointers, from a file or inline as
+// patch. Patches apply first, then the namespace, the name prefix and suffix
+// and the labels and annotations, which also go to the pod templates and the
+// selectors of the workloads and services. `rkl kustomize <dir>` prints the
+// result, `rkl apply -k <dir>` applies it.
+
+const FILE_NAMES: [&str; 2] = ["kustomization.yaml", "kustomization.yml"];
+// kinds whose selector and pod template get the common labels
+const WORKLOADS: [&str; 4] = ["Deployment", "ReplicaSet", "DaemonSet", "StatefulSet"];
+
+#[derive(Debug, Default, Deserialize)]
+struct Kustomization {
+    #[serde(default)]
+    resources: Vec<String>,
+    #[serde(rename = "namePrefix", default)]
+    name_prefix: String,
+    #[serde(rename = "nameSuffix", default)]
+    name_suffix: String,
+    #[serde(default)]
+    namespace: Option<String>,
+    #[serde(rename = "commonLabels", default)]
+    common_labels: BTreeMap<String, String>,
+    #[serde(rename = "commonAnnotations", default)]
+    common_annotations: BTreeMap<String, String>,
+    #[serde(rename = "patchesStrategicMerge", default)]
+    patches_strategic_merge: Vec<String>,
+    #[serde(rename = "patchesJson6902", default)]
+    patches_json6902: Vec<JsonPatch>,
+}
+
+#[derive(Debug, Deserialize)]
+struct JsonPatch {
+    target: Target,
+    // a file of operations
+    #[serde(default)]
+    path: Option<String>,
+    // or the operations inline
+    #[serde(default)]
+    patch: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Target {
+    kind: String,
+    name: String,
+    #[serde(default)]
+    namespace: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Operation {
+    op: String,
+    path: String,
+    #[serde(default)]
+    value: Option<Value>,
+    #[serde(default)]
+    from: Option<String>,
+}
+
+// the documents of a YAML file
+fn read_documents(path: &Path) -> Result<Vec<Value>> {
+    let contents = fs::read_to_string(path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+    let mut documents = Vec::new();
+    for document in serde_yaml::Deserializer::from_str(&contents) {
+        let value = Value::deserialize(document).map_err(|e| anyhow!("Invalid manifest {}: {}", path.display(), e))?;
+        match value {
+            Value::Null => {}
+            Value::Object(_) => documents.push(value),
+            _ => return Err(anyhow!("Invalid manifest {}: expected a map", path.display())),
+        }
+    }
+    Ok(documents)
+}
+
+fn describe(resource: &Value) -> String {
+    format!("{}/{}", resource["kind"].as_str().unwrap_or_default(), resource["metadata"]["name"].as_str().unwrap_or_default())
+}
+
+fn find<'a>(resources: &'a mut [Value], kind: &str, name: &str, namespace: Option<&str>) -> Result<&'a mut Value> {
+    resources
+        .iter_mut()
+        .find(|resource| {
+            resource["kind"] == kind
+                && resource["metadata"]["name"] == name
+                && namespace.is_none_or(|namespace| resource["metadata"]["namespace"] == namespace)
+        })
+        .ok_or_else(|| anyhow!("no resource {}/{} to patch", kind, name))
+}
+
+fn strategic_merge(base: &mut Value, patch: &Value) {
+    if let (Value::Object(fields), Value::Object(patch_fields)) = (&mut *base, patch) {
+        for (key, value) in patch_fields {
+            if value.is_null() {
+                fields.remove(key);
+                continue;
+            }
+            match fields.get_mut(key) {
+                Some(field) => strategic_merge(field, value),
+                None => {
+                    fields.insert(key.clone(), value.clone());
+                }
+            }
+        }
+        return;
+    }
+    if let (Value::Array(elements), Value::Array(patch_elements)) = (&mut *base, patch)
+        && apply::named(elements)
+        && apply::named(patch_elements)
+    {
+        for patch_element in patch_elements {
+            let position = elements.iter().position(|element| element["name"] == patch_element["name"]);
+            let delete = patch_element.get("$patch").and_then(Value::as_str) == Some("delete");
+            match position {
+                Some(i) if delete => {
+                    elements.remove(i);
+                }
+                Some(i) => strategic_merge(&mut elements[i], patch_element),
+                None if delete => {}
+                None => elements.push(patch_element.clone()),
+            }
+        }
+        return;
+    }
+    *base = patch.clone();
+}
+
+// the parent of the value a JSON pointer points at and the last token
+fn split_pointer(pointer: &str) -> Result<(&str, String)> {
+    let (parent, token) = pointer.rsplit_once('/').ok_or_else(|| anyhow!("invalid JSON pointer {:?}", pointer))?;
+    if !pointer.starts_with('/') {
+        return Err(anyhow!("invalid JSON pointer {:?}", pointer));
+    }
+    Ok((parent, token.replace("~1", "/").replace("~0", "~")))
+}
+
+fn parent_mut<'a>(resource: &'a mut Value, parent: &str, pointer: &str) -> Result<&'a mut Value> {
+    resource.pointer_mut(parent).ok_or_else(|| anyhow!("path {} not found", pointer))
+}
+
+fn index(token: &str, len: usize, pointer: &str) -> Result<usize> {
+    token.parse::<usize>().ok().filter(|i| *i < len).ok_or_else(|| anyhow!("path {} not found", pointer))
+}
+
+fn add(resource: &mut Value, pointer: &str, value: Value) -> Result<()> {
+    if pointer.is_empty() {
+        *resource = value;
+        return Ok(());
+    }
+    let (parent, token) = split_pointer(pointer)?;
+    match parent_mut(resource, parent, pointer)? {
+        Value::Object(fields) => {
+            fields.insert(token, value);
+        }
+        Value::Array(elements) if token == "-" => elements.push(value),
+        // inserting at the end is fine
+        Value::Array(elements) => elements.insert(index(&token, elements.len() + 1, pointer)?, value),
+        _ => return Err(anyhow!("path {} not found", pointer)),
+    }
+    Ok(())
+}
+
+fn remove(resource: &mut Value, pointer: &str) -> Result<Value> {
+    let (parent, token) = split_pointer(pointer)?;
+    match parent_mut(resource, parent, pointer)? {
+        Value::Object(fields) => fields.remove(&token).ok_or_else(|| anyhow!("path {} not found", pointer)),
+        Value::Array(elements) => Ok(elements.remove(index(&token, elements.len(), pointer)?)),
+        _ => Err(anyhow!("path {} not found", pointer)),
+    }
+}
+
+fn json_patch(resource: &mut Value, operations: &[Operation]) -> Result<()> {
+    for operation in operations {
+        let pointer = operation.path.as_str();
+        let value = || operation.value.clone().ok_or_else(|| anyhow!("{} of {} needs a value", operation.op, pointer));
+        let from = || operation.from.as_deref().ok_or_else(|| anyhow!("{} to {} needs a from", operation.op, pointer));
+        match operation.op.as_str() {
+            "add" => add(resource, pointer, value()?)?,
+            "remove" => {
+                remove(resource, pointer)?;
+            }
+            "replace" => {
+                let value = value()?;
+                remove(resource, pointer)?;
+                add(resource, pointer, value)?;
+            }
+            "move" => {
+                let moved = remove(resource, from()?)?;
+                add(resource, pointer, moved)?;
+            }
+            "copy" => {
+                let from = from()?;
+                let copied = resource.pointer(from).cloned().ok_or_else(|| anyhow!("path {} not found", from))?;
+                add(resource, pointer, copied)?;
+            }
+            "test" => {
+                if resource.pointer(pointer) != Some(&value()?) {
+                    return Err(anyhow!("test of {} failed", pointer));
+                }
+            }
+            op => return Err(anyhow!("unknown JSON patch operation {}", op)),
+        }
+    }
+    Ok(())
+}
+
+// add the entries to the map at the path, creating the maps on the way
+fn insert_all(resource: &mut Value, path: &[&str], entries: &BTreeMap<String, String>) {
+    if entries.is_empty() {
+        return;
+    }
+    let mut value = resource;
+    for key in path {
+        if !value.is_object() {
+            return;
+        }
+        value = &mut value[*key];
+        if value.is_null() {
+            *value = Value::Object(Map::new());
+        }
+    }
+    if let Value::Object(fields) = value {
+        for (key, entry) in entries {
+            fields.insert(key.clone(), Value::from(entry.as_str()));
+        }
+    }
+}
+
+fn transform(resource: &mut Value, kustomization: &Kustomization) {
+    let kind = resource["kind"].as_str().unwrap_or_default().to_string();
+    if let Some(namespace) = &kustomization.namespace
+        && kind != "Namespace"
+    {
+        resource["metadata"]["namespace"] = Value::from(namespace.as_str());
+    }
+    if let Some(name) = resource["metadata"]["name"].as_str() {
+        let name = format!("{}{}{}", kustomization.name_prefix, name, kustomization.name_suffix);
+        resource["metadata"]["name"] = Value::from(name);
+    }
+
+    let (labels, annotations) = (&kustomization.common_labels, &kustomization.common_annotations);
+    insert_all(resource, &["metadata", "labels"], labels);
+    insert_all(resource, &["metadata", "annotations"], annotations);
+    let template: &[&str] = match kind.as_str() {
+        "Service" => {
+            insert_all(resource, &["spec", "selector"], labels);
+            return;
+        }
+        kind if WORKLOADS.contains(&kind) => {
+            insert_all(resource, &["spec", "selector", "matchLabels"], labels);
+            &["spec", "template", "metadata"]
+        }
+        "Job" => &["spec", "template", "metadata"],
+        "CronJob" => &["spec", "jobTemplate", "spec", "template", "metadata"],
+        _ => return,
+    };
+    insert_all(resource, &[template, &["labels"][..]].concat(), labels);
+    insert_all(resource, &[template, &["annotations"][..]].concat(), annotations);
+}
+
+fn build_dir(dir: &Path, visiting: &mut Vec<PathBuf>) -> Result<Vec<Value>> {
+    let dir = dir.canonicalize().map_err(|e| anyhow!("Failed to read {}: {}", dir.display(), e))?;
+    if visiting.contains(&dir) {
+        return Err(anyhow!("{} includes itself", dir.display()));
+    }
+    let file = FILE_NAMES
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|file| file.exists())
+        .ok_or_else(|| anyhow!("No kustomization.yaml in {}", dir.display()))?;
+    let contents = fs::read_to_string(&file)?;
+    let kustomization: Kustomization =
+        strict::from_yaml(&contents).map_err(|e| anyhow!("Invalid {}: {}", file.display(), e))?;
+
+    visiting.push(dir.clone());
+    let mut resources = Vec::new();
+    for resource in &kustomization.resources {
+        let path = dir.join(resource);
+        if path.is_dir() {
+            resources.extend(build_dir(&path, visiting)?);
+        } else {
+            resources.extend(read_documents(&path)?);
+        }
+    }
+    visiting.pop();
+
+    for patch_file in &kustomization.patches_strategic_merge {
+        for patch in read_documents(&dir.join(patch_file))? {
+            let (kind, name) = (patch["kind"].as_str().unwrap_or_default(), patch["metadata"]["name"].as_str().unwrap_or_default());
+            let namespace = patch["metadata"]["namespace"].as_str();
+            let resource = find(&mut resources, kind, name, namespace).map_err(|e| anyhow!("{}: {}", patch_file, e))?;
+            strategic_merge(resource, &patch);
+        }
+    }
+    for patch in &kustomization.patches_json6902 {
+        let operations = match (&patch.path, &patch.patch) {
+            (Some(path), None) => fs::read_to_string(dir.join(path)).map_err(|e| anyhow!("Failed to read {}: {}", path, e))?,
+            (None, Some(inline)) => inline.clone(),
+            _ => return Err(anyhow!("a patchesJson6902 entry needs either path or patch")),
+        };
+        let operations: Vec<Operation> = serde_yaml::from_str(&operations).map_err(|e| anyhow!("Invalid JSON patch: {}", e))?;
+        let Target { kind, name, namespace } = &patch.target;
+        let resource = find(&mut resources, kind, name, namespace.as_deref())?;
+        json_patch(resource, &operations).map_err(|e| anyhow!("Failed to patch {}: {}", describe(resource), e))?;
+    }
+    for resource in &mut resources {
+        transform(resource, &kustomization);
+    }
+    Ok(resources)
+}
+
+// the manifests of the kustomization of the directory
+pub fn build(dir: &Path) -> Result<Vec<Value>> {
+    build_dir(dir, &mut Vec::new())
+}
+
+// the manifests as documents of a YAML stream
+pub fn to_yaml(resources: &[Value]) -> Result<String> {
+    let documents: Vec<String> = resources.iter().map(serde_yaml::to_string).collect::<Result<_, _>>()?;
+    Ok(documents.join("---\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POD: &str = r#"apiVersion: v1
+kind: Pod
+metadata:
+  name: web
+  labels:
+    app: web
+spec:
+  containers:
+    - name: app
+      image: app:v1
+      env:
+        - name: MODE
+          value: fast
+        - name: DEBUG
+          value: "1"
+    - name: sidecar
+      image: proxy:v1
+---
+apiVersion: v1
+kind: Service
+metadata:
+  name: web
+spec:
+  selector:
+    app: web
+  ports:
+    - port: 80
+"#;
+
+    #[test]
+    fn test_build() {
+        let root = tempfile::tempdir().unwrap();
+        let (base, overlay) = (root.path().join("base"), root.path().join("staging"));
+        fs::create_dir_all(&base).unwrap();
+        fs::create_dir_all(&overlay).unwrap();
+        fs::write(base.join("web.yaml"), POD).unwrap();
+        fs::write(base.join("kustomization.yaml"), "resources: [web.yaml]\n").unwrap();
+        fs::write(
+            overlay.join("kustomization.yaml"),
+            r#"resources: [../base]
+namePrefix: staging-
+namespace: staging
+commonLabels: {env: staging}
+patchesStrategicMerge: [pod.yaml]
+patchesJson6902:
+  - target: {kind: Pod, name: web}
+    patch: |
+      - op: replace
+        path: /spec/containers/0/image
+        value: app:v2
+      - op: add
+        path: /metadata/annotations
+        value: {team: web}
+"#,
+        )
+        .unwrap();
+        fs::write(
+            overlay.join("pod.yaml"),
+            r#"kind: Pod
+metadata:
+  name: web
+spec:
+  containers:
+    - name: app
+      env:
+        - name: DEBUG
+          $patch: delete
+        - name: LEVEL
+          value: info
+    - name: sidecar
+      $patch: delete
+"#,
+        )
+        .unwrap();
+
+        let resources = build(&overlay).unwrap();
+        let (pod, service) = (&resources[0], &resources[1]);
+        assert_eq!(pod["metadata"]["name"], "staging-web");
+        assert_eq!(pod["metadata"]["namespace"], "staging");
+        assert_eq!(pod["metadata"]["labels"]["env"], "staging");
+        assert_eq!(pod["metadata"]["labels"]["app"], "web");
+        assert_eq!(pod["metadata"]["annotations"]["team"], "web");
+        let containers = pod["spec"]["containers"].as_array().unwrap();
+        assert_eq!(containers.len(), 1);
+        assert_eq!(containers[0]["image"], "app:v2");
+        let env: Vec<&str> = containers[0]["env"].as_array().unwrap().iter().map(|e| e["name"].as_str().unwrap()).collect();
+        assert_eq!(env, ["MODE", "LEVEL"]);
+        assert_eq!(service["metadata"]["name"], "staging-web");
+        assert_eq!(service["spec"]["selector"]["env"], "staging");
+        assert!(to_yaml(&resources).unwrap().contains("---\n"));
+
+        // a base including itself, a patch of nothing
+        fs::write(base.join("kustomization.yaml"), "resources: [web.yaml, .]\n").unwrap();
+        assert!(build(&base).unwrap_err().to_string().contains("includes itself"));
+        fs::write(base.join("kustomization.yaml"), "resources: [web.yaml]\npatchesStrategicMerge: [../staging/pod.yaml]\n").unwrap();
+        fs::write(base.join("web.yaml"), POD.replace("name: web\n  labels", "name: db\n  labels")).unwrap();
+        assert!(build(&base).is_err());
+    }
+
+    #[test]
+    fn test_json_patch() {
+        let mut pod: Value = serde_yaml::from_str(POD.split("---").next().unwrap()).unwrap();
+        let operations: Vec<Operation> = serde_yaml::from_str(
+            r#"
+- {op: test, path: /metadata/name, value: web}
+- {op: copy, from: /metadata/labels, path: /metadata/annotations}
+- {op: move, from: /spec/containers/1, path: /spec/containers/0}
+- {op: add, path: /spec/containers/-, value: {name: init}}
+- {op: remove, path: /metadata/labels/app}
+- {op: add, path: /metadata/labels/a~1b, value: c}
+"#,
+        )
+        .unwrap();
+        json_patch(&mut pod, &operations).unwrap();
+        let names: Vec<&str> = pod["spec"]["containers"].as_array().unwrap().iter().map(|c| c["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["sidecar", "app", "init"]);
+        assert_eq!(pod["metadata"]["annotations"]["app"], "web");
+        assert_eq!(pod["metadata"]["labels"]["a/b"], "c");
+
+        let failing = |yaml: &str| {
+            let operations: Vec<Operation> = serde_yaml::from_str(yaml).unwrap();
+            json_patch(&mut pod.clone(), &operations).is_err()
+        };
+        assert!(failing("- {op: test, path: /metadata/name, value: db}"));
+        assert!(failing("- {op: remove, path: /spec/containers/7}"));
+        assert!(failing("- {op: replace, path: /spec/missing, value: 1}"));
+        assert!(failing("- {op: add, path: spec, value: 1}"));
+        assert!(failing("- {op: merge, path: /spec, value: 1}"));
+    }
+}
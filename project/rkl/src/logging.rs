@@ -0,0 +1,202 @@
+use std::fmt;
+use anyhow::{Result, anyhow};
+use clap::ValueEnum;
+use tonic::metadata::{Ascii, MetadataValue};
+use tracing::span::{Attributes, Id};
+use tracing::field::{Field, Visit};
+use tracing::{Span, Subscriber};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::registry::{LookupSpan, Registry};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+// What rkl logs goes through tracing to stderr, as text or as one JSON object
+// per line with --log-format json, at --log-level or per RUST_LOG; the output
+// of the commands themselves stays on stdout. Pods, containers and CRI calls
+// have spans, and every span belongs to a trace: the one of its parent, the
+// one a gRPC request came with, or a new one. The trace of the current span is
+// sent along with the gRPC requests rkl makes as W3C traceparent metadata, and
+// recorded as the trace_id field of the root spans, e.g. of a daemon sync.
+
+pub const TRACEPARENT: &str = "traceparent";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+// set up once at startup from --log-level and --log-format
+pub fn init(level: &str, format: LogFormat) -> Result<()> {
+    // the other crates, e.g. libcontainer, only tell about problems
+    let filter = match std::env::var("RUST_LOG") {
+        Ok(directives) if !directives.is_empty() => EnvFilter::new(directives),
+        _ => EnvFilter::try_new(format!("warn,rkl={}", level)).map_err(|e| anyhow!("invalid log level {}: {}", level, e))?,
+    };
+    let registry = tracing_subscriber::registry().with(filter).with(TraceLayer);
+    let result = match format {
+        LogFormat::Text => registry
+            .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr).with_target(false))
+            .try_init(),
+        LogFormat::Json => registry
+            .with(tracing_subscriber::fmt::layer().json().with_writer(std::io::stderr).with_current_span(true))
+            .try_init(),
+    };
+    result.map_err(|e| anyhow!("Failed to set up logging: {}", e))
+}
+
+// the trace a span belongs to, kept in its extensions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceId(u128);
+
+impl TraceId {
+    fn random() -> Self {
+        TraceId(uuid::Uuid::new_v4().as_u128())
+    }
+}
+
+impl fmt::Display for TraceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+// the trace id of a traceparent, 00-<trace id>-<parent id>-<flags>
+pub fn parse_traceparent(traceparent: &str) -> Option<TraceId> {
+    let mut parts = traceparent.trim().split('-');
+    let (version, trace_id, parent_id) = (parts.next()?, parts.next()?, parts.next()?);
+    if version != "00" || trace_id.len() != 32 || parent_id.len() != 16 {
+        return None;
+    }
+    // all zeros is invalid
+    u128::from_str_radix(trace_id, 16).ok().filter(|id| *id != 0).map(TraceId)
+}
+
+// picks up the trace_id field a span was created with
+struct TraceIdVisitor(Option<TraceId>);
+
+impl Visit for TraceIdVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "trace_id" {
+            self.0 = u128::from_str_radix(value, 16).ok().map(TraceId);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "trace_id" {
+            self.0 = u128::from_str_radix(&format!("{:?}", value), 16).ok().map(TraceId);
+        }
+    }
+}
+
+struct TraceLayer;
+
+impl<S> Layer<S> for TraceLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut visitor = TraceIdVisitor(None);
+        attrs.record(&mut visitor);
+        let trace_id = visitor
+            .0
+            .or_else(|| span.parent().and_then(|parent| parent.extensions().get::<TraceId>().copied()))
+            .unwrap_or_else(TraceId::random);
+        span.extensions_mut().insert(trace_id);
+    }
+}
+
+fn trace_of(span: &Span) -> Option<(TraceId, u64)> {
+    span.with_subscriber(|(id, dispatch)| {
+        let registry = dispatch.downcast_ref::<Registry>()?;
+        let trace_id = registry.span(id)?.extensions().get::<TraceId>().copied()?;
+        Some((trace_id, id.into_u64()))
+    })
+    .flatten()
+}
+
+// a root span of its own trace, recorded as its trace_id field
+pub fn root_span(span: Span) -> Span {
+    if let Some((trace_id, _)) = trace_of(&span) {
+        span.record("trace_id", trace_id.to_string());
+    }
+    span
+}
+
+// the span of the work on a pod, the root of a trace unless it is part of one;
+// the namespace is left out when it isn't known
+pub fn pod_span(namespace: &str, name: &str) -> Span {
+    let span = tracing::info_span!("pod", pod = %name, namespace = tracing::field::Empty, trace_id = tracing::field::Empty);
+    if !namespace.is_empty() {
+        span.record("namespace", namespace);
+    }
+    if Span::current().is_none() { root_span(span) } else { span }
+}
+
+// the traceparent of the current span, None outside of spans
+pub fn traceparent() -> Option<String> {
+    trace_of(&Span::current()).map(|(trace_id, span_id)| format!("00-{}-{:016x}-01", trace_id, span_id))
+}
+
+// sends the trace of the span it was made in along with every request
+#[derive(Clone)]
+pub struct Propagate(Option<MetadataValue<Ascii>>);
+
+impl Propagate {
+    pub fn current() -> Self {
+        Propagate(traceparent().and_then(|traceparent| traceparent.parse().ok()))
+    }
+}
+
+impl tonic::service::Interceptor for Propagate {
+    fn call(&mut self, mut request: tonic::Request<()>) -> Result<tonic::Request<()>, tonic::Status> {
+        if let Some(traceparent) = &self.0 {
+            request.metadata_mut().insert(TRACEPARENT, traceparent.clone());
+        }
+        Ok(request)
+    }
+}
+
+// the span of a gRPC request served by rkl, in the trace of the caller if it sent one
+pub fn request_span<T>(method: &str, request: &tonic::Request<T>) -> Span {
+    let remote = request
+        .metadata()
+        .get(TRACEPARENT)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_traceparent);
+    match remote {
+        Some(trace_id) => tracing::info_span!("request", method, trace_id = %trace_id),
+        None => root_span(tracing::info_span!("request", method, trace_id = tracing::field::Empty)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_traceparent() {
+        let trace_id = parse_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").unwrap();
+        assert_eq!(trace_id.to_string(), "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(parse_traceparent("00-00000000000000000000000000000000-00f067aa0ba902b7-01"), None);
+        assert_eq!(parse_traceparent("01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"), None);
+
+        // spans inherit the trace of their parent, which goes along with requests
+        let subscriber = tracing_subscriber::registry().with(TraceLayer);
+        tracing::subscriber::with_default(subscriber, || {
+            let request: tonic::Request<()> = {
+                let mut request = tonic::Request::new(());
+                request.metadata_mut().insert(TRACEPARENT, "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01".parse().unwrap());
+                request
+            };
+            let _request = request_span("CreatePod", &request).entered();
+            let _pod = tracing::info_span!("pod").entered();
+            let traceparent = traceparent().unwrap();
+            assert!(traceparent.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+            assert_eq!(parse_traceparent(&traceparent), Some(trace_id));
+        });
+    }
+}
@@ -1,11 +1,51 @@
 mod task; 
 mod cri;
 mod rootpath;
+mod ratelimit;
+mod cache;
+mod device;
+mod image;
+mod stream;
+mod events;
+mod smoke;
+mod daemon;
+mod admission;
+mod webhook;
+mod identity;
+mod node;
+mod cluster;
+mod adopt;
+mod configmap;
+mod secret;
+mod priority;
+mod selector;
+mod jsonpath;
+mod apply;
+mod values;
+mod kustomize;
+mod validate;
+mod strict;
+mod dryrun;
+mod printer;
+mod quantity;
+mod stats;
 mod commands;
 mod cli_commands;
-use task::task::TaskRunner; 
+mod runtime;
+mod logging;
+mod metrics;
+mod describe;
+mod dashboard;
+mod explain;
+mod completion;
+mod checkpoint;
+use task::task::{FailurePolicy, TaskRunner};
 use std::error::Error;
-use clap::{Parser, Subcommand};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::time::Duration;
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use clap_complete::engine::ArgValueCompleter;
 
 #[derive(Parser)]
 #[command(name = "rkl")]
@@ -13,34 +53,821 @@ use clap::{Parser, Subcommand};
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// Maximum CRI calls per second across all pods (0 disables the limit)
+    #[arg(long, global = true, default_value_t = 0.0)]
+    cri_qps: f64,
+    /// Number of CRI calls allowed in a burst across all pods
+    #[arg(long, global = true, default_value_t = 10)]
+    cri_burst: u32,
+    /// Maximum CRI calls per second for a single pod (0 disables the limit)
+    #[arg(long, global = true, default_value_t = 0.0)]
+    cri_pod_qps: f64,
+    /// Number of CRI calls allowed in a burst for a single pod
+    #[arg(long, global = true, default_value_t = 5)]
+    cri_pod_burst: u32,
+    /// Maximum number of CRI calls in flight at the same time (0 disables the cap)
+    #[arg(long, global = true, default_value_t = 0)]
+    cri_max_inflight: usize,
+    /// How long to wait for the network of a pod sandbox before giving up, e.g. 30s or 2m
+    #[arg(long, global = true, default_value = "30s", value_parser = quantity::parse_duration_arg)]
+    network_ready_timeout: Duration,
+    /// Give up on image pulls taking longer than this, e.g. 10m; pulls can take as long as they need when unset
+    #[arg(long, global = true, value_parser = quantity::parse_duration_arg)]
+    image_pull_timeout: Option<Duration>,
+    /// Sysctls pods may set besides the safe ones, by name or as a prefix, e.g. net.core.*,kernel.msg*
+    #[arg(long, global = true, value_delimiter = ',')]
+    allowed_unsafe_sysctls: Vec<String>,
+    /// Swap of the containers on cgroup v2: none with no-swap, a share of the node's with limited-swap for Burstable pods; left to the runtime when unset
+    #[arg(long, global = true, value_enum)]
+    swap_behavior: Option<task::memory::SwapBehavior>,
+    /// With static, containers of Guaranteed pods with whole cpu requests get CPUs of their own
+    #[arg(long, global = true, value_enum, default_value_t = task::cpumanager::CpuManagerPolicy::None)]
+    cpu_manager_policy: task::cpumanager::CpuManagerPolicy,
+    /// CPUs never given to a container for itself, e.g. 0-1
+    // the full path keeps clap from taking a list of values
+    #[arg(long, global = true, value_parser = task::cpumanager::parse_cpuset, default_value = "")]
+    reserved_cpus: ::std::vec::Vec<u32>,
+    /// Resolve the image tags of pods to the digests they point to at admission, and run those
+    #[arg(long, global = true)]
+    pin_image_digests: bool,
+    /// Cosign public key images must be signed by to be pulled, repeatable; a signature by any of them will do
+    #[arg(long = "cosign-public-key", global = true)]
+    cosign_public_keys: Vec<PathBuf>,
+    /// Directory of the CNI network configurations; pods join the first one
+    #[arg(long, global = true, default_value = libcni::DEFAULT_CONF_DIR)]
+    cni_conf_dir: PathBuf,
+    /// Directories the CNI plugins are searched in
+    #[arg(long, global = true, default_value = libcni::DEFAULT_PLUGIN_DIR, value_delimiter = ':')]
+    cni_bin_dir: Vec<PathBuf>,
+    /// Addresses of the built-in bridge network pods join when no CNI network is configured
+    #[arg(long, global = true, default_value = task::bridge::DEFAULT_POD_CIDR, value_parser = task::bridge::parse_cidr_arg)]
+    pod_cidr: task::bridge::Ipv4Cidr,
+    /// Leave pods with loopback only when no CNI network is configured
+    #[arg(long, global = true)]
+    no_bridge_network: bool,
+    /// Address of the cluster DNS served by `rkl daemon`, e.g. the bridge gateway 10.88.0.1; ClusterFirst pods resolve through it when set
+    #[arg(long, global = true)]
+    cluster_dns: Option<Ipv4Addr>,
+    /// Domain of the service and pod names of the cluster DNS
+    #[arg(long, global = true, default_value = task::dns::DEFAULT_CLUSTER_DOMAIN)]
+    cluster_domain: String,
+    /// Runtime to run pods with instead of the built-in one: containerd, crio, mock, unix:///<socket>, or an OCI runtime: runc, crun, youki or oci://<binary>
+    #[arg(long, global = true)]
+    runtime_endpoint: Option<String>,
+    /// RuntimeClasses pods can name, mapping each to the runtime handler it runs with
+    #[arg(long, global = true, default_value = runtime::class::DEFAULT_CONFIG_PATH)]
+    runtime_classes: PathBuf,
+    /// Directory of the container logs, one directory per pod sandbox
+    #[arg(long, global = true, default_value = task::logs::DEFAULT_POD_LOG_DIR)]
+    pod_log_dir: PathBuf,
+    /// Size at which `rkl daemon` rotates a container log, e.g. 10Mi
+    #[arg(long, global = true, default_value = task::logs::DEFAULT_CONTAINER_LOG_MAX_SIZE, value_parser = quantity::Quantity::parse)]
+    container_log_max_size: quantity::Quantity,
+    /// Log files kept per container, the current one included
+    #[arg(long, global = true, default_value_t = task::logs::DEFAULT_CONTAINER_LOG_MAX_FILES)]
+    container_log_max_files: usize,
+    /// Namespace defaults applied to every pod at admission
+    #[arg(long, global = true, default_value = admission::DEFAULT_CONFIG_PATH)]
+    namespace_defaults: PathBuf,
+    /// Admission plugins applied to every pod: allowed registries, sidecars and webhooks
+    #[arg(long, global = true, default_value = admission::DEFAULT_PLUGIN_CONFIG_PATH)]
+    admission_config: PathBuf,
+    /// SPIFFE trust domain; every pod gets an X.509 SVID of this trust domain when set
+    #[arg(long, global = true)]
+    spiffe_trust_domain: Option<String>,
+    /// Lifetime of the issued SVIDs, e.g. 1h; they are rotated at half of it
+    #[arg(long, global = true, default_value = identity::DEFAULT_SVID_TTL, value_parser = quantity::parse_duration_arg)]
+    spiffe_svid_ttl: Duration,
+    /// Store of the encrypted Secrets, e.g. etcd://10.0.0.1:2379 to share them between nodes with the same secret key; <root>/secrets.db when unset
+    #[arg(long, global = true)]
+    secret_store: Option<String>,
+    /// Configuration of the node with the labels pods can select it by
+    #[arg(long, global = true, default_value = node::DEFAULT_CONFIG_PATH)]
+    node_config: PathBuf,
+    /// Level of the logs of rkl on stderr: error, warn, info, debug or trace; RUST_LOG overrides it
+    #[arg(long, global = true, default_value = "info")]
+    log_level: String,
+    /// Format of the logs on stderr, json prints one object per line
+    #[arg(long, global = true, value_enum, default_value_t = logging::LogFormat::Text)]
+    log_format: logging::LogFormat,
+    /// Log every CRI request and response of this run as JSON to a file under <root>/debug
+    #[arg(long, global = true)]
+    debug_cri: bool,
+    /// Contexts of the clusters rkl manages
+    #[arg(long, global = true, default_value = cluster::DEFAULT_CONFIG_PATH)]
+    context_config: PathBuf,
+    /// Clusters to run get, apply and delete against: a context, a group, a comma separated list of them or all
+    #[arg(long, global = true)]
+    context: Option<String>,
+    /// Reject manifests with fields rkl doesn't know instead of warning about them
+    #[arg(long, global = true)]
+    strict: bool,
+}
+
+// the values a manifest is rendered with, see values
+#[derive(Args)]
+struct ValuesArgs {
+    /// Render the manifest as a template with the values of this YAML file; later files win
+    #[arg(long = "values", value_name = "VALUES_YAML")]
+    files: Vec<String>,
+    /// Set a value of the template, e.g. image.tag=v2; wins over --values
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    sets: Vec<String>,
+}
+
+impl ValuesArgs {
+    fn load(&self) -> anyhow::Result<values::Values> {
+        values::Values::load(&self.files, &self.sets)
+    }
+}
+
+// --dry-run of the commands that change something, see dryrun
+#[derive(Args)]
+struct DryRunArgs {
+    /// Only show what would be done: client checks and builds the requests without sending them, server also has the runtime or the clusters check them
+    #[arg(long = "dry-run", value_enum, value_name = "MODE", num_args = 0..=1, default_missing_value = "client")]
+    mode: Option<dryrun::DryRun>,
+    /// With --dry-run, print the requests (or the applied manifests) as JSON
+    #[arg(short = 'o', long, requires = "mode", value_parser = ["json"])]
+    output: Option<String>,
+}
+
+impl DryRunArgs {
+    fn json(&self) -> bool {
+        self.output.is_some()
+    }
 }
 
 #[derive(Subcommand)]
+// parsed once, the daemon flags make up most of it
+#[allow(clippy::large_enum_variant)]
 enum Commands {
     Run {
         #[arg(value_name = "POD_YAML")]
         pod_yaml: String,
+        /// What to do when one of the containers fails
+        #[arg(long, value_enum, default_value_t = FailurePolicy::Rollback)]
+        failure_policy: FailurePolicy,
+        #[command(flatten)]
+        values: ValuesArgs,
+        #[command(flatten)]
+        dry_run: DryRunArgs,
     },
+    /// Create a pod, or with `namespace <NAME>` a namespace of the clusters
     Create {
         #[arg(value_name = "POD_YAML")]
         pod_yaml: String,
+        #[arg(value_name = "NAME")]
+        name: Option<String>,
+        /// What to do when one of the containers fails
+        #[arg(long, value_enum, default_value_t = FailurePolicy::Rollback)]
+        failure_policy: FailurePolicy,
+        #[command(flatten)]
+        values: ValuesArgs,
+        #[command(flatten)]
+        dry_run: DryRunArgs,
     },
     Start {
-        #[arg(value_name = "POD_NAME")]
+        #[arg(value_name = "POD_NAME", add = ArgValueCompleter::new(completion::pods))]
         pod_name: String,
     },
+    /// Delete a pod, from the clusters of --context when given, or a namespace of the clusters written as namespace/<name>
     Delete {
-        #[arg(value_name = "POD_NAME")]
-        pod_name: String,
+        #[arg(value_name = "POD_NAME", required_unless_present = "selector", add = ArgValueCompleter::new(completion::pods))]
+        pod_name: Option<String>,
+        /// Delete the pods matching this label selector instead, e.g. app=web,tier in (frontend,cache)
+        #[arg(short = 'l', long, conflicts_with = "pod_name", value_parser = selector::parse_selector_arg)]
+        selector: Option<selector::Selector>,
+        /// Namespace of the pod on the clusters
+        #[arg(short = 'n', long, default_value = "default")]
+        namespace: String,
+        /// Seconds the containers get to stop after their preStop hook, instead of the terminationGracePeriodSeconds of the pod; 0 kills them right away
+        #[arg(long)]
+        grace_period: Option<u64>,
+        #[command(flatten)]
+        dry_run: DryRunArgs,
     },
+    /// Show the state of a pod, or of a job or cronjob of `rkl daemon` written as job/<name> or cronjob/<name>
     State {
-        #[arg(value_name = "POD_NAME")]
+        #[arg(value_name = "POD_NAME", add = ArgValueCompleter::new(completion::pods))]
         pod_name: String,
     },
+    /// Send a signal to the containers of a pod
+    Kill {
+        /// Pod name, optionally written as pod/<name>
+        #[arg(value_name = "POD", add = ArgValueCompleter::new(completion::pods))]
+        pod: String,
+        /// Only signal this container
+        #[arg(short = 'c', long)]
+        container: Option<String>,
+        /// Signal to deliver, e.g. SIGHUP, HUP or 1
+        #[arg(short, long, default_value = "SIGTERM")]
+        signal: String,
+    },
+    /// Attach to the stdin, stdout and stderr of a running container
+    Attach {
+        /// Pod name, optionally written as pod/<name>
+        #[arg(value_name = "POD", add = ArgValueCompleter::new(completion::pods))]
+        pod: String,
+        /// Container to attach to, the first container of the pod by default
+        #[arg(short = 'c', long)]
+        container: Option<String>,
+        /// Pass stdin to the container
+        #[arg(short = 'i', long)]
+        stdin: bool,
+        /// Stdin is a tty, the container must have been created with tty
+        #[arg(short = 't', long)]
+        tty: bool,
+    },
+    /// Run an ephemeral debug container in a running pod, e.g. rkl debug web --image busybox -it --target app
+    Debug {
+        /// Pod name, optionally written as pod/<name>
+        #[arg(value_name = "POD", add = ArgValueCompleter::new(completion::pods))]
+        pod: String,
+        /// Image of the debug container
+        #[arg(long)]
+        image: String,
+        /// Name of the debug container, debugger-<random> by default
+        #[arg(short = 'c', long)]
+        container: Option<String>,
+        /// Share the PID namespace of this container of the pod
+        #[arg(long)]
+        target: Option<String>,
+        /// Keep stdin open and attach to it
+        #[arg(short = 'i', long)]
+        stdin: bool,
+        /// Give the container a tty
+        #[arg(short = 't', long)]
+        tty: bool,
+        /// Command to run, sh by default
+        #[arg(last = true)]
+        command: Vec<String>,
+    },
+    /// Forward local ports to a pod, e.g. 8080:80
+    PortForward {
+        /// Pod name, optionally written as pod/<name>
+        #[arg(value_name = "POD", add = ArgValueCompleter::new(completion::pods))]
+        pod: String,
+        /// LOCAL:REMOTE, PORT for the same port on both sides or :REMOTE for a random local port
+        #[arg(value_name = "PORTS", required = true)]
+        ports: Vec<String>,
+        /// Local address to listen on
+        #[arg(long, default_value = "127.0.0.1")]
+        address: String,
+    },
+    /// Check that this node can run pods, end to end
+    Smoke {
+        /// Bundle of the pause container
+        #[arg(long, value_name = "PATH")]
+        pause_bundle: String,
+        /// Image of the test containers, must provide sh and httpd
+        #[arg(long, default_value = "busybox")]
+        image: String,
+        /// Keep the test pod instead of deleting it
+        #[arg(long)]
+        keep: bool,
+    },
+    /// Run as a node agent keeping the static pods of a manifest directory running
+    Daemon {
+        /// Directory of static pod manifests
+        #[arg(long, default_value = "/etc/rk8s/manifests")]
+        manifest_dir: PathBuf,
+        /// Time between two syncs of the pods with their manifests, e.g. 10s
+        #[arg(long, default_value = "10s", value_parser = quantity::parse_duration_arg)]
+        sync_interval: Duration,
+        /// Unix socket of the status API, <root>/rkl.sock by default
+        #[arg(long)]
+        api_socket: Option<PathBuf>,
+        /// Address of the gRPC control API, e.g. 0.0.0.0:7443; disabled when unset, requires --token-file unless on loopback
+        #[arg(long)]
+        grpc_listen: Option<SocketAddr>,
+        /// PEM certificate the gRPC control API is served over TLS with
+        #[arg(long, requires = "tls_private_key_file")]
+        tls_cert_file: Option<PathBuf>,
+        /// PEM private key of --tls-cert-file
+        #[arg(long, requires = "tls_cert_file")]
+        tls_private_key_file: Option<PathBuf>,
+        /// Register the node with the rks scheduler at this url, e.g. https://10.0.0.1:7500
+        #[arg(long)]
+        scheduler: Option<String>,
+        /// PEM certificate of the CA of an rks scheduler served over https
+        #[arg(long, requires = "scheduler")]
+        scheduler_ca_file: Option<PathBuf>,
+        /// Name of the node for the scheduler, the hostname by default
+        #[arg(long)]
+        node_name: Option<String>,
+        /// Url of the control API for the scheduler, http(s)://<grpc-listen> by default
+        #[arg(long)]
+        advertise_address: Option<String>,
+        /// File of the admin token of the token file for the scheduler to create pods with, $RKL_SCHEDULER_TOKEN by default
+        #[arg(long)]
+        scheduler_token_file: Option<PathBuf>,
+        /// File of the node token rks authenticates the registrations of the node with
+        #[arg(long)]
+        bootstrap_token_file: Option<PathBuf>,
+        /// How long the scheduler considers the node ready without another heartbeat, e.g. 40s
+        #[arg(long, default_value = daemon::register::DEFAULT_NODE_LEASE_DURATION, value_parser = quantity::parse_duration_arg)]
+        node_lease_duration: Duration,
+        /// Only allow API requests that don't change workloads (get, list, logs)
+        #[arg(long)]
+        read_only: bool,
+        /// File of "<token> <user> <admin|view>" lines; API requests need one of the tokens when set
+        #[arg(long)]
+        token_file: Option<PathBuf>,
+        /// Addresses the cluster IPs of the services are allocated from
+        #[arg(long, default_value = daemon::service::DEFAULT_SERVICE_CIDR, value_parser = task::bridge::parse_cidr_arg)]
+        service_cidr: task::bridge::Ipv4Cidr,
+        /// How connections to the cluster IPs reach the pods of the services
+        #[arg(long, value_enum, default_value_t = daemon::proxy::ProxyMode::Auto)]
+        proxy_mode: daemon::proxy::ProxyMode,
+        /// Address to serve the Prometheus metrics on, e.g. 0.0.0.0:10255; disabled when unset
+        #[arg(long)]
+        metrics_listen: Option<SocketAddr>,
+        /// Admit pods while their requests stay within this times the node's allocatable cpu and memory
+        #[arg(long, default_value_t = daemon::resources::DEFAULT_OVERCOMMIT_RATIO)]
+        overcommit_ratio: f64,
+        /// Evict pods when the node falls below these, e.g. memory.available<100Mi,nodefs.available<10%
+        #[arg(long, default_value = daemon::eviction::DEFAULT_EVICTION_HARD, value_parser = daemon::eviction::parse_thresholds_arg)]
+        eviction_hard: daemon::eviction::Thresholds,
+        /// Percent of the image filesystem used above which unused images are removed; 100 never removes them
+        #[arg(long, default_value_t = daemon::imagegc::DEFAULT_HIGH_THRESHOLD)]
+        image_gc_high_threshold: u8,
+        /// Percent of the image filesystem used that removing unused images brings it down to
+        #[arg(long, default_value_t = daemon::imagegc::DEFAULT_LOW_THRESHOLD)]
+        image_gc_low_threshold: u8,
+        /// How long an image is kept at least before it can be removed as unused, e.g. 2m
+        #[arg(long, default_value = daemon::imagegc::DEFAULT_MINIMUM_AGE, value_parser = quantity::parse_duration_arg)]
+        image_minimum_gc_age: Duration,
+        /// Run the Deployment, Job and CronJob controllers only while this node holds a lease of this store, e.g. etcd://10.0.0.1:2379
+        #[arg(long)]
+        leader_elect_store: Option<String>,
+        /// Name of the lease the nodes running the same workloads compete for
+        #[arg(long, default_value = daemon::election::DEFAULT_LEASE)]
+        leader_elect_lease: String,
+        /// How long a leader holds the lease without renewing it, e.g. 15s
+        #[arg(long, default_value = daemon::election::DEFAULT_LEASE_DURATION, value_parser = quantity::parse_duration_arg)]
+        leader_elect_lease_duration: Duration,
+    },
+    /// Manage the cache of remote manifest sources
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
+    /// Show the details of a resource
+    Describe {
+        #[command(subcommand)]
+        command: DescribeCommands,
+    },
+    /// Show the cpu, memory and filesystem usage of pods or containers
+    Top {
+        #[command(subcommand)]
+        command: TopCommands,
+    },
+    /// Checkpoint the running containers of a pod into an archive it can be restored from, here or on another node
+    Checkpoint {
+        /// Pod name, optionally written as pod/<name>
+        #[arg(value_name = "POD", add = ArgValueCompleter::new(completion::pods))]
+        pod: String,
+        /// Directory the archive is written to
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf,
+        /// Give up on the checkpoint of a container after this long, e.g. 2m; the runtime's default when unset
+        #[arg(long, value_parser = quantity::parse_duration_arg)]
+        timeout: Option<Duration>,
+    },
+    /// Run a pod again from the archive of `rkl checkpoint`, its containers restored where they were
+    Restore {
+        #[arg(value_name = "CHECKPOINT")]
+        checkpoint: PathBuf,
+    },
+    /// Move a pod of the clusters of --context to another node with the state of its containers, checkpointed and restored
+    Migrate {
+        /// Pod name, optionally written as pod/<name>
+        #[arg(value_name = "POD")]
+        pod: String,
+        /// Node to move the pod to
+        #[arg(long = "to", value_name = "NODE")]
+        node: String,
+        /// Namespace of the pod on the clusters
+        #[arg(short = 'n', long, default_value = "default")]
+        namespace: String,
+        /// Give up on the checkpoint of a container after this long, e.g. 2m; the runtime's default when unset
+        #[arg(long, value_parser = quantity::parse_duration_arg)]
+        timeout: Option<Duration>,
+    },
+    /// Stop the clusters of --context from scheduling pods onto a node
+    Cordon {
+        /// Node name, optionally written as node/<name>
+        #[arg(value_name = "NODE")]
+        node: String,
+    },
+    /// Let the clusters of --context schedule pods onto a cordoned node again
+    Uncordon {
+        /// Node name, optionally written as node/<name>
+        #[arg(value_name = "NODE")]
+        node: String,
+    },
+    /// Cordon a node and evict its pods onto the other nodes, but those of the daemon sets
+    Drain {
+        /// Node name, optionally written as node/<name>
+        #[arg(value_name = "NODE")]
+        node: String,
+        /// Give up waiting for the node to stop the evicted pods after this, e.g. 5m
+        #[arg(long, default_value = "5m", value_parser = quantity::parse_duration_arg)]
+        timeout: Duration,
+    },
+    /// Show the pods, containers, logs and usage of this node in a terminal UI
+    Dashboard {
+        /// How often it is refreshed, e.g. 2s
+        #[arg(long, default_value = "2s", value_parser = quantity::parse_duration_arg)]
+        interval: Duration,
+    },
+    /// Document the fields rkl supports of a kind or of a field of it, e.g. pod.spec.containers
+    Explain {
+        #[arg(value_name = "FIELD")]
+        field: String,
+    },
+    /// Print the script completing rkl in a shell, e.g. source <(rkl completion bash)
+    Completion {
+        #[arg(value_name = "SHELL", value_parser = completion::SHELLS)]
+        shell: String,
+    },
+    /// Manage the rollouts of the deployments of `rkl daemon`
+    Rollout {
+        #[command(subcommand)]
+        command: RolloutCommands,
+    },
+    /// List the nodes, namespaces or pods of the clusters
+    Get {
+        #[command(subcommand)]
+        command: GetCommands,
+        /// Only list the objects whose fields match, e.g. status.phase=Running,spec.nodeName!=node-1
+        #[arg(long, global = true, default_value = "", value_parser = selector::parse_field_selector_arg)]
+        field_selector: selector::FieldSelector,
+        #[command(flatten)]
+        output: printer::OutputArgs,
+    },
+    /// Schedule a pod onto the clusters, or update the pod of the same name with a three-way merge
+    Apply {
+        #[arg(short = 'f', long = "filename", value_name = "POD_YAML", required_unless_present = "kustomization")]
+        pod_yaml: Option<String>,
+        /// The pods of the kustomization.yaml of this directory instead, see `rkl kustomize`
+        #[arg(short = 'k', long = "kustomize", value_name = "DIR", conflicts_with_all = ["pod_yaml", "files", "sets"])]
+        kustomization: Option<String>,
+        #[command(flatten)]
+        values: ValuesArgs,
+        #[command(flatten)]
+        dry_run: DryRunArgs,
+    },
+    /// Show what `rkl apply -f` would change on the clusters
+    Diff {
+        #[arg(short = 'f', long = "filename", value_name = "POD_YAML", required_unless_present = "kustomization")]
+        pod_yaml: Option<String>,
+        /// The pods of the kustomization.yaml of this directory instead, see `rkl kustomize`
+        #[arg(short = 'k', long = "kustomize", value_name = "DIR", conflicts_with_all = ["pod_yaml", "files", "sets"])]
+        kustomization: Option<String>,
+        #[command(flatten)]
+        values: ValuesArgs,
+    },
+    /// Check a manifest against the schema of its kind: unknown fields, wrong types, missing and deprecated fields
+    Validate {
+        #[arg(short = 'f', long = "filename", value_name = "POD_YAML")]
+        pod_yaml: String,
+        #[command(flatten)]
+        values: ValuesArgs,
+    },
+    /// Print the manifests of the kustomization.yaml of a directory with its bases, patches, prefixes and labels applied
+    Kustomize {
+        #[arg(value_name = "DIR", default_value = ".")]
+        dir: PathBuf,
+    },
+    /// Take over the pods of other CRI clients running on this node
+    Adopt {
+        /// Only adopt the pods matching this label selector, e.g. app=web,tier in (frontend,cache)
+        #[arg(short = 'l', long, default_value = "", value_parser = selector::parse_selector_arg)]
+        selector: selector::Selector,
+        /// Only show the pods that would be adopted
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Manage the config maps pods of this node take env vars and volumes from
+    #[command(name = "configmap")]
+    ConfigMap {
+        #[command(subcommand)]
+        command: ConfigMapCommands,
+    },
+    /// Manage the secrets pods of this node take env vars and volumes from
+    Secret {
+        #[command(subcommand)]
+        command: SecretCommands,
+    },
+    /// Show the services of `rkl daemon` and the pods they route to
+    Service {
+        #[command(subcommand)]
+        command: ServiceCommands,
+    },
+    /// Manage the contexts of the clusters
+    Context {
+        #[command(subcommand)]
+        command: ContextCommands,
+    },
+    /// Manage the node rkl runs on
+    Node {
+        #[command(subcommand)]
+        command: NodeCommands,
+    },
+    /// Show the container runtime pods are run by
+    Runtime {
+        #[command(subcommand)]
+        command: RuntimeCommands,
+    },
+    /// Manage the images of the node, in the CRI runtime or the image store of rkl
+    Image {
+        #[command(subcommand)]
+        command: ImageCommands,
+    },
+    // io shim of a container created with stdin or tty, started by rkl itself
+    #[command(hide = true)]
+    Shim {
+        #[arg(long)]
+        socket: PathBuf,
+        #[arg(long)]
+        stdin: bool,
+        #[arg(long)]
+        stdin_once: bool,
+        #[arg(long)]
+        tty: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheCommands {
+    /// Remove every cached remote source
+    Clean,
+}
+
+#[derive(Subcommand)]
+enum DescribeCommands {
+    /// Show the containers, status and events of a pod
+    Pod {
+        /// Pod name, optionally written as pod/<name>
+        #[arg(value_name = "POD", required_unless_present = "selector", add = ArgValueCompleter::new(completion::pods))]
+        pod: Option<String>,
+        /// Describe the pods matching this label selector instead
+        #[arg(short = 'l', long, conflicts_with_all = ["pod", "watch_usage"], value_parser = selector::parse_selector_arg)]
+        selector: Option<selector::Selector>,
+        /// Keep showing the cpu and memory usage of the containers against their limits
+        #[arg(long)]
+        watch_usage: bool,
+        /// How often the usage is refreshed, e.g. 2s
+        #[arg(long, default_value = "2s", value_parser = quantity::parse_duration_arg)]
+        interval: Duration,
+    },
+}
+
+#[derive(Subcommand)]
+enum TopCommands {
+    /// Show the usage of every pod, or of one
+    Pod {
+        /// Pod name, optionally written as pod/<name>
+        #[arg(value_name = "POD", add = ArgValueCompleter::new(completion::pods))]
+        pod: Option<String>,
+        /// Only show the pods matching this label selector
+        #[arg(short = 'l', long, conflicts_with = "pod", value_parser = selector::parse_selector_arg)]
+        selector: Option<selector::Selector>,
+        /// Keep refreshing the usage
+        #[arg(long, short = 'w')]
+        watch: bool,
+        /// How often the usage is refreshed, e.g. 2s
+        #[arg(long, default_value = "2s", value_parser = quantity::parse_duration_arg)]
+        interval: Duration,
+    },
+    /// Show the usage of the containers of every pod, or of one
+    Container {
+        /// Pod name, optionally written as pod/<name>
+        #[arg(value_name = "POD", add = ArgValueCompleter::new(completion::pods))]
+        pod: Option<String>,
+        /// Only show the pods matching this label selector
+        #[arg(short = 'l', long, conflicts_with = "pod", value_parser = selector::parse_selector_arg)]
+        selector: Option<selector::Selector>,
+        /// Keep refreshing the usage
+        #[arg(long, short = 'w')]
+        watch: bool,
+        /// How often the usage is refreshed, e.g. 2s
+        #[arg(long, default_value = "2s", value_parser = quantity::parse_duration_arg)]
+        interval: Duration,
+    },
+}
+
+#[derive(Subcommand)]
+enum RolloutCommands {
+    /// Show the revisions of a deployment
+    History {
+        /// Deployment name, optionally written as deployment/<name>
+        #[arg(value_name = "DEPLOYMENT")]
+        deployment: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum GetCommands {
+    /// List the registered nodes
+    Nodes,
+    /// List the scheduled pods of a namespace
+    Pods {
+        /// Namespace of the pods
+        #[arg(short = 'n', long, default_value = "default")]
+        namespace: String,
+        /// List the pods of every namespace
+        #[arg(short = 'A', long)]
+        all_namespaces: bool,
+        /// Only list the pods matching this label selector
+        #[arg(short = 'l', long, value_parser = selector::parse_selector_arg)]
+        selector: Option<selector::Selector>,
+    },
+    /// List the namespaces
+    Namespaces,
+}
+
+#[derive(Subcommand)]
+enum ConfigMapCommands {
+    /// Store a config map, replacing the one of the same name
+    Apply {
+        #[arg(short = 'f', long = "filename", value_name = "CONFIGMAP_YAML")]
+        configmap_yaml: String,
+    },
+    /// Remove a config map
+    Delete {
+        #[arg(value_name = "NAME")]
+        name: String,
+    },
+    /// List the config maps
+    List {
+        #[command(flatten)]
+        output: printer::OutputArgs,
+    },
+}
+
+#[derive(Subcommand)]
+enum SecretCommands {
+    /// Encrypt a secret into the store, replacing the one of the same name
+    Apply {
+        #[arg(short = 'f', long = "filename", value_name = "SECRET_YAML")]
+        secret_yaml: String,
+    },
+    /// Remove a secret
+    Delete {
+        #[arg(value_name = "NAME")]
+        name: String,
+    },
+    /// List the secrets without their values
+    List {
+        #[command(flatten)]
+        output: printer::OutputArgs,
+    },
+}
+
+#[derive(Subcommand)]
+enum ServiceCommands {
+    /// List the services with their cluster IPs and endpoints
+    List {
+        #[command(flatten)]
+        output: printer::OutputArgs,
+    },
+}
+
+#[derive(Subcommand)]
+enum ContextCommands {
+    /// List the contexts
+    List {
+        #[command(flatten)]
+        output: printer::OutputArgs,
+    },
+    /// Make a context the current one
+    Use {
+        #[arg(value_name = "NAME")]
+        name: String,
+    },
+    /// Add a context or change it
+    Set {
+        #[arg(value_name = "NAME")]
+        name: String,
+        /// Address of the rks scheduler of the cluster
+        #[arg(long)]
+        server: String,
+        /// Group the context belongs to, can be repeated
+        #[arg(long = "group")]
+        groups: Vec<String>,
+        /// File of the bearer token for the rks scheduler, when it has a token file
+        #[arg(long)]
+        token_file: Option<PathBuf>,
+        /// PEM certificate of the CA of an rks scheduler served over https
+        #[arg(long)]
+        ca_file: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ImageCommands {
+    /// List the images with their tags and size
+    #[command(alias = "list")]
+    Ls {
+        /// Only list this image, by reference or id
+        #[arg(value_name = "IMAGE")]
+        image: Option<String>,
+        #[command(flatten)]
+        output: printer::OutputArgs,
+    },
+    /// Pull an image, e.g. busybox:1.36
+    Pull {
+        #[arg(value_name = "IMAGE")]
+        image: String,
+        /// Secret of registry credentials to pull with, like the imagePullSecrets of a pod
+        #[arg(long = "pull-secret", value_name = "NAME")]
+        pull_secrets: Vec<String>,
+        /// Give up on the pull after this, e.g. 5m, instead of after --image-pull-timeout
+        #[arg(long, value_parser = quantity::parse_duration_arg)]
+        timeout: Option<Duration>,
+    },
+    /// Remove images, by reference or id
+    #[command(alias = "rmi")]
+    Rm {
+        #[arg(value_name = "IMAGE", required = true)]
+        images: Vec<String>,
+    },
+    /// Show the usage of the filesystems the images are stored on
+    Fsinfo {
+        #[command(flatten)]
+        output: printer::OutputArgs,
+    },
+}
+
+#[derive(Subcommand)]
+enum RuntimeCommands {
+    /// Probe the runtime and show its version and readiness
+    Info,
+    /// List the RuntimeClasses pods can run with
+    Classes {
+        #[command(flatten)]
+        output: printer::OutputArgs,
+    },
+}
+
+#[derive(Subcommand)]
+enum NodeCommands {
+    /// Set labels with key=value, remove them with key-, list them without arguments
+    Label {
+        #[arg(value_name = "KEY=VALUE")]
+        labels: Vec<String>,
+        /// Allow changing the value of an existing label
+        #[arg(long)]
+        overwrite: bool,
+    },
+    /// Add taints with key[=value]:Effect, remove them with key:Effect- or key-, list them without arguments
+    Taint {
+        #[arg(value_name = "KEY[=VALUE]:EFFECT")]
+        taints: Vec<String>,
+        /// Allow changing the value of an existing taint
+        #[arg(long)]
+        overwrite: bool,
+    },
 }
 
 fn main() -> Result<(), anyhow::Error> {
+    clap_complete::CompleteEnv::with_factory(Cli::command).complete();
     let cli = Cli::parse();
+    logging::init(&cli.log_level, cli.log_format)?;
+    ratelimit::init(ratelimit::RateLimitConfig {
+        qps: cli.cri_qps,
+        burst: cli.cri_burst,
+        pod_qps: cli.cri_pod_qps,
+        pod_burst: cli.cri_pod_burst,
+        max_inflight: cli.cri_max_inflight,
+    });
+    task::network::init(cli.network_ready_timeout);
+    task::sysctl::init(cli.allowed_unsafe_sysctls);
+    task::memory::init(cli.swap_behavior);
+    task::cpumanager::init(cli.cpu_manager_policy, cli.reserved_cpus);
+    image::pull::init(cli.image_pull_timeout);
+    image::verify::init(cli.cosign_public_keys);
+    task::cni::init(cli.cni_conf_dir, cli.cni_bin_dir);
+    task::bridge::init(cli.pod_cidr, !cli.no_bridge_network);
+    task::dns::init(cli.cluster_dns, cli.cluster_domain);
+    task::logs::init(task::logs::LogConfig {
+        pod_log_dir: cli.pod_log_dir,
+        max_size: cli.container_log_max_size,
+        max_files: cli.container_log_max_files,
+    });
+    runtime::init(cli.runtime_endpoint);
+    runtime::class::init(cli.runtime_classes);
+    admission::init(cli.namespace_defaults, cli.admission_config, cli.pin_image_digests);
+    identity::init(cli.spiffe_trust_domain, cli.spiffe_svid_ttl)?;
+    secret::init(cli.secret_store);
+    node::init(cli.node_config);
+    cri::debug::init(cli.debug_cri);
+    cluster::init(cli.context_config, cli.context);
+    strict::init(cli.strict);
 
     match cli.command {
         //./rkl run xxx.yaml
@@ -49,10 +876,179 @@ fn main() -> Result<(), anyhow::Error> {
         //./rkl delete podname
         //./rkl state podname
 
-        Commands::Run { pod_yaml } => cli_commands::run_pod(&pod_yaml),
-        Commands::Create { pod_yaml } => cli_commands::create_pod(&pod_yaml),
+        Commands::Run { pod_yaml, values, dry_run: DryRunArgs { mode: Some(mode), output }, .. }
+        | Commands::Create { pod_yaml, name: None, values, dry_run: DryRunArgs { mode: Some(mode), output }, .. } => {
+            cli_commands::dry_run_pod(&pod_yaml, &values.load()?, mode, output.is_some())
+        }
+        Commands::Run { pod_yaml, failure_policy, values, .. } => cli_commands::run_pod(&pod_yaml, failure_policy, &values.load()?),
+        Commands::Create { pod_yaml, name: Some(_), dry_run: DryRunArgs { mode: Some(_), .. }, .. } if pod_yaml == "namespace" => {
+            Err(anyhow::anyhow!("--dry-run is not supported for namespaces"))
+        }
+        Commands::Create { pod_yaml, name: Some(name), .. } if pod_yaml == "namespace" => cluster::create_namespace(&name),
+        Commands::Create { name: Some(name), .. } => Err(anyhow::anyhow!("Unexpected argument {}", name)),
+        Commands::Create { pod_yaml, failure_policy, values, .. } => {
+            cli_commands::create_pod(&pod_yaml, failure_policy, &values.load()?)
+        }
         Commands::Start { pod_name } => cli_commands::start_pod(&pod_name),
-        Commands::Delete { pod_name } => cli_commands::delete_pod(&pod_name),
-        Commands::State { pod_name } => cli_commands::state_pod(&pod_name),
+        Commands::Delete { pod_name: Some(pod_name), dry_run: DryRunArgs { mode: Some(_), .. }, .. } if pod_name.starts_with("namespace/") => {
+            Err(anyhow::anyhow!("--dry-run is not supported for namespaces"))
+        }
+        Commands::Delete { pod_name: Some(pod_name), .. } if pod_name.starts_with("namespace/") => {
+            cluster::delete_namespace(pod_name.trim_start_matches("namespace/"))
+        }
+        Commands::Delete { pod_name: Some(pod_name), namespace, dry_run, .. } if cluster::targeted() => {
+            cluster::delete(&pod_name, &namespace, dry_run.mode)
+        }
+        Commands::Delete { selector: Some(selector), namespace, dry_run, .. } if cluster::targeted() => {
+            cluster::delete_selected(&selector, &namespace, dry_run.mode)
+        }
+        Commands::Delete { selector: Some(selector), grace_period, dry_run, .. } => {
+            cli_commands::delete_selected(&selector, grace_period, dry_run.mode, dry_run.json())
+        }
+        Commands::Delete { pod_name, dry_run: DryRunArgs { mode: Some(mode), output }, .. } => {
+            cli_commands::dry_run_delete(&pod_name.unwrap_or_default(), mode, output.is_some())
+        }
+        Commands::Delete { pod_name, grace_period, .. } => {
+            cli_commands::delete_pod_with_grace_period(&pod_name.unwrap_or_default(), grace_period)
+        }
+        Commands::State { pod_name } => match pod_name.split_once('/') {
+            Some(("job", job)) => daemon::job::status(job),
+            Some(("cronjob", cronjob)) => daemon::cronjob::status(cronjob),
+            _ => cli_commands::state_pod(&pod_name),
+        },
+        Commands::Kill { pod, container, signal } => cli_commands::kill_pod(&pod, container.as_deref(), &signal),
+        Commands::Attach { pod, container, stdin, tty } => {
+            cli_commands::attach_pod(&pod, container.as_deref(), stdin, tty)
+        }
+        Commands::Debug { pod, image, container, target, stdin, tty, command } => {
+            cli_commands::debug_pod(&pod, &image, container.as_deref(), target.as_deref(), stdin, tty, command)
+        }
+        Commands::PortForward { pod, ports, address } => cli_commands::port_forward_pod(&pod, &ports, &address),
+        Commands::Smoke { pause_bundle, image, keep } => smoke::run(&pause_bundle, &image, keep),
+        Commands::Daemon {
+            manifest_dir,
+            sync_interval,
+            api_socket,
+            grpc_listen,
+            tls_cert_file,
+            tls_private_key_file,
+            scheduler,
+            scheduler_ca_file,
+            node_name,
+            advertise_address,
+            scheduler_token_file,
+            bootstrap_token_file,
+            node_lease_duration,
+            read_only,
+            token_file,
+            service_cidr,
+            proxy_mode,
+            metrics_listen,
+            overcommit_ratio,
+            eviction_hard,
+            image_gc_high_threshold,
+            image_gc_low_threshold,
+            image_minimum_gc_age,
+            leader_elect_store,
+            leader_elect_lease,
+            leader_elect_lease_duration,
+        } => daemon::run(daemon::DaemonConfig {
+            manifest_dir,
+            sync_interval,
+            api_socket,
+            grpc_listen,
+            tls: tls_cert_file.zip(tls_private_key_file),
+            scheduler,
+            scheduler_ca_file,
+            node_name,
+            advertise_address,
+            scheduler_token_file,
+            bootstrap_token_file,
+            node_lease_duration,
+            read_only,
+            token_file,
+            service_cidr,
+            proxy_mode,
+            metrics_listen,
+            overcommit_ratio,
+            eviction_hard,
+            image_gc: daemon::imagegc::ImageGcPolicy {
+                high_threshold: image_gc_high_threshold,
+                low_threshold: image_gc_low_threshold,
+                minimum_age: image_minimum_gc_age,
+            },
+            leader_election: leader_elect_store.map(|store| daemon::election::ElectionConfig {
+                store,
+                lease: leader_elect_lease,
+                lease_duration: leader_elect_lease_duration,
+            }),
+        }),
+        Commands::Cache { command: CacheCommands::Clean } => cache::clean(),
+        Commands::Describe { command: DescribeCommands::Pod { selector: Some(selector), .. } } => {
+            cli_commands::describe_selected(&selector)
+        }
+        Commands::Describe { command: DescribeCommands::Pod { pod, watch_usage, interval, .. } } => {
+            cli_commands::describe_pod(&pod.unwrap_or_default(), watch_usage, interval)
+        }
+        Commands::Top { command: TopCommands::Pod { pod, selector, watch, interval } } => {
+            cli_commands::top(pod.as_deref(), selector.as_ref(), false, watch, interval)
+        }
+        Commands::Top { command: TopCommands::Container { pod, selector, watch, interval } } => {
+            cli_commands::top(pod.as_deref(), selector.as_ref(), true, watch, interval)
+        }
+        Commands::Dashboard { interval } => dashboard::run(interval),
+        Commands::Checkpoint { pod, output, timeout } => checkpoint::checkpoint_pod(&pod, &output, timeout),
+        Commands::Restore { checkpoint } => checkpoint::restore_pod(&checkpoint),
+        Commands::Migrate { pod, node, namespace, timeout } => cluster::migrate(&pod, &namespace, &node, timeout),
+        Commands::Cordon { node } => cluster::cordon(&node, true),
+        Commands::Uncordon { node } => cluster::cordon(&node, false),
+        Commands::Drain { node, timeout } => cluster::drain(&node, timeout),
+        Commands::Explain { field } => explain::explain(&field),
+        Commands::Completion { shell } => completion::print_script(&shell),
+        Commands::Rollout { command: RolloutCommands::History { deployment } } => {
+            daemon::deployment::history(deployment.strip_prefix("deployment/").unwrap_or(&deployment))
+        }
+        Commands::Get { command: GetCommands::Nodes, field_selector, output } => cluster::get_nodes(&field_selector, &output),
+        Commands::Get { command: GetCommands::Pods { namespace, all_namespaces, selector }, field_selector, output } => {
+            let namespace = if all_namespaces { None } else { Some(namespace.as_str()) };
+            cluster::get_pods(namespace, &selector.unwrap_or_default(), &field_selector, &output)
+        }
+        Commands::Get { command: GetCommands::Namespaces, field_selector, output } => {
+            cluster::get_namespaces(&field_selector, &output)
+        }
+        Commands::Apply { pod_yaml, kustomization, values, dry_run } => {
+            let manifests = cluster::read_manifests(pod_yaml.as_deref(), kustomization.as_deref(), &values.load()?)?;
+            cluster::apply(&manifests, dry_run.mode, dry_run.json())
+        }
+        Commands::Diff { pod_yaml, kustomization, values } => {
+            cluster::diff(&cluster::read_manifests(pod_yaml.as_deref(), kustomization.as_deref(), &values.load()?)?)
+        }
+        Commands::Validate { pod_yaml, values } => validate::validate_file(&pod_yaml, &values.load()?),
+        Commands::Kustomize { dir } => kustomize::build(&dir).and_then(|resources| {
+            print!("{}", kustomize::to_yaml(&resources)?);
+            Ok(())
+        }),
+        Commands::Adopt { selector, dry_run } => adopt::adopt(&selector, dry_run),
+        Commands::ConfigMap { command: ConfigMapCommands::Apply { configmap_yaml } } => configmap::apply(&configmap_yaml),
+        Commands::ConfigMap { command: ConfigMapCommands::Delete { name } } => configmap::delete(&name),
+        Commands::ConfigMap { command: ConfigMapCommands::List { output } } => configmap::print_list(&output),
+        Commands::Secret { command: SecretCommands::Apply { secret_yaml } } => secret::apply(&secret_yaml),
+        Commands::Secret { command: SecretCommands::Delete { name } } => secret::delete(&name),
+        Commands::Secret { command: SecretCommands::List { output } } => secret::print_list(&output),
+        Commands::Service { command: ServiceCommands::List { output } } => daemon::service::print_list(&output),
+        Commands::Context { command: ContextCommands::List { output } } => cluster::list_contexts(&output),
+        Commands::Context { command: ContextCommands::Use { name } } => cluster::use_context(&name),
+        Commands::Context { command: ContextCommands::Set { name, server, groups, token_file, ca_file } } => {
+            cluster::set_context(&name, &server, &groups, token_file, ca_file)
+        }
+        Commands::Node { command: NodeCommands::Label { labels, overwrite } } => node::label(&labels, overwrite),
+        Commands::Node { command: NodeCommands::Taint { taints, overwrite } } => node::taint(&taints, overwrite),
+        Commands::Runtime { command: RuntimeCommands::Info } => runtime::print_info(),
+        Commands::Runtime { command: RuntimeCommands::Classes { output } } => runtime::class::print_classes(&output),
+        Commands::Image { command: ImageCommands::Ls { image, output } } => image::manage::print_list(image.as_deref(), &output),
+        Commands::Image { command: ImageCommands::Pull { image, pull_secrets, timeout } } => image::manage::pull(&image, &pull_secrets, timeout),
+        Commands::Image { command: ImageCommands::Rm { images } } => image::manage::remove(&images),
+        Commands::Image { command: ImageCommands::Fsinfo { output } } => image::manage::print_fs_info(&output),
+        Commands::Shim { socket, stdin, stdin_once, tty } => stream::shim::run(&socket, stdin, stdin_once, tty),
     }
 }
\ No newline at end of file
@@ -0,0 +1,159 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// Prometheus metrics of this process, in memory: counted by TaskRunner, the
+// restarts of the daemon and every CRI call, and served by `rkl daemon
+// --metrics-listen` in the text exposition format (see daemon/metrics.rs).
+// Other rkl commands count as well but nobody reads their metrics.
+
+pub static PODS_STARTED: Counter = Counter::new("rkl_pods_started_total", "Pods whose sandbox and containers were started.");
+pub static PODS_FAILED: Counter = Counter::new("rkl_pods_failed_total", "Pods that failed to start.");
+pub static CONTAINER_RESTARTS: Counter =
+    Counter::new("rkl_container_restarts_total", "Exited containers restarted per restartPolicy.");
+pub static CRI_REQUEST_DURATION: Histogram = Histogram::new(
+    "rkl_cri_request_duration_seconds",
+    "Latency of the CRI calls, failed ones included.",
+    Some("method"),
+    &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0],
+);
+pub static IMAGE_PULL_DURATION: Histogram = Histogram::new(
+    "rkl_image_pull_duration_seconds",
+    "Time it took to pull an image.",
+    None,
+    &[0.5, 1.0, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0],
+);
+
+pub struct Counter {
+    name: &'static str,
+    help: &'static str,
+    value: AtomicU64,
+}
+
+impl Counter {
+    const fn new(name: &'static str, help: &'static str) -> Self {
+        Counter { name, help, value: AtomicU64::new(0) }
+    }
+
+    pub fn inc(&self) {
+        self.value.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String) {
+        let _ = writeln!(out, "# HELP {} {}\n# TYPE {} counter", self.name, self.help, self.name);
+        let _ = writeln!(out, "{} {}", self.name, self.value.load(Ordering::Relaxed));
+    }
+}
+
+#[derive(Default)]
+struct Series {
+    // per bucket, not cumulative
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+// a histogram, one series per value of its label when it has one
+pub struct Histogram {
+    name: &'static str,
+    help: &'static str,
+    label: Option<&'static str>,
+    buckets: &'static [f64],
+    series: Mutex<BTreeMap<String, Series>>,
+}
+
+impl Histogram {
+    const fn new(name: &'static str, help: &'static str, label: Option<&'static str>, buckets: &'static [f64]) -> Self {
+        Histogram { name, help, label, buckets, series: Mutex::new(BTreeMap::new()) }
+    }
+
+    pub fn observe(&self, duration: Duration) {
+        self.observe_with("", duration);
+    }
+
+    pub fn observe_with(&self, label_value: &str, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let mut series = self.series.lock().unwrap();
+        let series = series.entry(label_value.to_string()).or_default();
+        if series.counts.is_empty() {
+            series.counts = vec![0; self.buckets.len()];
+        }
+        if let Some(bucket) = self.buckets.iter().position(|bound| seconds <= *bound) {
+            series.counts[bucket] += 1;
+        }
+        series.sum += seconds;
+        series.count += 1;
+    }
+
+    fn render(&self, out: &mut String) {
+        let _ = writeln!(out, "# HELP {} {}\n# TYPE {} histogram", self.name, self.help, self.name);
+        for (label_value, series) in self.series.lock().unwrap().iter() {
+            let labels = match self.label {
+                Some(label) => format!("{}=\"{}\",", label, escape(label_value)),
+                None => String::new(),
+            };
+            let mut cumulative = 0;
+            for (bound, count) in self.buckets.iter().zip(&series.counts) {
+                cumulative += count;
+                let _ = writeln!(out, "{}_bucket{{{}le=\"{}\"}} {}", self.name, labels, bound, cumulative);
+            }
+            let _ = writeln!(out, "{}_bucket{{{}le=\"+Inf\"}} {}", self.name, labels, series.count);
+            let labels = labels.trim_end_matches(',');
+            let labels = if labels.is_empty() { String::new() } else { format!("{{{}}}", labels) };
+            let _ = writeln!(out, "{}_sum{} {}", self.name, labels, series.sum);
+            let _ = writeln!(out, "{}_count{} {}", self.name, labels, series.count);
+        }
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+// every metric in the Prometheus text format
+pub fn render() -> String {
+    let mut out = String::new();
+    for counter in [&PODS_STARTED, &PODS_FAILED, &CONTAINER_RESTARTS] {
+        counter.render(&mut out);
+    }
+    for histogram in [&CRI_REQUEST_DURATION, &IMAGE_PULL_DURATION] {
+        histogram.render(&mut out);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let counter = Counter::new("test_total", "A test.");
+        counter.inc();
+        counter.inc();
+        let mut out = String::new();
+        counter.render(&mut out);
+        assert_eq!(out, "# HELP test_total A test.\n# TYPE test_total counter\ntest_total 2\n");
+
+        let histogram = Histogram::new("test_seconds", "A test.", Some("method"), &[0.1, 1.0]);
+        histogram.observe_with("RunPodSandbox", Duration::from_millis(50));
+        histogram.observe_with("RunPodSandbox", Duration::from_millis(500));
+        histogram.observe_with("RunPodSandbox", Duration::from_secs(2));
+        let mut out = String::new();
+        histogram.render(&mut out);
+        assert!(out.contains("test_seconds_bucket{method=\"RunPodSandbox\",le=\"0.1\"} 1\n"));
+        assert!(out.contains("test_seconds_bucket{method=\"RunPodSandbox\",le=\"1\"} 2\n"));
+        assert!(out.contains("test_seconds_bucket{method=\"RunPodSandbox\",le=\"+Inf\"} 3\n"));
+        assert!(out.contains("test_seconds_sum{method=\"RunPodSandbox\"} 2.55\n"));
+        assert!(out.contains("test_seconds_count{method=\"RunPodSandbox\"} 3\n"));
+
+        let histogram = Histogram::new("pull_seconds", "A test.", None, &[1.0]);
+        histogram.observe(Duration::from_millis(200));
+        let mut out = String::new();
+        histogram.render(&mut out);
+        assert!(out.contains("pull_seconds_bucket{le=\"1\"} 1\n"));
+        assert!(out.contains("pull_seconds_count 1\n"));
+    }
+}
@@ -0,0 +1,328 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use crate::image::platform;
+use crate::task::task::Toleration;
+
+// The node rkl runs on. Its labels are the well-known kubernetes.io ones plus
+// those of the node configuration, e.g.
+//
+//   labels:
+//     disktype: ssd
+//     topology.kubernetes.io/zone: rack-1
+//   taints:
+//     - key: dedicated
+//       value: gpu
+//       effect: NoSchedule
+//
+// which `rkl node label` and `rkl node taint` edit. Pods only run where their
+// nodeSelector matches and they tolerate the NoSchedule and NoExecute taints;
+// a NoExecute taint also evicts the running pods that don't tolerate it, after
+// the tolerationSeconds of their toleration if any.
+
+pub const DEFAULT_CONFIG_PATH: &str = "/etc/rk8s/node.yaml";
+
+static CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+// set once at startup from --node-config
+pub fn init(config_path: PathBuf) {
+    let _ = CONFIG_PATH.set(config_path);
+}
+
+pub fn config_path() -> &'static Path {
+    CONFIG_PATH.get().map(PathBuf::as_path).unwrap_or(Path::new(DEFAULT_CONFIG_PATH))
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct NodeConfig {
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    #[serde(default)]
+    pub taints: Vec<Taint>,
+    // the CRI runtime pods are run by when --runtime-endpoint isn't given, see runtime/mod.rs
+    #[serde(rename = "runtimeEndpoint", default, skip_serializing_if = "Option::is_none")]
+    pub runtime_endpoint: Option<String>,
+}
+
+pub const NO_SCHEDULE: &str = "NoSchedule";
+pub const PREFER_NO_SCHEDULE: &str = "PreferNoSchedule";
+pub const NO_EXECUTE: &str = "NoExecute";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Taint {
+    pub key: String,
+    #[serde(default)]
+    pub value: String,
+    pub effect: String,
+    // unix time the taint was added, tolerationSeconds count from it
+    #[serde(rename = "timeAdded", default)]
+    pub time_added: u64,
+}
+
+impl fmt::Display for Taint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.value.is_empty() {
+            write!(f, "{}:{}", self.key, self.effect)
+        } else {
+            write!(f, "{}={}:{}", self.key, self.value, self.effect)
+        }
+    }
+}
+
+impl NodeConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(NodeConfig::default());
+        }
+        let contents = fs::read_to_string(path)?;
+        serde_yaml::from_str(&contents).map_err(|e| anyhow!("Invalid node configuration {}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_yaml::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+pub fn hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|name| name.trim().to_string())
+        .unwrap_or_else(|_| "localhost".to_string())
+}
+
+// every label of the node, the configured ones can't override the well-known
+// ones; the os and arch ones of the platform images are pulled for, also
+// under the beta.kubernetes.io/ keys older manifests select on
+pub fn labels(node_name: &str) -> Result<BTreeMap<String, String>> {
+    let mut labels = NodeConfig::load(config_path())?.labels;
+    let platform = platform::node();
+    labels.insert("kubernetes.io/hostname".to_string(), node_name.to_string());
+    for prefix in ["kubernetes.io", "beta.kubernetes.io"] {
+        labels.insert(format!("{}/os", prefix), platform.os.clone());
+        labels.insert(format!("{}/arch", prefix), platform.architecture.clone());
+    }
+    Ok(labels)
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+pub fn taints() -> Result<Vec<Taint>> {
+    Ok(NodeConfig::load(config_path())?.taints)
+}
+
+// the taints of the given effects none of the tolerations tolerates
+pub fn untolerated<'a>(taints: &'a [Taint], tolerations: &[Toleration], effects: &[&str]) -> Vec<&'a Taint> {
+    taints
+        .iter()
+        .filter(|taint| effects.contains(&taint.effect.as_str()))
+        .filter(|taint| !tolerations.iter().any(|toleration| toleration.tolerates(taint)))
+        .collect()
+}
+
+// when a pod has to leave the node because of a NoExecute taint, as unix time,
+// and the taint; like Kubernetes the shortest tolerationSeconds of the matching
+// tolerations applies and a toleration without it tolerates the taint forever
+pub fn eviction<'a>(taints: &'a [Taint], tolerations: &[Toleration]) -> Option<(u64, &'a Taint)> {
+    taints
+        .iter()
+        .filter(|taint| taint.effect == NO_EXECUTE)
+        .filter_map(|taint| {
+            let matching: Vec<&Toleration> = tolerations.iter().filter(|t| t.tolerates(taint)).collect();
+            if matching.is_empty() {
+                return Some((taint.time_added, taint));
+            }
+            let seconds = matching.iter().filter_map(|t| t.toleration_seconds).min()?;
+            Some((taint.time_added.saturating_add_signed(seconds.max(0)), taint))
+        })
+        .min_by_key(|(at, _)| *at)
+}
+
+// the labels of a node selector the node doesn't have, as key=value
+pub fn unmatched_selector(labels: &BTreeMap<String, String>, selector: &HashMap<String, String>) -> Vec<String> {
+    let mut unmatched: Vec<String> = selector
+        .iter()
+        .filter(|(key, value)| labels.get(*key) != Some(*value))
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect();
+    unmatched.sort();
+    unmatched
+}
+
+// label keys are an optional DNS subdomain prefix and a name of up to 63
+// characters, values are empty or such a name
+fn validate_label(key: &str, value: &str) -> Result<()> {
+    let valid_name = |name: &str| {
+        !name.is_empty()
+            && name.len() <= 63
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+            && name.starts_with(|c: char| c.is_ascii_alphanumeric())
+            && name.ends_with(|c: char| c.is_ascii_alphanumeric())
+    };
+    let (prefix, name) = key.rsplit_once('/').map_or((None, key), |(prefix, name)| (Some(prefix), name));
+    let valid_prefix = prefix.is_none_or(|prefix| {
+        prefix.len() <= 253 && prefix.split('.').all(|part| valid_name(part) && !part.contains('_'))
+    });
+    if !valid_name(name) || !valid_prefix {
+        return Err(anyhow!("invalid label key {:?}", key));
+    }
+    if !value.is_empty() && !valid_name(value) {
+        return Err(anyhow!("invalid value {:?} of label {}", value, key));
+    }
+    Ok(())
+}
+
+// apply `rkl node label` arguments, key=value sets a label and key- removes it
+pub fn apply_label_args(config: &mut NodeConfig, args: &[String], overwrite: bool) -> Result<()> {
+    for arg in args {
+        if let Some(key) = arg.strip_suffix('-') {
+            config.labels.remove(key);
+            continue;
+        }
+        let (key, value) = arg.split_once('=').ok_or_else(|| anyhow!("expected key=value or key-, got {}", arg))?;
+        validate_label(key, value)?;
+        if key.starts_with("kubernetes.io/") || key.starts_with("beta.kubernetes.io/") {
+            return Err(anyhow!("label {} is set by rkl itself", key));
+        }
+        match config.labels.get(key) {
+            Some(current) if current != value && !overwrite => {
+                return Err(anyhow!("label {} is already {}, use --overwrite to change it", key, current));
+            }
+            _ => {
+                config.labels.insert(key.to_string(), value.to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+// parse key[=value]:Effect
+fn parse_taint(arg: &str) -> Result<Taint> {
+    let (key_value, effect) = arg.rsplit_once(':').ok_or_else(|| anyhow!("expected key[=value]:Effect, got {}", arg))?;
+    if ![NO_SCHEDULE, PREFER_NO_SCHEDULE, NO_EXECUTE].contains(&effect) {
+        return Err(anyhow!("unknown taint effect {}, expected NoSchedule, PreferNoSchedule or NoExecute", effect));
+    }
+    let (key, value) = key_value.split_once('=').unwrap_or((key_value, ""));
+    validate_label(key, value).map_err(|e| anyhow!("invalid taint {}: {}", arg, e))?;
+    Ok(Taint { key: key.to_string(), value: value.to_string(), effect: effect.to_string(), time_added: 0 })
+}
+
+// apply `rkl node taint` arguments: key[=value]:Effect adds a taint, key:Effect-
+// removes it and key- removes every taint of the key
+pub fn apply_taint_args(config: &mut NodeConfig, args: &[String], overwrite: bool) -> Result<()> {
+    for arg in args {
+        if let Some(removed) = arg.strip_suffix('-') {
+            match removed.split_once(':') {
+                Some((key, effect)) => config.taints.retain(|t| !(t.key == key && t.effect == effect)),
+                None => config.taints.retain(|t| t.key != removed),
+            }
+            continue;
+        }
+        let taint = Taint { time_added: unix_now(), ..parse_taint(arg)? };
+        match config.taints.iter_mut().find(|t| t.key == taint.key && t.effect == taint.effect) {
+            Some(current) if current.value == taint.value => {}
+            Some(current) if !overwrite => {
+                return Err(anyhow!("node already has taint {}, use --overwrite to change it", current));
+            }
+            Some(current) => *current = taint,
+            None => config.taints.push(taint),
+        }
+    }
+    Ok(())
+}
+
+// `rkl node label`: change the labels of the node, or list them without arguments
+pub fn label(args: &[String], overwrite: bool) -> Result<()> {
+    let path = config_path();
+    if args.is_empty() {
+        for (key, value) in labels(&hostname())? {
+            println!("{}={}", key, value);
+        }
+        return Ok(());
+    }
+    let mut config = NodeConfig::load(path)?;
+    apply_label_args(&mut config, args, overwrite)?;
+    config.save(path)?;
+    println!("Node labels saved to {}", path.display());
+    Ok(())
+}
+
+// `rkl node taint`: change the taints of the node, or list them without arguments
+pub fn taint(args: &[String], overwrite: bool) -> Result<()> {
+    let path = config_path();
+    let mut config = NodeConfig::load(path)?;
+    if args.is_empty() {
+        for taint in &config.taints {
+            println!("{}", taint);
+        }
+        return Ok(());
+    }
+    apply_taint_args(&mut config, args, overwrite)?;
+    config.save(path)?;
+    println!("Node taints saved to {}", path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_label_args() {
+        let mut config = NodeConfig::default();
+        let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        apply_label_args(&mut config, &args(&["disktype=ssd", "topology.kubernetes.io/zone=rack-1"]), false).unwrap();
+        assert_eq!(config.labels["disktype"], "ssd");
+        assert!(apply_label_args(&mut config, &args(&["disktype=hdd"]), false).is_err());
+        apply_label_args(&mut config, &args(&["disktype=hdd", "topology.kubernetes.io/zone-"]), true).unwrap();
+        assert_eq!(config.labels.len(), 1);
+        assert!(apply_label_args(&mut config, &args(&["disk type=ssd"]), false).is_err());
+        assert!(apply_label_args(&mut config, &args(&["kubernetes.io/arch=arm64"]), false).is_err());
+
+        let selector = HashMap::from([("disktype".to_string(), "ssd".to_string())]);
+        assert_eq!(unmatched_selector(&config.labels, &selector), vec!["disktype=ssd".to_string()]);
+        config.labels.insert("disktype".to_string(), "ssd".to_string());
+        assert!(unmatched_selector(&config.labels, &selector).is_empty());
+    }
+
+    #[test]
+    fn test_taints_and_eviction() {
+        let mut config = NodeConfig::default();
+        let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        apply_taint_args(&mut config, &args(&["dedicated=gpu:NoSchedule", "maintenance:NoExecute"]), false).unwrap();
+        assert!(apply_taint_args(&mut config, &args(&["dedicated=db:NoSchedule"]), false).is_err());
+        assert!(apply_taint_args(&mut config, &args(&["dedicated=gpu:NoWay"]), false).is_err());
+        assert_eq!(config.taints[0].to_string(), "dedicated=gpu:NoSchedule");
+        config.taints[1].time_added = 1000;
+
+        let toleration = |key: &str, operator: Option<&str>, seconds: Option<i64>| Toleration {
+            key: key.to_string(),
+            operator: operator.map(str::to_string),
+            value: "gpu".to_string(),
+            effect: String::new(),
+            toleration_seconds: seconds,
+        };
+        let effects = [NO_SCHEDULE, NO_EXECUTE];
+        assert_eq!(untolerated(&config.taints, &[], &effects).len(), 2);
+        assert_eq!(untolerated(&config.taints, &[toleration("dedicated", None, None)], &effects).len(), 1);
+        assert!(untolerated(&config.taints, &[toleration("", Some("Exists"), None)], &effects).is_empty());
+
+        // untolerated NoExecute taints evict right away, tolerated ones after tolerationSeconds
+        assert_eq!(eviction(&config.taints, &[]).map(|(at, _)| at), Some(1000));
+        let tolerations = [toleration("maintenance", Some("Exists"), Some(300))];
+        assert_eq!(eviction(&config.taints, &tolerations).map(|(at, _)| at), Some(1300));
+        assert!(eviction(&config.taints, &[toleration("maintenance", Some("Exists"), None)]).is_none());
+
+        apply_taint_args(&mut config, &args(&["maintenance:NoExecute-", "dedicated-"]), false).unwrap();
+        assert!(config.taints.is_empty());
+    }
+}
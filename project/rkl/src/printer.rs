@@ -0,0 +1,339 @@
+use std::cmp::Ordering;
+use std::io::{self, Write};
+use anyhow::{Result, anyhow};
+use clap::Args;
+use serde_json::{Value, json};
+use crate::jsonpath::Template;
+
+// The output of the commands that list things (`rkl get`, `rkl configmap
+// list`, `rkl context list`...). A command puts what it lists into a Listing,
+// the rows of its table and the object each was made from, and -o picks the
+// Printer:
+//
+//   table (the default) and wide   aligned columns, wide with the extra ones
+//   json and yaml                  the objects as a v1 List
+//   name                           kind/name per object
+//   jsonpath=<template>            the template per object, see jsonpath
+//   custom-columns=<spec>          a table of NAME:.path columns, e.g.
+//                                  custom-columns=NAME:.metadata.name,IP:.status.podIP
+//
+// --no-headers leaves out the header of tables and --sort-by sorts by a column
+// of the table (its header) or a JSONPath of the objects, numbers as numbers.
+
+// a column of a listing, wide ones are only printed with -o wide
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub name: String,
+    pub wide: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    // a cell per column
+    pub cells: Vec<String>,
+    pub object: Value,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Listing {
+    pub columns: Vec<Column>,
+    pub items: Vec<Item>,
+}
+
+impl Listing {
+    pub fn new(columns: &[&str]) -> Self {
+        let columns = columns.iter().map(|name| Column { name: name.to_string(), wide: false }).collect();
+        Listing { columns, items: Vec::new() }
+    }
+
+    // add columns only -o wide prints, their cells come after the others
+    pub fn with_wide(mut self, columns: &[&str]) -> Self {
+        self.columns.extend(columns.iter().map(|name| Column { name: name.to_string(), wide: true }));
+        self
+    }
+
+    pub fn push(&mut self, cells: Vec<String>, object: Value) {
+        self.items.push(Item { cells, object });
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Format {
+    Table,
+    Wide,
+    Json,
+    Yaml,
+    Name,
+    JsonPath(Template),
+    CustomColumns(Vec<(String, Template)>),
+}
+
+// for clap
+pub fn parse_format_arg(text: &str) -> Result<Format, String> {
+    match text {
+        "table" => return Ok(Format::Table),
+        "wide" => return Ok(Format::Wide),
+        "json" => return Ok(Format::Json),
+        "yaml" => return Ok(Format::Yaml),
+        "name" => return Ok(Format::Name),
+        _ => {}
+    }
+    if let Some(template) = text.strip_prefix("jsonpath=") {
+        return Template::parse(template).map(Format::JsonPath).map_err(|e| e.to_string());
+    }
+    if let Some(spec) = text.strip_prefix("custom-columns=") {
+        return spec
+            .split(',')
+            .map(|column| {
+                let (name, path) = column
+                    .split_once(':')
+                    .filter(|(name, path)| !name.is_empty() && !path.is_empty())
+                    .ok_or_else(|| format!("invalid custom column {:?}, expected NAME:.path", column))?;
+                Ok((name.to_string(), path_template(path).map_err(|e| e.to_string())?))
+            })
+            .collect::<Result<_, String>>()
+            .map(Format::CustomColumns);
+    }
+    Err(format!(
+        "unsupported output format {:?}, expected table, wide, json, yaml, name, jsonpath=<template> or custom-columns=<spec>",
+        text
+    ))
+}
+
+// a JSONPath with or without its braces
+fn path_template(path: &str) -> Result<Template> {
+    if path.starts_with('{') { Template::parse(path) } else { Template::parse(&format!("{{{}}}", path)) }
+}
+
+// what to sort a listing by
+#[derive(Debug, Clone)]
+pub enum SortKey {
+    Column(String),
+    Path(Template),
+}
+
+// for clap
+pub fn parse_sort_arg(text: &str) -> Result<SortKey, String> {
+    if text.starts_with(['.', '{', '$']) {
+        path_template(text).map(SortKey::Path).map_err(|e| e.to_string())
+    } else {
+        Ok(SortKey::Column(text.to_string()))
+    }
+}
+
+// -o, --no-headers and --sort-by of the commands that list
+#[derive(Debug, Clone, Args)]
+pub struct OutputArgs {
+    /// Output format: table, wide, json, yaml, name, jsonpath=<template> or custom-columns=NAME:.path,...
+    #[arg(short = 'o', long = "output", global = true, default_value = "table", value_parser = parse_format_arg)]
+    pub format: Format,
+    /// Leave out the header of tables
+    #[arg(long, global = true)]
+    pub no_headers: bool,
+    /// Sort by a column, e.g. STATUS, or a JSONPath of the objects, e.g. .metadata.name
+    #[arg(long, global = true, value_parser = parse_sort_arg)]
+    pub sort_by: Option<SortKey>,
+}
+
+pub trait Printer {
+    fn print(&self, listing: &Listing, out: &mut dyn Write) -> io::Result<()>;
+}
+
+// aligned columns, the last one not padded
+fn write_table(rows: &[Vec<String>], out: &mut dyn Write) -> io::Result<()> {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns).map(|i| rows.iter().filter_map(|row| row.get(i)).map(String::len).max().unwrap_or(0)).collect();
+    for row in rows {
+        let cells: Vec<String> = row.iter().zip(&widths).map(|(cell, width)| format!("{:<width$}", cell, width = width)).collect();
+        writeln!(out, "{}", cells.join("   ").trim_end())?;
+    }
+    Ok(())
+}
+
+pub struct TablePrinter {
+    pub wide: bool,
+    pub no_headers: bool,
+}
+
+impl Printer for TablePrinter {
+    fn print(&self, listing: &Listing, out: &mut dyn Write) -> io::Result<()> {
+        let shown: Vec<usize> = (0..listing.columns.len()).filter(|i| self.wide || !listing.columns[*i].wide).collect();
+        let mut rows = Vec::new();
+        if !self.no_headers {
+            rows.push(shown.iter().map(|i| listing.columns[*i].name.clone()).collect());
+        }
+        for item in &listing.items {
+            rows.push(shown.iter().map(|i| item.cells.get(*i).cloned().unwrap_or_default()).collect());
+        }
+        write_table(&rows, out)
+    }
+}
+
+fn list(listing: &Listing) -> Value {
+    let items: Vec<&Value> = listing.items.iter().map(|item| &item.object).collect();
+    json!({ "apiVersion": "v1", "kind": "List", "items": items })
+}
+
+pub struct JsonPrinter;
+
+impl Printer for JsonPrinter {
+    fn print(&self, listing: &Listing, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "{}", serde_json::to_string_pretty(&list(listing))?)
+    }
+}
+
+pub struct YamlPrinter;
+
+impl Printer for YamlPrinter {
+    fn print(&self, listing: &Listing, out: &mut dyn Write) -> io::Result<()> {
+        let yaml = serde_yaml::to_string(&list(listing)).map_err(io::Error::other)?;
+        write!(out, "{}", yaml)
+    }
+}
+
+pub struct NamePrinter;
+
+impl Printer for NamePrinter {
+    fn print(&self, listing: &Listing, out: &mut dyn Write) -> io::Result<()> {
+        for item in &listing.items {
+            let kind = item.object["kind"].as_str().unwrap_or_default().to_lowercase();
+            writeln!(out, "{}/{}", kind, item.object["metadata"]["name"].as_str().unwrap_or_default())?;
+        }
+        Ok(())
+    }
+}
+
+pub struct JsonPathPrinter {
+    pub template: Template,
+}
+
+impl Printer for JsonPathPrinter {
+    fn print(&self, listing: &Listing, out: &mut dyn Write) -> io::Result<()> {
+        for item in &listing.items {
+            writeln!(out, "{}", self.template.render(&item.object))?;
+        }
+        Ok(())
+    }
+}
+
+pub struct CustomColumnsPrinter {
+    pub columns: Vec<(String, Template)>,
+    pub no_headers: bool,
+}
+
+impl Printer for CustomColumnsPrinter {
+    fn print(&self, listing: &Listing, out: &mut dyn Write) -> io::Result<()> {
+        let mut rows = Vec::new();
+        if !self.no_headers {
+            rows.push(self.columns.iter().map(|(name, _)| name.clone()).collect());
+        }
+        for item in &listing.items {
+            let cells = self.columns.iter().map(|(_, template)| template.render(&item.object)).map(|cell| {
+                // like kubectl, fields that are missing print as <none>
+                if cell.is_empty() { "<none>".to_string() } else { cell }
+            });
+            rows.push(cells.collect());
+        }
+        write_table(&rows, out)
+    }
+}
+
+// numbers as numbers, anything else as text
+fn compare(a: &str, b: &str) -> Ordering {
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        _ => a.cmp(b),
+    }
+}
+
+impl OutputArgs {
+    pub fn printer(&self) -> Box<dyn Printer> {
+        match &self.format {
+            Format::Table => Box::new(TablePrinter { wide: false, no_headers: self.no_headers }),
+            Format::Wide => Box::new(TablePrinter { wide: true, no_headers: self.no_headers }),
+            Format::Json => Box::new(JsonPrinter),
+            Format::Yaml => Box::new(YamlPrinter),
+            Format::Name => Box::new(NamePrinter),
+            Format::JsonPath(template) => Box::new(JsonPathPrinter { template: template.clone() }),
+            Format::CustomColumns(columns) => Box::new(CustomColumnsPrinter { columns: columns.clone(), no_headers: self.no_headers }),
+        }
+    }
+
+    pub fn sort(&self, listing: &mut Listing) -> Result<()> {
+        match &self.sort_by {
+            None => {}
+            Some(SortKey::Column(name)) => {
+                let column = listing
+                    .columns
+                    .iter()
+                    .position(|column| column.name.eq_ignore_ascii_case(name))
+                    .ok_or_else(|| anyhow!("no column {} to sort by", name))?;
+                let cell = |item: &Item| item.cells.get(column).cloned().unwrap_or_default();
+                listing.items.sort_by(|a, b| compare(&cell(a), &cell(b)));
+            }
+            Some(SortKey::Path(template)) => {
+                listing.items.sort_by(|a, b| compare(&template.render(&a.object), &template.render(&b.object)));
+            }
+        }
+        Ok(())
+    }
+
+    // sort the listing and print it to stdout
+    pub fn print(&self, mut listing: Listing) -> Result<()> {
+        self.sort(&mut listing)?;
+        self.printer().print(&listing, &mut io::stdout().lock())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listing() -> Listing {
+        let mut listing = Listing::new(&["NAME", "RESTARTS"]).with_wide(&["IP"]);
+        for (name, restarts, ip) in [("web", "10", "10.1.0.5"), ("db", "9", ""), ("cache", "10", "10.1.0.7")] {
+            let object = json!({ "kind": "Pod", "metadata": { "name": name }, "status": { "podIP": ip, "restarts": restarts.parse::<u32>().unwrap() } });
+            listing.push(vec![name.to_string(), restarts.to_string(), ip.to_string()], object);
+        }
+        listing
+    }
+
+    fn printed(args: &[&str]) -> String {
+        #[derive(clap::Parser)]
+        struct Cli {
+            #[command(flatten)]
+            output: OutputArgs,
+        }
+        let cli = <Cli as clap::Parser>::try_parse_from(std::iter::once("rkl").chain(args.iter().copied())).unwrap();
+        let mut listing = listing();
+        cli.output.sort(&mut listing).unwrap();
+        let mut out = Vec::new();
+        cli.output.printer().print(&listing, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_printers() {
+        assert_eq!(printed(&[]), "NAME    RESTARTS\nweb     10\ndb      9\ncache   10\n");
+        assert_eq!(printed(&["-o", "wide", "--no-headers"]), "web     10   10.1.0.5\ndb      9\ncache   10   10.1.0.7\n");
+        assert_eq!(printed(&["-o", "name", "--sort-by", "NAME"]), "pod/cache\npod/db\npod/web\n");
+        // numbers sort as numbers, ties keep their order
+        assert_eq!(printed(&["-o", "name", "--sort-by", "{.status.restarts}"]), "pod/db\npod/web\npod/cache\n");
+        assert_eq!(
+            printed(&["-o", "custom-columns=POD:.metadata.name,IP:{.status.podIP}", "--sort-by", "restarts"]),
+            "POD     IP\ndb      <none>\nweb     10.1.0.5\ncache   10.1.0.7\n"
+        );
+        assert_eq!(printed(&["-o", "jsonpath={.metadata.name}"]), "web\ndb\ncache\n");
+        let list: Value = serde_json::from_str(&printed(&["-o", "json"])).unwrap();
+        assert_eq!(list["items"][2]["metadata"]["name"], "cache");
+        let list: Value = serde_yaml::from_str(&printed(&["-o", "yaml"])).unwrap();
+        assert_eq!(list["kind"], "List");
+
+        assert!(parse_format_arg("xml").is_err());
+        assert!(parse_format_arg("custom-columns=NAME").is_err());
+        let mut listing = listing();
+        let args = OutputArgs { format: Format::Table, no_headers: false, sort_by: Some(SortKey::Column("AGE".to_string())) };
+        assert!(args.sort(&mut listing).is_err());
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use crate::configmap;
+use crate::task::task::{ObjectMeta, PodSpec, default_namespace};
+
+// PriorityClasses map a name to the priority of the pods using it, like
+// Kubernetes'. They are stored per node as <root>/priorityclasses/<name>.yaml
+// from the manifests of `rkl daemon`. A pod gets the value of its
+// priorityClassName, of the globalDefault class when it names none, else its
+// own priority or 0. The two system classes always exist. When the daemon
+// can't fit a pod it preempts running pods of lower priority, unless the
+// class of the pod has preemptionPolicy Never, see daemon::preemption.
+
+pub const KIND: &str = "PriorityClass";
+pub const API_VERSION: &str = "scheduling.k8s.io/v1";
+pub const PREEMPT_LOWER_PRIORITY: &str = "PreemptLowerPriority";
+pub const NEVER: &str = "Never";
+
+// user classes stay below the system ones
+const HIGHEST_USER_PRIORITY: i32 = 1_000_000_000;
+const SYSTEM_CLASSES: [(&str, i32); 2] = [
+    ("system-cluster-critical", 2_000_000_000),
+    ("system-node-critical", 2_000_001_000),
+];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PriorityClass {
+    #[serde(rename = "apiVersion")]
+    pub api_version: String,
+    pub kind: String,
+    pub metadata: ObjectMeta,
+    pub value: i32,
+    #[serde(rename = "globalDefault", default)]
+    pub global_default: bool,
+    #[serde(rename = "preemptionPolicy", default = "default_preemption_policy")]
+    pub preemption_policy: String,
+    #[serde(default)]
+    pub description: String,
+}
+
+fn default_preemption_policy() -> String {
+    PREEMPT_LOWER_PRIORITY.to_string()
+}
+
+fn class_path(root_path: &Path, name: &str) -> PathBuf {
+    root_path.join("priorityclasses").join(format!("{}.yaml", name))
+}
+
+impl PriorityClass {
+    pub fn parse(contents: &str) -> Result<Self> {
+        let class: PriorityClass = serde_yaml::from_str(contents)?;
+        if class.kind != KIND || class.api_version != API_VERSION {
+            return Err(anyhow!("expected apiVersion {} and kind {}", API_VERSION, KIND));
+        }
+        let name = &class.metadata.name;
+        if !configmap::valid_name(name) {
+            return Err(anyhow!("invalid PriorityClass name {:?}", name));
+        }
+        if name.starts_with("system-") {
+            return Err(anyhow!("PriorityClass {}: the system- prefix is reserved", name));
+        }
+        if class.value > HIGHEST_USER_PRIORITY {
+            return Err(anyhow!("PriorityClass {}: value must not exceed {}", name, HIGHEST_USER_PRIORITY));
+        }
+        if class.preemption_policy != PREEMPT_LOWER_PRIORITY && class.preemption_policy != NEVER {
+            return Err(anyhow!("PriorityClass {}: preemptionPolicy must be {} or {}", name, PREEMPT_LOWER_PRIORITY, NEVER));
+        }
+        Ok(class)
+    }
+
+    // replace the stored class of the same name, returns whether it changed
+    pub fn save(&self, root_path: &Path) -> Result<bool> {
+        let contents = serde_yaml::to_string(self)?;
+        let path = class_path(root_path, &self.metadata.name);
+        if fs::read_to_string(&path).ok().as_deref() == Some(contents.as_str()) {
+            return Ok(false);
+        }
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let staging = path.with_extension("new");
+        fs::write(&staging, contents)?;
+        fs::rename(&staging, &path)?;
+        Ok(true)
+    }
+}
+
+fn system_class(name: &str, value: i32) -> PriorityClass {
+    PriorityClass {
+        api_version: API_VERSION.to_string(),
+        kind: KIND.to_string(),
+        metadata: ObjectMeta {
+            name: name.to_string(),
+            namespace: default_namespace(),
+            labels: HashMap::new(),
+            annotations: HashMap::new(),
+        },
+        value,
+        global_default: false,
+        preemption_policy: default_preemption_policy(),
+        description: String::new(),
+    }
+}
+
+pub fn load(root_path: &Path, name: &str) -> Result<PriorityClass> {
+    if let Some((name, value)) = SYSTEM_CLASSES.iter().find(|(system, _)| *system == name) {
+        return Ok(system_class(name, *value));
+    }
+    if !configmap::valid_name(name) {
+        return Err(anyhow!("invalid PriorityClass name {:?}", name));
+    }
+    let contents = fs::read_to_string(class_path(root_path, name)).map_err(|_| anyhow!("PriorityClass {} not found", name))?;
+    PriorityClass::parse(&contents)
+}
+
+pub fn remove(root_path: &Path, name: &str) -> Result<()> {
+    let path = class_path(root_path, name);
+    if !path.exists() {
+        return Err(anyhow!("PriorityClass {} not found", name));
+    }
+    fs::remove_file(&path)?;
+    Ok(())
+}
+
+// the stored classes, by name
+fn list(root_path: &Path) -> Result<Vec<PriorityClass>> {
+    let dir = root_path.join("priorityclasses");
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut classes: Vec<PriorityClass> = fs::read_dir(&dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "yaml"))
+        .filter_map(|entry| fs::read_to_string(entry.path()).ok())
+        .filter_map(|contents| PriorityClass::parse(&contents).ok())
+        .collect();
+    classes.sort_by(|a, b| a.metadata.name.cmp(&b.metadata.name));
+    Ok(classes)
+}
+
+// the priority of a pod and whether it may preempt pods of lower priority
+pub fn resolve(root_path: &Path, spec: &PodSpec) -> Result<(i32, bool)> {
+    let class = match &spec.priority_class_name {
+        Some(name) => Some(load(root_path, name)?),
+        // the value of the highest default, when several claim to be
+        None => list(root_path)?.into_iter().filter(|class| class.global_default).max_by_key(|class| class.value),
+    };
+    match class {
+        Some(class) => {
+            if spec.priority.is_some_and(|priority| priority != class.value) {
+                return Err(anyhow!(
+                    "priority {} doesn't match the value {} of PriorityClass {}",
+                    spec.priority.unwrap_or_default(),
+                    class.value,
+                    class.metadata.name
+                ));
+            }
+            Ok((class.value, class.preemption_policy != NEVER))
+        }
+        None => Ok((spec.priority.unwrap_or(0), true)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::task::PodTask;
+
+    fn class(name: &str, value: i32, extra: &str) -> String {
+        format!(
+            "apiVersion: scheduling.k8s.io/v1\nkind: PriorityClass\nmetadata:\n  name: {}\nvalue: {}\n{}",
+            name, value, extra
+        )
+    }
+
+    fn spec(extra: &str) -> PodSpec {
+        let task: PodTask = serde_yaml::from_str(&format!(
+            "apiVersion: v1\nkind: Pod\nmetadata:\n  name: web\nspec:\n{}  containers:\n    - name: app\n      image: /bundle/app\n",
+            extra
+        ))
+        .unwrap();
+        task.spec
+    }
+
+    #[test]
+    fn test_parse() {
+        let high = PriorityClass::parse(&class("high", 1000, "description: important\n")).unwrap();
+        assert_eq!(high.preemption_policy, PREEMPT_LOWER_PRIORITY);
+        assert!(!high.global_default);
+
+        assert!(PriorityClass::parse(&class("huge", 1_500_000_000, "")).is_err());
+        assert!(PriorityClass::parse(&class("system-mine", 10, "")).is_err());
+        assert!(PriorityClass::parse(&class("odd", 10, "preemptionPolicy: Sometimes\n")).is_err());
+        assert!(PriorityClass::parse(&class("Upper", 10, "")).is_err());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let root = tempfile::tempdir().unwrap();
+        // nothing stored: the priority of the pod itself
+        assert_eq!(resolve(root.path(), &spec("")).unwrap(), (0, true));
+        assert_eq!(resolve(root.path(), &spec("  priority: 7\n")).unwrap(), (7, true));
+
+        let high = PriorityClass::parse(&class("high", 1000, "")).unwrap();
+        assert!(high.save(root.path()).unwrap());
+        assert!(!high.save(root.path()).unwrap());
+        PriorityClass::parse(&class("batch", 10, "globalDefault: true\npreemptionPolicy: Never\n"))
+            .unwrap()
+            .save(root.path())
+            .unwrap();
+
+        assert_eq!(resolve(root.path(), &spec("  priorityClassName: high\n")).unwrap(), (1000, true));
+        assert_eq!(resolve(root.path(), &spec("")).unwrap(), (10, false));
+        assert_eq!(resolve(root.path(), &spec("  priorityClassName: system-node-critical\n")).unwrap().0, 2_000_001_000);
+        let err = resolve(root.path(), &spec("  priorityClassName: high\n  priority: 5\n")).unwrap_err();
+        assert!(err.to_string().contains("doesn't match"));
+
+        remove(root.path(), "high").unwrap();
+        assert!(resolve(root.path(), &spec("  priorityClassName: high\n")).is_err());
+    }
+}
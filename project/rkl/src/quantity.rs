@@ -0,0 +1,219 @@
+use std::fmt;
+use std::time::Duration;
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+// Kubernetes quantities ("500m", "1Gi", "2.5", "1e3") and Go durations ("90s",
+// "1m30s", "250ms"). Manifests are checked when they are parsed, so "1GB" or
+// "512mb" are rejected up front instead of being misread as something else.
+
+const NANOS: i128 = 1_000_000_000;
+
+// an amount of a resource, kept exactly in billionths and as it was written
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "RawQuantity", into = "String")]
+pub struct Quantity {
+    text: String,
+    nanos: i128,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawQuantity {
+    Int(i64),
+    Float(f64),
+    Str(String),
+}
+
+impl TryFrom<RawQuantity> for Quantity {
+    type Error = anyhow::Error;
+
+    fn try_from(raw: RawQuantity) -> Result<Self> {
+        match raw {
+            RawQuantity::Int(i) => Quantity::parse(&i.to_string()),
+            RawQuantity::Float(f) => Quantity::parse(&f.to_string()),
+            RawQuantity::Str(s) => Quantity::parse(&s),
+        }
+    }
+}
+
+impl From<Quantity> for String {
+    fn from(quantity: Quantity) -> Self {
+        quantity.text
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+// the multiplier of a suffix in billionths
+fn suffix_nanos(suffix: &str) -> Option<i128> {
+    let binary = |power: u32| Some(NANOS << power);
+    match suffix {
+        "" => Some(NANOS),
+        "n" => Some(1),
+        "u" => Some(1_000),
+        "m" => Some(1_000_000),
+        "k" => Some(NANOS * 1_000),
+        "M" => Some(NANOS * 1_000_000),
+        "G" => Some(NANOS * 1_000_000_000),
+        "T" => Some(NANOS * 1_000_000_000_000),
+        "P" => Some(NANOS * 1_000_000_000_000_000),
+        "E" => Some(NANOS * 1_000_000_000_000_000_000),
+        "Ki" => binary(10),
+        "Mi" => binary(20),
+        "Gi" => binary(30),
+        "Ti" => binary(40),
+        "Pi" => binary(50),
+        "Ei" => binary(60),
+        _ => None,
+    }
+}
+
+impl Quantity {
+    pub fn parse(text: &str) -> Result<Self> {
+        let invalid = || anyhow!("invalid quantity {:?}, expected e.g. 500m, 2, 1.5Gi or 1e3", text);
+        let trimmed = text.trim();
+        let (negative, unsigned) = match trimmed.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, trimmed.strip_prefix('+').unwrap_or(trimmed)),
+        };
+        let number_len = unsigned.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(unsigned.len());
+        let (number, suffix) = unsigned.split_at(number_len);
+        let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+        if whole.is_empty() && fraction.is_empty() || fraction.contains('.') {
+            return Err(invalid());
+        }
+
+        // the number as digits and a power of ten, then the suffix
+        let digits: i128 = format!("{}{}", whole, fraction).parse().map_err(|_| invalid())?;
+        let mut exponent = -(fraction.len() as i32);
+        // E is exa unless an exponent follows
+        let multiplier = match suffix.strip_prefix(['e', 'E']).and_then(|power| power.parse::<i32>().ok()) {
+            Some(power) => {
+                exponent += power;
+                NANOS
+            }
+            None => suffix_nanos(suffix).ok_or_else(invalid)?,
+        };
+        let too_large = || anyhow!("quantity {} is too large", text);
+        let mut nanos = digits.checked_mul(multiplier).ok_or_else(too_large)?;
+        for _ in 0..exponent.max(0) {
+            nanos = nanos.checked_mul(10).ok_or_else(too_large)?;
+        }
+        // smaller than a billionth rounds up, like Kubernetes
+        for _ in 0..(-exponent).max(0) {
+            nanos = nanos / 10 + i128::from(nanos % 10 != 0);
+        }
+        if nanos > i128::from(i64::MAX) * NANOS {
+            return Err(too_large());
+        }
+        Ok(Quantity { text: trimmed.to_string(), nanos: if negative { -nanos } else { nanos } })
+    }
+
+    // in thousandths, rounded up: millicores for cpu
+    pub fn milli_value(&self) -> i64 {
+        ceil_div(self.nanos, 1_000_000) as i64
+    }
+
+    // rounded up to a whole number: bytes for memory
+    pub fn value(&self) -> i64 {
+        ceil_div(self.nanos, NANOS) as i64
+    }
+
+    // whole number of items, as used by extended resources like nvidia.com/gpu
+    pub fn as_count(&self) -> Option<usize> {
+        if self.nanos < 0 || self.nanos % NANOS != 0 {
+            return None;
+        }
+        usize::try_from(self.nanos / NANOS).ok()
+    }
+}
+
+fn ceil_div(value: i128, divisor: i128) -> i128 {
+    value.div_euclid(divisor) + i128::from(value.rem_euclid(divisor) != 0)
+}
+
+// a Go duration such as 1h30m, 90s, 1.5s or 250ms
+pub fn parse_duration(text: &str) -> Result<Duration> {
+    let invalid = || anyhow!("invalid duration {:?}, expected e.g. 30s, 1m30s or 250ms", text);
+    let mut rest = text.trim();
+    if rest == "0" {
+        return Ok(Duration::ZERO);
+    }
+    if rest.is_empty() {
+        return Err(invalid());
+    }
+    let mut total = 0f64;
+    while !rest.is_empty() {
+        let number_len = rest.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(rest.len());
+        let unit_len = rest[number_len..].find(|c: char| c.is_ascii_digit() || c == '.').unwrap_or(rest.len() - number_len);
+        let (number, unit) = (&rest[..number_len], &rest[number_len..number_len + unit_len]);
+        let value: f64 = number.parse().map_err(|_| invalid())?;
+        let seconds = match unit {
+            "ns" => 1e-9,
+            "us" | "µs" => 1e-6,
+            "ms" => 1e-3,
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            _ => return Err(invalid()),
+        };
+        total += value * seconds;
+        rest = &rest[number_len + unit_len..];
+    }
+    Duration::try_from_secs_f64(total).map_err(|_| invalid())
+}
+
+// a duration flag, a plain number is seconds like the flag used to take
+pub fn parse_duration_arg(text: &str) -> Result<Duration> {
+    match text.trim().parse::<u64>() {
+        Ok(seconds) => Ok(Duration::from_secs(seconds)),
+        Err(_) => parse_duration(text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quantity() {
+        let quantity = |text: &str| Quantity::parse(text).unwrap();
+        assert_eq!(quantity("500m").milli_value(), 500);
+        assert_eq!(quantity("0.5").milli_value(), 500);
+        assert_eq!(quantity("2").milli_value(), 2000);
+        assert_eq!(quantity("1Gi").value(), 1 << 30);
+        assert_eq!(quantity("1.5Gi").value(), 3 << 29);
+        assert_eq!(quantity("128M").value(), 128_000_000);
+        assert_eq!(quantity("1e3").value(), 1000);
+        assert_eq!(quantity("1Ei").value(), 1 << 60);
+        assert_eq!(quantity("1E-3").milli_value(), 1);
+        // like Kubernetes, fractions are rounded up
+        assert_eq!(quantity("100n").milli_value(), 1);
+        assert_eq!(quantity("2").as_count(), Some(2));
+        assert_eq!(quantity("1500m").as_count(), None);
+        for invalid in ["1GB", "512mb", "", "Gi", "1.2.3", "1 Gi", "10Ki0"] {
+            assert!(Quantity::parse(invalid).is_err(), "{} parsed", invalid);
+        }
+        let quantity: Quantity = serde_yaml::from_str("0.25").unwrap();
+        assert_eq!(quantity.milli_value(), 250);
+        assert!(serde_yaml::from_str::<Quantity>("1GB").is_err());
+    }
+
+    #[test]
+    fn test_duration() {
+        assert_eq!(parse_duration("1m30s").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("250ms").unwrap(), Duration::from_millis(250));
+        assert_eq!(parse_duration("1.5h").unwrap(), Duration::from_secs(5400));
+        assert_eq!(parse_duration("0").unwrap(), Duration::ZERO);
+        for invalid in ["30", "1d", "s", "", "-5s"] {
+            assert!(parse_duration(invalid).is_err(), "{} parsed", invalid);
+        }
+        assert_eq!(parse_duration_arg("30").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration_arg("2m").unwrap(), Duration::from_secs(120));
+    }
+}
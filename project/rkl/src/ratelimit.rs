@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// limits applied to the CRI calls made by TaskRunner (create, start, stop, remove, pull, stats).
+// a qps of 0 disables the matching bucket, a max_inflight of 0 disables the concurrency cap
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RateLimitConfig {
+    pub qps: f64,
+    pub burst: u32,
+    pub pod_qps: f64,
+    pub pod_burst: u32,
+    pub max_inflight: usize,
+}
+
+pub struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    refill_per_sec: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(qps: f64, burst: u32) -> Self {
+        let capacity = f64::from(burst.max(1));
+        TokenBucket {
+            capacity,
+            tokens: capacity,
+            refill_per_sec: qps,
+            last_refill: Instant::now(),
+        }
+    }
+
+    // take one token, or return how long to wait before one is available
+    pub fn try_take(&mut self) -> Result<(), Duration> {
+        self.try_take_at(Instant::now())
+    }
+
+    fn try_take_at(&mut self, now: Instant) -> Result<(), Duration> {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+        let missing = 1.0 - self.tokens;
+        Err(Duration::from_secs_f64(missing / self.refill_per_sec))
+    }
+}
+
+pub struct CriLimiter {
+    config: RateLimitConfig,
+    global: Mutex<Option<TokenBucket>>,
+    pods: Mutex<HashMap<String, TokenBucket>>,
+    inflight: Mutex<usize>,
+    released: Condvar,
+}
+
+// held for the duration of a CRI call, frees a concurrency slot when dropped
+pub struct Permit<'a> {
+    limiter: &'a CriLimiter,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if self.limiter.config.max_inflight == 0 {
+            return;
+        }
+        let mut inflight = self.limiter.inflight.lock().unwrap();
+        *inflight -= 1;
+        self.limiter.released.notify_one();
+    }
+}
+
+impl CriLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        let global = (config.qps > 0.0).then(|| TokenBucket::new(config.qps, config.burst));
+        CriLimiter {
+            config,
+            global: Mutex::new(global),
+            pods: Mutex::new(HashMap::new()),
+            inflight: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+
+    // block until the pod is allowed to make one more CRI call
+    pub fn acquire(&self, pod_name: &str) -> Permit<'_> {
+        if self.config.pod_qps > 0.0 {
+            self.wait_for(|| {
+                let mut pods = self.pods.lock().unwrap();
+                pods.entry(pod_name.to_string())
+                    .or_insert_with(|| TokenBucket::new(self.config.pod_qps, self.config.pod_burst))
+                    .try_take()
+            });
+        }
+        self.wait_for(|| match self.global.lock().unwrap().as_mut() {
+            Some(bucket) => bucket.try_take(),
+            None => Ok(()),
+        });
+
+        if self.config.max_inflight > 0 {
+            let mut inflight = self.inflight.lock().unwrap();
+            while *inflight >= self.config.max_inflight {
+                inflight = self.released.wait(inflight).unwrap();
+            }
+            *inflight += 1;
+        }
+        Permit { limiter: self }
+    }
+
+    // forget the bucket of a pod that has been deleted
+    pub fn forget_pod(&self, pod_name: &str) {
+        self.pods.lock().unwrap().remove(pod_name);
+    }
+
+    fn wait_for(&self, mut take: impl FnMut() -> Result<(), Duration>) {
+        while let Err(wait) = take() {
+            thread::sleep(wait);
+        }
+    }
+}
+
+static LIMITER: OnceLock<CriLimiter> = OnceLock::new();
+
+// set the limits used by the process, must be called before the first CRI call
+pub fn init(config: RateLimitConfig) {
+    let _ = LIMITER.set(CriLimiter::new(config));
+}
+
+pub fn limiter() -> &'static CriLimiter {
+    LIMITER.get_or_init(|| CriLimiter::new(RateLimitConfig::default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket_burst_and_refill() {
+        let mut bucket = TokenBucket::new(2.0, 2);
+        let start = bucket.last_refill;
+        assert!(bucket.try_take_at(start).is_ok());
+        assert!(bucket.try_take_at(start).is_ok());
+
+        let wait = bucket.try_take_at(start).unwrap_err();
+        assert_eq!(wait, Duration::from_millis(500));
+
+        assert!(bucket.try_take_at(start + Duration::from_millis(500)).is_ok());
+    }
+
+    #[test]
+    fn test_limiter_concurrency_cap() {
+        let limiter = CriLimiter::new(RateLimitConfig {
+            max_inflight: 1,
+            ..Default::default()
+        });
+        let permit = limiter.acquire("pod");
+        assert_eq!(*limiter.inflight.lock().unwrap(), 1);
+        drop(permit);
+        assert_eq!(*limiter.inflight.lock().unwrap(), 0);
+        let _permit = limiter.acquire("pod");
+    }
+}
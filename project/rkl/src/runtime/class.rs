@@ -0,0 +1,116 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use crate::printer::{Listing, OutputArgs};
+
+// simulate Kubernetes RuntimeClass: the runtimeClassName of a pod names one
+// of the classes of the runtime class configuration, e.g.
+//
+//   runtimeClasses:
+//     gvisor:
+//       handler: runsc
+//     kata:
+//       handler: kata
+//
+// and the handler of the class is the runtime handler of the CRI calls of the
+// pod, which the CRI runtime maps to e.g. gVisor or Kata. With an OCI runtime
+// endpoint the handler is the OCI runtime binary run for the pod instead. Pods
+// without runtimeClassName get the default handler of the runtime, "".
+
+pub const DEFAULT_CONFIG_PATH: &str = "/etc/rk8s/runtime-classes.yaml";
+
+static CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+// set once at startup from --runtime-classes
+pub fn init(config_path: PathBuf) {
+    let _ = CONFIG_PATH.set(config_path);
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RuntimeClassConfig {
+    #[serde(rename = "runtimeClasses", default)]
+    pub runtime_classes: BTreeMap<String, RuntimeClass>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuntimeClass {
+    pub handler: String,
+}
+
+impl RuntimeClassConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(RuntimeClassConfig::default());
+        }
+        let contents = fs::read_to_string(path)?;
+        let config: RuntimeClassConfig = serde_yaml::from_str(&contents)
+            .map_err(|e| anyhow!("Invalid runtime classes {}: {}", path.display(), e))?;
+        // a handler is a DNS label, like in Kubernetes
+        for (name, class) in &config.runtime_classes {
+            let valid = !class.handler.is_empty()
+                && class.handler.len() <= 63
+                && class.handler.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+            if !valid {
+                return Err(anyhow!("Invalid handler {:?} of RuntimeClass {} in {}", class.handler, name, path.display()));
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn handler(&self, runtime_class_name: Option<&str>) -> Result<String> {
+        let Some(name) = runtime_class_name else {
+            return Ok(String::new());
+        };
+        self.runtime_classes
+            .get(name)
+            .map(|class| class.handler.clone())
+            .ok_or_else(|| anyhow!("RuntimeClass {} not found", name))
+    }
+}
+
+fn config_path() -> &'static Path {
+    CONFIG_PATH.get().map(PathBuf::as_path).unwrap_or(Path::new(DEFAULT_CONFIG_PATH))
+}
+
+// the runtime handler of a pod with the configuration given at startup
+pub fn handler(runtime_class_name: Option<&str>) -> Result<String> {
+    RuntimeClassConfig::load(config_path())?.handler(runtime_class_name)
+}
+
+// `rkl runtime classes`
+pub fn print_classes(output: &OutputArgs) -> Result<()> {
+    let config = RuntimeClassConfig::load(config_path())?;
+    let mut listing = Listing::new(&["NAME", "HANDLER"]);
+    for (name, class) in &config.runtime_classes {
+        let object = json!({ "apiVersion": "node.k8s.io/v1", "kind": "RuntimeClass", "metadata": { "name": name }, "handler": class.handler });
+        listing.push(vec![name.clone(), class.handler.clone()], object);
+    }
+    output.print(listing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handler() {
+        let config: RuntimeClassConfig = serde_yaml::from_str(
+            r#"
+runtimeClasses:
+  gvisor:
+    handler: runsc
+  kata:
+    handler: kata-qemu
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.handler(Some("gvisor")).unwrap(), "runsc");
+        assert_eq!(config.handler(Some("kata")).unwrap(), "kata-qemu");
+        assert_eq!(config.handler(None).unwrap(), "");
+        assert!(config.handler(Some("firecracker")).is_err());
+    }
+}
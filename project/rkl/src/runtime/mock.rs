@@ -0,0 +1,338 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use anyhow::{Result, anyhow};
+use crate::cri::cri::{
+    CheckpointContainerRequest, CheckpointContainerResponse, ContainerAttributes, ContainerMetadata, ContainerState, ContainerStats, ContainerStatsRequest, ContainerStatsResponse,
+    ContainerStatus, ContainerStatusRequest, ContainerStatusResponse, CreateContainerRequest, CreateContainerResponse, Image, ImageFsInfoRequest, ImageFsInfoResponse, ImageStatusRequest, ImageStatusResponse,
+    LinuxPodSandboxStats, ListImagesRequest, ListImagesResponse, PodSandboxAttributes, PodSandboxState, PodSandboxStats, PodSandboxStatsRequest,
+    PodSandboxStatsResponse, PodSandboxStatus, PodSandboxStatusRequest, PodSandboxStatusResponse,
+    PullImageRequest, PullImageResponse, RemoveImageRequest, RemoveImageResponse, RemovePodSandboxRequest, RemovePodSandboxResponse, ReopenContainerLogRequest,
+    ReopenContainerLogResponse, RunPodSandboxRequest,
+    RunPodSandboxResponse, RuntimeCondition, RuntimeStatus, StartContainerRequest, StartContainerResponse,
+    StatusRequest, StatusResponse, ExecSyncRequest, ExecSyncResponse, StopContainerRequest, StopContainerResponse, StopPodSandboxRequest, StopPodSandboxResponse, VersionRequest, VersionResponse,
+};
+use crate::checkpoint;
+use crate::runtime::{CRI_API_VERSION, NETWORK_READY, RUNTIME_READY, RuntimeBackend};
+use tracing::info;
+
+// A runtime that runs nothing: it keeps the sandboxes, containers and images
+// in memory and checks the calls against them the way a CRI runtime would,
+// e.g. that a container is created in a sandbox that is there. Every call is
+// recorded, which is what `--runtime-endpoint mock` and the tests look at.
+
+pub const RUNTIME_NAME: &str = "mock";
+
+#[derive(Default)]
+struct State {
+    // sandbox id to whether it is running
+    sandboxes: HashMap<String, bool>,
+    // container id to its sandbox id and whether it was started
+    containers: HashMap<String, (String, bool)>,
+    images: HashSet<String>,
+    calls: Vec<String>,
+}
+
+#[derive(Default)]
+pub struct MockRuntime {
+    state: Mutex<State>,
+}
+
+impl MockRuntime {
+    fn record(&self, method: &str, id: &str) -> std::sync::MutexGuard<'_, State> {
+        let mut state = self.state.lock().unwrap();
+        state.calls.push(format!("{} {}", method, id).trim_end().to_string());
+        info!("mock runtime: {} {}", method, id);
+        state
+    }
+}
+
+impl RuntimeBackend for MockRuntime {
+    fn version(&self, _request: VersionRequest) -> Result<VersionResponse> {
+        drop(self.record("Version", ""));
+        Ok(VersionResponse {
+            version: "0.1.0".to_string(),
+            runtime_name: RUNTIME_NAME.to_string(),
+            runtime_version: env!("CARGO_PKG_VERSION").to_string(),
+            runtime_api_version: CRI_API_VERSION.to_string(),
+        })
+    }
+
+    fn status(&self, _request: StatusRequest) -> Result<StatusResponse> {
+        drop(self.record("Status", ""));
+        let ready = |kind: &str| RuntimeCondition { r#type: kind.to_string(), status: true, ..Default::default() };
+        Ok(StatusResponse {
+            status: Some(RuntimeStatus { conditions: vec![ready(RUNTIME_READY), ready(NETWORK_READY)] }),
+            ..Default::default()
+        })
+    }
+
+    fn run_pod_sandbox(&self, request: RunPodSandboxRequest) -> Result<RunPodSandboxResponse> {
+        let name = request
+            .config
+            .and_then(|config| config.metadata)
+            .map(|metadata| metadata.name)
+            .ok_or_else(|| anyhow!("PodSandbox metadata is required"))?;
+        let mut state = self.record("RunPodSandbox", &name);
+        if state.sandboxes.contains_key(&name) {
+            return Err(anyhow!("PodSandbox {} already exists", name));
+        }
+        state.sandboxes.insert(name.clone(), true);
+        Ok(RunPodSandboxResponse { pod_sandbox_id: name })
+    }
+
+    fn stop_pod_sandbox(&self, request: StopPodSandboxRequest) -> Result<StopPodSandboxResponse> {
+        let mut state = self.record("StopPodSandbox", &request.pod_sandbox_id);
+        // stopping a sandbox that is gone is not an error
+        if let Some(running) = state.sandboxes.get_mut(&request.pod_sandbox_id) {
+            *running = false;
+        }
+        Ok(StopPodSandboxResponse {})
+    }
+
+    fn remove_pod_sandbox(&self, request: RemovePodSandboxRequest) -> Result<RemovePodSandboxResponse> {
+        let mut state = self.record("RemovePodSandbox", &request.pod_sandbox_id);
+        state.sandboxes.remove(&request.pod_sandbox_id);
+        // and its containers with it
+        state.containers.retain(|_, (sandbox, _)| *sandbox != request.pod_sandbox_id);
+        Ok(RemovePodSandboxResponse {})
+    }
+
+    fn create_container(&self, request: CreateContainerRequest) -> Result<CreateContainerResponse> {
+        let config = request.config.ok_or_else(|| anyhow!("Container config is required"))?;
+        let name = config.metadata.map(|m| m.name).ok_or_else(|| anyhow!("Container metadata is required"))?;
+        let image = config.image.map(|image| image.image).unwrap_or_default();
+        let mut state = self.record("CreateContainer", &name);
+        if state.sandboxes.get(&request.pod_sandbox_id) != Some(&true) {
+            return Err(anyhow!("PodSandbox {} is not running", request.pod_sandbox_id));
+        }
+        // restored from a checkpoint archive when the image is one
+        if !state.images.contains(&image) && !checkpoint::is_archive(&image) {
+            return Err(anyhow!("image {} is not present", image));
+        }
+        state.containers.insert(name.clone(), (request.pod_sandbox_id, false));
+        Ok(CreateContainerResponse { container_id: name })
+    }
+
+    fn start_container(&self, request: StartContainerRequest) -> Result<StartContainerResponse> {
+        let mut state = self.record("StartContainer", &request.container_id);
+        match state.containers.get_mut(&request.container_id) {
+            Some((_, started)) if !*started => *started = true,
+            Some(_) => return Err(anyhow!("container {} is already started", request.container_id)),
+            None => return Err(anyhow!("container {} not found", request.container_id)),
+        }
+        Ok(StartContainerResponse {})
+    }
+
+    fn image_status(&self, request: ImageStatusRequest) -> Result<ImageStatusResponse> {
+        let image = request.image.map(|image| image.image).unwrap_or_default();
+        let state = self.record("ImageStatus", &image);
+        let present = state.images.contains(&image);
+        Ok(ImageStatusResponse {
+            image: present.then(|| Image { id: image.clone(), ..Default::default() }),
+            ..Default::default()
+        })
+    }
+
+    fn pull_image(&self, request: PullImageRequest) -> Result<PullImageResponse> {
+        let image = request.image.map(|image| image.image).unwrap_or_default();
+        let mut state = self.record("PullImage", &image);
+        state.images.insert(image.clone());
+        Ok(PullImageResponse { image_ref: image })
+    }
+
+    fn list_images(&self, request: ListImagesRequest) -> Result<ListImagesResponse> {
+        let filter = request.filter.and_then(|filter| filter.image).map(|image| image.image).unwrap_or_default();
+        let state = self.record("ListImages", &filter);
+        let mut images: Vec<Image> = state
+            .images
+            .iter()
+            .filter(|image| filter.is_empty() || **image == filter)
+            .map(|image| Image { id: image.clone(), repo_tags: vec![image.clone()], ..Default::default() })
+            .collect();
+        images.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(ListImagesResponse { images })
+    }
+
+    fn remove_image(&self, request: RemoveImageRequest) -> Result<RemoveImageResponse> {
+        let image = request.image.map(|image| image.image).unwrap_or_default();
+        let mut state = self.record("RemoveImage", &image);
+        // like the CRI runtimes, removing an image that isn't there succeeds
+        state.images.remove(&image);
+        Ok(RemoveImageResponse {})
+    }
+
+    fn image_fs_info(&self, _request: ImageFsInfoRequest) -> Result<ImageFsInfoResponse> {
+        drop(self.record("ImageFsInfo", ""));
+        Ok(ImageFsInfoResponse::default())
+    }
+
+    fn reopen_container_log(&self, request: ReopenContainerLogRequest) -> Result<ReopenContainerLogResponse> {
+        let state = self.record("ReopenContainerLog", &request.container_id);
+        match state.containers.get(&request.container_id) {
+            Some((_, true)) => Ok(ReopenContainerLogResponse {}),
+            Some(_) => Err(anyhow!("container {} is not running", request.container_id)),
+            None => Err(anyhow!("container {} not found", request.container_id)),
+        }
+    }
+
+    fn pod_sandbox_status(&self, request: PodSandboxStatusRequest) -> Result<PodSandboxStatusResponse> {
+        let state = self.record("PodSandboxStatus", &request.pod_sandbox_id);
+        let running = state
+            .sandboxes
+            .get(&request.pod_sandbox_id)
+            .ok_or_else(|| anyhow!("PodSandbox {} not found", request.pod_sandbox_id))?;
+        let sandbox_state = if *running { PodSandboxState::SandboxReady } else { PodSandboxState::SandboxNotready };
+        Ok(PodSandboxStatusResponse {
+            status: Some(PodSandboxStatus { id: request.pod_sandbox_id, state: sandbox_state as i32, ..Default::default() }),
+            ..Default::default()
+        })
+    }
+
+    fn container_status(&self, request: ContainerStatusRequest) -> Result<ContainerStatusResponse> {
+        let state = self.record("ContainerStatus", &request.container_id);
+        let (_, started) = state
+            .containers
+            .get(&request.container_id)
+            .ok_or_else(|| anyhow!("container {} not found", request.container_id))?;
+        let container_state = if *started { ContainerState::ContainerRunning } else { ContainerState::ContainerCreated };
+        Ok(ContainerStatusResponse {
+            status: Some(ContainerStatus { id: request.container_id, state: container_state as i32, ..Default::default() }),
+            ..Default::default()
+        })
+    }
+
+    // the containers use nothing
+    fn container_stats(&self, request: ContainerStatsRequest) -> Result<ContainerStatsResponse> {
+        let state = self.record("ContainerStats", &request.container_id);
+        if !state.containers.contains_key(&request.container_id) {
+            return Err(anyhow!("container {} not found", request.container_id));
+        }
+        Ok(ContainerStatsResponse { stats: Some(container_stats(&request.container_id)) })
+    }
+
+    fn pod_sandbox_stats(&self, request: PodSandboxStatsRequest) -> Result<PodSandboxStatsResponse> {
+        let state = self.record("PodSandboxStats", &request.pod_sandbox_id);
+        if !state.sandboxes.contains_key(&request.pod_sandbox_id) {
+            return Err(anyhow!("PodSandbox {} not found", request.pod_sandbox_id));
+        }
+        let mut containers: Vec<ContainerStats> = state
+            .containers
+            .iter()
+            .filter(|(_, (sandbox, _))| *sandbox == request.pod_sandbox_id)
+            .map(|(id, _)| container_stats(id))
+            .collect();
+        containers.sort_by(|a, b| a.attributes.as_ref().map(|a| &a.id).cmp(&b.attributes.as_ref().map(|b| &b.id)));
+        Ok(PodSandboxStatsResponse {
+            stats: Some(PodSandboxStats {
+                attributes: Some(PodSandboxAttributes { id: request.pod_sandbox_id, ..Default::default() }),
+                linux: Some(LinuxPodSandboxStats { containers, ..Default::default() }),
+                windows: None,
+            }),
+        })
+    }
+
+    fn stop_container(&self, request: StopContainerRequest) -> Result<StopContainerResponse> {
+        let state = self.record("StopContainer", &request.container_id);
+        if !state.containers.contains_key(&request.container_id) {
+            return Err(anyhow!("container {} not found", request.container_id));
+        }
+        Ok(StopContainerResponse {})
+    }
+
+    // every command succeeds without output
+    fn exec_sync(&self, request: ExecSyncRequest) -> Result<ExecSyncResponse> {
+        let state = self.record("ExecSync", &request.container_id);
+        match state.containers.get(&request.container_id) {
+            Some((_, true)) => Ok(ExecSyncResponse::default()),
+            Some(_) => Err(anyhow!("container {} is not running", request.container_id)),
+            None => Err(anyhow!("container {} not found", request.container_id)),
+        }
+    }
+
+    // the archive only holds the id of the container
+    fn checkpoint_container(&self, request: CheckpointContainerRequest) -> Result<CheckpointContainerResponse> {
+        let state = self.record("CheckpointContainer", &request.container_id);
+        match state.containers.get(&request.container_id) {
+            Some((_, true)) => {
+                std::fs::write(&request.location, &request.container_id)?;
+                Ok(CheckpointContainerResponse {})
+            }
+            Some(_) => Err(anyhow!("container {} is not running", request.container_id)),
+            None => Err(anyhow!("container {} not found", request.container_id)),
+        }
+    }
+}
+
+fn container_stats(id: &str) -> ContainerStats {
+    ContainerStats {
+        attributes: Some(ContainerAttributes {
+            id: id.to_string(),
+            metadata: Some(ContainerMetadata { name: id.to_string(), attempt: 0 }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::task::task::{FailurePolicy, PodTask, TaskRunner};
+
+    fn calls(mock: &MockRuntime) -> Vec<String> {
+        mock.state.lock().unwrap().calls.clone()
+    }
+
+    #[test]
+    fn test_run_pod() {
+        let task: PodTask = serde_yaml::from_str(
+            "
+apiVersion: v1
+kind: Pod
+metadata:
+  name: web
+spec:
+  containers:
+  - name: nginx
+    image: nginx:1.27
+  - name: broken
+    image: busybox:1.36
+    imagePullPolicy: Never
+",
+        )
+        .unwrap();
+        let mock = Arc::new(MockRuntime::default());
+        let mut runner = TaskRunner {
+            task,
+            pause_pid: None,
+            sandbox_config: None,
+            container_statuses: Vec::new(),
+            network_status: None,
+            backend: Some(mock.clone()),
+        };
+        // the image of the second container isn't there, the pod is rolled back
+        assert!(runner.run(FailurePolicy::Rollback).is_err());
+        assert_eq!(
+            calls(&mock),
+            [
+                "RunPodSandbox web",
+                "PodSandboxStatus web",
+                "ImageStatus nginx:1.27",
+                "ImageFsInfo",
+                "PullImage nginx:1.27",
+                "ImageFsInfo",
+                "CreateContainer nginx",
+                "PodSandboxStatus web",
+                "ImageStatus busybox:1.36",
+                "StopPodSandbox web",
+                "RemovePodSandbox web",
+            ]
+        );
+
+        runner.task.spec.containers.pop();
+        runner.container_statuses.clear();
+        assert_eq!(runner.run(FailurePolicy::Rollback).unwrap(), "web");
+        assert!(calls(&mock).ends_with(&["ImageStatus nginx:1.27".to_string(), "CreateContainer nginx".to_string(), "StartContainer nginx".to_string()]));
+    }
+}
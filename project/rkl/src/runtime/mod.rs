@@ -0,0 +1,223 @@
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+use anyhow::{Result, anyhow};
+use crate::checkpoint::Format;
+use crate::cri::cri::{
+    CheckpointContainerRequest, CheckpointContainerResponse, ContainerStatsRequest, ContainerStatsResponse, ContainerStatusRequest, ContainerStatusResponse, CreateContainerRequest, CreateContainerResponse, ImageFsInfoRequest, ImageFsInfoResponse, ImageStatusRequest, ImageStatusResponse, ListImagesRequest, ListImagesResponse, PullImageRequest,
+    PodSandboxStatsRequest, PodSandboxStatsResponse, PodSandboxStatusRequest, PodSandboxStatusResponse, PullImageResponse, RemoveImageRequest, RemoveImageResponse, RemovePodSandboxRequest, RemovePodSandboxResponse, ReopenContainerLogRequest,
+    ReopenContainerLogResponse, RunPodSandboxRequest,
+    RunPodSandboxResponse, StartContainerRequest, StartContainerResponse, StatusRequest, StatusResponse,
+    ExecSyncRequest, ExecSyncResponse, StopContainerRequest, StopContainerResponse, StopPodSandboxRequest, StopPodSandboxResponse, VersionRequest, VersionResponse,
+};
+use crate::image::pull::Cancel;
+use crate::node::{self, NodeConfig};
+use crate::rootpath;
+use tracing::warn;
+
+pub mod class;
+pub mod remote;
+pub mod mock;
+pub mod oci;
+
+// By default rkl is its own container runtime: the CRI calls of a pod are
+// served in process on top of libcontainer. With --runtime-endpoint, or the
+// runtimeEndpoint of the node configuration, they are sent to a CRI runtime
+// instead, containerd or CRI-O over their unix socket, or to an in-memory mock.
+// Or rkl serves them itself with an OCI runtime binary, runc, crun or youki,
+// doing the containers (see oci.rs).
+// The runtime is probed once before the first call: its Version tells which
+// runtime it is and which CRI version it speaks, its Status whether it is
+// ready to run pods and whether its network is. The RuntimeClass of a pod picks
+// the runtime handler of its calls (see class.rs).
+
+pub const CONTAINERD_ENDPOINT: &str = "unix:///run/containerd/containerd.sock";
+pub const CRIO_ENDPOINT: &str = "unix:///var/run/crio/crio.sock";
+// the only CRI version rkl speaks
+pub const CRI_API_VERSION: &str = "v1";
+
+pub const RUNTIME_READY: &str = "RuntimeReady";
+pub const NETWORK_READY: &str = "NetworkReady";
+
+// the CRI calls rkl makes to run pods
+pub trait RuntimeBackend: Send + Sync {
+    fn version(&self, request: VersionRequest) -> Result<VersionResponse>;
+    fn status(&self, request: StatusRequest) -> Result<StatusResponse>;
+    fn run_pod_sandbox(&self, request: RunPodSandboxRequest) -> Result<RunPodSandboxResponse>;
+    fn stop_pod_sandbox(&self, request: StopPodSandboxRequest) -> Result<StopPodSandboxResponse>;
+    fn remove_pod_sandbox(&self, request: RemovePodSandboxRequest) -> Result<RemovePodSandboxResponse>;
+    fn create_container(&self, request: CreateContainerRequest) -> Result<CreateContainerResponse>;
+    fn start_container(&self, request: StartContainerRequest) -> Result<StartContainerResponse>;
+    fn image_status(&self, request: ImageStatusRequest) -> Result<ImageStatusResponse>;
+    fn pull_image(&self, request: PullImageRequest) -> Result<PullImageResponse>;
+    // pull_image, giving up once cancelled where the runtime can stop a pull
+    fn pull_image_cancellable(&self, request: PullImageRequest, _cancel: &Cancel) -> Result<PullImageResponse> {
+        self.pull_image(request)
+    }
+    fn list_images(&self, request: ListImagesRequest) -> Result<ListImagesResponse>;
+    fn remove_image(&self, request: RemoveImageRequest) -> Result<RemoveImageResponse>;
+    fn image_fs_info(&self, request: ImageFsInfoRequest) -> Result<ImageFsInfoResponse>;
+    // open the log file of the container again after it was rotated
+    fn reopen_container_log(&self, request: ReopenContainerLogRequest) -> Result<ReopenContainerLogResponse>;
+    fn pod_sandbox_status(&self, request: PodSandboxStatusRequest) -> Result<PodSandboxStatusResponse>;
+    fn container_status(&self, request: ContainerStatusRequest) -> Result<ContainerStatusResponse>;
+    fn container_stats(&self, request: ContainerStatsRequest) -> Result<ContainerStatsResponse>;
+    fn pod_sandbox_stats(&self, request: PodSandboxStatsRequest) -> Result<PodSandboxStatsResponse>;
+    // SIGTERM, and SIGKILL once the timeout of the request is over
+    fn stop_container(&self, request: StopContainerRequest) -> Result<StopContainerResponse>;
+    fn exec_sync(&self, request: ExecSyncRequest) -> Result<ExecSyncResponse>;
+    // an archive of the state of a running container at the location of the request
+    fn checkpoint_container(&self, request: CheckpointContainerRequest) -> Result<CheckpointContainerResponse>;
+    // what the checkpoint archives are, the ones the runtime restores containers from
+    fn checkpoint_format(&self) -> Format {
+        Format::Cri
+    }
+}
+
+// what the probing found out about a runtime
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capabilities {
+    pub runtime_name: String,
+    pub runtime_version: String,
+    pub api_version: String,
+    pub network_ready: bool,
+}
+
+// where the CRI calls go
+#[derive(Debug, Clone, PartialEq)]
+pub enum Endpoint {
+    Unix(PathBuf),
+    Mock,
+    // an OCI runtime binary
+    Oci(PathBuf),
+}
+
+impl Endpoint {
+    // containerd, crio, mock, runc, crun, youki, oci://<binary>, unix:///path or /path
+    pub fn parse(endpoint: &str) -> Result<Self> {
+        if let Some(binary) = endpoint.strip_prefix("oci://") {
+            return Ok(Endpoint::Oci(PathBuf::from(binary)));
+        }
+        let endpoint = match endpoint {
+            "containerd" => CONTAINERD_ENDPOINT,
+            "crio" | "cri-o" => CRIO_ENDPOINT,
+            "mock" => return Ok(Endpoint::Mock),
+            "runc" | "crun" | "youki" => return Ok(Endpoint::Oci(PathBuf::from(endpoint))),
+            endpoint => endpoint,
+        };
+        let path = endpoint.strip_prefix("unix://").unwrap_or(endpoint);
+        if !path.starts_with('/') {
+            return Err(anyhow!("unsupported runtime endpoint {}, expected containerd, crio, mock, runc, crun, youki, oci://<binary> or unix:///<path>", endpoint));
+        }
+        Ok(Endpoint::Unix(PathBuf::from(path)))
+    }
+}
+
+static ENDPOINT: OnceLock<Option<String>> = OnceLock::new();
+// the backend with what its probing found, None without endpoint
+type Connected = Option<(Arc<dyn RuntimeBackend>, Capabilities)>;
+
+static BACKEND: OnceLock<Result<Connected, String>> = OnceLock::new();
+
+// set once at startup from --runtime-endpoint
+pub fn init(endpoint: Option<String>) {
+    let _ = ENDPOINT.set(endpoint);
+}
+
+// the flag wins over the node configuration
+fn configured_endpoint() -> Result<Option<String>> {
+    if let Some(endpoint) = ENDPOINT.get().cloned().flatten() {
+        return Ok(Some(endpoint));
+    }
+    Ok(NodeConfig::load(node::config_path())?.runtime_endpoint)
+}
+
+fn connect() -> Result<Connected> {
+    let Some(endpoint) = configured_endpoint()? else {
+        return Ok(None);
+    };
+    let backend: Arc<dyn RuntimeBackend> = match Endpoint::parse(&endpoint)? {
+        Endpoint::Unix(path) => Arc::new(remote::RemoteRuntime::connect(&path)?),
+        Endpoint::Mock => Arc::new(mock::MockRuntime::default()),
+        Endpoint::Oci(binary) => {
+            let binary = oci::find_binary(&binary.to_string_lossy())?;
+            Arc::new(oci::OciRuntime::new(binary, &rootpath::determine(None)?))
+        }
+    };
+    let capabilities = probe(backend.as_ref()).map_err(|e| anyhow!("runtime endpoint {}: {}", endpoint, e))?;
+    Ok(Some((backend, capabilities)))
+}
+
+// the CRI runtime the calls go to, None when rkl runs the pods itself
+pub fn backend() -> Result<Option<Arc<dyn RuntimeBackend>>> {
+    match BACKEND.get_or_init(|| connect().map_err(|e| e.to_string())) {
+        Ok(backend) => Ok(backend.as_ref().map(|(backend, _)| backend.clone())),
+        Err(e) => Err(anyhow!("{}", e)),
+    }
+}
+
+// check that the runtime speaks our CRI version and can run pods
+pub fn probe(backend: &dyn RuntimeBackend) -> Result<Capabilities> {
+    let version = backend.version(VersionRequest { version: CRI_API_VERSION.to_string() })?;
+    if version.runtime_api_version != CRI_API_VERSION {
+        return Err(anyhow!(
+            "{} {} speaks CRI {}, not {}",
+            version.runtime_name,
+            version.runtime_version,
+            version.runtime_api_version,
+            CRI_API_VERSION
+        ));
+    }
+    let status = backend.status(StatusRequest { verbose: false })?.status.unwrap_or_default();
+    let condition = |kind: &str| status.conditions.iter().find(|condition| condition.r#type == kind);
+    match condition(RUNTIME_READY) {
+        Some(ready) if ready.status => {}
+        Some(ready) => return Err(anyhow!("{} is not ready: {} {}", version.runtime_name, ready.reason, ready.message)),
+        None => return Err(anyhow!("{} doesn't report whether it is ready", version.runtime_name)),
+    }
+    let network_ready = condition(NETWORK_READY).is_some_and(|ready| ready.status);
+    if !network_ready {
+        warn!("The network of {} is not ready, pods may not get an IP", version.runtime_name);
+    }
+    Ok(Capabilities {
+        runtime_name: version.runtime_name,
+        runtime_version: version.runtime_version,
+        api_version: version.runtime_api_version,
+        network_ready,
+    })
+}
+
+// `rkl runtime info`
+pub fn print_info() -> Result<()> {
+    backend()?;
+    match BACKEND.get() {
+        Some(Ok(Some((_, capabilities)))) => {
+            println!("Runtime:     {} {}", capabilities.runtime_name, capabilities.runtime_version);
+            println!("CRI version: {}", capabilities.api_version);
+            println!("Network:     {}", if capabilities.network_ready { "ready" } else { "not ready" });
+        }
+        _ => {
+            println!("Runtime:     rkl (libcontainer) {}", env!("CARGO_PKG_VERSION"));
+            println!("CRI version: {}", CRI_API_VERSION);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint() {
+        assert_eq!(Endpoint::parse("containerd").unwrap(), Endpoint::Unix(PathBuf::from("/run/containerd/containerd.sock")));
+        assert_eq!(Endpoint::parse("unix:///run/x.sock").unwrap(), Endpoint::Unix(PathBuf::from("/run/x.sock")));
+        assert_eq!(Endpoint::parse("mock").unwrap(), Endpoint::Mock);
+        assert_eq!(Endpoint::parse("youki").unwrap(), Endpoint::Oci(PathBuf::from("youki")));
+        assert_eq!(Endpoint::parse("oci:///usr/local/bin/runc").unwrap(), Endpoint::Oci(PathBuf::from("/usr/local/bin/runc")));
+        assert!(Endpoint::parse("tcp://10.0.0.1:1234").is_err());
+
+        let capabilities = probe(&mock::MockRuntime::default()).unwrap();
+        assert_eq!(capabilities.runtime_name, mock::RUNTIME_NAME);
+        assert!(capabilities.network_ready);
+    }
+}
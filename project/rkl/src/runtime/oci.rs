@@ -0,0 +1,649 @@
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use anyhow::{Result, anyhow};
+use libcontainer::oci_spec::runtime::{ProcessBuilder, Spec};
+use serde::Deserialize;
+use crate::checkpoint::{self, Format};
+use crate::cri::cri::{
+    CheckpointContainerRequest, CheckpointContainerResponse, ContainerAttributes, ContainerConfig, ContainerMetadata, ContainerState, ContainerStats, ContainerStatsRequest,
+    ContainerStatsResponse, ContainerStatus, ContainerStatusRequest, ContainerStatusResponse, CpuUsage, MemoryUsage,
+    CreateContainerRequest, CreateContainerResponse, Image, ImageFsInfoRequest, ImageFsInfoResponse, ImageStatusRequest, ImageStatusResponse,
+    LinuxPodSandboxStats, ListImagesRequest, ListImagesResponse, PodSandboxAttributes, PodSandboxState, PodSandboxStats, PodSandboxStatsRequest,
+    PodSandboxStatsResponse, PodSandboxStatus, PodSandboxStatusRequest, UInt64Value, PodSandboxStatusResponse, PullImageRequest, PullImageResponse, RemoveImageRequest, RemoveImageResponse, RemovePodSandboxRequest, RemovePodSandboxResponse,
+    ReopenContainerLogRequest, ReopenContainerLogResponse, RunPodSandboxRequest, RunPodSandboxResponse,
+    RuntimeCondition, RuntimeStatus, StartContainerRequest, StartContainerResponse, StatusRequest, StatusResponse,
+    ExecSyncRequest, ExecSyncResponse, StopContainerRequest, StopContainerResponse, StopPodSandboxRequest, StopPodSandboxResponse, VersionRequest, VersionResponse,
+};
+use crate::device::host;
+use crate::image::{self, pull::Cancel};
+use crate::runtime::{CRI_API_VERSION, NETWORK_READY, RUNTIME_READY, RuntimeBackend};
+use crate::task::{cni, dns, hosts, logs, qos, sysctl};
+use crate::task::task::{self as pod, add_bind_mount, build_namespaces};
+use tracing::warn;
+
+// The CRI calls served by rkl itself with an OCI runtime binary, runc, crun or
+// youki, doing the containers, so that no CRI daemon is needed. Images come
+// from the image store of rkl and are unpacked into a bundle per container,
+// whose config.json is the one of the image with the command, env and mounts
+// of the container and the namespaces of its sandbox. The sandbox is the pause
+// bundle of the "bundle" label of the pod, like with the built-in runtime, and
+// gets its network the same way. The runtime handler of a pod, see class.rs,
+// is the binary its sandbox and containers are run with instead of the one of
+// the endpoint, e.g. runsc. Every runtime keeps its state under
+// <root>/oci/<runtime>. A container whose image is a checkpoint archive is
+// restored from it with `<runtime> restore` instead of created (see
+// checkpoint.rs), it is running once created.
+
+// the state of a container as every OCI runtime prints it
+#[derive(Debug, Deserialize)]
+struct OciState {
+    status: String,
+    #[serde(default)]
+    pid: i32,
+}
+
+pub struct OciRuntime {
+    // the binary of pods without runtime handler
+    binary: PathBuf,
+    // the root directory of rkl
+    root_path: PathBuf,
+}
+
+// the binary of a runtime given by name, looked up in PATH
+pub fn find_binary(name: &str) -> Result<PathBuf> {
+    if name.contains('/') {
+        return Ok(PathBuf::from(name));
+    }
+    std::env::var_os("PATH")
+        .iter()
+        .flat_map(std::env::split_paths)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+        .ok_or_else(|| anyhow!("OCI runtime {} not found in PATH", name))
+}
+
+impl OciRuntime {
+    pub fn new(binary: PathBuf, root_path: &Path) -> Self {
+        OciRuntime { binary, root_path: root_path.to_path_buf() }
+    }
+
+    fn command(&self, binary: &Path) -> Command {
+        let name = binary.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let mut command = Command::new(binary);
+        command.arg("--root").arg(self.root_path.join("oci").join(name));
+        command
+    }
+
+    fn run(&self, binary: &Path, args: &[&str]) -> Result<String> {
+        let output = self
+            .command(binary)
+            .args(args)
+            .output()
+            .map_err(|e| anyhow!("Failed to run {}: {}", binary.display(), e))?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "{} {} failed: {}",
+                binary.display(),
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    // the container process keeps the stdio of create, which therefore can't be
+    // captured: it is the log of the container, or nothing, and the errors of
+    // the runtime go to a log of their own in the sandbox directory; restoring
+    // it from the CRIU images of a checkpoint instead when given
+    fn create(&self, binary: &Path, sandbox_id: &str, bundle: &Path, id: &str, log: Option<File>, images: Option<&Path>) -> Result<()> {
+        let runtime_log = pod::sandbox_dir(&self.root_path, sandbox_id).join(format!("{}.runtime.log", id));
+        let (stdout, stderr) = match log {
+            Some(log) => (Stdio::from(log.try_clone()?), Stdio::from(log)),
+            None => (Stdio::null(), Stdio::null()),
+        };
+        let mut command = self.command(binary);
+        command.arg("--log").arg(&runtime_log);
+        match images {
+            Some(images) => command.arg("restore").arg("--detach").arg("--image-path").arg(images),
+            None => command.arg("create"),
+        };
+        let action = if images.is_some() { "restore" } else { "create" };
+        let status = command
+            .arg("--bundle")
+            .arg(bundle)
+            .arg(id)
+            .stdin(Stdio::null())
+            .stdout(stdout)
+            .stderr(stderr)
+            .status()
+            .map_err(|e| anyhow!("Failed to run {}: {}", binary.display(), e))?;
+        if !status.success() {
+            let errors = fs::read_to_string(&runtime_log).unwrap_or_default();
+            let error = errors.lines().rev().find(|line| !line.trim().is_empty()).unwrap_or_default();
+            return Err(anyhow!("{} {} {} failed with {}: {}", binary.display(), action, id, status, error));
+        }
+        Ok(())
+    }
+
+    fn state(&self, binary: &Path, id: &str) -> Result<OciState> {
+        let state = self.run(binary, &["state", id])?;
+        serde_json::from_str(&state).map_err(|e| anyhow!("invalid state of {}: {}", id, e))
+    }
+
+    // the pid of the pause process of a running sandbox
+    fn sandbox_pid(&self, binary: &Path, sandbox_id: &str) -> Result<i32> {
+        let state = self.state(binary, sandbox_id)?;
+        if state.status != "running" || state.pid == 0 {
+            return Err(anyhow!("PodSandbox {} is {}", sandbox_id, state.status));
+        }
+        Ok(state.pid)
+    }
+
+    fn bundles_dir(&self, sandbox_id: &str) -> PathBuf {
+        pod::sandbox_dir(&self.root_path, sandbox_id).join("bundles")
+    }
+
+    fn handler_path(&self, sandbox_id: &str) -> PathBuf {
+        pod::sandbox_dir(&self.root_path, sandbox_id).join("runtime-handler")
+    }
+
+    // the binary of a runtime handler, the one of the endpoint by default
+    fn handler_binary(&self, handler: &str) -> Result<PathBuf> {
+        if handler.is_empty() {
+            return Ok(self.binary.clone());
+        }
+        find_binary(handler)
+    }
+
+    // the binary the sandbox was run with
+    fn sandbox_binary(&self, sandbox_id: &str) -> Result<PathBuf> {
+        let handler = fs::read_to_string(self.handler_path(sandbox_id)).unwrap_or_default();
+        self.handler_binary(handler.trim())
+    }
+
+    // the cgroup usage of a container, from `events --stats`
+    fn stats(&self, binary: &Path, container_id: &str) -> Result<ContainerStats> {
+        let output = self.run(binary, &["events", "--stats", container_id])?;
+        let event: serde_json::Value =
+            serde_json::from_str(&output).map_err(|e| anyhow!("invalid stats of {}: {}", container_id, e))?;
+        Ok(event_stats(container_id, &event))
+    }
+
+    // the sandbox of a container is the one its bundle was unpacked in
+    fn container_sandbox(&self, container_id: &str) -> Option<String> {
+        fs::read_dir(self.root_path.join("sandboxes"))
+            .ok()?
+            .flatten()
+            .find(|entry| entry.path().join("bundles").join(container_id).exists())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+    }
+}
+
+// the stats of `events --stats`: runc and crun print an event with the stats
+// as data, youki the cgroup stats of libcgroups
+fn event_stats(container_id: &str, event: &serde_json::Value) -> ContainerStats {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as i64)
+        .unwrap_or_default();
+    let stats = event.get("data").unwrap_or(event);
+    let number = |paths: &[&str]| paths.iter().find_map(|path| stats.pointer(path).and_then(serde_json::Value::as_u64));
+    let cpu = number(&["/cpu/usage/total", "/cpu/usage/usage_total"]);
+    let memory = number(&["/memory/usage/usage", "/memory/memory/usage"]);
+    let inactive_file = number(&["/memory/stats/inactive_file", "/memory/stats/total_inactive_file"]).unwrap_or_default();
+    ContainerStats {
+        attributes: Some(ContainerAttributes {
+            id: container_id.to_string(),
+            metadata: Some(ContainerMetadata { name: container_id.to_string(), attempt: 0 }),
+            ..Default::default()
+        }),
+        cpu: Some(CpuUsage { timestamp, usage_core_nano_seconds: cpu.map(|value| UInt64Value { value }), usage_nano_cores: None }),
+        memory: Some(MemoryUsage {
+            timestamp,
+            working_set_bytes: memory.map(|value| UInt64Value { value: value.saturating_sub(inactive_file) }),
+            usage_bytes: memory.map(|value| UInt64Value { value }),
+            ..Default::default()
+        }),
+        writable_layer: None,
+        swap: None,
+    }
+}
+
+// the spec of a container of the sandbox whose pause process is pause_pid, made
+// of the image config of the bundle
+fn container_spec(
+    mut spec: Spec,
+    config: &ContainerConfig,
+    pause_pid: i32,
+    resolv_conf: Option<&Path>,
+    cgroup_parent: &str,
+) -> Result<Spec> {
+    let mut process = spec.process().clone().unwrap_or_else(|| ProcessBuilder::default().build().unwrap());
+    // like with the built-in runtime the args are the whole command line
+    let args = if config.args.is_empty() { &config.command } else { &config.args };
+    if !args.is_empty() {
+        process.set_args(Some(args.clone()));
+    }
+    let mut env = process.env().clone().unwrap_or_default();
+    for kv in &config.envs {
+        env.retain(|var| var.split('=').next() != Some(kv.key.as_str()));
+        env.push(format!("{}={}", kv.key, kv.value));
+    }
+    process.set_env(Some(env));
+    if !config.working_dir.is_empty() {
+        process.set_cwd(PathBuf::from(&config.working_dir));
+    }
+    process.set_terminal(Some(config.tty));
+    spec.set_process(Some(process));
+    let mut annotations = spec.annotations().clone().unwrap_or_default();
+    annotations.extend(config.annotations.clone());
+    spec.set_annotations(Some(annotations));
+
+    let options = config
+        .linux
+        .as_ref()
+        .and_then(|linux| linux.security_context.as_ref())
+        .and_then(|context| context.namespace_options.clone())
+        .unwrap_or_default();
+    let mut linux = spec.linux().clone().unwrap_or_default();
+    linux.set_namespaces(Some(build_namespaces(pause_pid, &options)?));
+    spec.set_linux(Some(linux));
+    host::add_to_spec(&mut spec, &config.devices)?;
+    if let Some(resources) = config.linux.as_ref().and_then(|linux| linux.resources.as_ref()) {
+        let container_id = config.metadata.as_ref().map(|m| m.name.as_str()).unwrap_or_default();
+        qos::apply_to_spec(&mut spec, cgroup_parent, container_id, resources)?;
+    }
+
+    // the mounts of the image config stay, e.g. /proc
+    for mount in config.mounts.iter().filter(|mount| mount.host_path.starts_with('/')) {
+        add_bind_mount(&mut spec, Path::new(&mount.host_path), &mount.container_path, mount.readonly)?;
+    }
+    if let Some(resolv_conf) = resolv_conf {
+        add_bind_mount(&mut spec, resolv_conf, "/etc/resolv.conf", true)?;
+    }
+    Ok(spec)
+}
+
+impl RuntimeBackend for OciRuntime {
+    fn version(&self, _request: VersionRequest) -> Result<VersionResponse> {
+        let output = Command::new(&self.binary)
+            .arg("--version")
+            .output()
+            .map_err(|e| anyhow!("Failed to run {}: {}", self.binary.display(), e))?;
+        // e.g. "runc version 1.1.12" or "youki version 0.5.1"
+        let version = String::from_utf8_lossy(&output.stdout);
+        let mut words = version.lines().next().unwrap_or_default().split_whitespace();
+        let name = words.next().unwrap_or_default().to_string();
+        let runtime_version = words.last().unwrap_or_default().to_string();
+        Ok(VersionResponse {
+            version: "0.1.0".to_string(),
+            runtime_name: name,
+            runtime_version,
+            runtime_api_version: CRI_API_VERSION.to_string(),
+        })
+    }
+
+    fn status(&self, _request: StatusRequest) -> Result<StatusResponse> {
+        let ready = |kind: &str| RuntimeCondition { r#type: kind.to_string(), status: true, ..Default::default() };
+        // the network is the one of rkl, which is always there
+        let mut runtime_ready = ready(RUNTIME_READY);
+        if let Err(e) = self.run(&self.binary, &["list"]) {
+            runtime_ready.status = false;
+            runtime_ready.reason = "RuntimeNotRunnable".to_string();
+            runtime_ready.message = e.to_string();
+        }
+        Ok(StatusResponse {
+            status: Some(RuntimeStatus { conditions: vec![runtime_ready, ready(NETWORK_READY)] }),
+            ..Default::default()
+        })
+    }
+
+    fn run_pod_sandbox(&self, request: RunPodSandboxRequest) -> Result<RunPodSandboxResponse> {
+        let config = request.config.unwrap_or_default();
+        let metadata = config.metadata.clone().unwrap_or_default();
+        let sandbox_id = metadata.name.clone();
+        if config.windows.is_some() {
+            return Err(anyhow!("PodSandbox {}: OCI runtimes only run Linux pods", sandbox_id));
+        }
+        let bundle = config
+            .labels
+            .get("bundle")
+            .ok_or_else(|| anyhow!("bundle not found in Pod labels"))?;
+        let binary = self.handler_binary(&request.runtime_handler)?;
+        let dir = pod::sandbox_dir(&self.root_path, &sandbox_id);
+        fs::create_dir_all(&dir)?;
+        fs::write(self.handler_path(&sandbox_id), &request.runtime_handler)?;
+        qos::create_pod_cgroup(&dir, &config)?;
+        self.create(&binary, &sandbox_id, Path::new(bundle), &sandbox_id, None, None)?;
+        self.run(&binary, &["start", &sandbox_id])?;
+        let pid = self.sandbox_pid(&binary, &sandbox_id)?;
+        if !config.hostname.is_empty()
+            && let Err(e) = hosts::set_hostname(pid, &config.hostname)
+        {
+            warn!("Failed to set hostname of PodSandbox {}: {}", sandbox_id, e);
+        }
+
+        let host_network = config
+            .linux
+            .as_ref()
+            .and_then(|linux| linux.security_context.as_ref())
+            .and_then(|context| context.namespace_options.as_ref())
+            .is_some_and(|options| options.network == crate::cri::cri::NamespaceMode::Node as i32);
+        if !host_network
+            && let Err(e) = cni::setup(&self.root_path, &sandbox_id, &metadata.namespace, &metadata.name, pid)
+        {
+            let _ = self.run(&binary, &["delete", "--force", &sandbox_id]);
+            return Err(anyhow!("Failed to attach network of PodSandbox {}: {}", sandbox_id, e));
+        }
+        if let Some(dns_config) = &config.dns_config {
+            fs::write(dir.join("resolv.conf"), dns::render_resolv_conf(dns_config))?;
+        }
+        Ok(RunPodSandboxResponse { pod_sandbox_id: sandbox_id })
+    }
+
+    fn stop_pod_sandbox(&self, request: StopPodSandboxRequest) -> Result<StopPodSandboxResponse> {
+        let sandbox_id = request.pod_sandbox_id;
+        if let Err(e) = cni::teardown(&self.root_path, &sandbox_id) {
+            warn!("Failed to detach network of PodSandbox {}: {}", sandbox_id, e);
+        }
+        let binary = self.sandbox_binary(&sandbox_id)?;
+        // a sandbox that already stopped is fine
+        if self.state(&binary, &sandbox_id).is_ok_and(|state| state.status == "running") {
+            self.run(&binary, &["kill", &sandbox_id, "KILL"])?;
+        }
+        Ok(StopPodSandboxResponse {})
+    }
+
+    fn remove_pod_sandbox(&self, request: RemovePodSandboxRequest) -> Result<RemovePodSandboxResponse> {
+        let sandbox_id = request.pod_sandbox_id;
+        let binary = self.sandbox_binary(&sandbox_id)?;
+        // the containers go with their sandbox
+        if let Ok(entries) = fs::read_dir(self.bundles_dir(&sandbox_id)) {
+            for entry in entries.flatten() {
+                let container_id = entry.file_name().to_string_lossy().into_owned();
+                if let Err(e) = self.run(&binary, &["delete", "--force", &container_id]) {
+                    warn!("Failed to delete container {}: {}", container_id, e);
+                }
+            }
+        }
+        if self.state(&binary, &sandbox_id).is_ok() {
+            self.run(&binary, &["delete", "--force", &sandbox_id])?;
+        }
+        pod::remove_sandbox_dir(&self.root_path, &sandbox_id)?;
+        Ok(RemovePodSandboxResponse {})
+    }
+
+    fn create_container(&self, request: CreateContainerRequest) -> Result<CreateContainerResponse> {
+        let config = request.config.as_ref().ok_or_else(|| anyhow!("Container config is required"))?;
+        let container_id = config
+            .metadata
+            .as_ref()
+            .map(|m| m.name.clone())
+            .ok_or_else(|| anyhow!("Container metadata is required"))?;
+        let image = config.image.as_ref().map(|image| image.image.clone()).unwrap_or_default();
+        let binary = self.sandbox_binary(&request.pod_sandbox_id)?;
+        let pause_pid = self.sandbox_pid(&binary, &request.pod_sandbox_id)?;
+
+        let bundle_dir = self.bundles_dir(&request.pod_sandbox_id).join(&container_id);
+        let restore = checkpoint::is_archive(&image);
+        if restore {
+            checkpoint::unpack_bundle(Path::new(&image), &bundle_dir)?;
+        } else {
+            image::unpack(&self.root_path, &image, &bundle_dir)?;
+        }
+        let config_path = bundle_dir.join("config.json");
+        let spec = Spec::load(&config_path).map_err(|e| anyhow!("invalid image config of {}: {}", image, e))?;
+        let spec = if restore { checkpoint::without_bind_mounts(spec) } else { spec };
+        let resolv_conf = pod::sandbox_dir(&self.root_path, &request.pod_sandbox_id).join("resolv.conf");
+        let resolv_conf = resolv_conf.exists().then_some(resolv_conf.as_path());
+        let cgroup_parent = request
+            .sandbox_config
+            .as_ref()
+            .and_then(|sandbox_config| sandbox_config.linux.as_ref())
+            .map(|linux| linux.cgroup_parent.as_str())
+            .unwrap_or_default();
+        let mut spec = container_spec(spec, config, pause_pid, resolv_conf, cgroup_parent)?;
+        // the pause container comes from a bundle of its own, so the containers set the sysctls of the pod
+        if let Some(linux) = request.sandbox_config.as_ref().and_then(|sandbox_config| sandbox_config.linux.as_ref()) {
+            sysctl::add_to_spec(&mut spec, &linux.sysctls);
+        }
+        spec.save(&config_path).map_err(|e| anyhow!("Failed to write {}: {}", config_path.display(), e))?;
+
+        let log = match &request.sandbox_config {
+            Some(sandbox_config) if !config.stdin && !config.tty => logs::open_container_log(sandbox_config, config)?,
+            _ => None,
+        };
+        let images = restore.then(|| bundle_dir.join(checkpoint::IMAGES));
+        self.create(&binary, &request.pod_sandbox_id, &bundle_dir, &container_id, log, images.as_deref())?;
+        Ok(CreateContainerResponse { container_id })
+    }
+
+    fn start_container(&self, request: StartContainerRequest) -> Result<StartContainerResponse> {
+        let sandbox_id = self
+            .container_sandbox(&request.container_id)
+            .ok_or_else(|| anyhow!("container {} not found", request.container_id))?;
+        let binary = self.sandbox_binary(&sandbox_id)?;
+        // restored containers run from the start
+        let restored = self.bundles_dir(&sandbox_id).join(&request.container_id).join(checkpoint::IMAGES).exists();
+        if restored && self.state(&binary, &request.container_id).is_ok_and(|state| state.status == "running") {
+            return Ok(StartContainerResponse {});
+        }
+        self.run(&binary, &["start", &request.container_id])?;
+        Ok(StartContainerResponse {})
+    }
+
+    fn image_status(&self, request: ImageStatusRequest) -> Result<ImageStatusResponse> {
+        let image = request.image.map(|image| image.image).unwrap_or_default();
+        let present = image::is_present(&self.root_path, &image);
+        Ok(ImageStatusResponse {
+            image: present.then(|| Image { id: image.clone(), ..Default::default() }),
+            ..Default::default()
+        })
+    }
+
+    fn pull_image(&self, request: PullImageRequest) -> Result<PullImageResponse> {
+        image::pull_image(&self.root_path, &request)
+    }
+
+    fn pull_image_cancellable(&self, request: PullImageRequest, cancel: &Cancel) -> Result<PullImageResponse> {
+        image::pull_image_cancellable(&self.root_path, &request, cancel)
+    }
+
+    fn list_images(&self, request: ListImagesRequest) -> Result<ListImagesResponse> {
+        let filter = request.filter.and_then(|filter| filter.image).map(|image| image.image).filter(|image| !image.is_empty());
+        Ok(ListImagesResponse { images: image::list_images(&self.root_path, filter.as_deref()) })
+    }
+
+    fn remove_image(&self, request: RemoveImageRequest) -> Result<RemoveImageResponse> {
+        let image = request.image.map(|image| image.image).unwrap_or_default();
+        image::remove_image(&self.root_path, &image)?;
+        Ok(RemoveImageResponse {})
+    }
+
+    fn image_fs_info(&self, _request: ImageFsInfoRequest) -> Result<ImageFsInfoResponse> {
+        Ok(ImageFsInfoResponse { image_filesystems: vec![image::fs_info(&self.root_path)], ..Default::default() })
+    }
+
+    // the container holds its log file itself, it can only be copied and truncated
+    fn reopen_container_log(&self, request: ReopenContainerLogRequest) -> Result<ReopenContainerLogResponse> {
+        Err(anyhow!("the log of container {} can't be reopened by an OCI runtime", request.container_id))
+    }
+
+    fn pod_sandbox_status(&self, request: PodSandboxStatusRequest) -> Result<PodSandboxStatusResponse> {
+        let sandbox_id = request.pod_sandbox_id;
+        let state = self.state(&self.sandbox_binary(&sandbox_id)?, &sandbox_id)?;
+        let sandbox_state = match state.status.as_str() {
+            "running" => PodSandboxState::SandboxReady,
+            _ => PodSandboxState::SandboxNotready,
+        };
+        Ok(PodSandboxStatusResponse {
+            status: Some(PodSandboxStatus { id: sandbox_id, state: sandbox_state as i32, ..Default::default() }),
+            ..Default::default()
+        })
+    }
+
+    fn container_status(&self, request: ContainerStatusRequest) -> Result<ContainerStatusResponse> {
+        let container_id = request.container_id;
+        let sandbox_id = self
+            .container_sandbox(&container_id)
+            .ok_or_else(|| anyhow!("container {} not found", container_id))?;
+        let state = self.state(&self.sandbox_binary(&sandbox_id)?, &container_id)?;
+        let container_state = match state.status.as_str() {
+            "created" => ContainerState::ContainerCreated,
+            "running" | "paused" => ContainerState::ContainerRunning,
+            "stopped" => ContainerState::ContainerExited,
+            _ => ContainerState::ContainerUnknown,
+        };
+        Ok(ContainerStatusResponse {
+            status: Some(ContainerStatus { id: container_id, state: container_state as i32, ..Default::default() }),
+            ..Default::default()
+        })
+    }
+
+    fn container_stats(&self, request: ContainerStatsRequest) -> Result<ContainerStatsResponse> {
+        let sandbox_id = self
+            .container_sandbox(&request.container_id)
+            .ok_or_else(|| anyhow!("container {} not found", request.container_id))?;
+        let stats = self.stats(&self.sandbox_binary(&sandbox_id)?, &request.container_id)?;
+        Ok(ContainerStatsResponse { stats: Some(stats) })
+    }
+
+    fn pod_sandbox_stats(&self, request: PodSandboxStatsRequest) -> Result<PodSandboxStatsResponse> {
+        let sandbox_id = request.pod_sandbox_id;
+        let binary = self.sandbox_binary(&sandbox_id)?;
+        let mut containers = Vec::new();
+        for entry in fs::read_dir(self.bundles_dir(&sandbox_id))?.flatten() {
+            let container_id = entry.file_name().to_string_lossy().into_owned();
+            match self.stats(&binary, &container_id) {
+                Ok(stats) => containers.push(stats),
+                Err(e) => warn!("Failed to get the stats of container {}: {}", container_id, e),
+            }
+        }
+        Ok(PodSandboxStatsResponse {
+            stats: Some(PodSandboxStats {
+                attributes: Some(PodSandboxAttributes { id: sandbox_id, ..Default::default() }),
+                linux: Some(LinuxPodSandboxStats { containers, ..Default::default() }),
+                windows: None,
+            }),
+        })
+    }
+
+    // SIGTERM, waiting for the container to stop, and SIGKILL after the timeout
+    fn stop_container(&self, request: StopContainerRequest) -> Result<StopContainerResponse> {
+        let container_id = request.container_id;
+        let sandbox_id = self
+            .container_sandbox(&container_id)
+            .ok_or_else(|| anyhow!("container {} not found", container_id))?;
+        let binary = self.sandbox_binary(&sandbox_id)?;
+        let running = || self.state(&binary, &container_id).is_ok_and(|state| state.status == "running");
+        if !running() {
+            return Ok(StopContainerResponse {});
+        }
+        if request.timeout > 0 {
+            self.run(&binary, &["kill", &container_id, "TERM"])?;
+            let deadline = Instant::now() + Duration::from_secs(request.timeout as u64);
+            while running() && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(100));
+            }
+        }
+        if running() {
+            self.run(&binary, &["kill", &container_id, "KILL"])?;
+        }
+        Ok(StopContainerResponse {})
+    }
+
+    fn exec_sync(&self, request: ExecSyncRequest) -> Result<ExecSyncResponse> {
+        let container_id = request.container_id;
+        let sandbox_id = self
+            .container_sandbox(&container_id)
+            .ok_or_else(|| anyhow!("container {} not found", container_id))?;
+        let binary = self.sandbox_binary(&sandbox_id)?;
+        let mut child = self
+            .command(&binary)
+            .arg("exec")
+            .arg(&container_id)
+            .args(&request.cmd)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| anyhow!("Failed to run {}: {}", binary.display(), e))?;
+        // a timeout of 0 waits as long as the command runs
+        let deadline = (request.timeout > 0).then(|| Instant::now() + Duration::from_secs(request.timeout as u64));
+        while child.try_wait()?.is_none() {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(anyhow!("command timed out after {}s", request.timeout));
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        let output = child.wait_with_output()?;
+        Ok(ExecSyncResponse { stdout: output.stdout, stderr: output.stderr, exit_code: output.status.code().unwrap_or(-1) })
+    }
+
+    // the bundle of the container with the CRIU images of `<runtime> checkpoint`,
+    // left running; the timeout of the request isn't supported
+    fn checkpoint_container(&self, request: CheckpointContainerRequest) -> Result<CheckpointContainerResponse> {
+        let container_id = request.container_id;
+        let sandbox_id = self
+            .container_sandbox(&container_id)
+            .ok_or_else(|| anyhow!("container {} not found", container_id))?;
+        let binary = self.sandbox_binary(&sandbox_id)?;
+        let bundle_dir = self.bundles_dir(&sandbox_id).join(&container_id);
+        checkpoint::checkpoint_bundle(&bundle_dir, Path::new(&request.location), |images| {
+            let images = images.display().to_string();
+            self.run(&binary, &["checkpoint", "--image-path", &images, "--leave-running", "--tcp-established", &container_id])?;
+            Ok(())
+        })?;
+        Ok(CheckpointContainerResponse {})
+    }
+
+    fn checkpoint_format(&self) -> Format {
+        Format::Bundle
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cri::cri::{KeyValue, LinuxContainerConfig, LinuxContainerSecurityContext, Mount, NamespaceOption};
+    use libcontainer::oci_spec::runtime::LinuxNamespaceType;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_container_spec() {
+        let image: Spec = serde_json::from_str(
+            r#"{"ociVersion": "1.0.2", "root": {"path": "rootfs"},
+                "process": {"user": {"uid": 0, "gid": 0}, "args": ["nginx"], "env": ["PATH=/bin", "NGINX_VERSION=1.27"], "cwd": "/"}}"#,
+        )
+        .unwrap();
+        let config = ContainerConfig {
+            envs: vec![KeyValue { key: "PATH".to_string(), value: "/usr/bin".to_string() }],
+            mounts: vec![Mount { host_path: "/data".to_string(), container_path: "/var/data".to_string(), ..Default::default() }],
+            linux: Some(LinuxContainerConfig {
+                security_context: Some(LinuxContainerSecurityContext {
+                    namespace_options: Some(NamespaceOption::default()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            annotations: HashMap::from([("rk8s.io/container-hash".to_string(), "0a1b".to_string())]),
+            ..Default::default()
+        };
+        let spec = container_spec(image, &config, 42, Some(Path::new("/run/r.conf")), "").unwrap();
+        let process = spec.process().as_ref().unwrap();
+        // the image command stays when the container has none
+        assert_eq!(process.args().as_ref().unwrap(), &["nginx"]);
+        assert_eq!(process.env().as_ref().unwrap(), &["NGINX_VERSION=1.27", "PATH=/usr/bin"]);
+        let namespaces = spec.linux().as_ref().unwrap().namespaces().clone().unwrap();
+        let network = namespaces.iter().find(|ns| ns.typ() == LinuxNamespaceType::Network).unwrap();
+        assert_eq!(network.path().as_ref().unwrap(), Path::new("/proc/42/ns/net"));
+        let mounts: Vec<_> = spec.mounts().as_ref().unwrap().iter().map(|m| m.destination().clone()).collect();
+        assert!(mounts.contains(&PathBuf::from("/var/data")) && mounts.contains(&PathBuf::from("/etc/resolv.conf")));
+        assert_eq!(spec.annotations().as_ref().unwrap()["rk8s.io/container-hash"], "0a1b");
+    }
+}
@@ -0,0 +1,160 @@
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use anyhow::{Result, anyhow};
+use tokio::net::UnixStream;
+use tonic::codegen::InterceptedService;
+use tonic::transport::{Channel, Endpoint, Uri};
+use tower::service_fn;
+use crate::cri::cri::image_service_client::ImageServiceClient;
+use crate::cri::cri::runtime_service_client::RuntimeServiceClient;
+use crate::cri::cri::{
+    CheckpointContainerRequest, CheckpointContainerResponse, ContainerStatsRequest, ContainerStatsResponse, ContainerStatusRequest, ContainerStatusResponse, CreateContainerRequest, CreateContainerResponse, ImageFsInfoRequest, ImageFsInfoResponse, ImageStatusRequest, ImageStatusResponse, ListImagesRequest, ListImagesResponse, PullImageRequest,
+    PodSandboxStatsRequest, PodSandboxStatsResponse, PodSandboxStatusRequest, PodSandboxStatusResponse, PullImageResponse, RemoveImageRequest, RemoveImageResponse, RemovePodSandboxRequest, RemovePodSandboxResponse, ReopenContainerLogRequest,
+    ReopenContainerLogResponse, RunPodSandboxRequest,
+    RunPodSandboxResponse, StartContainerRequest, StartContainerResponse, StatusRequest, StatusResponse,
+    ExecSyncRequest, ExecSyncResponse, StopContainerRequest, StopContainerResponse, StopPodSandboxRequest, StopPodSandboxResponse, VersionRequest, VersionResponse,
+};
+use crate::image::pull::{CANCEL_POLL, Cancel};
+use crate::logging::Propagate;
+use crate::runtime::RuntimeBackend;
+
+// A CRI runtime listening on a unix socket, containerd and CRI-O alike. The
+// calls are made on a runtime of their own so that they can be made from the
+// synchronous code of rkl.
+
+pub struct RemoteRuntime {
+    path: PathBuf,
+    runtime: tokio::runtime::Runtime,
+    channel: Channel,
+}
+
+impl RemoteRuntime {
+    pub fn connect(path: &Path) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        let socket = path.to_path_buf();
+        // the uri is required but unused, the connector dials the socket
+        let channel = runtime
+            .block_on(Endpoint::from_static("http://[::]:50051").connect_with_connector(service_fn(move |_: Uri| {
+                UnixStream::connect(socket.clone())
+            })))
+            .map_err(|e| anyhow!("Failed to connect to {}: {:#}", path.display(), anyhow::Error::from(e)))?;
+        Ok(RemoteRuntime { path: path.to_path_buf(), runtime, channel })
+    }
+
+    fn call<T, F>(&self, method: &str, call: F) -> Result<T>
+    where
+        F: Future<Output = Result<tonic::Response<T>, tonic::Status>>,
+    {
+        self.runtime
+            .block_on(call)
+            .map(tonic::Response::into_inner)
+            .map_err(|status| anyhow!("{} on {}: {}", method, self.path.display(), status.message()))
+    }
+
+    // made for every call, in the trace of the caller
+    fn runtime_client(&self) -> RuntimeServiceClient<InterceptedService<Channel, Propagate>> {
+        RuntimeServiceClient::with_interceptor(self.channel.clone(), Propagate::current())
+    }
+
+    fn image_client(&self) -> ImageServiceClient<InterceptedService<Channel, Propagate>> {
+        ImageServiceClient::with_interceptor(self.channel.clone(), Propagate::current())
+    }
+}
+
+impl RuntimeBackend for RemoteRuntime {
+    fn version(&self, request: VersionRequest) -> Result<VersionResponse> {
+        self.call("Version", self.runtime_client().version(request))
+    }
+
+    fn status(&self, request: StatusRequest) -> Result<StatusResponse> {
+        self.call("Status", self.runtime_client().status(request))
+    }
+
+    fn run_pod_sandbox(&self, request: RunPodSandboxRequest) -> Result<RunPodSandboxResponse> {
+        self.call("RunPodSandbox", self.runtime_client().run_pod_sandbox(request))
+    }
+
+    fn stop_pod_sandbox(&self, request: StopPodSandboxRequest) -> Result<StopPodSandboxResponse> {
+        self.call("StopPodSandbox", self.runtime_client().stop_pod_sandbox(request))
+    }
+
+    fn remove_pod_sandbox(&self, request: RemovePodSandboxRequest) -> Result<RemovePodSandboxResponse> {
+        self.call("RemovePodSandbox", self.runtime_client().remove_pod_sandbox(request))
+    }
+
+    fn create_container(&self, request: CreateContainerRequest) -> Result<CreateContainerResponse> {
+        self.call("CreateContainer", self.runtime_client().create_container(request))
+    }
+
+    fn start_container(&self, request: StartContainerRequest) -> Result<StartContainerResponse> {
+        self.call("StartContainer", self.runtime_client().start_container(request))
+    }
+
+    fn image_status(&self, request: ImageStatusRequest) -> Result<ImageStatusResponse> {
+        self.call("ImageStatus", self.image_client().image_status(request))
+    }
+
+    fn pull_image(&self, request: PullImageRequest) -> Result<PullImageResponse> {
+        self.call("PullImage", self.image_client().pull_image(request))
+    }
+
+    // the runtime stops pulling when the call is dropped
+    fn pull_image_cancellable(&self, request: PullImageRequest, cancel: &Cancel) -> Result<PullImageResponse> {
+        let mut client = self.image_client();
+        let cancel = cancel.clone();
+        self.call("PullImage", async move {
+            tokio::select! {
+                response = client.pull_image(request) => response,
+                _ = async {
+                    while !cancel.is_cancelled() {
+                        tokio::time::sleep(CANCEL_POLL).await;
+                    }
+                } => Err(tonic::Status::cancelled("the pull was cancelled")),
+            }
+        })
+    }
+
+    fn list_images(&self, request: ListImagesRequest) -> Result<ListImagesResponse> {
+        self.call("ListImages", self.image_client().list_images(request))
+    }
+
+    fn remove_image(&self, request: RemoveImageRequest) -> Result<RemoveImageResponse> {
+        self.call("RemoveImage", self.image_client().remove_image(request))
+    }
+
+    fn image_fs_info(&self, request: ImageFsInfoRequest) -> Result<ImageFsInfoResponse> {
+        self.call("ImageFsInfo", self.image_client().image_fs_info(request))
+    }
+
+    fn reopen_container_log(&self, request: ReopenContainerLogRequest) -> Result<ReopenContainerLogResponse> {
+        self.call("ReopenContainerLog", self.runtime_client().reopen_container_log(request))
+    }
+
+    fn pod_sandbox_status(&self, request: PodSandboxStatusRequest) -> Result<PodSandboxStatusResponse> {
+        self.call("PodSandboxStatus", self.runtime_client().pod_sandbox_status(request))
+    }
+
+    fn container_status(&self, request: ContainerStatusRequest) -> Result<ContainerStatusResponse> {
+        self.call("ContainerStatus", self.runtime_client().container_status(request))
+    }
+
+    fn container_stats(&self, request: ContainerStatsRequest) -> Result<ContainerStatsResponse> {
+        self.call("ContainerStats", self.runtime_client().container_stats(request))
+    }
+
+    fn pod_sandbox_stats(&self, request: PodSandboxStatsRequest) -> Result<PodSandboxStatsResponse> {
+        self.call("PodSandboxStats", self.runtime_client().pod_sandbox_stats(request))
+    }
+
+    fn stop_container(&self, request: StopContainerRequest) -> Result<StopContainerResponse> {
+        self.call("StopContainer", self.runtime_client().stop_container(request))
+    }
+
+    fn exec_sync(&self, request: ExecSyncRequest) -> Result<ExecSyncResponse> {
+        self.call("ExecSync", self.runtime_client().exec_sync(request))
+    }
+
+    fn checkpoint_container(&self, request: CheckpointContainerRequest) -> Result<CheckpointContainerResponse> {
+        self.call("CheckpointContainer", self.runtime_client().checkpoint_container(request))
+    }
+}
@@ -0,0 +1,511 @@
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use anyhow::{Result, anyhow};
+use base64::Engine;
+use nix::mount::{self, MntFlags, MsFlags};
+use openssl::rand::rand_bytes;
+use openssl::symm::{Cipher, decrypt_aead, encrypt_aead};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use store::Store;
+use tracing::warn;
+use crate::printer::{Listing, OutputArgs};
+use crate::configmap::{self, KeyToPath};
+use crate::rootpath;
+use crate::strict;
+use crate::task::task::ObjectMeta;
+
+// Secrets are like config maps for sensitive values. They are stored with
+// `rkl secret apply` or as a manifest of `rkl daemon`, encrypted with
+// AES-256-GCM under a key of the node, <root>/keys/secrets.key, generated on
+// first use, which is all that stays on the node: the encrypted secrets are
+// kept as /registry/secrets/<name> of the store of --secret-store, by default
+// a file store of the node at <root>/secrets.db. Nodes sharing a store, e.g.
+// etcd, read each other's secrets once they have the same key. Secrets of the
+// <root>/secrets/<name>/secret.enc files of earlier versions are moved into
+// the store on first use.
+// Pods use them through env vars
+//
+//   env:
+//     - name: DB_PASSWORD
+//       valueFrom:
+//         secretKeyRef:
+//           name: db
+//           key: password
+//
+// and secret volumes, written to a tmpfs of the sandbox and mounted read-only
+// into the containers. A kubernetes.io/dockerconfigjson secret can be an
+// imagePullSecret as well. The values handed to containers are scrubbed from
+// the events and the --debug-cri log.
+
+pub const KIND: &str = "Secret";
+pub const DOCKER_CONFIG_JSON: &str = "kubernetes.io/dockerconfigjson";
+// the key of the docker config in a kubernetes.io/dockerconfigjson secret
+pub const DOCKER_CONFIG_JSON_KEY: &str = ".dockerconfigjson";
+
+const MAGIC: &[u8] = b"rk8s-secret-v1\n";
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const REDACTED: &str = "<redacted>";
+// shorter values would scrub unrelated text
+const MIN_SCRUBBED_LEN: usize = 4;
+const SECRETS_PREFIX: &str = "/registry/secrets/";
+// how long to wait for the file store while another rkl has it open
+const STORE_OPEN_TIMEOUT: Duration = Duration::from_secs(5);
+
+static STORE: OnceLock<Option<String>> = OnceLock::new();
+
+// set once at startup from --secret-store
+pub fn init(store: Option<String>) {
+    let _ = STORE.set(store);
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Secret {
+    #[serde(rename = "apiVersion")]
+    pub api_version: String,
+    pub kind: String,
+    pub metadata: ObjectMeta,
+    #[serde(rename = "type", default = "default_type")]
+    pub secret_type: String,
+    // base64 encoded values
+    #[serde(default)]
+    pub data: BTreeMap<String, String>,
+    // plain values, they win over the ones of data
+    #[serde(rename = "stringData", default)]
+    pub string_data: BTreeMap<String, String>,
+}
+
+fn default_type() -> String {
+    "Opaque".to_string()
+}
+
+// a secret as it is encrypted, the values in base64
+#[derive(Debug, Serialize, Deserialize)]
+struct Sealed {
+    #[serde(rename = "type")]
+    secret_type: String,
+    data: BTreeMap<String, String>,
+}
+
+// a decrypted secret of the store
+#[derive(Debug, PartialEq, Eq)]
+pub struct StoredSecret {
+    pub secret_type: String,
+    pub data: BTreeMap<String, Vec<u8>>,
+}
+
+// simulate Kubernetes SecretKeySelector
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SecretKeySelector {
+    pub name: String,
+    pub key: String,
+    #[serde(default)]
+    pub optional: bool,
+}
+
+// simulate Kubernetes SecretVolumeSource
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecretVolumeSource {
+    #[serde(rename = "secretName")]
+    pub secret_name: String,
+    #[serde(default)]
+    pub items: Vec<KeyToPath>,
+    #[serde(rename = "defaultMode", default = "configmap::default_mode")]
+    pub default_mode: u32,
+    #[serde(default)]
+    pub optional: bool,
+}
+
+fn key_path(root_path: &Path) -> PathBuf {
+    root_path.join("keys").join("secrets.key")
+}
+
+fn secret_key(name: &str) -> String {
+    format!("{}{}", SECRETS_PREFIX, name)
+}
+
+// the file of a secret of earlier versions
+fn legacy_path(root_path: &Path, name: &str) -> PathBuf {
+    root_path.join("secrets").join(name).join("secret.enc")
+}
+
+// the store of --secret-store, or the file store of the node, with the
+// secrets of earlier versions moved in
+fn open_store(root_path: &Path) -> Result<Box<dyn Store>> {
+    let url = match STORE.get().cloned().flatten() {
+        Some(url) => url,
+        None => root_path.join("secrets.db").display().to_string(),
+    };
+    // a file store is open in one process at a time, only briefly in rkl
+    let deadline = Instant::now() + STORE_OPEN_TIMEOUT;
+    let store = loop {
+        match store::open(&url) {
+            Ok(store) => break store,
+            Err(e) if Instant::now() >= deadline => return Err(anyhow!("Failed to open the secret store {}: {}", url, e)),
+            Err(_) => thread::sleep(Duration::from_millis(50)),
+        }
+    };
+    migrate(root_path, store.as_ref())?;
+    Ok(store)
+}
+
+fn migrate(root_path: &Path, store: &dyn Store) -> Result<()> {
+    let Ok(entries) = fs::read_dir(root_path.join("secrets")) else {
+        return Ok(());
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let path = legacy_path(root_path, &name);
+        if !configmap::valid_name(&name) || !path.exists() {
+            continue;
+        }
+        // a secret applied since wins
+        if store.get(&secret_key(&name))?.is_none() {
+            store.put(&secret_key(&name), &fs::read(&path)?)?;
+        }
+        fs::remove_file(&path)?;
+        // the directory may still hold a plain pull secret
+        let _ = fs::remove_dir(entry.path());
+        warn!("Moved Secret {} into the secret store", name);
+    }
+    Ok(())
+}
+
+// the key of the node, generated on first use
+fn node_key(root_path: &Path) -> Result<Vec<u8>> {
+    let path = key_path(root_path);
+    if !path.exists() {
+        let dir = path.parent().ok_or_else(|| anyhow!("Invalid key path {}", path.display()))?;
+        fs::create_dir_all(dir)?;
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+        let mut key = vec![0; KEY_LEN];
+        rand_bytes(&mut key)?;
+        // written in full under a name of its own and then linked into place,
+        // so that a concurrent rkl never reads a partial key
+        let mut suffix = [0; 8];
+        rand_bytes(&mut suffix)?;
+        let tmp = path.with_extension(format!("{}.tmp", u64::from_ne_bytes(suffix)));
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&tmp)
+            .map_err(|e| anyhow!("Failed to create the secret key {}: {}", tmp.display(), e))?;
+        let linked = file.write_all(&key).and_then(|_| file.sync_all()).and_then(|_| fs::hard_link(&tmp, &path));
+        let _ = fs::remove_file(&tmp);
+        match linked {
+            Ok(()) => {}
+            // generated by a concurrent rkl
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(anyhow!("Failed to create the secret key {}: {}", path.display(), e)),
+        }
+    }
+    let key = fs::read(&path).map_err(|e| anyhow!("Failed to read the secret key {}: {}", path.display(), e))?;
+    if key.len() != KEY_LEN {
+        return Err(anyhow!("Invalid secret key {}: expected {} bytes", path.display(), KEY_LEN));
+    }
+    Ok(key)
+}
+
+// MAGIC, nonce, tag and ciphertext; the name is authenticated so that the
+// file of one secret can't pass for another
+fn seal(key: &[u8], name: &str, plaintext: &[u8]) -> Result<Vec<u8>> {
+    let mut nonce = [0; NONCE_LEN];
+    rand_bytes(&mut nonce)?;
+    let mut tag = [0; TAG_LEN];
+    let ciphertext = encrypt_aead(Cipher::aes_256_gcm(), key, Some(&nonce), name.as_bytes(), plaintext, &mut tag)?;
+    Ok([MAGIC, &nonce, &tag, &ciphertext].concat())
+}
+
+fn open(key: &[u8], name: &str, sealed: &[u8]) -> Result<Vec<u8>> {
+    let body = sealed.strip_prefix(MAGIC).ok_or_else(|| anyhow!("not an encrypted secret"))?;
+    if body.len() < NONCE_LEN + TAG_LEN {
+        return Err(anyhow!("encrypted secret is truncated"));
+    }
+    let (nonce, rest) = body.split_at(NONCE_LEN);
+    let (tag, ciphertext) = rest.split_at(TAG_LEN);
+    decrypt_aead(Cipher::aes_256_gcm(), key, Some(nonce), name.as_bytes(), ciphertext, tag)
+        .map_err(|_| anyhow!("failed to decrypt, the node key doesn't match"))
+}
+
+impl Secret {
+    pub fn parse(contents: &str) -> Result<Self> {
+        let secret: Secret = strict::from_yaml(contents)?;
+        if secret.kind != KIND || secret.api_version != "v1" {
+            return Err(anyhow!("expected apiVersion v1 and kind Secret"));
+        }
+        if !configmap::valid_name(&secret.metadata.name) {
+            return Err(anyhow!("invalid Secret name {:?}", secret.metadata.name));
+        }
+        if let Some(key) = secret.data.keys().chain(secret.string_data.keys()).find(|key| !configmap::valid_key(key)) {
+            return Err(anyhow!("Secret {}: invalid key {:?}", secret.metadata.name, key));
+        }
+        let data = secret.entries()?;
+        if secret.secret_type == DOCKER_CONFIG_JSON && !data.contains_key(DOCKER_CONFIG_JSON_KEY) {
+            return Err(anyhow!("Secret {} of type {} has no {} key", secret.metadata.name, DOCKER_CONFIG_JSON, DOCKER_CONFIG_JSON_KEY));
+        }
+        Ok(secret)
+    }
+
+    // every key with its decoded value
+    fn entries(&self) -> Result<BTreeMap<String, Vec<u8>>> {
+        let mut entries = BTreeMap::new();
+        for (key, value) in &self.data {
+            let decoded = base64::engine::general_purpose::STANDARD
+                .decode(value)
+                .map_err(|e| anyhow!("Secret {}: data {} is not base64: {}", self.metadata.name, key, e))?;
+            entries.insert(key.clone(), decoded);
+        }
+        for (key, value) in &self.string_data {
+            entries.insert(key.clone(), value.clone().into_bytes());
+        }
+        Ok(entries)
+    }
+
+    // encrypt the secret into the store, returns whether it changed
+    pub fn save(&self, root_path: &Path) -> Result<bool> {
+        let stored = StoredSecret { secret_type: self.secret_type.clone(), data: self.entries()? };
+        let name = &self.metadata.name;
+        if load(root_path, name).ok().as_ref() == Some(&stored) {
+            return Ok(false);
+        }
+        let sealed = Sealed {
+            secret_type: stored.secret_type,
+            data: stored.data
+                .iter()
+                .map(|(key, value)| (key.clone(), base64::engine::general_purpose::STANDARD.encode(value)))
+                .collect(),
+        };
+        let contents = seal(&node_key(root_path)?, name, &serde_json::to_vec(&sealed)?)?;
+        open_store(root_path)?.put(&secret_key(name), &contents)?;
+        Ok(true)
+    }
+}
+
+// decrypt a secret of the store
+pub fn load(root_path: &Path, name: &str) -> Result<StoredSecret> {
+    if !configmap::valid_name(name) {
+        return Err(anyhow!("invalid Secret name {:?}", name));
+    }
+    let sealed = open_store(root_path)?.get(&secret_key(name))?.ok_or_else(|| anyhow!("Secret {} not found", name))?.value;
+    let plaintext = open(&node_key(root_path)?, name, &sealed).map_err(|e| anyhow!("Secret {}: {}", name, e))?;
+    let sealed: Sealed = serde_json::from_slice(&plaintext)?;
+    let mut data = BTreeMap::new();
+    for (key, value) in sealed.data {
+        data.insert(key, base64::engine::general_purpose::STANDARD.decode(value)?);
+    }
+    Ok(StoredSecret { secret_type: sealed.secret_type, data })
+}
+
+pub fn exists(root_path: &Path, name: &str) -> bool {
+    configmap::valid_name(name) && open_store(root_path).and_then(|store| store.get(&secret_key(name))).is_ok_and(|kv| kv.is_some())
+}
+
+pub fn remove(root_path: &Path, name: &str) -> Result<()> {
+    if !configmap::valid_name(name) || !open_store(root_path)?.delete(&secret_key(name), None)? {
+        return Err(anyhow!("Secret {} not found", name));
+    }
+    Ok(())
+}
+
+// the names of the stored secrets, in order
+pub fn list(root_path: &Path) -> Result<Vec<String>> {
+    Ok(open_store(root_path)?.list(SECRETS_PREFIX)?.items.into_iter().map(|kv| kv.key[SECRETS_PREFIX.len()..].to_string()).collect())
+}
+
+// values handed to containers by this process
+static REVEALED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+fn reveal(value: &[u8]) {
+    let value = String::from_utf8_lossy(value).to_string();
+    if value.len() < MIN_SCRUBBED_LEN {
+        return;
+    }
+    let mut revealed = REVEALED.lock().unwrap_or_else(|e| e.into_inner());
+    if !revealed.contains(&value) {
+        revealed.push(value);
+    }
+}
+
+// whether the value is the one of a secret used by this process
+pub fn is_revealed(value: &str) -> bool {
+    REVEALED.lock().unwrap_or_else(|e| e.into_inner()).iter().any(|revealed| revealed == value)
+}
+
+// the text with the values of the secrets used by this process redacted
+pub fn scrub(text: &str) -> String {
+    let revealed = REVEALED.lock().unwrap_or_else(|e| e.into_inner());
+    revealed.iter().fold(text.to_string(), |text, value| text.replace(value.as_str(), REDACTED))
+}
+
+// the value of a secretKeyRef env var, None when it is optional and missing
+pub fn key_value(root_path: &Path, selector: &SecretKeySelector) -> Result<Option<String>> {
+    if selector.optional && !exists(root_path, &selector.name) {
+        return Ok(None);
+    }
+    let secret = load(root_path, &selector.name)?;
+    match secret.data.get(&selector.key) {
+        Some(value) => {
+            reveal(value);
+            Ok(Some(String::from_utf8_lossy(value).to_string()))
+        }
+        None if selector.optional => Ok(None),
+        None => Err(anyhow!("Secret {} has no key {}", selector.name, selector.key)),
+    }
+}
+
+// write the keys of a secret volume into dir, a tmpfs mounted on first use so
+// that the values stay in memory
+pub fn project(root_path: &Path, source: &SecretVolumeSource, dir: &Path) -> Result<()> {
+    let data = if source.optional && !exists(root_path, &source.secret_name) {
+        BTreeMap::new()
+    } else {
+        load(root_path, &source.secret_name)?.data
+    };
+    data.values().for_each(|value| reveal(value));
+    fs::create_dir_all(dir)?;
+    if !is_mount_point(dir)? {
+        mount::mount(
+            Some("tmpfs"),
+            dir,
+            Some("tmpfs"),
+            MsFlags::MS_NOSUID | MsFlags::MS_NODEV | MsFlags::MS_NOEXEC,
+            Some("mode=0755"),
+        )
+        .map_err(|e| anyhow!("Failed to mount a tmpfs on {}: {}", dir.display(), e))?;
+    }
+    let object = format!("Secret {}", source.secret_name);
+    configmap::write_keys(&object, &data, &source.items, source.default_mode, source.optional, dir)
+}
+
+fn is_mount_point(dir: &Path) -> Result<bool> {
+    let parent = dir.parent().ok_or_else(|| anyhow!("{} has no parent", dir.display()))?;
+    Ok(fs::metadata(dir)?.dev() != fs::metadata(parent)?.dev())
+}
+
+// unmount the secret volumes of a sandbox before its directory is removed
+pub fn unmount_volumes(volumes_dir: &Path) -> Result<()> {
+    if !volumes_dir.exists() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(volumes_dir)? {
+        let path = entry?.path();
+        if is_mount_point(&path)? {
+            mount::umount2(&path, MntFlags::MNT_DETACH)
+                .map_err(|e| anyhow!("Failed to unmount {}: {}", path.display(), e))?;
+        }
+    }
+    Ok(())
+}
+
+// `rkl secret apply`
+pub fn apply(path: &str) -> Result<()> {
+    let contents = fs::read_to_string(path).map_err(|e| anyhow!("Failed to read {}: {}", path, e))?;
+    let secret = Secret::parse(&contents).map_err(|e| anyhow!("Invalid Secret {}: {}", path, e))?;
+    let root_path = rootpath::determine(None)?;
+    if secret.save(&root_path)? {
+        println!("Secret {} configured", secret.metadata.name);
+    } else {
+        println!("Secret {} unchanged", secret.metadata.name);
+    }
+    Ok(())
+}
+
+// `rkl secret delete`
+pub fn delete(name: &str) -> Result<()> {
+    let root_path = rootpath::determine(None)?;
+    remove(&root_path, name)?;
+    println!("Secret {} deleted", name);
+    Ok(())
+}
+
+// `rkl secret list`, the values are never shown
+// without the values, -o json and yaml only name the keys
+pub fn print_list(output: &OutputArgs) -> Result<()> {
+    let root_path = rootpath::determine(None)?;
+    let mut listing = Listing::new(&["NAME", "TYPE", "DATA"]);
+    for name in list(&root_path)? {
+        let secret = load(&root_path, &name)?;
+        let keys: Vec<&String> = secret.data.keys().collect();
+        let object = json!({ "apiVersion": "v1", "kind": "Secret", "metadata": { "name": name }, "type": secret.secret_type, "keys": keys });
+        listing.push(vec![name.clone(), secret.secret_type.clone(), secret.data.len().to_string()], object);
+    }
+    output.print(listing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "
+apiVersion: v1
+kind: Secret
+metadata:
+  name: db
+data:
+  password: aHVudGVyMg==
+stringData:
+  user: admin
+";
+
+    #[test]
+    fn test_encrypted_store() {
+        let root = tempfile::tempdir().unwrap();
+        let secret = Secret::parse(SECRET).unwrap();
+        assert!(secret.save(root.path()).unwrap());
+        assert!(!secret.save(root.path()).unwrap());
+        assert_eq!(list(root.path()).unwrap(), vec!["db"]);
+
+        // nothing of the values is in the store
+        let sealed = open_store(root.path()).unwrap().get(&secret_key("db")).unwrap().unwrap().value;
+        assert!(!String::from_utf8_lossy(&sealed).contains("hunter2"));
+        assert!(!fs::read_to_string(root.path().join("secrets.db")).unwrap().contains("hunter2"));
+        let stored = load(root.path(), "db").unwrap();
+        assert_eq!(stored.secret_type, "Opaque");
+        assert_eq!(stored.data["password"], b"hunter2");
+        // the value of a secret doesn't decrypt as another one
+        open_store(root.path()).unwrap().put(&secret_key("other"), &sealed).unwrap();
+        assert!(load(root.path(), "other").is_err());
+        remove(root.path(), "other").unwrap();
+        assert!(remove(root.path(), "other").is_err());
+
+        // the files of earlier versions move into the store
+        fs::create_dir_all(legacy_path(root.path(), "legacy").parent().unwrap()).unwrap();
+        fs::write(legacy_path(root.path(), "legacy"), seal(&node_key(root.path()).unwrap(), "legacy", br#"{"type": "Opaque", "data": {}}"#).unwrap()).unwrap();
+        assert_eq!(list(root.path()).unwrap(), vec!["db", "legacy"]);
+        assert!(!legacy_path(root.path(), "legacy").exists());
+        assert!(load(root.path(), "legacy").unwrap().data.is_empty());
+
+        let selector = SecretKeySelector { name: "db".to_string(), key: "password".to_string(), optional: false };
+        assert_eq!(key_value(root.path(), &selector).unwrap().as_deref(), Some("hunter2"));
+        assert_eq!(scrub("login failed for hunter2"), "login failed for <redacted>");
+        let missing = SecretKeySelector { name: "missing".to_string(), optional: true, ..selector.clone() };
+        assert_eq!(key_value(root.path(), &missing).unwrap(), None);
+        let missing_key = SecretKeySelector { key: "token".to_string(), ..selector };
+        assert!(key_value(root.path(), &missing_key).is_err());
+
+        let docker = SECRET.replace("metadata:", "type: kubernetes.io/dockerconfigjson\nmetadata:");
+        assert!(Secret::parse(&docker).is_err());
+        assert!(Secret::parse(&SECRET.replace("aHVudGVyMg==", "hunter2!")).is_err());
+    }
+
+    #[test]
+    fn test_node_key_generated_concurrently() {
+        let root = tempfile::tempdir().unwrap();
+        let keys: Vec<Vec<u8>> = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..8).map(|_| scope.spawn(|| node_key(root.path()).unwrap())).collect();
+            threads.into_iter().map(|thread| thread.join().unwrap()).collect()
+        });
+        assert!(keys.iter().all(|key| *key == keys[0]));
+        // only the key is left behind
+        assert_eq!(fs::read_dir(root.path().join("keys")).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,319 @@
+use std::collections::HashMap;
+use std::fmt;
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use serde_json::Value;
+
+// Kubernetes label selectors, as written after -l
+//
+//   app=web,tier in (frontend,cache),!canary
+//
+// a comma separated list of requirements that all have to hold: key=value (or
+// key==value), key!=value, key in (values), key notin (values), key to have
+// the label and !key not to. Like in Kubernetes != and notin also match pods
+// without the label. The empty selector matches everything. The matchLabels
+// and matchExpressions of workloads are the same requirements.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Equals,
+    NotEquals,
+    In,
+    NotIn,
+    Exists,
+    DoesNotExist,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Requirement {
+    pub key: String,
+    pub operator: Operator,
+    pub values: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Selector {
+    requirements: Vec<Requirement>,
+}
+
+fn valid_key(key: &str) -> bool {
+    !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '/'))
+}
+
+fn valid_value(value: &str) -> bool {
+    value.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+impl Requirement {
+    fn new(key: &str, operator: Operator, values: Vec<String>) -> Result<Self> {
+        if !valid_key(key) {
+            return Err(anyhow!("invalid label key {:?}", key));
+        }
+        if let Some(value) = values.iter().find(|value| !valid_value(value)) {
+            return Err(anyhow!("invalid label value {:?} of {}", value, key));
+        }
+        let expected = match operator {
+            Operator::Exists | Operator::DoesNotExist => values.is_empty(),
+            Operator::Equals | Operator::NotEquals => values.len() == 1,
+            Operator::In | Operator::NotIn => !values.is_empty(),
+        };
+        if !expected {
+            return Err(anyhow!("wrong number of values for {:?} of {}", operator, key));
+        }
+        Ok(Requirement { key: key.to_string(), operator, values })
+    }
+
+    fn parse(term: &str) -> Result<Self> {
+        if let Some(key) = term.strip_prefix('!') {
+            return Requirement::new(key.trim(), Operator::DoesNotExist, Vec::new());
+        }
+        if let Some((head, rest)) = term.split_once('(') {
+            let values = rest.strip_suffix(')').ok_or_else(|| anyhow!("missing ) in {}", term))?;
+            let values: Vec<String> = values.split(',').map(|value| value.trim().to_string()).collect();
+            let operator = match head.split_whitespace().collect::<Vec<_>>()[..] {
+                [key, "in"] => (key, Operator::In),
+                [key, "notin"] => (key, Operator::NotIn),
+                _ => return Err(anyhow!("expected <key> in (...) or <key> notin (...), got {}", term)),
+            };
+            return Requirement::new(operator.0, operator.1, values);
+        }
+        if let Some((key, value)) = term.split_once("!=") {
+            return Requirement::new(key.trim(), Operator::NotEquals, vec![value.trim().to_string()]);
+        }
+        if let Some((key, value)) = term.split_once("==").or_else(|| term.split_once('=')) {
+            return Requirement::new(key.trim(), Operator::Equals, vec![value.trim().to_string()]);
+        }
+        Requirement::new(term, Operator::Exists, Vec::new())
+    }
+
+    fn matches(&self, labels: &HashMap<String, String>) -> bool {
+        let value = labels.get(&self.key);
+        let listed = value.is_some_and(|value| self.values.contains(value));
+        match self.operator {
+            Operator::Equals | Operator::In => listed,
+            Operator::NotEquals | Operator::NotIn => !listed,
+            Operator::Exists => value.is_some(),
+            Operator::DoesNotExist => value.is_none(),
+        }
+    }
+}
+
+impl fmt::Display for Requirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.operator {
+            Operator::Equals => write!(f, "{}={}", self.key, self.values[0]),
+            Operator::NotEquals => write!(f, "{}!={}", self.key, self.values[0]),
+            Operator::In => write!(f, "{} in ({})", self.key, self.values.join(",")),
+            Operator::NotIn => write!(f, "{} notin ({})", self.key, self.values.join(",")),
+            Operator::Exists => write!(f, "{}", self.key),
+            Operator::DoesNotExist => write!(f, "!{}", self.key),
+        }
+    }
+}
+
+impl Selector {
+    pub fn parse(text: &str) -> Result<Self> {
+        // commas inside the parentheses of a set separate values, not requirements
+        let mut terms = Vec::new();
+        let (mut depth, mut start) = (0, 0);
+        for (i, c) in text.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                ',' if depth == 0 => {
+                    terms.push(&text[start..i]);
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        terms.push(&text[start..]);
+        let requirements = terms
+            .into_iter()
+            .map(str::trim)
+            .filter(|term| !term.is_empty())
+            .map(|term| Requirement::parse(term).map_err(|e| anyhow!("invalid selector {:?}: {}", text, e)))
+            .collect::<Result<_>>()?;
+        Ok(Selector { requirements })
+    }
+
+    // the selector of labels that all have to be there
+    pub fn from_labels<'a>(labels: impl IntoIterator<Item = (&'a String, &'a String)>) -> Self {
+        let mut requirements: Vec<Requirement> = labels
+            .into_iter()
+            .map(|(key, value)| Requirement { key: key.clone(), operator: Operator::Equals, values: vec![value.clone()] })
+            .collect();
+        requirements.sort_by(|a, b| a.key.cmp(&b.key));
+        Selector { requirements }
+    }
+
+    pub fn matches(&self, labels: &HashMap<String, String>) -> bool {
+        self.requirements.iter().all(|requirement| requirement.matches(labels))
+    }
+
+    // the requirements the labels don't meet
+    pub fn unmatched(&self, labels: &HashMap<String, String>) -> Vec<String> {
+        self.requirements.iter().filter(|requirement| !requirement.matches(labels)).map(|r| r.to_string()).collect()
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let requirements: Vec<String> = self.requirements.iter().map(|r| r.to_string()).collect();
+        write!(f, "{}", requirements.join(","))
+    }
+}
+
+// for clap
+pub fn parse_selector_arg(text: &str) -> Result<Selector, String> {
+    Selector::parse(text).map_err(|e| e.to_string())
+}
+
+// Field selectors, as written after --field-selector
+//
+//   status.phase=Running,spec.nodeName!=node-1
+//
+// compare fields of the objects rkl get lists, by their dotted path, with =
+// (or ==) and !=. A missing field is the empty string, so metadata.labels.x=
+// is also how to ask for objects without it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FieldSelector {
+    // path, whether it has to equal the value, value
+    requirements: Vec<(String, bool, String)>,
+}
+
+impl FieldSelector {
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut requirements = Vec::new();
+        for term in text.split(',').map(str::trim).filter(|term| !term.is_empty()) {
+            let (path, equals, value) = match term.split_once("!=") {
+                Some((path, value)) => (path, false, value),
+                None => {
+                    let (path, value) = term
+                        .split_once("==")
+                        .or_else(|| term.split_once('='))
+                        .ok_or_else(|| anyhow!("invalid field selector {:?}: expected <field>=<value> or <field>!=<value>", term))?;
+                    (path, true, value)
+                }
+            };
+            let path = path.trim();
+            if path.is_empty() || path.split('.').any(|field| field.is_empty()) {
+                return Err(anyhow!("invalid field selector {:?}: invalid field {:?}", term, path));
+            }
+            requirements.push((path.to_string(), equals, value.trim().to_string()));
+        }
+        Ok(FieldSelector { requirements })
+    }
+
+    pub fn matches(&self, object: &Value) -> bool {
+        self.requirements.iter().all(|(path, equals, value)| {
+            let field = path.split('.').try_fold(object, |value, field| value.get(field));
+            let field = match field {
+                Some(Value::String(text)) => text.clone(),
+                None | Some(Value::Null) => String::new(),
+                Some(other) => other.to_string(),
+            };
+            (field == *value) == *equals
+        })
+    }
+}
+
+// for clap
+pub fn parse_field_selector_arg(text: &str) -> Result<FieldSelector, String> {
+    FieldSelector::parse(text).map_err(|e| e.to_string())
+}
+
+// simulate Kubernetes LabelSelector
+#[derive(Debug, Default, Deserialize)]
+pub struct LabelSelector {
+    #[serde(rename = "matchLabels", default)]
+    pub match_labels: HashMap<String, String>,
+    #[serde(rename = "matchExpressions", default)]
+    pub match_expressions: Vec<LabelSelectorRequirement>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LabelSelectorRequirement {
+    pub key: String,
+    // In, NotIn, Exists or DoesNotExist
+    pub operator: String,
+    #[serde(default)]
+    pub values: Vec<String>,
+}
+
+impl LabelSelector {
+    pub fn selector(&self) -> Result<Selector> {
+        let mut selector = Selector::from_labels(&self.match_labels);
+        for expression in &self.match_expressions {
+            let operator = match expression.operator.as_str() {
+                "In" => Operator::In,
+                "NotIn" => Operator::NotIn,
+                "Exists" => Operator::Exists,
+                "DoesNotExist" => Operator::DoesNotExist,
+                other => return Err(anyhow!("unknown operator {} of matchExpressions", other)),
+            };
+            selector.requirements.push(Requirement::new(&expression.key, operator, expression.values.clone())?);
+        }
+        Ok(selector)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        let selector = Selector::parse("app=web, tier in (frontend, cache),!canary,env!=prod").unwrap();
+        assert_eq!(selector.to_string(), "app=web,tier in (frontend,cache),!canary,env!=prod");
+        assert!(selector.matches(&labels(&[("app", "web"), ("tier", "cache")])));
+        assert!(!selector.matches(&labels(&[("app", "web"), ("tier", "backend")])));
+        assert!(!selector.matches(&labels(&[("app", "web"), ("tier", "cache"), ("canary", "")])));
+        assert!(!selector.matches(&labels(&[("app", "web"), ("tier", "cache"), ("env", "prod")])));
+        assert_eq!(selector.unmatched(&labels(&[("tier", "cache")])), ["app=web"]);
+
+        let selector = Selector::parse("release notin (v1),app.kubernetes.io/name,version==2").unwrap();
+        assert!(selector.matches(&labels(&[("app.kubernetes.io/name", "db"), ("version", "2")])));
+        assert!(!selector.matches(&labels(&[("app.kubernetes.io/name", "db"), ("version", "2"), ("release", "v1")])));
+
+        assert!(Selector::parse("").unwrap().matches(&HashMap::new()));
+        assert!(Selector::parse("tier in (a,b").is_err());
+        assert!(Selector::parse("tier within (a)").is_err());
+        assert!(Selector::parse("app=we b").is_err());
+        assert!(Selector::parse("=web").is_err());
+    }
+
+    #[test]
+    fn test_field_selector() {
+        let pod = serde_json::json!({
+            "metadata": {"name": "web", "namespace": "default"},
+            "spec": {"nodeName": "node-1"},
+            "status": {"phase": "Running", "restartCount": 2},
+        });
+        assert!(FieldSelector::parse("status.phase=Running,spec.nodeName!=node-2").unwrap().matches(&pod));
+        assert!(FieldSelector::parse("status.restartCount==2").unwrap().matches(&pod));
+        assert!(FieldSelector::parse("status.podIP=").unwrap().matches(&pod));
+        assert!(!FieldSelector::parse("metadata.name!=web").unwrap().matches(&pod));
+        assert!(FieldSelector::parse("").unwrap().matches(&pod));
+        assert!(FieldSelector::parse("status.phase").is_err());
+        assert!(FieldSelector::parse("status..phase=Running").is_err());
+    }
+
+    #[test]
+    fn test_label_selector() {
+        let label_selector: LabelSelector = serde_yaml::from_str(
+            "matchLabels:\n  app: web\nmatchExpressions:\n  - key: tier\n    operator: NotIn\n    values: [batch]\n",
+        )
+        .unwrap();
+        let selector = label_selector.selector().unwrap();
+        assert_eq!(selector.to_string(), "app=web,tier notin (batch)");
+        assert!(selector.matches(&labels(&[("app", "web")])));
+
+        let invalid: LabelSelector = serde_yaml::from_str("matchExpressions:\n  - key: tier\n    operator: Exists\n    values: [a]\n").unwrap();
+        assert!(invalid.selector().is_err());
+    }
+}
@@ -0,0 +1,347 @@
+use std::fmt;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+use anyhow::{Result, anyhow};
+use crate::cli_commands::{self, PodInfo};
+use crate::cri::cri::{AttachRequest, ImageSpec, PortForwardRequest, PullImageRequest};
+use crate::image;
+use crate::rootpath;
+use crate::stream::{self, portforward, STREAM_STDIN, STREAM_STDOUT};
+use crate::task::cni;
+use crate::task::logs;
+use crate::task::task::{FailurePolicy, PodTask};
+use crate::values::Values;
+
+// `rkl smoke` validates a node end to end: it pulls a small image, runs a pod
+// with a web server and a shell, talks to both and deletes the pod again. The
+// web server is reached on the pod IP and through a port forward, its
+// readiness probe is run, a command is run in it, the downwardAPI volume it
+// mounts is read back with that and what it logged is read from its log.
+// hostPort publishing isn't implemented, so there is nothing to check there.
+
+const POD_NAME: &str = "rkl-smoke";
+const WEB_PORT: u16 = 8080;
+const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+const ATTACH_MARKER: &str = "rkl-smoke-ok";
+// the web server logs this before it serves
+const LOG_MARKER: &str = "rkl-smoke-log";
+const EXEC_MARKER: &str = "rkl-smoke-exec";
+const PODINFO_DIR: &str = "/etc/podinfo";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Pass,
+    Fail,
+    Skip,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Pass => write!(f, "PASS"),
+            Outcome::Fail => write!(f, "FAIL"),
+            Outcome::Skip => write!(f, "SKIP"),
+        }
+    }
+}
+
+struct Check {
+    name: &'static str,
+    outcome: Outcome,
+    detail: String,
+}
+
+#[derive(Default)]
+struct Report {
+    checks: Vec<Check>,
+}
+
+impl Report {
+    // run a check and record how it went, printing progress as it goes
+    fn run(&mut self, name: &'static str, check: impl FnOnce() -> Result<String>) -> bool {
+        let started = Instant::now();
+        let (outcome, detail) = match check() {
+            Ok(detail) => (Outcome::Pass, format!("{} ({:.1}s)", detail, started.elapsed().as_secs_f64())),
+            Err(e) => (Outcome::Fail, e.to_string()),
+        };
+        println!("{} {}", outcome, name);
+        self.checks.push(Check { name, outcome, detail });
+        outcome == Outcome::Pass
+    }
+
+    fn skip(&mut self, name: &'static str, reason: &str) {
+        self.checks.push(Check { name, outcome: Outcome::Skip, detail: reason.to_string() });
+    }
+
+    fn failures(&self) -> usize {
+        self.checks.iter().filter(|c| c.outcome == Outcome::Fail).count()
+    }
+
+    fn print(&self) {
+        println!("\n{:<14} {:<6} DETAIL", "CHECK", "RESULT");
+        for check in &self.checks {
+            println!("{:<14} {:<6} {}", check.name, check.outcome, check.detail);
+        }
+    }
+}
+
+fn pod_manifest(pause_bundle: &str, image: &str) -> String {
+    format!(
+        r#"apiVersion: v1
+kind: Pod
+metadata:
+  name: {pod}
+  labels:
+    bundle: {pause_bundle}
+spec:
+  containers:
+    - name: {pod}-web
+      image: {image}
+      args: ["sh", "-c", "echo {log_marker}; exec httpd -f -p {port} -h /"]
+      ports:
+        - containerPort: {port}
+      readinessProbe:
+        httpGet:
+          port: {port}
+      volumeMounts:
+        - name: podinfo
+          mountPath: {podinfo}
+    - name: {pod}-shell
+      image: {image}
+      args: ["sh"]
+      stdin: true
+  volumes:
+    - name: podinfo
+      downwardAPI:
+        items:
+          - path: name
+            fieldRef:
+              fieldPath: metadata.name
+"#,
+        pod = POD_NAME,
+        pause_bundle = pause_bundle,
+        image = image,
+        port = WEB_PORT,
+        log_marker = LOG_MARKER,
+        podinfo = PODINFO_DIR,
+    )
+}
+
+fn web_container() -> String {
+    format!("{}-web", POD_NAME)
+}
+
+// retry until the check passes or the timeout expires, returning the last error
+fn retry<T>(mut attempt: impl FnMut() -> Result<T>) -> Result<T> {
+    let deadline = Instant::now() + CHECK_TIMEOUT;
+    loop {
+        match attempt() {
+            Ok(value) => return Ok(value),
+            Err(e) if Instant::now() >= deadline => return Err(e),
+            Err(_) => thread::sleep(Duration::from_millis(200)),
+        }
+    }
+}
+
+fn check_pull(root_path: &Path, image: &str) -> Result<String> {
+    let request = PullImageRequest {
+        image: Some(ImageSpec {
+            image: image.to_string(),
+            user_specified_image: image.to_string(),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    Ok(image::pull_image(root_path, &request)?.image_ref)
+}
+
+fn check_port_forward(root_path: &Path) -> Result<String> {
+    let pod_info = PodInfo::load(root_path, POD_NAME)?;
+    let request = PortForwardRequest {
+        pod_sandbox_id: pod_info.pod_sandbox_id,
+        port: vec![WEB_PORT as i32],
+    };
+    let response = portforward::port_forward(root_path, &request)?;
+    let status_line = retry(|| {
+        let mut connection = portforward::dial(&response.url, WEB_PORT)?;
+        connection.set_read_timeout(Some(CHECK_TIMEOUT))?;
+        connection.write_all(b"GET / HTTP/1.0\r\n\r\n")?;
+        let mut reply = String::new();
+        connection.read_to_string(&mut reply)?;
+        reply
+            .lines()
+            .next()
+            .filter(|line| line.starts_with("HTTP/"))
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("no HTTP response from port {}", WEB_PORT))
+    })?;
+    Ok(format!("port {}: {}", WEB_PORT, status_line))
+}
+
+fn pod_ip(root_path: &Path) -> Result<IpAddr> {
+    let pod_info = PodInfo::load(root_path, POD_NAME)?;
+    let ip = cni::pod_ip(root_path, &pod_info.pod_sandbox_id).ok_or_else(|| anyhow!("pod {} has no IP", POD_NAME))?;
+    Ok(ip.parse()?)
+}
+
+// the containerPort answers on the pod IP
+fn check_pod_port(root_path: &Path) -> Result<String> {
+    let address = SocketAddr::new(pod_ip(root_path)?, WEB_PORT);
+    let status_line = retry(|| {
+        let mut connection = TcpStream::connect_timeout(&address, CHECK_TIMEOUT)?;
+        connection.set_read_timeout(Some(CHECK_TIMEOUT))?;
+        connection.write_all(b"GET / HTTP/1.0\r\n\r\n")?;
+        let mut reply = String::new();
+        connection.read_to_string(&mut reply)?;
+        reply
+            .lines()
+            .next()
+            .filter(|line| line.starts_with("HTTP/"))
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("no HTTP response from {}", address))
+    })?;
+    Ok(format!("{}: {}", address, status_line))
+}
+
+// the readiness probe of the web container, the way `rkl daemon` runs it
+fn check_probe(root_path: &Path, manifest: &str) -> Result<String> {
+    let task: PodTask = serde_yaml::from_str(manifest)?;
+    let probe = task
+        .spec
+        .containers
+        .iter()
+        .find_map(|container| container.readiness_probe.clone())
+        .ok_or_else(|| anyhow!("the smoke pod has no readiness probe"))?;
+    let ip = pod_ip(root_path)?;
+    retry(|| probe.run(ip))?;
+    Ok(format!("httpGet {}:{} ready", ip, WEB_PORT))
+}
+
+fn exec_output(command: &[&str]) -> Result<String> {
+    let command = command.iter().map(|arg| arg.to_string()).collect();
+    let (code, output) = cli_commands::exec_in_container(POD_NAME, &web_container(), command, CHECK_TIMEOUT)?;
+    let output = String::from_utf8_lossy(&output).trim().to_string();
+    if code != 0 {
+        return Err(anyhow!("exited with {}: {}", code, output));
+    }
+    Ok(output)
+}
+
+fn check_exec() -> Result<String> {
+    match exec_output(&["echo", EXEC_MARKER])? {
+        output if output == EXEC_MARKER => Ok(format!("echo in {}", web_container())),
+        output => Err(anyhow!("expected {}, got {:?}", EXEC_MARKER, output)),
+    }
+}
+
+// the downwardAPI volume holds the name of the pod
+fn check_volumes() -> Result<String> {
+    let path = format!("{}/name", PODINFO_DIR);
+    match exec_output(&["cat", &path])? {
+        name if name == POD_NAME => Ok(format!("downwardAPI {} is {}", path, name)),
+        name => Err(anyhow!("{} is {:?}, not {}", path, name, POD_NAME)),
+    }
+}
+
+fn check_logs() -> Result<String> {
+    let log = retry(|| {
+        let log = logs::read_container_log(POD_NAME, &web_container())?;
+        match String::from_utf8_lossy(&log).contains(LOG_MARKER) {
+            true => Ok(log),
+            false => Err(anyhow!("{} is not in the log of {}", LOG_MARKER, web_container())),
+        }
+    })?;
+    Ok(format!("{} bytes logged by {}", log.len(), web_container()))
+}
+
+fn check_attach(root_path: &Path) -> Result<String> {
+    let request = AttachRequest {
+        container_id: format!("{}-shell", POD_NAME),
+        stdin: true,
+        tty: false,
+        stdout: true,
+        stderr: true,
+    };
+    let response = stream::attach(root_path, &request)?;
+    let mut connection = stream::connect(&response.url, &request)?;
+    connection.set_read_timeout(Some(CHECK_TIMEOUT))?;
+    stream::write_frame(&mut connection, STREAM_STDIN, format!("echo {}\n", ATTACH_MARKER).as_bytes())?;
+
+    let mut output = String::new();
+    while !output.contains(ATTACH_MARKER) {
+        match stream::read_frame(&mut connection)? {
+            Some((STREAM_STDOUT, data)) => output.push_str(&String::from_utf8_lossy(&data)),
+            Some(_) => {}
+            None => return Err(anyhow!("attach stream closed before the shell answered")),
+        }
+    }
+    Ok("shell echoed over stdin/stdout".to_string())
+}
+
+pub fn run(pause_bundle: &str, image: &str, keep: bool) -> Result<()> {
+    let root_path = rootpath::determine(None)?;
+    if PodInfo::load(&root_path, POD_NAME).is_ok() {
+        println!("Removing Pod {} left by an earlier run", POD_NAME);
+        cli_commands::delete_pod(POD_NAME)?;
+    }
+    let manifest_dir = root_path.join("smoke");
+    fs::create_dir_all(&manifest_dir)?;
+    let manifest = manifest_dir.join("pod.yaml");
+    let pod = pod_manifest(pause_bundle, image);
+    fs::write(&manifest, &pod)?;
+
+    let mut report = Report::default();
+    let pulled = report.run("image pull", || check_pull(&root_path, image));
+    let running = pulled
+        && report.run("pod run", || {
+            cli_commands::run_pod(&manifest.display().to_string(), FailurePolicy::Rollback, &Values::default())?;
+            Ok(format!("pod {} with 2 containers", POD_NAME))
+        });
+    let checks = ["pod port", "port forward", "probes", "exec", "volumes", "logs", "attach"];
+    if running {
+        report.run("pod port", || check_pod_port(&root_path));
+        report.run("port forward", || check_port_forward(&root_path));
+        report.run("probes", || check_probe(&root_path, &pod));
+        report.run("exec", check_exec);
+        report.run("volumes", check_volumes);
+        report.run("logs", check_logs);
+        report.run("attach", || check_attach(&root_path));
+    } else {
+        for check in checks {
+            report.skip(check, "the pod didn't run");
+        }
+    }
+    if running && !keep {
+        report.run("cleanup", || {
+            cli_commands::delete_pod(POD_NAME)?;
+            Ok(format!("pod {} deleted", POD_NAME))
+        });
+    }
+    let _ = fs::remove_dir_all(&manifest_dir);
+
+    report.print();
+    match report.failures() {
+        0 => Ok(()),
+        failures => Err(anyhow!("smoke test failed: {} of {} checks failed", failures, report.checks.len())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pod_manifest_parses() {
+        let task: PodTask = serde_yaml::from_str(&pod_manifest("/var/lib/pause", "busybox")).unwrap();
+        assert_eq!(task.metadata.labels["bundle"], "/var/lib/pause");
+        assert_eq!(task.spec.containers.len(), 2);
+        assert!(task.spec.containers[1].stdin);
+        assert!(task.spec.containers[0].readiness_probe.as_ref().unwrap().validate().is_ok());
+        assert_eq!(task.spec.containers[0].volume_mounts[0].mount_path, PODINFO_DIR);
+        assert!(task.spec.volumes[0].downward_api.is_some());
+    }
+}
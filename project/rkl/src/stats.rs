@@ -0,0 +1,335 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use anyhow::{Result, anyhow};
+use libcgroups::common::CgroupManager;
+use libcontainer::oci_spec::runtime::Spec;
+use crate::commands::{create_cgroup_manager, load_container};
+use crate::cri::cri::{
+    self, ContainerAttributes, ContainerStatsRequest, CpuUsage, FilesystemUsage, MemoryUsage, PodSandboxStatsRequest,
+    UInt64Value,
+};
+use crate::runtime::RuntimeBackend;
+
+// Resource usage of a container, read from its cgroup through libcgroups so
+// that cgroup v1, v2 and systemd cgroups all work. The limits are the ones the
+// container was created with, taken from its OCI spec: what the kernel
+// enforces, not what the manifest asked for.
+//
+// `rkl top` and the scheduler see the usage of the pods through the CRI stats
+// instead: ContainerStats and PodSandboxStats of the runtime, or the same made
+// by rkl for the built-in runtime, whose writable layer is the rootfs of the
+// container. The cpu is a rate between two samples of a UsageSampler unless
+// the runtime tells the rate itself.
+
+// a sample of the usage of one container
+#[derive(Debug, Clone)]
+pub struct ContainerStats {
+    pub taken: Instant,
+    // cpu time used since the container started, in nanoseconds
+    pub cpu_usage: u64,
+    // cfs periods that elapsed and those in which the container was throttled
+    pub periods: u64,
+    pub throttled_periods: u64,
+    pub memory_usage: u64,
+    // millicores and bytes, None when unlimited
+    pub cpu_limit: Option<u64>,
+    pub memory_limit: Option<u64>,
+}
+
+pub fn collect(root_path: &Path, container_name: &str) -> Result<ContainerStats> {
+    let container = load_container(root_path, container_name)?;
+    let (cpu_limit, memory_limit) = spec_limits(&Spec::load(container.bundle().join("config.json"))?);
+    let stats = create_cgroup_manager(root_path, container_name)?
+        .stats()
+        .map_err(|e| anyhow!("Failed to read the cgroup of container {}: {}", container_name, e))?;
+    Ok(ContainerStats {
+        taken: Instant::now(),
+        cpu_usage: stats.cpu.usage.usage_total,
+        periods: stats.cpu.throttling.periods,
+        throttled_periods: stats.cpu.throttling.throttled_periods,
+        memory_usage: stats.memory.memory.usage,
+        cpu_limit,
+        memory_limit,
+    })
+}
+
+fn spec_limits(spec: &Spec) -> (Option<u64>, Option<u64>) {
+    let Some(resources) = spec.linux().as_ref().and_then(|linux| linux.resources().as_ref()) else {
+        return (None, None);
+    };
+    // a quota of -1 and a limit of -1 are unlimited
+    let cpu = resources.cpu().as_ref().and_then(|cpu| {
+        let quota = u64::try_from(cpu.quota()?).ok()?;
+        let period = cpu.period().unwrap_or(100_000);
+        (period > 0).then(|| quota * 1000 / period)
+    });
+    let memory = resources.memory().as_ref().and_then(|memory| u64::try_from(memory.limit()?).ok());
+    (cpu, memory)
+}
+
+impl ContainerStats {
+    // average millicores used between an earlier sample and this one
+    pub fn cpu_millis(&self, earlier: &ContainerStats) -> u64 {
+        let elapsed = self.taken.duration_since(earlier.taken).as_nanos() as u64;
+        if elapsed == 0 {
+            return 0;
+        }
+        self.cpu_usage.saturating_sub(earlier.cpu_usage) * 1000 / elapsed
+    }
+
+    // percentage of the cfs periods in which the container was throttled
+    // between an earlier sample and this one
+    pub fn throttled_percent(&self, earlier: &ContainerStats) -> u64 {
+        let periods = self.periods.saturating_sub(earlier.periods);
+        if periods == 0 {
+            return 0;
+        }
+        self.throttled_periods.saturating_sub(earlier.throttled_periods) * 100 / periods
+    }
+}
+
+pub fn now_nanos() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as i64).unwrap_or_default()
+}
+
+fn value(value: u64) -> Option<UInt64Value> {
+    Some(UInt64Value { value })
+}
+
+// bytes and inodes used by the files under a directory, not following links
+pub fn disk_usage(dir: &Path) -> (u64, u64) {
+    let (mut bytes, mut inodes) = (0, 0);
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(metadata) = entry.path().symlink_metadata() else {
+                continue;
+            };
+            bytes += metadata.len();
+            inodes += 1;
+            if metadata.is_dir() {
+                dirs.push(entry.path());
+            }
+        }
+    }
+    (bytes, inodes)
+}
+
+// the CRI stats of a container of the built-in runtime
+pub fn cri_stats(root_path: &Path, container_name: &str) -> Result<cri::ContainerStats> {
+    let container = load_container(root_path, container_name)?;
+    let stats = create_cgroup_manager(root_path, container_name)?
+        .stats()
+        .map_err(|e| anyhow!("Failed to read the cgroup of container {}: {}", container_name, e))?;
+    let timestamp = now_nanos();
+    // like the kubelet, the working set leaves out the page cache that can be reclaimed
+    let inactive_file = ["inactive_file", "total_inactive_file"]
+        .iter()
+        .find_map(|key| stats.memory.stats.get(*key).copied())
+        .unwrap_or_default();
+    let (used_bytes, inodes_used) = disk_usage(&container.bundle().join("rootfs"));
+    Ok(cri::ContainerStats {
+        attributes: Some(ContainerAttributes { id: container_name.to_string(), ..Default::default() }),
+        cpu: Some(CpuUsage { timestamp, usage_core_nano_seconds: value(stats.cpu.usage.usage_total), usage_nano_cores: None }),
+        memory: Some(MemoryUsage {
+            timestamp,
+            working_set_bytes: value(stats.memory.memory.usage.saturating_sub(inactive_file)),
+            usage_bytes: value(stats.memory.memory.usage),
+            ..Default::default()
+        }),
+        writable_layer: Some(FilesystemUsage {
+            timestamp,
+            used_bytes: value(used_bytes),
+            inodes_used: value(inodes_used),
+            ..Default::default()
+        }),
+        swap: None,
+    })
+}
+
+// the CRI stats of the containers of a sandbox, from the runtime with a backend
+pub fn pod_stats(
+    root_path: &Path,
+    backend: Option<&dyn RuntimeBackend>,
+    sandbox_id: &str,
+    container_names: &[String],
+) -> Vec<cri::ContainerStats> {
+    let Some(backend) = backend else {
+        return container_names.iter().filter_map(|name| cri_stats(root_path, name).ok()).collect();
+    };
+    let request = PodSandboxStatsRequest { pod_sandbox_id: sandbox_id.to_string() };
+    let containers = backend
+        .pod_sandbox_stats(request)
+        .ok()
+        .and_then(|response| response.stats)
+        .and_then(|stats| stats.linux)
+        .map(|linux| linux.containers)
+        .unwrap_or_default();
+    if !containers.is_empty() {
+        return containers;
+    }
+    // not every runtime has the stats of the sandboxes
+    container_names
+        .iter()
+        .filter_map(|name| backend.container_stats(ContainerStatsRequest { container_id: name.clone() }).ok())
+        .filter_map(|response| response.stats)
+        .collect()
+}
+
+// what a container uses right now
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub cpu_millis: u64,
+    // the working set
+    pub memory_bytes: u64,
+    pub fs_bytes: u64,
+}
+
+impl std::ops::AddAssign for Usage {
+    fn add_assign(&mut self, other: Usage) {
+        self.cpu_millis += other.cpu_millis;
+        self.memory_bytes += other.memory_bytes;
+        self.fs_bytes += other.fs_bytes;
+    }
+}
+
+// the usage a sample shows, the cpu rate against an earlier sample
+pub fn usage(earlier: Option<&cri::ContainerStats>, later: &cri::ContainerStats) -> Usage {
+    let cpu_millis = match later.cpu.as_ref() {
+        Some(CpuUsage { usage_nano_cores: Some(rate), .. }) => rate.value / 1_000_000,
+        Some(CpuUsage { timestamp, usage_core_nano_seconds: Some(total), .. }) => {
+            match earlier.and_then(|earlier| earlier.cpu.as_ref()) {
+                Some(CpuUsage { timestamp: earlier_timestamp, usage_core_nano_seconds: Some(earlier_total), .. })
+                    if timestamp > earlier_timestamp =>
+                {
+                    let elapsed = (timestamp - earlier_timestamp) as u128;
+                    (total.value.saturating_sub(earlier_total.value) as u128 * 1000 / elapsed) as u64
+                }
+                _ => 0,
+            }
+        }
+        _ => 0,
+    };
+    let memory = later.memory.as_ref();
+    let memory_bytes = memory
+        .and_then(|memory| memory.working_set_bytes.as_ref().or(memory.usage_bytes.as_ref()))
+        .map_or(0, |bytes| bytes.value);
+    let fs_bytes = later.writable_layer.as_ref().and_then(|fs| fs.used_bytes.as_ref()).map_or(0, |bytes| bytes.value);
+    Usage { cpu_millis, memory_bytes, fs_bytes }
+}
+
+// keeps the last sample of every container for the cpu rates of the next one
+#[derive(Default)]
+pub struct UsageSampler {
+    earlier: HashMap<String, cri::ContainerStats>,
+}
+
+impl UsageSampler {
+    // the usage of every container of the sandbox that has stats, by name
+    pub fn sample(
+        &mut self,
+        root_path: &Path,
+        backend: Option<&dyn RuntimeBackend>,
+        sandbox_id: &str,
+        container_names: &[String],
+    ) -> Vec<(String, Usage)> {
+        let mut usages = Vec::new();
+        for stats in pod_stats(root_path, backend, sandbox_id, container_names) {
+            let attributes = stats.attributes.as_ref();
+            // CRI runtimes name the containers in the metadata, their ids are their own
+            let name = attributes
+                .and_then(|attributes| attributes.metadata.as_ref().map(|metadata| metadata.name.clone()))
+                .or_else(|| attributes.map(|attributes| attributes.id.clone()))
+                .unwrap_or_default();
+            let key = format!("{}/{}", sandbox_id, name);
+            usages.push((name, usage(self.earlier.get(&key), &stats)));
+            self.earlier.insert(key, stats);
+        }
+        usages
+    }
+}
+
+// a text bar of used against limit, full past the limit
+pub fn bar(used: u64, limit: u64, width: usize) -> String {
+    let filled = if limit == 0 {
+        width
+    } else {
+        ((used as u128 * width as u128).div_ceil(limit as u128) as usize).min(width)
+    };
+    let percent = if limit == 0 { 100 } else { used as u128 * 100 / limit as u128 };
+    format!("[{}{}] {:>3}%", "#".repeat(filled), "-".repeat(width - filled), percent)
+}
+
+// bytes in the binary units of memory quantities
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["Ki", "Mi", "Gi", "Ti", "Pi"];
+    if bytes < 1024 {
+        return bytes.to_string();
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1}{}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use libcontainer::oci_spec::runtime::{LinuxBuilder, LinuxCpuBuilder, LinuxMemoryBuilder, LinuxResourcesBuilder};
+
+    #[test]
+    fn test_usage() {
+        let earlier = ContainerStats {
+            taken: Instant::now(),
+            cpu_usage: 1_000_000_000,
+            periods: 100,
+            throttled_periods: 10,
+            memory_usage: 64 << 20,
+            cpu_limit: Some(500),
+            memory_limit: Some(128 << 20),
+        };
+        let later = ContainerStats {
+            taken: earlier.taken + Duration::from_secs(2),
+            cpu_usage: 1_500_000_000,
+            periods: 120,
+            throttled_periods: 15,
+            ..earlier.clone()
+        };
+        assert_eq!(later.cpu_millis(&earlier), 250);
+        assert_eq!(later.throttled_percent(&earlier), 25);
+        assert_eq!(bar(64 << 20, 128 << 20, 10), "[#####-----]  50%");
+        assert_eq!(bar(300, 200, 4), "[####] 150%");
+        assert_eq!(format_bytes(64 << 20), "64.0Mi");
+        assert_eq!(format_bytes(512), "512");
+
+        let resources = LinuxResourcesBuilder::default()
+            .cpu(LinuxCpuBuilder::default().quota(50_000i64).period(100_000u64).build().unwrap())
+            .memory(LinuxMemoryBuilder::default().limit(128i64 << 20).build().unwrap())
+            .build()
+            .unwrap();
+        let mut spec = Spec::default();
+        spec.set_linux(Some(LinuxBuilder::default().resources(resources).build().unwrap()));
+        assert_eq!(spec_limits(&spec), (Some(500), Some(128 << 20)));
+        assert_eq!(spec_limits(&Spec::default()), (None, None));
+
+        // cpu as a rate between two CRI samples, half a core over 2s here
+        let sample = |timestamp: i64, total: u64| cri::ContainerStats {
+            cpu: Some(CpuUsage { timestamp, usage_core_nano_seconds: value(total), ..Default::default() }),
+            memory: Some(MemoryUsage { working_set_bytes: value(32 << 20), usage_bytes: value(64 << 20), ..Default::default() }),
+            writable_layer: Some(FilesystemUsage { used_bytes: value(4096), ..Default::default() }),
+            ..Default::default()
+        };
+        let (earlier, later) = (sample(1_000_000_000, 1_000_000_000), sample(3_000_000_000, 2_000_000_000));
+        assert_eq!(usage(None, &later), Usage { cpu_millis: 0, memory_bytes: 32 << 20, fs_bytes: 4096 });
+        assert_eq!(usage(Some(&earlier), &later).cpu_millis, 500);
+    }
+}
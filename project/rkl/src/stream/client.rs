@@ -0,0 +1,101 @@
+use std::io::{self, IsTerminal, Read, Write};
+use std::net::Shutdown;
+use std::os::fd::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::thread;
+use anyhow::Result;
+use nix::pty::Winsize;
+use nix::sys::termios::{self, SetArg, Termios};
+use crate::stream::{self, STREAM_RESIZE, STREAM_STDERR, STREAM_STDIN, STREAM_STDOUT};
+
+// ctrl-p ctrl-q detaches from a tty without stopping the container, like docker
+const DETACH_KEYS: [u8; 2] = [0x10, 0x11];
+
+nix::ioctl_read_bad!(get_winsize, nix::libc::TIOCGWINSZ, Winsize);
+
+// puts the local terminal in raw mode and restores it when dropped
+struct RawTerminal {
+    original: Termios,
+}
+
+impl RawTerminal {
+    fn new() -> Result<Self> {
+        let original = termios::tcgetattr(io::stdin())?;
+        let mut raw = original.clone();
+        termios::cfmakeraw(&mut raw);
+        termios::tcsetattr(io::stdin(), SetArg::TCSANOW, &raw)?;
+        Ok(RawTerminal { original })
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let _ = termios::tcsetattr(io::stdin(), SetArg::TCSANOW, &self.original);
+    }
+}
+
+fn terminal_size() -> Option<Winsize> {
+    let mut size = Winsize { ws_row: 0, ws_col: 0, ws_xpixel: 0, ws_ypixel: 0 };
+    // SAFETY: stdout is a valid descriptor and size outlives the call
+    unsafe { get_winsize(io::stdout().as_raw_fd(), &mut size) }.ok()?;
+    Some(size)
+}
+
+// relay the local stdin, stdout and stderr over an attach connection
+// until the container's output ends or the user detaches
+pub fn run(mut connection: UnixStream, attach_stdin: bool, tty: bool) -> Result<()> {
+    let _raw = if tty && io::stdin().is_terminal() { Some(RawTerminal::new()?) } else { None };
+    if tty && let Some(size) = terminal_size() {
+        let mut payload = size.ws_row.to_be_bytes().to_vec();
+        payload.extend_from_slice(&size.ws_col.to_be_bytes());
+        stream::write_frame(&mut connection, STREAM_RESIZE, &payload)?;
+    }
+
+    if attach_stdin {
+        let mut writer = connection.try_clone()?;
+        thread::spawn(move || {
+            let mut buf = [0u8; 4096];
+            let mut last = 0u8;
+            loop {
+                let n = match io::stdin().read(&mut buf) {
+                    Ok(0) | Err(_) => {
+                        // end of input closes the container's stdin
+                        let _ = stream::write_frame(&mut writer, STREAM_STDIN, &[]);
+                        return;
+                    }
+                    Ok(n) => n,
+                };
+                let data = &buf[..n];
+                if tty {
+                    let detach = data.windows(2).any(|w| w == DETACH_KEYS)
+                        || (last == DETACH_KEYS[0] && data[0] == DETACH_KEYS[1]);
+                    if detach {
+                        let _ = writer.shutdown(Shutdown::Both);
+                        return;
+                    }
+                    last = data[n - 1];
+                }
+                if stream::write_frame(&mut writer, STREAM_STDIN, data).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+
+    let mut stdout = io::stdout();
+    let mut stderr = io::stderr();
+    while let Ok(Some((stream_id, data))) = stream::read_frame(&mut connection) {
+        match stream_id {
+            STREAM_STDOUT => {
+                stdout.write_all(&data)?;
+                stdout.flush()?;
+            }
+            STREAM_STDERR => {
+                stderr.write_all(&data)?;
+                stderr.flush()?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,117 @@
+use std::io::{self, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use anyhow::{Result, anyhow};
+use libcontainer::container::ContainerStatus;
+use libcontainer::oci_spec::runtime::Spec;
+use crate::commands::load_container;
+use crate::cri::cri::{AttachRequest, AttachResponse};
+
+pub mod shim;
+pub mod client;
+pub mod portforward;
+
+// Streaming endpoint of the runtime. A container created with stdin or tty gets an
+// io shim (`rkl shim`), a small process holding the container's pty or pipes that
+// serves them on <root>/<container id>/attach.sock, so the socket goes away with the
+// container. Both sides exchange frames: a stream id byte, a big endian u32 length
+// and the payload. A client starts with an options frame telling which streams it wants.
+
+pub const STREAM_STDIN: u8 = 0;
+pub const STREAM_STDOUT: u8 = 1;
+pub const STREAM_STDERR: u8 = 2;
+// payload: rows and columns as big endian u16
+pub const STREAM_RESIZE: u8 = 3;
+// payload: one byte each for stdin, stdout and stderr
+pub const STREAM_OPTIONS: u8 = 4;
+
+// frames bigger than this are a protocol error
+const MAX_FRAME: usize = 1 << 20;
+
+pub fn write_frame(writer: &mut impl Write, stream: u8, data: &[u8]) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(5 + data.len());
+    frame.push(stream);
+    frame.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    frame.extend_from_slice(data);
+    writer.write_all(&frame)
+}
+
+// read the next frame, None once the peer closed the connection
+pub fn read_frame(reader: &mut impl Read) -> io::Result<Option<(u8, Vec<u8>)>> {
+    let mut header = [0u8; 5];
+    match reader.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    if len > MAX_FRAME {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("frame of {} bytes is too big", len)));
+    }
+    let mut data = vec![0u8; len];
+    reader.read_exact(&mut data)?;
+    Ok(Some((header[0], data)))
+}
+
+pub fn attach_socket_path(root_path: &Path, container_id: &str) -> PathBuf {
+    root_path.join(container_id).join("attach.sock")
+}
+
+// serve an AttachRequest: check the container can be attached to as asked
+// and return the url of its streaming endpoint
+pub fn attach(root_path: &Path, request: &AttachRequest) -> Result<AttachResponse> {
+    let container_id = &request.container_id;
+    if !request.stdin && !request.stdout && !request.stderr {
+        return Err(anyhow!("one of stdin, stdout or stderr must be attached"));
+    }
+    if request.tty && request.stderr {
+        return Err(anyhow!("stderr can't be attached with tty, it is merged into stdout"));
+    }
+
+    let container = load_container(root_path, container_id)?;
+    if container.status() != ContainerStatus::Running {
+        return Err(anyhow!("container {} is not running ({})", container_id, container.status()));
+    }
+    let spec = Spec::load(container.bundle().join("config.json"))?;
+    let terminal = spec.process().as_ref().and_then(|p| p.terminal()).unwrap_or(false);
+    if request.tty && !terminal {
+        return Err(anyhow!("container {} was created without tty", container_id));
+    }
+    if !request.tty && terminal {
+        return Err(anyhow!("container {} was created with tty, attach with tty", container_id));
+    }
+
+    let socket = attach_socket_path(root_path, container_id);
+    if !socket.exists() {
+        return Err(anyhow!("container {} was created without stdin or tty, it can't be attached", container_id));
+    }
+    Ok(AttachResponse {
+        url: format!("unix://{}", socket.display()),
+    })
+}
+
+// connect to a streaming url and ask for the streams of the request
+pub fn connect(url: &str, request: &AttachRequest) -> Result<UnixStream> {
+    let path = url.strip_prefix("unix://").ok_or_else(|| anyhow!("unsupported streaming url {}", url))?;
+    let mut stream = UnixStream::connect(path).map_err(|e| anyhow!("Failed to connect to {}: {}", url, e))?;
+    let options = [request.stdin as u8, request.stdout as u8, request.stderr as u8];
+    write_frame(&mut stream, STREAM_OPTIONS, &options)?;
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_roundtrip() {
+        let mut buf = Vec::new();
+        write_frame(&mut buf, STREAM_STDOUT, b"hello").unwrap();
+        write_frame(&mut buf, STREAM_STDIN, b"").unwrap();
+
+        let mut reader = io::Cursor::new(buf);
+        assert_eq!(read_frame(&mut reader).unwrap(), Some((STREAM_STDOUT, b"hello".to_vec())));
+        assert_eq!(read_frame(&mut reader).unwrap(), Some((STREAM_STDIN, Vec::new())));
+        assert_eq!(read_frame(&mut reader).unwrap(), None);
+    }
+}
@@ -0,0 +1,109 @@
+use std::fs::File;
+use std::io;
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::thread;
+use anyhow::{Result, anyhow};
+use libcontainer::container::ContainerStatus;
+use nix::sched::{self, CloneFlags};
+use crate::commands::load_container;
+use crate::cri::cri::{PortForwardRequest, PortForwardResponse};
+use tracing::warn;
+
+// Port forwarding doesn't need a shim: the streaming url names the network
+// namespace of the pause container and connections are dialed from inside it.
+
+// serve a PortForwardRequest with the url of the sandbox network namespace
+pub fn port_forward(root_path: &Path, request: &PortForwardRequest) -> Result<PortForwardResponse> {
+    if let Some(port) = request.port.iter().find(|p| !(1..=65535).contains(*p)) {
+        return Err(anyhow!("invalid port {}", port));
+    }
+    let sandbox = load_container(root_path, &request.pod_sandbox_id)?;
+    if sandbox.status() != ContainerStatus::Running {
+        return Err(anyhow!("PodSandbox {} is not running ({})", request.pod_sandbox_id, sandbox.status()));
+    }
+    let pid = sandbox.pid().ok_or_else(|| anyhow!("PID not found for PodSandbox {}", request.pod_sandbox_id))?;
+    Ok(PortForwardResponse {
+        url: format!("netns:///proc/{}/ns/net", pid),
+    })
+}
+
+// connect to a port on the loopback of the network namespace of the url
+pub fn dial(url: &str, port: u16) -> Result<TcpStream> {
+    let netns = url.strip_prefix("netns://").ok_or_else(|| anyhow!("unsupported streaming url {}", url))?;
+    let netns = File::open(netns).map_err(|e| anyhow!("Failed to open network namespace {}: {}", netns, e))?;
+    // the network namespace is per thread, enter it on a thread of its own
+    thread::spawn(move || -> Result<TcpStream> {
+        sched::setns(&netns, CloneFlags::CLONE_NEWNET)?;
+        let addrs = [SocketAddr::from(([127, 0, 0, 1], port)), SocketAddr::from(([0, 0, 0, 0, 0, 0, 0, 1], port))];
+        Ok(TcpStream::connect(&addrs[..])?)
+    })
+    .join()
+    .map_err(|_| anyhow!("port forward dialer panicked"))?
+}
+
+// "8080:80" forwards local port 8080 to pod port 80, "80" uses the same port
+// on both sides and ":80" lets the system choose the local port
+pub fn parse_port_spec(spec: &str) -> Result<(u16, u16)> {
+    let parse = |port: &str| -> Result<u16> {
+        port.parse().map_err(|_| anyhow!("invalid port {} in {}", port, spec))
+    };
+    let (local, remote) = match spec.split_once(':') {
+        Some(("", remote)) => (0, parse(remote)?),
+        Some((local, remote)) => (parse(local)?, parse(remote)?),
+        None => (parse(spec)?, parse(spec)?),
+    };
+    if remote == 0 {
+        return Err(anyhow!("invalid pod port in {}", spec));
+    }
+    Ok((local, remote))
+}
+
+// accept local connections and relay each one to the pod port
+pub fn forward(listener: TcpListener, url: String, remote_port: u16) {
+    for local in listener.incoming() {
+        let Ok(local) = local else {
+            continue;
+        };
+        let url = url.clone();
+        thread::spawn(move || match dial(&url, remote_port) {
+            Ok(remote) => {
+                if let Err(e) = relay(local, remote) {
+                    warn!("Port forward to {} ended: {}", remote_port, e);
+                }
+            }
+            Err(e) => {
+                warn!("Failed to forward to port {}: {}", remote_port, e);
+                let _ = local.shutdown(Shutdown::Both);
+            }
+        });
+    }
+}
+
+pub fn relay(local: TcpStream, remote: TcpStream) -> io::Result<()> {
+    let (mut local_read, mut remote_write) = (local.try_clone()?, remote.try_clone()?);
+    let upstream = thread::spawn(move || {
+        let _ = io::copy(&mut local_read, &mut remote_write);
+        let _ = remote_write.shutdown(Shutdown::Write);
+    });
+    let (mut remote_read, mut local_write) = (remote, local);
+    io::copy(&mut remote_read, &mut local_write)?;
+    let _ = local_write.shutdown(Shutdown::Write);
+    let _ = upstream.join();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_port_spec() {
+        assert_eq!(parse_port_spec("8080:80").unwrap(), (8080, 80));
+        assert_eq!(parse_port_spec("80").unwrap(), (80, 80));
+        assert_eq!(parse_port_spec(":80").unwrap(), (0, 80));
+        assert!(parse_port_spec("8080:").is_err());
+        assert!(parse_port_spec("8080:0").is_err());
+        assert!(parse_port_spec("http").is_err());
+    }
+}
@@ -0,0 +1,305 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, IoSlice, IoSliceMut, Read, Write};
+use std::os::fd::{AsFd, AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use anyhow::{Result, anyhow};
+use nix::pty::Winsize;
+use nix::sys::socket::{self, ControlMessage, ControlMessageOwned, MsgFlags, UnixAddr};
+use crate::commands::create::ContainerStdio;
+use crate::stream::{self, STREAM_OPTIONS, STREAM_RESIZE, STREAM_STDERR, STREAM_STDIN, STREAM_STDOUT};
+
+// how long to wait for the runtime to send the pty and for the shim to be ready
+const SETUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+nix::ioctl_write_ptr_bad!(set_winsize, nix::libc::TIOCSWINSZ, Winsize);
+
+// io of a container created with stdin or tty. It is set up before the container is
+// created: a console socket receiving the pty master for a tty, pipes otherwise.
+// Once the container exists its ends are handed over to the shim.
+pub struct ContainerIo {
+    stdin: bool,
+    stdin_once: bool,
+    tty: bool,
+    console_socket: Option<(PathBuf, UnixListener)>,
+    container_stdio: Option<ContainerStdio>,
+    // stdin writer, stdout and stderr readers
+    shim_fds: Vec<OwnedFd>,
+}
+
+impl ContainerIo {
+    pub fn new(work_dir: &Path, container_id: &str, stdin: bool, stdin_once: bool, tty: bool) -> Result<Self> {
+        let mut io = ContainerIo {
+            stdin,
+            stdin_once,
+            tty,
+            console_socket: None,
+            container_stdio: None,
+            shim_fds: Vec::new(),
+        };
+        if tty {
+            fs::create_dir_all(work_dir)?;
+            let path = work_dir.join(format!("{}.console.sock", container_id));
+            if path.exists() {
+                fs::remove_file(&path)?;
+            }
+            let listener = UnixListener::bind(&path)
+                .map_err(|e| anyhow!("Failed to create console socket {}: {}", path.display(), e))?;
+            io.console_socket = Some((path, listener));
+        } else {
+            let (stdin_r, stdin_w) = nix::unistd::pipe()?;
+            let (stdout_r, stdout_w) = nix::unistd::pipe()?;
+            let (stderr_r, stderr_w) = nix::unistd::pipe()?;
+            io.container_stdio = Some(ContainerStdio {
+                stdin: Some(stdin_r),
+                stdout: Some(stdout_w),
+                stderr: Some(stderr_w),
+            });
+            io.shim_fds = vec![stdin_w, stdout_r, stderr_r];
+        }
+        Ok(io)
+    }
+
+    pub fn console_socket(&self) -> Option<PathBuf> {
+        self.console_socket.as_ref().map(|(path, _)| path.clone())
+    }
+
+    pub fn take_container_stdio(&mut self) -> ContainerStdio {
+        self.container_stdio.take().unwrap_or_default()
+    }
+
+    // once the container is created, start the shim serving its attach socket
+    pub fn start_shim(mut self, attach_socket: &Path) -> Result<()> {
+        if let Some((path, listener)) = self.console_socket.take() {
+            let master = receive_pty_master(&listener);
+            let _ = fs::remove_file(&path);
+            self.shim_fds = vec![master?];
+        }
+
+        let (control, shim_end) = UnixStream::pair()?;
+        let mut command = Command::new(env::current_exe()?);
+        command.arg("shim").arg("--socket").arg(attach_socket);
+        if self.stdin {
+            command.arg("--stdin");
+        }
+        if self.stdin_once {
+            command.arg("--stdin-once");
+        }
+        if self.tty {
+            command.arg("--tty");
+        }
+        // its own process group keeps the shim alive when rkl is interrupted
+        command
+            .stdin(Stdio::from(OwnedFd::from(shim_end)))
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .process_group(0);
+        command.spawn().map_err(|e| anyhow!("Failed to start io shim: {}", e))?;
+        drop(command);
+
+        let fds: Vec<RawFd> = self.shim_fds.iter().map(|fd| fd.as_raw_fd()).collect();
+        socket::sendmsg::<UnixAddr>(
+            control.as_raw_fd(),
+            &[IoSlice::new(b"io")],
+            &[ControlMessage::ScmRights(&fds)],
+            MsgFlags::empty(),
+            None,
+        )?;
+        self.shim_fds.clear();
+
+        let mut control = control;
+        control.set_read_timeout(Some(SETUP_TIMEOUT))?;
+        let mut ready = [0u8; 1];
+        control
+            .read_exact(&mut ready)
+            .map_err(|e| anyhow!("io shim didn't come up: {}", e))?;
+        Ok(())
+    }
+}
+
+fn receive_pty_master(listener: &UnixListener) -> Result<OwnedFd> {
+    listener.set_nonblocking(true)?;
+    let deadline = Instant::now() + SETUP_TIMEOUT;
+    let stream = loop {
+        match listener.accept() {
+            Ok((stream, _)) => break stream,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock && Instant::now() < deadline => {
+                thread::sleep(Duration::from_millis(10));
+            }
+            Err(e) => return Err(anyhow!("Failed to receive the container's pty: {}", e)),
+        }
+    };
+    stream.set_nonblocking(false)?;
+    receive_fds(&stream)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("runtime sent no pty master"))
+}
+
+fn receive_fds(stream: &UnixStream) -> Result<Vec<OwnedFd>> {
+    let mut buf = [0u8; 64];
+    let mut iov = [IoSliceMut::new(&mut buf)];
+    let mut cmsg = nix::cmsg_space!([RawFd; 3]);
+    let msg = socket::recvmsg::<UnixAddr>(stream.as_raw_fd(), &mut iov, Some(&mut cmsg), MsgFlags::empty())?;
+    let mut fds = Vec::new();
+    for cmsg in msg.cmsgs() {
+        if let ControlMessageOwned::ScmRights(received) = cmsg {
+            // SAFETY: the descriptors were just received and nothing else owns them
+            fds.extend(received.into_iter().map(|fd| unsafe { OwnedFd::from_raw_fd(fd) }));
+        }
+    }
+    Ok(fds)
+}
+
+struct Client {
+    stream: UnixStream,
+    stdout: bool,
+    stderr: bool,
+}
+
+type Clients = Arc<Mutex<Vec<Client>>>;
+
+// the `rkl shim` process: receives the container's io on its stdin and
+// serves it on the attach socket until the container's output is closed
+pub fn run(attach_socket: &Path, stdin: bool, stdin_once: bool, tty: bool) -> Result<()> {
+    let control = UnixStream::from(io::stdin().as_fd().try_clone_to_owned()?);
+    let mut fds = receive_fds(&control)?.into_iter().map(File::from);
+
+    let mut pty = None;
+    let (input, outputs) = if tty {
+        let master = fds.next().ok_or_else(|| anyhow!("no pty master received"))?;
+        let output = master.try_clone()?;
+        pty = Some(Arc::new(master.try_clone()?));
+        (master, vec![(STREAM_STDOUT, output)])
+    } else {
+        match (fds.next(), fds.next(), fds.next()) {
+            (Some(stdin_w), Some(stdout_r), Some(stderr_r)) => {
+                (stdin_w, vec![(STREAM_STDOUT, stdout_r), (STREAM_STDERR, stderr_r)])
+            }
+            _ => return Err(anyhow!("expected stdin, stdout and stderr pipes")),
+        }
+    };
+    // without stdin the container's stdin is closed right away
+    let input = Arc::new(Mutex::new(if stdin { Some(input) } else { None }));
+
+    if attach_socket.exists() {
+        fs::remove_file(attach_socket)?;
+    }
+    let listener = UnixListener::bind(attach_socket)?;
+    (&control).write_all(b"1")?;
+    drop(control);
+
+    let clients: Clients = Arc::new(Mutex::new(Vec::new()));
+    let forwarders: Vec<_> = outputs
+        .into_iter()
+        .map(|(stream_id, output)| {
+            let clients = clients.clone();
+            thread::spawn(move || forward_output(stream_id, output, &clients))
+        })
+        .collect();
+
+    let accept_clients = clients.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let clients = accept_clients.clone();
+            let input = input.clone();
+            let pty = pty.clone();
+            thread::spawn(move || {
+                let _ = serve_client(stream, &clients, &input, pty.as_deref(), stdin_once);
+            });
+        }
+    });
+
+    // the shim lives as long as the container's output
+    for forwarder in forwarders {
+        let _ = forwarder.join();
+    }
+    let _ = fs::remove_file(attach_socket);
+    Ok(())
+}
+
+fn forward_output(stream_id: u8, mut output: File, clients: &Clients) {
+    let mut buf = [0u8; 32 * 1024];
+    loop {
+        // a pty master reads EIO once the container is gone
+        let n = match output.read(&mut buf) {
+            Ok(0) | Err(_) => return,
+            Ok(n) => n,
+        };
+        let mut clients = clients.lock().unwrap();
+        clients.retain_mut(|client| {
+            let wanted = if stream_id == STREAM_STDERR { client.stderr } else { client.stdout };
+            !wanted || stream::write_frame(&mut client.stream, stream_id, &buf[..n]).is_ok()
+        });
+    }
+}
+
+fn serve_client(
+    mut stream: UnixStream,
+    clients: &Clients,
+    input: &Mutex<Option<File>>,
+    pty: Option<&File>,
+    stdin_once: bool,
+) -> Result<()> {
+    let options = match stream::read_frame(&mut stream)? {
+        Some((STREAM_OPTIONS, options)) if options.len() == 3 => options,
+        _ => return Err(anyhow!("attach client sent no options")),
+    };
+    clients.lock().unwrap().push(Client {
+        stream: stream.try_clone()?,
+        stdout: options[1] == 1,
+        stderr: options[2] == 1,
+    });
+
+    let attach_stdin = options[0] == 1;
+    let result = forward_input(&mut stream, input, pty, attach_stdin);
+    // with stdinOnce the first client to detach takes stdin with it, the container
+    // sees EOF on a pipe and later clients can't write to a tty
+    if attach_stdin && stdin_once {
+        input.lock().unwrap().take();
+    }
+    result
+}
+
+fn forward_input(
+    stream: &mut UnixStream,
+    input: &Mutex<Option<File>>,
+    pty: Option<&File>,
+    attach_stdin: bool,
+) -> Result<()> {
+    while let Some((stream_id, data)) = stream::read_frame(stream)? {
+        match stream_id {
+            STREAM_STDIN if attach_stdin => {
+                let mut input = input.lock().unwrap();
+                if data.is_empty() {
+                    // an empty frame closes stdin, a tty is kept for the next client
+                    if pty.is_none() {
+                        input.take();
+                    }
+                } else if let Some(input) = input.as_mut() {
+                    input.write_all(&data)?;
+                }
+            }
+            STREAM_RESIZE if data.len() == 4 => {
+                let size = Winsize {
+                    ws_row: u16::from_be_bytes([data[0], data[1]]),
+                    ws_col: u16::from_be_bytes([data[2], data[3]]),
+                    ws_xpixel: 0,
+                    ws_ypixel: 0,
+                };
+                if let Some(master) = pty {
+                    // SAFETY: the descriptor is the pty master and size outlives the call
+                    unsafe { set_winsize(master.as_raw_fd(), &size) }?;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use std::sync::OnceLock;
+use anyhow::{Result, anyhow};
+use serde::de::DeserializeOwned;
+use tracing::warn;
+
+// serde skips the fields of a manifest it doesn't know, so a typo like
+// `imagePullPolicyy: Never` does nothing and says nothing. Manifests (pods,
+// ConfigMaps, Secrets, kustomizations) are parsed with from_yaml, which
+// collects the fields serde skipped: by default each is logged as a warning
+// and the manifest is used, with --strict the parse fails on them the way
+// #[serde(deny_unknown_fields)] would.
+
+static STRICT: OnceLock<bool> = OnceLock::new();
+
+// set once at startup from --strict
+pub fn init(strict: bool) {
+    let _ = STRICT.set(strict);
+}
+
+fn strict() -> bool {
+    STRICT.get().copied().unwrap_or(false)
+}
+
+// the value and the paths of the fields serde skipped, e.g.
+// spec.containers.0.imagePullPolicyy
+fn parse<T: DeserializeOwned>(contents: &str) -> Result<(T, Vec<String>)> {
+    let mut unknown = Vec::new();
+    let value = serde_ignored::deserialize(serde_yaml::Deserializer::from_str(contents), |path| {
+        unknown.push(path.to_string())
+    })?;
+    Ok((value, unknown))
+}
+
+pub fn from_yaml<T: DeserializeOwned>(contents: &str) -> Result<T> {
+    let (value, unknown) = parse(contents)?;
+    if unknown.is_empty() {
+        return Ok(value);
+    }
+    if strict() {
+        return Err(anyhow!("unknown field(s) {}", unknown.join(", ")));
+    }
+    for field in &unknown {
+        warn!(field = %field, "ignoring unknown field of the manifest, --strict rejects it");
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::task::PodTask;
+
+    #[test]
+    fn test_parse() {
+        let manifest = r#"
+apiVersion: v1
+kind: Pod
+metadata:
+  name: web
+  lables:
+    app: web
+spec:
+  containers:
+    - name: app
+      image: app:v1
+      imagePullPolicyy: Never
+"#;
+        let (task, unknown): (PodTask, _) = parse(manifest).unwrap();
+        assert_eq!(task.metadata.name, "web");
+        assert_eq!(unknown, ["metadata.lables", "spec.containers.0.imagePullPolicyy"]);
+        let (_, unknown): (PodTask, _) = parse(&manifest.replace("lables", "labels").replace("Policyy", "Policy")).unwrap();
+        assert!(unknown.is_empty());
+        // lenient by default
+        assert!(from_yaml::<PodTask>(manifest).is_ok());
+    }
+}
@@ -0,0 +1,297 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::net::Ipv4Addr;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::OnceLock;
+use anyhow::{Result, anyhow};
+use nix::sched::{CloneFlags, setns};
+use sha2::{Digest, Sha256};
+use crate::cri::cri::PodSandboxNetworkStatus;
+use tracing::warn;
+
+// The network pods get when no CNI network is configured, so that a fresh
+// machine needs no plugins: every sandbox is plugged into the linux bridge
+// rkl0 through a veth pair, its end in the sandbox renamed to eth0 and given
+// an address of the pod CIDR, with the bridge (the first address) as default
+// gateway. Traffic leaving the CIDR is masqueraded when iptables is there.
+//
+// Addresses are handed out like the host-local IPAM plugin does: a file per
+// allocated address under <root>/ipam holding the sandbox id, created
+// exclusively so that concurrent runs never get the same one, and the last one
+// handed out so that addresses of deleted pods are not reused right away.
+// The links are set up with ip(8).
+
+pub const DEFAULT_POD_CIDR: &str = "10.88.0.0/16";
+pub const BRIDGE: &str = "rkl0";
+const LAST_RESERVED: &str = "last_reserved_ip";
+
+struct BridgeConfig {
+    enabled: bool,
+    cidr: Ipv4Cidr,
+}
+
+static CONFIG: OnceLock<BridgeConfig> = OnceLock::new();
+
+// set once at startup from --pod-cidr and --no-bridge-network
+pub fn init(cidr: Ipv4Cidr, enabled: bool) {
+    let _ = CONFIG.set(BridgeConfig { enabled, cidr });
+}
+
+fn config() -> &'static BridgeConfig {
+    CONFIG.get_or_init(|| BridgeConfig { enabled: true, cidr: DEFAULT_POD_CIDR.parse().unwrap() })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ipv4Cidr {
+    pub network: Ipv4Addr,
+    pub prefix: u8,
+}
+
+impl std::str::FromStr for Ipv4Cidr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || anyhow!("invalid CIDR {}, expected e.g. {}", s, DEFAULT_POD_CIDR);
+        let (address, prefix) = s.split_once('/').ok_or_else(invalid)?;
+        let address: Ipv4Addr = address.parse().map_err(|_| invalid())?;
+        let prefix: u8 = prefix.parse().map_err(|_| invalid())?;
+        // room for the network, the gateway, a pod and the broadcast address
+        if !(8..=30).contains(&prefix) {
+            return Err(anyhow!("CIDR {} must have a prefix between /8 and /30", s));
+        }
+        let mask = u32::MAX << (32 - prefix);
+        Ok(Ipv4Cidr { network: Ipv4Addr::from(u32::from(address) & mask), prefix })
+    }
+}
+
+impl std::fmt::Display for Ipv4Cidr {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+impl Ipv4Cidr {
+    pub fn gateway(&self) -> Ipv4Addr {
+        Ipv4Addr::from(u32::from(self.network) + 1)
+    }
+
+    // the addresses pods can get, between the gateway and the broadcast address
+    pub fn hosts(&self) -> (u32, u32) {
+        let network = u32::from(self.network);
+        (network + 2, network + (1 << (32 - self.prefix)) - 2)
+    }
+
+    pub fn contains(&self, ip: Ipv4Addr) -> bool {
+        let (first, last) = self.hosts();
+        (first..=last).contains(&u32::from(ip))
+    }
+}
+
+pub fn parse_cidr_arg(s: &str) -> Result<Ipv4Cidr, String> {
+    s.parse().map_err(|e: anyhow::Error| e.to_string())
+}
+
+fn ipam_dir(root_path: &Path) -> PathBuf {
+    root_path.join("ipam")
+}
+
+// the address allocated to the sandbox, if any
+fn allocated(dir: &Path, sandbox_id: &str) -> Option<Ipv4Addr> {
+    fs::read_dir(dir).ok()?.flatten().find_map(|entry| {
+        let ip = entry.file_name().to_str()?.parse::<Ipv4Addr>().ok()?;
+        (fs::read_to_string(entry.path()).ok()?.trim() == sandbox_id).then_some(ip)
+    })
+}
+
+// allocate an address of the CIDR to the sandbox; it keeps the one it has
+fn allocate(dir: &Path, cidr: &Ipv4Cidr, sandbox_id: &str) -> Result<Ipv4Addr> {
+    fs::create_dir_all(dir)?;
+    if let Some(ip) = allocated(dir, sandbox_id).filter(|ip| cidr.contains(*ip)) {
+        return Ok(ip);
+    }
+    let (first, last) = cidr.hosts();
+    let start = fs::read_to_string(dir.join(LAST_RESERVED))
+        .ok()
+        .and_then(|ip| ip.trim().parse::<Ipv4Addr>().ok())
+        .filter(|ip| cidr.contains(*ip))
+        .map(|ip| if u32::from(ip) == last { first } else { u32::from(ip) + 1 })
+        .unwrap_or(first);
+    let candidates = (start..=last).chain(first..start);
+    for candidate in candidates {
+        let ip = Ipv4Addr::from(candidate);
+        match OpenOptions::new().write(true).create_new(true).open(dir.join(ip.to_string())) {
+            Ok(mut file) => {
+                file.write_all(sandbox_id.as_bytes())?;
+                fs::write(dir.join(LAST_RESERVED), ip.to_string())?;
+                return Ok(ip);
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(anyhow!("Failed to allocate {}: {}", ip, e)),
+        }
+    }
+    Err(anyhow!("no addresses left in {}", cidr))
+}
+
+// give back the addresses of the sandbox
+fn release(dir: &Path, sandbox_id: &str) -> Result<bool> {
+    let mut released = false;
+    while let Some(ip) = allocated(dir, sandbox_id) {
+        fs::remove_file(dir.join(ip.to_string()))?;
+        released = true;
+    }
+    Ok(released)
+}
+
+// the host end of the veth pair of a sandbox, names have at most 15 bytes
+fn host_veth(sandbox_id: &str) -> String {
+    let hash = format!("{:x}", Sha256::digest(sandbox_id.as_bytes()));
+    format!("veth{}", &hash[..8])
+}
+
+pub fn run(command: &mut Command) -> Result<()> {
+    let output = command.output().map_err(|e| anyhow!("Failed to run {:?}: {}", command.get_program(), e))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{:?} {:?} failed: {}",
+            command.get_program(),
+            command.get_args().collect::<Vec<_>>(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+pub fn ip(args: &[&str]) -> Result<()> {
+    run(Command::new("ip").args(args))
+}
+
+// ip(8) in the network namespace of pid
+fn ip_in(pid: i32, args: &[&str]) -> Result<()> {
+    let netns = File::open(format!("/proc/{}/ns/net", pid))?;
+    let mut command = Command::new("ip");
+    command.args(args);
+    // SAFETY: setns is async-signal-safe and only touches the child
+    unsafe {
+        command.pre_exec(move || setns(&netns, CloneFlags::CLONE_NEWNET).map_err(std::io::Error::from));
+    }
+    run(&mut command)
+}
+
+pub fn link_exists(name: &str) -> bool {
+    Path::new("/sys/class/net").join(name).exists()
+}
+
+// create the bridge unless it is there, which a concurrent run may have just done
+fn ensure_bridge(cidr: &Ipv4Cidr) -> Result<()> {
+    if !link_exists(BRIDGE)
+        && let Err(e) = ip(&["link", "add", BRIDGE, "type", "bridge"])
+        && !link_exists(BRIDGE)
+    {
+        return Err(e);
+    }
+    let gateway = format!("{}/{}", cidr.gateway(), cidr.prefix);
+    ip(&["addr", "replace", &gateway, "dev", BRIDGE])?;
+    ip(&["link", "set", BRIDGE, "up"])?;
+    fs::write("/proc/sys/net/ipv4/ip_forward", "1")
+        .map_err(|e| anyhow!("Failed to enable IP forwarding: {}", e))?;
+
+    let source = cidr.to_string();
+    let rule = ["-t", "nat", "POSTROUTING", "-s", &source, "!", "-o", BRIDGE, "-j", "MASQUERADE"];
+    let with = |action: &str| {
+        let mut args = rule.to_vec();
+        args.insert(2, action);
+        run(Command::new("iptables").args(args))
+    };
+    if with("-C").is_err()
+        && let Err(e) = with("-A")
+    {
+        warn!("Pods of {} can't reach beyond the node: {}", cidr, e);
+    }
+    Ok(())
+}
+
+// create the bridge when the address is its gateway, so that it can be listened on
+pub fn ensure_gateway(address: Ipv4Addr) -> Result<()> {
+    let config = config();
+    if config.enabled && config.cidr.gateway() == address {
+        ensure_bridge(&config.cidr)?;
+    }
+    Ok(())
+}
+
+// plug the sandbox whose pause container is pid into the bridge. None when
+// the built-in network is disabled
+pub fn setup(root_path: &Path, sandbox_id: &str, pid: i32) -> Result<Option<PodSandboxNetworkStatus>> {
+    let config = config();
+    if !config.enabled {
+        return Ok(None);
+    }
+    let cidr = &config.cidr;
+    ensure_bridge(cidr)?;
+    let address = allocate(&ipam_dir(root_path), cidr, sandbox_id)?;
+
+    let host = host_veth(sandbox_id);
+    let peer = format!("vp{}", &host[4..]);
+    if link_exists(&host) {
+        // left behind by an earlier attempt
+        ip(&["link", "del", &host])?;
+    }
+    ip(&["link", "add", &host, "type", "veth", "peer", "name", &peer])?;
+    ip(&["link", "set", &peer, "netns", &pid.to_string()])?;
+    ip(&["link", "set", &host, "master", BRIDGE, "up"])?;
+
+    let address_cidr = format!("{}/{}", address, cidr.prefix);
+    let gateway = cidr.gateway().to_string();
+    ip_in(pid, &["link", "set", "lo", "up"])?;
+    ip_in(pid, &["link", "set", &peer, "name", "eth0"])?;
+    ip_in(pid, &["addr", "add", &address_cidr, "dev", "eth0"])?;
+    ip_in(pid, &["link", "set", "eth0", "up"])?;
+    ip_in(pid, &["route", "replace", "default", "via", &gateway, "dev", "eth0"])?;
+    Ok(Some(PodSandboxNetworkStatus { ip: address.to_string(), additional_ips: Vec::new() }))
+}
+
+// unplug the sandbox and release its address; doing it again does nothing
+pub fn teardown(root_path: &Path, sandbox_id: &str) -> Result<()> {
+    let host = host_veth(sandbox_id);
+    // deleting one end deletes the pair
+    if link_exists(&host) {
+        ip(&["link", "del", &host])?;
+    }
+    release(&ipam_dir(root_path), sandbox_id)?;
+    Ok(())
+}
+
+// the address of the sandbox on the bridge, if it has one
+pub fn pod_ip(root_path: &Path, sandbox_id: &str) -> Option<String> {
+    allocated(&ipam_dir(root_path), sandbox_id).map(|ip| ip.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allocate() {
+        let dir = tempfile::tempdir().unwrap();
+        let cidr: Ipv4Cidr = "10.88.0.17/29".parse().unwrap();
+        assert_eq!(cidr.to_string(), "10.88.0.16/29");
+        assert_eq!(cidr.gateway(), Ipv4Addr::new(10, 88, 0, 17));
+        assert!("10.88.0.0/31".parse::<Ipv4Cidr>().is_err());
+
+        // .18 to .22, the broadcast address .23 is never handed out
+        let ips: Vec<Ipv4Addr> =
+            (0..5).map(|i| allocate(dir.path(), &cidr, &format!("pod-{}", i)).unwrap()).collect();
+        assert_eq!(ips[0], Ipv4Addr::new(10, 88, 0, 18));
+        assert_eq!(ips[4], Ipv4Addr::new(10, 88, 0, 22));
+        assert_eq!(allocate(dir.path(), &cidr, "pod-2").unwrap(), ips[2]);
+        assert!(allocate(dir.path(), &cidr, "pod-5").is_err());
+
+        assert!(release(dir.path(), "pod-1").unwrap());
+        assert!(!release(dir.path(), "pod-1").unwrap());
+        assert_eq!(allocated(dir.path(), "pod-1"), None);
+        assert_eq!(allocate(dir.path(), &cidr, "pod-5").unwrap(), ips[1]);
+        assert_eq!(host_veth("pod-5").len(), 12);
+    }
+}
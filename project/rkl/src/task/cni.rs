@@ -0,0 +1,167 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use anyhow::{Result, anyhow};
+use libcni::{CniConfig, CniResult, NetworkConfigList, RuntimeConf};
+use serde::{Deserialize, Serialize};
+use crate::cri::cri::{PodIp, PodSandboxNetworkStatus};
+use crate::task::bridge;
+
+// Pod sandboxes not on the host network are attached to the first network of
+// the CNI configuration directory once their pause container runs, and
+// detached before it is stopped. What ADD was run with and what it returned is
+// recorded under <root>/cni/<sandbox id>.json so that DEL gets the same
+// network and prevResult even if the configuration changed in between, and so
+// that the pod IP can be shown later on. Without any network configuration
+// sandboxes get the built-in bridge network instead, see bridge.rs.
+
+const IF_NAME: &str = "eth0";
+
+struct CniPaths {
+    conf_dir: PathBuf,
+    bin_dirs: Vec<PathBuf>,
+}
+
+static PATHS: OnceLock<CniPaths> = OnceLock::new();
+
+// set once at startup from --cni-conf-dir and --cni-bin-dir
+pub fn init(conf_dir: PathBuf, bin_dirs: Vec<PathBuf>) {
+    let _ = PATHS.set(CniPaths { conf_dir, bin_dirs });
+}
+
+fn paths() -> &'static CniPaths {
+    PATHS.get_or_init(|| CniPaths {
+        conf_dir: PathBuf::from(libcni::DEFAULT_CONF_DIR),
+        bin_dirs: vec![PathBuf::from(libcni::DEFAULT_PLUGIN_DIR)],
+    })
+}
+
+// an attached sandbox
+#[derive(Debug, Serialize, Deserialize)]
+struct Attachment {
+    network: NetworkConfigList,
+    netns: PathBuf,
+    args: Vec<(String, String)>,
+    result: serde_json::Value,
+}
+
+impl Attachment {
+    fn runtime(&self, sandbox_id: &str) -> RuntimeConf {
+        RuntimeConf {
+            container_id: sandbox_id.to_string(),
+            netns: self.netns.clone(),
+            if_name: IF_NAME.to_string(),
+            args: self.args.clone(),
+        }
+    }
+}
+
+fn attachment_path(root_path: &Path, sandbox_id: &str) -> PathBuf {
+    root_path.join("cni").join(format!("{}.json", sandbox_id))
+}
+
+fn load(root_path: &Path, sandbox_id: &str) -> Result<Option<Attachment>> {
+    let path = attachment_path(root_path, sandbox_id);
+    match fs::read_to_string(&path) {
+        Ok(contents) => Ok(Some(serde_json::from_str(&contents)?)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(anyhow!("Failed to read {}: {}", path.display(), e)),
+    }
+}
+
+fn network_status(result: &CniResult) -> Option<PodSandboxNetworkStatus> {
+    let mut ips = result.ips().into_iter().map(|ip| ip.to_string());
+    let ip = ips.next()?;
+    Some(PodSandboxNetworkStatus { ip, additional_ips: ips.map(|ip| PodIp { ip }).collect() })
+}
+
+// run ADD for the sandbox whose pause container is pid, or plug it into the
+// bridge without a network configured. None when it got no address
+pub fn setup(
+    root_path: &Path,
+    sandbox_id: &str,
+    namespace: &str,
+    pod_name: &str,
+    pid: i32,
+) -> Result<Option<PodSandboxNetworkStatus>> {
+    let paths = paths();
+    let Some(network) = libcni::default_network(&paths.conf_dir)? else {
+        return bridge::setup(root_path, sandbox_id, pid);
+    };
+    let attachment = Attachment {
+        network,
+        netns: PathBuf::from(format!("/proc/{}/ns/net", pid)),
+        args: vec![
+            ("IgnoreUnknown".to_string(), "1".to_string()),
+            ("K8S_POD_NAMESPACE".to_string(), namespace.to_string()),
+            ("K8S_POD_NAME".to_string(), pod_name.to_string()),
+            ("K8S_POD_INFRA_CONTAINER_ID".to_string(), sandbox_id.to_string()),
+        ],
+        result: serde_json::Value::Null,
+    };
+    let cni = CniConfig::new(paths.bin_dirs.clone());
+    let path = attachment_path(root_path, sandbox_id);
+    fs::create_dir_all(path.parent().unwrap())?;
+    // recorded first, a failing ADD may have left something behind for DEL
+    fs::write(&path, serde_json::to_string(&attachment)?)?;
+    let result = cni
+        .add_network_list(&attachment.network, &attachment.runtime(sandbox_id))
+        .map_err(|e| anyhow!("network {}: {}", attachment.network.name, e))?;
+    let attachment = Attachment { result: result.0.clone(), ..attachment };
+    fs::write(&path, serde_json::to_string(&attachment)?)?;
+    Ok(network_status(&result))
+}
+
+// run DEL for the sandbox if it was attached; doing it again does nothing
+pub fn teardown(root_path: &Path, sandbox_id: &str) -> Result<()> {
+    let Some(attachment) = load(root_path, sandbox_id)? else {
+        return bridge::teardown(root_path, sandbox_id);
+    };
+    let prev_result = (!attachment.result.is_null()).then(|| CniResult(attachment.result.clone()));
+    CniConfig::new(paths().bin_dirs.clone())
+        .del_network_list(&attachment.network, &attachment.runtime(sandbox_id), prev_result.as_ref())
+        .map_err(|e| anyhow!("network {}: {}", attachment.network.name, e))?;
+    fs::remove_file(attachment_path(root_path, sandbox_id))?;
+    Ok(())
+}
+
+// the IP the network assigned to the sandbox, if it is attached
+pub fn pod_ip(root_path: &Path, sandbox_id: &str) -> Option<String> {
+    let Some(attachment) = load(root_path, sandbox_id).ok()? else {
+        return bridge::pod_ip(root_path, sandbox_id);
+    };
+    network_status(&CniResult(attachment.result)).map(|status| status.ip)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_setup_and_teardown() {
+        let root = tempfile::tempdir().unwrap();
+        let conf_dir = root.path().join("net.d");
+        let bin_dir = root.path().join("bin");
+        fs::create_dir_all(&conf_dir).unwrap();
+        fs::create_dir_all(&bin_dir).unwrap();
+        fs::write(conf_dir.join("10-pods.conf"), r#"{"cniVersion": "1.0.0", "name": "pods", "type": "fake"}"#).unwrap();
+        let plugin = bin_dir.join("fake");
+        let script = format!(
+            "#!/bin/sh\ncat > /dev/null\necho $CNI_COMMAND >> {}\n\
+             [ $CNI_COMMAND = ADD ] && echo '{{\"ips\": [{{\"address\": \"10.88.0.7/16\"}}]}}'\nexit 0\n",
+            root.path().join("calls").display()
+        );
+        fs::write(&plugin, script).unwrap();
+        fs::set_permissions(&plugin, fs::Permissions::from_mode(0o755)).unwrap();
+        init(conf_dir, vec![bin_dir]);
+
+        let status = setup(root.path(), "web", "default", "web", 1).unwrap().unwrap();
+        assert_eq!(status.ip, "10.88.0.7");
+        assert_eq!(pod_ip(root.path(), "web").as_deref(), Some("10.88.0.7"));
+        teardown(root.path(), "web").unwrap();
+        teardown(root.path(), "web").unwrap();
+        assert_eq!(pod_ip(root.path(), "web"), None);
+        assert_eq!(fs::read_to_string(root.path().join("calls")).unwrap(), "ADD\nDEL\n");
+    }
+}
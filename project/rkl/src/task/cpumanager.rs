@@ -0,0 +1,274 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use crate::quantity::Quantity;
+use crate::task::qos::{self, QosClass};
+use crate::task::task::{ContainerSpec, PodSpec};
+
+// The CPU manager, like the kubelet's: with --cpu-manager-policy static the
+// containers of Guaranteed pods whose cpu request is a whole number of CPUs
+// get that many CPUs of their own, e.g. with
+//
+//   resources:
+//     limits:
+//       cpu: 2
+//       memory: 1Gi
+//
+// pinned through their cpuset, whole cores first so that no other container
+// runs on their hyperthreads. Every other container runs on the shared pool,
+// the online CPUs no container has for itself. The --reserved-cpus stay in
+// the shared pool for the system and rkl, and a container is refused when
+// there aren't enough CPUs left or the shared pool would be empty.
+// Containers already running keep the shared pool they were created with. The
+// assignments are kept in <root>/cpu_manager_state, so that restarts of the
+// daemon and of the containers keep their CPUs, until the container or the
+// pod is removed. Devices allocated to a pinned container prefer those local
+// to its CPUs, see device. The default policy none leaves the cpuset alone.
+
+const STATE_FILE: &str = "cpu_manager_state";
+const ONLINE_CPUS: &str = "/sys/devices/system/cpu/online";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum CpuManagerPolicy {
+    #[default]
+    None,
+    Static,
+}
+
+impl CpuManagerPolicy {
+    fn name(self) -> &'static str {
+        match self {
+            CpuManagerPolicy::None => "none",
+            CpuManagerPolicy::Static => "static",
+        }
+    }
+}
+
+struct Config {
+    policy: CpuManagerPolicy,
+    reserved: Vec<u32>,
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+// set once at startup from --cpu-manager-policy and --reserved-cpus
+pub fn init(policy: CpuManagerPolicy, reserved: Vec<u32>) {
+    let _ = CONFIG.set(Config { policy, reserved });
+}
+
+// the CPUs of a container, exclusive when they are its own
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpuAssignment {
+    pub cpus: Vec<u32>,
+    pub exclusive: bool,
+}
+
+// <pod> -> <container> -> cpuset, and the shared pool when it was saved
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct State {
+    policy_name: String,
+    default_cpu_set: String,
+    entries: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+// a kernel cpu list like 0-3,8, of cpusets, sysfs and --reserved-cpus
+pub fn parse_cpuset(text: &str) -> Result<Vec<u32>> {
+    let invalid = || anyhow!("invalid cpu list {:?}, expected e.g. 0-3,8", text);
+    let mut cpus = Vec::new();
+    for range in text.trim().split(',').filter(|range| !range.is_empty()) {
+        let (first, last) = range.split_once('-').unwrap_or((range, range));
+        let (first, last): (u32, u32) = (first.trim().parse().map_err(|_| invalid())?, last.trim().parse().map_err(|_| invalid())?);
+        if first > last {
+            return Err(invalid());
+        }
+        cpus.extend(first..=last);
+    }
+    cpus.sort_unstable();
+    cpus.dedup();
+    Ok(cpus)
+}
+
+pub fn format_cpuset(cpus: &[u32]) -> String {
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+    for &cpu in cpus {
+        match ranges.last_mut() {
+            Some((_, last)) if *last + 1 == cpu => *last = cpu,
+            _ => ranges.push((cpu, cpu)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|&(first, last)| if first == last { first.to_string() } else { format!("{}-{}", first, last) })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+// the number of CPUs the container gets for itself, None when it shares
+pub fn exclusive_cpus(spec: &PodSpec, container: &ContainerSpec) -> Option<usize> {
+    if qos::qos_class(spec) != QosClass::Guaranteed {
+        return None;
+    }
+    let millis = qos::request(container, "cpu").map(Quantity::milli_value)?;
+    (millis > 0 && millis % 1000 == 0).then_some((millis / 1000) as usize)
+}
+
+// the CPUs sharing a core with each online CPU, e.g. [[0, 4], [1, 5], ...]
+fn cores(online: &[u32]) -> Vec<Vec<u32>> {
+    let mut cores: Vec<Vec<u32>> = Vec::new();
+    for cpu in online {
+        let siblings = fs::read_to_string(format!("/sys/devices/system/cpu/cpu{}/topology/thread_siblings_list", cpu))
+            .ok()
+            .and_then(|list| parse_cpuset(&list).ok())
+            .unwrap_or_else(|| vec![*cpu]);
+        if !cores.contains(&siblings) {
+            cores.push(siblings);
+        }
+    }
+    cores
+}
+
+// count of the free CPUs, whole cores first
+fn choose(free: &[u32], cores: &[Vec<u32>], count: usize) -> Vec<u32> {
+    let mut chosen: Vec<u32> = Vec::new();
+    for core in cores {
+        if chosen.len() + core.len() <= count && core.iter().all(|cpu| free.contains(cpu)) {
+            chosen.extend(core);
+        }
+    }
+    for cpu in free {
+        if chosen.len() == count {
+            break;
+        }
+        if !chosen.contains(cpu) {
+            chosen.push(*cpu);
+        }
+    }
+    chosen.sort_unstable();
+    chosen
+}
+
+fn load(root_path: &Path) -> Result<State> {
+    let path = root_path.join(STATE_FILE);
+    if !path.exists() {
+        return Ok(State::default());
+    }
+    serde_json::from_str(&fs::read_to_string(&path)?).map_err(|e| anyhow!("invalid {}: {}", path.display(), e))
+}
+
+fn save(root_path: &Path, state: &mut State, online: &[u32], policy: CpuManagerPolicy) -> Result<()> {
+    state.policy_name = policy.name().to_string();
+    state.default_cpu_set = format_cpuset(&shared_pool(state, online));
+    fs::write(root_path.join(STATE_FILE), serde_json::to_string_pretty(state)?)?;
+    Ok(())
+}
+
+fn assigned(state: &State) -> Vec<u32> {
+    state.entries.values().flat_map(|containers| containers.values()).filter_map(|cpuset| parse_cpuset(cpuset).ok()).flatten().collect()
+}
+
+fn shared_pool(state: &State, online: &[u32]) -> Vec<u32> {
+    let assigned = assigned(state);
+    online.iter().copied().filter(|cpu| !assigned.contains(cpu)).collect()
+}
+
+// give count CPUs of its own to the container, the ones it has if any
+fn assign(state: &mut State, online: &[u32], reserved: &[u32], cores: &[Vec<u32>], owner: (&str, &str), count: usize) -> Result<Vec<u32>> {
+    let (pod_name, container_name) = owner;
+    if let Some(cpuset) = state.entries.get(pod_name).and_then(|containers| containers.get(container_name)) {
+        return parse_cpuset(cpuset);
+    }
+    let shared = shared_pool(state, online);
+    let free: Vec<u32> = shared.iter().copied().filter(|cpu| !reserved.contains(cpu)).collect();
+    if free.len() < count {
+        return Err(anyhow!("not enough exclusive CPUs for {}/{}: {} requested, {} available", pod_name, container_name, count, free.len()));
+    }
+    if shared.len() == count {
+        return Err(anyhow!("{}/{} would take every CPU of the shared pool, see --reserved-cpus", pod_name, container_name));
+    }
+    let cpus = choose(&free, cores, count);
+    state.entries.entry(pod_name.to_string()).or_default().insert(container_name.to_string(), format_cpuset(&cpus));
+    Ok(cpus)
+}
+
+// the cpuset of a container, None with the none policy; the exclusive CPUs
+// are only recorded when record is set, dry runs just check they are there
+pub fn container_cpus(root_path: &Path, pod_name: &str, spec: &PodSpec, container: &ContainerSpec, record: bool) -> Result<Option<CpuAssignment>> {
+    let Some(config) = CONFIG.get().filter(|config| config.policy == CpuManagerPolicy::Static) else {
+        return Ok(None);
+    };
+    let online = parse_cpuset(&fs::read_to_string(ONLINE_CPUS)?)?;
+    let mut state = load(root_path)?;
+    let Some(count) = exclusive_cpus(spec, container) else {
+        return Ok(Some(CpuAssignment { cpus: shared_pool(&state, &online), exclusive: false }));
+    };
+    let cpus = assign(&mut state, &online, &config.reserved, &cores(&online), (pod_name, &container.name), count)?;
+    if record {
+        save(root_path, &mut state, &online, config.policy)?;
+    }
+    Ok(Some(CpuAssignment { cpus, exclusive: true }))
+}
+
+fn release(root_path: &Path, remove: impl FnOnce(&mut State)) -> Result<()> {
+    if !root_path.join(STATE_FILE).exists() {
+        return Ok(());
+    }
+    let mut state = load(root_path)?;
+    remove(&mut state);
+    let online = parse_cpuset(&fs::read_to_string(ONLINE_CPUS).unwrap_or_default()).unwrap_or_default();
+    let policy = CONFIG.get().map(|config| config.policy).unwrap_or_default();
+    save(root_path, &mut state, &online, policy)
+}
+
+// give the CPUs of every container of the pod back to the shared pool
+pub fn release_pod(root_path: &Path, pod_name: &str) -> Result<()> {
+    release(root_path, |state| {
+        state.entries.remove(pod_name);
+    })
+}
+
+// give the CPUs of one container back, e.g. before it is recreated
+pub fn release_container(root_path: &Path, pod_name: &str, container_name: &str) -> Result<()> {
+    release(root_path, |state| {
+        if let Some(containers) = state.entries.get_mut(pod_name) {
+            containers.remove(container_name);
+            if containers.is_empty() {
+                state.entries.remove(pod_name);
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assign() {
+        assert_eq!(parse_cpuset("0-3,8\n").unwrap(), [0, 1, 2, 3, 8]);
+        assert_eq!(format_cpuset(&[0, 1, 2, 3, 5, 7, 8]), "0-3,5,7-8");
+        assert!(parse_cpuset("3-1").is_err());
+        assert!(parse_cpuset("").unwrap().is_empty());
+
+        // 4 cores with 2 hyperthreads each, cpu 0 reserved
+        let online: Vec<u32> = (0..8).collect();
+        let cores: Vec<Vec<u32>> = (0..4).map(|core| vec![core, core + 4]).collect();
+        let mut state = State::default();
+        let db = assign(&mut state, &online, &[0], &cores, ("db", "postgres"), 2).unwrap();
+        assert_eq!(db, [1, 5]);
+        // an odd count takes a whole core and a lone cpu
+        let web = assign(&mut state, &online, &[0], &cores, ("web", "nginx"), 3).unwrap();
+        assert_eq!(web, [2, 3, 6]);
+        assert_eq!(assign(&mut state, &online, &[0], &cores, ("db", "postgres"), 2).unwrap(), db);
+        assert_eq!(format_cpuset(&shared_pool(&state, &online)), "0,4,7");
+        assert_eq!(
+            assign(&mut state, &online, &[0], &cores, ("batch", "job"), 3).unwrap_err().to_string(),
+            "not enough exclusive CPUs for batch/job: 3 requested, 2 available"
+        );
+        // without reserved CPUs the shared pool must keep one
+        assert!(assign(&mut state, &online, &[], &cores, ("batch", "job"), 3).is_err());
+    }
+}
@@ -0,0 +1,198 @@
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::Path;
+use std::sync::OnceLock;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use crate::cri::cri::DnsConfig;
+
+pub const HOST_RESOLV_CONF: &str = "/etc/resolv.conf";
+pub const DEFAULT_CLUSTER_DOMAIN: &str = "cluster.local";
+// like the kubelet, so that the service names of the namespace are tried first
+const CLUSTER_NDOTS: &str = "ndots:5";
+
+// the DNS server `rkl daemon` runs for the pods, see daemon/dns.rs
+struct ClusterDns {
+    server: Option<Ipv4Addr>,
+    domain: String,
+}
+
+static CLUSTER_DNS: OnceLock<ClusterDns> = OnceLock::new();
+
+// set once at startup from --cluster-dns and --cluster-domain
+pub fn init(server: Option<Ipv4Addr>, domain: String) {
+    let _ = CLUSTER_DNS.set(ClusterDns { server, domain });
+}
+
+// the address and domain of the cluster DNS, None while it isn't configured
+pub fn cluster_dns() -> Option<(Ipv4Addr, &'static str)> {
+    let config = CLUSTER_DNS.get_or_init(|| ClusterDns { server: None, domain: DEFAULT_CLUSTER_DOMAIN.to_string() });
+    config.server.map(|server| (server, config.domain.as_str()))
+}
+
+// simulate Kubernetes dnsPolicy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum DnsPolicy {
+    // use the cluster DNS, or the node's resolv.conf while no cluster DNS is configured
+    #[default]
+    ClusterFirst,
+    // same as ClusterFirst, also for pods running with hostNetwork
+    ClusterFirstWithHostNet,
+    // inherit the node's resolv.conf
+    Default,
+    // only use what dnsConfig sets
+    None,
+}
+
+// simulate Kubernetes PodDNSConfig
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PodDnsConfig {
+    #[serde(default)]
+    pub nameservers: Vec<String>,
+    #[serde(default)]
+    pub searches: Vec<String>,
+    #[serde(default)]
+    pub options: Vec<PodDnsConfigOption>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PodDnsConfigOption {
+    pub name: String,
+    #[serde(default)]
+    pub value: Option<String>,
+}
+
+// read nameservers, search domains and options from a resolv.conf
+pub fn parse_resolv_conf(contents: &str) -> DnsConfig {
+    let mut config = DnsConfig::default();
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        match fields.next() {
+            Some("nameserver") => config.servers.extend(fields.next().map(str::to_string)),
+            // the last domain or search line wins, like in the resolver
+            Some("search") | Some("domain") => config.searches = fields.map(str::to_string).collect(),
+            Some("options") => config.options.extend(fields.map(str::to_string)),
+            _ => {}
+        }
+    }
+    config
+}
+
+pub fn host_dns_config() -> Result<DnsConfig> {
+    if !Path::new(HOST_RESOLV_CONF).exists() {
+        return Ok(DnsConfig::default());
+    }
+    Ok(parse_resolv_conf(&fs::read_to_string(HOST_RESOLV_CONF)?))
+}
+
+// resolve through the cluster DNS, the names of the namespace first and then
+// the search domains of the node
+pub fn cluster_dns_config(server: Ipv4Addr, domain: &str, namespace: &str, host: DnsConfig) -> DnsConfig {
+    let mut searches = vec![format!("{}.svc.{}", namespace, domain), format!("svc.{}", domain), domain.to_string()];
+    for search in host.searches {
+        if !searches.contains(&search) {
+            searches.push(search);
+        }
+    }
+    DnsConfig { servers: vec![server.to_string()], searches, options: vec![CLUSTER_NDOTS.to_string()] }
+}
+
+// compute the sandbox DNS config: the base given by the policy, extended with dnsConfig.
+// Like Kubernetes, ClusterFirst pods running with hostNetwork use the node's resolv.conf
+pub fn pod_dns_config(
+    policy: DnsPolicy,
+    pod_config: Option<&PodDnsConfig>,
+    host_network: bool,
+    namespace: &str,
+) -> Result<DnsConfig> {
+    let cluster = match policy {
+        DnsPolicy::ClusterFirst if !host_network => cluster_dns(),
+        DnsPolicy::ClusterFirstWithHostNet => cluster_dns(),
+        _ => None,
+    };
+    let mut config = match (policy, cluster) {
+        (DnsPolicy::None, _) => DnsConfig::default(),
+        (_, Some((server, domain))) => cluster_dns_config(server, domain, namespace, host_dns_config()?),
+        (_, None) => host_dns_config()?,
+    };
+
+    if let Some(pod_config) = pod_config {
+        for server in &pod_config.nameservers {
+            if !config.servers.contains(server) {
+                config.servers.push(server.clone());
+            }
+        }
+        for search in &pod_config.searches {
+            if !config.searches.contains(search) {
+                config.searches.push(search.clone());
+            }
+        }
+        for option in &pod_config.options {
+            // an option given in dnsConfig replaces the one with the same name
+            config.options.retain(|o| o.split(':').next() != Some(option.name.as_str()));
+            config.options.push(match &option.value {
+                Some(value) => format!("{}:{}", option.name, value),
+                None => option.name.clone(),
+            });
+        }
+    }
+    Ok(config)
+}
+
+pub fn render_resolv_conf(config: &DnsConfig) -> String {
+    let mut contents = String::new();
+    if !config.searches.is_empty() {
+        contents.push_str(&format!("search {}\n", config.searches.join(" ")));
+    }
+    for server in &config.servers {
+        contents.push_str(&format!("nameserver {}\n", server));
+    }
+    if !config.options.is_empty() {
+        contents.push_str(&format!("options {}\n", config.options.join(" ")));
+    }
+    contents
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_resolv_conf() {
+        let config = parse_resolv_conf(
+            "# generated\nnameserver 10.0.0.2\nnameserver 10.0.0.3\nsearch a.local b.local\noptions ndots:2 edns0\n",
+        );
+        assert_eq!(config.servers, vec!["10.0.0.2", "10.0.0.3"]);
+        assert_eq!(config.searches, vec!["a.local", "b.local"]);
+        assert_eq!(config.options, vec!["ndots:2", "edns0"]);
+    }
+
+    #[test]
+    fn test_pod_dns_config_none_policy() {
+        let pod_config = PodDnsConfig {
+            nameservers: vec!["1.1.1.1".to_string()],
+            searches: vec!["svc.local".to_string()],
+            options: vec![PodDnsConfigOption { name: "ndots".to_string(), value: Some("5".to_string()) }],
+        };
+        let config = pod_dns_config(DnsPolicy::None, Some(&pod_config), false, "default").unwrap();
+        assert_eq!(config.servers, vec!["1.1.1.1"]);
+        assert_eq!(
+            render_resolv_conf(&config),
+            "search svc.local\nnameserver 1.1.1.1\noptions ndots:5\n"
+        );
+    }
+
+    #[test]
+    fn test_cluster_dns_config() {
+        let host = parse_resolv_conf("nameserver 10.0.0.2\nsearch corp.example cluster.local\n");
+        let config = cluster_dns_config(Ipv4Addr::new(10, 88, 0, 1), "cluster.local", "web", host);
+        assert_eq!(
+            render_resolv_conf(&config),
+            "search web.svc.cluster.local svc.cluster.local cluster.local corp.example\nnameserver 10.88.0.1\noptions ndots:5\n"
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use crate::configmap::{self, KeyToPath};
+
+// The downward API lets containers see what their pod is: fieldRef env vars
+// and the files of downwardAPI volumes take the values of
+//
+//   metadata.name, metadata.namespace, metadata.uid, status.podIP
+//   metadata.labels['<key>'], metadata.annotations['<key>']
+//
+// and, in volumes only, metadata.labels and metadata.annotations as
+// key="value" lines. The uid and the IP only exist once the sandbox is up,
+// they are recorded in its directory so that restarted containers see the same.
+
+const STATUS_FILE: &str = "status.json";
+
+// simulate Kubernetes ObjectFieldSelector
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ObjectFieldSelector {
+    #[serde(rename = "fieldPath")]
+    pub field_path: String,
+}
+
+// simulate Kubernetes DownwardAPIVolumeSource
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownwardApiVolumeSource {
+    #[serde(default)]
+    pub items: Vec<DownwardApiVolumeFile>,
+    #[serde(rename = "defaultMode", default = "configmap::default_mode")]
+    pub default_mode: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownwardApiVolumeFile {
+    pub path: String,
+    #[serde(rename = "fieldRef")]
+    pub field_ref: ObjectFieldSelector,
+    #[serde(default)]
+    pub mode: Option<u32>,
+}
+
+// what the sandbox knows of the pod once it runs
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SandboxStatus {
+    pub uid: String,
+    #[serde(rename = "podIP", default)]
+    pub pod_ip: String,
+}
+
+fn status_path(sandbox_dir: &Path) -> PathBuf {
+    sandbox_dir.join(STATUS_FILE)
+}
+
+impl SandboxStatus {
+    pub fn save(&self, sandbox_dir: &Path) -> Result<()> {
+        fs::create_dir_all(sandbox_dir)?;
+        fs::write(status_path(sandbox_dir), serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn load(sandbox_dir: &Path) -> Result<Self> {
+        let path = status_path(sandbox_dir);
+        let contents = fs::read_to_string(&path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+}
+
+// the values the downward API takes from
+pub struct PodFields<'a> {
+    pub name: &'a str,
+    pub namespace: &'a str,
+    pub labels: &'a HashMap<String, String>,
+    pub annotations: &'a HashMap<String, String>,
+    pub status: SandboxStatus,
+}
+
+// key="value" lines sorted by key, how Kubernetes writes labels and annotations to files
+fn format_map(map: &HashMap<String, String>) -> String {
+    let sorted: BTreeMap<&String, &String> = map.iter().collect();
+    sorted.iter().map(|(key, value)| format!("{}={:?}\n", key, value)).collect()
+}
+
+impl PodFields<'_> {
+    // the value of a field path; whole maps can only be written to files
+    pub fn field(&self, path: &str, in_volume: bool) -> Result<String> {
+        let lookup = |map: &HashMap<String, String>, key: &str| map.get(key).cloned().unwrap_or_default();
+        let subscript = |prefix: &str| {
+            path.strip_prefix(prefix)
+                .and_then(|rest| rest.strip_prefix("['"))
+                .and_then(|rest| rest.strip_suffix("']"))
+                .map(str::to_string)
+        };
+        match path {
+            "metadata.name" => Ok(self.name.to_string()),
+            "metadata.namespace" => Ok(self.namespace.to_string()),
+            "metadata.uid" => Ok(self.status.uid.clone()),
+            "status.podIP" => Ok(self.status.pod_ip.clone()),
+            "metadata.labels" if in_volume => Ok(format_map(self.labels)),
+            "metadata.annotations" if in_volume => Ok(format_map(self.annotations)),
+            _ => {
+                if let Some(key) = subscript("metadata.labels") {
+                    return Ok(lookup(self.labels, &key));
+                }
+                if let Some(key) = subscript("metadata.annotations") {
+                    return Ok(lookup(self.annotations, &key));
+                }
+                Err(anyhow!("unsupported fieldPath {}", path))
+            }
+        }
+    }
+
+    // write the files of a downwardAPI volume into dir
+    pub fn project(&self, source: &DownwardApiVolumeSource, dir: &Path) -> Result<()> {
+        let mut data = BTreeMap::new();
+        let mut items = Vec::new();
+        for file in &source.items {
+            let value = self.field(&file.field_ref.field_path, true)?;
+            data.insert(file.path.clone(), value.into_bytes());
+            items.push(KeyToPath { key: file.path.clone(), path: file.path.clone(), mode: file.mode });
+        }
+        configmap::write_keys("downwardAPI volume", &data, &items, source.default_mode, false, dir)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fields() {
+        let labels = HashMap::from([("app".to_string(), "web".to_string()), ("tier".to_string(), "front".to_string())]);
+        let annotations = HashMap::new();
+        let fields = PodFields {
+            name: "web-1",
+            namespace: "default",
+            labels: &labels,
+            annotations: &annotations,
+            status: SandboxStatus { uid: "1234".to_string(), pod_ip: "10.1.0.5".to_string() },
+        };
+        assert_eq!(fields.field("metadata.uid", false).unwrap(), "1234");
+        assert_eq!(fields.field("status.podIP", false).unwrap(), "10.1.0.5");
+        assert_eq!(fields.field("metadata.labels['app']", false).unwrap(), "web");
+        assert!(fields.field("metadata.labels", false).is_err());
+        assert!(fields.field("spec.nodeName", false).is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let file = |path: &str, field: &str| DownwardApiVolumeFile {
+            path: path.to_string(),
+            field_ref: ObjectFieldSelector { field_path: field.to_string() },
+            mode: None,
+        };
+        let source = DownwardApiVolumeSource {
+            items: vec![file("labels", "metadata.labels"), file("name", "metadata.name")],
+            default_mode: 0o644,
+        };
+        fields.project(&source, dir.path()).unwrap();
+        assert_eq!(fs::read_to_string(dir.path().join("labels")).unwrap(), "app=\"web\"\ntier=\"front\"\n");
+        assert_eq!(fs::read_to_string(dir.path().join("name")).unwrap(), "web-1");
+    }
+}
@@ -0,0 +1,104 @@
+use anyhow::{Result, anyhow};
+use libcontainer::oci_spec::runtime::{LinuxNamespace, LinuxNamespaceBuilder, LinuxNamespaceType};
+use serde_json::json;
+use crate::commands::load_container;
+use crate::cri::cri::StartContainerRequest;
+use crate::events;
+use crate::rootpath;
+use crate::task::task::{ContainerSpec, TaskRunner};
+
+// Ephemeral containers, like those of `kubectl debug`: `rkl debug <pod>
+// --image busybox` adds a container to the running sandbox of a pod, in its
+// network, IPC and UTS namespaces, with the tools of its own image, e.g. to
+// look into a distroless image that has no shell. With --target the container
+// joins the PID namespace of that container, so its processes and their
+// filesystems under /proc/<pid>/root can be seen. An ephemeral container is
+// recorded in the ephemeralContainers of the spec of the pod and removed with
+// the pod, but it is never restarted, probed or counted for the readiness of
+// the pod, and doesn't appear among its containers. Only the built-in runtime
+// can add containers to a running sandbox.
+
+// the command when none is given, busybox and most debug images have it
+pub const DEFAULT_COMMAND: &str = "sh";
+
+// the spec of a debug container, stdin kept open for the first attach only
+pub fn container(name: &str, image: &str, command: Vec<String>, target: Option<&str>, stdin: bool, tty: bool) -> Result<ContainerSpec> {
+    let command = if command.is_empty() { vec![DEFAULT_COMMAND.to_string()] } else { command };
+    let spec = json!({
+        "name": name,
+        "image": image,
+        "args": command,
+        "stdin": stdin,
+        "stdinOnce": stdin,
+        "tty": tty,
+        "targetContainerName": target,
+    });
+    serde_json::from_value(spec).map_err(|e| anyhow!("invalid ephemeral container: {}", e))
+}
+
+// put the container into the PID namespace of the target container
+pub fn join_target_pid_namespace(namespaces: &mut Vec<LinuxNamespace>, target: &str) -> Result<()> {
+    let root_path = rootpath::determine(None)?;
+    let container = load_container(root_path, target).map_err(|e| anyhow!("target container {}: {}", target, e))?;
+    let pid = container.pid().ok_or_else(|| anyhow!("target container {} is not running", target))?;
+    pid_namespace(namespaces, pid.as_raw())
+}
+
+fn pid_namespace(namespaces: &mut Vec<LinuxNamespace>, pid: i32) -> Result<()> {
+    namespaces.retain(|namespace| namespace.typ() != LinuxNamespaceType::Pid);
+    let namespace = LinuxNamespaceBuilder::default().typ(LinuxNamespaceType::Pid).path(format!("/proc/{}/ns/pid", pid)).build()?;
+    namespaces.insert(0, namespace);
+    Ok(())
+}
+
+impl TaskRunner {
+    // create and start an ephemeral container in the running sandbox of the pod
+    pub fn run_ephemeral_container(&mut self, pod_sandbox_id: &str, container: ContainerSpec) -> Result<()> {
+        if self.backend.is_some() {
+            return Err(anyhow!("ephemeral containers can only be run by the built-in runtime"));
+        }
+        let spec = &self.task.spec;
+        let mut taken = spec.init_containers.iter().chain(&spec.sidecars).chain(&spec.containers).chain(&spec.ephemeral_containers);
+        if taken.any(|existing| existing.name == container.name) {
+            return Err(anyhow!("container {} already exists in Pod {}", container.name, self.task.metadata.name));
+        }
+        if let Some(target) = &container.target_container_name
+            && spec.running_containers().all(|running| &running.name != target)
+        {
+            return Err(anyhow!("target container {} not found in Pod {}", target, self.task.metadata.name));
+        }
+        let name = container.name.clone();
+        self.task.spec.ephemeral_containers.push(container);
+        self.join_sandbox(pod_sandbox_id)?;
+
+        let container = self.task.spec.container(&name).ok_or_else(|| anyhow!("Container spec not found for ID: {}", name))?;
+        let request = self.build_create_container_request(pod_sandbox_id, container)?;
+        self.create_container(request)?;
+        self.events().normal(events::CREATED, &format!("Created container {}", name));
+        self.start_container(StartContainerRequest { container_id: name.clone() })?;
+        self.events().normal(events::STARTED, &format!("Started container {}", name));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ephemeral_container() {
+        let container = container("debugger", "busybox", Vec::new(), Some("app"), true, true).unwrap();
+        assert_eq!(container.args, [DEFAULT_COMMAND]);
+        assert!(container.stdin_once && container.tty);
+        assert_eq!(container.target_container_name.as_deref(), Some("app"));
+        assert!(!container.is_sidecar());
+
+        let mut namespaces = vec![
+            LinuxNamespaceBuilder::default().typ(LinuxNamespaceType::Pid).build().unwrap(),
+            LinuxNamespaceBuilder::default().typ(LinuxNamespaceType::Network).path("/proc/1/ns/net").build().unwrap(),
+        ];
+        pid_namespace(&mut namespaces, 42).unwrap();
+        assert_eq!(namespaces.len(), 2);
+        assert_eq!(namespaces[0].path().as_deref(), Some(std::path::Path::new("/proc/42/ns/pid")));
+    }
+}
@@ -0,0 +1,210 @@
+use std::fs::{self, File};
+use std::net::IpAddr;
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use std::thread;
+use anyhow::{Result, anyhow};
+use nix::sched::{CloneFlags, setns};
+use nix::unistd::sethostname;
+use serde::{Deserialize, Serialize};
+use crate::rootpath;
+use crate::task::dns;
+use crate::task::task::{PodSpec, TaskRunner, sandbox_dir};
+
+// The hostname and /etc/hosts of a pod, like the kubelet sets them up: the
+// hostname is the name of the pod, cut to 63 characters, unless the spec sets
+// one, and with a subdomain the pod is also
+// <hostname>.<subdomain>.<namespace>.svc.<cluster domain>, the name a
+// headless service called like the subdomain gives it. Every container gets
+// the hosts file of the sandbox mounted at /etc/hosts, with localhost, the IP
+// of the pod under its names and the hostAliases, e.g.
+//
+//   hostname: db-0
+//   subdomain: db
+//   hostAliases:
+//     - ip: 10.1.2.3
+//       hostnames: [legacy.corp.example]
+//
+// A pod with hostNetwork keeps the hostname of the node and gets the node's
+// /etc/hosts with its hostAliases appended. The built-in runtime and the OCI
+// runtimes set the hostname in the UTS namespace of the pause container, CRI
+// runtimes take it from the sandbox config.
+
+pub const MOUNT_PATH: &str = "/etc/hosts";
+
+const HOST_HOSTS: &str = "/etc/hosts";
+
+// the longest DNS label, and hostname
+const MAX_LABEL_LENGTH: usize = 63;
+
+// simulate Kubernetes HostAlias
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HostAlias {
+    pub ip: String,
+    #[serde(default)]
+    pub hostnames: Vec<String>,
+}
+
+// a DNS-1123 label: lowercase alphanumerics and '-', alphanumeric at both ends
+fn is_dns_label(name: &str) -> bool {
+    let alphanumeric = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit();
+    !name.is_empty()
+        && name.len() <= MAX_LABEL_LENGTH
+        && name.chars().all(|c| alphanumeric(c) || c == '-')
+        && name.starts_with(alphanumeric)
+        && name.ends_with(alphanumeric)
+}
+
+impl PodSpec {
+    // the hostname of the pod called pod_name
+    pub fn hostname(&self, pod_name: &str) -> String {
+        if let Some(hostname) = &self.hostname {
+            return hostname.clone();
+        }
+        let mut hostname = pod_name.chars().take(MAX_LABEL_LENGTH).collect::<String>();
+        // the cut may leave a '-' or '.' at the end
+        while hostname.ends_with(['-', '.']) {
+            hostname.pop();
+        }
+        hostname
+    }
+
+    // the fully qualified name of the pod, only with a subdomain
+    pub fn fqdn(&self, pod_name: &str, namespace: &str) -> Option<String> {
+        let subdomain = self.subdomain.as_ref()?;
+        let domain = dns::cluster_dns().map(|(_, domain)| domain).unwrap_or(dns::DEFAULT_CLUSTER_DOMAIN);
+        Some(format!("{}.{}.{}.svc.{}", self.hostname(pod_name), subdomain, namespace, domain))
+    }
+
+    pub fn validate_hostname(&self) -> Result<()> {
+        for (field, name) in [("hostname", &self.hostname), ("subdomain", &self.subdomain)] {
+            if let Some(name) = name
+                && !is_dns_label(name)
+            {
+                return Err(anyhow!("{} {:?} must be a DNS label of at most {} characters", field, name, MAX_LABEL_LENGTH));
+            }
+        }
+        for alias in &self.host_aliases {
+            if alias.ip.parse::<IpAddr>().is_err() {
+                return Err(anyhow!("hostAliases: invalid ip {:?}", alias.ip));
+            }
+            if alias.hostnames.iter().any(|hostname| hostname.is_empty() || hostname.contains(char::is_whitespace)) {
+                return Err(anyhow!("hostAliases: invalid hostnames for {}", alias.ip));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn render_aliases(aliases: &[HostAlias]) -> String {
+    if aliases.is_empty() {
+        return String::new();
+    }
+    let mut contents = "\n# Entries added by HostAliases.\n".to_string();
+    for alias in aliases.iter().filter(|alias| !alias.hostnames.is_empty()) {
+        contents.push_str(&format!("{}\t{}\n", alias.ip, alias.hostnames.join("\t")));
+    }
+    contents
+}
+
+// the hosts file of a pod with its own network
+pub fn render(hostname: &str, fqdn: Option<&str>, pod_ip: Option<IpAddr>, aliases: &[HostAlias]) -> String {
+    let mut contents = "# Kubernetes-managed hosts file.\n\
+        127.0.0.1\tlocalhost\n\
+        ::1\tlocalhost ip6-localhost ip6-loopback\n\
+        fe00::0\tip6-localnet\n\
+        fe00::0\tip6-mcastprefix\n\
+        fe00::1\tip6-allnodes\n\
+        fe00::2\tip6-allrouters\n"
+        .to_string();
+    if let Some(ip) = pod_ip {
+        match fqdn {
+            Some(fqdn) => contents.push_str(&format!("{}\t{}\t{}\n", ip, fqdn, hostname)),
+            None => contents.push_str(&format!("{}\t{}\n", ip, hostname)),
+        }
+    }
+    contents + &render_aliases(aliases)
+}
+
+// the hosts file of a pod with hostNetwork, the node's one
+pub fn render_host_network(node_hosts: &str, aliases: &[HostAlias]) -> String {
+    let mut contents = node_hosts.to_string();
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    contents + &render_aliases(aliases)
+}
+
+// set the hostname of the sandbox whose pause container runs as pid
+pub fn set_hostname(pid: i32, hostname: &str) -> Result<()> {
+    let namespace = format!("/proc/{}/ns/uts", pid);
+    // a pause container without a UTS namespace of its own would rename the node
+    if fs::metadata(&namespace)?.ino() == fs::metadata("/proc/self/ns/uts")?.ino() {
+        return Err(anyhow!("the sandbox shares the UTS namespace of the node"));
+    }
+    let namespace = File::open(&namespace)?;
+    let hostname = hostname.to_string();
+    // setns only moves the calling thread, so a thread of its own does it
+    thread::spawn(move || -> Result<()> {
+        setns(&namespace, CloneFlags::CLONE_NEWUTS)?;
+        sethostname(&hostname)?;
+        Ok(())
+    })
+    .join()
+    .map_err(|_| anyhow!("setting the hostname panicked"))?
+}
+
+impl TaskRunner {
+    // where the hosts file of the sandbox is, written when write is set
+    pub fn etc_hosts(&self, pod_sandbox_id: &str, write: bool) -> Result<PathBuf> {
+        let root_path = rootpath::determine(None)?;
+        let dir = sandbox_dir(&root_path, pod_sandbox_id);
+        let path = dir.join("hosts");
+        if !write {
+            return Ok(path);
+        }
+        let spec = &self.task.spec;
+        let contents = if spec.host_network {
+            render_host_network(&fs::read_to_string(HOST_HOSTS).unwrap_or_default(), &spec.host_aliases)
+        } else {
+            let metadata = &self.task.metadata;
+            let fqdn = spec.fqdn(&metadata.name, &metadata.namespace);
+            render(&spec.hostname(&metadata.name), fqdn.as_deref(), self.pod_ip(pod_sandbox_id), &spec.host_aliases)
+        };
+        fs::create_dir_all(&dir)?;
+        fs::write(&path, contents)?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::task::PodTask;
+
+    #[test]
+    fn test_render() {
+        let task: PodTask = serde_yaml::from_str(
+            "apiVersion: v1\nkind: Pod\nmetadata:\n  name: db-7d9f\nspec:\n  hostname: db-0\n  subdomain: db\n  hostAliases:\n    - ip: 10.1.2.3\n      hostnames: [legacy.corp.example, legacy]\n",
+        )
+        .unwrap();
+        let spec = &task.spec;
+        spec.validate_hostname().unwrap();
+        let fqdn = spec.fqdn("db-7d9f", "default").unwrap();
+        assert_eq!(fqdn, "db-0.db.default.svc.cluster.local");
+        let hosts = render(&spec.hostname("db-7d9f"), Some(&fqdn), "10.244.0.5".parse().ok(), &spec.host_aliases);
+        assert!(hosts.starts_with("# Kubernetes-managed hosts file.\n127.0.0.1\tlocalhost\n"));
+        assert!(hosts.ends_with(
+            "10.244.0.5\tdb-0.db.default.svc.cluster.local\tdb-0\n\n# Entries added by HostAliases.\n10.1.2.3\tlegacy.corp.example\tlegacy\n"
+        ));
+        assert_eq!(
+            render_host_network("127.0.0.1 localhost", &spec.host_aliases),
+            "127.0.0.1 localhost\n\n# Entries added by HostAliases.\n10.1.2.3\tlegacy.corp.example\tlegacy\n"
+        );
+
+        let invalid: PodTask = serde_yaml::from_str("apiVersion: v1\nkind: Pod\nmetadata:\n  name: x\nspec:\n  subdomain: Db\n").unwrap();
+        assert!(invalid.spec.validate_hostname().is_err());
+        // cut to 63 characters, without the '-' left at the end
+        assert_eq!(invalid.spec.hostname(&format!("{}-abc", "a".repeat(62))), "a".repeat(62));
+    }
+}
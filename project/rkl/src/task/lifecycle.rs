@@ -0,0 +1,263 @@
+use std::net::{IpAddr, Ipv4Addr};
+use std::thread;
+use std::time::{Duration, Instant};
+use anyhow::{Result, anyhow};
+use libcontainer::container::ContainerStatus;
+use liboci_cli::Kill;
+use serde::{Deserialize, Serialize};
+use crate::commands::{exec, kill, load_container};
+use crate::cri::cri::{
+    ExecSyncRequest, ExecSyncResponse, PodSandboxStatusRequest, StopContainerRequest, StopContainerResponse,
+};
+use crate::cri::debug;
+use crate::events;
+use crate::ratelimit;
+use crate::rootpath;
+use crate::task::cni;
+use crate::task::probe::HttpGetAction;
+use crate::task::task::{ContainerSpec, TaskRunner};
+use tracing::{info, warn};
+
+// Graceful termination of the containers of a pod, like the kubelet does it:
+// every container first runs its preStop hook, a command executed in the
+// container or an httpGet against the pod IP, then gets SIGTERM and is killed
+// once the terminationGracePeriodSeconds of the pod are over. The hook counts
+// against the grace period and a failing hook doesn't keep the container from
+// being stopped. The containers of a pod are stopped all at once, its sidecars
+// after them one by one in reverse order, see sidecar; with a CRI
+// runtime StopContainer is called with what is left of the grace period and
+// the runtime does the escalation.
+// The postStart hook of a container runs right after it started, before the
+// next container of the pod is started. A container whose hook fails is
+// killed and counts as failed to start, the daemon restarts it per the
+// restartPolicy of the pod.
+
+// the kubelet's default
+pub const DEFAULT_TERMINATION_GRACE_PERIOD: u64 = 30;
+
+// how often the built-in runtime looks whether a container stopped
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// simulate Kubernetes Lifecycle
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Lifecycle {
+    #[serde(rename = "postStart", default, skip_serializing_if = "Option::is_none")]
+    pub post_start: Option<LifecycleHandler>,
+    #[serde(rename = "preStop", default, skip_serializing_if = "Option::is_none")]
+    pub pre_stop: Option<LifecycleHandler>,
+}
+
+// simulate Kubernetes LifecycleHandler
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LifecycleHandler {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exec: Option<ExecAction>,
+    #[serde(rename = "httpGet", default, skip_serializing_if = "Option::is_none")]
+    pub http_get: Option<HttpGetAction>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecAction {
+    #[serde(default)]
+    pub command: Vec<String>,
+}
+
+impl LifecycleHandler {
+    pub fn validate(&self) -> Result<()> {
+        match (&self.exec, &self.http_get) {
+            (Some(_), Some(_)) => Err(anyhow!("a lifecycle hook can only have one of exec and httpGet")),
+            (None, None) => Err(anyhow!("a lifecycle hook needs exec or httpGet")),
+            (Some(exec), None) if exec.command.is_empty() => Err(anyhow!("the exec of a lifecycle hook needs a command")),
+            (None, Some(http)) if http.scheme != "HTTP" && http.scheme != "HTTPS" => {
+                Err(anyhow!("unsupported httpGet scheme {}", http.scheme))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl ContainerSpec {
+    pub fn post_start(&self) -> Option<&LifecycleHandler> {
+        self.lifecycle.as_ref().and_then(|lifecycle| lifecycle.post_start.as_ref())
+    }
+
+    pub fn pre_stop(&self) -> Option<&LifecycleHandler> {
+        self.lifecycle.as_ref().and_then(|lifecycle| lifecycle.pre_stop.as_ref())
+    }
+}
+
+// what is left until the deadline, nothing once it passed
+fn remaining(deadline: Instant) -> Duration {
+    deadline.saturating_duration_since(Instant::now())
+}
+
+impl TaskRunner {
+    fn grace_period(&self) -> Duration {
+        Duration::from_secs(self.task.spec.termination_grace_period_seconds.unwrap_or(DEFAULT_TERMINATION_GRACE_PERIOD))
+    }
+
+    // run the postStart hook of a container that just started, killing the
+    // container when the hook fails
+    pub fn post_start(&self, pod_sandbox_id: &str, container_name: &str) -> Result<()> {
+        let container = self.task.spec.container(container_name);
+        let Some(post_start) = container.and_then(ContainerSpec::post_start) else {
+            return Ok(());
+        };
+        let ip = self.pod_ip(pod_sandbox_id);
+        let Err(e) = self.run_hook("postStart", container_name, post_start, ip, self.grace_period()) else {
+            return Ok(());
+        };
+        warn!("PostStart hook of container {} failed: {}", container_name, e);
+        self.events().warning(events::FAILED_POST_START_HOOK, &format!("PostStartHook failed: {}", e));
+        self.events().normal(events::KILLING, &format!("Container {} failed post-start hook, will be killed", container_name));
+        let timeout = self.grace_period().as_secs() as i64;
+        if let Err(e) = self.stop_container(StopContainerRequest { container_id: container_name.to_string(), timeout }) {
+            warn!("Failed to stop container {}: {}", container_name, e);
+        }
+        Err(anyhow!("PostStartHook failed: {}", e))
+    }
+
+    // stop the containers of the pod gracefully, grace_period overrides the
+    // terminationGracePeriodSeconds of the pod and 0 kills them right away
+    pub fn terminate(&self, pod_sandbox_id: &str, container_names: &[String], grace_period: Option<u64>) {
+        let grace_period = grace_period.map(Duration::from_secs).unwrap_or_else(|| self.grace_period());
+        let deadline = Instant::now() + grace_period;
+        let ip = self.pod_ip(pod_sandbox_id);
+        let (mut sidecars, containers): (Vec<&String>, Vec<&String>) =
+            container_names.iter().partition(|name| self.task.spec.is_sidecar(name));
+        thread::scope(|scope| {
+            for name in containers {
+                scope.spawn(move || self.terminate_container(name, ip, deadline));
+            }
+        });
+        // the sidecars serve the containers until they stopped
+        sidecars.sort_by_key(|name| self.task.spec.sidecars().position(|sidecar| &sidecar.name == *name));
+        for name in sidecars.into_iter().rev() {
+            self.terminate_container(name, ip, deadline);
+        }
+    }
+
+    fn terminate_container(&self, name: &str, ip: Option<IpAddr>, deadline: Instant) {
+        let container = self.task.spec.container(name);
+        let pre_stop = container.and_then(ContainerSpec::pre_stop);
+        if let Some(pre_stop) = pre_stop
+            && !remaining(deadline).is_zero()
+            && let Err(e) = self.run_hook("preStop", name, pre_stop, ip, remaining(deadline))
+        {
+            warn!("PreStop hook of container {} failed: {}", name, e);
+            self.events().warning(events::FAILED_PRE_STOP_HOOK, &format!("PreStopHook failed: {}", e));
+        }
+        self.events().normal(events::KILLING, &format!("Stopping container {}", name));
+        let timeout = remaining(deadline).as_secs() as i64;
+        if let Err(e) = self.stop_container(StopContainerRequest { container_id: name.to_string(), timeout }) {
+            warn!("Failed to stop container {}: {}", name, e);
+        }
+    }
+
+    // the address httpGet hooks and sidecar probes go to
+    pub fn pod_ip(&self, pod_sandbox_id: &str) -> Option<IpAddr> {
+        if self.task.spec.host_network {
+            return Some(IpAddr::V4(Ipv4Addr::LOCALHOST));
+        }
+        if let Some(ip) = self.network_status.as_ref().and_then(|status| status.ip.parse().ok()) {
+            return Some(ip);
+        }
+        let root_path = rootpath::determine(None).ok()?;
+        let ip = cni::pod_ip(&root_path, pod_sandbox_id).or_else(|| {
+            let backend = self.backend.as_ref()?;
+            let request = PodSandboxStatusRequest { pod_sandbox_id: pod_sandbox_id.to_string(), verbose: false };
+            backend.pod_sandbox_status(request).ok()?.status?.network.map(|network| network.ip)
+        });
+        ip.and_then(|ip| ip.parse().ok())
+    }
+
+    fn run_hook(&self, kind: &str, container_name: &str, hook: &LifecycleHandler, ip: Option<IpAddr>, timeout: Duration) -> Result<()> {
+        hook.validate()?;
+        if let Some(http) = &hook.http_get {
+            let ip = ip.ok_or_else(|| anyhow!("the pod has no IP"))?;
+            return http.get(ip, timeout);
+        }
+        let command = hook.exec.as_ref().map(|exec| exec.command.clone()).unwrap_or_default();
+        info!("Running {} hook of container {}: {}", kind, container_name, command.join(" "));
+        // a timeout of 0 would be no timeout at all
+        let timeout = timeout.as_secs().max(1) as i64;
+        let response = self.exec_sync(ExecSyncRequest { container_id: container_name.to_string(), cmd: command, timeout })?;
+        if response.exit_code != 0 {
+            let stderr = String::from_utf8_lossy(&response.stderr);
+            return Err(anyhow!("command exited with {}: {}", response.exit_code, stderr.trim()));
+        }
+        Ok(())
+    }
+
+    pub fn stop_container(&self, request: StopContainerRequest) -> Result<StopContainerResponse> {
+        debug::traced("StopContainer", request, |request| match &self.backend {
+            Some(backend) => {
+                let _permit = ratelimit::limiter().acquire(&self.task.metadata.name);
+                backend.stop_container(request)
+            }
+            None => self.stop_container_inner(request),
+        })
+    }
+
+    // SIGTERM, and SIGKILL when the container is still running after the timeout
+    fn stop_container_inner(&self, request: StopContainerRequest) -> Result<StopContainerResponse> {
+        let root_path = rootpath::determine(None)?;
+        let container_id = request.container_id;
+        let running = || load_container(&root_path, &container_id).is_ok_and(|c| c.status() == ContainerStatus::Running);
+        let signal = |signal: &str| {
+            let args = Kill { container_id: container_id.clone(), signal: signal.to_string(), all: false };
+            kill::kill(args, root_path.clone())
+        };
+        if !running() {
+            return Ok(StopContainerResponse {});
+        }
+        let deadline = Instant::now() + Duration::from_secs(request.timeout.max(0) as u64);
+        if request.timeout > 0 {
+            signal("SIGTERM").map_err(|e| anyhow!("Failed to stop container {}: {}", container_id, e))?;
+            while running() && !remaining(deadline).is_zero() {
+                thread::sleep(STOP_POLL_INTERVAL);
+            }
+        }
+        if running() {
+            signal("SIGKILL").map_err(|e| anyhow!("Failed to kill container {}: {}", container_id, e))?;
+        }
+        Ok(StopContainerResponse {})
+    }
+
+    pub fn exec_sync(&self, request: ExecSyncRequest) -> Result<ExecSyncResponse> {
+        debug::traced("ExecSync", request, |request| match &self.backend {
+            Some(backend) => backend.exec_sync(request),
+            None => {
+                let root_path = rootpath::determine(None)?;
+                let timeout = Duration::from_secs(request.timeout.max(1) as u64);
+                // an exit code reaped by somebody else is taken for a success
+                let exit_code = exec::exec(&request.container_id, request.cmd, root_path, timeout)?.unwrap_or(0);
+                Ok(ExecSyncResponse { exit_code, ..Default::default() })
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lifecycle_handler() {
+        let lifecycle: Lifecycle = serde_yaml::from_str("preStop:\n  exec:\n    command: [nginx, -s, quit]\n").unwrap();
+        let pre_stop = lifecycle.pre_stop.unwrap();
+        pre_stop.validate().unwrap();
+        assert_eq!(pre_stop.exec.unwrap().command, ["nginx", "-s", "quit"]);
+
+        let lifecycle: Lifecycle = serde_yaml::from_str(
+            "postStart:\n  exec:\n    command: [sh, -c, 'echo started > /ready']\npreStop:\n  httpGet:\n    path: /shutdown\n    port: 8080\n",
+        )
+        .unwrap();
+        lifecycle.post_start.unwrap().validate().unwrap();
+        lifecycle.pre_stop.unwrap().validate().unwrap();
+        let empty: LifecycleHandler = serde_yaml::from_str("{}").unwrap();
+        assert!(empty.validate().is_err());
+        let no_command: LifecycleHandler = serde_yaml::from_str("exec: {}").unwrap();
+        assert!(no_command.validate().is_err());
+    }
+}
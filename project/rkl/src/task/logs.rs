@@ -0,0 +1,107 @@
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use anyhow::{Result, anyhow};
+use crate::cri::cri::{ContainerConfig, PodSandboxConfig};
+use crate::quantity::Quantity;
+
+// Container logs live where the kubelet puts them:
+// <pod log dir>/<namespace>_<pod>_<uid>/<container>/<restart>.log. The CRI
+// runtime of --runtime-endpoint writes them itself; the built-in and OCI
+// runtimes hand the log file to the container as its stdout and stderr, in
+// append mode, unless the container has stdin or a tty, whose io goes to the
+// shim instead. Logs bigger than --container-log-max-size are rotated by
+// `rkl daemon`, keeping --container-log-max-files files per container (see
+// daemon/logs.rs).
+
+pub const DEFAULT_POD_LOG_DIR: &str = "/var/log/pods";
+pub const DEFAULT_CONTAINER_LOG_MAX_SIZE: &str = "10Mi";
+pub const DEFAULT_CONTAINER_LOG_MAX_FILES: usize = 5;
+
+#[derive(Debug, Clone)]
+pub struct LogConfig {
+    pub pod_log_dir: PathBuf,
+    pub max_size: Quantity,
+    // the current file included
+    pub max_files: usize,
+}
+
+static CONFIG: OnceLock<LogConfig> = OnceLock::new();
+
+// set once at startup from --pod-log-dir and --container-log-max-*
+pub fn init(config: LogConfig) {
+    let _ = CONFIG.set(config);
+}
+
+pub fn config() -> LogConfig {
+    CONFIG.get().cloned().unwrap_or_else(|| LogConfig {
+        pod_log_dir: PathBuf::from(DEFAULT_POD_LOG_DIR),
+        max_size: Quantity::parse(DEFAULT_CONTAINER_LOG_MAX_SIZE).unwrap(),
+        max_files: DEFAULT_CONTAINER_LOG_MAX_FILES,
+    })
+}
+
+// the log directory of a sandbox of a pod
+pub fn pod_log_dir(namespace: &str, pod_name: &str, uid: &str) -> PathBuf {
+    config().pod_log_dir.join(format!("{}_{}_{}", namespace, pod_name, uid))
+}
+
+// the log file of a container of the sandbox, created with its directory;
+// None when the sandbox or the container has no log path
+pub fn open_container_log(sandbox_config: &PodSandboxConfig, config: &ContainerConfig) -> Result<Option<File>> {
+    if sandbox_config.log_directory.is_empty() || config.log_path.is_empty() {
+        return Ok(None);
+    }
+    let path = Path::new(&sandbox_config.log_directory).join(&config.log_path);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| anyhow!("Failed to open log {}: {}", path.display(), e))?;
+    Ok(Some(file))
+}
+
+// the log directory of the latest sandbox of the pod
+fn latest_pod_log_dir(pod_name: &str) -> Option<PathBuf> {
+    fs::read_dir(config().pod_log_dir)
+        .ok()?
+        .flatten()
+        .filter(|entry| entry.file_name().to_string_lossy().split('_').nth(1) == Some(pod_name))
+        .max_by_key(|entry| entry.metadata().and_then(|metadata| metadata.modified()).ok())
+        .map(|entry| entry.path())
+}
+
+// the current log of a container of the pod, the only one when container is empty
+pub fn read_container_log(pod_name: &str, container: &str) -> Result<Vec<u8>> {
+    let dir = latest_pod_log_dir(pod_name).ok_or_else(|| anyhow!("Pod {} has no logs", pod_name))?;
+    let container_dir = if container.is_empty() {
+        let mut containers: Vec<PathBuf> = fs::read_dir(&dir)?.flatten().map(|entry| entry.path()).collect();
+        if containers.len() != 1 {
+            return Err(anyhow!("Pod {} has {} containers with logs, one has to be chosen", pod_name, containers.len()));
+        }
+        containers.remove(0)
+    } else if container.contains('/') || container == ".." {
+        return Err(anyhow!("invalid container name {}", container));
+    } else {
+        dir.join(container)
+    };
+    let log = container_dir.join("0.log");
+    fs::read(&log).map_err(|e| anyhow!("Failed to read {}: {}", log.display(), e))
+}
+
+// the log directories of every sandbox the pod had
+pub fn remove_pod_logs(pod_name: &str) -> Result<()> {
+    let Ok(entries) = fs::read_dir(config().pod_log_dir) else {
+        return Ok(());
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.split('_').nth(1) == Some(pod_name) {
+            fs::remove_dir_all(entry.path())?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,159 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::sync::OnceLock;
+use anyhow::{Result, anyhow};
+use libcgroups::common::{CgroupSetup, get_cgroup_setup};
+use crate::cri::cri::HugepageLimit;
+use crate::daemon::resources::memory_total;
+use crate::quantity::Quantity;
+use crate::task::qos::{self, QosClass};
+use crate::task::task::{ContainerSpec, PodSpec};
+
+// Hugepages and swap of containers, like the kubelet handles them. A
+// hugepages-<size> resource limits the huge pages of that size a container
+// may use, e.g.
+//
+//   resources:
+//     limits:
+//       hugepages-2Mi: 100Mi
+//       memory: 256Mi
+//
+// through the hugetlb cgroup of the container, and the pod cgroup is limited
+// to the sum of its containers. Like in Kubernetes hugepages can't be
+// overcommitted: a container sets a limit, and a request only equal to it.
+// With --swap-behavior the swap of the containers is set on cgroup v2:
+// no-swap gives them none, limited-swap gives the containers of Burstable pods
+// a share of the swap of the node as large as the share of its memory they
+// request, and none to the others, Guaranteed and BestEffort pods and
+// containers whose request is their limit. Without it the swap of the
+// runtime is left alone.
+
+pub const HUGEPAGES_PREFIX: &str = "hugepages-";
+
+pub const SWAP_MAX: &str = "memory.swap.max";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SwapBehavior {
+    NoSwap,
+    LimitedSwap,
+}
+
+static SWAP_BEHAVIOR: OnceLock<Option<SwapBehavior>> = OnceLock::new();
+
+// set once at startup from --swap-behavior
+pub fn init(swap_behavior: Option<SwapBehavior>) {
+    let _ = SWAP_BEHAVIOR.set(swap_behavior);
+}
+
+// the name of a page size in the hugetlb files of the kernel, e.g. 2MB for hugepages-2Mi
+pub fn page_size(resource: &str) -> Option<Result<String>> {
+    let size = resource.strip_prefix(HUGEPAGES_PREFIX)?;
+    let invalid = || anyhow!("invalid hugepages resource {}, expected e.g. hugepages-2Mi or hugepages-1Gi", resource);
+    let bytes = match Quantity::parse(size) {
+        Ok(quantity) if quantity.value() > 0 => quantity.value(),
+        _ => return Some(Err(invalid())),
+    };
+    let name = [("GB", 1 << 30), ("MB", 1 << 20), ("KB", 1 << 10)]
+        .iter()
+        .find(|(_, unit)| bytes % unit == 0)
+        .map(|(suffix, unit)| format!("{}{}", bytes / unit, suffix));
+    Some(name.ok_or_else(invalid))
+}
+
+// the hugetlb limits of a container, by page size
+pub fn hugepage_limits(container: &ContainerSpec) -> Result<Vec<HugepageLimit>> {
+    let resources = &container.resources;
+    if let Some(resource) = resources.requests.keys().find(|resource| resource.starts_with(HUGEPAGES_PREFIX) && !resources.limits.contains_key(*resource)) {
+        return Err(anyhow!("{} must have a limit", resource));
+    }
+    let mut limits = BTreeMap::new();
+    for (resource, limit) in &resources.limits {
+        let Some(page_size) = page_size(resource) else {
+            continue;
+        };
+        if resources.requests.get(resource).is_some_and(|request| request.value() != limit.value()) {
+            return Err(anyhow!("the {} request must equal its limit", resource));
+        }
+        limits.insert(page_size?, limit.value().max(0) as u64);
+    }
+    Ok(limits.into_iter().map(|(page_size, limit)| HugepageLimit { page_size, limit }).collect())
+}
+
+// the sum of the limits of the containers, for the pod cgroup
+pub fn pod_hugepage_limits(spec: &PodSpec) -> Result<Vec<HugepageLimit>> {
+    let mut limits: Vec<HugepageLimit> = Vec::new();
+    for container in spec.running_containers() {
+        for limit in hugepage_limits(container).map_err(|e| anyhow!("Container {}: {}", container.name, e))? {
+            match limits.iter_mut().find(|existing| existing.page_size == limit.page_size) {
+                Some(existing) => existing.limit += limit.limit,
+                None => limits.push(limit),
+            }
+        }
+    }
+    Ok(limits)
+}
+
+fn swap_total() -> Result<i64> {
+    let meminfo = fs::read_to_string("/proc/meminfo")?;
+    meminfo
+        .lines()
+        .find_map(|line| line.strip_prefix("SwapTotal:"))
+        .and_then(|kb| kb.trim().trim_end_matches("kB").trim().parse::<i64>().ok())
+        .map(|kb| kb * 1024)
+        .ok_or_else(|| anyhow!("SwapTotal not found in /proc/meminfo"))
+}
+
+// the swap a container gets on a node with that much memory and swap
+pub fn swap_limit(behavior: SwapBehavior, spec: &PodSpec, container: &ContainerSpec, memory_capacity: i64, swap_capacity: i64) -> i64 {
+    if behavior == SwapBehavior::NoSwap || qos::qos_class(spec) != QosClass::Burstable || memory_capacity <= 0 {
+        return 0;
+    }
+    let request = qos::request(container, "memory").map_or(0, Quantity::value);
+    let limit = container.resources.limits.get("memory").map(Quantity::value);
+    if limit == Some(request) {
+        return 0;
+    }
+    (request as i128 * swap_capacity as i128 / memory_capacity as i128) as i64
+}
+
+// the memory.swap.max of a container, None when --swap-behavior isn't set or
+// the node isn't on cgroup v2
+pub fn swap_max(spec: &PodSpec, container: &ContainerSpec) -> Result<Option<i64>> {
+    let Some(behavior) = SWAP_BEHAVIOR.get().copied().flatten() else {
+        return Ok(None);
+    };
+    if !matches!(get_cgroup_setup()?, CgroupSetup::Unified) {
+        return Ok(None);
+    }
+    Ok(Some(swap_limit(behavior, spec, container, memory_total()?, swap_total()?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::qos::tests::pod;
+
+    #[test]
+    fn test_hugepages_and_swap() {
+        let spec = pod(
+            "    - name: a\n      image: /bundle/a\n      resources:\n        requests:\n          memory: 1Gi\n        limits:\n          hugepages-2Mi: 100Mi\n          hugepages-1Gi: 2Gi\n\
+             \x20   - name: b\n      image: /bundle/b\n      resources:\n        limits:\n          hugepages-2Mi: 50Mi\n          memory: 512Mi\n",
+        );
+        let limits = hugepage_limits(&spec.containers[0]).unwrap();
+        let limits: Vec<(&str, u64)> = limits.iter().map(|limit| (limit.page_size.as_str(), limit.limit)).collect();
+        assert_eq!(limits, [("1GB", 2 << 30), ("2MB", 100 << 20)]);
+        let pod_limits = pod_hugepage_limits(&spec).unwrap();
+        assert_eq!(pod_limits.iter().find(|limit| limit.page_size == "2MB").unwrap().limit, 150 << 20);
+        assert!(page_size("hugepages-1500").unwrap().is_err());
+
+        let unequal = pod("    - name: a\n      image: /bundle/a\n      resources:\n        requests:\n          hugepages-2Mi: 10Mi\n        limits:\n          hugepages-2Mi: 20Mi\n");
+        assert_eq!(hugepage_limits(&unequal.containers[0]).unwrap_err().to_string(), "the hugepages-2Mi request must equal its limit");
+
+        // a quarter of the memory of the node, a quarter of its swap
+        let gib = 1 << 30;
+        assert_eq!(swap_limit(SwapBehavior::LimitedSwap, &spec, &spec.containers[0], 4 * gib, 2 * gib), gib / 2);
+        assert_eq!(swap_limit(SwapBehavior::NoSwap, &spec, &spec.containers[0], 4 * gib, 2 * gib), 0);
+        // the request of b is its limit
+        assert_eq!(swap_limit(SwapBehavior::LimitedSwap, &spec, &spec.containers[1], 4 * gib, 2 * gib), 0);
+    }
+}
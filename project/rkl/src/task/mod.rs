@@ -1 +1,19 @@
-pub mod task;
\ No newline at end of file
+pub mod task;
+pub mod dns;
+pub mod hosts;
+pub mod network;
+pub mod downward;
+pub mod cni;
+pub mod bridge;
+pub mod probe;
+pub mod lifecycle;
+pub mod sidecar;
+pub mod ephemeral;
+pub mod termination;
+pub mod qos;
+pub mod memory;
+pub mod cpumanager;
+pub mod windows;
+pub mod sysctl;
+pub mod ulimit;
+pub mod logs;
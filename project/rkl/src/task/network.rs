@@ -0,0 +1,155 @@
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant};
+use anyhow::{Result, anyhow};
+use crate::cri::cri::PodSandboxNetworkStatus;
+
+// Containers are only created once the sandbox network is ready: the network
+// plugin reported its result (the pod IP), the IP is assigned inside the pause
+// container's network namespace and a default route is present there.
+// Sandboxes on the host network, or without a network plugin (loopback only),
+// have nothing to wait for.
+
+const DEFAULT_READY_TIMEOUT: Duration = Duration::from_secs(30);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+static READY_TIMEOUT: OnceLock<Duration> = OnceLock::new();
+
+// set once at startup from --network-ready-timeout
+pub fn init(ready_timeout: Duration) {
+    let _ = READY_TIMEOUT.set(ready_timeout);
+}
+
+pub fn ready_timeout() -> Duration {
+    READY_TIMEOUT.get().copied().unwrap_or(DEFAULT_READY_TIMEOUT)
+}
+
+// wait until the network of the sandbox whose pause container is pid is ready
+pub fn wait_ready(pid: i32, status: &PodSandboxNetworkStatus, timeout: Duration) -> Result<()> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let reason = match check_ready(pid, status) {
+            Ok(()) => return Ok(()),
+            Err(reason) => reason,
+        };
+        if Instant::now() >= deadline {
+            return Err(anyhow!("network not ready after {}s: {}", timeout.as_secs(), reason));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn check_ready(pid: i32, status: &PodSandboxNetworkStatus) -> Result<(), String> {
+    if status.ip.is_empty() {
+        return Err("no IP assigned by the network plugin".to_string());
+    }
+    let ip: IpAddr = status.ip.parse().map_err(|_| format!("invalid pod IP {}", status.ip))?;
+
+    let assigned = match ip {
+        IpAddr::V4(ip) => read_proc(pid, "fib_trie").map(|t| local_ipv4_addresses(&t).contains(&ip)),
+        IpAddr::V6(ip) => read_proc(pid, "if_inet6").map(|t| ipv6_addresses(&t).contains(&ip)),
+    }?;
+    if !assigned {
+        return Err(format!("IP {} is not assigned in the sandbox", ip));
+    }
+
+    let routed = match ip {
+        IpAddr::V4(_) => read_proc(pid, "route").map(|t| has_default_ipv4_route(&t)),
+        IpAddr::V6(_) => read_proc(pid, "ipv6_route").map(|t| has_default_ipv6_route(&t)),
+    }?;
+    if !routed {
+        return Err("no default route in the sandbox".to_string());
+    }
+    Ok(())
+}
+
+// /proc/<pid>/net shows the network namespace of the process
+fn read_proc(pid: i32, name: &str) -> Result<String, String> {
+    let path = format!("/proc/{}/net/{}", pid, name);
+    fs::read_to_string(&path).map_err(|e| format!("failed to read {}: {}", path, e))
+}
+
+// addresses of the namespace are the "/32 host LOCAL" leaves of the fib trie
+fn local_ipv4_addresses(fib_trie: &str) -> Vec<Ipv4Addr> {
+    let mut addresses = Vec::new();
+    let mut last_leaf = None;
+    for line in fib_trie.lines() {
+        let line = line.trim();
+        if let Some(leaf) = line.strip_prefix("|-- ") {
+            last_leaf = leaf.parse::<Ipv4Addr>().ok();
+        } else if line.starts_with("/32 host LOCAL")
+            && let Some(ip) = last_leaf
+            && !ip.is_loopback()
+            && !addresses.contains(&ip)
+        {
+            addresses.push(ip);
+        }
+    }
+    addresses
+}
+
+fn ipv6_addresses(if_inet6: &str) -> Vec<Ipv6Addr> {
+    if_inet6
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .filter_map(|hex| u128::from_str_radix(hex, 16).ok())
+        .map(Ipv6Addr::from)
+        .filter(|ip| !ip.is_loopback())
+        .collect()
+}
+
+fn has_default_ipv4_route(route: &str) -> bool {
+    route.lines().skip(1).any(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        // Iface Destination Gateway Flags RefCnt Use Metric Mask ...
+        fields.len() > 7 && fields[1] == "00000000" && fields[7] == "00000000"
+    })
+}
+
+fn has_default_ipv6_route(ipv6_route: &str) -> bool {
+    ipv6_route.lines().any(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        // the kernel keeps an unreachable default route on lo
+        fields.len() > 9 && fields[0].chars().all(|c| c == '0') && fields[1] == "00" && fields[9] != "lo"
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_ipv4_addresses() {
+        let fib_trie = "\
+Main:
+  +-- 0.0.0.0/0 3 0 5
+     |-- 0.0.0.0
+        /0 universe UNICAST
+     +-- 10.88.0.0/16 2 0 2
+        |-- 10.88.0.0
+           /16 link UNICAST
+        |-- 10.88.0.7
+           /32 host LOCAL
+     |-- 127.0.0.1
+        /32 host LOCAL
+";
+        assert_eq!(local_ipv4_addresses(fib_trie), vec![Ipv4Addr::new(10, 88, 0, 7)]);
+    }
+
+    #[test]
+    fn test_default_routes() {
+        let route = "\
+Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT
+eth0\t0000580A\t00000000\t0001\t0\t0\t0\t0000FFFF\t0\t0\t0
+";
+        assert!(!has_default_ipv4_route(route));
+        let route = format!("{}eth0\t00000000\t0100580A\t0003\t0\t0\t0\t00000000\t0\t0\t0\n", route);
+        assert!(has_default_ipv4_route(&route));
+
+        let unreachable = "00000000000000000000000000000000 00 00000000000000000000000000000000 00 \
+00000000000000000000000000000000 ffffffff 00000001 00000000 00200200       lo\n";
+        assert!(!has_default_ipv6_route(unreachable));
+    }
+}
@@ -0,0 +1,197 @@
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::time::{Duration, Instant};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+// Readiness probes of containers, run by `rkl daemon` against the pod IP: a
+// tcpSocket probe succeeds when the port accepts a connection, an httpGet
+// probe when the response status is below 400. A container is ready once
+// successThreshold probes in a row succeeded and stops being ready after
+// failureThreshold failures in a row; without a probe it is ready while it
+// runs. Only ready pods are endpoints of services.
+
+// simulate Kubernetes Probe
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Probe {
+    #[serde(rename = "httpGet", default, skip_serializing_if = "Option::is_none")]
+    pub http_get: Option<HttpGetAction>,
+    #[serde(rename = "tcpSocket", default, skip_serializing_if = "Option::is_none")]
+    pub tcp_socket: Option<TcpSocketAction>,
+    #[serde(rename = "initialDelaySeconds", default)]
+    pub initial_delay_seconds: u64,
+    #[serde(rename = "periodSeconds", default = "default_period")]
+    pub period_seconds: u64,
+    #[serde(rename = "timeoutSeconds", default = "default_timeout")]
+    pub timeout_seconds: u64,
+    #[serde(rename = "successThreshold", default = "default_success_threshold")]
+    pub success_threshold: u32,
+    #[serde(rename = "failureThreshold", default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpGetAction {
+    #[serde(default = "default_path")]
+    pub path: String,
+    pub port: u16,
+    // HTTP or HTTPS
+    #[serde(default = "default_scheme")]
+    pub scheme: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TcpSocketAction {
+    pub port: u16,
+}
+
+fn default_period() -> u64 {
+    10
+}
+
+fn default_timeout() -> u64 {
+    1
+}
+
+fn default_success_threshold() -> u32 {
+    1
+}
+
+fn default_failure_threshold() -> u32 {
+    3
+}
+
+fn default_path() -> String {
+    "/".to_string()
+}
+
+fn default_scheme() -> String {
+    "HTTP".to_string()
+}
+
+impl Probe {
+    pub fn validate(&self) -> Result<()> {
+        match (&self.http_get, &self.tcp_socket) {
+            (Some(_), Some(_)) => Err(anyhow!("a probe can only have one of httpGet and tcpSocket")),
+            (None, None) => Err(anyhow!("a probe needs httpGet or tcpSocket")),
+            (Some(http), None) if http.scheme != "HTTP" && http.scheme != "HTTPS" => {
+                Err(anyhow!("unsupported httpGet scheme {}", http.scheme))
+            }
+            _ if self.period_seconds == 0 || self.timeout_seconds == 0 => {
+                Err(anyhow!("periodSeconds and timeoutSeconds must be at least 1"))
+            }
+            _ if self.success_threshold == 0 || self.failure_threshold == 0 => {
+                Err(anyhow!("successThreshold and failureThreshold must be at least 1"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // probe the pod at ip once
+    pub fn run(&self, ip: IpAddr) -> Result<()> {
+        let timeout = Duration::from_secs(self.timeout_seconds);
+        if let Some(tcp) = &self.tcp_socket {
+            TcpStream::connect_timeout(&SocketAddr::new(ip, tcp.port), timeout)
+                .map_err(|e| anyhow!("dial tcp {}:{}: {}", ip, tcp.port, e))?;
+            return Ok(());
+        }
+        let Some(http) = &self.http_get else {
+            return Err(anyhow!("a probe needs httpGet or tcpSocket"));
+        };
+        http.get(ip, timeout)
+    }
+}
+
+impl HttpGetAction {
+    // succeeds when the response status is below 400, also run for lifecycle hooks
+    pub fn get(&self, ip: IpAddr, timeout: Duration) -> Result<()> {
+        let url = format!("{}://{}/{}", self.scheme.to_lowercase(), SocketAddr::new(ip, self.port), self.path.trim_start_matches('/'));
+        match ureq::get(&url).timeout(timeout).call() {
+            Ok(_) => Ok(()),
+            Err(ureq::Error::Status(code, _)) => Err(anyhow!("HTTP probe failed with statuscode: {}", code)),
+            Err(e) => Err(anyhow!("Get {}: {}", url, e)),
+        }
+    }
+}
+
+// the readiness of a container as its probe results make it
+#[derive(Debug, Clone, Default)]
+pub struct Readiness {
+    pub ready: bool,
+    successes: u32,
+    failures: u32,
+    last_probe: Option<Instant>,
+    // why the container isn't ready, from the last failed probe
+    pub reason: Option<String>,
+}
+
+impl Readiness {
+    // whether the probe is due, the container having started at started
+    pub fn due(&self, probe: &Probe, started: Instant, now: Instant) -> bool {
+        if now.duration_since(started) < Duration::from_secs(probe.initial_delay_seconds) {
+            return false;
+        }
+        self.last_probe.is_none_or(|last| now.duration_since(last) >= Duration::from_secs(probe.period_seconds))
+    }
+
+    // take the result of a probe, returning whether the readiness changed
+    pub fn record(&mut self, probe: &Probe, result: Result<()>, now: Instant) -> bool {
+        self.last_probe = Some(now);
+        let was_ready = self.ready;
+        match result {
+            Ok(()) => {
+                self.failures = 0;
+                self.successes += 1;
+                self.reason = None;
+                if self.successes >= probe.success_threshold {
+                    self.ready = true;
+                }
+            }
+            Err(e) => {
+                self.successes = 0;
+                self.failures += 1;
+                self.reason = Some(e.to_string());
+                if self.failures >= probe.failure_threshold {
+                    self.ready = false;
+                }
+            }
+        }
+        self.ready != was_ready
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, TcpListener};
+
+    #[test]
+    fn test_readiness() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let probe: Probe = serde_yaml::from_str(&format!(
+            "tcpSocket:\n  port: {}\nperiodSeconds: 5\nsuccessThreshold: 2\nfailureThreshold: 2\n",
+            port
+        ))
+        .unwrap();
+        probe.validate().unwrap();
+        let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+        let start = Instant::now();
+        let mut readiness = Readiness::default();
+        assert!(readiness.due(&probe, start, start));
+        assert!(!readiness.record(&probe, probe.run(localhost), start));
+        assert!(!readiness.due(&probe, start, start + Duration::from_secs(1)));
+        assert!(readiness.record(&probe, probe.run(localhost), start + Duration::from_secs(5)));
+        assert!(readiness.ready);
+
+        // one failure is tolerated, the second one makes it unready
+        drop(listener);
+        assert!(!readiness.record(&probe, probe.run(localhost), start + Duration::from_secs(10)));
+        assert!(readiness.record(&probe, probe.run(localhost), start + Duration::from_secs(15)));
+        assert!(!readiness.ready);
+        assert!(readiness.reason.as_deref().is_some_and(|reason| reason.starts_with("dial tcp")));
+
+        let both: Probe = serde_yaml::from_str("tcpSocket:\n  port: 80\nhttpGet:\n  port: 80\n").unwrap();
+        assert!(both.validate().is_err());
+    }
+}
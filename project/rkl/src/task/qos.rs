@@ -0,0 +1,414 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{Result, anyhow};
+use libcgroups::common::{CgroupSetup, DEFAULT_CGROUP_ROOT, get_cgroup_setup};
+use libcontainer::oci_spec::runtime::{LinuxCpuBuilder, LinuxHugepageLimitBuilder, LinuxMemoryBuilder, LinuxResourcesBuilder, Spec};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use crate::cri::cri::{LinuxContainerResources, PodSandboxConfig};
+use crate::quantity::Quantity;
+use crate::task::task::{ContainerSpec, PodSpec};
+
+// QoS classes and pod cgroups, laid out like the kubelet's cgroupfs driver does:
+// Guaranteed pods get /kubepods/pod<uid>, the others /kubepods/burstable/pod<uid>
+// and /kubepods/besteffort/pod<uid>. The pod cgroup is the cgroup_parent of the
+// sandbox and enforces the sum of the limits of the containers, each of which
+// gets its own cgroup below it with its requests as shares and its limits as
+// quota. The pod cgroup is recorded in the sandbox directory to be removed with it.
+// The oom_score_adj of the containers follows the class too, so that the
+// kernel kills best effort containers first and guaranteed ones last: -997
+// for guaranteed containers and those of system-node-critical pods, 1000 for
+// best effort ones and between 2 and 999 for burstable ones, the lower the
+// more of the memory of the node they request. A sidecar is killed no sooner
+// than the containers of its pod. The rk8s extensions oomScoreAdj and
+// oomKillPolicy of a container override the score, and whether the kernel
+// kills the whole container (Group) or only the process it picked (Single)
+// when the memory limit is hit, which needs cgroup v2.
+
+pub const KUBEPODS: &str = "/kubepods";
+// the kubelet's CFS period and minimum shares and quota
+const CPU_PERIOD: i64 = 100_000;
+const MIN_SHARES: i64 = 2;
+const MIN_QUOTA: i64 = 1_000;
+const CGROUP_FILE: &str = "cgroup";
+// the kubelet's scores
+const GUARANTEED_OOM_SCORE_ADJ: i64 = -997;
+const BEST_EFFORT_OOM_SCORE_ADJ: i64 = 1000;
+const NODE_CRITICAL: &str = "system-node-critical";
+const OOM_GROUP: &str = "memory.oom.group";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QosClass {
+    Guaranteed,
+    Burstable,
+    BestEffort,
+}
+
+impl fmt::Display for QosClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            QosClass::Guaranteed => "Guaranteed",
+            QosClass::Burstable => "Burstable",
+            QosClass::BestEffort => "BestEffort",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OomKillPolicy {
+    Group,
+    Single,
+}
+
+// like Kubernetes a missing request defaults to the limit
+pub fn request<'a>(container: &'a ContainerSpec, resource: &str) -> Option<&'a Quantity> {
+    container.resources.requests.get(resource).or_else(|| container.resources.limits.get(resource))
+}
+
+fn limit<'a>(container: &'a ContainerSpec, resource: &str) -> Option<&'a Quantity> {
+    container.resources.limits.get(resource)
+}
+
+fn all_containers(spec: &PodSpec) -> impl Iterator<Item = &ContainerSpec> {
+    spec.init_containers.iter().chain(&spec.sidecars).chain(&spec.containers)
+}
+
+// only cpu and memory count, like for the kubelet
+pub fn qos_class(spec: &PodSpec) -> QosClass {
+    let resources = ["cpu", "memory"];
+    let any = all_containers(spec).any(|c| resources.iter().any(|r| request(c, r).is_some()));
+    if !any {
+        return QosClass::BestEffort;
+    }
+    let guaranteed = all_containers(spec).all(|c| {
+        resources.iter().all(|r| match (request(c, r), limit(c, r)) {
+            (Some(request), Some(limit)) => request.milli_value() == limit.milli_value(),
+            _ => false,
+        })
+    });
+    if guaranteed { QosClass::Guaranteed } else { QosClass::Burstable }
+}
+
+pub fn cgroup_parent(class: QosClass, uid: &str) -> String {
+    match class {
+        QosClass::Guaranteed => format!("{}/pod{}", KUBEPODS, uid),
+        QosClass::Burstable => format!("{}/burstable/pod{}", KUBEPODS, uid),
+        QosClass::BestEffort => format!("{}/besteffort/pod{}", KUBEPODS, uid),
+    }
+}
+
+fn cpu_shares(millis: i64) -> i64 {
+    (millis * 1024 / 1000).max(MIN_SHARES)
+}
+
+fn cpu_quota(millis: i64) -> i64 {
+    (millis * CPU_PERIOD / 1000).max(MIN_QUOTA)
+}
+
+pub fn container_resources(container: &ContainerSpec) -> LinuxContainerResources {
+    let mut resources = LinuxContainerResources {
+        cpu_shares: cpu_shares(request(container, "cpu").map_or(0, Quantity::milli_value)),
+        ..Default::default()
+    };
+    if let Some(cpu) = limit(container, "cpu") {
+        resources.cpu_period = CPU_PERIOD;
+        resources.cpu_quota = cpu_quota(cpu.milli_value());
+    }
+    if let Some(memory) = limit(container, "memory") {
+        resources.memory_limit_in_bytes = memory.value();
+    }
+    if let Some(policy) = container.oom_kill_policy {
+        let group = if policy == OomKillPolicy::Group { "1" } else { "0" };
+        resources.unified.insert(OOM_GROUP.to_string(), group.to_string());
+    }
+    resources
+}
+
+// the oom_score_adj of a container on a node with memory_capacity bytes
+pub fn oom_score_adj(spec: &PodSpec, container: &ContainerSpec, memory_capacity: i64) -> i64 {
+    if let Some(adj) = container.oom_score_adj {
+        return adj;
+    }
+    let adj = class_oom_score_adj(spec, container, memory_capacity);
+    if !spec.is_sidecar(&container.name) {
+        return adj;
+    }
+    spec.containers.iter().map(|c| oom_score_adj(spec, c, memory_capacity)).fold(adj, i64::min)
+}
+
+fn class_oom_score_adj(spec: &PodSpec, container: &ContainerSpec, memory_capacity: i64) -> i64 {
+    if spec.priority_class_name.as_deref() == Some(NODE_CRITICAL) {
+        return GUARANTEED_OOM_SCORE_ADJ;
+    }
+    match qos_class(spec) {
+        QosClass::Guaranteed => GUARANTEED_OOM_SCORE_ADJ,
+        QosClass::BestEffort => BEST_EFFORT_OOM_SCORE_ADJ,
+        QosClass::Burstable => {
+            let memory = request(container, "memory").map_or(0, Quantity::value);
+            let adj = if memory_capacity > 0 { 1000 - 1000 * memory / memory_capacity } else { 1000 };
+            // above the guaranteed containers and below the best effort ones
+            adj.clamp(1000 + GUARANTEED_OOM_SCORE_ADJ, BEST_EFFORT_OOM_SCORE_ADJ - 1)
+        }
+    }
+}
+
+pub fn validate_oom_score_adj(container: &ContainerSpec) -> Result<()> {
+    match container.oom_score_adj {
+        Some(adj) if !(-1000..=1000).contains(&adj) => Err(anyhow!("oomScoreAdj must be between -1000 and 1000")),
+        _ => Ok(()),
+    }
+}
+
+// the pod is limited only when every container is, for the kubelet too
+pub fn pod_resources(spec: &PodSpec) -> LinuxContainerResources {
+    let containers: Vec<&ContainerSpec> = spec.running_containers().collect();
+    let requested: i64 = containers.iter().filter_map(|c| request(c, "cpu")).map(Quantity::milli_value).sum();
+    let mut resources = LinuxContainerResources {
+        cpu_shares: cpu_shares(requested),
+        ..Default::default()
+    };
+    let cpu_limit: Option<i64> = containers.iter().map(|c| limit(c, "cpu").map(Quantity::milli_value)).sum();
+    if let Some(millis) = cpu_limit.filter(|_| !containers.is_empty()) {
+        resources.cpu_period = CPU_PERIOD;
+        resources.cpu_quota = cpu_quota(millis);
+    }
+    let memory_limit: Option<i64> = containers.iter().map(|c| limit(c, "memory").map(Quantity::value)).sum();
+    if let Some(bytes) = memory_limit.filter(|_| !containers.is_empty()) {
+        resources.memory_limit_in_bytes = bytes;
+    }
+    resources
+}
+
+// put the container into a cgroup of its own below the pod cgroup and limit it
+pub fn apply_to_spec(spec: &mut Spec, cgroup_parent: &str, container_id: &str, resources: &LinuxContainerResources) -> Result<()> {
+    let mut linux = spec.linux().clone().unwrap_or_default();
+    if !cgroup_parent.is_empty() {
+        linux.set_cgroups_path(Some(PathBuf::from(format!("{}/{}", cgroup_parent, container_id))));
+    }
+    let mut cpu = LinuxCpuBuilder::default().shares(resources.cpu_shares as u64);
+    // the exclusive CPUs or the shared pool, see cpumanager
+    if !resources.cpuset_cpus.is_empty() {
+        cpu = cpu.cpus(resources.cpuset_cpus.clone());
+    }
+    if resources.cpu_quota > 0 {
+        cpu = cpu.quota(resources.cpu_quota).period(resources.cpu_period as u64);
+    }
+    let mut linux_resources = linux
+        .resources()
+        .clone()
+        .unwrap_or_else(|| LinuxResourcesBuilder::default().build().unwrap());
+    linux_resources.set_cpu(Some(cpu.build()?));
+    if resources.memory_limit_in_bytes > 0 {
+        linux_resources.set_memory(Some(LinuxMemoryBuilder::default().limit(resources.memory_limit_in_bytes).build()?));
+    }
+    if !resources.hugepage_limits.is_empty() {
+        let limits = resources
+            .hugepage_limits
+            .iter()
+            .map(|limit| LinuxHugepageLimitBuilder::default().page_size(limit.page_size.clone()).limit(limit.limit as i64).build())
+            .collect::<Result<Vec<_>, _>>()?;
+        linux_resources.set_hugepage_limits(Some(limits));
+    }
+    if !resources.unified.is_empty() {
+        if !matches!(get_cgroup_setup()?, CgroupSetup::Unified) {
+            let settings: Vec<&str> = resources.unified.keys().map(String::as_str).collect();
+            return Err(anyhow!("{} can only be set with cgroup v2", settings.join(", ")));
+        }
+        let mut unified = linux_resources.unified().clone().unwrap_or_default();
+        unified.extend(resources.unified.clone());
+        linux_resources.set_unified(Some(unified));
+    }
+    linux.set_resources(Some(linux_resources));
+    spec.set_linux(Some(linux));
+    // set on the process, which the spec has to have by now
+    if resources.oom_score_adj != 0
+        && let Some(mut process) = spec.process().clone()
+    {
+        process.set_oom_score_adj(Some(resources.oom_score_adj as i32));
+        spec.set_process(Some(process));
+    }
+    Ok(())
+}
+
+// e.g. hugetlb.2MB.max
+fn hugetlb_file(page_size: &str, setting: &str) -> String {
+    format!("hugetlb.{}.{}", page_size, setting)
+}
+
+// the cgroup files of one controller and their values
+fn cgroup_writes(setup: &CgroupSetup, resources: &LinuxContainerResources) -> Vec<(&'static str, String, String)> {
+    let mut writes = Vec::new();
+    match setup {
+        CgroupSetup::Unified => {
+            // the conversion of libcgroups and runc from shares to weight
+            let weight = 1 + ((resources.cpu_shares - MIN_SHARES) * 9999) / 262142;
+            writes.push(("", "cpu.weight".to_string(), weight.to_string()));
+            if resources.cpu_quota > 0 {
+                writes.push(("", "cpu.max".to_string(), format!("{} {}", resources.cpu_quota, resources.cpu_period)));
+            }
+            if resources.memory_limit_in_bytes > 0 {
+                writes.push(("", "memory.max".to_string(), resources.memory_limit_in_bytes.to_string()));
+            }
+            for limit in &resources.hugepage_limits {
+                writes.push(("", hugetlb_file(&limit.page_size, "max"), limit.limit.to_string()));
+            }
+        }
+        CgroupSetup::Legacy | CgroupSetup::Hybrid => {
+            writes.push(("cpu", "cpu.shares".to_string(), resources.cpu_shares.to_string()));
+            if resources.cpu_quota > 0 {
+                writes.push(("cpu", "cpu.cfs_period_us".to_string(), resources.cpu_period.to_string()));
+                writes.push(("cpu", "cpu.cfs_quota_us".to_string(), resources.cpu_quota.to_string()));
+            }
+            if resources.memory_limit_in_bytes > 0 {
+                writes.push(("memory", "memory.limit_in_bytes".to_string(), resources.memory_limit_in_bytes.to_string()));
+            }
+            for limit in &resources.hugepage_limits {
+                writes.push(("hugetlb", hugetlb_file(&limit.page_size, "limit_in_bytes"), limit.limit.to_string()));
+            }
+        }
+    }
+    writes
+}
+
+// with cgroup v2 the cpu, memory, hugetlb and cpuset controllers have to be
+// enabled for the children of every ancestor of the pod cgroup
+fn enable_controllers(root: &Path, cgroup: &str) {
+    let mut dir = root.to_path_buf();
+    for component in cgroup.split('/').filter(|c| !c.is_empty()) {
+        let _ = fs::write(dir.join("cgroup.subtree_control"), "+cpu +memory");
+        // hugetlb and cpuset on their own, nodes may not have them
+        let _ = fs::write(dir.join("cgroup.subtree_control"), "+hugetlb");
+        let _ = fs::write(dir.join("cgroup.subtree_control"), "+cpuset");
+        dir = dir.join(component);
+        let _ = fs::create_dir(&dir);
+    }
+}
+
+// create the pod cgroup of the sandbox config, if it has one
+pub fn create_pod_cgroup(sandbox_dir: &Path, config: &PodSandboxConfig) -> Result<()> {
+    let Some(linux) = config.linux.as_ref().filter(|linux| !linux.cgroup_parent.is_empty()) else {
+        return Ok(());
+    };
+    let resources = linux.resources.clone().unwrap_or_default();
+    let setup = get_cgroup_setup()?;
+    let root = Path::new(DEFAULT_CGROUP_ROOT);
+    if matches!(setup, CgroupSetup::Unified) {
+        enable_controllers(root, &linux.cgroup_parent);
+    }
+    for (controller, file, value) in cgroup_writes(&setup, &resources) {
+        let dir = root.join(controller).join(linux.cgroup_parent.trim_start_matches('/'));
+        fs::create_dir_all(&dir)
+            .map_err(|e| anyhow!("Failed to create pod cgroup {}: {}", dir.display(), e))?;
+        fs::write(dir.join(&file), &value)
+            .map_err(|e| anyhow!("Failed to set {} of pod cgroup {}: {}", file, linux.cgroup_parent, e))?;
+    }
+    fs::create_dir_all(sandbox_dir)?;
+    fs::write(sandbox_dir.join(CGROUP_FILE), &linux.cgroup_parent)?;
+    Ok(())
+}
+
+// remove the pod cgroup once its containers are gone
+pub fn remove_pod_cgroup(sandbox_dir: &Path) {
+    let Ok(cgroup_parent) = fs::read_to_string(sandbox_dir.join(CGROUP_FILE)) else {
+        return;
+    };
+    let root = Path::new(DEFAULT_CGROUP_ROOT);
+    let dirs: Vec<PathBuf> = match get_cgroup_setup() {
+        Ok(CgroupSetup::Unified) => vec![root.to_path_buf()],
+        Ok(_) => vec![root.join("cpu"), root.join("memory"), root.join("hugetlb")],
+        Err(_) => return,
+    };
+    for dir in dirs {
+        let dir = dir.join(cgroup_parent.trim_start_matches('/'));
+        if dir.exists()
+            && let Err(e) = fs::remove_dir(&dir)
+        {
+            warn!("Failed to remove pod cgroup {}: {}", dir.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::task::task::PodTask;
+
+    // a pod of the containers given as the YAML items of spec.containers,
+    // shared with the tests of memory
+    pub(crate) fn pod(containers: &str) -> PodSpec {
+        let yaml = format!("apiVersion: v1\nkind: Pod\nmetadata:\n  name: qos-pod\nspec:\n  containers:\n{}", containers);
+        serde_yaml::from_str::<PodTask>(&yaml).unwrap().spec
+    }
+
+    #[test]
+    fn test_qos_class() {
+        let best_effort = pod("    - name: a\n      image: /bundle/a\n");
+        assert_eq!(qos_class(&best_effort), QosClass::BestEffort);
+
+        // requests default to the limits
+        let guaranteed = pod(
+            "    - name: a\n      image: /bundle/a\n      resources:\n        limits:\n          cpu: 500m\n          memory: 128Mi\n",
+        );
+        assert_eq!(qos_class(&guaranteed), QosClass::Guaranteed);
+        assert_eq!(cgroup_parent(QosClass::Guaranteed, "u1"), "/kubepods/podu1");
+
+        let burstable = pod(
+            "    - name: a\n      image: /bundle/a\n      resources:\n        limits:\n          cpu: 500m\n          memory: 128Mi\n\
+             \x20   - name: b\n      image: /bundle/b\n      resources:\n        requests:\n          cpu: 100m\n",
+        );
+        assert_eq!(qos_class(&burstable), QosClass::Burstable);
+        assert_eq!(cgroup_parent(QosClass::Burstable, "u1"), "/kubepods/burstable/podu1");
+    }
+
+    #[test]
+    fn test_resources() {
+        let spec = pod(
+            "    - name: a\n      image: /bundle/a\n      resources:\n        limits:\n          cpu: 500m\n          memory: 128Mi\n\
+             \x20   - name: b\n      image: /bundle/b\n      resources:\n        requests:\n          cpu: 250m\n",
+        );
+        let a = container_resources(&spec.containers[0]);
+        assert_eq!((a.cpu_shares, a.cpu_quota, a.cpu_period), (512, 50_000, CPU_PERIOD));
+        assert_eq!(a.memory_limit_in_bytes, 128 * 1024 * 1024);
+        let b = container_resources(&spec.containers[1]);
+        assert_eq!((b.cpu_shares, b.cpu_quota, b.memory_limit_in_bytes), (256, 0, 0));
+
+        // b has no limits, so neither has the pod
+        let pod_resources = pod_resources(&spec);
+        assert_eq!((pod_resources.cpu_shares, pod_resources.cpu_quota, pod_resources.memory_limit_in_bytes), (768, 0, 0));
+
+        let mut oci = Spec::default();
+        apply_to_spec(&mut oci, "/kubepods/burstable/podu1", "a", &a).unwrap();
+        let linux = oci.linux().as_ref().unwrap();
+        assert_eq!(linux.cgroups_path().as_deref(), Some(Path::new("/kubepods/burstable/podu1/a")));
+        let cpu = linux.resources().as_ref().unwrap().cpu().as_ref().unwrap();
+        assert_eq!((cpu.shares(), cpu.quota()), (Some(512), Some(50_000)));
+    }
+
+    #[test]
+    fn test_oom_score_adj() {
+        let gib = 1 << 30;
+        let spec = pod(
+            "    - name: a\n      image: /bundle/a\n      resources:\n        requests:\n          memory: 1Gi\n\
+             \x20   - name: b\n      image: /bundle/b\n      resources:\n        requests:\n          cpu: 100m\n\
+             \x20   - name: c\n      image: /bundle/c\n      oomScoreAdj: -500\n",
+        );
+        assert_eq!(oom_score_adj(&spec, &spec.containers[0], 4 * gib), 750);
+        // no memory request at all, just below best effort
+        assert_eq!(oom_score_adj(&spec, &spec.containers[1], 4 * gib), 999);
+        // most of the node, just above guaranteed
+        assert_eq!(oom_score_adj(&spec, &spec.containers[0], gib), 3);
+        assert_eq!(oom_score_adj(&spec, &spec.containers[2], 4 * gib), -500);
+
+        let guaranteed = pod("    - name: a\n      image: /bundle/a\n      resources:\n        limits:\n          cpu: 1\n          memory: 1Gi\n");
+        assert_eq!(oom_score_adj(&guaranteed, &guaranteed.containers[0], 4 * gib), -997);
+        let best_effort = pod("    - name: a\n      image: /bundle/a\n");
+        assert_eq!(oom_score_adj(&best_effort, &best_effort.containers[0], 4 * gib), 1000);
+
+        let mut oci = Spec::default();
+        let resources = LinuxContainerResources { oom_score_adj: 750, ..Default::default() };
+        apply_to_spec(&mut oci, "", "a", &resources).unwrap();
+        assert_eq!(oci.process().as_ref().unwrap().oom_score_adj(), Some(750));
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::HashSet;
+use std::thread;
+use std::time::{Duration, Instant};
+use anyhow::{Result, anyhow};
+use libcontainer::container::ContainerStatus;
+use crate::commands::load_container;
+use crate::rootpath;
+use crate::task::probe::Readiness;
+use crate::task::task::{ContainerSpec, PodSpec, RestartPolicy, TaskRunner};
+
+// Sidecar containers, like the native sidecars of Kubernetes: an init
+// container with restartPolicy Always, or a container of the rk8s sidecars
+// list of the pod, e.g.
+//
+//   spec:
+//     init_containers:
+//       - name: proxy
+//         image: envoy:v1
+//         restartPolicy: Always
+//         readinessProbe:
+//           tcpSocket:
+//             port: 9901
+//     sidecars:
+//       - name: log-shipper
+//         image: fluent-bit:3
+//
+// The sidecars start in order before the containers of the pod, each once the
+// one before it is ready: when its readiness probe succeeded, or when it runs
+// if it has none. The containers only start once every sidecar is ready, a
+// sidecar that exits or isn't ready after SIDECAR_READY_TIMEOUT fails the
+// start of the pod. `rkl daemon` restarts sidecars whatever the restartPolicy
+// of the pod, and a pod is only ready while its sidecars are. When the pod is
+// stopped its containers are stopped first, then the sidecars one after the
+// other in reverse order, with what is left of the grace period.
+
+// how long the start of the containers waits for a sidecar
+pub const SIDECAR_READY_TIMEOUT: Duration = Duration::from_secs(120);
+
+// how often a sidecar without a due probe is looked at
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+impl ContainerSpec {
+    // an init container running alongside the containers
+    pub fn is_sidecar(&self) -> bool {
+        self.restart_policy == Some(RestartPolicy::Always)
+    }
+}
+
+impl PodSpec {
+    // the sidecars in the order they start
+    pub fn sidecars(&self) -> impl Iterator<Item = &ContainerSpec> {
+        self.init_containers.iter().filter(|container| container.is_sidecar()).chain(&self.sidecars)
+    }
+
+    // what runs for the lifetime of the pod, the sidecars first
+    pub fn running_containers(&self) -> impl Iterator<Item = &ContainerSpec> {
+        self.sidecars().chain(&self.containers)
+    }
+
+    // a container the pod runs, ephemeral ones included
+    pub fn container(&self, name: &str) -> Option<&ContainerSpec> {
+        self.running_containers().chain(&self.ephemeral_containers).find(|container| container.name == name)
+    }
+
+    pub fn is_sidecar(&self, name: &str) -> bool {
+        self.sidecars().any(|container| container.name == name)
+    }
+
+    pub fn validate_sidecars(&self) -> Result<()> {
+        for container in &self.init_containers {
+            if container.restart_policy.is_some_and(|policy| policy != RestartPolicy::Always) {
+                return Err(anyhow!("init container {}: restartPolicy can only be Always", container.name));
+            }
+        }
+        for container in self.sidecars.iter().chain(&self.containers) {
+            if container.restart_policy.is_some_and(|policy| policy != RestartPolicy::Always) {
+                return Err(anyhow!("container {}: restartPolicy is only supported on init containers", container.name));
+            }
+        }
+        let mut names = HashSet::new();
+        for container in self.init_containers.iter().chain(&self.sidecars).chain(&self.containers) {
+            if !names.insert(container.name.as_str()) {
+                return Err(anyhow!("container name {} is used more than once", container.name));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TaskRunner {
+    // wait for a sidecar that just started to be ready, the start of the
+    // containers waits on it
+    pub fn wait_sidecar_ready(&self, pod_sandbox_id: &str, container_name: &str) -> Result<()> {
+        let container = self.task.spec.container(container_name).ok_or_else(|| anyhow!("Sidecar {} not found", container_name))?;
+        let root_path = rootpath::determine(None)?;
+        // with a CRI runtime only the probe tells
+        let running = || self.backend.is_some() || load_container(&root_path, container_name).is_ok_and(|c| c.status() == ContainerStatus::Running);
+        let Some(probe) = &container.readiness_probe else {
+            return if running() { Ok(()) } else { Err(anyhow!("sidecar {} exited", container_name)) };
+        };
+        let ip = self.pod_ip(pod_sandbox_id).ok_or_else(|| anyhow!("the pod has no IP to probe sidecar {} at", container_name))?;
+        let started = Instant::now();
+        let mut readiness = Readiness::default();
+        loop {
+            let now = Instant::now();
+            if !running() {
+                return Err(anyhow!("sidecar {} exited before it was ready", container_name));
+            }
+            if readiness.due(probe, started, now) {
+                readiness.record(probe, probe.run(ip), now);
+                if readiness.ready {
+                    return Ok(());
+                }
+            }
+            if now.duration_since(started) >= SIDECAR_READY_TIMEOUT {
+                let reason = readiness.reason.unwrap_or_else(|| "not probed yet".to_string());
+                return Err(anyhow!("sidecar {} not ready after {}s: {}", container_name, SIDECAR_READY_TIMEOUT.as_secs(), reason));
+            }
+            thread::sleep(READY_POLL_INTERVAL);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::task::task::PodTask;
+
+    const POD: &str = r#"
+apiVersion: v1
+kind: Pod
+metadata:
+  name: web
+spec:
+  init_containers:
+    - name: migrate
+      image: app:v1
+    - name: proxy
+      image: envoy:v1
+      restartPolicy: Always
+  sidecars:
+    - name: log-shipper
+      image: fluent-bit:3
+  containers:
+    - name: app
+      image: app:v1
+"#;
+
+    #[test]
+    fn test_sidecars() {
+        let task: PodTask = serde_yaml::from_str(POD).unwrap();
+        task.spec.validate_sidecars().unwrap();
+        let names: Vec<&str> = task.spec.running_containers().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["proxy", "log-shipper", "app"]);
+        assert!(task.spec.is_sidecar("log-shipper"));
+        assert!(!task.spec.is_sidecar("migrate"));
+        assert!(task.spec.container("migrate").is_none());
+
+        let never: PodTask = serde_yaml::from_str(&POD.replace("restartPolicy: Always", "restartPolicy: Never")).unwrap();
+        assert_eq!(
+            never.spec.validate_sidecars().unwrap_err().to_string(),
+            "init container proxy: restartPolicy can only be Always"
+        );
+        let twice: PodTask = serde_yaml::from_str(&POD.replace("name: log-shipper", "name: app")).unwrap();
+        assert!(twice.spec.validate_sidecars().is_err());
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use anyhow::{Result, anyhow};
+use libcontainer::oci_spec::runtime::Spec;
+use serde::{Deserialize, Serialize};
+
+// Sysctls of pods, like the kubelet handles them: the securityContext of the
+// pod sets them in the namespaces of its sandbox, e.g.
+//
+//   securityContext:
+//     sysctls:
+//       - name: net.core.somaxconn
+//         value: "1024"
+//
+// and the securityContext of a container for that container only, which only
+// the built-in runtime can do, CRI has no sysctls per container. Only
+// namespaced sysctls can be set, net.* in the network namespace and
+// kernel.shm*, kernel.msg*, kernel.sem and fs.mqueue.* in the IPC one, not
+// when the pod uses the namespace of the node. The SAFE_SYSCTLS are allowed
+// for every pod, others only when --allowed-unsafe-sysctls lists them, by
+// name or as a prefix ending in *, e.g. net.core.*. A pod with a sysctl that
+// isn't allowed is refused before its sandbox is created.
+
+// isolated from the node and the other pods, the kubelet's safe set
+pub const SAFE_SYSCTLS: [&str; 10] = [
+    "kernel.shm_rmid_forced",
+    "net.ipv4.ip_local_port_range",
+    "net.ipv4.ip_local_reserved_ports",
+    "net.ipv4.ip_unprivileged_port_start",
+    "net.ipv4.ping_group_range",
+    "net.ipv4.tcp_syncookies",
+    "net.ipv4.tcp_keepalive_time",
+    "net.ipv4.tcp_keepalive_intvl",
+    "net.ipv4.tcp_keepalive_probes",
+    "net.ipv4.tcp_fin_timeout",
+];
+
+static ALLOWED_UNSAFE: OnceLock<Vec<String>> = OnceLock::new();
+
+// set once at startup from --allowed-unsafe-sysctls
+pub fn init(allowed_unsafe: Vec<String>) {
+    let _ = ALLOWED_UNSAFE.set(allowed_unsafe);
+}
+
+// simulate Kubernetes Sysctl
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sysctl {
+    pub name: String,
+    pub value: String,
+}
+
+// the namespace a sysctl belongs to, None for those of the node
+fn namespace(name: &str) -> Option<&'static str> {
+    if name.starts_with("net.") {
+        return Some("net");
+    }
+    let ipc = ["kernel.shm", "kernel.msg", "fs.mqueue."].iter().any(|prefix| name.starts_with(prefix));
+    (ipc || name == "kernel.sem").then_some("ipc")
+}
+
+fn allowed(name: &str, allowed_unsafe: &[String]) -> bool {
+    SAFE_SYSCTLS.contains(&name)
+        || allowed_unsafe.iter().any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => name == pattern,
+        })
+}
+
+// refuse sysctls that aren't namespaced, aren't allowed or would change the node
+pub fn validate(sysctls: &[Sysctl], host_network: bool, host_ipc: bool) -> Result<()> {
+    let allowed_unsafe = ALLOWED_UNSAFE.get().map(Vec::as_slice).unwrap_or_default();
+    check(sysctls, host_network, host_ipc, allowed_unsafe)
+}
+
+fn check(sysctls: &[Sysctl], host_network: bool, host_ipc: bool, allowed_unsafe: &[String]) -> Result<()> {
+    for sysctl in sysctls {
+        let name = sysctl.name.as_str();
+        match namespace(name) {
+            None => return Err(anyhow!("sysctl {} is not namespaced", name)),
+            Some("net") if host_network => return Err(anyhow!("sysctl {} can't be set with hostNetwork", name)),
+            Some("ipc") if host_ipc => return Err(anyhow!("sysctl {} can't be set with hostIPC", name)),
+            _ => {}
+        }
+        if !allowed(name, allowed_unsafe) {
+            return Err(anyhow!("forbidden sysctl: {} not allowlisted, see --allowed-unsafe-sysctls", name));
+        }
+    }
+    Ok(())
+}
+
+// the sysctls of the pod with those of a container over them
+pub fn merged(pod: &HashMap<String, String>, container: &[Sysctl]) -> HashMap<String, String> {
+    let mut sysctls = pod.clone();
+    sysctls.extend(container.iter().map(|sysctl| (sysctl.name.clone(), sysctl.value.clone())));
+    sysctls
+}
+
+// set the sysctls when the container is created, in the namespaces it joined
+pub fn add_to_spec(spec: &mut Spec, sysctls: &HashMap<String, String>) {
+    if sysctls.is_empty() {
+        return;
+    }
+    let mut linux = spec.linux().clone().unwrap_or_default();
+    let mut all = linux.sysctl().clone().unwrap_or_default();
+    all.extend(sysctls.clone());
+    linux.set_sysctl(Some(all));
+    spec.set_linux(Some(linux));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sysctls(names: &[&str]) -> Vec<Sysctl> {
+        names.iter().map(|name| Sysctl { name: name.to_string(), value: "1".to_string() }).collect()
+    }
+
+    #[test]
+    fn test_validate() {
+        let unsafe_net = vec!["net.core.*".to_string()];
+        assert!(check(&sysctls(&["net.ipv4.tcp_syncookies", "kernel.shm_rmid_forced"]), false, false, &[]).is_ok());
+        assert!(check(&sysctls(&["net.core.somaxconn"]), false, false, &unsafe_net).is_ok());
+        assert_eq!(
+            check(&sysctls(&["net.core.somaxconn"]), false, false, &[]).unwrap_err().to_string(),
+            "forbidden sysctl: net.core.somaxconn not allowlisted, see --allowed-unsafe-sysctls"
+        );
+        assert!(check(&sysctls(&["net.core.somaxconn"]), true, false, &unsafe_net).is_err());
+        assert!(check(&sysctls(&["kernel.msgmax"]), false, true, &["kernel.msgmax".to_string()]).is_err());
+        assert!(check(&sysctls(&["vm.swappiness"]), false, false, &["vm.*".to_string()]).is_err());
+
+        let pod = HashMap::from([("net.core.somaxconn".to_string(), "1024".to_string())]);
+        let mut spec = Spec::default();
+        add_to_spec(&mut spec, &merged(&pod, &sysctls(&["net.core.somaxconn"])));
+        let set = spec.linux().as_ref().and_then(|linux| linux.sysctl().clone()).unwrap();
+        assert_eq!(set.get("net.core.somaxconn").map(String::as_str), Some("1"));
+    }
+}
@@ -2,23 +2,69 @@ use crate::cri::cri::{
     PodSandboxConfig, PodSandboxMetadata, PortMapping, Protocol,
     RunPodSandboxRequest, RunPodSandboxResponse,
     CreateContainerRequest, CreateContainerResponse,
-    ContainerConfig, ContainerMetadata, ImageSpec, KeyValue, Mount,
+    ContainerConfig, ContainerMetadata, Device, ImageSpec, KeyValue, Mount,
     StartContainerRequest, StopPodSandboxRequest, RemovePodSandboxRequest,
-    StartContainerResponse, StopPodSandboxResponse,RemovePodSandboxResponse
+    StartContainerResponse, StopPodSandboxResponse,RemovePodSandboxResponse,
+    LinuxPodSandboxConfig, LinuxSandboxSecurityContext, LinuxContainerConfig,
+    LinuxContainerSecurityContext, NamespaceOption, NamespaceMode, DnsConfig,
+    PullImageRequest, PullImageResponse, ImageStatusRequest, PodSandboxNetworkStatus, ContainerStatusRequest
 };
 use libcontainer::oci_spec::runtime::{
-    LinuxBuilder, LinuxNamespaceBuilder, LinuxNamespaceType, Spec,ProcessBuilder
+    LinuxBuilder, LinuxNamespace, LinuxNamespaceBuilder, LinuxNamespaceType, Spec,ProcessBuilder,
+    MountBuilder, LinuxResources, LinuxResourcesBuilder, LinuxBlockIoBuilder, LinuxIOPriority, LinuxIOPriorityBuilder, IOPriorityClass
 };
 use liboci_cli::{Create,Start,State,Kill,Delete};
 use crate::commands::{create, start, state,kill,delete,load_container};
+use crate::commands::create::ContainerStdio;
 use crate::rootpath;
+use crate::cri::debug;
+use crate::ratelimit;
+use crate::cache;
+use crate::admission;
+use crate::checkpoint;
+use crate::strict;
+use crate::dryrun;
+use crate::configmap::{self, ConfigMapEnvSource, ConfigMapVolumeSource};
+use crate::secret::{self, SecretKeySelector, SecretVolumeSource};
+use crate::identity;
+use crate::quantity::Quantity;
+use crate::node::Taint;
+use crate::task::dns::{self, DnsPolicy, PodDnsConfig};
+use crate::task::{cni, cpumanager, ephemeral, hosts, logs, memory, network, qos, sysctl, ulimit, windows};
+use crate::task::hosts::HostAlias;
+use crate::task::windows::{PodOs, WindowsSecurityContextOptions};
+use crate::task::sysctl::Sysctl;
+use crate::task::ulimit::Ulimit;
+use crate::task::qos::OomKillPolicy;
+use crate::daemon::resources::memory_total;
+use crate::task::downward::{DownwardApiVolumeSource, ObjectFieldSelector, PodFields, SandboxStatus};
+use crate::task::probe::Probe;
+use crate::task::lifecycle::Lifecycle;
+use crate::task::termination::{self, TerminationMessagePolicy};
+use crate::events::{self, EventRecorder};
+use crate::device::{self, DeviceManager, host::{self, DeviceMapping}};
+use crate::image::{self, auth, ImageReference, pull::{self, PullOptions}};
+use crate::stream::{self, shim::ContainerIo};
+use crate::runtime::{self, RuntimeBackend};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use std::fs;
 use std::fs::File;
-use std::io::{Read,BufWriter,Write};
+use std::io::{BufWriter,Write};
 use serde_json::json;
+use sha2::{Digest, Sha256};
 use std::path::{PathBuf,Path};
 use anyhow::{Result, anyhow};
+use tracing::{info, info_span, warn};
+use crate::logging;
+use crate::metrics;
+
+// how often a pull says how far it got in the log
+const PULL_PROGRESS_LOG_INTERVAL: Duration = Duration::from_secs(10);
+
 // simulate Kubernetes Pod 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TypeMeta {
@@ -50,6 +96,186 @@ pub struct PodSpec {
     pub containers: Vec<ContainerSpec>,
     #[serde(default)]
     pub init_containers: Vec<ContainerSpec>,
+    // started before the containers and stopped after them, see sidecar
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sidecars: Vec<ContainerSpec>,
+    // added to the running pod by `rkl debug`, see ephemeral
+    #[serde(rename = "ephemeralContainers", default, skip_serializing_if = "Vec::is_empty")]
+    pub ephemeral_containers: Vec<ContainerSpec>,
+    // linux by default, windows pods need a CRI runtime on a Windows node, see windows
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os: Option<PodOs>,
+    // the name of the pod by default, see hosts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subdomain: Option<String>,
+    // extra entries of the /etc/hosts of the containers
+    #[serde(rename = "hostAliases", default, skip_serializing_if = "Vec::is_empty")]
+    pub host_aliases: Vec<HostAlias>,
+    // use the node's network namespace instead of the pod's
+    #[serde(rename = "hostNetwork", default)]
+    pub host_network: bool,
+    // use the node's PID namespace instead of the pod's
+    #[serde(rename = "hostPID", default)]
+    pub host_pid: bool,
+    // use the node's IPC namespace instead of the pod's
+    #[serde(rename = "hostIPC", default)]
+    pub host_ipc: bool,
+    // share a single PID namespace between all containers of the pod
+    #[serde(rename = "shareProcessNamespace", default)]
+    pub share_process_namespace: bool,
+    // the sysctls of the sandbox, see sysctl
+    #[serde(rename = "securityContext", default, skip_serializing_if = "Option::is_none")]
+    pub security_context: Option<SecurityContext>,
+    #[serde(rename = "dnsPolicy", default)]
+    pub dns_policy: DnsPolicy,
+    #[serde(rename = "dnsConfig", default)]
+    pub dns_config: Option<PodDnsConfig>,
+    // secrets holding registry credentials, see image::auth
+    #[serde(rename = "imagePullSecrets", default)]
+    pub image_pull_secrets: Vec<LocalObjectReference>,
+    // applied by `rkl daemon` when a container exits
+    #[serde(rename = "restartPolicy", default)]
+    pub restart_policy: RestartPolicy,
+    #[serde(rename = "runtimeClassName", default)]
+    pub runtime_class_name: Option<String>,
+    // which pods are preempted for which, see priority
+    #[serde(rename = "priorityClassName", default)]
+    pub priority_class_name: Option<String>,
+    #[serde(default)]
+    pub priority: Option<i32>,
+    #[serde(default)]
+    pub tolerations: Vec<Toleration>,
+    // labels the node must have for the pod to run there, see node
+    #[serde(rename = "nodeSelector", default)]
+    pub node_selector: HashMap<String, String>,
+    // node and pod affinity, kept for rks, which places the pod by it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub affinity: Option<serde_yaml::Value>,
+    // how the replicas spread over zones and nodes, for rks as well
+    #[serde(rename = "topologySpreadConstraints", default, skip_serializing_if = "Option::is_none")]
+    pub topology_spread_constraints: Option<serde_yaml::Value>,
+    // part of the SPIFFE ID of the pod, see identity
+    #[serde(rename = "serviceAccountName", default)]
+    pub service_account_name: Option<String>,
+    #[serde(default)]
+    pub volumes: Vec<Volume>,
+    // how long the containers get to stop before they are killed, see lifecycle
+    #[serde(rename = "terminationGracePeriodSeconds", default, skip_serializing_if = "Option::is_none")]
+    pub termination_grace_period_seconds: Option<u64>,
+}
+
+// simulate Kubernetes Volume, only configMap, secret and downwardAPI volumes are supported
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Volume {
+    pub name: String,
+    #[serde(rename = "configMap", default)]
+    pub config_map: Option<ConfigMapVolumeSource>,
+    #[serde(default)]
+    pub secret: Option<SecretVolumeSource>,
+    #[serde(rename = "downwardAPI", default)]
+    pub downward_api: Option<DownwardApiVolumeSource>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VolumeMount {
+    pub name: String,
+    // the supported volumes are always mounted read-only
+    #[serde(rename = "mountPath")]
+    pub mount_path: String,
+}
+
+// simulate Kubernetes EnvFromSource
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnvFromSource {
+    #[serde(rename = "configMapRef", default)]
+    pub config_map_ref: Option<ConfigMapEnvSource>,
+    // prepended to every key of the source
+    #[serde(default)]
+    pub prefix: String,
+}
+
+// simulate Kubernetes Toleration
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Toleration {
+    #[serde(default)]
+    pub key: String,
+    // Equal (default) or Exists
+    #[serde(default)]
+    pub operator: Option<String>,
+    #[serde(default)]
+    pub value: String,
+    // NoSchedule, PreferNoSchedule or NoExecute, every effect when empty
+    #[serde(default)]
+    pub effect: String,
+    #[serde(rename = "tolerationSeconds", default)]
+    pub toleration_seconds: Option<i64>,
+}
+
+impl Toleration {
+    pub fn tolerates(&self, taint: &Taint) -> bool {
+        if !self.effect.is_empty() && self.effect != taint.effect {
+            return false;
+        }
+        match self.operator.as_deref() {
+            // an empty key with Exists tolerates every taint
+            Some("Exists") => self.key.is_empty() || self.key == taint.key,
+            _ => self.key == taint.key && self.value == taint.value,
+        }
+    }
+}
+
+// simulate Kubernetes imagePullPolicy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PullPolicy {
+    Always,
+    IfNotPresent,
+    Never,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnvVar {
+    pub name: String,
+    #[serde(default)]
+    pub value: String,
+    #[serde(rename = "valueFrom", default, skip_serializing_if = "Option::is_none")]
+    pub value_from: Option<EnvVarSource>,
+}
+
+// simulate Kubernetes EnvVarSource
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnvVarSource {
+    #[serde(rename = "secretKeyRef", default)]
+    pub secret_key_ref: Option<SecretKeySelector>,
+    // the downward API, see task::downward
+    #[serde(rename = "fieldRef", default)]
+    pub field_ref: Option<ObjectFieldSelector>,
+}
+
+// simulate Kubernetes restartPolicy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum RestartPolicy {
+    #[default]
+    Always,
+    OnFailure,
+    Never,
+}
+
+impl RestartPolicy {
+    // exit_code is None when the exit status of the container is unknown
+    pub fn should_restart(&self, exit_code: Option<i32>) -> bool {
+        match self {
+            RestartPolicy::Always => true,
+            RestartPolicy::OnFailure => exit_code != Some(0),
+            RestartPolicy::Never => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocalObjectReference {
+    pub name: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -60,28 +286,465 @@ pub struct ContainerSpec {
     pub ports: Vec<Port>,
     #[serde(default)]  
     pub args: Vec<String>,  
+    // empty keeps the working directory of the image
+    #[serde(rename = "workingDir", default)]
+    pub working_dir: String,
+    // rk8s extensions for IO heavy workloads such as databases
+    // cgroup IO weight (io.weight on cgroup v2, blkio.weight on v1), 10 to 1000
+    #[serde(rename = "ioWeight", default)]
+    pub io_weight: Option<u16>,
+    // ionice class and priority of the container processes
+    #[serde(rename = "ioPriority", default)]
+    pub io_priority: Option<IoPriority>,
+    #[serde(rename = "transparentHugePages", default)]
+    pub transparent_huge_pages: ThpMode,
+    // rlimits of the container processes, e.g. a higher nofile, see ulimit
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ulimits: Vec<Ulimit>,
+    // over the oom_score_adj of its QoS class, -1000 to 1000, see qos
+    #[serde(rename = "oomScoreAdj", default, skip_serializing_if = "Option::is_none")]
+    pub oom_score_adj: Option<i64>,
+    // kill the whole container or a single process on OOM, cgroup v2 only
+    #[serde(rename = "oomKillPolicy", default, skip_serializing_if = "Option::is_none")]
+    pub oom_kill_policy: Option<OomKillPolicy>,
+    #[serde(default)]
+    pub resources: ResourceRequirements,
+    // keep the container's stdin open and/or give it a terminal, see `rkl attach`
+    #[serde(default)]
+    pub stdin: bool,
+    // close stdin once the first attached client detaches
+    #[serde(rename = "stdinOnce", default)]
+    pub stdin_once: bool,
+    #[serde(default)]
+    pub tty: bool,
+    #[serde(default)]
+    pub env: Vec<EnvVar>,
+    #[serde(rename = "envFrom", default)]
+    pub env_from: Vec<EnvFromSource>,
+    #[serde(rename = "volumeMounts", default)]
+    pub volume_mounts: Vec<VolumeMount>,
+    // device nodes of the host passed through, e.g. /dev/fuse
+    #[serde(default)]
+    pub devices: Vec<DeviceMapping>,
+    #[serde(rename = "imagePullPolicy", default)]
+    pub image_pull_policy: Option<PullPolicy>,
+    // only run by `rkl daemon`, see probe.rs
+    #[serde(rename = "readinessProbe", default, skip_serializing_if = "Option::is_none")]
+    pub readiness_probe: Option<Probe>,
+    // the preStop hook, run when the container is stopped, see lifecycle
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lifecycle: Option<Lifecycle>,
+    // where the container writes why it exits, see termination
+    #[serde(rename = "terminationMessagePath", default = "termination::default_termination_message_path")]
+    pub termination_message_path: String,
+    #[serde(rename = "terminationMessagePolicy", default)]
+    pub termination_message_policy: TerminationMessagePolicy,
+    // only Always, on init containers, which makes them sidecars
+    #[serde(rename = "restartPolicy", default, skip_serializing_if = "Option::is_none")]
+    pub restart_policy: Option<RestartPolicy>,
+    // the container whose processes an ephemeral container sees
+    #[serde(rename = "targetContainerName", default, skip_serializing_if = "Option::is_none")]
+    pub target_container_name: Option<String>,
+    // sysctls of this container over those of the pod, built-in runtime only
+    #[serde(rename = "securityContext", default, skip_serializing_if = "Option::is_none")]
+    pub security_context: Option<SecurityContext>,
+}
+
+impl ContainerSpec {
+    // like Kubernetes: Always for :latest or untagged images, IfNotPresent otherwise
+    pub fn pull_policy(&self) -> PullPolicy {
+        if let Some(policy) = self.image_pull_policy {
+            return policy;
+        }
+        match ImageReference::parse(&self.image) {
+            Ok(reference) if reference.digest.is_none() && reference.tag.as_deref() == Some("latest") => PullPolicy::Always,
+            _ => PullPolicy::IfNotPresent,
+        }
+    }
+}
+
+// the annotation of a container with the hash of its spec, so that a changed
+// pod only has the containers whose spec changed recreated (see daemon/sync.rs)
+pub const CONTAINER_HASH: &str = "rk8s.io/container-hash";
+
+// sha256 of the spec of a container once admitted; serde_json sorts the keys
+// of its maps, which makes the hash the same for the same spec
+pub fn container_hash(container: &ContainerSpec) -> String {
+    let canonical = serde_json::to_value(container).map(|value| value.to_string()).unwrap_or_default();
+    format!("{:x}", Sha256::digest(canonical.as_bytes()))
+}
+
+// the hash a created container was made with, None for containers created
+// before it was recorded
+pub fn running_container_hash(backend: Option<&dyn RuntimeBackend>, root_path: &Path, container_id: &str) -> Option<String> {
+    let annotations = match backend {
+        Some(backend) => {
+            let request = ContainerStatusRequest { container_id: container_id.to_string(), verbose: false };
+            backend.container_status(request).ok()?.status?.annotations
+        }
+        None => {
+            let container = load_container(root_path, container_id).ok()?;
+            Spec::load(container.bundle().join("config.json")).ok()?.annotations().clone()?
+        }
+    };
+    annotations.get(CONTAINER_HASH).cloned()
+}
+
+// simulate Kubernetes SecurityContext, of which only the sysctls and the
+// windowsOptions are supported
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SecurityContext {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sysctls: Vec<Sysctl>,
+    #[serde(rename = "windowsOptions", default, skip_serializing_if = "Option::is_none")]
+    pub windows_options: Option<WindowsSecurityContextOptions>,
+}
+
+impl SecurityContext {
+    pub fn sysctls(context: &Option<SecurityContext>) -> &[Sysctl] {
+        context.as_ref().map(|context| context.sysctls.as_slice()).unwrap_or_default()
+    }
+}
+
+// simulate Kubernetes ResourceRequirements
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ResourceRequirements {
+    #[serde(default)]
+    pub limits: HashMap<String, Quantity>,
+    #[serde(default)]
+    pub requests: HashMap<String, Quantity>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IoPriority {
+    pub class: IoClass,
+    // 0 (highest) to 7 (lowest), ignored for the Idle class
+    #[serde(default = "default_io_priority")]
+    pub priority: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IoClass {
+    RealTime,
+    BestEffort,
+    Idle,
+}
+
+fn default_io_priority() -> i64 {
+    4
+}
+
+// transparent huge pages can only be turned off for a process (PR_SET_THP_DISABLE),
+// Inherit keeps the node's /sys/kernel/mm/transparent_hugepage setting
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThpMode {
+    #[default]
+    Inherit,
+    Never,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Port {
     #[serde(rename = "containerPort")]
     pub container_port: i32,
+    // TCP, UDP or SCTP
     #[serde(default = "default_protocol")]
     pub protocol: String,
+    // port published on the node, 0 keeps the port private to the pod
     #[serde(rename = "hostPort", default)]
     pub host_port: i32,
     #[serde(rename = "hostIP", default)]
     pub host_ip: String,
 }
 
+// namespaces of a work container: POD mode joins the pause container's namespace,
+// CONTAINER mode creates a new one and NODE mode leaves the namespace out so that
+// the host's one is used
+pub fn build_namespaces(pause_pid: i32, options: &NamespaceOption) -> Result<Vec<LinuxNamespace>, anyhow::Error> {
+    let shared = [
+        (LinuxNamespaceType::Pid, "pid", options.pid),
+        (LinuxNamespaceType::Network, "net", options.network),
+        (LinuxNamespaceType::Ipc, "ipc", options.ipc),
+    ];
+
+    let mut namespaces = Vec::new();
+    for (typ, name, mode) in shared {
+        if mode == NamespaceMode::Node as i32 {
+            continue;
+        }
+        let mut namespace = LinuxNamespaceBuilder::default().typ(typ);
+        if mode != NamespaceMode::Container as i32 {
+            namespace = namespace.path(format!("/proc/{}/ns/{}", pause_pid, name));
+        }
+        namespaces.push(namespace.build()?);
+    }
+    namespaces.push(
+        LinuxNamespaceBuilder::default()
+            .typ(LinuxNamespaceType::Uts)
+            .path(format!("/proc/{}/ns/uts", pause_pid))
+            .build()?,
+    );
+    namespaces.push(LinuxNamespaceBuilder::default().typ(LinuxNamespaceType::Mount).build()?);
+    namespaces.push(LinuxNamespaceBuilder::default().typ(LinuxNamespaceType::Cgroup).build()?);
+    Ok(namespaces)
+}
+
+// the blkio weight of a container as OCI resources, None without ioWeight
+fn io_resources(container: &ContainerSpec) -> Result<Option<LinuxResources>, anyhow::Error> {
+    let Some(weight) = container.io_weight else {
+        return Ok(None);
+    };
+    if !(10..=1000).contains(&weight) {
+        return Err(anyhow!("ioWeight must be between 10 and 1000"));
+    }
+    let block_io = LinuxBlockIoBuilder::default().weight(weight).build()?;
+    Ok(Some(LinuxResourcesBuilder::default().block_io(block_io).build()?))
+}
+
+// the ionice class and priority of the process of a container, None without ioPriority
+fn io_priority(container: &ContainerSpec) -> Result<Option<LinuxIOPriority>, anyhow::Error> {
+    let Some(io_priority) = &container.io_priority else {
+        return Ok(None);
+    };
+    if !(0..=7).contains(&io_priority.priority) {
+        return Err(anyhow!("ioPriority priority must be between 0 and 7"));
+    }
+    let class = match io_priority.class {
+        IoClass::RealTime => IOPriorityClass::IoprioClassRt,
+        IoClass::BestEffort => IOPriorityClass::IoprioClassBe,
+        IoClass::Idle => IOPriorityClass::IoprioClassIdle,
+    };
+    Ok(Some(LinuxIOPriorityBuilder::default().class(class).priority(io_priority.priority).build()?))
+}
+
+// disables transparent huge pages for rkl while a container is being created, so that
+// the forked container process inherits the flag. The flag is process wide, the lock
+// keeps concurrent creates from seeing each other's setting, and the setting rkl had
+// before is put back afterwards
+struct ThpGuard<'a> {
+    // the lock and the previous setting while the flag is changed
+    changed: Option<(std::sync::MutexGuard<'a, ()>, bool)>,
+}
+
+static THP_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+impl ThpGuard<'static> {
+    fn new(mode: ThpMode) -> Result<Self, anyhow::Error> {
+        ThpGuard::with_lock(&THP_LOCK, mode)
+    }
+}
+
+impl<'a> ThpGuard<'a> {
+    // the tests hold THP_LOCK themselves and give the guards another lock
+    fn with_lock(lock: &'a std::sync::Mutex<()>, mode: ThpMode) -> Result<Self, anyhow::Error> {
+        if mode == ThpMode::Inherit {
+            return Ok(ThpGuard { changed: None });
+        }
+        let lock = lock.lock().unwrap_or_else(|e| e.into_inner());
+        let previous = nix::sys::prctl::get_thp_disable()
+            .map_err(|e| anyhow!("Failed to read the transparent huge pages setting: {}", e))?;
+        nix::sys::prctl::set_thp_disable(true)
+            .map_err(|e| anyhow!("Failed to disable transparent huge pages: {}", e))?;
+        Ok(ThpGuard { changed: Some((lock, previous)) })
+    }
+}
+
+impl Drop for ThpGuard<'_> {
+    fn drop(&mut self) {
+        if let Some((_, previous)) = &self.changed {
+            let _ = nix::sys::prctl::set_thp_disable(*previous);
+        }
+    }
+}
+
+// files generated for a sandbox (resolv.conf, ...) that get mounted into its containers
+pub fn sandbox_dir(root_path: &Path, pod_sandbox_id: &str) -> PathBuf {
+    root_path.join("sandboxes").join(pod_sandbox_id)
+}
+
+pub fn remove_sandbox_dir(root_path: &Path, pod_sandbox_id: &str) -> Result<(), anyhow::Error> {
+    let dir = sandbox_dir(root_path, pod_sandbox_id);
+    if dir.exists() {
+        // the secret volumes are tmpfs mounts
+        secret::unmount_volumes(&dir.join("volumes"))?;
+        qos::remove_pod_cgroup(&dir);
+        fs::remove_dir_all(&dir)?;
+    }
+    Ok(())
+}
+
+pub fn add_bind_mount(spec: &mut Spec, source: &Path, destination: &str, readonly: bool) -> Result<(), anyhow::Error> {
+    let mut options = vec!["rbind".to_string()];
+    options.push(if readonly { "ro" } else { "rw" }.to_string());
+    let mount = MountBuilder::default()
+        .destination(destination)
+        .typ("bind")
+        .source(source)
+        .options(options)
+        .build()?;
+    let mut mounts = spec.mounts().clone().unwrap_or_default();
+    mounts.retain(|m| m.destination() != mount.destination());
+    mounts.push(mount);
+    spec.set_mounts(Some(mounts));
+    Ok(())
+}
+
+fn parse_protocol(protocol: &str) -> Option<Protocol> {
+    match protocol.to_uppercase().as_str() {
+        "TCP" => Some(Protocol::Tcp),
+        "UDP" => Some(Protocol::Udp),
+        "SCTP" => Some(Protocol::Sctp),
+        _ => None,
+    }
+}
+
+// two published ports conflict when they use the same host port and protocol on
+// the same address, an empty or 0.0.0.0 address binds every address
+fn host_ports_conflict(a: &PortMapping, b: &PortMapping) -> bool {
+    let any = |ip: &str| ip.is_empty() || ip == "0.0.0.0";
+    a.host_port == b.host_port
+        && a.protocol == b.protocol
+        && (any(&a.host_ip) || any(&b.host_ip) || a.host_ip == b.host_ip)
+}
+
 fn default_protocol() -> String {
     "TCP".to_string()
 }
 
+// what to do with the rest of the pod when one of its containers fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum FailurePolicy {
+    // remove every container created so far and the PodSandbox
+    #[default]
+    Rollback,
+    // keep going with the remaining containers and leave the pod partially running
+    Continue,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureStage {
+    Create,
+    Start,
+    Rollback,
+}
+
+impl fmt::Display for FailureStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FailureStage::Create => write!(f, "create"),
+            FailureStage::Start => write!(f, "start"),
+            FailureStage::Rollback => write!(f, "rollback"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContainerState {
+    Created,
+    Running,
+    Failed(FailureStage),
+    // exited, with its exit code when it is known
+    Terminated(Option<i32>),
+}
+
+// per-container status, recorded in the pod file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerStatus {
+    pub name: String,
+    pub state: ContainerState,
+    pub reason: Option<String>,
+}
+
+impl ContainerStatus {
+    pub fn new(name: &str, state: ContainerState) -> Self {
+        ContainerStatus { name: name.to_string(), state, reason: None }
+    }
+
+    pub fn failed(name: &str, stage: FailureStage, err: &anyhow::Error) -> Self {
+        ContainerStatus {
+            name: name.to_string(),
+            state: ContainerState::Failed(stage),
+            // keep the reason on a single line so it fits in the pod file
+            reason: Some(err.to_string().replace('\n', " ")),
+        }
+    }
+
+    // the termination message is the reason, on a single line as well
+    pub fn terminated(name: &str, exit_code: Option<i32>, message: Option<&str>) -> Self {
+        ContainerStatus {
+            name: name.to_string(),
+            state: ContainerState::Terminated(exit_code),
+            reason: message.map(|message| message.lines().map(str::trim).collect::<Vec<_>>().join(" ")),
+        }
+    }
+}
+
+impl fmt::Display for ContainerStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.state {
+            ContainerState::Created => write!(f, "Created")?,
+            ContainerState::Running => write!(f, "Running")?,
+            ContainerState::Failed(stage) => write!(f, "Failed ({})", stage)?,
+            ContainerState::Terminated(None) => write!(f, "Terminated")?,
+            ContainerState::Terminated(Some(code)) => write!(f, "Terminated (exit code {})", code)?,
+        }
+        if let Some(reason) = &self.reason {
+            write!(f, ": {}", reason)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct ContainerFailure {
+    pub container: String,
+    pub stage: FailureStage,
+    pub error: anyhow::Error,
+}
+
+impl ContainerFailure {
+    pub fn new(container: &str, stage: FailureStage, error: anyhow::Error) -> Self {
+        ContainerFailure { container: container.to_string(), stage, error }
+    }
+}
+
+// returned by TaskRunner::run when one or more containers failed.
+// if rolled_back is false the PodSandbox and created_containers are still there
+#[derive(Debug)]
+pub struct PodRunError {
+    pub pod_name: String,
+    pub pod_sandbox_id: String,
+    pub created_containers: Vec<String>,
+    pub container_statuses: Vec<ContainerStatus>,
+    pub failures: Vec<ContainerFailure>,
+    pub rolled_back: bool,
+}
+
+impl fmt::Display for PodRunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Pod {}: {} failure(s)", self.pod_name, self.failures.len())?;
+        if self.rolled_back {
+            write!(f, ", pod rolled back")?;
+        }
+        for failure in &self.failures {
+            write!(f, "\n  - {} ({}): {}", failure.container, failure.stage, failure.error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for PodRunError {}
+
 pub struct TaskRunner {
     pub task: PodTask,
     pub pause_pid: Option<i32>, // pid of pause container
     pub sandbox_config: Option<PodSandboxConfig>,
+    pub container_statuses: Vec<ContainerStatus>, // status of every container of the last run
+    // set by the network plugin once the sandbox is attached, None with loopback only
+    pub network_status: Option<PodSandboxNetworkStatus>,
+    // the CRI runtime the calls go to, rkl runs the pod itself when None
+    pub backend: Option<Arc<dyn RuntimeBackend>>,
 }
 
 //some information from file.yaml
@@ -96,14 +759,31 @@ pub struct PodTask {
 }
 
 impl TaskRunner {
+    // the lifecycle events of the pod
+    pub fn events(&self) -> EventRecorder {
+        EventRecorder::for_pod(&self.task.metadata.name)
+    }
+
     //get information from a file  record in Podtask 
+    // path can also be a remote source, see cache::read_manifest
     pub fn from_file(path: &str) -> Result<Self> {
-        let mut file = File::open(path)?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)?;
+        Self::from_manifest(&cache::read_manifest(path)?)
+    }
 
-        let task: PodTask = serde_yaml::from_str(&contents)?;
-        Ok(TaskRunner { task, pause_pid: None,sandbox_config:None })
+    pub fn from_manifest(contents: &str) -> Result<Self> {
+        let mut task: PodTask = strict::from_yaml(contents)?;
+        admission::admit(&mut task)?;
+        task.spec.validate_sidecars()?;
+        task.spec.validate_hostname()?;
+        task.spec.validate_os()?;
+        Ok(TaskRunner {
+            task,
+            pause_pid: None,
+            sandbox_config: None,
+            container_statuses: Vec::new(),
+            network_status: None,
+            backend: runtime::backend()?,
+        })
     }
 
     //get PodSandboxConfig
@@ -117,52 +797,156 @@ impl TaskRunner {
         };
     
 
-        let port_mappings = self.task.spec.containers
-            .iter()
-            .flat_map(|c| c.ports.iter().map(|p| PortMapping {
-                protocol: match p.protocol.as_str() {
-                    "TCP" => Protocol::Tcp,
-                    "UDP" => Protocol::Udp,
-                    _ => Protocol::Tcp,
-                } as i32,
-                container_port: p.container_port,
-                host_port: p.host_port,
-                host_ip: p.host_ip.clone(),
-            }))
-            .collect();
+        let port_mappings = self.port_mappings()?;
+        let spec = &self.task.spec;
+        if spec.is_windows() && !windows::supported() {
+            return Err(anyhow!("Windows pods need rkl built with the windows feature"));
+        }
+        if spec.is_windows() && self.backend.is_none() {
+            return Err(anyhow!("Windows pods need a --runtime-endpoint of a runtime on a Windows node"));
+        }
+        let mut resources = qos::pod_resources(spec);
+        resources.hugepage_limits = memory::pod_hugepage_limits(spec)?;
+        let sysctls = SecurityContext::sysctls(&spec.security_context);
+        sysctl::validate(sysctls, spec.host_network, spec.host_ipc)?;
     
         // create PodSandboxConfig
         //now some data isn't used
         Ok(PodSandboxConfig {
             metadata: Some(metadata),
-            hostname: self.task.metadata.name.clone(),
-            log_directory: format!("/var/log/pods/{}_{}_{}/", self.task.metadata.namespace, self.task.metadata.name, uid),
-            dns_config: None,
+            hostname: if spec.host_network { String::new() } else { spec.hostname(&self.task.metadata.name) },
+            log_directory: logs::pod_log_dir(&self.task.metadata.namespace, &self.task.metadata.name, uid)
+                .display()
+                .to_string(),
+            dns_config: Some(self.dns_config()?),
             port_mappings,
             labels: self.task.metadata.labels.clone(),
             annotations: self.task.metadata.annotations.clone(),
-            linux: None, 
-            windows: None,
+            linux: (!spec.is_windows()).then(|| LinuxPodSandboxConfig {
+                cgroup_parent: qos::cgroup_parent(qos::qos_class(&self.task.spec), uid),
+                security_context: Some(LinuxSandboxSecurityContext {
+                    namespace_options: Some(self.namespace_options()),
+                    ..Default::default()
+                }),
+                resources: Some(resources),
+                sysctls: sysctls.iter().map(|sysctl| (sysctl.name.clone(), sysctl.value.clone())).collect(),
+                ..Default::default()
+            }),
+            windows: windows::sandbox_config(spec),
         })
     }
 
+    // port mappings of all containers, rejecting invalid ports and host ports
+    // published twice on the same protocol and address
+    pub fn port_mappings(&self) -> Result<Vec<PortMapping>, anyhow::Error> {
+        let mut port_mappings: Vec<PortMapping> = Vec::new();
+        for container in self.task.spec.running_containers() {
+            for port in &container.ports {
+                if !(1..=65535).contains(&port.container_port) {
+                    return Err(anyhow!("Container {}: invalid containerPort {}", container.name, port.container_port));
+                }
+                if !(0..=65535).contains(&port.host_port) {
+                    return Err(anyhow!("Container {}: invalid hostPort {}", container.name, port.host_port));
+                }
+                let protocol = parse_protocol(&port.protocol)
+                    .ok_or_else(|| anyhow!("Container {}: unsupported protocol {}", container.name, port.protocol))?;
+                let mapping = PortMapping {
+                    protocol: protocol as i32,
+                    container_port: port.container_port,
+                    host_port: port.host_port,
+                    host_ip: port.host_ip.clone(),
+                };
+
+                // a hostPort of 0 means the port isn't published
+                if mapping.host_port != 0
+                    && let Some(existing) = port_mappings.iter().find(|m| host_ports_conflict(m, &mapping))
+                {
+                    return Err(anyhow!(
+                        "Container {}: hostPort {}/{} conflicts with containerPort {} already published on {}",
+                        container.name,
+                        mapping.host_port,
+                        protocol.as_str_name(),
+                        existing.container_port,
+                        if existing.host_ip.is_empty() { "0.0.0.0" } else { &existing.host_ip },
+                    ));
+                }
+                port_mappings.push(mapping);
+            }
+        }
+        Ok(port_mappings)
+    }
+
+    // resolver configuration of the sandbox, derived from dnsPolicy and dnsConfig
+    pub fn dns_config(&self) -> Result<DnsConfig, anyhow::Error> {
+        let spec = &self.task.spec;
+        if spec.dns_policy == DnsPolicy::None && spec.dns_config.is_none() {
+            return Err(anyhow!("dnsConfig must be set when dnsPolicy is None"));
+        }
+        dns::pod_dns_config(spec.dns_policy, spec.dns_config.as_ref(), spec.host_network, &self.task.metadata.namespace)
+    }
+
+    // namespace modes of the pod, shared by the sandbox and its containers
+    pub fn namespace_options(&self) -> NamespaceOption {
+        let mode = |host: bool| if host { NamespaceMode::Node } else { NamespaceMode::Pod } as i32;
+        // like Kubernetes every container gets its own PID namespace unless
+        // shareProcessNamespace is set
+        let pid = if self.task.spec.host_pid {
+            NamespaceMode::Node
+        } else if self.task.spec.share_process_namespace {
+            NamespaceMode::Pod
+        } else {
+            NamespaceMode::Container
+        };
+        NamespaceOption {
+            network: mode(self.task.spec.host_network),
+            pid: pid as i32,
+            ipc: mode(self.task.spec.host_ipc),
+            ..Default::default()
+        }
+    }
+
     //get RunPodSandboxRequest 
-    pub fn build_run_pod_sandbox_request(&self) -> RunPodSandboxRequest {
+    pub fn build_run_pod_sandbox_request(&self) -> Result<RunPodSandboxRequest, anyhow::Error> {
         let uid = uuid::Uuid::new_v4().to_string();
         let attempt = 0; 
-        RunPodSandboxRequest {
-            config: Some(self.create_pod_sandbox_config(&uid, attempt).unwrap_or_default()), 
-            runtime_handler: "pause".to_string(), // just mean that pause container is started
-        }
+        Ok(RunPodSandboxRequest {
+            config: Some(self.create_pod_sandbox_config(&uid, attempt)?),
+            runtime_handler: self.runtime_handler()?,
+        })
+    }
+
+    // the handler of the RuntimeClass of the pod, see runtime::class
+    fn runtime_handler(&self) -> Result<String, anyhow::Error> {
+        runtime::class::handler(self.task.spec.runtime_class_name.as_deref())
     }
 
     //create pause container and start it
-    pub fn run_pod_sandbox(
+    pub fn run_pod_sandbox(&mut self, request: RunPodSandboxRequest) -> Result<RunPodSandboxResponse, anyhow::Error> {
+        debug::traced("RunPodSandbox", request, |request| match self.backend.clone() {
+            Some(backend) => {
+                let _permit = ratelimit::limiter().acquire(&self.task.metadata.name);
+                backend.run_pod_sandbox(request)
+            }
+            None => self.run_pod_sandbox_inner(request),
+        })
+    }
+
+    fn run_pod_sandbox_inner(
         &mut self,
         request: RunPodSandboxRequest,
     ) -> Result<RunPodSandboxResponse, anyhow::Error> {
+        let _permit = ratelimit::limiter().acquire(&self.task.metadata.name);
+        // libcontainer is the only runtime in process
+        if !request.runtime_handler.is_empty() {
+            return Err(anyhow!(
+                "runtime handler {} of RuntimeClass {} needs a --runtime-endpoint that has it",
+                request.runtime_handler,
+                self.task.spec.runtime_class_name.as_deref().unwrap_or_default()
+            ));
+        }
         let config = request.config.unwrap_or_default();
-        let sandbox_id = format!("{}", config.metadata.unwrap_or_default().name);
+        let metadata = config.metadata.clone().unwrap_or_default();
+        let sandbox_id = metadata.name.clone();
 
         // get bundle path of pause container from labels
         let bundle_path = self.task.metadata.labels
@@ -186,6 +970,8 @@ impl TaskRunner {
 
         let root_path = rootpath::determine(None)
             .map_err(|e| anyhow!("Failed to determine root path: {}", e))?;
+        // the containers of the pod are created below its cgroup
+        qos::create_pod_cgroup(&sandbox_dir(&root_path, &sandbox_id), &config)?;
 
         create::create(create_args, root_path.clone(), false)
             .map_err(|e| anyhow!("Failed to create container: {}", e))?;
@@ -202,6 +988,34 @@ impl TaskRunner {
             .ok_or_else(|| anyhow!("PID not found for container {}", sandbox_id))?;
 
         self.pause_pid = Some(pid_i32);
+        if !config.hostname.is_empty()
+            && let Err(e) = hosts::set_hostname(pid_i32, &config.hostname)
+        {
+            warn!("Failed to set hostname of sandbox {}: {}", sandbox_id, e);
+        }
+
+        // the containers of the pod are only created once its network is up
+        if let Err(e) = self.attach_network(&root_path, &sandbox_id, pid_i32).and_then(|()| self.wait_network_ready(pid_i32)) {
+            let message = format!("Failed to set up network for sandbox {}: {}", sandbox_id, e);
+            EventRecorder::new(&root_path, &self.task.metadata.name).warning(events::FAILED_CREATE_POD_SANDBOX, &message);
+            if let Err(e) = cni::teardown(&root_path, &sandbox_id) {
+                warn!("Failed to detach network of sandbox {}: {}", sandbox_id, e);
+            }
+            let delete_args = Delete {
+                container_id: sandbox_id.clone(),
+                force: true,
+            };
+            let _ = delete::delete(delete_args, root_path.clone());
+            self.pause_pid = None;
+            self.network_status = None;
+            return Err(anyhow!(message));
+        }
+        // for the downward API of containers restarted later on
+        let status = SandboxStatus {
+            uid: metadata.uid,
+            pod_ip: self.network_status.as_ref().map(|status| status.ip.clone()).unwrap_or_default(),
+        };
+        status.save(&sandbox_dir(&root_path, &sandbox_id))?;
 
         let response = RunPodSandboxResponse {
             pod_sandbox_id: sandbox_id,
@@ -210,32 +1024,148 @@ impl TaskRunner {
         Ok(response)
     }
     
+    // attach the sandbox through CNI, which sets the network status
+    fn attach_network(&mut self, root_path: &Path, sandbox_id: &str, pause_pid: i32) -> Result<(), anyhow::Error> {
+        if self.task.spec.host_network {
+            return Ok(());
+        }
+        let metadata = &self.task.metadata;
+        self.network_status = cni::setup(root_path, sandbox_id, &metadata.namespace, &metadata.name, pause_pid)?;
+        Ok(())
+    }
+
+    fn wait_network_ready(&self, pause_pid: i32) -> Result<(), anyhow::Error> {
+        if self.task.spec.host_network {
+            return Ok(());
+        }
+        match &self.network_status {
+            Some(status) => network::wait_ready(pause_pid, status, network::ready_timeout()),
+            None => Ok(()),
+        }
+    }
+
     pub fn build_create_container_request(
         &self,
         pod_sandbox_id: &str,
         container: &ContainerSpec,
     ) -> Result<CreateContainerRequest, anyhow::Error> {
-        let config = ContainerConfig {
-            //just create accronding to the format of ContainerConfig
-            //now some data isn't used 
-            metadata: Some(ContainerMetadata {
-                name: container.name.clone(),
-                attempt: 0, 
-            }),
-            image: Some(ImageSpec {
-                image: container.image.clone(),
-                annotations: std::collections::HashMap::new(),
-                user_specified_image: container.image.clone(),
-                runtime_handler: "".to_string(),
-            }),
-            command: vec!["/bin/sh".to_string()],
-            args: container.args.clone(),
-            working_dir: "/".to_string(),
-            envs: vec![KeyValue {
+        self.container_request(pod_sandbox_id, container, true)
+    }
+
+    // prepare is false for dry runs, which neither allocate devices nor write
+    // the termination log
+    fn container_request(
+        &self,
+        pod_sandbox_id: &str,
+        container: &ContainerSpec,
+        prepare: bool,
+    ) -> Result<CreateContainerRequest, anyhow::Error> {
+        let sysctls = SecurityContext::sysctls(&container.security_context);
+        if !sysctls.is_empty() {
+            if self.backend.is_some() {
+                return Err(anyhow!("Container {}: sysctls of a container need the built-in runtime, set them on the pod", container.name));
+            }
+            let spec = &self.task.spec;
+            sysctl::validate(sysctls, spec.host_network, spec.host_ipc).map_err(|e| anyhow!("Container {}: {}", container.name, e))?;
+        }
+        if !container.ulimits.is_empty() {
+            if self.backend.is_some() {
+                return Err(anyhow!("Container {}: ulimits need the built-in runtime", container.name));
+            }
+            ulimit::validate(&container.ulimits).map_err(|e| anyhow!("Container {}: {}", container.name, e))?;
+        }
+        qos::validate_oom_score_adj(container).map_err(|e| anyhow!("Container {}: {}", container.name, e))?;
+        let mut resources = qos::container_resources(container);
+        resources.oom_score_adj = qos::oom_score_adj(&self.task.spec, container, memory_total().unwrap_or_default());
+        resources.hugepage_limits = memory::hugepage_limits(container).map_err(|e| anyhow!("Container {}: {}", container.name, e))?;
+        if let Some(swap_max) = memory::swap_max(&self.task.spec, container)? {
+            resources.unified.insert(memory::SWAP_MAX.to_string(), swap_max.to_string());
+        }
+        let is_windows = self.task.spec.is_windows();
+        // Windows images bring their own PATH
+        let mut envs = if is_windows {
+            vec![]
+        } else {
+            vec![KeyValue {
                 key: "PATH".to_string(),
                 value: "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin".to_string(),
-            }],
-            mounts: vec![
+            }]
+        };
+        // later sources win over earlier ones and env over all of them
+        let root_path = rootpath::determine(None)?;
+        for source in &container.env_from {
+            let Some(config_map) = &source.config_map_ref else {
+                continue;
+            };
+            let vars = configmap::env(&root_path, config_map, &source.prefix)
+                .map_err(|e| anyhow!("Container {}: {}", container.name, e))?;
+            for (key, value) in vars {
+                if container.env.iter().any(|e| e.name == key) {
+                    continue;
+                }
+                envs.retain(|e| e.key != key);
+                envs.push(KeyValue { key, value });
+            }
+        }
+        let fields = self.pod_fields();
+        for var in &container.env {
+            let invalid = |e: anyhow::Error| anyhow!("Container {}: env {}: {}", container.name, var.name, e);
+            let value = match &var.value_from {
+                Some(EnvVarSource { secret_key_ref: Some(selector), .. }) => {
+                    secret::key_value(&root_path, selector).map_err(invalid)?
+                }
+                Some(EnvVarSource { field_ref: Some(selector), .. }) => {
+                    Some(fields.field(&selector.field_path, false).map_err(invalid)?)
+                }
+                _ => Some(var.value.clone()),
+            };
+            // an optional secret that is missing leaves the var out
+            if let Some(value) = value {
+                envs.push(KeyValue { key: var.name.clone(), value });
+            }
+        }
+        // exclusive CPUs first, the devices go by them
+        let cpus = cpumanager::container_cpus(&root_path, &self.task.metadata.name, &self.task.spec, container, prepare)
+            .map_err(|e| anyhow!("Container {}: {}", container.name, e))?;
+        if let Some(assignment) = &cpus {
+            resources.cpuset_cpus = cpumanager::format_cpuset(&assignment.cpus);
+        }
+        let pinned_cpus = cpus.filter(|assignment| assignment.exclusive).map(|assignment| assignment.cpus).unwrap_or_default();
+        let (device_envs, allocated_devices) = self.allocate_devices(container, prepare, &pinned_cpus)?;
+        envs.extend(device_envs);
+        if !container.working_dir.is_empty() && !container.working_dir.starts_with('/') {
+            return Err(anyhow!("Container {}: workingDir must be an absolute path", container.name));
+        }
+        let mut devices = container.devices
+            .iter()
+            .map(host::cri_device)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow!("Container {}: {}", container.name, e))?;
+        devices.extend(allocated_devices);
+        // the container writes why it exits into this file
+        let termination_log = if prepare {
+            termination::prepare(&root_path, pod_sandbox_id, &container.name)?
+        } else {
+            termination::host_path(&root_path, pod_sandbox_id, &container.name)
+        };
+        let etc_hosts = self.etc_hosts(pod_sandbox_id, prepare && !is_windows)?;
+
+        let mut mounts = vec![
+            Mount {
+                container_path: container.termination_message_path.clone(),
+                host_path: termination_log.display().to_string(),
+                readonly: false,
+                selinux_relabel: false,
+                propagation: 0,
+                uid_mappings: vec![],
+                gid_mappings: vec![],
+                recursive_read_only: false,
+                image: None,
+            },
+        ];
+        // the Linux filesystems and /etc/hosts, Windows containers have their own
+        if !is_windows {
+            mounts.extend([
                 Mount {
                     container_path: "/proc".to_string(),
                     host_path: "proc".to_string(),
@@ -258,31 +1188,266 @@ impl TaskRunner {
                     recursive_read_only: false,
                     image: None,
                 },
-            ],
-            devices: vec![],
-            labels: std::collections::HashMap::new(),
-            annotations: std::collections::HashMap::new(),
-            log_path: format!("{}/0.log", container.name),
-            stdin: false,
-            stdin_once: false,
-            tty: false,
-            linux: None,
-            windows: None,
-            cdi_devices: vec![],
-        };
-    
-        Ok(CreateContainerRequest {
-            pod_sandbox_id: pod_sandbox_id.to_string(),
-            config: Some(config),
-            sandbox_config: self.sandbox_config.clone(),
+                Mount {
+                    container_path: hosts::MOUNT_PATH.to_string(),
+                    host_path: etc_hosts.display().to_string(),
+                    readonly: false,
+                    selinux_relabel: false,
+                    propagation: 0,
+                    uid_mappings: vec![],
+                    gid_mappings: vec![],
+                    recursive_read_only: false,
+                    image: None,
+                },
+            ]);
+        }
+
+        let config = ContainerConfig {
+            //just create accronding to the format of ContainerConfig
+            //now some data isn't used 
+            metadata: Some(ContainerMetadata {
+                name: container.name.clone(),
+                attempt: 0, 
+            }),
+            image: Some(ImageSpec {
+                image: container.image.clone(),
+                annotations: std::collections::HashMap::new(),
+                user_specified_image: container.image.clone(),
+                runtime_handler: self.runtime_handler()?,
+            }),
+            command: vec!["/bin/sh".to_string()],
+            args: container.args.clone(),
+            working_dir: container.working_dir.clone(),
+            envs,
+            mounts,
+            devices,
+            labels: std::collections::HashMap::new(),
+            annotations: HashMap::from([(CONTAINER_HASH.to_string(), container_hash(container))]),
+            log_path: format!("{}/0.log", container.name),
+            stdin: container.stdin,
+            stdin_once: container.stdin_once,
+            tty: container.tty,
+            linux: (!is_windows).then(|| LinuxContainerConfig {
+                resources: Some(resources),
+                security_context: Some(LinuxContainerSecurityContext {
+                    namespace_options: Some(self.namespace_options()),
+                    ..Default::default()
+                }),
+            }),
+            windows: windows::container_config(&self.task.spec, container),
+            cdi_devices: vec![],
+        };
+    
+        Ok(CreateContainerRequest {
+            pod_sandbox_id: pod_sandbox_id.to_string(),
+            config: Some(config),
+            sandbox_config: self.sandbox_config.clone(),
         })
     }
    
+    // allocate the extended resources (devices) the container asks for in its limits
+    // and return the environment and device nodes that give the container the ones it got,
+    // only checking that they can be asked for without allocate
+    fn allocate_devices(&self, container: &ContainerSpec, allocate: bool, pinned_cpus: &[u32]) -> Result<(Vec<KeyValue>, Vec<Device>), anyhow::Error> {
+        let mut envs = Vec::new();
+        let mut devices = Vec::new();
+        for (resource, quantity) in &container.resources.limits {
+            // only domain prefixed names are extended resources
+            if !resource.contains('/') {
+                continue;
+            }
+            let plugin = device::plugin(resource)
+                .ok_or_else(|| anyhow!("Container {}: no device plugin for resource {}", container.name, resource))?;
+            let count = quantity.as_count()
+                .ok_or_else(|| anyhow!("Container {}: {} must be a whole number", container.name, resource))?;
+            if count == 0 || !allocate {
+                continue;
+            }
+
+            let root_path = rootpath::determine(None)?;
+            let owner = format!("{}/{}", self.task.metadata.name, container.name);
+            let allocated = DeviceManager::discover(&root_path, plugin.as_ref())?.allocate(resource, count, &owner, pinned_cpus)?;
+            let ids: Vec<&str> = allocated.iter().map(|d| d.id.as_str()).collect();
+            info!("Container {}: allocated {} {}", container.name, resource, ids.join(","));
+            let allocation = plugin.allocation(&allocated);
+            envs.extend(allocation.envs.into_iter().map(|(key, value)| KeyValue { key, value }));
+            for path in allocation.device_paths {
+                let mapping = DeviceMapping {
+                    host_path: path,
+                    container_path: String::new(),
+                    permissions: host::DEFAULT_PERMISSIONS.to_string(),
+                };
+                devices.push(host::cri_device(&mapping).map_err(|e| anyhow!("Container {}: {}", container.name, e))?);
+            }
+        }
+        Ok((envs, devices))
+    }
+
+    pub fn build_pull_image_request(&self, container: &ContainerSpec) -> Result<PullImageRequest, anyhow::Error> {
+        let reference = ImageReference::parse(&container.image)?;
+        let root_path = rootpath::determine(None)?;
+        let pull_secrets: Vec<String> = self.task.spec.image_pull_secrets.iter().map(|s| s.name.clone()).collect();
+        let auth = auth::resolve(&root_path, &pull_secrets, &reference.registry)?;
+        Ok(PullImageRequest {
+            image: Some(ImageSpec {
+                image: container.image.clone(),
+                annotations: std::collections::HashMap::new(),
+                user_specified_image: container.image.clone(),
+                runtime_handler: self.runtime_handler()?,
+            }),
+            auth,
+            sandbox_config: self.sandbox_config.clone(),
+        })
+    }
+
+    // bundle directory of a container: the image itself when it is a bundle path,
+    // otherwise the image is pulled if needed and unpacked into the sandbox directory
+    fn ensure_bundle(&self, pod_sandbox_id: &str, container: &ContainerSpec) -> Result<PathBuf, anyhow::Error> {
+        if container.image.is_empty() {
+            return Err(anyhow!("Bundle path (image) for container {} is empty", container.name));
+        }
+        if image::is_bundle_path(&container.image) {
+            let bundle_dir = PathBuf::from(&container.image);
+            if !bundle_dir.exists() {
+                return Err(anyhow!("Bundle directory does not exist"));
+            }
+            return Ok(bundle_dir);
+        }
+
+        let root_path = rootpath::determine(None)?;
+        let present = image::is_present(&root_path, &container.image);
+        let pull = match container.pull_policy() {
+            PullPolicy::Always => true,
+            PullPolicy::IfNotPresent => !present,
+            PullPolicy::Never if present => false,
+            PullPolicy::Never => {
+                return Err(anyhow!("Container {}: image {} is not present and imagePullPolicy is Never", container.name, container.image));
+            }
+        };
+        if pull {
+            let request = self.build_pull_image_request(container)?;
+            // runs under the rate limit permit of create_container
+            let started = Instant::now();
+            let response = self.pull_image(None, &root_path, request)?;
+            metrics::IMAGE_PULL_DURATION.observe(started.elapsed());
+            info!("Image pulled: {}", response.image_ref);
+            let message = format!("Successfully pulled image {}", response.image_ref);
+            self.events().normal(events::PULLED, &message);
+        }
+        let bundle_dir = sandbox_dir(&root_path, pod_sandbox_id).join("bundles").join(&container.name);
+        image::unpack(&root_path, &container.image, &bundle_dir)?;
+        Ok(bundle_dir)
+    }
+
+    // pull the image of a container, logging how far it got when it takes a while
+    fn pull_image(&self, backend: Option<&dyn RuntimeBackend>, root_path: &Path, request: PullImageRequest) -> Result<PullImageResponse, anyhow::Error> {
+        let image = request.image.as_ref().map(|image| image.image.clone()).unwrap_or_default();
+        let mut logged = Instant::now();
+        debug::traced("PullImage", request, |request| {
+            pull::pull(backend, root_path, &request, &PullOptions::default(), |progress| {
+                if logged.elapsed() >= PULL_PROGRESS_LOG_INTERVAL {
+                    logged = Instant::now();
+                    info!("Pulling image {}: {} in {}", image, progress, events::age(progress.elapsed.as_secs()));
+                }
+            })
+        })
+    }
+
+    // write a volume of the pod into the sandbox directory, where it is shared
+    // by every container mounting it
+    fn project_volume(&self, root_path: &Path, pod_sandbox_id: &str, name: &str) -> Result<PathBuf, anyhow::Error> {
+        let volume = self.task.spec.volumes
+            .iter()
+            .find(|v| v.name == name)
+            .ok_or_else(|| anyhow!("volume {} is not defined by the pod", name))?;
+        if name.is_empty() || name.contains('/') || name == "." || name == ".." {
+            return Err(anyhow!("invalid volume name {:?}", name));
+        }
+        let dir = sandbox_dir(root_path, pod_sandbox_id).join("volumes").join(name);
+        let sources = [volume.config_map.is_some(), volume.secret.is_some(), volume.downward_api.is_some()];
+        if sources.iter().filter(|source| **source).count() > 1 {
+            return Err(anyhow!("volume {} has more than one source", name));
+        }
+        if let Some(source) = &volume.config_map {
+            configmap::project(root_path, source, &dir)?;
+        } else if let Some(source) = &volume.secret {
+            secret::project(root_path, source, &dir)?;
+        } else if let Some(source) = &volume.downward_api {
+            self.pod_fields().project(source, &dir)?;
+        } else {
+            return Err(anyhow!("volume {} has no supported source", name));
+        }
+        Ok(dir)
+    }
+
+    // the downward API values of the pod, with the uid and IP of its sandbox
+    fn pod_fields(&self) -> PodFields<'_> {
+        let metadata = &self.task.metadata;
+        let uid = self.sandbox_config
+            .as_ref()
+            .and_then(|config| config.metadata.as_ref())
+            .map(|m| m.uid.clone())
+            .unwrap_or_default();
+        let pod_ip = self.network_status.as_ref().map(|status| status.ip.clone()).unwrap_or_default();
+        PodFields {
+            name: &metadata.name,
+            namespace: &metadata.namespace,
+            labels: &metadata.labels,
+            annotations: &metadata.annotations,
+            status: SandboxStatus { uid, pod_ip },
+        }
+    }
+
    //create work container
-    pub fn create_container(
+    pub fn create_container(&self, request: CreateContainerRequest) -> Result<CreateContainerResponse, anyhow::Error> {
+        debug::traced("CreateContainer", request, |request| match &self.backend {
+            Some(backend) => {
+                let _permit = ratelimit::limiter().acquire(&self.task.metadata.name);
+                self.ensure_image(backend.as_ref(), &request)?;
+                backend.create_container(request)
+            }
+            None => self.create_container_inner(request),
+        })
+    }
+
+    // pull the image of a container into the CRI runtime as its imagePullPolicy says
+    fn ensure_image(&self, backend: &dyn RuntimeBackend, request: &CreateContainerRequest) -> Result<(), anyhow::Error> {
+        let name = request.config.as_ref().and_then(|config| config.metadata.as_ref()).map(|m| m.name.as_str());
+        let Some(container) = name.and_then(|name| self.task.spec.container(name)) else {
+            return Ok(());
+        };
+        // restored from the archive, nothing to pull
+        if checkpoint::is_archive(&container.image) {
+            return Ok(());
+        }
+        let pull_request = self.build_pull_image_request(container)?;
+        let present = || -> Result<bool, anyhow::Error> {
+            let status = ImageStatusRequest { image: pull_request.image.clone(), verbose: false };
+            Ok(backend.image_status(status)?.image.is_some())
+        };
+        let pull = match container.pull_policy() {
+            PullPolicy::Always => true,
+            PullPolicy::IfNotPresent => !present()?,
+            PullPolicy::Never if present()? => false,
+            PullPolicy::Never => {
+                return Err(anyhow!("Container {}: image {} is not present and imagePullPolicy is Never", container.name, container.image));
+            }
+        };
+        if pull {
+            let started = Instant::now();
+            let response = self.pull_image(Some(backend), &rootpath::determine(None)?, pull_request)?;
+            metrics::IMAGE_PULL_DURATION.observe(started.elapsed());
+            info!("Image pulled: {}", response.image_ref);
+            self.events().normal(events::PULLED, &format!("Successfully pulled image {}", response.image_ref));
+        }
+        Ok(())
+    }
+
+    fn create_container_inner(
         &self,
         request: CreateContainerRequest,
-    ) -> Result<CreateContainerResponse, anyhow::Error> {   
+    ) -> Result<CreateContainerResponse, anyhow::Error> {
+        let _permit = ratelimit::limiter().acquire(&self.task.metadata.name);
         let pod_sandbox_id = request.pod_sandbox_id.clone();
         let config = request.config.as_ref().ok_or_else(|| anyhow!("Container config is required"))?;
         let container_id = config.metadata.as_ref()
@@ -290,60 +1455,88 @@ impl TaskRunner {
         .ok_or_else(|| anyhow!("Container metadata is required"))?;
     
         // check sandbox_config
-        if self.sandbox_config.is_none() {
-            return Err(anyhow!("PodSandboxConfig is not set"));
-        }
+        let sandbox_config = self.sandbox_config.as_ref().ok_or_else(|| anyhow!("PodSandboxConfig is not set"))?;
         let pause_pid = self.pause_pid.ok_or_else(|| anyhow!("Pause container PID is not set"))?;
         // create  OCI Spec
         let mut spec = Spec::default();
-        let namespaces = vec![
-        LinuxNamespaceBuilder::default()
-            .typ(LinuxNamespaceType::Pid)
-            .path(format!("/proc/{}/ns/pid", pause_pid))
-            .build()?,
-        LinuxNamespaceBuilder::default()
-            .typ(LinuxNamespaceType::Network)
-            .path(format!("/proc/{}/ns/net",pause_pid))
-            .build()?,
-        LinuxNamespaceBuilder::default()
-            .typ(LinuxNamespaceType::Ipc)
-            .path(format!("/proc/{}/ns/ipc", pause_pid))
-            .build()?,
-        LinuxNamespaceBuilder::default()
-            .typ(LinuxNamespaceType::Uts)
-            .path(format!("/proc/{}/ns/uts", pause_pid))
-            .build()?,
-        LinuxNamespaceBuilder::default()
-            .typ(LinuxNamespaceType::Mount)
-            .build()?,
-        LinuxNamespaceBuilder::default()
-            .typ(LinuxNamespaceType::Cgroup)
-            .build()?,
-    ];
-
-        let linux = LinuxBuilder::default()
-            .namespaces(namespaces)
-            .build()?;
-        spec.set_linux(Some(linux));
+        let namespace_options = config.linux.as_ref()
+            .and_then(|l| l.security_context.as_ref())
+            .and_then(|sc| sc.namespace_options.clone())
+            .unwrap_or_default();
+        let mut namespaces = build_namespaces(pause_pid, &namespace_options)?;
 
-        let container_spec = self.task.spec.containers
-            .iter()
-            .find(|c| c.name == container_id)
+        let container_spec = self.task.spec
+            .container(&container_id)
             .ok_or_else(|| anyhow!("Container spec not found for ID: {}", container_id))?;
+        if let Some(target) = &container_spec.target_container_name {
+            ephemeral::join_target_pid_namespace(&mut namespaces, target)?;
+        }
+
+        let mut linux = LinuxBuilder::default().namespaces(namespaces);
+        if let Some(resources) = io_resources(container_spec).map_err(|e| anyhow!("Container {}: {}", container_id, e))? {
+            linux = linux.resources(resources);
+        }
+        spec.set_linux(Some(linux.build()?));
+        let pod_sysctls = sandbox_config.linux.as_ref().map(|linux| linux.sysctls.clone()).unwrap_or_default();
+        sysctl::add_to_spec(&mut spec, &sysctl::merged(&pod_sysctls, SecurityContext::sysctls(&container_spec.security_context)));
+        spec.set_annotations(Some(config.annotations.clone()));
+        host::add_to_spec(&mut spec, &config.devices)?;
+
+        let env: Vec<String> = config.envs.iter().map(|kv| format!("{}={}", kv.key, kv.value)).collect();
         let mut process = ProcessBuilder::default()
                                         .args(container_spec.args.clone())
-                                        .build()?;
+                                        .env(env)
+                                        .terminal(config.tty);
+        if !config.working_dir.is_empty() {
+            process = process.cwd(&config.working_dir);
+        }
+        if let Some(io_priority) = io_priority(container_spec).map_err(|e| anyhow!("Container {}: {}", container_id, e))? {
+            process = process.io_priority(io_priority);
+        }
 
-        spec.set_process( Some(process));
-        
-        let bundle_path = container_spec.image.clone();
-        if bundle_path.is_empty() {
-            return Err(anyhow!("Bundle path (image) for container {} is empty", container_id));
+        let mut process = process.build()?;
+        ulimit::add_to_process(&mut process, &container_spec.ulimits).map_err(|e| anyhow!("Container {}: {}", container_id, e))?;
+        spec.set_process(Some(process));
+        // after the process, which gets the oom_score_adj
+        if let Some(resources) = config.linux.as_ref().and_then(|linux| linux.resources.as_ref()) {
+            let cgroup_parent = sandbox_config.linux.as_ref().map(|linux| linux.cgroup_parent.as_str()).unwrap_or_default();
+            qos::apply_to_spec(&mut spec, cgroup_parent, &container_id, resources)?;
         }
-        let bundle_dir = PathBuf::from(&bundle_path);
-        if !bundle_dir.exists() {
-            return Err(anyhow!("Bundle directory does not exist"));
+
+        // get root_path
+        let root_path = rootpath::determine(None)
+            .map_err(|e| anyhow!("Failed to determine root path: {}", e))?;
+
+        // the sandbox resolv.conf is shared by every container of the pod
+        if let Some(dns_config) = &sandbox_config.dns_config {
+            let resolv_conf = sandbox_dir(&root_path, &pod_sandbox_id).join("resolv.conf");
+            fs::create_dir_all(sandbox_dir(&root_path, &pod_sandbox_id))?;
+            fs::write(&resolv_conf, dns::render_resolv_conf(dns_config))?;
+            add_bind_mount(&mut spec, &resolv_conf, "/etc/resolv.conf", true)?;
+        }
+        // so is its hosts file, written by container_request
+        if let Some(mount) = config.mounts.iter().find(|mount| mount.container_path == hosts::MOUNT_PATH) {
+            add_bind_mount(&mut spec, Path::new(&mount.host_path), hosts::MOUNT_PATH, false)?;
+        }
+        // the SVID directory is mounted rather than its files so that rotations show up
+        if identity::config().is_some() {
+            let metadata = &self.task.metadata;
+            let service_account = self.task.spec.service_account_name.as_deref().unwrap_or("default");
+            // readable by the user the container runs as only
+            let owner = spec.process().as_ref().map(|process| (process.user().uid(), process.user().gid())).unwrap_or_default();
+            let svid_dir = identity::ensure_svid(&root_path, &pod_sandbox_id, &metadata.namespace, service_account, owner)?;
+            add_bind_mount(&mut spec, &svid_dir, identity::MOUNT_PATH, true)?;
         }
+        for mount in &container_spec.volume_mounts {
+            let dir = self.project_volume(&root_path, &pod_sandbox_id, &mount.name)
+                .map_err(|e| anyhow!("Container {}: {}", container_id, e))?;
+            add_bind_mount(&mut spec, &dir, &mount.mount_path, true)?;
+        }
+        let termination_log = termination::host_path(&root_path, &pod_sandbox_id, &container_id);
+        add_bind_mount(&mut spec, &termination_log, &container_spec.termination_message_path, false)?;
+        
+        let bundle_dir = self.ensure_bundle(&pod_sandbox_id, container_spec)?;
+        let bundle_path = bundle_dir.display().to_string();
         // write into config.json
         let config_path = format!("{}/config.json", bundle_path);
         if Path::new(&config_path).exists() {
@@ -355,32 +1548,70 @@ impl TaskRunner {
         serde_json::to_writer_pretty(&mut writer, &spec)?;
         writer.flush()?;
     
+        // containers with stdin or tty get an io shim for `rkl attach`
+        let mut io = if config.stdin || config.tty {
+            let work_dir = sandbox_dir(&root_path, &pod_sandbox_id);
+            Some(ContainerIo::new(&work_dir, &container_id, config.stdin, config.stdin_once, config.tty)?)
+        } else {
+            None
+        };
         let create_args = Create {
             bundle: bundle_path.clone().into(),
-            console_socket: None,
+            console_socket: io.as_ref().and_then(|io| io.console_socket()),
             pid_file: None,
             no_pivot: false,
             no_new_keyring: false,
             preserve_fds: 0,
             container_id: container_id.clone(),
         };
+        let stdio = match io.as_mut() {
+            Some(io) => io.take_container_stdio(),
+            // the others write their log
+            None => match logs::open_container_log(sandbox_config, config)? {
+                Some(log) => {
+                    let stderr = log.try_clone()?;
+                    ContainerStdio { stdin: None, stdout: Some(log.into()), stderr: Some(stderr.into()) }
+                }
+                None => ContainerStdio::default(),
+            },
+        };
     
-        // get root_path
-        let root_path = rootpath::determine(None)
-            .map_err(|e| anyhow!("Failed to determine root path: {}", e))?;
-    
-        create::create(create_args, root_path.clone(), false)
+        // the container init process inherits the THP flag when it is forked
+        let _thp = ThpGuard::new(container_spec.transparent_huge_pages)?;
+        create::create_with_stdio(create_args, root_path.clone(), false, stdio)
             .map_err(|e| anyhow!("Failed to create container: {}", e))?;
+
+        if let Some(io) = io
+            && let Err(e) = io.start_shim(&stream::attach_socket_path(&root_path, &container_id))
+        {
+            let delete_args = Delete {
+                container_id: container_id.clone(),
+                force: true,
+            };
+            let _ = delete::delete(delete_args, root_path.clone());
+            return Err(anyhow!("Failed to set up io of container {}: {}", container_id, e));
+        }
         
         Ok(CreateContainerResponse {
             container_id,
         })
     }
     
-    pub fn start_container(
+    pub fn start_container(&self, request: StartContainerRequest) -> Result<StartContainerResponse, anyhow::Error> {
+        debug::traced("StartContainer", request, |request| match &self.backend {
+            Some(backend) => {
+                let _permit = ratelimit::limiter().acquire(&self.task.metadata.name);
+                backend.start_container(request)
+            }
+            None => self.start_container_inner(request),
+        })
+    }
+
+    fn start_container_inner(
         &self,
         request: StartContainerRequest,
     ) -> Result<StartContainerResponse, anyhow::Error> {
+        let _permit = ratelimit::limiter().acquire(&self.task.metadata.name);
         let container_id = request.container_id;
         let root_path = rootpath::determine(None)?;
 
@@ -394,12 +1625,27 @@ impl TaskRunner {
     }
     
     //stop pause container
-    pub fn stop_pod_sandbox(
+    pub fn stop_pod_sandbox(&self, request: StopPodSandboxRequest) -> Result<StopPodSandboxResponse, anyhow::Error> {
+        debug::traced("StopPodSandbox", request, |request| match &self.backend {
+            Some(backend) => {
+                let _permit = ratelimit::limiter().acquire(&self.task.metadata.name);
+                backend.stop_pod_sandbox(request)
+            }
+            None => self.stop_pod_sandbox_inner(request),
+        })
+    }
+
+    fn stop_pod_sandbox_inner(
         &self,
         request: StopPodSandboxRequest,
     ) -> Result<StopPodSandboxResponse, anyhow::Error> {
+        let _permit = ratelimit::limiter().acquire(&self.task.metadata.name);
         let pod_sandbox_id = request.pod_sandbox_id;
         let root_path = rootpath::determine(None)?;
+        // detached while the network namespace is still there
+        if let Err(e) = cni::teardown(&root_path, &pod_sandbox_id) {
+            warn!("Failed to detach network of PodSandbox {}: {}", pod_sandbox_id, e);
+        }
         let kill_args = Kill {
                     container_id: pod_sandbox_id.clone(),
                     signal: "SIGKILL".to_string(),
@@ -410,10 +1656,21 @@ impl TaskRunner {
         Ok(StopPodSandboxResponse {})
     }
     //delete pause container
-    pub fn remove_pod_sandbox(
+    pub fn remove_pod_sandbox(&self, request: RemovePodSandboxRequest) -> Result<RemovePodSandboxResponse, anyhow::Error> {
+        debug::traced("RemovePodSandbox", request, |request| match &self.backend {
+            Some(backend) => {
+                let _permit = ratelimit::limiter().acquire(&self.task.metadata.name);
+                backend.remove_pod_sandbox(request)
+            }
+            None => self.remove_pod_sandbox_inner(request),
+        })
+    }
+
+    fn remove_pod_sandbox_inner(
         &self,
         request: RemovePodSandboxRequest,
     ) -> Result<RemovePodSandboxResponse, anyhow::Error> {
+        let _permit = ratelimit::limiter().acquire(&self.task.metadata.name);
         let pod_sandbox_id = request.pod_sandbox_id;
         let root_path = rootpath::determine(None)?;
         let delete_args = Delete {
@@ -422,124 +1679,525 @@ impl TaskRunner {
         };
         delete::delete(delete_args, root_path.clone())
             .map_err(|e| anyhow!("Failed to delete PodSandbox {}: {}", pod_sandbox_id, e))?;
+        remove_sandbox_dir(&root_path, &pod_sandbox_id)?;
 
         Ok(RemovePodSandboxResponse {})
     }
 
-    pub fn run(&mut self) -> Result<String, anyhow::Error> {
-        // run PodSandbox（Pause container）
-        let pod_request = self.build_run_pod_sandbox_request();
-        let config = pod_request.config.as_ref().ok_or_else(|| anyhow!("PodSandbox config is required"))?;
-        self.sandbox_config = Some(config.clone());
-        let pod_response = self.run_pod_sandbox(pod_request)
-            .map_err(|e| anyhow!("Failed to run PodSandbox: {}", e))?;
-        let pod_sandbox_id = pod_response.pod_sandbox_id;
-        let pause_pid = self.pause_pid
-            .ok_or_else(|| anyhow!("Pause container PID not found for PodSandbox ID: {}", pod_sandbox_id))?;
-        println!("PodSandbox (Pause) started: {}, pid: {}\n", pod_sandbox_id, pause_pid);
-    
-        //record the container ID if succeed 
-        // if fail clear all containers created
+    // delete the containers created so far, then stop and remove the pause container.
+    // every step is attempted; the ones that fail are returned as failures
+    pub fn rollback(&self, pod_sandbox_id: &str, created_containers: &[String]) -> Vec<ContainerFailure> {
+        let mut failures = Vec::new();
+
+        // a CRI runtime removes the containers with their sandbox
+        for container_id in created_containers {
+            self.events().normal(events::KILLING, &format!("Stopping container {}", container_id));
+        }
+        let own_containers = if self.backend.is_none() { created_containers } else { &[] };
+        for container_id in own_containers {
+            let delete_args = Delete {
+                container_id: container_id.clone(),
+                force: true,
+            };
+            let result = rootpath::determine(None).and_then(|root_path| delete::delete(delete_args, root_path));
+            if let Err(delete_err) = result {
+                warn!("Failed to delete container {} during rollback: {}", container_id, delete_err);
+                failures.push(ContainerFailure::new(container_id, FailureStage::Rollback, delete_err));
+            } else {
+                info!("Container deleted during rollback: {}", container_id);
+            }
+        }
+
+        // stop pause
+        let stop_request = StopPodSandboxRequest {
+            pod_sandbox_id: pod_sandbox_id.to_string(),
+        };
+        if let Err(stop_err) = self.stop_pod_sandbox(stop_request) {
+            warn!("Failed to stop PodSandbox {} during rollback: {}", pod_sandbox_id, stop_err);
+            failures.push(ContainerFailure::new(pod_sandbox_id, FailureStage::Rollback, stop_err));
+        } else {
+            info!("PodSandbox stopped during rollback: {}", pod_sandbox_id);
+        }
+
+        // delete pause
+        let remove_request = RemovePodSandboxRequest {
+            pod_sandbox_id: pod_sandbox_id.to_string(),
+        };
+        if let Err(remove_err) = self.remove_pod_sandbox(remove_request) {
+            warn!("Failed to remove PodSandbox {} during rollback: {}", pod_sandbox_id, remove_err);
+            failures.push(ContainerFailure::new(pod_sandbox_id, FailureStage::Rollback, remove_err));
+        } else {
+            info!("PodSandbox deleted during rollback: {}", pod_sandbox_id);
+        }
+
+        if let Err(err) = rootpath::determine(None).and_then(|root_path| device::release_pod(&root_path, &self.task.metadata.name)) {
+            warn!("Failed to release devices of Pod {} during rollback: {}", self.task.metadata.name, err);
+        }
+        if let Err(err) = rootpath::determine(None).and_then(|root_path| cpumanager::release_pod(&root_path, &self.task.metadata.name)) {
+            warn!("Failed to release CPUs of Pod {} during rollback: {}", self.task.metadata.name, err);
+        }
+
+        failures
+    }
+
+    // recreate a stopped container in the running sandbox of the pod, used by
+    // the daemon to restart containers according to the restart policy
+    pub fn restart_container(&mut self, pod_sandbox_id: &str, container_name: &str) -> Result<(), anyhow::Error> {
+        if self.backend.is_some() {
+            return Err(anyhow!("containers can only be restarted by the built-in runtime"));
+        }
+        let root_path = rootpath::determine(None)?;
+        self.join_sandbox(pod_sandbox_id)?;
+
+        let container = self.task.spec
+            .container(container_name)
+            .ok_or_else(|| anyhow!("Container spec not found for ID: {}", container_name))?;
+        let delete_args = Delete {
+            container_id: container_name.to_string(),
+            force: true,
+        };
+        delete::delete(delete_args, root_path.clone())?;
+        device::release_container(&root_path, &self.task.metadata.name, container_name)?;
+        cpumanager::release_container(&root_path, &self.task.metadata.name, container_name)?;
+
+        let request = self.build_create_container_request(pod_sandbox_id, container)?;
+        self.create_container(request)?;
+        self.events().normal(events::CREATED, &format!("Created container {}", container_name));
+        self.start_container(StartContainerRequest {
+            container_id: container_name.to_string(),
+        })?;
+        self.events().normal(events::STARTED, &format!("Started container {}", container_name));
+        self.post_start(pod_sandbox_id, container_name)
+    }
+
+    // take over the running sandbox of the pod for creating containers in it,
+    // only with the built-in runtime
+    pub fn join_sandbox(&mut self, pod_sandbox_id: &str) -> Result<(), anyhow::Error> {
+        let root_path = rootpath::determine(None)?;
+        let sandbox = load_container(root_path.clone(), pod_sandbox_id)?;
+        self.pause_pid = Some(sandbox.pid().ok_or_else(|| anyhow!("PID not found for PodSandbox {}", pod_sandbox_id))?.as_raw());
+        if self.sandbox_config.is_none() {
+            self.sandbox_config = self.build_run_pod_sandbox_request()?.config;
+            // the pod keeps the uid and IP of its sandbox
+            if let Ok(status) = SandboxStatus::load(&sandbox_dir(&root_path, pod_sandbox_id)) {
+                if let Some(metadata) = self.sandbox_config.as_mut().and_then(|config| config.metadata.as_mut()) {
+                    metadata.uid = status.uid;
+                }
+                if !status.pod_ip.is_empty() {
+                    self.network_status = Some(PodSandboxNetworkStatus { ip: status.pod_ip, ..Default::default() });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // create every container of the pod in the given sandbox, the sidecars first.
+    // the ids of the created containers are returned together with the failures;
+    // with FailurePolicy::Rollback creation stops at the first failure
+    pub fn create_containers(
+        &mut self,
+        pod_sandbox_id: &str,
+        policy: FailurePolicy,
+    ) -> Result<(Vec<String>, Vec<ContainerFailure>), anyhow::Error> {
         let mut created_containers = Vec::new();
-    
-        // create all container
-        for container in &self.task.spec.containers {
-            let create_request = self.build_create_container_request(&pod_sandbox_id, container)?;
-            match self.create_container(create_request) {
+        let mut failures = Vec::new();
+
+        for container in self.task.spec.running_containers() {
+            let _span = info_span!("container", container = %container.name).entered();
+            let result = self
+                .build_create_container_request(pod_sandbox_id, container)
+                .and_then(|request| self.create_container(request));
+            match result {
                 Ok(create_response) => {
                     created_containers.push(create_response.container_id.clone());
-                    println!("Container created: {} (ID: {})", container.name, create_response.container_id);
+                    self.container_statuses.push(ContainerStatus::new(&container.name, ContainerState::Created));
+                    info!("Container created: {} (ID: {})", container.name, create_response.container_id);
+                    self.events().normal(events::CREATED, &format!("Created container {}", container.name));
                 }
                 Err(e) => {
-                    eprintln!("Failed to create container {}: {}", container.name, e);
-    
-                    // delete container created
-                    for container_id in &created_containers {
-                        let delete_args = Delete {
-                            container_id: container_id.clone(),
-                            force: true, 
-                        };
-                        let root_path = rootpath::determine(None)?;
-                        if let Err(delete_err) = delete::delete(delete_args, root_path.clone()) {
-                            eprintln!("Failed to delete container {} during rollback: {}", container_id, delete_err);
-                        } else {
-                            println!("Container deleted during rollback: {}", container_id);
-                        }
-                    }
-    
-                    // stop pause
-                    let stop_request = StopPodSandboxRequest {
-                        pod_sandbox_id: pod_sandbox_id.clone(),
-                    };
-                    if let Err(stop_err) = self.stop_pod_sandbox(stop_request) {
-                        eprintln!("Failed to stop PodSandbox {} during rollback: {}", pod_sandbox_id, stop_err);
-                    } else {
-                        println!("PodSandbox stopped during rollback: {}", pod_sandbox_id);
+                    warn!("Failed to create container {}: {}", container.name, e);
+                    self.events().warning(events::FAILED, &format!("Error: failed to create container {}: {}", container.name, e));
+                    self.container_statuses.push(ContainerStatus::failed(&container.name, FailureStage::Create, &e));
+                    failures.push(ContainerFailure::new(&container.name, FailureStage::Create, e));
+                    if policy == FailurePolicy::Rollback {
+                        break;
                     }
-    
-                    // delete pause
-                    let remove_request = RemovePodSandboxRequest {
-                        pod_sandbox_id: pod_sandbox_id.clone(),
-                    };
-                    if let Err(remove_err) = self.remove_pod_sandbox(remove_request) {
-                        eprintln!("Failed to remove PodSandbox {} during rollback: {}", pod_sandbox_id, remove_err);
-                    } else {
-                        println!("PodSandbox deleted during rollback: {}", pod_sandbox_id);
-                    }
-    
-                    return Err(anyhow!("Failed to create container {}: {}", container.name, e));
                 }
             }
         }
-    
-        // start all container
+
+        Ok((created_containers, failures))
+    }
+
+    // the CRI requests run would send, built and checked without sending them:
+    // the sandbox id is a placeholder and the images are listed as pulled
+    // whatever their pull policy
+    pub fn dry_run_requests(&mut self) -> Result<Vec<dryrun::Request>, anyhow::Error> {
+        let pod_request = self.build_run_pod_sandbox_request()?;
+        self.sandbox_config = pod_request.config.clone();
+        let mut requests = vec![dryrun::Request::new("RunPodSandbox", &pod_request)];
+        for container in self.task.spec.running_containers() {
+            if !image::is_bundle_path(&container.image) {
+                requests.push(dryrun::Request::new("PullImage", &self.build_pull_image_request(container)?));
+            }
+            let request = self.container_request(dryrun::SANDBOX_ID, container, false)?;
+            requests.push(dryrun::Request::new("CreateContainer", &request));
+        }
+        Ok(requests)
+    }
+
+    // the checks of --dry-run=server: the images that aren't present and would
+    // be pulled, failing for those that can't be, asking the CRI runtime when
+    // there is one (it was probed for being ready when it connected)
+    pub fn check_images(&self) -> Result<Vec<String>, anyhow::Error> {
+        let root_path = rootpath::determine(None)?;
+        let mut pulled = Vec::new();
+        for container in self.task.spec.running_containers() {
+            if self.backend.is_none() && image::is_bundle_path(&container.image) {
+                if !Path::new(&container.image).exists() {
+                    return Err(anyhow!("Container {}: bundle directory {} does not exist", container.name, container.image));
+                }
+                continue;
+            }
+            let present = match &self.backend {
+                Some(backend) => {
+                    let image = self.build_pull_image_request(container)?.image;
+                    backend.image_status(ImageStatusRequest { image, verbose: false })?.image.is_some()
+                }
+                None => image::is_present(&root_path, &container.image),
+            };
+            match container.pull_policy() {
+                PullPolicy::Never if !present => {
+                    return Err(anyhow!("Container {}: image {} is not present and imagePullPolicy is Never", container.name, container.image));
+                }
+                PullPolicy::Always => pulled.push(container.image.clone()),
+                PullPolicy::IfNotPresent if !present => pulled.push(container.image.clone()),
+                _ => {}
+            }
+        }
+        Ok(pulled)
+    }
+
+    // run the pod, handling container failures according to the policy.
+    // on failure a PodRunError listing every failed container is returned
+    pub fn run(&mut self, policy: FailurePolicy) -> Result<String, anyhow::Error> {
+        let span = logging::pod_span(&self.task.metadata.namespace, &self.task.metadata.name);
+        let _span = span.enter();
+        let result = self.run_inner(policy);
+        match &result {
+            Ok(_) => metrics::PODS_STARTED.inc(),
+            Err(_) => metrics::PODS_FAILED.inc(),
+        }
+        result
+    }
+
+    fn run_inner(&mut self, policy: FailurePolicy) -> Result<String, anyhow::Error> {
+        // run PodSandbox（Pause container）
+        let pod_request = self.build_run_pod_sandbox_request()?;
+        let config = pod_request.config.as_ref().ok_or_else(|| anyhow!("PodSandbox config is required"))?;
+        self.sandbox_config = Some(config.clone());
+        let pod_response = self.run_pod_sandbox(pod_request)
+            .map_err(|e| anyhow!("Failed to run PodSandbox: {}", e))?;
+        let pod_sandbox_id = pod_response.pod_sandbox_id;
+        match self.pause_pid {
+            Some(pause_pid) => info!("PodSandbox (Pause) started: {}, pid: {}", pod_sandbox_id, pause_pid),
+            None if self.backend.is_some() => info!("PodSandbox started: {}", pod_sandbox_id),
+            None => return Err(anyhow!("Pause container PID not found for PodSandbox ID: {}", pod_sandbox_id)),
+        }
+
+        // create all container
+        let (created_containers, mut failures) = self.create_containers(&pod_sandbox_id, policy)?;
+        if !failures.is_empty() && policy == FailurePolicy::Rollback {
+            failures.extend(self.rollback(&pod_sandbox_id, &created_containers));
+            return Err(self.run_error(&pod_sandbox_id, created_containers, failures, true));
+        }
+
+        // start all container, the sidecars first, each once the one before is ready
         for container_id in &created_containers {
+            let _span = info_span!("container", container = %container_id).entered();
             let start_request = StartContainerRequest {
                 container_id: container_id.clone(),
             };
-            match self.start_container(start_request) {
+            let sidecar = self.task.spec.is_sidecar(container_id);
+            let result = self.start_container(start_request).and_then(|_| {
+                info!("Container started: {}", container_id);
+                self.events().normal(events::STARTED, &format!("Started container {}", container_id));
+                self.post_start(&pod_sandbox_id, container_id)
+            });
+            let result = result.and_then(|_| if sidecar { self.wait_sidecar_ready(&pod_sandbox_id, container_id) } else { Ok(()) });
+            match result {
                 Ok(_) => {
-                    println!("Container started: {}", container_id);
+                    self.set_container_state(container_id, ContainerStatus::new(container_id, ContainerState::Running));
                 }
                 Err(e) => {
-                    eprintln!("Failed to start container {}: {}", container_id, e);
-                    for container_id in &created_containers {
-                        let delete_args = Delete {
-                            container_id: container_id.clone(),
-                            force: true, 
-                        };
-                        let root_path = rootpath::determine(None)?;
-                        if let Err(delete_err) = delete::delete(delete_args, root_path.clone()) {
-                            eprintln!("Failed to delete container {} during rollback: {}", container_id, delete_err);
-                        } else {
-                            println!("Container deleted during rollback: {}", container_id);
-                        }
+                    warn!("Failed to start container {}: {}", container_id, e);
+                    self.events().warning(events::FAILED, &format!("Error: failed to start container {}: {}", container_id, e));
+                    self.set_container_state(container_id, ContainerStatus::failed(container_id, FailureStage::Start, &e));
+                    failures.push(ContainerFailure::new(container_id, FailureStage::Start, e));
+                    if policy == FailurePolicy::Rollback {
+                        failures.extend(self.rollback(&pod_sandbox_id, &created_containers));
+                        return Err(self.run_error(&pod_sandbox_id, created_containers, failures, true));
                     }
-    
-                    let stop_request = StopPodSandboxRequest {
-                        pod_sandbox_id: pod_sandbox_id.clone(),
-                    };
-                    if let Err(stop_err) = self.stop_pod_sandbox(stop_request) {
-                        eprintln!("Failed to stop PodSandbox {} during rollback: {}", pod_sandbox_id, stop_err);
-                    } else {
-                        println!("PodSandbox stopped during rollback: {}", pod_sandbox_id);
+                    // the containers don't start without their sidecars
+                    if sidecar {
+                        break;
                     }
-    
-                    let remove_request = RemovePodSandboxRequest {
-                        pod_sandbox_id: pod_sandbox_id.clone(),
-                    };
-                    if let Err(remove_err) = self.remove_pod_sandbox(remove_request) {
-                        eprintln!("Failed to remove PodSandbox {} during rollback: {}", pod_sandbox_id, remove_err);
-                    } else {
-                        println!("PodSandbox deleted during rollback: {}", pod_sandbox_id);
-                    }
-    
-                    return Err(anyhow!("Failed to start container {}: {}", container_id, e));
                 }
             }
         }
-    
+
+        if !failures.is_empty() {
+            return Err(self.run_error(&pod_sandbox_id, created_containers, failures, false));
+        }
+
         Ok(pod_sandbox_id)
     }
 
-}
\ No newline at end of file
+    fn set_container_state(&mut self, name: &str, status: ContainerStatus) {
+        match self.container_statuses.iter_mut().find(|s| s.name == name) {
+            Some(existing) => *existing = status,
+            None => self.container_statuses.push(status),
+        }
+    }
+
+    fn run_error(
+        &self,
+        pod_sandbox_id: &str,
+        created_containers: Vec<String>,
+        failures: Vec<ContainerFailure>,
+        rolled_back: bool,
+    ) -> anyhow::Error {
+        PodRunError {
+            pod_name: self.task.metadata.name.clone(),
+            pod_sandbox_id: pod_sandbox_id.to_string(),
+            created_containers,
+            container_statuses: self.container_statuses.clone(),
+            failures,
+            rolled_back,
+        }
+        .into()
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn runner(yaml: &str) -> TaskRunner {
+        let task: PodTask = serde_yaml::from_str(yaml).unwrap();
+        TaskRunner { task, pause_pid: None, sandbox_config: None, container_statuses: Vec::new(), network_status: None, backend: None }
+    }
+
+    #[test]
+    fn test_io_settings() {
+        let runner = runner(
+            r#"
+apiVersion: v1
+kind: Pod
+metadata:
+  name: io-pod
+spec:
+  containers:
+  - name: db
+    image: /bundle/db
+    ioWeight: 500
+    ioPriority:
+      class: RealTime
+      priority: 2
+  - name: batch
+    image: /bundle/batch
+    ioPriority:
+      class: Idle
+  - name: plain
+    image: /bundle/plain
+    ioWeight: 5000
+"#,
+        );
+        let containers = &runner.task.spec.containers;
+        let resources = io_resources(&containers[0]).unwrap().unwrap();
+        assert_eq!(resources.block_io().as_ref().and_then(|block_io| block_io.weight()), Some(500));
+        // as the process of the OCI spec has them
+        let priority = |container: &ContainerSpec| serde_json::to_value(io_priority(container).unwrap().unwrap()).unwrap();
+        assert_eq!(priority(&containers[0]), json!({"class": "IOPRIO_CLASS_RT", "priority": 2}));
+        // the default priority of a class
+        assert_eq!(priority(&containers[1]), json!({"class": "IOPRIO_CLASS_IDLE", "priority": 4}));
+        assert!(io_resources(&containers[1]).unwrap().is_none());
+        assert_eq!(io_resources(&containers[2]).unwrap_err().to_string(), "ioWeight must be between 10 and 1000");
+    }
+
+    #[test]
+    fn test_thp_guard_restores_the_previous_setting() {
+        use nix::sys::prctl::{get_thp_disable, set_thp_disable};
+        // no container is created meanwhile
+        let _lock = THP_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let guard_lock = std::sync::Mutex::new(());
+        let original = get_thp_disable().unwrap();
+        set_thp_disable(false).unwrap();
+
+        {
+            let _guard = ThpGuard::with_lock(&guard_lock, ThpMode::Never).unwrap();
+            assert!(get_thp_disable().unwrap());
+        }
+        assert!(!get_thp_disable().unwrap());
+        {
+            let _guard = ThpGuard::with_lock(&guard_lock, ThpMode::Inherit).unwrap();
+            assert!(!get_thp_disable().unwrap());
+        }
+        // a flag rkl already had stays set
+        set_thp_disable(true).unwrap();
+        drop(ThpGuard::with_lock(&guard_lock, ThpMode::Never).unwrap());
+        assert!(get_thp_disable().unwrap());
+        set_thp_disable(original).unwrap();
+    }
+
+    #[test]
+    fn test_host_namespaces() {
+        let runner = runner(
+            r#"
+apiVersion: v1
+kind: Pod
+metadata:
+  name: host-pod
+spec:
+  hostNetwork: true
+  hostIPC: true
+  containers: []
+"#,
+        );
+        let options = runner.namespace_options();
+        assert_eq!(options.network, NamespaceMode::Node as i32);
+        assert_eq!(options.pid, NamespaceMode::Container as i32);
+        assert_eq!(options.ipc, NamespaceMode::Node as i32);
+
+        let namespaces = build_namespaces(42, &options).unwrap();
+        let types: Vec<_> = namespaces.iter().map(|ns| ns.typ()).collect();
+        assert!(!types.contains(&LinuxNamespaceType::Network));
+        assert!(!types.contains(&LinuxNamespaceType::Ipc));
+        let pid = namespaces.iter().find(|ns| ns.typ() == LinuxNamespaceType::Pid).unwrap();
+        assert!(pid.path().is_none());
+    }
+
+    #[test]
+    fn test_port_mappings() {
+        let runner = runner(
+            r#"
+apiVersion: v1
+kind: Pod
+metadata:
+  name: ports-pod
+spec:
+  containers:
+    - name: web
+      image: /bundle/web
+      ports:
+        - containerPort: 80
+          hostPort: 8080
+        - containerPort: 53
+          hostPort: 8080
+          protocol: udp
+    - name: metrics
+      image: /bundle/metrics
+      ports:
+        - containerPort: 9090
+          hostPort: 8080
+          hostIP: 127.0.0.1
+          protocol: SCTP
+"#,
+        );
+        let mappings = runner.port_mappings().unwrap();
+        assert_eq!(mappings.len(), 3);
+        assert_eq!(mappings[1].protocol, Protocol::Udp as i32);
+        assert_eq!(mappings[2].protocol, Protocol::Sctp as i32);
+        assert_eq!(mappings[2].host_ip, "127.0.0.1");
+    }
+
+    #[test]
+    fn test_port_mappings_conflict() {
+        let runner = runner(
+            r#"
+apiVersion: v1
+kind: Pod
+metadata:
+  name: ports-pod
+spec:
+  containers:
+    - name: a
+      image: /bundle/a
+      ports:
+        - containerPort: 80
+          hostPort: 8080
+          hostIP: 127.0.0.1
+    - name: b
+      image: /bundle/b
+      ports:
+        - containerPort: 81
+          hostPort: 8080
+"#,
+        );
+        let err = runner.port_mappings().unwrap_err();
+        assert!(err.to_string().contains("conflicts"));
+    }
+
+    #[test]
+    fn test_share_process_namespace() {
+        let runner = runner(
+            r#"
+apiVersion: v1
+kind: Pod
+metadata:
+  name: shared-pod
+spec:
+  shareProcessNamespace: true
+  containers: []
+"#,
+        );
+        let options = runner.namespace_options();
+        assert_eq!(options.pid, NamespaceMode::Pod as i32);
+
+        let namespaces = build_namespaces(42, &options).unwrap();
+        let pid = namespaces.iter().find(|ns| ns.typ() == LinuxNamespaceType::Pid).unwrap();
+        assert_eq!(pid.path().as_ref().unwrap().to_str(), Some("/proc/42/ns/pid"));
+    }
+
+    #[test]
+    fn test_interactive_container_fields() {
+        let runner = runner(
+            r#"
+apiVersion: v1
+kind: Pod
+metadata:
+  name: debug-pod
+spec:
+  containers:
+    - name: shell
+      image: /bundle/busybox
+      workingDir: /tmp
+      stdin: true
+      stdinOnce: true
+      tty: true
+    - name: web
+      image: /bundle/web
+"#,
+        );
+        let shell = &runner.task.spec.containers[0];
+        assert_eq!(shell.working_dir, "/tmp");
+        assert!(shell.stdin && shell.stdin_once && shell.tty);
+        let web = &runner.task.spec.containers[1];
+        assert!(web.working_dir.is_empty());
+        assert!(!web.stdin_once);
+    }
+
+    #[test]
+    fn test_container_hash() {
+        let container = |resources: &str, image: &str| {
+            serde_yaml::from_str::<ContainerSpec>(&format!("name: app\nimage: {}\nresources:\n  limits:\n{}", image, resources)).unwrap()
+        };
+        let hash = container_hash(&container("    cpu: 500m\n    memory: 64Mi\n", "app:v1"));
+        // the same spec hashes the same whatever the order of its maps
+        assert_eq!(container_hash(&container("    memory: 64Mi\n    cpu: 500m\n", "app:v1")), hash);
+        assert_ne!(container_hash(&container("    cpu: 500m\n    memory: 64Mi\n", "app:v2")), hash);
+        // a container created before the hash was recorded has none
+        assert_eq!(running_container_hash(None, Path::new("/nonexistent"), "app"), None);
+    }
+}
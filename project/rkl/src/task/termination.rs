@@ -0,0 +1,88 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use crate::task::logs;
+use crate::task::task::{ContainerSpec, sandbox_dir};
+
+// Why a container died, like with the kubelet: a file of the sandbox directory
+// is mounted at the terminationMessagePath of every container, for it to write
+// a message into before it exits. Once the container exited the file is read,
+// or with terminationMessagePolicy FallbackToLogsOnError the tail of its log
+// when the file is empty and the container failed, and the message goes into
+// the status of the container in the pod file, which `rkl state` shows.
+
+pub const DEFAULT_TERMINATION_MESSAGE_PATH: &str = "/dev/termination-log";
+// the kubelet's limits
+const MAX_MESSAGE_BYTES: usize = 4096;
+const FALLBACK_LOG_LINES: usize = 80;
+const FALLBACK_LOG_BYTES: usize = 2048;
+
+// simulate Kubernetes TerminationMessagePolicy
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum TerminationMessagePolicy {
+    #[default]
+    File,
+    FallbackToLogsOnError,
+}
+
+pub fn default_termination_message_path() -> String {
+    DEFAULT_TERMINATION_MESSAGE_PATH.to_string()
+}
+
+// the file of the sandbox directory mounted into the container
+pub fn host_path(root_path: &Path, pod_sandbox_id: &str, container_name: &str) -> PathBuf {
+    sandbox_dir(root_path, pod_sandbox_id).join("termination").join(container_name)
+}
+
+// an empty file for the container to write into, every time it is created
+pub fn prepare(root_path: &Path, pod_sandbox_id: &str, container_name: &str) -> Result<PathBuf> {
+    let path = host_path(root_path, pod_sandbox_id, container_name);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, "")?;
+    Ok(path)
+}
+
+// the message of a container that exited with exit_code, None if it left none
+pub fn message(root_path: &Path, pod_name: &str, pod_sandbox_id: &str, container: &ContainerSpec, exit_code: Option<i32>) -> Option<String> {
+    let file = fs::read(host_path(root_path, pod_sandbox_id, &container.name)).unwrap_or_default();
+    let mut message = truncate(&file, MAX_MESSAGE_BYTES).trim().to_string();
+    if message.is_empty()
+        && container.termination_message_policy == TerminationMessagePolicy::FallbackToLogsOnError
+        && exit_code != Some(0)
+    {
+        let log = logs::read_container_log(pod_name, &container.name).ok()?;
+        message = tail(&log, FALLBACK_LOG_LINES, FALLBACK_LOG_BYTES).trim().to_string();
+    }
+    (!message.is_empty()).then_some(message)
+}
+
+fn truncate(bytes: &[u8], max: usize) -> String {
+    String::from_utf8_lossy(&bytes[..bytes.len().min(max)]).into_owned()
+}
+
+// the last lines of a log, at most max_bytes of them
+fn tail(log: &[u8], max_lines: usize, max_bytes: usize) -> String {
+    let log = String::from_utf8_lossy(log);
+    let lines: Vec<&str> = log.lines().collect();
+    let lines = lines[lines.len().saturating_sub(max_lines)..].join("\n");
+    let start = lines.len().saturating_sub(max_bytes);
+    // not in the middle of a character
+    let start = (start..=lines.len()).find(|i| lines.is_char_boundary(*i)).unwrap_or(lines.len());
+    lines[start..].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tail() {
+        let log = b"starting\nlistening on :8080\npanic: out of memory\n";
+        assert_eq!(tail(log, 2, 2048), "listening on :8080\npanic: out of memory");
+        assert_eq!(tail(log, 80, 20), "panic: out of memory");
+        assert_eq!(truncate("né".as_bytes(), 2), "n\u{fffd}");
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::HashSet;
+use anyhow::{Result, anyhow};
+use libcontainer::oci_spec::runtime::{PosixRlimit, PosixRlimitBuilder, PosixRlimitType, Process};
+use serde::{Deserialize, Deserializer, Serialize};
+
+// Resource limits of the processes of a container, an rk8s extension like the
+// --ulimit of docker, since databases and proxies usually need more open files
+// than the 1024 of the runtime, e.g.
+//
+//   ulimits:
+//     - name: nofile
+//       soft: 65536
+//       hard: 65536
+//     - name: memlock
+//       soft: unlimited
+//
+// name is one of NAMES, the resource of setrlimit(2) without RLIMIT_, and hard
+// is soft when left out. soft and hard are numbers or unlimited. The limits
+// replace those of the same resources the container would have, the others
+// are kept. A limit over the hard one of rkl itself can only be set by root.
+// CRI has no rlimits per container, so only the built-in runtime sets them.
+
+pub const NAMES: [&str; 16] = [
+    "as", "core", "cpu", "data", "fsize", "locks", "memlock", "msgqueue", "nice", "nofile", "nproc", "rss", "rtprio", "rttime", "sigpending",
+    "stack",
+];
+
+// RLIM_INFINITY
+const UNLIMITED: u64 = u64::MAX;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ulimit {
+    pub name: String,
+    #[serde(deserialize_with = "limit")]
+    pub soft: u64,
+    #[serde(default, deserialize_with = "optional_limit", skip_serializing_if = "Option::is_none")]
+    pub hard: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Limit {
+    Value(u64),
+    Name(String),
+}
+
+fn parse(limit: Limit) -> Result<u64, String> {
+    match limit {
+        Limit::Value(value) => Ok(value),
+        Limit::Name(name) if name == "unlimited" => Ok(UNLIMITED),
+        Limit::Name(name) => Err(format!("invalid limit {:?}, expected a number or unlimited", name)),
+    }
+}
+
+fn limit<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    parse(Limit::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+}
+
+fn optional_limit<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    limit(deserializer).map(Some)
+}
+
+impl Ulimit {
+    pub fn hard(&self) -> u64 {
+        self.hard.unwrap_or(self.soft)
+    }
+}
+
+fn resource(name: &str) -> Option<PosixRlimitType> {
+    let typ = match name {
+        "as" => PosixRlimitType::RlimitAs,
+        "core" => PosixRlimitType::RlimitCore,
+        "cpu" => PosixRlimitType::RlimitCpu,
+        "data" => PosixRlimitType::RlimitData,
+        "fsize" => PosixRlimitType::RlimitFsize,
+        "locks" => PosixRlimitType::RlimitLocks,
+        "memlock" => PosixRlimitType::RlimitMemlock,
+        "msgqueue" => PosixRlimitType::RlimitMsgqueue,
+        "nice" => PosixRlimitType::RlimitNice,
+        "nofile" => PosixRlimitType::RlimitNofile,
+        "nproc" => PosixRlimitType::RlimitNproc,
+        "rss" => PosixRlimitType::RlimitRss,
+        "rtprio" => PosixRlimitType::RlimitRtprio,
+        "rttime" => PosixRlimitType::RlimitRttime,
+        "sigpending" => PosixRlimitType::RlimitSigpending,
+        "stack" => PosixRlimitType::RlimitStack,
+        _ => return None,
+    };
+    Some(typ)
+}
+
+pub fn validate(ulimits: &[Ulimit]) -> Result<()> {
+    let mut names = HashSet::new();
+    for ulimit in ulimits {
+        if resource(&ulimit.name).is_none() {
+            return Err(anyhow!("unknown ulimit {}, expected one of {}", ulimit.name, NAMES.join(", ")));
+        }
+        if !names.insert(ulimit.name.as_str()) {
+            return Err(anyhow!("ulimit {} is set more than once", ulimit.name));
+        }
+        if ulimit.soft > ulimit.hard() {
+            return Err(anyhow!("ulimit {}: soft limit {} is over the hard limit {}", ulimit.name, ulimit.soft, ulimit.hard()));
+        }
+    }
+    Ok(())
+}
+
+// set the limits on the process of the container, over those it has
+pub fn add_to_process(process: &mut Process, ulimits: &[Ulimit]) -> Result<()> {
+    if ulimits.is_empty() {
+        return Ok(());
+    }
+    validate(ulimits)?;
+    let mut rlimits: Vec<PosixRlimit> = process.rlimits().clone().unwrap_or_default();
+    for ulimit in ulimits {
+        let typ = resource(&ulimit.name).ok_or_else(|| anyhow!("unknown ulimit {}", ulimit.name))?;
+        rlimits.retain(|rlimit| rlimit.typ() != typ);
+        rlimits.push(PosixRlimitBuilder::default().typ(typ).soft(ulimit.soft).hard(ulimit.hard()).build()?);
+    }
+    process.set_rlimits(Some(rlimits));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_to_process() {
+        let ulimits: Vec<Ulimit> = serde_yaml::from_str("- name: nofile\n  soft: 65536\n- name: memlock\n  soft: unlimited\n").unwrap();
+        let mut process = Process::default();
+        add_to_process(&mut process, &ulimits).unwrap();
+        let rlimits = process.rlimits().clone().unwrap();
+        let nofile: Vec<_> = rlimits.iter().filter(|rlimit| rlimit.typ() == PosixRlimitType::RlimitNofile).collect();
+        assert_eq!(nofile.len(), 1);
+        assert_eq!((nofile[0].soft(), nofile[0].hard()), (65536, 65536));
+        assert!(rlimits.iter().any(|rlimit| rlimit.typ() == PosixRlimitType::RlimitMemlock && rlimit.hard() == UNLIMITED));
+
+        let over: Vec<Ulimit> = serde_yaml::from_str("- name: nproc\n  soft: 200\n  hard: 100\n").unwrap();
+        assert_eq!(validate(&over).unwrap_err().to_string(), "ulimit nproc: soft limit 200 is over the hard limit 100");
+        let unknown: Vec<Ulimit> = serde_yaml::from_str("- name: files\n  soft: 10\n").unwrap();
+        assert!(validate(&unknown).is_err());
+        assert!(serde_yaml::from_str::<Vec<Ulimit>>("- name: nofile\n  soft: lots\n").is_err());
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use crate::cri::cri::{WindowsContainerConfig, WindowsPodSandboxConfig};
+#[cfg(feature = "windows")]
+use crate::cri::cri::{
+    NamespaceMode, WindowsContainerResources, WindowsContainerSecurityContext, WindowsNamespaceOption, WindowsSandboxSecurityContext,
+};
+#[cfg(feature = "windows")]
+use crate::quantity::Quantity;
+#[cfg(feature = "windows")]
+use crate::task::qos;
+use crate::task::task::{ContainerSpec, PodSpec, SecurityContext};
+
+// Windows pods, for a CRI runtime on a Windows node, containerd usually: a
+// pod with
+//
+//   spec:
+//     os:
+//       name: windows
+//     securityContext:
+//       windowsOptions:
+//         runAsUserName: ContainerUser
+//
+// gets the windows part of its sandbox and container configs instead of the
+// linux one, with the windowsOptions of the pod, those of a container over
+// them, and the cpu and memory limits of the container as the job object
+// limits of Windows: the cpu limit as a share of the CPUs of the node in
+// 1/10000ths, the cpu request as well when there is no limit. The Linux only
+// settings of rk8s, such as sysctls, ulimits, hostPID and hostIPC, are refused
+// for Windows pods, as are windowsOptions for Linux ones. rkl itself only runs
+// Linux containers, so Windows pods need a --runtime-endpoint. A pod with an
+// os also selects nodes of that os, kubernetes.io/os is added to its
+// nodeSelector, which may only select the same os itself; rks schedules it
+// the same way.
+// The configs are only built with the windows feature, `cargo build
+// --features windows`; without it Windows pods are validated and scheduled
+// like with it but refused by the node.
+
+pub const LINUX: &str = "linux";
+pub const WINDOWS: &str = "windows";
+
+// the node label of the os, see node.rs
+pub const OS_LABEL: &str = "kubernetes.io/os";
+
+// the whole node for the job object limits
+#[cfg(feature = "windows")]
+const CPU_MAXIMUM: i64 = 10_000;
+
+// simulate Kubernetes PodOS
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PodOs {
+    pub name: String,
+}
+
+// simulate Kubernetes WindowsSecurityContextOptions
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WindowsSecurityContextOptions {
+    // the contents of the GMSA credential spec, like the kubelet resolves gmsaCredentialSpecName
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gmsa_credential_spec: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_as_user_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host_process: Option<bool>,
+}
+
+fn windows_options(context: &Option<SecurityContext>) -> Option<&WindowsSecurityContextOptions> {
+    context.as_ref().and_then(|context| context.windows_options.as_ref())
+}
+
+// the options of the container over those of the pod
+fn merged(spec: &PodSpec, container: &ContainerSpec) -> WindowsSecurityContextOptions {
+    let pod = windows_options(&spec.security_context).cloned().unwrap_or_default();
+    let Some(container) = windows_options(&container.security_context) else {
+        return pod;
+    };
+    WindowsSecurityContextOptions {
+        gmsa_credential_spec: container.gmsa_credential_spec.clone().or(pod.gmsa_credential_spec),
+        run_as_user_name: container.run_as_user_name.clone().or(pod.run_as_user_name),
+        host_process: container.host_process.or(pod.host_process),
+    }
+}
+
+impl PodSpec {
+    pub fn is_windows(&self) -> bool {
+        self.os.as_ref().is_some_and(|os| os.name == WINDOWS)
+    }
+
+    // the nodeSelector with the os of the pod when it has one
+    pub fn effective_node_selector(&self) -> HashMap<String, String> {
+        let mut selector = self.node_selector.clone();
+        if let Some(os) = &self.os {
+            selector.entry(OS_LABEL.to_string()).or_insert_with(|| os.name.clone());
+        }
+        selector
+    }
+
+    // what a pod can't set for its os, like the API server checks it
+    pub fn validate_os(&self) -> Result<()> {
+        let name = self.os.as_ref().map(|os| os.name.as_str()).unwrap_or(LINUX);
+        if name != LINUX && name != WINDOWS {
+            return Err(anyhow!("os.name {:?} must be {} or {}", name, LINUX, WINDOWS));
+        }
+        if let (Some(os), Some(selected)) = (&self.os, self.node_selector.get(OS_LABEL))
+            && *selected != os.name
+        {
+            return Err(anyhow!("nodeSelector {}={} doesn't match os.name {}", OS_LABEL, selected, os.name));
+        }
+        let containers: Vec<&ContainerSpec> = self.init_containers.iter().chain(&self.sidecars).chain(&self.containers).collect();
+        if !self.is_windows() {
+            let windows = windows_options(&self.security_context).is_some() || containers.iter().any(|c| windows_options(&c.security_context).is_some());
+            return if windows { Err(anyhow!("windowsOptions can only be set with os.name windows")) } else { Ok(()) };
+        }
+        let linux_only = [
+            ("hostPID", self.host_pid),
+            ("hostIPC", self.host_ipc),
+            ("shareProcessNamespace", self.share_process_namespace),
+            ("securityContext.sysctls", !SecurityContext::sysctls(&self.security_context).is_empty()),
+        ];
+        if let Some((field, _)) = linux_only.iter().find(|(_, set)| *set) {
+            return Err(anyhow!("{} can't be set for a Windows pod", field));
+        }
+        for container in &containers {
+            let linux_only = [
+                ("securityContext.sysctls", !SecurityContext::sysctls(&container.security_context).is_empty()),
+                ("ulimits", !container.ulimits.is_empty()),
+                ("oomScoreAdj", container.oom_score_adj.is_some()),
+                ("oomKillPolicy", container.oom_kill_policy.is_some()),
+            ];
+            if let Some((field, _)) = linux_only.iter().find(|(_, set)| *set) {
+                return Err(anyhow!("container {}: {} can't be set for a Windows pod", container.name, field));
+            }
+        }
+        // HostProcess containers run on the node, all of them or none, like in Kubernetes
+        let host_process: Vec<bool> = containers.iter().map(|c| merged(self, c).host_process.unwrap_or(false)).collect();
+        if host_process.contains(&true) {
+            if host_process.contains(&false) {
+                return Err(anyhow!("either every container of a Windows pod is a HostProcess container or none"));
+            }
+            if !self.host_network {
+                return Err(anyhow!("HostProcess containers need hostNetwork"));
+            }
+        }
+        Ok(())
+    }
+}
+
+// whether this build runs Windows pods at all
+pub fn supported() -> bool {
+    cfg!(feature = "windows")
+}
+
+// the windows part of the sandbox config, None for Linux pods
+#[cfg(feature = "windows")]
+pub fn sandbox_config(spec: &PodSpec) -> Option<WindowsPodSandboxConfig> {
+    if !spec.is_windows() {
+        return None;
+    }
+    let options = windows_options(&spec.security_context).cloned().unwrap_or_default();
+    let network = if spec.host_network { NamespaceMode::Node } else { NamespaceMode::Pod };
+    Some(WindowsPodSandboxConfig {
+        security_context: Some(WindowsSandboxSecurityContext {
+            run_as_username: options.run_as_user_name.unwrap_or_default(),
+            credential_spec: options.gmsa_credential_spec.unwrap_or_default(),
+            host_process: options.host_process.unwrap_or(false),
+            namespace_options: Some(WindowsNamespaceOption { network: network as i32 }),
+        }),
+    })
+}
+
+// the job object limits of a container on a node with cpus CPUs
+#[cfg(feature = "windows")]
+pub fn container_resources(container: &ContainerSpec, cpus: i64) -> WindowsContainerResources {
+    // a share of the whole node, at least the smallest one
+    let share = |millis: i64| (CPU_MAXIMUM * millis / (1000 * cpus.max(1))).clamp(1, CPU_MAXIMUM);
+    let mut resources = WindowsContainerResources::default();
+    match container.resources.limits.get("cpu").map(Quantity::milli_value) {
+        Some(millis) => resources.cpu_maximum = share(millis),
+        None => resources.cpu_shares = qos::request(container, "cpu").map_or(0, |request| share(request.milli_value())),
+    }
+    if let Some(memory) = container.resources.limits.get("memory") {
+        resources.memory_limit_in_bytes = memory.value();
+    }
+    resources
+}
+
+// the windows part of the config of a container, None for Linux pods
+#[cfg(feature = "windows")]
+pub fn container_config(spec: &PodSpec, container: &ContainerSpec) -> Option<WindowsContainerConfig> {
+    if !spec.is_windows() {
+        return None;
+    }
+    let options = merged(spec, container);
+    let cpus = std::thread::available_parallelism().map_or(1, |cpus| cpus.get() as i64);
+    Some(WindowsContainerConfig {
+        resources: Some(container_resources(container, cpus)),
+        security_context: Some(WindowsContainerSecurityContext {
+            run_as_username: options.run_as_user_name.unwrap_or_default(),
+            credential_spec: options.gmsa_credential_spec.unwrap_or_default(),
+            host_process: options.host_process.unwrap_or(false),
+        }),
+    })
+}
+
+#[cfg(not(feature = "windows"))]
+pub fn sandbox_config(_spec: &PodSpec) -> Option<WindowsPodSandboxConfig> {
+    None
+}
+
+#[cfg(not(feature = "windows"))]
+pub fn container_config(_spec: &PodSpec, _container: &ContainerSpec) -> Option<WindowsContainerConfig> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::task::task::PodTask;
+
+    const POD: &str = r#"
+apiVersion: v1
+kind: Pod
+metadata:
+  name: iis
+spec:
+  os:
+    name: windows
+  securityContext:
+    windowsOptions:
+      runAsUserName: ContainerUser
+  containers:
+    - name: web
+      image: mcr.microsoft.com/windows/servercore/iis
+      securityContext:
+        windowsOptions:
+          runAsUserName: ContainerAdministrator
+      resources:
+        limits:
+          cpu: 500m
+          memory: 1Gi
+"#;
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn test_windows_configs() {
+        let task: PodTask = serde_yaml::from_str(POD).unwrap();
+        let spec = &task.spec;
+        spec.validate_os().unwrap();
+        let sandbox = sandbox_config(spec).unwrap().security_context.unwrap();
+        assert_eq!(sandbox.run_as_username, "ContainerUser");
+        assert_eq!(spec.effective_node_selector()[OS_LABEL], WINDOWS);
+
+        let container = container_config(spec, &spec.containers[0]).unwrap();
+        assert_eq!(container.security_context.unwrap().run_as_username, "ContainerAdministrator");
+        let resources = container_resources(&spec.containers[0], 4);
+        // half a CPU of 4
+        assert_eq!((resources.cpu_maximum, resources.cpu_shares, resources.memory_limit_in_bytes), (1250, 0, 1 << 30));
+
+        let sysctls: PodTask = serde_yaml::from_str(&POD.replace("      runAsUserName: ContainerUser", "      runAsUserName: ContainerUser\n    sysctls:\n      - name: net.core.somaxconn\n        value: \"1024\"")).unwrap();
+        assert_eq!(sysctls.spec.validate_os().unwrap_err().to_string(), "securityContext.sysctls can't be set for a Windows pod");
+        let linux: PodTask = serde_yaml::from_str(&POD.replace("name: windows", "name: linux")).unwrap();
+        assert!(linux.spec.validate_os().is_err());
+        assert!(container_config(&linux.spec, &linux.spec.containers[0]).is_none());
+    }
+
+    #[test]
+    fn test_windows_validation() {
+        let task: PodTask = serde_yaml::from_str(POD).unwrap();
+        task.spec.validate_os().unwrap();
+        assert_eq!(task.spec.effective_node_selector()[OS_LABEL], WINDOWS);
+        assert_eq!(sandbox_config(&task.spec).is_some(), supported());
+    }
+}
@@ -0,0 +1,446 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use serde_json::Value;
+use crate::values::Values;
+
+// Validation of manifests against the schemas of the kinds rkl knows,
+// subsets of the Kubernetes OpenAPI schemas with what rkl reads of them
+// (rkl/schemas/*.json). `rkl validate -f` reports, with the line and column of
+// the manifest,
+//
+//   pod.yaml:12:7: error: spec.containers[0].imagePullPolicy: expected one of "Always", "IfNotPresent", "Never"
+//   pod.yaml:18:5: warning: spec.serviceAccount: deprecated alias of serviceAccountName, ...
+//
+// fields that aren't in the schema (which rkl would silently ignore), values
+// of the wrong type or not among the allowed ones, missing required fields and
+// deprecated fields. Errors fail the command, warnings don't. `rkl apply`
+// validates its manifests the same way before sending them anywhere, and
+// `rkl explain` documents the fields from the descriptions of the schemas.
+
+const SCHEMAS: [(&str, &str); 3] = [
+    ("Pod", include_str!("../schemas/pod.json")),
+    ("ConfigMap", include_str!("../schemas/configmap.json")),
+    ("Secret", include_str!("../schemas/secret.json")),
+];
+// the definitions every schema can refer to
+const SHARED: [(&str, &str); 1] = [("ObjectMeta", include_str!("../schemas/objectmeta.json"))];
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Schema {
+    #[serde(rename = "type", default)]
+    pub value_type: Option<String>,
+    #[serde(default)]
+    pub properties: BTreeMap<String, Schema>,
+    #[serde(rename = "additionalProperties", default)]
+    pub additional_properties: Option<Box<Schema>>,
+    #[serde(default)]
+    pub items: Option<Box<Schema>>,
+    #[serde(default)]
+    pub required: Vec<String>,
+    #[serde(rename = "enum", default)]
+    pub allowed: Vec<Value>,
+    #[serde(default)]
+    pub deprecated: bool,
+    #[serde(default)]
+    pub description: String,
+    #[serde(rename = "$ref", default)]
+    pub reference: Option<String>,
+    #[serde(rename = "x-kubernetes-int-or-string", default)]
+    pub int_or_string: bool,
+    #[serde(default)]
+    pub definitions: BTreeMap<String, Schema>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    // 1-based, 0 when unknown
+    pub line: usize,
+    pub column: usize,
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}:{}: {}: ", self.line, self.column, severity)?;
+        if !self.path.is_empty() {
+            write!(f, "{}: ", self.path)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+fn load(text: &str) -> Schema {
+    serde_json::from_str(text).expect("embedded schema")
+}
+
+// the kinds there are schemas of
+pub fn kinds() -> Vec<&'static str> {
+    SCHEMAS.iter().map(|(kind, _)| *kind).collect()
+}
+
+// the schema of a kind with every definition its references can name
+pub fn schema(kind: &str) -> Option<(Schema, BTreeMap<String, Schema>)> {
+    let (_, text) = SCHEMAS.iter().find(|(schema_kind, _)| *schema_kind == kind)?;
+    let mut schema = load(text);
+    let mut definitions: BTreeMap<String, Schema> = SHARED.iter().map(|(name, text)| (name.to_string(), load(text))).collect();
+    definitions.append(&mut schema.definitions);
+    Some((schema, definitions))
+}
+
+// the path of a field as written in diagnostics, e.g. spec.containers[0].image
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() { key.to_string() } else { format!("{}.{}", path, key) }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(number) if number.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+// where the fields of a YAML document are, by path
+#[derive(Debug, Default)]
+struct Positions {
+    positions: BTreeMap<String, (usize, usize)>,
+}
+
+// an open mapping key or sequence item of the document, by its column
+struct Open {
+    column: usize,
+    path: String,
+    item: bool,
+}
+
+// the key of a `key: value` or `key:` line, None for anything else
+fn split_key(text: &str) -> Option<(String, &str)> {
+    if text.starts_with(['{', '[', '#', '&', '*', '!', '|', '>']) {
+        return None;
+    }
+    for quote in ['"', '\''] {
+        if let Some(rest) = text.strip_prefix(quote) {
+            let (key, rest) = rest.split_once(quote)?;
+            let rest = rest.trim_start().strip_prefix(':')?;
+            return Some((key.to_string(), rest));
+        }
+    }
+    let end = text.find(": ").or_else(|| text.strip_suffix(':').map(str::len))?;
+    Some((text[..end].trim_end().to_string(), &text[end + 1..]))
+}
+
+impl Positions {
+    // the positions of the block mappings and sequences of the document, the
+    // inside of flow collections and multi-line scalars is not indexed
+    fn index(document: &str, first_line: usize) -> Self {
+        let mut positions = BTreeMap::new();
+        let mut open: Vec<Open> = Vec::new();
+        let mut counters: BTreeMap<String, usize> = BTreeMap::new();
+        // the lines of a block scalar are more indented than its key
+        let mut block_scalar: Option<usize> = None;
+        for (number, line) in document.lines().enumerate() {
+            let trimmed = line.trim_start();
+            let mut column = line.len() - trimmed.len();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            if let Some(indent) = block_scalar {
+                if column > indent {
+                    continue;
+                }
+                block_scalar = None;
+            }
+            let line_number = first_line + number;
+            let mut text = trimmed;
+            loop {
+                if text == "-" || text.starts_with("- ") {
+                    // an item at the column of an open one is its sibling
+                    while open.last().is_some_and(|top| top.column > column || (top.column == column && top.item)) {
+                        open.pop();
+                    }
+                    let parent = open.last().map(|top| top.path.clone()).unwrap_or_default();
+                    let counter = counters.entry(parent.clone()).or_default();
+                    let path = format!("{}[{}]", parent, counter);
+                    *counter += 1;
+                    positions.insert(path.clone(), (line_number, column + 1));
+                    open.push(Open { column, path, item: true });
+                    let rest = text[1..].trim_start();
+                    column += text.len() - rest.len();
+                    text = rest;
+                    continue;
+                }
+                if let Some((key, value)) = split_key(text) {
+                    while open.last().is_some_and(|top| top.column >= column) {
+                        open.pop();
+                    }
+                    let path = join(&open.last().map(|top| top.path.clone()).unwrap_or_default(), &key);
+                    positions.insert(path.clone(), (line_number, column + 1));
+                    // a new mapping or sequence under the key starts counting anew
+                    counters.remove(&path);
+                    let value = value.trim_start();
+                    if value.starts_with('|') || value.starts_with('>') {
+                        block_scalar = Some(column);
+                    }
+                    open.push(Open { column, path, item: false });
+                }
+                break;
+            }
+        }
+        Positions { positions }
+    }
+
+    // the position of the path or of the closest parent that has one
+    fn find(&self, path: &str) -> (usize, usize) {
+        let mut path = path;
+        loop {
+            if let Some(position) = self.positions.get(path) {
+                return *position;
+            }
+            match path.rfind(['.', '[']) {
+                Some(end) => path = &path[..end],
+                None => return (0, 0),
+            }
+        }
+    }
+}
+
+struct Validator<'a> {
+    definitions: &'a BTreeMap<String, Schema>,
+    shared: &'a BTreeMap<String, Schema>,
+    positions: &'a Positions,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Validator<'a> {
+    fn report(&mut self, severity: Severity, path: &str, message: String) {
+        let (line, column) = self.positions.find(path);
+        self.diagnostics.push(Diagnostic { severity, line, column, path: path.to_string(), message });
+    }
+
+    fn resolve<'s>(&self, schema: &'s Schema) -> &'s Schema
+    where
+        'a: 's,
+    {
+        let Some(reference) = &schema.reference else {
+            return schema;
+        };
+        let name = reference.trim_start_matches("#/definitions/");
+        self.definitions.get(name).or_else(|| self.shared.get(name)).expect("embedded schema reference")
+    }
+
+    fn check(&mut self, schema: &Schema, value: &Value, path: &str) {
+        let schema = self.resolve(schema);
+        if schema.deprecated {
+            let message = if schema.description.is_empty() { "deprecated".to_string() } else { schema.description.clone() };
+            self.report(Severity::Warning, path, message);
+        }
+        if schema.int_or_string {
+            if !matches!(type_name(value), "integer" | "string") {
+                self.report(Severity::Error, path, format!("expected an integer or a string, got {}", type_name(value)));
+            }
+            return;
+        }
+        if let Some(expected) = &schema.value_type {
+            let actual = type_name(value);
+            if actual != expected && !(expected == "number" && actual == "integer") {
+                self.report(Severity::Error, path, format!("expected {}, got {}", expected, actual));
+                return;
+            }
+        }
+        if !schema.allowed.is_empty() && !schema.allowed.contains(value) {
+            let allowed: Vec<String> = schema.allowed.iter().map(Value::to_string).collect();
+            self.report(Severity::Error, path, format!("expected one of {}", allowed.join(", ")));
+        }
+        match value {
+            Value::Object(fields) => {
+                for required in schema.required.iter().filter(|required| !fields.contains_key(*required)) {
+                    self.report(Severity::Error, path, format!("missing required field {}", required));
+                }
+                for (key, field) in fields {
+                    let field_path = join(path, key);
+                    match (schema.properties.get(key), schema.additional_properties.as_deref()) {
+                        (Some(field_schema), _) | (None, Some(field_schema)) => self.check(field_schema, field, &field_path),
+                        (None, None) => self.report(Severity::Error, &field_path, "unknown field".to_string()),
+                    }
+                }
+            }
+            Value::Array(elements) => {
+                if let Some(items) = &schema.items {
+                    for (i, element) in elements.iter().enumerate() {
+                        self.check(items, element, &format!("{}[{}]", path, i));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// the documents of a YAML stream with the line each starts on
+fn documents(text: &str) -> Vec<(String, usize)> {
+    let mut documents = Vec::new();
+    let (mut current, mut first_line) = (String::new(), 1);
+    for (number, line) in text.lines().enumerate() {
+        if line == "---" || line.starts_with("--- ") {
+            documents.push((std::mem::take(&mut current), first_line));
+            first_line = number + 2;
+            continue;
+        }
+        current.push_str(line);
+        current.push('\n');
+    }
+    documents.push((current, first_line));
+    documents
+}
+
+fn validate_document(document: &str, first_line: usize, diagnostics: &mut Vec<Diagnostic>) {
+    let error = |line, column, message| Diagnostic { severity: Severity::Error, line, column, path: String::new(), message };
+    let value: Value = match serde_yaml::from_str(document) {
+        Ok(value) => value,
+        Err(e) => {
+            let (line, column) = e.location().map(|l| (first_line + l.line() - 1, l.column())).unwrap_or((first_line, 0));
+            diagnostics.push(error(line, column, format!("invalid YAML: {}", e)));
+            return;
+        }
+    };
+    match &value {
+        // an empty document
+        Value::Null => return,
+        Value::Object(_) => {}
+        other => {
+            diagnostics.push(error(first_line, 1, format!("expected a manifest object, got {}", type_name(other))));
+            return;
+        }
+    }
+    let positions = Positions::index(document, first_line);
+    let Some(kind) = value["kind"].as_str() else {
+        let (line, column) = positions.find("kind");
+        diagnostics.push(Diagnostic { path: "kind".to_string(), ..error(line.max(first_line), column.max(1), "missing or not a string".to_string()) });
+        return;
+    };
+    let Some((_, schema)) = SCHEMAS.iter().find(|(schema_kind, _)| *schema_kind == kind) else {
+        let (line, column) = positions.find("kind");
+        let message = format!("unsupported kind {}, expected one of {}", kind, kinds().join(", "));
+        diagnostics.push(Diagnostic { path: "kind".to_string(), ..error(line, column, message) });
+        return;
+    };
+    let schema = load(schema);
+    let shared: BTreeMap<String, Schema> = SHARED.iter().map(|(name, text)| (name.to_string(), load(text))).collect();
+    let mut validator = Validator { definitions: &schema.definitions, shared: &shared, positions: &positions, diagnostics: Vec::new() };
+    validator.check(&schema, &value, "");
+    diagnostics.extend(validator.diagnostics);
+}
+
+// the diagnostics of every document of the manifest
+pub fn validate(manifest: &str) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for (document, first_line) in documents(manifest) {
+        validate_document(&document, first_line, &mut diagnostics);
+    }
+    diagnostics
+}
+
+// print the diagnostics of the manifest, failing when there are errors
+pub fn check(manifest: &str, source: &str) -> Result<()> {
+    let diagnostics = validate(manifest);
+    for diagnostic in &diagnostics {
+        eprintln!("{}:{}", source, diagnostic);
+    }
+    let errors = diagnostics.iter().filter(|diagnostic| diagnostic.severity == Severity::Error).count();
+    if errors > 0 {
+        return Err(anyhow!("{}: {} error(s)", source, errors));
+    }
+    Ok(())
+}
+
+// `rkl validate`
+pub fn validate_file(path: &str, values: &Values) -> Result<()> {
+    let manifest = fs::read_to_string(path).map_err(|e| anyhow!("Failed to read {}: {}", path, e))?;
+    check(&values.render(&manifest)?, path)?;
+    println!("{} is valid", path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POD: &str = r#"apiVersion: v1
+kind: Pod
+metadata:
+  name: web
+  labels:
+    app: web
+spec:
+  serviceAccount: web
+  containers:
+    - name: app
+      image: app:v1
+      imagePullPolicy: Sometimes
+      ports:
+        - containerPort: "80"
+      args: |
+        --verbose
+        bogus: still the scalar
+    - image: proxy:v1
+      resources:
+        limits:
+          cpu: 500m
+          memory: 128
+      env:
+      - name: MODE
+        valeu: fast
+  restartPolicy: Never
+"#;
+
+    fn found(diagnostics: &[Diagnostic], path: &str) -> (Severity, usize, usize, String) {
+        let diagnostic = diagnostics.iter().find(|d| d.path == path).unwrap_or_else(|| panic!("no diagnostic for {}", path));
+        (diagnostic.severity, diagnostic.line, diagnostic.column, diagnostic.message.clone())
+    }
+
+    #[test]
+    fn test_validate() {
+        let diagnostics = validate(POD);
+        let (severity, line, column, _) = found(&diagnostics, "spec.serviceAccount");
+        assert_eq!((severity, line, column), (Severity::Warning, 8, 3));
+        let (severity, line, column, message) = found(&diagnostics, "spec.containers[0].imagePullPolicy");
+        assert_eq!((severity, line, column), (Severity::Error, 12, 7));
+        assert!(message.contains("\"IfNotPresent\""));
+        let (_, line, column, message) = found(&diagnostics, "spec.containers[0].ports[0].containerPort");
+        assert_eq!((line, column, message.as_str()), (14, 11, "expected integer, got string"));
+        // the args are a string, not a list, the block scalar isn't indexed
+        assert_eq!(found(&diagnostics, "spec.containers[0].args").1, 15);
+        let (_, line, column, message) = found(&diagnostics, "spec.containers[1]");
+        assert_eq!((line, column, message.as_str()), (18, 5, "missing required field name"));
+        let (_, line, column, message) = found(&diagnostics, "spec.containers[1].env[0].valeu");
+        assert_eq!((line, column, message.as_str()), (25, 9, "unknown field"));
+        assert_eq!(diagnostics.len(), 6, "{:?}", diagnostics);
+
+        let valid = "kind: ConfigMap\napiVersion: v1\nmetadata: {name: settings}\ndata:\n  mode: fast\n";
+        assert!(validate(valid).is_empty());
+        let diagnostics = validate(&format!("{}---\nkind: Deployment\n", valid));
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!((diagnostics[0].line, diagnostics[0].column, diagnostics[0].path.as_str()), (7, 1, "kind"));
+        assert_eq!(validate("kind: Pod\nmetadata: [\n")[0].message.split(':').next(), Some("invalid YAML"));
+        assert!(check(POD.replace("spec:\n", "spec:\n  hostNetwork: true\n").as_str(), "pod.yaml").is_err());
+    }
+}
@@ -0,0 +1,133 @@
+use std::fs;
+use anyhow::{Result, anyhow};
+use handlebars::Handlebars;
+use serde_json::{Map, Value};
+use crate::cache;
+
+// Manifests as templates: with --values or --set a manifest is rendered with
+// handlebars before it is parsed, e.g.
+//
+//   image: "registry.local/web:{{image.tag}}"
+//
+// rendered with `--values values.yaml --set image.tag=v2`. The values of the
+// files are merged in order, later ones win, and --set key.path=value (or
+// several separated by commas) wins over them; true, false and integers are
+// set as such, anything else as a string. A value the manifest uses but
+// nobody set is an error, nothing is HTML escaped. Without --values and --set
+// manifests are used as written, {{ included.
+
+#[derive(Debug, Clone, Default)]
+pub struct Values {
+    // None without --values and --set
+    values: Option<Value>,
+}
+
+// merge the value over the base, maps key by key
+fn merge(base: &mut Value, value: Value) {
+    match (base, value) {
+        (Value::Object(base), Value::Object(fields)) => {
+            for (key, value) in fields {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, value) => *base = value,
+    }
+}
+
+fn parse_scalar(text: &str) -> Value {
+    match text {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ => text.parse::<i64>().map(Value::from).unwrap_or_else(|_| Value::from(text)),
+    }
+}
+
+// key.path=value as the nested maps of the value
+fn parse_set(assignment: &str) -> Result<Value> {
+    let (path, value) = assignment
+        .split_once('=')
+        .ok_or_else(|| anyhow!("invalid --set {:?}: expected key=value", assignment))?;
+    let keys: Vec<&str> = path.trim().split('.').collect();
+    if keys.iter().any(|key| key.is_empty()) {
+        return Err(anyhow!("invalid --set {:?}: invalid key {:?}", assignment, path));
+    }
+    Ok(keys.iter().rev().fold(parse_scalar(value.trim()), |value, key| {
+        Value::Object(Map::from_iter([(key.to_string(), value)]))
+    }))
+}
+
+impl Values {
+    pub fn load(files: &[String], sets: &[String]) -> Result<Self> {
+        if files.is_empty() && sets.is_empty() {
+            return Ok(Values::default());
+        }
+        let mut values = Value::Object(Map::new());
+        for file in files {
+            let contents = fs::read_to_string(file).map_err(|e| anyhow!("Failed to read {}: {}", file, e))?;
+            let file_values: Value = serde_yaml::from_str(&contents).map_err(|e| anyhow!("Invalid values {}: {}", file, e))?;
+            match file_values {
+                // an empty file
+                Value::Null => {}
+                Value::Object(_) => merge(&mut values, file_values),
+                _ => return Err(anyhow!("Invalid values {}: expected a map", file)),
+            }
+        }
+        for set in sets {
+            for assignment in set.split(',').filter(|assignment| !assignment.trim().is_empty()) {
+                merge(&mut values, parse_set(assignment)?);
+            }
+        }
+        Ok(Values { values: Some(values) })
+    }
+
+    pub fn render(&self, manifest: &str) -> Result<String> {
+        let Some(values) = &self.values else {
+            return Ok(manifest.to_string());
+        };
+        let mut handlebars = Handlebars::new();
+        handlebars.set_strict_mode(true);
+        handlebars.register_escape_fn(handlebars::no_escape);
+        handlebars.render_template(manifest, values).map_err(|e| anyhow!("Failed to render the manifest: {}", e))
+    }
+}
+
+// the manifest of a file or remote source, rendered with the values
+pub fn read_manifest(source: &str, values: &Values) -> Result<String> {
+    values.render(&cache::read_manifest(source)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POD: &str = "metadata:\n  name: {{name}}\nspec:\n  containers:\n    - image: \"web:{{image.tag}}\"\n      args: [\"{{args}}\"]\n";
+
+    #[test]
+    fn test_render() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("values.yaml");
+        fs::write(&file, "name: web\nimage:\n  tag: v1\n  pull: Always\nargs: a<b\n").unwrap();
+        let file = file.display().to_string();
+
+        let values = Values::load(std::slice::from_ref(&file), &["image.tag=v2".to_string()]).unwrap();
+        assert_eq!(
+            values.render(POD).unwrap(),
+            "metadata:\n  name: web\nspec:\n  containers:\n    - image: \"web:v2\"\n      args: [\"a<b\"]\n"
+        );
+        let values = Values::load(&[file], &["name=db,image.pull=true".to_string()]).unwrap();
+        assert!(values.render(POD).unwrap().contains("name: db\n"));
+        assert_eq!(values.values.as_ref().unwrap()["image"]["pull"], Value::Bool(true));
+
+        // a value nobody set
+        assert!(Values::load(&[], &["name=web".to_string()]).unwrap().render(POD).is_err());
+        assert!(Values::load(&[], &["=web".to_string()]).is_err());
+        assert!(Values::load(&[], &["image..tag=v2".to_string()]).is_err());
+        // no templating without values
+        assert_eq!(Values::load(&[], &[]).unwrap().render(POD).unwrap(), POD);
+    }
+}
@@ -0,0 +1,287 @@
+use std::time::Duration;
+use anyhow::{Result, anyhow};
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tracing::warn;
+use crate::admission::AdmissionPlugin;
+use crate::task::task::PodTask;
+
+// Admission webhooks, like the dynamic admission control of Kubernetes: the
+// pod is POSTed to the url of the webhook as the object of an AdmissionReview
+// (admission.k8s.io/v1, operation CREATE) and the webhook answers with one
+// whose response allows the pod or not, with a status message telling why.
+// A mutating webhook may also return a base64 JSONPatch (RFC 6902) of the pod,
+// which is applied before the next plugin runs; a validating one only decides.
+// A webhook applies to the pods of its namespaces, of every namespace when it
+// has none. A webhook that can't be reached, times out or answers something
+// else than an AdmissionReview refuses the pod with failurePolicy Fail, the
+// default, and is skipped with Ignore. Webhooks see a pod every time it is
+// admitted, when `rkl daemon` restarts its containers as well, so mutations
+// have to leave a pod they already changed alone.
+
+pub const DEFAULT_TIMEOUT_SECONDS: u64 = 10;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookConfig {
+    pub name: String,
+    pub url: String,
+    #[serde(default)]
+    pub mutating: bool,
+    // every namespace when empty
+    #[serde(default)]
+    pub namespaces: Vec<String>,
+    #[serde(default = "default_timeout_seconds")]
+    pub timeout_seconds: u64,
+    #[serde(default)]
+    pub failure_policy: FailurePolicy,
+}
+
+fn default_timeout_seconds() -> u64 {
+    DEFAULT_TIMEOUT_SECONDS
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum FailurePolicy {
+    #[default]
+    Fail,
+    Ignore,
+}
+
+#[derive(Debug, Deserialize)]
+struct AdmissionReview {
+    response: Option<AdmissionResponse>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AdmissionResponse {
+    #[serde(default)]
+    uid: String,
+    allowed: bool,
+    #[serde(default)]
+    status: Option<ResponseStatus>,
+    #[serde(default)]
+    patch: Option<String>,
+    #[serde(default)]
+    patch_type: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResponseStatus {
+    #[serde(default)]
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PatchOperation {
+    pub op: String,
+    pub path: String,
+    #[serde(default)]
+    pub value: Option<Value>,
+    #[serde(default)]
+    pub from: Option<String>,
+}
+
+pub struct Webhook(pub WebhookConfig);
+
+impl Webhook {
+    fn applies(&self, task: &PodTask) -> bool {
+        self.0.namespaces.is_empty() || self.0.namespaces.contains(&task.metadata.namespace)
+    }
+
+    fn call(&self, task: &PodTask, object: &Value) -> Result<AdmissionResponse> {
+        let uid = uuid::Uuid::new_v4().to_string();
+        let review = json!({
+            "apiVersion": "admission.k8s.io/v1",
+            "kind": "AdmissionReview",
+            "request": {
+                "uid": uid,
+                "kind": {"group": "", "version": "v1", "kind": "Pod"},
+                "resource": {"group": "", "version": "v1", "resource": "pods"},
+                "name": task.metadata.name,
+                "namespace": task.metadata.namespace,
+                "operation": "CREATE",
+                "object": object,
+            },
+        });
+        let response = ureq::post(&self.0.url)
+            .timeout(Duration::from_secs(self.0.timeout_seconds))
+            .set("Content-Type", "application/json")
+            .send_string(&review.to_string())
+            .map_err(|e| anyhow!("failed to call {}: {}", self.0.url, e))?;
+        let review: AdmissionReview =
+            serde_json::from_str(&response.into_string()?).map_err(|e| anyhow!("invalid AdmissionReview from {}: {}", self.0.url, e))?;
+        let response = review.response.ok_or_else(|| anyhow!("the AdmissionReview from {} has no response", self.0.url))?;
+        if response.uid != uid {
+            return Err(anyhow!("the AdmissionReview from {} answers request {:?}, not {}", self.0.url, response.uid, uid));
+        }
+        Ok(response)
+    }
+
+    // the answer of the webhook, None when it failed and may be ignored
+    fn review(&self, task: &PodTask, object: &Value) -> Result<Option<AdmissionResponse>> {
+        match self.call(task, object) {
+            Ok(response) => Ok(Some(response)),
+            Err(e) if self.0.failure_policy == FailurePolicy::Ignore => {
+                warn!("Ignoring the admission webhook {} for pod {}: {:#}", self.0.name, task.metadata.name, e);
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+fn verdict(response: &AdmissionResponse) -> Result<()> {
+    if response.allowed {
+        return Ok(());
+    }
+    match response.status.as_ref().map(|status| status.message.as_str()).filter(|message| !message.is_empty()) {
+        Some(message) => Err(anyhow!("denied: {}", message)),
+        None => Err(anyhow!("denied without a reason")),
+    }
+}
+
+impl AdmissionPlugin for Webhook {
+    fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    fn mutate(&self, task: &mut PodTask) -> Result<()> {
+        if !self.0.mutating || !self.applies(task) {
+            return Ok(());
+        }
+        let mut object = serde_json::to_value(&*task)?;
+        let Some(response) = self.review(task, &object)? else {
+            return Ok(());
+        };
+        verdict(&response)?;
+        let Some(patch) = response.patch else {
+            return Ok(());
+        };
+        if let Some(patch_type) = response.patch_type.filter(|patch_type| patch_type != "JSONPatch") {
+            return Err(anyhow!("unsupported patch type {}, expected JSONPatch", patch_type));
+        }
+        let patch = base64::engine::general_purpose::STANDARD.decode(patch).map_err(|e| anyhow!("invalid base64 patch: {}", e))?;
+        let patch: Vec<PatchOperation> = serde_json::from_slice(&patch).map_err(|e| anyhow!("invalid JSONPatch: {}", e))?;
+        apply_patch(&mut object, &patch)?;
+        *task = serde_json::from_value(object).map_err(|e| anyhow!("the patched pod is invalid: {}", e))?;
+        Ok(())
+    }
+
+    fn validate(&self, task: &PodTask) -> Result<()> {
+        if self.0.mutating || !self.applies(task) {
+            return Ok(());
+        }
+        match self.review(task, &serde_json::to_value(task)?)? {
+            Some(response) => verdict(&response),
+            None => Ok(()),
+        }
+    }
+}
+
+// apply a JSONPatch, every operation of RFC 6902
+pub fn apply_patch(doc: &mut Value, patch: &[PatchOperation]) -> Result<()> {
+    for operation in patch {
+        let path = operation.path.as_str();
+        let value = || operation.value.clone().ok_or_else(|| anyhow!("{} of {} has no value", operation.op, path));
+        let from = || operation.from.as_deref().ok_or_else(|| anyhow!("{} to {} has no from", operation.op, path));
+        match operation.op.as_str() {
+            "add" => add(doc, path, value()?)?,
+            "remove" => {
+                remove(doc, path)?;
+            }
+            "replace" => {
+                remove(doc, path)?;
+                add(doc, path, value()?)?;
+            }
+            "move" => {
+                let moved = remove(doc, from()?)?;
+                add(doc, path, moved)?;
+            }
+            "copy" => {
+                let from = from()?;
+                let copied = doc.pointer(from).cloned().ok_or_else(|| anyhow!("nothing to copy at {}", from))?;
+                add(doc, path, copied)?;
+            }
+            "test" => {
+                if doc.pointer(path) != Some(&value()?) {
+                    return Err(anyhow!("test of {} failed", path));
+                }
+            }
+            op => return Err(anyhow!("unsupported patch operation {}", op)),
+        }
+    }
+    Ok(())
+}
+
+// the JSON pointer of the parent and the last token, unescaped
+fn split(path: &str) -> Result<(&str, String)> {
+    let i = path.rfind('/').ok_or_else(|| anyhow!("invalid JSON pointer {:?}", path))?;
+    Ok((&path[..i], path[i + 1..].replace("~1", "/").replace("~0", "~")))
+}
+
+fn add(doc: &mut Value, path: &str, value: Value) -> Result<()> {
+    if path.is_empty() {
+        *doc = value;
+        return Ok(());
+    }
+    let (parent, token) = split(path)?;
+    match doc.pointer_mut(parent) {
+        Some(Value::Object(map)) => {
+            map.insert(token, value);
+        }
+        // "-" appends
+        Some(Value::Array(items)) => {
+            let index = if token == "-" { Some(items.len()) } else { token.parse::<usize>().ok().filter(|i| *i <= items.len()) };
+            let index = index.ok_or_else(|| anyhow!("invalid array index {:?} in {}", token, path))?;
+            items.insert(index, value);
+        }
+        _ => return Err(anyhow!("nothing to add to at {}", path)),
+    }
+    Ok(())
+}
+
+fn remove(doc: &mut Value, path: &str) -> Result<Value> {
+    let (parent, token) = split(path)?;
+    let removed = match doc.pointer_mut(parent) {
+        Some(Value::Object(map)) => map.remove(&token),
+        Some(Value::Array(items)) => token.parse::<usize>().ok().filter(|i| *i < items.len()).map(|i| items.remove(i)),
+        _ => None,
+    };
+    removed.ok_or_else(|| anyhow!("nothing to remove at {}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_patch() {
+        let mut pod = json!({"metadata": {"labels": {"app": "web"}}, "spec": {"containers": [{"name": "app"}]}});
+        let patch: Vec<PatchOperation> = serde_json::from_value(json!([
+            {"op": "add", "path": "/spec/containers/-", "value": {"name": "proxy"}},
+            {"op": "add", "path": "/metadata/labels/sidecar.rk8s.io~1injected", "value": "true"},
+            {"op": "replace", "path": "/spec/containers/0/name", "value": "main"},
+            {"op": "copy", "from": "/metadata/labels/app", "path": "/metadata/labels/team"},
+            {"op": "test", "path": "/spec/containers/1/name", "value": "proxy"},
+            {"op": "remove", "path": "/metadata/labels/app"},
+        ]))
+        .unwrap();
+        apply_patch(&mut pod, &patch).unwrap();
+        assert_eq!(
+            pod,
+            json!({
+                "metadata": {"labels": {"sidecar.rk8s.io/injected": "true", "team": "web"}},
+                "spec": {"containers": [{"name": "main"}, {"name": "proxy"}]},
+            })
+        );
+
+        let failing: Vec<PatchOperation> = serde_json::from_value(json!([{"op": "remove", "path": "/spec/volumes"}])).unwrap();
+        assert!(apply_patch(&mut pod, &failing).is_err());
+        let response: AdmissionResponse =
+            serde_json::from_value(json!({"uid": "1", "allowed": false, "status": {"message": "image registry not allowed"}})).unwrap();
+        assert_eq!(verdict(&response).unwrap_err().to_string(), "denied: image registry not allowed");
+    }
+}
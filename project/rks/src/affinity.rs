@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+use serde::Deserialize;
+use crate::pb::scheduler::Node;
+
+// The affinity of a pod, like in Kubernetes:
+//   nodeAffinity     the node has to match one of the required terms, every
+//                    preferred term it matches adds its weight to its score
+//   podAffinity      a node is in the same topology domain as pods matching
+//                    the term, the nodes with the same value of the label
+//                    topologyKey; required terms have to hold, preferred ones
+//                    add their weight
+//   podAntiAffinity  the same but no matching pod may be in the domain, the
+//                    preferred terms cost their weight
+// Only the IgnoredDuringExecution variants exist, pods stay where they are
+// placed. The pods of a term are those of its namespaces, of the namespace of
+// the pod when it has none. A required anti-affinity of a placed pod keeps the
+// pods it matches out of its domain as well, and the first pod of a group that
+// is required to run next to its own kind may go anywhere.
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Affinity {
+    #[serde(default)]
+    pub node_affinity: Option<NodeAffinity>,
+    #[serde(default)]
+    pub pod_affinity: Option<PodAffinity>,
+    #[serde(default)]
+    pub pod_anti_affinity: Option<PodAffinity>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeAffinity {
+    #[serde(default)]
+    pub required_during_scheduling_ignored_during_execution: Option<NodeSelector>,
+    #[serde(default)]
+    pub preferred_during_scheduling_ignored_during_execution: Vec<PreferredSchedulingTerm>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeSelector {
+    #[serde(default)]
+    pub node_selector_terms: Vec<NodeSelectorTerm>,
+}
+
+// the requirements of a term all have to hold
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeSelectorTerm {
+    #[serde(default)]
+    pub match_expressions: Vec<Requirement>,
+    // only metadata.name
+    #[serde(default)]
+    pub match_fields: Vec<Requirement>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PreferredSchedulingTerm {
+    pub weight: i64,
+    pub preference: NodeSelectorTerm,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PodAffinity {
+    #[serde(default)]
+    pub required_during_scheduling_ignored_during_execution: Vec<PodAffinityTerm>,
+    #[serde(default)]
+    pub preferred_during_scheduling_ignored_during_execution: Vec<WeightedPodAffinityTerm>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PodAffinityTerm {
+    // no pods when unset
+    #[serde(default)]
+    pub label_selector: Option<LabelSelector>,
+    #[serde(default)]
+    pub namespaces: Vec<String>,
+    pub topology_key: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WeightedPodAffinityTerm {
+    pub weight: i64,
+    pub pod_affinity_term: PodAffinityTerm,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LabelSelector {
+    #[serde(default)]
+    pub match_labels: HashMap<String, String>,
+    #[serde(default)]
+    pub match_expressions: Vec<Requirement>,
+}
+
+// In, NotIn, Exists and DoesNotExist, and Gt and Lt for node labels
+#[derive(Debug, Clone, Deserialize)]
+pub struct Requirement {
+    pub key: String,
+    pub operator: String,
+    #[serde(default)]
+    pub values: Vec<String>,
+}
+
+impl Requirement {
+    fn matches(&self, value: Option<&String>) -> bool {
+        let number = |value: Option<&String>| value.and_then(|value| value.parse::<i64>().ok());
+        match self.operator.as_str() {
+            "In" => value.is_some_and(|value| self.values.contains(value)),
+            "NotIn" => value.is_none_or(|value| !self.values.contains(value)),
+            "Exists" => value.is_some(),
+            "DoesNotExist" => value.is_none(),
+            "Gt" | "Lt" => {
+                let (Some(value), Some(bound)) = (number(value), number(self.values.first())) else {
+                    return false;
+                };
+                if self.operator == "Gt" { value > bound } else { value < bound }
+            }
+            _ => false,
+        }
+    }
+}
+
+impl NodeSelectorTerm {
+    // an empty term matches no node
+    pub fn matches(&self, node: &Node) -> bool {
+        if self.match_expressions.is_empty() && self.match_fields.is_empty() {
+            return false;
+        }
+        self.match_expressions.iter().all(|requirement| requirement.matches(node.labels.get(&requirement.key)))
+            && self.match_fields.iter().all(|requirement| requirement.key == "metadata.name" && requirement.matches(Some(&node.name)))
+    }
+}
+
+impl LabelSelector {
+    pub fn matches(&self, labels: &HashMap<String, String>) -> bool {
+        self.match_labels.iter().all(|(key, value)| labels.get(key) == Some(value))
+            && self.match_expressions.iter().all(|requirement| requirement.matches(labels.get(&requirement.key)))
+    }
+}
+
+// a pod placed onto a node, as the affinity of other pods sees it
+pub struct Placed<'a> {
+    pub node: &'a Node,
+    pub namespace: &'a str,
+    pub labels: &'a HashMap<String, String>,
+    // its required anti-affinity terms
+    pub anti_affinity: &'a [PodAffinityTerm],
+}
+
+impl PodAffinityTerm {
+    // whether the term selects a pod of the namespace, for a pod of namespace
+    pub fn selects(&self, own_namespace: &str, namespace: &str, labels: &HashMap<String, String>) -> bool {
+        let in_namespace = if self.namespaces.is_empty() { namespace == own_namespace } else { self.namespaces.iter().any(|n| n == namespace) };
+        in_namespace && self.label_selector.as_ref().is_some_and(|selector| selector.matches(labels))
+    }
+
+    // whether the nodes are in the same domain of the topology key
+    fn same_domain(&self, a: &Node, b: &Node) -> bool {
+        a.labels.get(&self.topology_key).is_some_and(|value| b.labels.get(&self.topology_key) == Some(value))
+    }
+
+    // whether a pod the term selects is in the domain of the node
+    fn present(&self, own_namespace: &str, node: &Node, placed: &[Placed]) -> bool {
+        placed.iter().any(|pod| self.same_domain(node, pod.node) && self.selects(own_namespace, pod.namespace, pod.labels))
+    }
+}
+
+impl Affinity {
+    // whether the node matches the required node affinity, if any
+    pub fn node_matches(&self, node: &Node) -> bool {
+        let Some(required) = self.node_affinity.as_ref().and_then(|affinity| affinity.required_during_scheduling_ignored_during_execution.as_ref()) else {
+            return true;
+        };
+        required.node_selector_terms.iter().any(|term| term.matches(node))
+    }
+
+    // why the pod of namespace and labels can't go onto the node next to the
+    // placed pods, None if it can
+    pub fn pod_mismatch(&self, namespace: &str, labels: &HashMap<String, String>, node: &Node, placed: &[Placed]) -> Option<&'static str> {
+        let required = |affinity: &Option<PodAffinity>| {
+            affinity.as_ref().map(|affinity| affinity.required_during_scheduling_ignored_during_execution.clone()).unwrap_or_default()
+        };
+        for term in required(&self.pod_affinity) {
+            let first = !placed.iter().any(|pod| term.selects(namespace, pod.namespace, pod.labels)) && term.selects(namespace, namespace, labels);
+            if !first && !term.present(namespace, node, placed) {
+                return Some("didn't match pod affinity rules");
+            }
+        }
+        if required(&self.pod_anti_affinity).iter().any(|term| term.present(namespace, node, placed)) {
+            return Some("didn't match pod anti-affinity rules");
+        }
+        let repelled = placed.iter().any(|pod| {
+            pod.anti_affinity
+                .iter()
+                .any(|term| term.same_domain(node, pod.node) && term.selects(pod.namespace, namespace, labels))
+        });
+        if repelled {
+            return Some("didn't satisfy existing pods anti-affinity rules");
+        }
+        None
+    }
+
+    // the weights of the preferred terms the node matches, less those of the
+    // preferred anti-affinity terms
+    pub fn score(&self, namespace: &str, node: &Node, placed: &[Placed]) -> i64 {
+        let mut score = 0;
+        if let Some(affinity) = &self.node_affinity {
+            for term in &affinity.preferred_during_scheduling_ignored_during_execution {
+                if term.preference.matches(node) {
+                    score += term.weight;
+                }
+            }
+        }
+        let preferred = |affinity: &Option<PodAffinity>| {
+            affinity.as_ref().map(|affinity| affinity.preferred_during_scheduling_ignored_during_execution.clone()).unwrap_or_default()
+        };
+        for term in preferred(&self.pod_affinity) {
+            if term.pod_affinity_term.present(namespace, node, placed) {
+                score += term.weight;
+            }
+        }
+        for term in preferred(&self.pod_anti_affinity) {
+            if term.pod_affinity_term.present(namespace, node, placed) {
+                score -= term.weight;
+            }
+        }
+        score
+    }
+
+    // the terms that keep other pods away from this one
+    pub fn required_anti_affinity(&self) -> &[PodAffinityTerm] {
+        self.pod_anti_affinity.as_ref().map_or(&[], |affinity| &affinity.required_during_scheduling_ignored_during_execution)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(name: &str, zone: &str) -> Node {
+        let mut node = Node { name: name.to_string(), ..Default::default() };
+        node.labels.insert("kubernetes.io/hostname".to_string(), name.to_string());
+        node.labels.insert("topology.kubernetes.io/zone".to_string(), zone.to_string());
+        node
+    }
+
+    #[test]
+    fn test_affinity() {
+        let affinity: Affinity = serde_yaml::from_str(
+            "nodeAffinity:\n  requiredDuringSchedulingIgnoredDuringExecution:\n    nodeSelectorTerms:\n      - matchExpressions:\n          - {key: topology.kubernetes.io/zone, operator: In, values: [a, b]}\n  preferredDuringSchedulingIgnoredDuringExecution:\n    - weight: 20\n      preference:\n        matchExpressions:\n          - {key: topology.kubernetes.io/zone, operator: In, values: [b]}\npodAntiAffinity:\n  requiredDuringSchedulingIgnoredDuringExecution:\n    - labelSelector: {matchLabels: {app: web}}\n      topologyKey: kubernetes.io/hostname\npodAffinity:\n  requiredDuringSchedulingIgnoredDuringExecution:\n    - labelSelector: {matchLabels: {app: cache}}\n      topologyKey: topology.kubernetes.io/zone\n",
+        )
+        .unwrap();
+        let (a1, a2, b1, c1) = (node("a1", "a"), node("a2", "a"), node("b1", "b"), node("c1", "c"));
+        assert!(affinity.node_matches(&a1) && affinity.node_matches(&b1) && !affinity.node_matches(&c1));
+        assert_eq!(affinity.score("default", &b1, &[]), 20);
+
+        let web = HashMap::from([("app".to_string(), "web".to_string())]);
+        let cache = HashMap::from([("app".to_string(), "cache".to_string())]);
+        let placed = [
+            Placed { node: &a1, namespace: "default", labels: &cache, anti_affinity: &[] },
+            Placed { node: &a1, namespace: "default", labels: &web, anti_affinity: &[] },
+        ];
+        // next to the cache, but not on the node of the other web pod
+        assert_eq!(affinity.pod_mismatch("default", &web, &a2, &placed), None);
+        assert!(affinity.pod_mismatch("default", &web, &a1, &placed).is_some());
+        assert!(affinity.pod_mismatch("default", &web, &b1, &placed).is_some());
+        // the cache of another namespace doesn't count
+        assert!(affinity.pod_mismatch("other", &web, &a2, &placed).is_some());
+
+        // a placed pod keeps the pods its anti-affinity matches away
+        let plain = Affinity::default();
+        let repelling = [Placed { node: &b1, namespace: "default", labels: &cache, anti_affinity: affinity.required_anti_affinity() }];
+        assert!(plain.pod_mismatch("default", &web, &b1, &repelling).is_some());
+        assert_eq!(plain.pod_mismatch("default", &web, &a1, &repelling), None);
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use anyhow::{Result, anyhow};
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::{Request, Status};
+
+// Access control of the scheduler API. With --token-file every request needs a
+// bearer token of the file, one "<token> <user> <role>" line per token:
+//   admin   everything, e.g. scheduling pods, which rks forwards to the nodes
+//           with their admin tokens
+//   view    listing only, e.g. for dashboards
+//   node    registering nodes and renewing their leases, e.g. the bootstrap
+//           token of a node
+// A node stays bound to the user that registered it first: its address and
+// token, which rks sends pods and admin requests to, only change when the
+// same user registers it again, so every node should get a token of its own.
+// Without a token file rks only listens on a loopback address. Clients pass
+// their token in $RKS_TOKEN, kept off the command line.
+
+pub const TOKEN_ENV: &str = "RKS_TOKEN";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Admin,
+    View,
+    Node,
+}
+
+// what a request does to the cluster
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verb {
+    Read,
+    Write,
+    Register,
+}
+
+#[derive(Debug)]
+pub enum AuthError {
+    Unauthenticated,
+    Forbidden(String),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::Unauthenticated => write!(f, "a valid bearer token is required"),
+            AuthError::Forbidden(reason) => write!(f, "forbidden: {}", reason),
+        }
+    }
+}
+
+impl From<AuthError> for Status {
+    fn from(e: AuthError) -> Self {
+        match e {
+            AuthError::Unauthenticated => Status::unauthenticated(e.to_string()),
+            AuthError::Forbidden(_) => Status::permission_denied(e.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Authorizer {
+    // user and role by token, no authentication when unset
+    tokens: Option<HashMap<String, (String, Role)>>,
+}
+
+impl Authorizer {
+    pub fn new(token_file: Option<&Path>) -> Result<Self> {
+        let tokens = token_file
+            .map(|path| {
+                let contents = fs::read_to_string(path)
+                    .map_err(|e| anyhow!("Failed to read token file {}: {}", path.display(), e))?;
+                parse_tokens(&contents, &path.display().to_string())
+            })
+            .transpose()?;
+        Ok(Authorizer { tokens })
+    }
+
+    pub fn authenticates(&self) -> bool {
+        self.tokens.is_some()
+    }
+
+    // the user of the request, None without a token file
+    pub fn authorize<T>(&self, request: &Request<T>, verb: Verb) -> Result<Option<String>, AuthError> {
+        let Some(tokens) = &self.tokens else {
+            return Ok(None);
+        };
+        let (user, role) = request_token(request).and_then(|token| tokens.get(token)).ok_or(AuthError::Unauthenticated)?;
+        match (role, verb) {
+            (Role::Admin, _) | (Role::View, Verb::Read) | (Role::Node, Verb::Register) => Ok(Some(user.clone())),
+            (Role::View, _) => Err(AuthError::Forbidden(format!("{} has a view-only token", user))),
+            (Role::Node, _) => Err(AuthError::Forbidden(format!("{} has a node token", user))),
+        }
+    }
+}
+
+// bearer token of the authorization metadata of a request
+fn request_token<T>(request: &Request<T>) -> Option<&str> {
+    let header = request.metadata().get("authorization")?.to_str().ok()?;
+    let (scheme, token) = header.trim().split_once(' ')?;
+    scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+}
+
+fn parse_tokens(contents: &str, source: &str) -> Result<HashMap<String, (String, Role)>> {
+    let mut tokens = HashMap::new();
+    for (number, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [token, user, role] = fields[..] else {
+            return Err(anyhow!("{}:{}: expected \"<token> <user> <role>\"", source, number + 1));
+        };
+        let role = match role {
+            "admin" => Role::Admin,
+            "view" => Role::View,
+            "node" => Role::Node,
+            other => return Err(anyhow!("{}:{}: unknown role {}", source, number + 1, other)),
+        };
+        tokens.insert(token.to_string(), (user.to_string(), role));
+    }
+    Ok(tokens)
+}
+
+// sends the token of $RKS_TOKEN with every request of a client
+#[derive(Clone, Default)]
+pub struct Bearer(Option<MetadataValue<Ascii>>);
+
+impl Bearer {
+    pub fn from_env() -> Result<Self> {
+        match std::env::var(TOKEN_ENV) {
+            Ok(token) if !token.trim().is_empty() => {
+                let value = format!("Bearer {}", token.trim()).parse().map_err(|_| anyhow!("invalid ${}", TOKEN_ENV))?;
+                Ok(Bearer(Some(value)))
+            }
+            _ => Ok(Bearer(None)),
+        }
+    }
+}
+
+impl tonic::service::Interceptor for Bearer {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(authorization) = &self.0 {
+            request.metadata_mut().insert("authorization", authorization.clone());
+        }
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_authorize() {
+        let tokens = parse_tokens("# clients\ns3cret ops admin\nlook dashboard view\nb00t node-1 node\n", "tokens").unwrap();
+        let authorizer = Authorizer { tokens: Some(tokens) };
+        let request = |token: Option<&str>| {
+            let mut request = Request::new(());
+            if let Some(token) = token {
+                request.metadata_mut().insert("authorization", format!("Bearer {}", token).parse().unwrap());
+            }
+            request
+        };
+        assert_eq!(authorizer.authorize(&request(Some("s3cret")), Verb::Write).unwrap().as_deref(), Some("ops"));
+        assert!(authorizer.authorize(&request(Some("look")), Verb::Read).is_ok());
+        assert!(matches!(authorizer.authorize(&request(Some("look")), Verb::Write), Err(AuthError::Forbidden(_))));
+        assert!(matches!(authorizer.authorize(&request(Some("look")), Verb::Register), Err(AuthError::Forbidden(_))));
+        assert_eq!(authorizer.authorize(&request(Some("b00t")), Verb::Register).unwrap().as_deref(), Some("node-1"));
+        assert!(matches!(authorizer.authorize(&request(Some("b00t")), Verb::Read), Err(AuthError::Forbidden(_))));
+        assert!(matches!(authorizer.authorize(&request(None), Verb::Read), Err(AuthError::Unauthenticated)));
+        assert!(matches!(authorizer.authorize(&request(Some("guess")), Verb::Read), Err(AuthError::Unauthenticated)));
+        assert_eq!(Authorizer::default().authorize(&request(None), Verb::Write).unwrap(), None);
+
+        assert!(parse_tokens("s3cret ops root\n", "tokens").is_err());
+        assert!(parse_tokens("s3cret ops\n", "tokens").is_err());
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use serde_yaml::{Mapping, Value};
+use crate::pb::scheduler::Node;
+use crate::pod::{Metadata, PodManifest};
+use crate::schedule;
+
+// A DaemonSet runs one pod of its template on every ready node whose labels
+// and taints the template admits, named <daemonset>-<node>. Pods are created
+// on nodes as they register or start matching, and deleted from nodes that
+// stop matching; a node that stops registering loses its pod, which is created
+// there again once it is back. A changed template replaces the pods of the
+// other templates.
+
+pub const DAEMONSET_NAME: &str = "daemonset-name";
+pub const POD_TEMPLATE_HASH: &str = "pod-template-hash";
+
+#[derive(Debug, Deserialize)]
+pub struct DaemonSet {
+    #[serde(rename = "apiVersion")]
+    pub api_version: String,
+    pub kind: String,
+    pub metadata: Metadata,
+    pub spec: DaemonSetSpec,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DaemonSetSpec {
+    // kept as written, the pods are made of it
+    pub template: Value,
+}
+
+// FNV-1a, stable across builds unlike the std hasher
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3))
+}
+
+impl DaemonSet {
+    pub fn parse(manifest: &str) -> Result<Self> {
+        let daemonset: DaemonSet = serde_yaml::from_str(manifest).map_err(|e| anyhow!("invalid daemon set manifest: {}", e))?;
+        if daemonset.kind != "DaemonSet" || daemonset.api_version != "apps/v1" {
+            return Err(anyhow!("expected apiVersion apps/v1 and kind DaemonSet"));
+        }
+        let pod = PodManifest::parse(&daemonset.pod_manifest("node")?)?;
+        if pod.spec.containers.is_empty() {
+            return Err(anyhow!("the pod template has no containers"));
+        }
+        Ok(daemonset)
+    }
+
+    // the revision the pods are of, a hash of the template
+    pub fn template_hash(&self) -> String {
+        let template = serde_yaml::to_string(&self.spec.template).unwrap_or_default();
+        format!("{:016x}", fnv1a(template.as_bytes()))[..10].to_string()
+    }
+
+    pub fn pod_name(&self, node: &str) -> String {
+        format!("{}-{}", self.metadata.name, node)
+    }
+
+    // the manifest of the pod of the node
+    pub fn pod_manifest(&self, node: &str) -> Result<String> {
+        let mut metadata = self.spec.template.get("metadata").cloned().unwrap_or(Value::Null);
+        metadata["name"] = Value::from(self.pod_name(node));
+        // the pods are of the namespace of the daemon set
+        metadata["namespace"] = Value::from(self.metadata.namespace.as_str());
+        metadata["labels"][DAEMONSET_NAME] = Value::from(self.metadata.name.as_str());
+        metadata["labels"][POD_TEMPLATE_HASH] = Value::from(self.template_hash());
+        let spec = self.spec.template.get("spec").cloned().ok_or_else(|| anyhow!("the pod template has no spec"))?;
+        let mut pod = Mapping::new();
+        pod.insert("apiVersion".into(), "v1".into());
+        pod.insert("kind".into(), "Pod".into());
+        pod.insert("metadata".into(), metadata);
+        pod.insert("spec".into(), spec);
+        Ok(serde_yaml::to_string(&pod)?)
+    }
+}
+
+// a node as seen by the daemon set controller
+pub struct NodeView<'a> {
+    pub node: &'a Node,
+    pub ready: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Action {
+    // create the pod of the current template on the node
+    Create(String),
+    // delete the pod from the node
+    Delete(String),
+}
+
+// the changes that bring the pods, given as template hash by node, to one pod
+// of the current template on every matching ready node
+pub fn plan(daemonset: &DaemonSet, nodes: &[NodeView], pods: &HashMap<String, String>) -> Result<Vec<Action>> {
+    let hash = daemonset.template_hash();
+    let mut actions = Vec::new();
+    for view in nodes {
+        let name = &view.node.name;
+        let pod = PodManifest::parse(&daemonset.pod_manifest(name)?)?;
+        let eligible = view.ready && schedule::mismatch(&pod, view.node).is_none();
+        match pods.get(name) {
+            Some(pod_hash) if !eligible || *pod_hash != hash => {
+                actions.push(Action::Delete(name.clone()));
+                if eligible {
+                    actions.push(Action::Create(name.clone()));
+                }
+            }
+            None if eligible => actions.push(Action::Create(name.clone())),
+            _ => {}
+        }
+    }
+    // nodes that are gone altogether
+    for node in pods.keys() {
+        if !nodes.iter().any(|view| view.node.name == *node) {
+            actions.push(Action::Delete(node.clone()));
+        }
+    }
+    Ok(actions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::scheduler::Taint;
+
+    const DAEMONSET: &str = "
+apiVersion: apps/v1
+kind: DaemonSet
+metadata:
+  name: agent
+spec:
+  template:
+    metadata:
+      labels:
+        app: agent
+    spec:
+      nodeSelector:
+        role: worker
+      containers:
+        - name: agent
+          image: agent:v1
+";
+
+    #[test]
+    fn test_plan() {
+        let daemonset = DaemonSet::parse(DAEMONSET).unwrap();
+        let node = |name: &str, role: &str| {
+            let mut node = Node { name: name.to_string(), ..Default::default() };
+            node.labels.insert("role".to_string(), role.to_string());
+            node
+        };
+        let (a, b, c) = (node("a", "worker"), node("b", "worker"), node("c", "control"));
+        let mut tainted = node("d", "worker");
+        tainted.taints.push(Taint { key: "maintenance".to_string(), value: String::new(), effect: "NoSchedule".to_string() });
+        let nodes = [
+            NodeView { node: &a, ready: true },
+            NodeView { node: &b, ready: false },
+            NodeView { node: &c, ready: true },
+            NodeView { node: &tainted, ready: true },
+        ];
+        assert_eq!(plan(&daemonset, &nodes, &HashMap::new()).unwrap(), vec![Action::Create("a".to_string())]);
+
+        // b isn't ready anymore and e is gone
+        let hash = daemonset.template_hash();
+        let pods = HashMap::from([("a".to_string(), hash.clone()), ("b".to_string(), hash), ("e".to_string(), "old".to_string())]);
+        let actions = plan(&daemonset, &nodes, &pods).unwrap();
+        assert_eq!(actions, vec![Action::Delete("b".to_string()), Action::Delete("e".to_string())]);
+        let pods = HashMap::from([("a".to_string(), "old".to_string())]);
+        let actions = plan(&daemonset, &nodes[..1], &pods).unwrap();
+        assert_eq!(actions, vec![Action::Delete("a".to_string()), Action::Create("a".to_string())]);
+
+        let manifest = PodManifest::parse(&daemonset.pod_manifest("a").unwrap()).unwrap();
+        assert_eq!(manifest.metadata.name, "agent-a");
+        assert_eq!(manifest.metadata.labels.get(DAEMONSET_NAME).map(String::as_str), Some("agent"));
+        assert_eq!(manifest.metadata.namespace, "default");
+        let monitoring = DaemonSet::parse(&DAEMONSET.replace("  name: agent", "  name: agent\n  namespace: monitoring")).unwrap();
+        let manifest = PodManifest::parse(&monitoring.pod_manifest("a").unwrap()).unwrap();
+        assert_eq!(manifest.metadata.namespace, "monitoring");
+        assert!(DaemonSet::parse(&DAEMONSET.replace("DaemonSet", "Deployment")).is_err());
+    }
+}
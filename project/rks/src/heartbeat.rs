@@ -0,0 +1,98 @@
+use std::time::{Duration, Instant};
+
+// Node heartbeats, like the node leases of Kubernetes: besides registering
+// with its status every few seconds a node renews a lease of its own, a small
+// RenewNodeLease call with how long the node is ready for without another one
+// (--node-lease-duration of `rkl daemon`). A node with a lease is NotReady once
+// it expired, one that never renewed once it didn't register again within
+// --node-timeout. A node that stays NotReady for --pod-eviction-timeout has
+// its pods scheduled onto other nodes, except those of the daemon sets; when
+// it comes back it is told to delete the pods it lost. The leases are kept as
+// /registry/leases/kube-node-lease/<node> in the store.
+
+pub const LEASES_PREFIX: &str = "/registry/leases/kube-node-lease/";
+// how often the nodes are checked
+pub const MONITOR_PERIOD: Duration = Duration::from_secs(5);
+pub const DEFAULT_POD_EVICTION_TIMEOUT: u64 = 300;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct NodeLease {
+    renewed: Instant,
+    duration: Duration,
+}
+
+// a change of the readiness of a node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transition {
+    NotReady,
+    Ready,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Health {
+    lease: Option<NodeLease>,
+    // since when the monitor sees the node NotReady
+    not_ready_since: Option<Instant>,
+}
+
+impl Health {
+    pub fn renew(&mut self, duration: Duration, now: Instant) {
+        self.lease = Some(NodeLease { renewed: now, duration });
+    }
+
+    // last_seen is the last registration of the node
+    pub fn ready(&self, last_seen: Instant, node_timeout: Duration, now: Instant) -> bool {
+        match self.lease {
+            Some(lease) => now.duration_since(lease.renewed) < lease.duration,
+            None => now.duration_since(last_seen) < node_timeout,
+        }
+    }
+
+    // record what the monitor sees, returning how the node changed
+    pub fn observe(&mut self, ready: bool, now: Instant) -> Option<Transition> {
+        match (ready, self.not_ready_since) {
+            (true, Some(_)) => {
+                self.not_ready_since = None;
+                Some(Transition::Ready)
+            }
+            (false, None) => {
+                self.not_ready_since = Some(now);
+                Some(Transition::NotReady)
+            }
+            _ => None,
+        }
+    }
+
+    // whether the pods of the node are to be scheduled elsewhere
+    pub fn eviction_due(&self, timeout: Duration, now: Instant) -> bool {
+        self.not_ready_since.is_some_and(|since| now.duration_since(since) >= timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health() {
+        let start = Instant::now();
+        let timeout = Duration::from_secs(30);
+        let mut health = Health::default();
+        // without a lease the registrations count
+        assert!(health.ready(start, timeout, start + Duration::from_secs(10)));
+        assert!(!health.ready(start, timeout, start + timeout));
+
+        health.renew(Duration::from_secs(40), start);
+        assert!(health.ready(start, timeout, start + Duration::from_secs(35)));
+        let expired = start + Duration::from_secs(40);
+        assert!(!health.ready(start, timeout, expired));
+
+        let eviction_timeout = Duration::from_secs(300);
+        assert_eq!(health.observe(false, expired), Some(Transition::NotReady));
+        assert_eq!(health.observe(false, expired + MONITOR_PERIOD), None);
+        assert!(!health.eviction_due(eviction_timeout, expired + MONITOR_PERIOD));
+        assert!(health.eviction_due(eviction_timeout, expired + eviction_timeout));
+        assert_eq!(health.observe(true, expired + eviction_timeout), Some(Transition::Ready));
+        assert!(!health.eviction_due(eviction_timeout, expired + eviction_timeout * 2));
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::{BTreeMap, HashMap};
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use serde_yaml::{Mapping, Value};
+use crate::pod::{self, Metadata, PodManifest};
+
+// A LimitRange gives the containers of the new pods of its namespace what
+// they leave unset and bounds what they ask for, like in Kubernetes:
+//   default         the limits of a container without them
+//   defaultRequest  the requests of a container with neither requests nor
+//                   limits, the default limits when unset
+//   min, max        a container has to request at least min and be limited
+//                   to at most max
+// Only the cpu and memory of Container limits are supported. The defaults are
+// written into the manifest the node gets, so the containers run with them,
+// before the resource quotas of the namespace count the pod, see quota. A
+// pod out of bounds is refused naming the bound. Limit ranges are kept as
+// /registry/limitranges/<name> in the store, their names are unique across
+// the cluster like those of daemon sets.
+
+const RESOURCES: [&str; 2] = ["cpu", "memory"];
+
+#[derive(Debug, Deserialize)]
+pub struct LimitRange {
+    #[serde(rename = "apiVersion")]
+    pub api_version: String,
+    pub kind: String,
+    pub metadata: Metadata,
+    pub spec: LimitRangeSpec,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LimitRangeSpec {
+    #[serde(default)]
+    pub limits: Vec<LimitRangeItem>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LimitRangeItem {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub default: BTreeMap<String, Value>,
+    #[serde(default)]
+    pub default_request: BTreeMap<String, Value>,
+    #[serde(default)]
+    pub min: BTreeMap<String, Value>,
+    #[serde(default)]
+    pub max: BTreeMap<String, Value>,
+}
+
+fn amount(resource: &str, value: &Value) -> Result<i64> {
+    let quantity = pod::quantity_string(value);
+    match resource {
+        "cpu" => pod::parse_cpu(&quantity),
+        "memory" => pod::parse_memory(&quantity),
+        _ => Err(anyhow!("unsupported resource {}, expected cpu or memory", resource)),
+    }
+}
+
+// "resource=quantity" as written, comma separated
+pub fn written(quantities: &BTreeMap<String, Value>) -> String {
+    quantities.iter().map(|(resource, value)| format!("{}={}", resource, pod::quantity_string(value))).collect::<Vec<_>>().join(",")
+}
+
+impl LimitRange {
+    pub fn parse(manifest: &str) -> Result<Self> {
+        let range: LimitRange = serde_yaml::from_str(manifest).map_err(|e| anyhow!("invalid limit range manifest: {}", e))?;
+        if range.kind != "LimitRange" || range.api_version != "v1" {
+            return Err(anyhow!("expected apiVersion v1 and kind LimitRange"));
+        }
+        for item in &range.spec.limits {
+            if item.kind != "Container" {
+                return Err(anyhow!("unsupported limit type {}, only Container is", item.kind));
+            }
+            for quantities in [&item.default, &item.default_request, &item.min, &item.max] {
+                for (resource, value) in quantities {
+                    amount(resource, value)?;
+                }
+            }
+        }
+        Ok(range)
+    }
+
+    // the defaults a container is missing, as (requests or limits, resource, quantity)
+    fn defaults(&self, container: &Value) -> Vec<(&'static str, &'static str, Value)> {
+        let set = |field: &str, resource: &str| container.get("resources").and_then(|r| r.get(field)).and_then(|f| f.get(resource)).is_some();
+        let mut defaults = Vec::new();
+        for item in &self.spec.limits {
+            for resource in RESOURCES {
+                let limit = set("limits", resource);
+                if !limit && let Some(default) = item.default.get(resource) {
+                    defaults.push(("limits", resource, default.clone()));
+                }
+                // a limit without a request is also the request, see pod
+                if !limit
+                    && !set("requests", resource)
+                    && let Some(default) = item.default_request.get(resource).or_else(|| item.default.get(resource))
+                {
+                    defaults.push(("requests", resource, default.clone()));
+                }
+            }
+        }
+        defaults
+    }
+
+    // why a container is out of bounds, None if it isn't
+    fn violation(&self, resources: &pod::ResourceRequirements) -> Result<Option<String>> {
+        let quantity = |quantities: &HashMap<String, Value>, resource: &str| {
+            quantities.get(resource).map(|value| Ok::<_, anyhow::Error>((amount(resource, value)?, pod::quantity_string(value)))).transpose()
+        };
+        for item in &self.spec.limits {
+            for resource in RESOURCES {
+                let limit = quantity(&resources.limits, resource)?;
+                let request = quantity(&resources.requests, resource)?.or_else(|| limit.clone());
+                if let Some(max) = item.max.get(resource) {
+                    let bound = pod::quantity_string(max);
+                    match limit {
+                        None => return Ok(Some(format!("maximum {} usage per Container is {}. No limit is specified", resource, bound))),
+                        Some((limit, written)) if limit > amount(resource, max)? => {
+                            return Ok(Some(format!("maximum {} usage per Container is {}, but limit is {}", resource, bound, written)));
+                        }
+                        _ => {}
+                    }
+                }
+                if let Some(min) = item.min.get(resource) {
+                    let bound = pod::quantity_string(min);
+                    match request {
+                        None => return Ok(Some(format!("minimum {} usage per Container is {}. No request is specified", resource, bound))),
+                        Some((request, written)) if request < amount(resource, min)? => {
+                            return Ok(Some(format!("minimum {} usage per Container is {}, but request is {}", resource, bound, written)));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+// the pod of the manifest with the defaults of the limit ranges of its
+// namespace, refused when out of their bounds; a manifest without anything to
+// default stays as it is
+pub fn admit(manifest: &str, ranges: &[&LimitRange]) -> Result<(PodManifest, String)> {
+    let mut value: Value = serde_yaml::from_str(manifest).map_err(|e| anyhow!("invalid pod manifest: {}", e))?;
+    let mut changed = false;
+    for field in ["containers", "init_containers"] {
+        let Some(containers) = value.get_mut("spec").and_then(|spec| spec.get_mut(field)).and_then(Value::as_sequence_mut) else {
+            continue;
+        };
+        for container in containers {
+            for range in ranges {
+                for (kind, resource, default) in range.defaults(container) {
+                    let Some(container) = container.as_mapping_mut() else {
+                        continue;
+                    };
+                    let resources = mapping(container, "resources");
+                    mapping(resources, kind).insert(Value::from(resource), default);
+                    changed = true;
+                }
+            }
+        }
+    }
+    let manifest = if changed { serde_yaml::to_string(&value)? } else { manifest.to_string() };
+    let pod = PodManifest::parse(&manifest)?;
+    for container in pod.all_containers() {
+        for range in ranges {
+            if let Some(violation) = range.violation(&container.resources)? {
+                return Err(anyhow!("pod {} is forbidden: {}", pod.metadata.name, violation));
+            }
+        }
+    }
+    Ok((pod, manifest))
+}
+
+// the mapping under key, replacing whatever else is there
+fn mapping<'a>(parent: &'a mut Mapping, key: &str) -> &'a mut Mapping {
+    if !parent.get(key).is_some_and(Value::is_mapping) {
+        parent.insert(Value::from(key), Value::Mapping(Mapping::new()));
+    }
+    parent.get_mut(key).and_then(Value::as_mapping_mut).expect("inserted above")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RANGE: &str = "apiVersion: v1\nkind: LimitRange\nmetadata:\n  name: containers\n  namespace: team-a\nspec:\n  limits:\n    - type: Container\n      default:\n        cpu: 500m\n        memory: 256Mi\n      defaultRequest:\n        cpu: 100m\n      max:\n        cpu: 2\n";
+
+    fn manifest(resources: &str) -> String {
+        format!(
+            "apiVersion: v1\nkind: Pod\nmetadata:\n  name: web\n  namespace: team-a\nspec:\n  containers:\n    - name: app\n      image: app:v1\n{}",
+            resources
+        )
+    }
+
+    #[test]
+    fn test_admit() {
+        let range = LimitRange::parse(RANGE).unwrap();
+        let (pod, defaulted) = admit(&manifest(""), &[&range]).unwrap();
+        assert_eq!(pod.requests().unwrap(), pod::Requests { cpu_millis: 100, memory_bytes: 256 << 20 });
+        assert_eq!(pod.limits().unwrap(), pod::Requests { cpu_millis: 500, memory_bytes: 256 << 20 });
+        assert!(defaulted.contains("256Mi"));
+
+        // a limit stands for the request, a manifest with everything set stays untouched
+        let limited = manifest("      resources:\n        limits:\n          cpu: 1\n          memory: 1Gi\n");
+        let (pod, unchanged) = admit(&limited, &[&range]).unwrap();
+        assert_eq!(pod.requests().unwrap(), pod::Requests { cpu_millis: 1000, memory_bytes: 1 << 30 });
+        assert_eq!(unchanged, limited);
+
+        let large = manifest("      resources:\n        limits:\n          cpu: 4\n");
+        assert_eq!(
+            admit(&large, &[&range]).unwrap_err().to_string(),
+            "pod web is forbidden: maximum cpu usage per Container is 2, but limit is 4"
+        );
+        assert!(LimitRange::parse(&RANGE.replace("type: Container", "type: Pod")).is_err());
+    }
+}
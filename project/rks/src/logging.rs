@@ -0,0 +1,169 @@
+use std::fmt;
+use anyhow::{Result, anyhow};
+use tonic::codegen::http;
+use tonic::metadata::MetadataMap;
+use tracing::span::{Attributes, Id};
+use tracing::field::{Field, Visit};
+use tracing::{Span, Subscriber};
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::registry::{LookupSpan, Registry};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+// What `rks serve` logs goes through tracing to stderr, at info or per
+// RUST_LOG; the output of the other commands stays on stdout. Every request
+// rks serves has a span in the trace of the W3C traceparent it came with, e.g.
+// from rkl, or in a new one, and so have the rounds of the node monitor and of
+// the daemon set sync. The trace of the current span goes along with the
+// requests rks makes to the nodes, whose rkl daemons log their side in it.
+
+pub const TRACEPARENT: &str = "traceparent";
+
+// set up once by `rks serve`
+pub fn init() -> Result<()> {
+    let filter = match std::env::var("RUST_LOG") {
+        Ok(directives) if !directives.is_empty() => EnvFilter::new(directives),
+        _ => EnvFilter::new("warn,rks=info,store=info"),
+    };
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(TraceLayer)
+        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stderr).with_target(false))
+        .try_init()
+        .map_err(|e| anyhow!("Failed to set up logging: {}", e))
+}
+
+// the trace a span belongs to, kept in its extensions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceId(u128);
+
+impl TraceId {
+    fn random() -> Self {
+        TraceId(uuid::Uuid::new_v4().as_u128())
+    }
+}
+
+impl fmt::Display for TraceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+// the trace id of a traceparent, 00-<trace id>-<parent id>-<flags>
+pub fn parse_traceparent(traceparent: &str) -> Option<TraceId> {
+    let mut parts = traceparent.trim().split('-');
+    let (version, trace_id, parent_id) = (parts.next()?, parts.next()?, parts.next()?);
+    if version != "00" || trace_id.len() != 32 || parent_id.len() != 16 {
+        return None;
+    }
+    // all zeros is invalid
+    u128::from_str_radix(trace_id, 16).ok().filter(|id| *id != 0).map(TraceId)
+}
+
+// picks up the trace_id field a span was created with
+struct TraceIdVisitor(Option<TraceId>);
+
+impl Visit for TraceIdVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "trace_id" {
+            self.0 = u128::from_str_radix(value, 16).ok().map(TraceId);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "trace_id" {
+            self.0 = u128::from_str_radix(&format!("{:?}", value), 16).ok().map(TraceId);
+        }
+    }
+}
+
+struct TraceLayer;
+
+impl<S> Layer<S> for TraceLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut visitor = TraceIdVisitor(None);
+        attrs.record(&mut visitor);
+        let trace_id = visitor
+            .0
+            .or_else(|| span.parent().and_then(|parent| parent.extensions().get::<TraceId>().copied()))
+            .unwrap_or_else(TraceId::random);
+        span.extensions_mut().insert(trace_id);
+    }
+}
+
+fn trace_of(span: &Span) -> Option<(TraceId, u64)> {
+    span.with_subscriber(|(id, dispatch)| {
+        let registry = dispatch.downcast_ref::<Registry>()?;
+        let trace_id = registry.span(id)?.extensions().get::<TraceId>().copied()?;
+        Some((trace_id, id.into_u64()))
+    })
+    .flatten()
+}
+
+// a root span of its own trace, recorded as its trace_id field
+pub fn root_span(span: Span) -> Span {
+    if let Some((trace_id, _)) = trace_of(&span) {
+        span.record("trace_id", trace_id.to_string());
+    }
+    span
+}
+
+// the traceparent of the current span, None outside of spans
+pub fn traceparent() -> Option<String> {
+    trace_of(&Span::current()).map(|(trace_id, span_id)| format!("00-{}-{:016x}-01", trace_id, span_id))
+}
+
+// send the trace of the current span along with a request
+pub fn propagate(metadata: &mut MetadataMap) {
+    if let Some(traceparent) = traceparent().and_then(|traceparent| traceparent.parse().ok()) {
+        metadata.insert(TRACEPARENT, traceparent);
+    }
+}
+
+// the span of a gRPC request served by rks, in the trace of the caller if it
+// sent one; the method is the last part of the path
+pub fn request_span(request: &http::Request<()>) -> Span {
+    let method = request.uri().path().rsplit('/').next().unwrap_or_default();
+    let remote = request
+        .headers()
+        .get(TRACEPARENT)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_traceparent);
+    match remote {
+        Some(trace_id) => tracing::info_span!("request", method, trace_id = %trace_id),
+        None => root_span(tracing::info_span!("request", method, trace_id = tracing::field::Empty)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_traceparent() {
+        let trace_id = parse_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").unwrap();
+        assert_eq!(trace_id.to_string(), "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(parse_traceparent("00-00000000000000000000000000000000-00f067aa0ba902b7-01"), None);
+
+        // the requests to the nodes go in the trace of the request being served
+        let subscriber = tracing_subscriber::registry().with(TraceLayer);
+        tracing::subscriber::with_default(subscriber, || {
+            let request = http::Request::builder()
+                .uri("/scheduler.Scheduler/SchedulePod")
+                .header(TRACEPARENT, "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01")
+                .body(())
+                .unwrap();
+            let _request = request_span(&request).entered();
+            let mut metadata = MetadataMap::new();
+            propagate(&mut metadata);
+            let traceparent = metadata.get(TRACEPARENT).unwrap().to_str().unwrap();
+            assert_eq!(parse_traceparent(traceparent), Some(trace_id));
+        });
+    }
+}
@@ -0,0 +1,479 @@
+mod pb;
+mod pod;
+mod namespace;
+mod daemonset;
+mod schedule;
+mod server;
+mod heartbeat;
+mod pdb;
+mod affinity;
+mod spread;
+mod quota;
+mod limitrange;
+mod auth;
+mod logging;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
+use anyhow::anyhow;
+use clap::{Parser, Subcommand};
+use tonic::codegen::InterceptedService;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig};
+use auth::Bearer;
+use pb::scheduler::scheduler_client::SchedulerClient;
+use pb::scheduler::{
+    ApplyDaemonSetRequest, ApplyLimitRangeRequest, ApplyPodDisruptionBudgetRequest, ApplyResourceQuotaRequest,
+    DeleteDaemonSetRequest, DeleteLimitRangeRequest, DeletePodDisruptionBudgetRequest, DeletePodRequest,
+    DeleteResourceQuotaRequest, DeregisterNodeRequest, ListDaemonSetsRequest, ListLimitRangesRequest, ListNodesRequest,
+    ListPodDisruptionBudgetsRequest, ListResourceQuotasRequest, SchedulePodRequest,
+};
+
+// set once at startup from --ca-file
+static CA_FILE: OnceLock<PathBuf> = OnceLock::new();
+
+#[derive(Parser)]
+#[command(name = "rks")]
+#[command(about = "The rk8s scheduler, places pods onto nodes running `rkl daemon`", long_about = None)]
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+    /// PEM certificate of the CA of a scheduler served over https
+    #[arg(long, global = true)]
+    ca_file: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Run the scheduler
+    Serve {
+        /// Address to listen on for nodes and clients
+        #[arg(long, default_value = "127.0.0.1:7500")]
+        listen: SocketAddr,
+        /// PEM certificate the scheduler is served over TLS with; required unless listening on loopback
+        #[arg(long, requires = "tls_key")]
+        tls_cert: Option<PathBuf>,
+        /// PEM private key of --tls-cert
+        #[arg(long, requires = "tls_cert")]
+        tls_key: Option<PathBuf>,
+        /// Seconds after which a node that stopped registering is no longer scheduled onto
+        #[arg(long, default_value_t = 30)]
+        node_timeout: u64,
+        /// Seconds a node stays NotReady before its pods are scheduled onto other nodes
+        #[arg(long, default_value_t = heartbeat::DEFAULT_POD_EVICTION_TIMEOUT)]
+        pod_eviction_timeout: u64,
+        /// Cluster state store, etcd://host:port[,...] or the path of an embedded store; state is lost on restart when unset
+        #[arg(long)]
+        store: Option<String>,
+        /// File of "<token> <user> <admin|view|node>" lines, requests need one of the tokens; required unless listening on loopback
+        #[arg(long)]
+        token_file: Option<PathBuf>,
+        /// PEM certificate of the CA of the nodes serving their control API over https
+        #[arg(long)]
+        node_ca_file: Option<PathBuf>,
+    },
+    /// Schedule a pod onto one of the registered nodes
+    Schedule {
+        #[arg(value_name = "POD_YAML")]
+        pod_yaml: String,
+        /// Address of the scheduler
+        #[arg(long, default_value = "http://127.0.0.1:7500")]
+        server: String,
+    },
+    /// Delete a scheduled pod from its node
+    Delete {
+        #[arg(value_name = "POD_NAME")]
+        pod_name: String,
+        /// Namespace of the pod
+        #[arg(short = 'n', long, default_value = namespace::DEFAULT)]
+        namespace: String,
+        /// Address of the scheduler
+        #[arg(long, default_value = "http://127.0.0.1:7500")]
+        server: String,
+    },
+    /// List the registered nodes
+    Nodes {
+        /// Address of the scheduler
+        #[arg(long, default_value = "http://127.0.0.1:7500")]
+        server: String,
+    },
+    /// Remove a node leaving the cluster, deleting the pods of the daemon sets from it
+    Deregister {
+        #[arg(value_name = "NODE_NAME")]
+        node_name: String,
+        /// Address of the scheduler
+        #[arg(long, default_value = "http://127.0.0.1:7500")]
+        server: String,
+    },
+    /// Manage the daemon sets, which run a pod on every matching node
+    Daemonset {
+        #[command(subcommand)]
+        command: DaemonSetCommands,
+    },
+    /// Manage the pod disruption budgets, which limit how many pods a drain takes down at once
+    Pdb {
+        #[command(subcommand)]
+        command: PdbCommands,
+    },
+    /// Manage the resource quotas, which cap what the pods of a namespace take together
+    Quota {
+        #[command(subcommand)]
+        command: QuotaCommands,
+    },
+    /// Manage the limit ranges, which default and bound the resources of the containers of a namespace
+    Limitrange {
+        #[command(subcommand)]
+        command: LimitRangeCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum DaemonSetCommands {
+    /// Create or update a daemon set
+    Apply {
+        #[arg(value_name = "DAEMONSET_YAML")]
+        daemonset_yaml: String,
+        /// Address of the scheduler
+        #[arg(long, default_value = "http://127.0.0.1:7500")]
+        server: String,
+    },
+    /// Delete a daemon set and its pods
+    Delete {
+        #[arg(value_name = "NAME")]
+        name: String,
+        /// Address of the scheduler
+        #[arg(long, default_value = "http://127.0.0.1:7500")]
+        server: String,
+    },
+    /// List the daemon sets
+    List {
+        /// Address of the scheduler
+        #[arg(long, default_value = "http://127.0.0.1:7500")]
+        server: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum PdbCommands {
+    /// Create or update a pod disruption budget
+    Apply {
+        #[arg(value_name = "PDB_YAML")]
+        pdb_yaml: String,
+        /// Address of the scheduler
+        #[arg(long, default_value = "http://127.0.0.1:7500")]
+        server: String,
+    },
+    /// Delete a pod disruption budget
+    Delete {
+        #[arg(value_name = "NAME")]
+        name: String,
+        /// Namespace of the pod disruption budget
+        #[arg(short = 'n', long, default_value = namespace::DEFAULT)]
+        namespace: String,
+        /// Address of the scheduler
+        #[arg(long, default_value = "http://127.0.0.1:7500")]
+        server: String,
+    },
+    /// List the pod disruption budgets with the disruptions they allow
+    List {
+        /// Address of the scheduler
+        #[arg(long, default_value = "http://127.0.0.1:7500")]
+        server: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum QuotaCommands {
+    /// Create or update a resource quota
+    Apply {
+        #[arg(value_name = "QUOTA_YAML")]
+        quota_yaml: String,
+        /// Address of the scheduler
+        #[arg(long, default_value = "http://127.0.0.1:7500")]
+        server: String,
+    },
+    /// Delete a resource quota
+    Delete {
+        #[arg(value_name = "NAME")]
+        name: String,
+        /// Namespace of the resource quota
+        #[arg(short = 'n', long, default_value = namespace::DEFAULT)]
+        namespace: String,
+        /// Address of the scheduler
+        #[arg(long, default_value = "http://127.0.0.1:7500")]
+        server: String,
+    },
+    /// List the resource quotas with what their namespaces use
+    List {
+        /// Address of the scheduler
+        #[arg(long, default_value = "http://127.0.0.1:7500")]
+        server: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum LimitRangeCommands {
+    /// Create or update a limit range
+    Apply {
+        #[arg(value_name = "LIMITRANGE_YAML")]
+        limitrange_yaml: String,
+        /// Address of the scheduler
+        #[arg(long, default_value = "http://127.0.0.1:7500")]
+        server: String,
+    },
+    /// Delete a limit range
+    Delete {
+        #[arg(value_name = "NAME")]
+        name: String,
+        /// Namespace of the limit range
+        #[arg(short = 'n', long, default_value = namespace::DEFAULT)]
+        namespace: String,
+        /// Address of the scheduler
+        #[arg(long, default_value = "http://127.0.0.1:7500")]
+        server: String,
+    },
+    /// List the limit ranges
+    List {
+        /// Address of the scheduler
+        #[arg(long, default_value = "http://127.0.0.1:7500")]
+        server: String,
+    },
+}
+
+// a client of the scheduler with the token of $RKS_TOKEN, if any, verifying
+// the certificate of an https scheduler with the CA of --ca-file
+async fn connect(server: &str) -> Result<SchedulerClient<InterceptedService<Channel, Bearer>>, anyhow::Error> {
+    let bearer = Bearer::from_env()?;
+    let mut endpoint = Channel::from_shared(server.to_string()).map_err(|e| anyhow!("Invalid scheduler address {}: {}", server, e))?;
+    if let Some(path) = CA_FILE.get() {
+        let ca = fs::read(path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        endpoint = endpoint
+            .tls_config(ClientTlsConfig::new().ca_certificate(Certificate::from_pem(ca)))
+            .map_err(|e| anyhow!("Invalid CA certificate {}: {}", path.display(), e))?;
+    }
+    let channel = endpoint
+        .connect()
+        .await
+        .map_err(|e| anyhow!("Failed to connect to the scheduler at {}: {}", server, e))?;
+    Ok(SchedulerClient::with_interceptor(channel, bearer))
+}
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    let cli = Cli::parse();
+    if let Some(ca_file) = cli.ca_file {
+        let _ = CA_FILE.set(ca_file);
+    }
+    match cli.command {
+        Commands::Serve { listen, tls_cert, tls_key, node_timeout, pod_eviction_timeout, store, token_file, node_ca_file } => {
+            logging::init()?;
+            let store = store.as_deref().map(store::open).transpose()?;
+            let authorizer = auth::Authorizer::new(token_file.as_deref())?;
+            let (node_timeout, pod_eviction_timeout) = (Duration::from_secs(node_timeout), Duration::from_secs(pod_eviction_timeout));
+            let tls = tls_cert.zip(tls_key);
+            server::serve(listen, tls, node_timeout, pod_eviction_timeout, store, authorizer, node_ca_file.as_deref()).await
+        }
+        Commands::Schedule { pod_yaml, server } => {
+            let manifest = fs::read_to_string(&pod_yaml).map_err(|e| anyhow!("Failed to read {}: {}", pod_yaml, e))?;
+            let response = connect(&server)
+                .await?
+                .schedule_pod(SchedulePodRequest { manifest, update: false, dry_run: false })
+                .await
+                .map_err(|e| anyhow!("Failed to schedule {}: {}", pod_yaml, e.message()))?;
+            println!("Scheduled onto node {}", response.into_inner().node);
+            Ok(())
+        }
+        Commands::Delete { pod_name, namespace, server } => {
+            let response = connect(&server)
+                .await?
+                .delete_pod(DeletePodRequest { name: pod_name.clone(), namespace, dry_run: false })
+                .await
+                .map_err(|e| anyhow!("Failed to delete {}: {}", pod_name, e.message()))?;
+            println!("Deleted from node {}", response.into_inner().node);
+            Ok(())
+        }
+        Commands::Nodes { server } => {
+            let nodes = connect(&server).await?.list_nodes(ListNodesRequest {}).await?.into_inner().nodes;
+            println!("{:<20} {:<6} {:>12} {:>16} {:>5}", "NAME", "READY", "CPU(m)", "MEMORY", "PODS");
+            for status in nodes {
+                let node = status.node.unwrap_or_default();
+                let capacity = node.capacity.unwrap_or_default();
+                let allocated = status.allocated.unwrap_or_default();
+                println!(
+                    "{:<20} {:<6} {:>12} {:>16} {:>5}",
+                    node.name,
+                    status.ready,
+                    format!("{}/{}", allocated.cpu_millis, capacity.cpu_millis),
+                    format!("{}Mi/{}Mi", allocated.memory_bytes >> 20, capacity.memory_bytes >> 20),
+                    format!("{}/{}", allocated.pods, capacity.pods),
+                );
+            }
+            Ok(())
+        }
+        Commands::Deregister { node_name, server } => {
+            connect(&server)
+                .await?
+                .deregister_node(DeregisterNodeRequest { name: node_name.clone() })
+                .await
+                .map_err(|e| anyhow!("Failed to deregister {}: {}", node_name, e.message()))?;
+            println!("Node {} deregistered", node_name);
+            Ok(())
+        }
+        Commands::Daemonset { command: DaemonSetCommands::Apply { daemonset_yaml, server } } => {
+            let manifest = fs::read_to_string(&daemonset_yaml)
+                .map_err(|e| anyhow!("Failed to read {}: {}", daemonset_yaml, e))?;
+            connect(&server)
+                .await?
+                .apply_daemon_set(ApplyDaemonSetRequest { manifest })
+                .await
+                .map_err(|e| anyhow!("Failed to apply {}: {}", daemonset_yaml, e.message()))?;
+            println!("Daemon set of {} applied", daemonset_yaml);
+            Ok(())
+        }
+        Commands::Daemonset { command: DaemonSetCommands::Delete { name, server } } => {
+            connect(&server)
+                .await?
+                .delete_daemon_set(DeleteDaemonSetRequest { name: name.clone() })
+                .await
+                .map_err(|e| anyhow!("Failed to delete {}: {}", name, e.message()))?;
+            println!("Daemon set {} deleted", name);
+            Ok(())
+        }
+        Commands::Daemonset { command: DaemonSetCommands::List { server } } => {
+            let daemonsets = connect(&server)
+                .await?
+                .list_daemon_sets(ListDaemonSetsRequest {})
+                .await?
+                .into_inner()
+                .daemon_sets;
+            println!("{:<20} {:>7} {:>7} {:>10}  NODES", "NAME", "DESIRED", "CURRENT", "UP-TO-DATE");
+            for status in daemonsets {
+                println!(
+                    "{:<20} {:>7} {:>7} {:>10}  {}",
+                    status.name,
+                    status.desired,
+                    status.current,
+                    status.up_to_date,
+                    status.nodes.join(",")
+                );
+            }
+            Ok(())
+        }
+        Commands::Pdb { command: PdbCommands::Apply { pdb_yaml, server } } => {
+            let manifest = fs::read_to_string(&pdb_yaml).map_err(|e| anyhow!("Failed to read {}: {}", pdb_yaml, e))?;
+            connect(&server)
+                .await?
+                .apply_pod_disruption_budget(ApplyPodDisruptionBudgetRequest { manifest })
+                .await
+                .map_err(|e| anyhow!("Failed to apply {}: {}", pdb_yaml, e.message()))?;
+            println!("Pod disruption budget of {} applied", pdb_yaml);
+            Ok(())
+        }
+        Commands::Pdb { command: PdbCommands::Delete { name, namespace, server } } => {
+            connect(&server)
+                .await?
+                .delete_pod_disruption_budget(DeletePodDisruptionBudgetRequest { name: name.clone(), namespace })
+                .await
+                .map_err(|e| anyhow!("Failed to delete {}: {}", name, e.message()))?;
+            println!("Pod disruption budget {} deleted", name);
+            Ok(())
+        }
+        Commands::Pdb { command: PdbCommands::List { server } } => {
+            let budgets = connect(&server)
+                .await?
+                .list_pod_disruption_budgets(ListPodDisruptionBudgetsRequest {})
+                .await?
+                .into_inner()
+                .budgets;
+            println!("{:<20} {:<12} {:>13} {:>15} {:>7} {:>19}", "NAME", "NAMESPACE", "MIN AVAILABLE", "MAX UNAVAILABLE", "HEALTHY", "ALLOWED DISRUPTIONS");
+            let written = |value: String| if value.is_empty() { "N/A".to_string() } else { value };
+            for status in budgets {
+                println!(
+                    "{:<20} {:<12} {:>13} {:>15} {:>7} {:>19}",
+                    status.name,
+                    status.namespace,
+                    written(status.min_available),
+                    written(status.max_unavailable),
+                    format!("{}/{}", status.healthy, status.expected),
+                    status.disruptions_allowed
+                );
+            }
+            Ok(())
+        }
+        Commands::Quota { command: QuotaCommands::Apply { quota_yaml, server } } => {
+            let manifest = fs::read_to_string(&quota_yaml).map_err(|e| anyhow!("Failed to read {}: {}", quota_yaml, e))?;
+            connect(&server)
+                .await?
+                .apply_resource_quota(ApplyResourceQuotaRequest { manifest })
+                .await
+                .map_err(|e| anyhow!("Failed to apply {}: {}", quota_yaml, e.message()))?;
+            println!("Resource quota of {} applied", quota_yaml);
+            Ok(())
+        }
+        Commands::Quota { command: QuotaCommands::Delete { name, namespace, server } } => {
+            connect(&server)
+                .await?
+                .delete_resource_quota(DeleteResourceQuotaRequest { name: name.clone(), namespace })
+                .await
+                .map_err(|e| anyhow!("Failed to delete {}: {}", name, e.message()))?;
+            println!("Resource quota {} deleted", name);
+            Ok(())
+        }
+        Commands::Quota { command: QuotaCommands::List { server } } => {
+            let quotas = connect(&server)
+                .await?
+                .list_resource_quotas(ListResourceQuotasRequest {})
+                .await?
+                .into_inner()
+                .quotas;
+            println!("{:<20} {:<12} {:<40}  LIMIT", "NAME", "NAMESPACE", "REQUEST");
+            for status in quotas {
+                println!("{:<20} {:<12} {:<40}  {}", status.name, status.namespace, status.request, status.limit);
+            }
+            Ok(())
+        }
+        Commands::Limitrange { command: LimitRangeCommands::Apply { limitrange_yaml, server } } => {
+            let manifest = fs::read_to_string(&limitrange_yaml)
+                .map_err(|e| anyhow!("Failed to read {}: {}", limitrange_yaml, e))?;
+            connect(&server)
+                .await?
+                .apply_limit_range(ApplyLimitRangeRequest { manifest })
+                .await
+                .map_err(|e| anyhow!("Failed to apply {}: {}", limitrange_yaml, e.message()))?;
+            println!("Limit range of {} applied", limitrange_yaml);
+            Ok(())
+        }
+        Commands::Limitrange { command: LimitRangeCommands::Delete { name, namespace, server } } => {
+            connect(&server)
+                .await?
+                .delete_limit_range(DeleteLimitRangeRequest { name: name.clone(), namespace })
+                .await
+                .map_err(|e| anyhow!("Failed to delete {}: {}", name, e.message()))?;
+            println!("Limit range {} deleted", name);
+            Ok(())
+        }
+        Commands::Limitrange { command: LimitRangeCommands::List { server } } => {
+            let ranges = connect(&server)
+                .await?
+                .list_limit_ranges(ListLimitRangesRequest {})
+                .await?
+                .into_inner()
+                .limit_ranges;
+            println!("{:<20} {:<12} {:<24} {:<24} {:<24} MAX", "NAME", "NAMESPACE", "DEFAULT", "DEFAULT REQUEST", "MIN");
+            let written = |value: String| if value.is_empty() { "-".to_string() } else { value };
+            for status in ranges {
+                println!(
+                    "{:<20} {:<12} {:<24} {:<24} {:<24} {}",
+                    status.name,
+                    status.namespace,
+                    written(status.default),
+                    written(status.default_request),
+                    written(status.min),
+                    written(status.max)
+                );
+            }
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use anyhow::{Result, anyhow};
+
+// Namespaces divide the pods and daemon sets of the cluster. They are objects
+// of their own, kept as /registry/namespaces/<name> in the store, created with
+// `rkl create namespace` and deleted once nothing is left in them; a pod or
+// daemon set of a namespace that doesn't exist is refused. The built-in ones
+// always exist. Pod names stay unique across the cluster since the nodes run
+// pods by name, the daemon set a pod belongs to is only looked up within its
+// namespace.
+
+pub const DEFAULT: &str = "default";
+pub const BUILT_IN: [&str; 2] = [DEFAULT, "kube-system"];
+
+pub fn default_namespace() -> String {
+    DEFAULT.to_string()
+}
+
+// an RFC 1123 label, like the names of Kubernetes namespaces
+pub fn validate(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= 63
+        && name.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && name.ends_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+    if !valid {
+        return Err(anyhow!("invalid namespace name {:?}: lowercase letters, digits and '-' only, at most 63", name));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        assert!(validate("team-a").is_ok());
+        assert!(validate("kube-system").is_ok());
+        assert!(validate("").is_err());
+        assert!(validate("Team").is_err());
+        assert!(validate("a.b").is_err());
+        assert!(validate("-a").is_err());
+        assert!(validate(&"a".repeat(64)).is_err());
+    }
+}
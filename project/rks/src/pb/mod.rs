@@ -0,0 +1,4 @@
+// generated with tonic-build from proto/scheduler.proto and, client only, from
+// the control API of the rkl daemon in rkl/proto/control.proto
+pub mod scheduler;
+pub mod control;
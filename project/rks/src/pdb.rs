@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use crate::pod::Metadata;
+
+// A PodDisruptionBudget limits how many of the pods it selects the voluntary
+// evictions of rkl drain (EvictPod) take down at once: with minAvailable that
+// many of them stay healthy, with maxUnavailable no more than that many are
+// not, either a count or a percentage of the selected pods rounded up. A pod
+// is healthy while it runs on a ready node. An eviction that would leave fewer
+// healthy pods than the budget wants is refused with ResourceExhausted, which
+// rkl drain retries until the pods evicted before run on their new nodes.
+// rks doesn't know the controllers of the pods, the pods of the namespace the
+// selector matches stand for their replicas; an empty selector matches all of
+// them. The pods of nodes NotReady for long are evicted regardless, see
+// heartbeat. Budgets are kept as /registry/poddisruptionbudgets/<name> in the
+// store, their names are unique across the cluster like those of daemon sets.
+
+#[derive(Debug, Deserialize)]
+pub struct PodDisruptionBudget {
+    #[serde(rename = "apiVersion")]
+    pub api_version: String,
+    pub kind: String,
+    pub metadata: Metadata,
+    pub spec: PodDisruptionBudgetSpec,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PodDisruptionBudgetSpec {
+    #[serde(default)]
+    pub selector: LabelSelector,
+    pub min_available: Option<IntOrPercent>,
+    pub max_unavailable: Option<IntOrPercent>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LabelSelector {
+    #[serde(default)]
+    pub match_labels: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum IntOrPercent {
+    Int(i64),
+    Percent(String),
+}
+
+impl IntOrPercent {
+    // the count, a percentage of total rounded up; None when invalid
+    fn scaled(&self, total: i64) -> Option<i64> {
+        match self {
+            IntOrPercent::Int(count) => Some(*count).filter(|count| *count >= 0),
+            IntOrPercent::Percent(percent) => percent
+                .strip_suffix('%')
+                .and_then(|percent| percent.parse::<i64>().ok())
+                .filter(|percent| (0..=100).contains(percent))
+                .map(|percent| (percent * total + 99) / 100),
+        }
+    }
+}
+
+impl std::fmt::Display for IntOrPercent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IntOrPercent::Int(count) => write!(f, "{}", count),
+            IntOrPercent::Percent(percent) => write!(f, "{}", percent),
+        }
+    }
+}
+
+// how the pods a budget selects stand against it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Disruption {
+    pub expected: i64,
+    pub healthy: i64,
+    pub desired_healthy: i64,
+}
+
+impl Disruption {
+    // how many healthy pods may be evicted right now
+    pub fn allowed(&self) -> i64 {
+        (self.healthy - self.desired_healthy).max(0)
+    }
+}
+
+impl PodDisruptionBudget {
+    pub fn parse(manifest: &str) -> Result<Self> {
+        let budget: PodDisruptionBudget =
+            serde_yaml::from_str(manifest).map_err(|e| anyhow!("invalid pod disruption budget manifest: {}", e))?;
+        if budget.kind != "PodDisruptionBudget" || budget.api_version != "policy/v1" {
+            return Err(anyhow!("expected apiVersion policy/v1 and kind PodDisruptionBudget"));
+        }
+        let value = match (&budget.spec.min_available, &budget.spec.max_unavailable) {
+            (Some(value), None) | (None, Some(value)) => value,
+            _ => return Err(anyhow!("exactly one of minAvailable and maxUnavailable is required")),
+        };
+        if value.scaled(0).is_none() {
+            return Err(anyhow!("invalid budget {}, expected a count or a percentage like 50%", value));
+        }
+        Ok(budget)
+    }
+
+    pub fn selects(&self, namespace: &str, labels: &HashMap<String, String>) -> bool {
+        namespace == self.metadata.namespace
+            && self.spec.selector.match_labels.iter().all(|(key, value)| labels.get(key) == Some(value))
+    }
+
+    pub fn disruption(&self, expected: i64, healthy: i64) -> Disruption {
+        let desired_healthy = match (&self.spec.min_available, &self.spec.max_unavailable) {
+            (Some(min), _) => min.scaled(expected).unwrap_or(expected),
+            (None, Some(max)) => expected - max.scaled(expected).unwrap_or(0),
+            (None, None) => expected,
+        };
+        Disruption { expected, healthy, desired_healthy: desired_healthy.max(0) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disruption() {
+        let budget = PodDisruptionBudget::parse(
+            "apiVersion: policy/v1\nkind: PodDisruptionBudget\nmetadata:\n  name: web\nspec:\n  minAvailable: 2\n  selector:\n    matchLabels:\n      app: web\n",
+        )
+        .unwrap();
+        let labels = HashMap::from([("app".to_string(), "web".to_string())]);
+        assert!(budget.selects("default", &labels));
+        assert!(!budget.selects("other", &labels));
+        assert!(!budget.selects("default", &HashMap::new()));
+        assert_eq!(budget.disruption(3, 3).allowed(), 1);
+        assert_eq!(budget.disruption(3, 2).allowed(), 0);
+
+        let budget = PodDisruptionBudget::parse(
+            "apiVersion: policy/v1\nkind: PodDisruptionBudget\nmetadata:\n  name: web\nspec:\n  maxUnavailable: 25%\n",
+        )
+        .unwrap();
+        // an empty selector matches every pod of the namespace, 25% of 5 is 2
+        assert!(budget.selects("default", &HashMap::new()));
+        assert_eq!(budget.disruption(5, 5), Disruption { expected: 5, healthy: 5, desired_healthy: 3 });
+        assert_eq!(budget.disruption(5, 4).allowed(), 1);
+
+        let invalid = "apiVersion: policy/v1\nkind: PodDisruptionBudget\nmetadata:\n  name: web\nspec:\n  minAvailable: 150%\n";
+        assert!(PodDisruptionBudget::parse(invalid).is_err());
+        let both = "apiVersion: policy/v1\nkind: PodDisruptionBudget\nmetadata:\n  name: web\nspec:\n  minAvailable: 1\n  maxUnavailable: 1\n";
+        assert!(PodDisruptionBudget::parse(both).is_err());
+    }
+}
@@ -0,0 +1,278 @@
+use std::collections::HashMap;
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use crate::affinity::Affinity;
+use crate::spread::TopologySpreadConstraint;
+use crate::namespace;
+
+// The scheduler only needs a few fields of a pod manifest, the full manifest
+// is passed on to the node untouched.
+
+#[derive(Debug, Deserialize)]
+pub struct PodManifest {
+    pub metadata: Metadata,
+    pub spec: PodSpec,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Metadata {
+    pub name: String,
+    #[serde(default = "namespace::default_namespace")]
+    pub namespace: String,
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PodSpec {
+    #[serde(default)]
+    pub containers: Vec<Container>,
+    #[serde(default)]
+    pub init_containers: Vec<Container>,
+    #[serde(rename = "nodeSelector", default)]
+    pub node_selector: HashMap<String, String>,
+    #[serde(default)]
+    pub os: Option<PodOs>,
+    #[serde(default)]
+    pub tolerations: Vec<Toleration>,
+    #[serde(default)]
+    pub affinity: Option<Affinity>,
+    #[serde(rename = "topologySpreadConstraints", default)]
+    pub topology_spread_constraints: Vec<TopologySpreadConstraint>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PodOs {
+    pub name: String,
+}
+
+impl PodSpec {
+    // a pod with an os only goes onto nodes of that os, like rkl admits it
+    pub fn node_selector(&self) -> HashMap<String, String> {
+        let mut selector = self.node_selector.clone();
+        if let Some(os) = &self.os {
+            selector.entry("kubernetes.io/os".to_string()).or_insert_with(|| os.name.clone());
+        }
+        selector
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Container {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub resources: ResourceRequirements,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ResourceRequirements {
+    #[serde(default)]
+    pub limits: HashMap<String, serde_yaml::Value>,
+    #[serde(default)]
+    pub requests: HashMap<String, serde_yaml::Value>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Toleration {
+    #[serde(default)]
+    pub key: String,
+    // Equal (default) or Exists
+    #[serde(default)]
+    pub operator: Option<String>,
+    #[serde(default)]
+    pub value: String,
+    // every effect when empty
+    #[serde(default)]
+    pub effect: String,
+}
+
+impl Toleration {
+    pub fn tolerates(&self, key: &str, value: &str, effect: &str) -> bool {
+        if !self.effect.is_empty() && self.effect != effect {
+            return false;
+        }
+        match self.operator.as_deref() {
+            // an empty key with Exists tolerates every taint
+            Some("Exists") => self.key.is_empty() || self.key == key,
+            _ => self.key == key && self.value == value,
+        }
+    }
+}
+
+// what a pod asks for, in millicores and bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Requests {
+    pub cpu_millis: i64,
+    pub memory_bytes: i64,
+}
+
+impl Container {
+    // like Kubernetes, a limit without a request is also the request
+    fn requests(&self) -> Result<Requests> {
+        let resources = &self.resources;
+        let get = |name: &str| resources.requests.get(name).or_else(|| resources.limits.get(name));
+        Ok(Requests {
+            cpu_millis: get("cpu").map(|q| parse_cpu(&quantity_string(q))).transpose()?.unwrap_or_default(),
+            memory_bytes: get("memory").map(|q| parse_memory(&quantity_string(q))).transpose()?.unwrap_or_default(),
+        })
+    }
+
+    // what is unlimited counts as 0
+    fn limits(&self) -> Result<Requests> {
+        let limits = &self.resources.limits;
+        Ok(Requests {
+            cpu_millis: limits.get("cpu").map(|q| parse_cpu(&quantity_string(q))).transpose()?.unwrap_or_default(),
+            memory_bytes: limits.get("memory").map(|q| parse_memory(&quantity_string(q))).transpose()?.unwrap_or_default(),
+        })
+    }
+}
+
+impl PodManifest {
+    pub fn parse(manifest: &str) -> Result<Self> {
+        serde_yaml::from_str(manifest).map_err(|e| anyhow!("invalid pod manifest: {}", e))
+    }
+
+    // init containers run one after another before the others, so a pod needs
+    // the largest of them or the sum of its containers, whichever is more
+    pub fn requests(&self) -> Result<Requests> {
+        self.total(Container::requests)
+    }
+
+    // the limits of the pod, added up like the requests
+    pub fn limits(&self) -> Result<Requests> {
+        self.total(Container::limits)
+    }
+
+    fn total(&self, resources: impl Fn(&Container) -> Result<Requests>) -> Result<Requests> {
+        let mut total = Requests::default();
+        for container in &self.spec.containers {
+            let requests = resources(container)?;
+            total.cpu_millis += requests.cpu_millis;
+            total.memory_bytes += requests.memory_bytes;
+        }
+        for container in &self.spec.init_containers {
+            let requests = resources(container)?;
+            total.cpu_millis = total.cpu_millis.max(requests.cpu_millis);
+            total.memory_bytes = total.memory_bytes.max(requests.memory_bytes);
+        }
+        Ok(total)
+    }
+
+    pub fn all_containers(&self) -> impl Iterator<Item = &Container> {
+        self.spec.init_containers.iter().chain(&self.spec.containers)
+    }
+}
+
+// quantities may be written as numbers or strings in YAML
+pub fn quantity_string(value: &serde_yaml::Value) -> String {
+    match value {
+        serde_yaml::Value::String(s) => s.clone(),
+        other => serde_yaml::to_string(other).unwrap_or_default().trim().to_string(),
+    }
+}
+
+// "500m" or "0.5" is half a core
+pub fn parse_cpu(quantity: &str) -> Result<i64> {
+    let invalid = || anyhow!("invalid cpu quantity {:?}", quantity);
+    let millis = match quantity.strip_suffix('m') {
+        Some(millis) => millis.parse::<f64>().map_err(|_| invalid())?,
+        None => quantity.parse::<f64>().map_err(|_| invalid())? * 1000.0,
+    };
+    if !millis.is_finite() || millis < 0.0 {
+        return Err(invalid());
+    }
+    Ok(millis.ceil() as i64)
+}
+
+// "128Mi", "1G" or a plain number of bytes
+pub fn parse_memory(quantity: &str) -> Result<i64> {
+    const SUFFIXES: [(&str, f64); 12] = [
+        ("Ki", 1024.0),
+        ("Mi", 1048576.0),
+        ("Gi", 1073741824.0),
+        ("Ti", 1099511627776.0),
+        ("Pi", 1125899906842624.0),
+        ("Ei", 1152921504606846976.0),
+        ("k", 1e3),
+        ("M", 1e6),
+        ("G", 1e9),
+        ("T", 1e12),
+        ("P", 1e15),
+        ("E", 1e18),
+    ];
+    let invalid = || anyhow!("invalid memory quantity {:?}", quantity);
+    let (number, multiplier) = SUFFIXES
+        .iter()
+        .find_map(|(suffix, multiplier)| quantity.strip_suffix(suffix).map(|n| (n, *multiplier)))
+        .unwrap_or((quantity, 1.0));
+    let bytes = number.parse::<f64>().map_err(|_| invalid())? * multiplier;
+    if !bytes.is_finite() || bytes < 0.0 {
+        return Err(invalid());
+    }
+    Ok(bytes.ceil() as i64)
+}
+
+// the shortest of "2" or "1500m"
+pub fn format_cpu(millis: i64) -> String {
+    if millis % 1000 == 0 { format!("{}", millis / 1000) } else { format!("{}m", millis) }
+}
+
+// the largest binary suffix that divides the bytes, like "512Mi"
+pub fn format_memory(bytes: i64) -> String {
+    const SUFFIXES: [(&str, i64); 4] = [("Ti", 1 << 40), ("Gi", 1 << 30), ("Mi", 1 << 20), ("Ki", 1 << 10)];
+    SUFFIXES
+        .iter()
+        .find(|(_, multiplier)| bytes != 0 && bytes % multiplier == 0)
+        .map(|(suffix, multiplier)| format!("{}{}", bytes / multiplier, suffix))
+        .unwrap_or_else(|| bytes.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pod_requests() {
+        let pod = PodManifest::parse(
+            r#"
+apiVersion: v1
+kind: Pod
+metadata:
+  name: web
+spec:
+  init_containers:
+    - name: migrate
+      image: app:v1
+      resources:
+        requests:
+          memory: 1Gi
+  containers:
+    - name: app
+      image: app:v1
+      resources:
+        requests:
+          cpu: 500m
+          memory: 128Mi
+    - name: sidecar
+      image: proxy:v1
+      resources:
+        limits:
+          cpu: 1
+          memory: 64Mi
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            pod.requests().unwrap(),
+            Requests { cpu_millis: 1500, memory_bytes: 1 << 30 }
+        );
+        assert_eq!(parse_cpu("0.25").unwrap(), 250);
+        assert_eq!(parse_memory("1G").unwrap(), 1_000_000_000);
+        assert!(parse_memory("lots").is_err());
+        assert!(parse_cpu("-1").is_err());
+        assert_eq!(pod.limits().unwrap(), Requests { cpu_millis: 1000, memory_bytes: 64 << 20 });
+        assert_eq!((format_cpu(1500), format_cpu(2000)), ("1500m".to_string(), "2".to_string()));
+        assert_eq!((format_memory(1 << 30), format_memory(1000)), ("1Gi".to_string(), "1000".to_string()));
+    }
+}
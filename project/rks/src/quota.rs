@@ -0,0 +1,199 @@
+use std::collections::BTreeMap;
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use crate::pod::{self, Metadata, PodManifest, Requests};
+
+// A ResourceQuota caps what the pods of its namespace take together, like in
+// Kubernetes:
+//   pods                          the number of scheduled pods
+//   requests.cpu, requests.memory the sum of their requests, also as cpu and
+//                                 memory
+//   limits.cpu, limits.memory     the sum of their limits
+// A new pod that would take its namespace over any quota is refused with
+// ResourceExhausted, naming the quota and what it would exceed. With a quota
+// on a resource every container of a pod has to set it, a request or a limit
+// for the requests and a limit for the limits, which the defaults of a limit
+// range usually take care of, see limitrange. Pods of the daemon sets and
+// pods evicted onto other nodes are admitted like new ones. Quotas are kept as
+// /registry/resourcequotas/<name> in the store, their names are unique across
+// the cluster like those of daemon sets.
+
+#[derive(Debug, Deserialize)]
+pub struct ResourceQuota {
+    #[serde(rename = "apiVersion")]
+    pub api_version: String,
+    pub kind: String,
+    pub metadata: Metadata,
+    pub spec: ResourceQuotaSpec,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResourceQuotaSpec {
+    #[serde(default)]
+    pub hard: BTreeMap<String, serde_yaml::Value>,
+}
+
+// what the pods of a namespace take
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub pods: i64,
+    pub requests: Requests,
+    pub limits: Requests,
+}
+
+impl Usage {
+    pub fn of(pod: &PodManifest) -> Result<Self> {
+        Ok(Usage { pods: 1, requests: pod.requests()?, limits: pod.limits()? })
+    }
+
+    fn get(&self, resource: &str) -> i64 {
+        match resource {
+            "pods" => self.pods,
+            "cpu" | "requests.cpu" => self.requests.cpu_millis,
+            "memory" | "requests.memory" => self.requests.memory_bytes,
+            "limits.cpu" => self.limits.cpu_millis,
+            "limits.memory" => self.limits.memory_bytes,
+            _ => 0,
+        }
+    }
+}
+
+impl std::ops::Add for Usage {
+    type Output = Usage;
+
+    fn add(self, other: Usage) -> Usage {
+        let sum = |a: Requests, b: Requests| Requests {
+            cpu_millis: a.cpu_millis + b.cpu_millis,
+            memory_bytes: a.memory_bytes + b.memory_bytes,
+        };
+        Usage { pods: self.pods + other.pods, requests: sum(self.requests, other.requests), limits: sum(self.limits, other.limits) }
+    }
+}
+
+// the amount of a resource as written in a quota
+fn parse(resource: &str, value: &serde_yaml::Value) -> Result<i64> {
+    let quantity = pod::quantity_string(value);
+    match resource {
+        "pods" => quantity.parse::<i64>().ok().filter(|count| *count >= 0).ok_or_else(|| anyhow!("invalid pod count {:?}", quantity)),
+        "cpu" | "requests.cpu" | "limits.cpu" => pod::parse_cpu(&quantity),
+        "memory" | "requests.memory" | "limits.memory" => pod::parse_memory(&quantity),
+        _ => Err(anyhow!("unsupported resource {}, expected pods, requests.cpu, requests.memory, limits.cpu or limits.memory", resource)),
+    }
+}
+
+fn format(resource: &str, amount: i64) -> String {
+    if resource.ends_with("cpu") {
+        pod::format_cpu(amount)
+    } else if resource.ends_with("memory") {
+        pod::format_memory(amount)
+    } else {
+        amount.to_string()
+    }
+}
+
+impl ResourceQuota {
+    pub fn parse(manifest: &str) -> Result<Self> {
+        let quota: ResourceQuota =
+            serde_yaml::from_str(manifest).map_err(|e| anyhow!("invalid resource quota manifest: {}", e))?;
+        if quota.kind != "ResourceQuota" || quota.api_version != "v1" {
+            return Err(anyhow!("expected apiVersion v1 and kind ResourceQuota"));
+        }
+        for (resource, value) in &quota.spec.hard {
+            parse(resource, value)?;
+        }
+        Ok(quota)
+    }
+
+    // the resources and their caps, valid since parse
+    fn hard(&self) -> impl Iterator<Item = (&str, i64)> {
+        self.spec.hard.iter().map(|(resource, value)| (resource.as_str(), parse(resource, value).unwrap_or_default()))
+    }
+
+    // admit a pod of the namespace of the quota whose pods use used already
+    pub fn admit(&self, pod: &PodManifest, used: Usage) -> Result<()> {
+        let requested = Usage::of(pod)?;
+        for (resource, _) in self.hard() {
+            let unset: Vec<&str> = pod
+                .all_containers()
+                .filter(|container| {
+                    let resources = &container.resources;
+                    match resource.split_once('.') {
+                        Some(("limits", name)) => !resources.limits.contains_key(name),
+                        Some((_, name)) => !resources.requests.contains_key(name) && !resources.limits.contains_key(name),
+                        None if resource == "pods" => false,
+                        None => !resources.requests.contains_key(resource) && !resources.limits.contains_key(resource),
+                    }
+                })
+                .map(|container| container.name.as_str())
+                .collect();
+            if !unset.is_empty() {
+                return Err(anyhow!("failed quota: {}: must specify {} for: {}", self.metadata.name, resource, unset.join(",")));
+            }
+        }
+        let exceeded: Vec<(&str, i64)> = self.hard().filter(|(resource, hard)| used.get(resource) + requested.get(resource) > *hard).collect();
+        if exceeded.is_empty() {
+            return Ok(());
+        }
+        let list = |amount: &dyn Fn(&str, i64) -> i64| {
+            exceeded.iter().map(|&(resource, hard)| format!("{}={}", resource, format(resource, amount(resource, hard)))).collect::<Vec<_>>().join(",")
+        };
+        Err(anyhow!(
+            "exceeded quota: {}, requested: {}, used: {}, limited: {}",
+            self.metadata.name,
+            list(&|resource, _| requested.get(resource)),
+            list(&|resource, _| used.get(resource)),
+            list(&|_, hard| hard)
+        ))
+    }
+
+    // "resource: used/hard" of the requests and of the limits, like kubectl get quota
+    pub fn status(&self, used: Usage) -> (String, String) {
+        let (mut requests, mut limits) = (Vec::new(), Vec::new());
+        for (resource, hard) in self.hard() {
+            let entry = format!("{}: {}/{}", resource, format(resource, used.get(resource)), format(resource, hard));
+            if resource.starts_with("limits.") { limits.push(entry) } else { requests.push(entry) }
+        }
+        (requests.join(", "), limits.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pod(resources: &str) -> PodManifest {
+        PodManifest::parse(&format!(
+            "apiVersion: v1\nkind: Pod\nmetadata:\n  name: web\nspec:\n  containers:\n    - name: app\n      image: app:v1\n      resources:\n{}",
+            resources
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_admit() {
+        let quota = ResourceQuota::parse(
+            "apiVersion: v1\nkind: ResourceQuota\nmetadata:\n  name: compute\n  namespace: team-a\nspec:\n  hard:\n    pods: 3\n    requests.cpu: 2\n    limits.memory: 1Gi\n",
+        )
+        .unwrap();
+        let web = pod("        requests:\n          cpu: 600m\n        limits:\n          memory: 256Mi\n");
+        let used = Usage { pods: 2, requests: Requests { cpu_millis: 1000, memory_bytes: 0 }, limits: Requests { cpu_millis: 0, memory_bytes: 512 << 20 } };
+        assert!(quota.admit(&web, used).is_ok());
+        assert_eq!(
+            quota.status(used),
+            ("pods: 2/3, requests.cpu: 1/2".to_string(), "limits.memory: 512Mi/1Gi".to_string())
+        );
+
+        let used = used + Usage::of(&web).unwrap();
+        assert_eq!(
+            quota.admit(&web, used).unwrap_err().to_string(),
+            "exceeded quota: compute, requested: pods=1,requests.cpu=600m, used: pods=3,requests.cpu=1600m, limited: pods=3,requests.cpu=2"
+        );
+        // without a memory limit the pod can't be counted against limits.memory
+        let unlimited = pod("        requests:\n          cpu: 500m\n");
+        assert_eq!(
+            quota.admit(&unlimited, Usage::default()).unwrap_err().to_string(),
+            "failed quota: compute: must specify limits.memory for: app"
+        );
+        assert!(ResourceQuota::parse("apiVersion: v1\nkind: ResourceQuota\nmetadata:\n  name: gpus\nspec:\n  hard:\n    nvidia.com/gpu: 1\n").is_err());
+    }
+}
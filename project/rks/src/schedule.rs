@@ -0,0 +1,210 @@
+use std::collections::BTreeMap;
+use anyhow::{Result, anyhow};
+use crate::affinity::{Affinity, Placed};
+use crate::pb::scheduler::Node;
+use crate::pod::{PodManifest, Requests};
+use crate::spread::Spread;
+
+// Placement of a pod, in two steps like the kube-scheduler:
+//   filter: the node has the labels of the nodeSelector, and the
+//           kubernetes.io/os of the os of the pod if any, matches the
+//           required node affinity, every NoSchedule and NoExecute taint is
+//           tolerated, the required pod affinity and anti-affinity hold against
+//           the placed pods, the DoNotSchedule topology spread constraints
+//           keep their maxSkew and the requests fit into what the scheduled
+//           pods leave of the capacity
+//   score:  the least allocated node after placing the pod wins, where a node
+//           whose pods use more than they requested counts as allocated by
+//           what they use, every PreferNoSchedule taint that isn't tolerated
+//           costs 10 points, the preferred affinity terms add or cost their
+//           weight, see affinity, and the ScheduleAnyway spread constraints
+//           cost points for the skew, see spread
+// Ties go to the node whose name sorts first so placements are deterministic.
+
+const PREFER_NO_SCHEDULE_PENALTY: i64 = 10;
+
+// a node as seen by the scheduler
+pub struct Candidate<'a> {
+    pub node: &'a Node,
+    pub allocated: Requests,
+    pub pods: usize,
+}
+
+// why the pod can't go onto the node whatever runs there, None if it can
+pub fn mismatch(pod: &PodManifest, node: &Node) -> Option<&'static str> {
+    let selector = pod.spec.node_selector();
+    if selector.iter().any(|(key, value)| node.labels.get(key) != Some(value)) {
+        return Some("didn't match Pod's node selector");
+    }
+    if pod.spec.affinity.as_ref().is_some_and(|affinity| !affinity.node_matches(node)) {
+        return Some("didn't match Pod's node affinity");
+    }
+    let untolerated = node.taints.iter().any(|taint| {
+        matches!(taint.effect.as_str(), "NoSchedule" | "NoExecute")
+            && !pod.spec.tolerations.iter().any(|t| t.tolerates(&taint.key, &taint.value, &taint.effect))
+    });
+    if untolerated {
+        return Some("had untolerated taint");
+    }
+    None
+}
+
+impl Candidate<'_> {
+    // why the pod can't go onto the node, None if it can
+    fn unfit_reason(&self, pod: &PodManifest, affinity: &Affinity, spreads: &[Spread], requests: Requests, placed: &[Placed]) -> Option<&'static str> {
+        if let Some(reason) = mismatch(pod, self.node) {
+            return Some(reason);
+        }
+        if let Some(reason) = affinity.pod_mismatch(&pod.metadata.namespace, &pod.metadata.labels, self.node, placed) {
+            return Some(reason);
+        }
+        if spreads.iter().any(|spread| spread.violated(self.node)) {
+            return Some("didn't match pod topology spread constraints");
+        }
+        let capacity = self.node.capacity.clone().unwrap_or_default();
+        if self.pods as i64 >= capacity.pods {
+            return Some("Too many pods");
+        }
+        if self.allocated.cpu_millis + requests.cpu_millis > capacity.cpu_millis {
+            return Some("Insufficient cpu");
+        }
+        if self.allocated.memory_bytes + requests.memory_bytes > capacity.memory_bytes {
+            return Some("Insufficient memory");
+        }
+        None
+    }
+
+    fn score(&self, pod: &PodManifest, affinity: &Affinity, spreads: &[Spread], requests: Requests, placed: &[Placed]) -> i64 {
+        let capacity = self.node.capacity.clone().unwrap_or_default();
+        let usage = self.node.usage.clone().unwrap_or_default();
+        let free = |used: i64, capacity: i64| if capacity <= 0 { 0 } else { (capacity - used) * 100 / capacity };
+        let cpu = free(self.allocated.cpu_millis.max(usage.cpu_millis) + requests.cpu_millis, capacity.cpu_millis);
+        let memory = free(self.allocated.memory_bytes.max(usage.memory_bytes) + requests.memory_bytes, capacity.memory_bytes);
+        let preferred_taints = self.node.taints
+            .iter()
+            .filter(|taint| taint.effect == "PreferNoSchedule")
+            .filter(|taint| !pod.spec.tolerations.iter().any(|t| t.tolerates(&taint.key, &taint.value, &taint.effect)))
+            .count() as i64;
+        (cpu + memory) / 2 - preferred_taints * PREFER_NO_SCHEDULE_PENALTY
+            + affinity.score(&pod.metadata.namespace, self.node, placed)
+            - spreads.iter().map(|spread| spread.penalty(self.node)).sum::<i64>()
+    }
+}
+
+// pick the node for a pod next to the placed pods, the error tells why no node fits
+pub fn select_node<'a>(pod: &PodManifest, candidates: &[Candidate<'a>], placed: &[Placed]) -> Result<&'a Node> {
+    let requests = pod.requests()?;
+    let none = Affinity::default();
+    let affinity = pod.spec.affinity.as_ref().unwrap_or(&none);
+    // the domains of the spread constraints are those of the nodes the pod
+    // could go onto whatever runs there
+    let eligible: Vec<&Node> = candidates.iter().map(|candidate| candidate.node).filter(|node| mismatch(pod, node).is_none()).collect();
+    let spreads: Vec<Spread> = pod.spec.topology_spread_constraints
+        .iter()
+        .map(|constraint| Spread::new(constraint, &pod.metadata.namespace, &pod.metadata.labels, &eligible, placed))
+        .collect();
+    let mut reasons: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut best: Option<(i64, &Node)> = None;
+    for candidate in candidates {
+        if let Some(reason) = candidate.unfit_reason(pod, affinity, &spreads, requests, placed) {
+            *reasons.entry(reason).or_default() += 1;
+            continue;
+        }
+        let score = candidate.score(pod, affinity, &spreads, requests, placed);
+        let better = best.is_none_or(|(best_score, node)| {
+            score > best_score || (score == best_score && candidate.node.name < node.name)
+        });
+        if better {
+            best = Some((score, candidate.node));
+        }
+    }
+    best.map(|(_, node)| node).ok_or_else(|| {
+        let reasons: Vec<String> = reasons.iter().map(|(reason, count)| format!("{} node(s) {}", count, reason)).collect();
+        let reasons = if reasons.is_empty() { String::new() } else { format!(": {}", reasons.join(", ")) };
+        anyhow!("0/{} nodes are available{}", candidates.len(), reasons)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::scheduler::{Resources, Taint};
+
+    fn node(name: &str, cpu_millis: i64) -> Node {
+        Node {
+            name: name.to_string(),
+            capacity: Some(Resources { cpu_millis, memory_bytes: 8 << 30, pods: 110 }),
+            ..Default::default()
+        }
+    }
+
+    fn pod(spec: &str) -> PodManifest {
+        PodManifest::parse(&format!(
+            "apiVersion: v1\nkind: Pod\nmetadata:\n  name: web\nspec:\n{}",
+            spec
+        ))
+        .unwrap()
+    }
+
+    const REQUESTS: &str = "  containers:\n    - name: app\n      image: app:v1\n      resources:\n        requests:\n          cpu: 1\n";
+
+    #[test]
+    fn test_least_allocated_node_wins() {
+        let (small, large) = (node("a", 2000), node("b", 4000));
+        let candidates = [
+            Candidate { node: &small, allocated: Requests::default(), pods: 0 },
+            Candidate { node: &large, allocated: Requests::default(), pods: 0 },
+        ];
+        assert_eq!(select_node(&pod(REQUESTS), &candidates, &[]).unwrap().name, "b");
+
+        // the large node is busy now and the small one doesn't fit
+        let candidates = [
+            Candidate { node: &small, allocated: Requests { cpu_millis: 1500, memory_bytes: 0 }, pods: 1 },
+            Candidate { node: &large, allocated: Requests { cpu_millis: 3500, memory_bytes: 0 }, pods: 3 },
+        ];
+        let err = select_node(&pod(REQUESTS), &candidates, &[]).unwrap_err();
+        assert_eq!(err.to_string(), "0/2 nodes are available: 2 node(s) Insufficient cpu");
+
+        // the pods of the large node use far more than they requested
+        let mut busy = node("b", 4000);
+        busy.usage = Some(Resources { cpu_millis: 3000, memory_bytes: 0, pods: 0 });
+        let candidates = [
+            Candidate { node: &small, allocated: Requests::default(), pods: 0 },
+            Candidate { node: &busy, allocated: Requests { cpu_millis: 500, memory_bytes: 0 }, pods: 1 },
+        ];
+        assert_eq!(select_node(&pod(REQUESTS), &candidates, &[]).unwrap().name, "a");
+    }
+
+    #[test]
+    fn test_node_selector_and_taints() {
+        let mut gpu = node("gpu", 4000);
+        gpu.labels.insert("accelerator".to_string(), "nvidia".to_string());
+        gpu.taints.push(Taint {
+            key: "dedicated".to_string(),
+            value: "gpu".to_string(),
+            effect: "NoSchedule".to_string(),
+        });
+        let plain = node("plain", 4000);
+        let candidates = [
+            Candidate { node: &gpu, allocated: Requests::default(), pods: 0 },
+            Candidate { node: &plain, allocated: Requests::default(), pods: 0 },
+        ];
+        assert_eq!(select_node(&pod(REQUESTS), &candidates, &[]).unwrap().name, "plain");
+
+        let selector = format!("  nodeSelector:\n    accelerator: nvidia\n{}", REQUESTS);
+        assert!(select_node(&pod(&selector), &candidates, &[]).is_err());
+        let tolerated = format!(
+            "{}  tolerations:\n    - key: dedicated\n      value: gpu\n      effect: NoSchedule\n",
+            selector
+        );
+        assert_eq!(select_node(&pod(&tolerated), &candidates, &[]).unwrap().name, "gpu");
+
+        // a Windows pod only goes onto Windows nodes
+        let windows = format!("  os:\n    name: windows\n{}", REQUESTS);
+        assert!(select_node(&pod(&windows), &candidates, &[]).is_err());
+        let mut windows_node = node("win", 4000);
+        windows_node.labels.insert("kubernetes.io/os".to_string(), "windows".to_string());
+        let candidates = [Candidate { node: &windows_node, allocated: Requests::default(), pods: 0 }];
+        assert_eq!(select_node(&pod(&windows), &candidates, &[]).unwrap().name, "win");
+    }
+}
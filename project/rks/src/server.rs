@@ -0,0 +1,1474 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use anyhow::{Result, anyhow};
+use prost::Message;
+use store::Store;
+use store::lease::Lease;
+use tonic::codegen::tokio_stream;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity, Server, ServerTlsConfig};
+use tonic::{Request, Response, Status};
+use crate::pb::control::{CheckpointPodRequest, CreatePodRequest, DeletePodRequest as DeleteNodePodRequest, RestorePodChunk};
+use crate::pb::control::pod_service_client::PodServiceClient;
+use crate::pb::scheduler::scheduler_server::{Scheduler, SchedulerServer};
+use crate::pb::scheduler::{
+    ApplyDaemonSetRequest, ApplyDaemonSetResponse, ApplyLimitRangeRequest, ApplyLimitRangeResponse,
+    ApplyPodDisruptionBudgetRequest, ApplyPodDisruptionBudgetResponse, ApplyResourceQuotaRequest,
+    ApplyResourceQuotaResponse, CordonNodeRequest, CordonNodeResponse, CreateNamespaceRequest,
+    CreateNamespaceResponse, DaemonSetStatus, DeleteDaemonSetRequest, DeleteDaemonSetResponse, DeleteLimitRangeRequest,
+    DeleteLimitRangeResponse, DeleteNamespaceRequest, DeleteNamespaceResponse, DeletePodDisruptionBudgetRequest,
+    DeletePodDisruptionBudgetResponse, DeletePodRequest, DeletePodResponse, DeleteResourceQuotaRequest,
+    DeleteResourceQuotaResponse, DeregisterNodeRequest, DeregisterNodeResponse, EvictPodRequest, EvictPodResponse,
+    GetPodRequest, GetPodResponse, LimitRangeStatus, ListDaemonSetsRequest, ListDaemonSetsResponse,
+    ListLimitRangesRequest, ListLimitRangesResponse, ListNamespacesRequest, ListNamespacesResponse, ListNodesRequest,
+    ListNodesResponse, ListPodDisruptionBudgetsRequest, ListPodDisruptionBudgetsResponse, ListResourceQuotasRequest,
+    ListResourceQuotasResponse, MigratePodRequest, MigratePodResponse, Node, NodeStatus, PodDisruptionBudgetStatus,
+    PodLabels, PodState, RegisterNodeRequest, RegisterNodeResponse, RenewNodeLeaseRequest, RenewNodeLeaseResponse, Resources,
+    ResourceQuotaStatus, SchedulePodRequest, SchedulePodResponse,
+};
+use crate::daemonset::{self, Action, DaemonSet, NodeView};
+use crate::heartbeat::{self, Health, Transition};
+use crate::namespace;
+use crate::pdb::{self, Disruption, PodDisruptionBudget};
+use crate::quota::{ResourceQuota, Usage};
+use crate::limitrange::{self, LimitRange};
+use crate::affinity::{Placed, PodAffinityTerm};
+use crate::pod::{PodManifest, Requests};
+use crate::schedule::{self, Candidate};
+use crate::auth::{Authorizer, Verb};
+use crate::logging;
+use tracing::{Instrument, Span, info, info_span, warn};
+
+// a pod placed onto a node
+struct ScheduledPod {
+    name: String,
+    namespace: String,
+    labels: HashMap<String, String>,
+    manifest: String,
+    requests: Requests,
+    limits: Requests,
+    scheduled: Instant,
+    // keeps the pods it matches off the nodes of its domain, see affinity
+    anti_affinity: Vec<PodAffinityTerm>,
+}
+
+impl ScheduledPod {
+    fn new(pod: &PodManifest, manifest: &str, requests: Requests) -> Self {
+        ScheduledPod {
+            name: pod.metadata.name.clone(),
+            namespace: pod.metadata.namespace.clone(),
+            labels: pod.metadata.labels.clone(),
+            manifest: manifest.to_string(),
+            requests,
+            limits: pod.limits().unwrap_or_default(),
+            scheduled: Instant::now(),
+            anti_affinity: pod.spec.affinity.as_ref().map(|affinity| affinity.required_anti_affinity().to_vec()).unwrap_or_default(),
+        }
+    }
+}
+
+// a registered node and the pods placed onto it
+struct NodeState {
+    node: Node,
+    last_seen: Instant,
+    health: Health,
+    // the scheduled pods by namespaced_key
+    pods: HashMap<String, ScheduledPod>,
+    // pods scheduled onto other nodes while this one was NotReady, which it
+    // still runs until it is told to delete them, by namespaced_key
+    evicted: BTreeSet<String>,
+    // the user whose token registered the node, see auth
+    owner: Option<String>,
+}
+
+// pods, pod disruption budgets, resource quotas and limit ranges are only
+// unique within their namespace, the nodes report their pods by name as a node
+// runs one pod of a name
+fn namespaced_key(namespace: &str, name: &str) -> String {
+    format!("{}/{}", namespace, name)
+}
+
+// the namespace and the name of a namespaced_key
+fn split_key(key: &str) -> (&str, &str) {
+    key.split_once('/').unwrap_or((namespace::DEFAULT, key))
+}
+
+// the pods of every node for the affinity of a pod, but the pod itself
+fn placed<'a>(nodes: &'a HashMap<String, NodeState>, except: &str) -> Vec<Placed<'a>> {
+    nodes
+        .values()
+        .flat_map(|state| {
+            state.pods.iter().filter(move |(key, _)| key.as_str() != except).map(|(_, pod)| Placed {
+                node: &state.node,
+                namespace: &pod.namespace,
+                labels: &pod.labels,
+                anti_affinity: &pod.anti_affinity,
+            })
+        })
+        .collect()
+}
+
+// what the scheduled pods of the namespace take, for its quotas
+fn usage(nodes: &HashMap<String, NodeState>, namespace: &str) -> Usage {
+    nodes
+        .values()
+        .flat_map(|state| state.pods.values())
+        .filter(|pod| pod.namespace == namespace)
+        .fold(Usage::default(), |total, pod| total + Usage { pods: 1, requests: pod.requests, limits: pod.limits })
+}
+
+impl NodeState {
+    fn new(node: Node, last_seen: Instant) -> Self {
+        NodeState { node, last_seen, health: Health::default(), pods: HashMap::new(), evicted: BTreeSet::new(), owner: None }
+    }
+
+    fn allocated(&self) -> Requests {
+        self.pods.values().fold(Requests::default(), |total, ScheduledPod { requests, .. }| Requests {
+            cpu_millis: total.cpu_millis + requests.cpu_millis,
+            memory_bytes: total.memory_bytes + requests.memory_bytes,
+        })
+    }
+}
+
+// a daemon set and the template hash of its pod on every node that has one
+struct DaemonSetState {
+    daemonset: DaemonSet,
+    pods: HashMap<String, String>,
+}
+
+// With a store the nodes and the pods scheduled onto them are kept there, as
+// /registry/nodes/<node> (the Node message, without its token),
+// /registry/nodeowners/<node> (the user that registered it),
+// /registry/pods/<node>/<namespace>/<pod> (the manifest) and /registry/podips/<node> (the
+// IPs the node reported for its pods, JSON by pod name), so that a restarted
+// scheduler still accounts for the pods it placed before and knows where they are. Daemon sets are kept as
+// /registry/daemonsets/<name> (the manifest) and namespaces as
+// /registry/namespaces/<name>, see namespace. Pod disruption budgets are kept
+// as /registry/poddisruptionbudgets/<namespace>/<name> (the manifest), see pdb, resource
+// quotas as /registry/resourcequotas/<namespace>/<name> and limit ranges as
+// /registry/limitranges/<namespace>/<name>, see quota and limitrange.
+const NODES_PREFIX: &str = "/registry/nodes/";
+const NODE_OWNERS_PREFIX: &str = "/registry/nodeowners/";
+const PODS_PREFIX: &str = "/registry/pods/";
+const POD_IPS_PREFIX: &str = "/registry/podips/";
+const DAEMONSETS_PREFIX: &str = "/registry/daemonsets/";
+const NAMESPACES_PREFIX: &str = "/registry/namespaces/";
+const BUDGETS_PREFIX: &str = "/registry/poddisruptionbudgets/";
+const QUOTAS_PREFIX: &str = "/registry/resourcequotas/";
+const LIMIT_RANGES_PREFIX: &str = "/registry/limitranges/";
+// how often the pods of the daemon sets are checked against the nodes
+const DAEMONSET_SYNC_INTERVAL: Duration = Duration::from_secs(5);
+// the checkpoint archives of migrations are streamed in chunks of this size,
+// like the nodes stream them, within the default limits of gRPC
+const CHECKPOINT_CHUNK_SIZE: usize = 1 << 20;
+// and are refused past this
+const MAX_CHECKPOINT_SIZE: usize = u32::MAX as usize;
+
+pub struct SchedulerService {
+    nodes: Arc<Mutex<HashMap<String, NodeState>>>,
+    daemonsets: Mutex<HashMap<String, DaemonSetState>>,
+    namespaces: Mutex<BTreeSet<String>>,
+    budgets: Mutex<HashMap<String, PodDisruptionBudget>>,
+    quotas: Mutex<HashMap<String, ResourceQuota>>,
+    limit_ranges: Mutex<HashMap<String, LimitRange>>,
+    // nodes that didn't register again within this are not scheduled onto
+    node_timeout: Duration,
+    // the pods of nodes NotReady for this long are scheduled elsewhere, see heartbeat
+    pod_eviction_timeout: Duration,
+    store: Option<Box<dyn Store>>,
+    // who may call which methods, see auth
+    authorizer: Authorizer,
+}
+
+impl SchedulerService {
+    pub fn new(
+        node_timeout: Duration,
+        pod_eviction_timeout: Duration,
+        store: Option<Box<dyn Store>>,
+        authorizer: Authorizer,
+    ) -> Result<Self> {
+        let mut nodes = HashMap::new();
+        let mut daemonsets = HashMap::new();
+        let mut budgets = HashMap::new();
+        let mut quotas = HashMap::new();
+        let mut limit_ranges = HashMap::new();
+        let mut namespaces: BTreeSet<String> = namespace::BUILT_IN.iter().map(|name| name.to_string()).collect();
+        if let Some(store) = &store {
+            for kv in store.list(NAMESPACES_PREFIX)?.items {
+                namespaces.insert(kv.key[NAMESPACES_PREFIX.len()..].to_string());
+            }
+            for kv in store.list(DAEMONSETS_PREFIX)?.items {
+                let manifest = String::from_utf8(kv.value)?;
+                match DaemonSet::parse(&manifest) {
+                    Ok(daemonset) => {
+                        let state = DaemonSetState { daemonset, pods: HashMap::new() };
+                        daemonsets.insert(state.daemonset.metadata.name.clone(), state);
+                    }
+                    Err(e) => warn!("Ignoring stored daemon set {}: {}", kv.key, e),
+                }
+            }
+            for kv in store.list(BUDGETS_PREFIX)?.items {
+                let manifest = String::from_utf8(kv.value)?;
+                match PodDisruptionBudget::parse(&manifest) {
+                    Ok(budget) => {
+                        let key = namespaced_key(&budget.metadata.namespace, &budget.metadata.name);
+                        // budgets stored by name alone before they were by namespace
+                        if kv.key[BUDGETS_PREFIX.len()..] != key {
+                            store.put(&format!("{}{}", BUDGETS_PREFIX, key), manifest.as_bytes())?;
+                            store.delete(&kv.key, None)?;
+                        }
+                        budgets.insert(key, budget);
+                    }
+                    Err(e) => warn!("Ignoring stored pod disruption budget {}: {}", kv.key, e),
+                }
+            }
+            for kv in store.list(QUOTAS_PREFIX)?.items {
+                let manifest = String::from_utf8(kv.value)?;
+                match ResourceQuota::parse(&manifest) {
+                    Ok(quota) => {
+                        let key = namespaced_key(&quota.metadata.namespace, &quota.metadata.name);
+                        // quotas stored by name alone before they were by namespace
+                        if kv.key[QUOTAS_PREFIX.len()..] != key {
+                            store.put(&format!("{}{}", QUOTAS_PREFIX, key), manifest.as_bytes())?;
+                            store.delete(&kv.key, None)?;
+                        }
+                        quotas.insert(key, quota);
+                    }
+                    Err(e) => warn!("Ignoring stored resource quota {}: {}", kv.key, e),
+                }
+            }
+            for kv in store.list(LIMIT_RANGES_PREFIX)?.items {
+                let manifest = String::from_utf8(kv.value)?;
+                match LimitRange::parse(&manifest) {
+                    Ok(range) => {
+                        let key = namespaced_key(&range.metadata.namespace, &range.metadata.name);
+                        // limit ranges stored by name alone before they were by namespace
+                        if kv.key[LIMIT_RANGES_PREFIX.len()..] != key {
+                            store.put(&format!("{}{}", LIMIT_RANGES_PREFIX, key), manifest.as_bytes())?;
+                            store.delete(&kv.key, None)?;
+                        }
+                        limit_ranges.insert(key, range);
+                    }
+                    Err(e) => warn!("Ignoring stored limit range {}: {}", kv.key, e),
+                }
+            }
+            // restored nodes aren't ready until they register again
+            let last_seen = Instant::now().checked_sub(node_timeout).unwrap_or_else(Instant::now);
+            for kv in store.list(NODES_PREFIX)?.items {
+                let node = Node::decode(kv.value.as_slice()).map_err(|e| anyhow!("Invalid node {}: {}", kv.key, e))?;
+                nodes.insert(node.name.clone(), NodeState::new(node, last_seen));
+            }
+            // the phases are only known once the nodes report them again
+            for kv in store.list(POD_IPS_PREFIX)?.items {
+                let ips: BTreeMap<String, String> = match serde_json::from_slice(&kv.value) {
+                    Ok(ips) => ips,
+                    Err(e) => {
+                        warn!("Ignoring stored pod IPs {}: {}", kv.key, e);
+                        continue;
+                    }
+                };
+                if let Some(state) = nodes.get_mut(&kv.key[POD_IPS_PREFIX.len()..]) {
+                    state.node.pod_states = ips.into_iter().map(|(pod, pod_ip)| (pod, PodState { phase: String::new(), pod_ip })).collect();
+                }
+            }
+            for kv in store.list(NODE_OWNERS_PREFIX)?.items {
+                if let Some(state) = nodes.get_mut(&kv.key[NODE_OWNERS_PREFIX.len()..]) {
+                    state.owner = Some(String::from_utf8(kv.value)?);
+                }
+            }
+            for kv in store.list(PODS_PREFIX)?.items {
+                let Some((node, stored_key)) = kv.key[PODS_PREFIX.len()..].split_once('/') else {
+                    continue;
+                };
+                let contents = String::from_utf8(kv.value).map_err(anyhow::Error::from);
+                let manifest = contents.as_ref().map_err(|e| anyhow!("{}", e)).and_then(|contents| PodManifest::parse(contents));
+                let requests = manifest.as_ref().map_err(|e| anyhow!("{}", e)).and_then(|pod| pod.requests());
+                match (nodes.get_mut(node), requests) {
+                    (Some(state), Ok(requests)) => {
+                        let (Ok(contents), Ok(manifest)) = (&contents, manifest) else {
+                            continue;
+                        };
+                        let key = namespaced_key(&manifest.metadata.namespace, &manifest.metadata.name);
+                        // pods stored by name alone before they were by namespace
+                        if stored_key != key {
+                            store.put(&format!("{}{}/{}", PODS_PREFIX, node, key), contents.as_bytes())?;
+                            store.delete(&kv.key, None)?;
+                        }
+                        state.pods.insert(key, ScheduledPod::new(&manifest, contents, requests));
+                        // the pods of the daemon sets of their namespace belong to them again
+                        let labels = &manifest.metadata.labels;
+                        if let (Some(owner), Some(hash)) = (labels.get(daemonset::DAEMONSET_NAME), labels.get(daemonset::POD_TEMPLATE_HASH))
+                            && let Some(owner) = daemonsets.get_mut(owner)
+                            && owner.daemonset.metadata.namespace == manifest.metadata.namespace
+                        {
+                            owner.pods.insert(node.to_string(), hash.clone());
+                        }
+                    }
+                    (_, Err(e)) => warn!("Ignoring stored pod {}: {}", kv.key, e),
+                    (None, _) => warn!("Ignoring stored pod {}: node {} is unknown", kv.key, node),
+                }
+            }
+            info!("Restored {} node(s) and {} daemon set(s) from the store", nodes.len(), daemonsets.len());
+        }
+        Ok(SchedulerService {
+            nodes: Arc::new(Mutex::new(nodes)),
+            daemonsets: Mutex::new(daemonsets),
+            namespaces: Mutex::new(namespaces),
+            budgets: Mutex::new(budgets),
+            quotas: Mutex::new(quotas),
+            limit_ranges: Mutex::new(limit_ranges),
+            node_timeout,
+            pod_eviction_timeout,
+            store,
+            authorizer,
+        })
+    }
+
+    // whether the node renews its lease, or registers for one without
+    fn ready(&self, state: &NodeState) -> bool {
+        state.health.ready(state.last_seen, self.node_timeout, Instant::now())
+    }
+
+    // how the pods the budget selects stand, a pod being healthy while its
+    // node is ready and reports it running
+    fn disruption(&self, nodes: &HashMap<String, NodeState>, budget: &PodDisruptionBudget) -> Disruption {
+        let (mut expected, mut healthy) = (0, 0);
+        for state in nodes.values() {
+            for pod in state.pods.values().filter(|pod| budget.selects(&pod.namespace, &pod.labels)) {
+                expected += 1;
+                if self.healthy(state, &pod.name) {
+                    healthy += 1;
+                }
+            }
+        }
+        budget.disruption(expected, healthy)
+    }
+
+    fn healthy(&self, state: &NodeState, pod: &str) -> bool {
+        self.ready(state) && state.node.pod_states.get(pod).is_some_and(|pod| pod.phase == "Running")
+    }
+
+    // apply a change to the store, if any; the in-memory state stays
+    // authoritative so a failing store only costs the state on restart
+    fn persist(&self, change: impl FnOnce(&dyn Store) -> Result<()>) {
+        if let Some(store) = &self.store
+            && let Err(e) = tokio::task::block_in_place(|| change(store.as_ref()))
+        {
+            warn!("Failed to update the store: {}", e);
+        }
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn check_namespace(&self, name: &str) -> Result<(), Status> {
+        if !self.namespaces.lock().unwrap().contains(name) {
+            return Err(Status::not_found(format!("namespace {} not found", name)));
+        }
+        Ok(())
+    }
+
+    // the new pod with the defaults of the limit ranges of its namespace
+    fn limit(&self, pod: PodManifest, manifest: String) -> Result<(PodManifest, String)> {
+        let limit_ranges = self.limit_ranges.lock().unwrap();
+        let ranges: Vec<&LimitRange> = limit_ranges.values().filter(|range| range.metadata.namespace == pod.metadata.namespace).collect();
+        if ranges.is_empty() {
+            return Ok((pod, manifest));
+        }
+        limitrange::admit(&manifest, &ranges)
+    }
+
+    // whether the quotas of its namespace leave room for a new pod
+    fn check_quotas(&self, nodes: &HashMap<String, NodeState>, pod: &PodManifest) -> Result<()> {
+        let quotas = self.quotas.lock().unwrap();
+        let mut quotas = quotas.values().filter(|quota| quota.metadata.namespace == pod.metadata.namespace).peekable();
+        if quotas.peek().is_none() {
+            return Ok(());
+        }
+        let used = usage(nodes, &pod.metadata.namespace);
+        quotas.try_for_each(|quota| quota.admit(pod, used))
+    }
+
+    // pick a node and reserve the requests of the pod on it
+    #[allow(clippy::result_large_err)]
+    fn place(&self, pod: &PodManifest, manifest: &str, dry_run: bool) -> Result<Node, Status> {
+        let requests = pod.requests().map_err(|e| Status::invalid_argument(e.to_string()))?;
+        self.check_namespace(&pod.metadata.namespace)?;
+        let key = namespaced_key(&pod.metadata.namespace, &pod.metadata.name);
+        let mut nodes = self.nodes.lock().unwrap();
+        if let Some(state) = nodes.values().find(|state| state.pods.contains_key(&key)) {
+            return Err(Status::already_exists(format!(
+                "pod {} is already scheduled onto {}",
+                pod.metadata.name, state.node.name
+            )));
+        }
+        self.check_quotas(&nodes, pod).map_err(|e| Status::resource_exhausted(e.to_string()))?;
+        let candidates: Vec<Candidate> = nodes
+            .values()
+            .filter(|state| self.ready(state) && !state.node.unschedulable)
+            .map(|state| Candidate { node: &state.node, allocated: state.allocated(), pods: state.pods.len() })
+            .collect();
+        let node = schedule::select_node(pod, &candidates, &placed(&nodes, &key))
+            .map_err(|e| Status::failed_precondition(e.to_string()))?
+            .clone();
+        if dry_run {
+            return Ok(node);
+        }
+        if let Some(state) = nodes.get_mut(&node.name) {
+            state.pods.insert(key.clone(), ScheduledPod::new(pod, manifest, requests));
+            state.evicted.remove(&key);
+        }
+        drop(nodes);
+        self.persist(|store| store.put(&format!("{}{}/{}", PODS_PREFIX, node.name, key), manifest.as_bytes()).map(|_| ()));
+        Ok(node)
+    }
+
+    // the node of a scheduled pod of the namespace
+    #[allow(clippy::result_large_err)]
+    fn scheduled_node(&self, name: &str, namespace: &str) -> Result<Node, Status> {
+        self.nodes
+            .lock()
+            .unwrap()
+            .values()
+            .find(|state| state.pods.contains_key(&namespaced_key(namespace, name)))
+            .map(|state| state.node.clone())
+            .ok_or_else(|| Status::not_found(format!("pod {} is not scheduled in namespace {}", name, namespace)))
+    }
+
+    // record the manifest a scheduled pod was updated to on its node
+    fn replace(&self, node: &str, pod: &PodManifest, manifest: &str, requests: Requests) {
+        let key = namespaced_key(&pod.metadata.namespace, &pod.metadata.name);
+        if let Some(state) = self.nodes.lock().unwrap().get_mut(node) {
+            state.pods.insert(key.clone(), ScheduledPod::new(pod, manifest, requests));
+        }
+        self.persist(|store| store.put(&format!("{}{}/{}", PODS_PREFIX, node, key), manifest.as_bytes()).map(|_| ()));
+    }
+
+    // reserve the requests of a pod of a daemon set on its node
+    fn reserve(&self, node: &str, pod: &PodManifest, manifest: &str) -> Result<()> {
+        let requests = pod.requests()?;
+        let key = namespaced_key(&pod.metadata.namespace, &pod.metadata.name);
+        let mut nodes = self.nodes.lock().unwrap();
+        if let Some(state) = nodes.values().find(|state| state.pods.contains_key(&key)) {
+            return Err(anyhow!("pod {} is already scheduled onto {}", pod.metadata.name, state.node.name));
+        }
+        self.check_quotas(&nodes, pod)?;
+        let state = nodes.get_mut(node).ok_or_else(|| anyhow!("node {} is not registered", node))?;
+        state.pods.insert(key.clone(), ScheduledPod::new(pod, manifest, requests));
+        drop(nodes);
+        self.persist(|store| store.put(&format!("{}{}/{}", PODS_PREFIX, node, key), manifest.as_bytes()).map(|_| ()));
+        Ok(())
+    }
+
+    fn unreserve(&self, node: &str, key: &str) {
+        if let Some(state) = self.nodes.lock().unwrap().get_mut(node) {
+            state.pods.remove(key);
+        }
+        self.persist(|store| store.delete(&format!("{}{}/{}", PODS_PREFIX, node, key), None).map(|_| ()));
+    }
+
+    // the ready node a scheduled pod can be migrated to, checked like a placement
+    #[allow(clippy::result_large_err)]
+    fn migration_target(&self, key: &str, source: &str, target: &str) -> Result<Node, Status> {
+        let (_, name) = split_key(key);
+        if target == source {
+            return Err(Status::invalid_argument(format!("pod {} already runs on node {}", name, target)));
+        }
+        let nodes = self.nodes.lock().unwrap();
+        let pod = nodes
+            .get(source)
+            .and_then(|state| state.pods.get(key))
+            .ok_or_else(|| Status::not_found(format!("pod {} is not scheduled onto node {}", name, source)))?;
+        let manifest = PodManifest::parse(&pod.manifest).map_err(|e| Status::internal(e.to_string()))?;
+        if let Some(owner) = manifest.metadata.labels.get(daemonset::DAEMONSET_NAME) {
+            return Err(Status::failed_precondition(format!("pod {} belongs to daemon set {}, which has one on every node", name, owner)));
+        }
+        let state = nodes.get(target).ok_or_else(|| Status::not_found(format!("node {} is not registered", target)))?;
+        if !self.ready(state) {
+            return Err(Status::failed_precondition(format!("node {} is not ready", target)));
+        }
+        if state.node.unschedulable {
+            return Err(Status::failed_precondition(format!("node {} is cordoned", target)));
+        }
+        let candidate = Candidate { node: &state.node, allocated: state.allocated(), pods: state.pods.len() };
+        schedule::select_node(&manifest, &[candidate], &placed(&nodes, key)).map_err(|e| Status::failed_precondition(e.to_string()))?;
+        Ok(state.node.clone())
+    }
+
+    // move the reservation of a migrated pod to its new node
+    fn relocate(&self, key: &str, source: &str, target: &str) {
+        let manifest = {
+            let mut nodes = self.nodes.lock().unwrap();
+            let Some(mut pod) = nodes.get_mut(source).and_then(|state| state.pods.remove(key)) else {
+                return;
+            };
+            // the new node may not report the pod at its next registration yet
+            pod.scheduled = Instant::now();
+            let manifest = pod.manifest.clone();
+            if let Some(state) = nodes.get_mut(target) {
+                state.pods.insert(key.to_string(), pod);
+            }
+            manifest
+        };
+        self.persist(|store| {
+            store.put(&format!("{}{}/{}", PODS_PREFIX, target, key), manifest.as_bytes())?;
+            store.delete(&format!("{}{}/{}", PODS_PREFIX, source, key), None)?;
+            Ok(())
+        });
+    }
+
+    // follow the readiness of the nodes, scheduling the pods of the nodes
+    // NotReady for the pod eviction timeout onto others
+    pub async fn monitor_nodes(&self) {
+        let now = Instant::now();
+        let mut evictions = Vec::new();
+        {
+            let mut nodes = self.nodes.lock().unwrap();
+            for state in nodes.values_mut() {
+                let ready = state.health.ready(state.last_seen, self.node_timeout, now);
+                match state.health.observe(ready, now) {
+                    Some(Transition::NotReady) => info!("Node {} is NotReady", state.node.name),
+                    Some(Transition::Ready) => info!("Node {} is Ready", state.node.name),
+                    None => {}
+                }
+                if !state.health.eviction_due(self.pod_eviction_timeout, now) {
+                    continue;
+                }
+                // the daemon sets have their own pod on every node
+                for (key, pod) in &state.pods {
+                    if !pod.labels.contains_key(daemonset::DAEMONSET_NAME) {
+                        evictions.push((state.node.name.clone(), key.clone(), pod.manifest.clone()));
+                    }
+                }
+            }
+        }
+        for (source, key, manifest) in evictions {
+            match self.evict(&source, &key, manifest).await {
+                Ok(target) => info!("Evicted Pod {} from NotReady node {} onto node {}", key, source, target),
+                Err(e) => warn!("Failed to evict Pod {} from NotReady node {}: {}", key, source, e),
+            }
+        }
+    }
+
+    // schedule a pod of a NotReady node onto another node, returning which
+    async fn evict(&self, source: &str, key: &str, manifest: String) -> Result<String> {
+        let pod = PodManifest::parse(&manifest)?;
+        let Some(scheduled) = self.nodes.lock().unwrap().get_mut(source).and_then(|state| state.pods.remove(key)) else {
+            return Err(anyhow!("pod {} is no longer scheduled onto node {}", key, source));
+        };
+        // the pod stays on its node until it runs elsewhere
+        let restore = |scheduled| {
+            if let Some(state) = self.nodes.lock().unwrap().get_mut(source) {
+                state.pods.insert(key.to_string(), scheduled);
+            }
+        };
+        let node = match self.place(&pod, &manifest, false) {
+            Ok(node) => node,
+            Err(e) => {
+                restore(scheduled);
+                return Err(anyhow!("{}", e.message()));
+            }
+        };
+        if let Err(e) = dispatch(&node, manifest, false).await {
+            self.unreserve(&node.name, key);
+            restore(scheduled);
+            return Err(e);
+        }
+        if let Some(state) = self.nodes.lock().unwrap().get_mut(source) {
+            state.evicted.insert(key.to_string());
+        }
+        self.persist(|store| store.delete(&format!("{}{}/{}", PODS_PREFIX, source, key), None).map(|_| ()));
+        Ok(node.name)
+    }
+
+    // bring the pods of the daemon sets in line with the nodes
+    pub async fn sync_daemonsets(&self) {
+        let mut work = Vec::new();
+        {
+            let nodes = self.nodes.lock().unwrap();
+            let mut daemonsets = self.daemonsets.lock().unwrap();
+            let ready = |state: &NodeState| self.ready(state);
+            let mut views: Vec<NodeView> = nodes.values().map(|state| NodeView { node: &state.node, ready: ready(state) }).collect();
+            views.sort_by(|a, b| a.node.name.cmp(&b.node.name));
+            for (name, state) in daemonsets.iter_mut() {
+                // pods that vanished from their node are forgotten like other pods
+                let daemonset = &state.daemonset;
+                let namespace = &daemonset.metadata.namespace;
+                state.pods.retain(|node, _| nodes.get(node).is_some_and(|n| n.pods.contains_key(&namespaced_key(namespace, &daemonset.pod_name(node)))));
+                match daemonset::plan(daemonset, &views, &state.pods) {
+                    Ok(actions) => {
+                        for action in actions {
+                            let node = match &action {
+                                Action::Create(node) | Action::Delete(node) => nodes.get(node),
+                            };
+                            let node = node.filter(|state| ready(state)).map(|state| state.node.clone());
+                            work.push((name.clone(), action, node));
+                        }
+                    }
+                    Err(e) => warn!("Failed to plan daemon set {}: {}", name, e),
+                }
+            }
+        }
+
+        for (name, action, node) in work {
+            let (namespace, pod_name, manifest, hash) = {
+                let daemonsets = self.daemonsets.lock().unwrap();
+                let Some(state) = daemonsets.get(&name) else {
+                    continue;
+                };
+                let node_name = match &action {
+                    Action::Create(node) | Action::Delete(node) => node,
+                };
+                let manifest = state.daemonset.pod_manifest(node_name);
+                let namespace = state.daemonset.metadata.namespace.clone();
+                (namespace, state.daemonset.pod_name(node_name), manifest, state.daemonset.template_hash())
+            };
+            let key = namespaced_key(&namespace, &pod_name);
+            match action {
+                Action::Delete(node_name) => {
+                    // a node that isn't ready can't be asked, its pod is only forgotten
+                    if let Some(node) = &node
+                        && let Err(e) = recall(node, namespace, pod_name.clone()).await
+                    {
+                        warn!("Failed to delete Pod {} of daemon set {}: {}", pod_name, name, e);
+                        continue;
+                    }
+                    self.unreserve(&node_name, &key);
+                    if let Some(state) = self.daemonsets.lock().unwrap().get_mut(&name) {
+                        state.pods.remove(&node_name);
+                    }
+                    info!("Deleted Pod {} of daemon set {} from node {}", pod_name, name, node_name);
+                }
+                Action::Create(node_name) => {
+                    let Some(node) = node else {
+                        continue;
+                    };
+                    let result = match manifest.and_then(|manifest| self.limit(PodManifest::parse(&manifest)?, manifest)) {
+                        Ok((pod, manifest)) => match self.reserve(&node_name, &pod, &manifest) {
+                            Ok(()) => dispatch(&node, manifest, false).await.inspect_err(|_| self.unreserve(&node_name, &key)),
+                            Err(e) => Err(e),
+                        },
+                        Err(e) => Err(e),
+                    };
+                    if let Err(e) = result {
+                        warn!("Failed to create Pod {} of daemon set {}: {}", pod_name, name, e);
+                        continue;
+                    }
+                    if let Some(state) = self.daemonsets.lock().unwrap().get_mut(&name) {
+                        state.pods.insert(node_name.clone(), hash);
+                    }
+                    info!("Created Pod {} of daemon set {} on node {}", pod_name, name, node_name);
+                }
+            }
+        }
+    }
+}
+
+// the node as it is stored, without what changes at every registration
+fn stored(node: &Node) -> Node {
+    Node { token: String::new(), pods: Vec::new(), pod_states: HashMap::new(), ..node.clone() }
+}
+
+// the IPs the node reported for its pods, by pod name
+fn pod_ips(node: &Node) -> BTreeMap<String, String> {
+    node.pod_states
+        .iter()
+        .filter(|(_, state)| !state.pod_ip.is_empty())
+        .map(|(pod, state)| (pod.clone(), state.pod_ip.clone()))
+        .collect()
+}
+
+// verifies the nodes serving their control API over https, set once by serve
+static NODE_TLS: OnceLock<ClientTlsConfig> = OnceLock::new();
+
+async fn connect_node(node: &Node) -> Result<PodServiceClient<Channel>> {
+    let mut endpoint = Endpoint::from_shared(node.address.clone())
+        .map_err(|e| anyhow!("Invalid address {} of node {}: {}", node.address, node.name, e))?;
+    if node.address.starts_with("https://") {
+        let tls = NODE_TLS.get().ok_or_else(|| anyhow!("--node-ca-file is required to reach node {} over https", node.name))?;
+        endpoint = endpoint.tls_config(tls.clone())?;
+    }
+    let channel = endpoint
+        .connect()
+        .await
+        .map_err(|e| anyhow!("Failed to connect to node {} at {}: {}", node.name, node.address, e))?;
+    Ok(PodServiceClient::new(channel))
+}
+
+// a request to the control API of the node, with its token if any
+fn node_request<T>(node: &Node, message: T) -> Result<Request<T>> {
+    let mut request = Request::new(message);
+    if !node.token.is_empty() {
+        let authorization = format!("Bearer {}", node.token)
+            .parse()
+            .map_err(|_| anyhow!("invalid token of node {}", node.name))?;
+        request.metadata_mut().insert("authorization", authorization);
+    }
+    logging::propagate(request.metadata_mut());
+    Ok(request)
+}
+
+// create the pod on the node through the control API of its daemon, or
+// replace its manifest
+async fn dispatch(node: &Node, manifest: String, replace: bool) -> Result<()> {
+    connect_node(node)
+        .await?
+        .create_pod(node_request(node, CreatePodRequest { manifest, replace })?)
+        .await
+        .map_err(|e| anyhow!("Node {} refused the pod: {}", node.name, e.message()))?;
+    Ok(())
+}
+
+// delete the pod from the node, a pod the node doesn't have is already gone
+async fn recall(node: &Node, namespace: String, name: String) -> Result<()> {
+    let result = connect_node(node)
+        .await?
+        .delete_pod(node_request(node, DeleteNodePodRequest { name, namespace })?)
+        .await;
+    match result {
+        Err(e) if e.code() != tonic::Code::NotFound => {
+            Err(anyhow!("Node {} failed to delete the pod: {}", node.name, e.message()))
+        }
+        _ => Ok(()),
+    }
+}
+
+// the checkpoint archive of the pod on the node, which keeps running it
+async fn checkpoint(node: &Node, name: String, timeout: i64) -> Result<Vec<u8>> {
+    let failed = |e: Status| anyhow!("Node {} failed to checkpoint the pod: {}", node.name, e.message());
+    let mut chunks = connect_node(node)
+        .await?
+        .checkpoint_pod(node_request(node, CheckpointPodRequest { name, timeout })?)
+        .await
+        .map_err(failed)?
+        .into_inner();
+    let mut archive = Vec::new();
+    while let Some(chunk) = chunks.message().await.map_err(failed)? {
+        if archive.len() + chunk.data.len() > MAX_CHECKPOINT_SIZE {
+            return Err(anyhow!("The checkpoint archive of node {} exceeds {} bytes", node.name, MAX_CHECKPOINT_SIZE));
+        }
+        archive.extend_from_slice(&chunk.data);
+    }
+    Ok(archive)
+}
+
+// run the pod of a checkpoint archive on the node
+async fn restore(node: &Node, archive: Vec<u8>, source: String) -> Result<()> {
+    // the source goes with the first chunk
+    let mut chunks: Vec<RestorePodChunk> =
+        archive.chunks(CHECKPOINT_CHUNK_SIZE).map(|data| RestorePodChunk { data: data.to_vec(), source: String::new() }).collect();
+    match chunks.first_mut() {
+        Some(first) => first.source = source,
+        None => chunks.push(RestorePodChunk { data: Vec::new(), source }),
+    }
+    connect_node(node)
+        .await?
+        .restore_pod(node_request(node, tokio_stream::iter(chunks))?)
+        .await
+        .map_err(|e| anyhow!("Node {} failed to restore the pod: {}", node.name, e.message()))?;
+    Ok(())
+}
+
+#[tonic::async_trait]
+impl Scheduler for SchedulerService {
+    async fn register_node(&self, request: Request<RegisterNodeRequest>) -> Result<Response<RegisterNodeResponse>, Status> {
+        let user = self.authorizer.authorize(&request, Verb::Register)?;
+        let mut node = request.into_inner().node.ok_or_else(|| Status::invalid_argument("node is required"))?;
+        if node.name.is_empty() || node.address.is_empty() {
+            return Err(Status::invalid_argument("node name and address are required"));
+        }
+        let mut nodes = self.nodes.lock().unwrap();
+        let known = nodes.contains_key(&node.name);
+        // only the user that registered the node may redirect what rks sends it
+        if let Some(state) = nodes.get(&node.name)
+            && state.owner.is_some()
+            && state.owner != user
+            && (state.node.address != node.address || state.node.token != node.token)
+        {
+            return Err(Status::permission_denied(format!(
+                "node {} was registered by {}, only it can change its address or token",
+                node.name,
+                state.owner.as_deref().unwrap_or_default()
+            )));
+        }
+        let state = nodes.entry(node.name.clone()).or_insert_with(|| {
+            info!("Node {} registered from {}", node.name, node.address);
+            NodeState::new(node.clone(), Instant::now())
+        });
+        // pods that were deleted on the node free their resources, pods that
+        // were just dispatched may not be reported yet
+        let timeout = self.node_timeout;
+        let gone: Vec<String> = state.pods
+            .iter()
+            .filter(|(_, pod)| !node.pods.contains(&pod.name) && pod.scheduled.elapsed() >= timeout)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &gone {
+            state.pods.remove(key);
+        }
+        let claimed = user.is_some() && state.owner.is_none();
+        if claimed {
+            state.owner = user.clone();
+        }
+        // registrations repeat every few seconds, only changes are stored
+        node.unschedulable = state.node.unschedulable && known;
+        let changed = !known || stored(&state.node) != stored(&node);
+        let ips = pod_ips(&node);
+        let ips_changed = !known || pod_ips(&state.node) != ips;
+        let record = stored(&node);
+        state.node = node;
+        state.last_seen = Instant::now();
+        // the pods scheduled elsewhere while the node was NotReady are deleted
+        // from it once it is back
+        let reported = &state.node.pods;
+        state.evicted.retain(|key| reported.iter().any(|name| name == split_key(key).1));
+        let evicted: Vec<String> = state.evicted.iter().cloned().collect();
+        let back = state.node.clone();
+        drop(nodes);
+        if !evicted.is_empty() {
+            tokio::spawn(async move {
+                for key in evicted {
+                    let (namespace, name) = split_key(&key);
+                    match recall(&back, namespace.to_string(), name.to_string()).await {
+                        Ok(()) => info!("Deleted Pod {} from node {}, it was evicted", name, back.name),
+                        Err(e) => warn!("Failed to delete evicted Pod {} from node {}: {}", name, back.name, e),
+                    }
+                }
+            }.instrument(Span::current()));
+        }
+        self.persist(|store| {
+            for key in &gone {
+                store.delete(&format!("{}{}/{}", PODS_PREFIX, record.name, key), None)?;
+            }
+            if changed {
+                store.put(&format!("{}{}", NODES_PREFIX, record.name), &record.encode_to_vec())?;
+            }
+            if ips_changed {
+                store.put(&format!("{}{}", POD_IPS_PREFIX, record.name), &serde_json::to_vec(&ips)?)?;
+            }
+            if let Some(user) = user.as_ref().filter(|_| claimed) {
+                store.put(&format!("{}{}", NODE_OWNERS_PREFIX, record.name), user.as_bytes())?;
+            }
+            Ok(())
+        });
+        Ok(Response::new(RegisterNodeResponse {}))
+    }
+
+    async fn renew_node_lease(&self, request: Request<RenewNodeLeaseRequest>) -> Result<Response<RenewNodeLeaseResponse>, Status> {
+        let user = self.authorizer.authorize(&request, Verb::Register)?;
+        let request = request.into_inner();
+        let duration = Duration::from_secs(request.lease_duration_seconds.max(1) as u64);
+        match self.nodes.lock().unwrap().get_mut(&request.node_name) {
+            Some(state) if state.owner.is_some() && state.owner != user => {
+                return Err(Status::permission_denied(format!("node {} was registered by another user", request.node_name)));
+            }
+            Some(state) => state.health.renew(duration, Instant::now()),
+            None => return Err(Status::not_found(format!("node {} is not registered", request.node_name))),
+        }
+        let key = format!("{}{}", heartbeat::LEASES_PREFIX, request.node_name);
+        let unix = SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_millis() as u64).unwrap_or_default();
+        self.persist(|store| {
+            let acquired = store
+                .get(&key)?
+                .and_then(|kv| serde_json::from_slice::<Lease>(&kv.value).ok())
+                .map_or(unix, |lease| lease.acquire_time);
+            let lease = Lease {
+                holder_identity: request.node_name.clone(),
+                lease_duration_seconds: duration.as_secs(),
+                acquire_time: acquired,
+                renew_time: unix,
+                lease_transitions: 0,
+            };
+            store.put(&key, &serde_json::to_vec(&lease)?).map(|_| ())
+        });
+        Ok(Response::new(RenewNodeLeaseResponse {}))
+    }
+
+    async fn cordon_node(&self, request: Request<CordonNodeRequest>) -> Result<Response<CordonNodeResponse>, Status> {
+        self.authorizer.authorize(&request, Verb::Write)?;
+        let CordonNodeRequest { name, unschedulable } = request.into_inner();
+        let record = {
+            let mut nodes = self.nodes.lock().unwrap();
+            let state = nodes.get_mut(&name).ok_or_else(|| Status::not_found(format!("node {} is not registered", name)))?;
+            if state.node.unschedulable == unschedulable {
+                return Ok(Response::new(CordonNodeResponse {}));
+            }
+            state.node.unschedulable = unschedulable;
+            stored(&state.node)
+        };
+        self.persist(|store| store.put(&format!("{}{}", NODES_PREFIX, name), &record.encode_to_vec()).map(|_| ()));
+        info!("Node {} {}", name, if unschedulable { "cordoned" } else { "uncordoned" });
+        Ok(Response::new(CordonNodeResponse {}))
+    }
+
+    // the pod runs on the other node before it is deleted from its own
+    async fn evict_pod(&self, request: Request<EvictPodRequest>) -> Result<Response<EvictPodResponse>, Status> {
+        self.authorizer.authorize(&request, Verb::Write)?;
+        let EvictPodRequest { name, namespace } = request.into_inner();
+        let namespace = if namespace.is_empty() { namespace::default_namespace() } else { namespace };
+        let source = self.scheduled_node(&name, &namespace)?;
+        let key = namespaced_key(&namespace, &name);
+        let manifest = {
+            let nodes = self.nodes.lock().unwrap();
+            let state = nodes.get(&source.name).ok_or_else(|| Status::not_found(format!("node {} is not registered", source.name)))?;
+            // the pod would be scheduled right back
+            if !state.node.unschedulable {
+                return Err(Status::failed_precondition(format!("node {} isn't cordoned", source.name)));
+            }
+            let pod = state.pods.get(&key).ok_or_else(|| Status::not_found(format!("pod {} is not scheduled", name)))?;
+            if let Some(owner) = pod.labels.get(daemonset::DAEMONSET_NAME) {
+                return Err(Status::failed_precondition(format!("pod {} belongs to daemon set {}", name, owner)));
+            }
+            // a pod that isn't healthy takes nothing from the budgets
+            if self.healthy(state, &name) {
+                for budget in self.budgets.lock().unwrap().values() {
+                    if !budget.selects(&pod.namespace, &pod.labels) {
+                        continue;
+                    }
+                    let disruption = self.disruption(&nodes, budget);
+                    if disruption.allowed() < 1 {
+                        return Err(Status::resource_exhausted(format!(
+                            "evicting pod {} would violate the pod disruption budget {}, it needs {} healthy pod(s) and has {}",
+                            name, budget.metadata.name, disruption.desired_healthy, disruption.healthy
+                        )));
+                    }
+                }
+            }
+            pod.manifest.clone()
+        };
+        let target = self.evict(&source.name, &key, manifest).await.map_err(|e| Status::unavailable(e.to_string()))?;
+        info!("Evicted Pod {} from node {} onto node {}", name, source.name, target);
+        // a node that fails to delete it now does so at its next registration
+        recall(&source, namespace, name.clone()).await.map_err(|e| {
+            Status::unavailable(format!("Pod {} was scheduled onto node {} but still runs on node {}: {}", name, target, source.name, e))
+        })?;
+        if let Some(state) = self.nodes.lock().unwrap().get_mut(&source.name) {
+            state.evicted.remove(&key);
+        }
+        Ok(Response::new(EvictPodResponse { source: source.name, node: target }))
+    }
+
+    async fn list_nodes(&self, request: Request<ListNodesRequest>) -> Result<Response<ListNodesResponse>, Status> {
+        self.authorizer.authorize(&request, Verb::Read)?;
+        let nodes = self.nodes.lock().unwrap();
+        let mut statuses: Vec<NodeStatus> = nodes
+            .values()
+            .map(|state| {
+                let allocated = state.allocated();
+                // by name, which is unique on a node
+                let mut pods: Vec<String> = state.pods.values().map(|pod| pod.name.clone()).collect();
+                pods.sort();
+                let namespaces = state.pods.values().map(|pod| (pod.name.clone(), pod.namespace.clone())).collect();
+                let labels = state
+                    .pods
+                    .values()
+                    .map(|pod| (pod.name.clone(), PodLabels { labels: pod.labels.clone() }))
+                    .collect();
+                // the token of a node is a credential, never hand it out
+                let node = Node { token: String::new(), ..state.node.clone() };
+                NodeStatus {
+                    node: Some(node),
+                    allocated: Some(Resources {
+                        cpu_millis: allocated.cpu_millis,
+                        memory_bytes: allocated.memory_bytes,
+                        pods: state.pods.len() as i64,
+                    }),
+                    pods,
+                    ready: self.ready(state),
+                    namespaces,
+                    labels,
+                }
+            })
+            .collect();
+        statuses.sort_by(|a, b| a.node.as_ref().map(|n| &n.name).cmp(&b.node.as_ref().map(|n| &n.name)));
+        Ok(Response::new(ListNodesResponse { nodes: statuses }))
+    }
+
+    async fn schedule_pod(&self, request: Request<SchedulePodRequest>) -> Result<Response<SchedulePodResponse>, Status> {
+        self.authorizer.authorize(&request, Verb::Write)?;
+        let SchedulePodRequest { manifest, update, dry_run } = request.into_inner();
+        let pod = PodManifest::parse(&manifest).map_err(|e| Status::invalid_argument(e.to_string()))?;
+        if update {
+            // the node admits the changed requests like those of any pod
+            let requests = pod.requests().map_err(|e| Status::invalid_argument(e.to_string()))?;
+            let node = self.scheduled_node(&pod.metadata.name, &pod.metadata.namespace)?;
+            if dry_run {
+                return Ok(Response::new(SchedulePodResponse { node: node.name }));
+            }
+            dispatch(&node, manifest.clone(), true).await.map_err(|e| Status::unavailable(e.to_string()))?;
+            self.replace(&node.name, &pod, &manifest, requests);
+            info!("Updated Pod {} on node {}", pod.metadata.name, node.name);
+            return Ok(Response::new(SchedulePodResponse { node: node.name }));
+        }
+        let (pod, manifest) = self.limit(pod, manifest).map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let node = self.place(&pod, &manifest, dry_run)?;
+        if dry_run {
+            return Ok(Response::new(SchedulePodResponse { node: node.name }));
+        }
+        if let Err(e) = dispatch(&node, manifest, false).await {
+            self.unreserve(&node.name, &namespaced_key(&pod.metadata.namespace, &pod.metadata.name));
+            return Err(Status::unavailable(e.to_string()));
+        }
+        info!("Scheduled Pod {} onto node {}", pod.metadata.name, node.name);
+        Ok(Response::new(SchedulePodResponse { node: node.name }))
+    }
+
+    async fn delete_pod(&self, request: Request<DeletePodRequest>) -> Result<Response<DeletePodResponse>, Status> {
+        self.authorizer.authorize(&request, Verb::Write)?;
+        let DeletePodRequest { name, namespace, dry_run } = request.into_inner();
+        let namespace = if namespace.is_empty() { namespace::default_namespace() } else { namespace };
+        let node = self.scheduled_node(&name, &namespace)?;
+        if dry_run {
+            return Ok(Response::new(DeletePodResponse { node: node.name }));
+        }
+        let key = namespaced_key(&namespace, &name);
+        recall(&node, namespace, name.clone()).await.map_err(|e| Status::unavailable(e.to_string()))?;
+        self.unreserve(&node.name, &key);
+        info!("Deleted Pod {} from node {}", name, node.name);
+        Ok(Response::new(DeletePodResponse { node: node.name }))
+    }
+
+    async fn deregister_node(&self, request: Request<DeregisterNodeRequest>) -> Result<Response<DeregisterNodeResponse>, Status> {
+        self.authorizer.authorize(&request, Verb::Write)?;
+        let name = request.into_inner().name;
+        let node = self.nodes
+            .lock()
+            .unwrap()
+            .get(&name)
+            .map(|state| state.node.clone())
+            .ok_or_else(|| Status::not_found(format!("node {} is not registered", name)))?;
+        let pods: Vec<(String, String)> = self.daemonsets
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|state| state.pods.contains_key(&name))
+            .map(|state| (state.daemonset.metadata.namespace.clone(), state.daemonset.pod_name(&name)))
+            .collect();
+        for (namespace, pod) in pods {
+            if let Err(e) = recall(&node, namespace, pod.clone()).await {
+                warn!("Failed to delete Pod {} from leaving node {}: {}", pod, name, e);
+            }
+        }
+        for state in self.daemonsets.lock().unwrap().values_mut() {
+            state.pods.remove(&name);
+        }
+        let pods: Vec<String> = self.nodes.lock().unwrap().remove(&name).map(|state| state.pods.into_keys().collect()).unwrap_or_default();
+        self.persist(|store| {
+            for pod in &pods {
+                store.delete(&format!("{}{}/{}", PODS_PREFIX, name, pod), None)?;
+            }
+            store.delete(&format!("{}{}", NODES_PREFIX, name), None)?;
+            store.delete(&format!("{}{}", NODE_OWNERS_PREFIX, name), None)?;
+            store.delete(&format!("{}{}", POD_IPS_PREFIX, name), None)?;
+            Ok(())
+        });
+        info!("Node {} deregistered", name);
+        Ok(Response::new(DeregisterNodeResponse {}))
+    }
+
+    async fn apply_daemon_set(&self, request: Request<ApplyDaemonSetRequest>) -> Result<Response<ApplyDaemonSetResponse>, Status> {
+        self.authorizer.authorize(&request, Verb::Write)?;
+        let manifest = request.into_inner().manifest;
+        let daemonset = DaemonSet::parse(&manifest).map_err(|e| Status::invalid_argument(e.to_string()))?;
+        self.check_namespace(&daemonset.metadata.namespace)?;
+        let name = daemonset.metadata.name.clone();
+        {
+            let mut daemonsets = self.daemonsets.lock().unwrap();
+            let pods = daemonsets.remove(&name).map(|state| state.pods).unwrap_or_default();
+            daemonsets.insert(name.clone(), DaemonSetState { daemonset, pods });
+        }
+        self.persist(|store| store.put(&format!("{}{}", DAEMONSETS_PREFIX, name), manifest.as_bytes()).map(|_| ()));
+        info!("Applied daemon set {}", name);
+        self.sync_daemonsets().await;
+        Ok(Response::new(ApplyDaemonSetResponse {}))
+    }
+
+    async fn delete_daemon_set(&self, request: Request<DeleteDaemonSetRequest>) -> Result<Response<DeleteDaemonSetResponse>, Status> {
+        self.authorizer.authorize(&request, Verb::Write)?;
+        let name = request.into_inner().name;
+        let state = self.daemonsets
+            .lock()
+            .unwrap()
+            .remove(&name)
+            .ok_or_else(|| Status::not_found(format!("daemon set {} not found", name)))?;
+        self.persist(|store| store.delete(&format!("{}{}", DAEMONSETS_PREFIX, name), None).map(|_| ()));
+        let namespace = &state.daemonset.metadata.namespace;
+        for node_name in state.pods.keys() {
+            let pod = state.daemonset.pod_name(node_name);
+            let node = self.nodes.lock().unwrap().get(node_name).map(|state| state.node.clone());
+            if let Some(node) = node
+                && let Err(e) = recall(&node, namespace.clone(), pod.clone()).await
+            {
+                warn!("Failed to delete Pod {} of daemon set {}: {}", pod, name, e);
+            }
+            self.unreserve(node_name, &namespaced_key(namespace, &pod));
+        }
+        info!("Deleted daemon set {}", name);
+        Ok(Response::new(DeleteDaemonSetResponse {}))
+    }
+
+    async fn list_daemon_sets(&self, request: Request<ListDaemonSetsRequest>) -> Result<Response<ListDaemonSetsResponse>, Status> {
+        self.authorizer.authorize(&request, Verb::Read)?;
+        let nodes = self.nodes.lock().unwrap();
+        let daemonsets = self.daemonsets.lock().unwrap();
+        let views: Vec<NodeView> = nodes
+            .values()
+            .map(|state| NodeView { node: &state.node, ready: self.ready(state) })
+            .collect();
+        let mut statuses: Vec<DaemonSetStatus> = daemonsets
+            .iter()
+            .map(|(name, state)| {
+                let hash = state.daemonset.template_hash();
+                let desired = views
+                    .iter()
+                    .filter(|view| view.ready)
+                    .filter(|view| {
+                        state.daemonset
+                            .pod_manifest(&view.node.name)
+                            .and_then(|manifest| PodManifest::parse(&manifest))
+                            .is_ok_and(|pod| schedule::mismatch(&pod, view.node).is_none())
+                    })
+                    .count();
+                let mut nodes: Vec<String> = state.pods.keys().cloned().collect();
+                nodes.sort();
+                DaemonSetStatus {
+                    name: name.clone(),
+                    desired: desired as i64,
+                    current: state.pods.len() as i64,
+                    up_to_date: state.pods.values().filter(|pod_hash| **pod_hash == hash).count() as i64,
+                    nodes,
+                }
+            })
+            .collect();
+        statuses.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(Response::new(ListDaemonSetsResponse { daemon_sets: statuses }))
+    }
+
+    async fn apply_pod_disruption_budget(
+        &self,
+        request: Request<ApplyPodDisruptionBudgetRequest>,
+    ) -> Result<Response<ApplyPodDisruptionBudgetResponse>, Status> {
+        self.authorizer.authorize(&request, Verb::Write)?;
+        let manifest = request.into_inner().manifest;
+        let budget = PodDisruptionBudget::parse(&manifest).map_err(|e| Status::invalid_argument(e.to_string()))?;
+        self.check_namespace(&budget.metadata.namespace)?;
+        let key = namespaced_key(&budget.metadata.namespace, &budget.metadata.name);
+        self.budgets.lock().unwrap().insert(key.clone(), budget);
+        self.persist(|store| store.put(&format!("{}{}", BUDGETS_PREFIX, key), manifest.as_bytes()).map(|_| ()));
+        info!("Applied pod disruption budget {}", key);
+        Ok(Response::new(ApplyPodDisruptionBudgetResponse {}))
+    }
+
+    async fn delete_pod_disruption_budget(
+        &self,
+        request: Request<DeletePodDisruptionBudgetRequest>,
+    ) -> Result<Response<DeletePodDisruptionBudgetResponse>, Status> {
+        self.authorizer.authorize(&request, Verb::Write)?;
+        let DeletePodDisruptionBudgetRequest { name, namespace } = request.into_inner();
+        let namespace = if namespace.is_empty() { namespace::default_namespace() } else { namespace };
+        let key = namespaced_key(&namespace, &name);
+        if self.budgets.lock().unwrap().remove(&key).is_none() {
+            return Err(Status::not_found(format!("pod disruption budget {} not found in namespace {}", name, namespace)));
+        }
+        self.persist(|store| store.delete(&format!("{}{}", BUDGETS_PREFIX, key), None).map(|_| ()));
+        info!("Deleted pod disruption budget {}", key);
+        Ok(Response::new(DeletePodDisruptionBudgetResponse {}))
+    }
+
+    async fn list_pod_disruption_budgets(
+        &self,
+        request: Request<ListPodDisruptionBudgetsRequest>,
+    ) -> Result<Response<ListPodDisruptionBudgetsResponse>, Status> {
+        self.authorizer.authorize(&request, Verb::Read)?;
+        let nodes = self.nodes.lock().unwrap();
+        let mut statuses: Vec<PodDisruptionBudgetStatus> = self.budgets
+            .lock()
+            .unwrap()
+            .values()
+            .map(|budget| {
+                let disruption = self.disruption(&nodes, budget);
+                let written = |value: &Option<pdb::IntOrPercent>| value.as_ref().map(ToString::to_string).unwrap_or_default();
+                PodDisruptionBudgetStatus {
+                    name: budget.metadata.name.clone(),
+                    namespace: budget.metadata.namespace.clone(),
+                    min_available: written(&budget.spec.min_available),
+                    max_unavailable: written(&budget.spec.max_unavailable),
+                    expected: disruption.expected,
+                    healthy: disruption.healthy,
+                    desired_healthy: disruption.desired_healthy,
+                    disruptions_allowed: disruption.allowed(),
+                }
+            })
+            .collect();
+        statuses.sort_by(|a, b| (&a.namespace, &a.name).cmp(&(&b.namespace, &b.name)));
+        Ok(Response::new(ListPodDisruptionBudgetsResponse { budgets: statuses }))
+    }
+
+    async fn apply_resource_quota(&self, request: Request<ApplyResourceQuotaRequest>) -> Result<Response<ApplyResourceQuotaResponse>, Status> {
+        self.authorizer.authorize(&request, Verb::Write)?;
+        let manifest = request.into_inner().manifest;
+        let quota = ResourceQuota::parse(&manifest).map_err(|e| Status::invalid_argument(e.to_string()))?;
+        self.check_namespace(&quota.metadata.namespace)?;
+        let key = namespaced_key(&quota.metadata.namespace, &quota.metadata.name);
+        self.quotas.lock().unwrap().insert(key.clone(), quota);
+        self.persist(|store| store.put(&format!("{}{}", QUOTAS_PREFIX, key), manifest.as_bytes()).map(|_| ()));
+        info!("Applied resource quota {}", key);
+        Ok(Response::new(ApplyResourceQuotaResponse {}))
+    }
+
+    async fn delete_resource_quota(&self, request: Request<DeleteResourceQuotaRequest>) -> Result<Response<DeleteResourceQuotaResponse>, Status> {
+        self.authorizer.authorize(&request, Verb::Write)?;
+        let DeleteResourceQuotaRequest { name, namespace } = request.into_inner();
+        let namespace = if namespace.is_empty() { namespace::default_namespace() } else { namespace };
+        let key = namespaced_key(&namespace, &name);
+        if self.quotas.lock().unwrap().remove(&key).is_none() {
+            return Err(Status::not_found(format!("resource quota {} not found in namespace {}", name, namespace)));
+        }
+        self.persist(|store| store.delete(&format!("{}{}", QUOTAS_PREFIX, key), None).map(|_| ()));
+        info!("Deleted resource quota {}", key);
+        Ok(Response::new(DeleteResourceQuotaResponse {}))
+    }
+
+    async fn list_resource_quotas(&self, request: Request<ListResourceQuotasRequest>) -> Result<Response<ListResourceQuotasResponse>, Status> {
+        self.authorizer.authorize(&request, Verb::Read)?;
+        let nodes = self.nodes.lock().unwrap();
+        let mut statuses: Vec<ResourceQuotaStatus> = self.quotas
+            .lock()
+            .unwrap()
+            .values()
+            .map(|quota| {
+                let (request, limit) = quota.status(usage(&nodes, &quota.metadata.namespace));
+                ResourceQuotaStatus { name: quota.metadata.name.clone(), namespace: quota.metadata.namespace.clone(), request, limit }
+            })
+            .collect();
+        statuses.sort_by(|a, b| (&a.namespace, &a.name).cmp(&(&b.namespace, &b.name)));
+        Ok(Response::new(ListResourceQuotasResponse { quotas: statuses }))
+    }
+
+    async fn apply_limit_range(&self, request: Request<ApplyLimitRangeRequest>) -> Result<Response<ApplyLimitRangeResponse>, Status> {
+        self.authorizer.authorize(&request, Verb::Write)?;
+        let manifest = request.into_inner().manifest;
+        let range = LimitRange::parse(&manifest).map_err(|e| Status::invalid_argument(e.to_string()))?;
+        self.check_namespace(&range.metadata.namespace)?;
+        let key = namespaced_key(&range.metadata.namespace, &range.metadata.name);
+        self.limit_ranges.lock().unwrap().insert(key.clone(), range);
+        self.persist(|store| store.put(&format!("{}{}", LIMIT_RANGES_PREFIX, key), manifest.as_bytes()).map(|_| ()));
+        info!("Applied limit range {}", key);
+        Ok(Response::new(ApplyLimitRangeResponse {}))
+    }
+
+    async fn delete_limit_range(&self, request: Request<DeleteLimitRangeRequest>) -> Result<Response<DeleteLimitRangeResponse>, Status> {
+        self.authorizer.authorize(&request, Verb::Write)?;
+        let DeleteLimitRangeRequest { name, namespace } = request.into_inner();
+        let namespace = if namespace.is_empty() { namespace::default_namespace() } else { namespace };
+        let key = namespaced_key(&namespace, &name);
+        if self.limit_ranges.lock().unwrap().remove(&key).is_none() {
+            return Err(Status::not_found(format!("limit range {} not found in namespace {}", name, namespace)));
+        }
+        self.persist(|store| store.delete(&format!("{}{}", LIMIT_RANGES_PREFIX, key), None).map(|_| ()));
+        info!("Deleted limit range {}", key);
+        Ok(Response::new(DeleteLimitRangeResponse {}))
+    }
+
+    async fn list_limit_ranges(&self, request: Request<ListLimitRangesRequest>) -> Result<Response<ListLimitRangesResponse>, Status> {
+        self.authorizer.authorize(&request, Verb::Read)?;
+        let mut statuses: Vec<LimitRangeStatus> = self.limit_ranges
+            .lock()
+            .unwrap()
+            .values()
+            .flat_map(|range| {
+                range.spec.limits.iter().map(|item| LimitRangeStatus {
+                    name: range.metadata.name.clone(),
+                    namespace: range.metadata.namespace.clone(),
+                    default: limitrange::written(&item.default),
+                    default_request: limitrange::written(&item.default_request),
+                    min: limitrange::written(&item.min),
+                    max: limitrange::written(&item.max),
+                })
+            })
+            .collect();
+        // stable, so the items of a limit range keep their order
+        statuses.sort_by(|a, b| (&a.namespace, &a.name).cmp(&(&b.namespace, &b.name)));
+        Ok(Response::new(ListLimitRangesResponse { limit_ranges: statuses }))
+    }
+
+    async fn create_namespace(&self, request: Request<CreateNamespaceRequest>) -> Result<Response<CreateNamespaceResponse>, Status> {
+        self.authorizer.authorize(&request, Verb::Write)?;
+        let name = request.into_inner().name;
+        namespace::validate(&name).map_err(|e| Status::invalid_argument(e.to_string()))?;
+        if !self.namespaces.lock().unwrap().insert(name.clone()) {
+            return Err(Status::already_exists(format!("namespace {} already exists", name)));
+        }
+        self.persist(|store| store.put(&format!("{}{}", NAMESPACES_PREFIX, name), &[]).map(|_| ()));
+        info!("Created namespace {}", name);
+        Ok(Response::new(CreateNamespaceResponse {}))
+    }
+
+    // only an empty namespace is deleted, its pods and daemon sets have to go first
+    async fn delete_namespace(&self, request: Request<DeleteNamespaceRequest>) -> Result<Response<DeleteNamespaceResponse>, Status> {
+        self.authorizer.authorize(&request, Verb::Write)?;
+        let name = request.into_inner().name;
+        if namespace::BUILT_IN.contains(&name.as_str()) {
+            return Err(Status::invalid_argument(format!("namespace {} can't be deleted", name)));
+        }
+        self.check_namespace(&name)?;
+        let pods = self.nodes.lock().unwrap().values().flat_map(|state| state.pods.values()).filter(|pod| pod.namespace == name).count();
+        let daemonsets = self.daemonsets
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|state| state.daemonset.metadata.namespace == name)
+            .count();
+        let budgets = self.budgets.lock().unwrap().values().filter(|budget| budget.metadata.namespace == name).count();
+        let quotas = self.quotas.lock().unwrap().values().filter(|quota| quota.metadata.namespace == name).count();
+        let limit_ranges = self.limit_ranges.lock().unwrap().values().filter(|range| range.metadata.namespace == name).count();
+        if pods > 0 || daemonsets > 0 || budgets > 0 || quotas > 0 || limit_ranges > 0 {
+            return Err(Status::failed_precondition(format!(
+                "namespace {} still has {} pod(s), {} daemon set(s), {} pod disruption budget(s), {} resource quota(s) and {} limit range(s)",
+                name, pods, daemonsets, budgets, quotas, limit_ranges
+            )));
+        }
+        self.namespaces.lock().unwrap().remove(&name);
+        self.persist(|store| store.delete(&format!("{}{}", NAMESPACES_PREFIX, name), None).map(|_| ()));
+        info!("Deleted namespace {}", name);
+        Ok(Response::new(DeleteNamespaceResponse {}))
+    }
+
+    async fn list_namespaces(&self, request: Request<ListNamespacesRequest>) -> Result<Response<ListNamespacesResponse>, Status> {
+        self.authorizer.authorize(&request, Verb::Read)?;
+        let names = self.namespaces.lock().unwrap().iter().cloned().collect();
+        Ok(Response::new(ListNamespacesResponse { names }))
+    }
+
+    async fn get_pod(&self, request: Request<GetPodRequest>) -> Result<Response<GetPodResponse>, Status> {
+        self.authorizer.authorize(&request, Verb::Read)?;
+        let GetPodRequest { name, namespace } = request.into_inner();
+        let namespace = if namespace.is_empty() { namespace::default_namespace() } else { namespace };
+        let node = self.scheduled_node(&name, &namespace)?;
+        let nodes = self.nodes.lock().unwrap();
+        let state = nodes.get(&node.name);
+        let manifest = state
+            .and_then(|state| state.pods.get(&namespaced_key(&namespace, &name)))
+            .map(|pod| pod.manifest.clone())
+            .ok_or_else(|| Status::not_found(format!("pod {} is not scheduled in namespace {}", name, namespace)))?;
+        let pod_ip = state.and_then(|state| state.node.pod_states.get(&name)).map(|pod| pod.pod_ip.clone()).unwrap_or_default();
+        Ok(Response::new(GetPodResponse { manifest, node: node.name, pod_ip }))
+    }
+
+    // the pod keeps running on its node until it runs on the new one, a
+    // migration that fails leaves it where it was
+    async fn migrate_pod(&self, request: Request<MigratePodRequest>) -> Result<Response<MigratePodResponse>, Status> {
+        self.authorizer.authorize(&request, Verb::Write)?;
+        let MigratePodRequest { name, namespace, node, timeout } = request.into_inner();
+        let namespace = if namespace.is_empty() { namespace::default_namespace() } else { namespace };
+        let source = self.scheduled_node(&name, &namespace)?;
+        let key = namespaced_key(&namespace, &name);
+        let target = self.migration_target(&key, &source.name, &node)?;
+        let archive = checkpoint(&source, name.clone(), timeout).await.map_err(|e| Status::unavailable(e.to_string()))?;
+        restore(&target, archive, source.name.clone()).await.map_err(|e| Status::unavailable(e.to_string()))?;
+        self.relocate(&key, &source.name, &target.name);
+        info!("Migrated Pod {} from node {} to node {}", name, source.name, target.name);
+        recall(&source, namespace, name.clone()).await.map_err(|e| {
+            Status::unavailable(format!("Pod {} was migrated to node {} but still runs on node {}: {}", name, target.name, source.name, e))
+        })?;
+        Ok(Response::new(MigratePodResponse { source: source.name }))
+    }
+}
+
+pub async fn serve(
+    listen: SocketAddr,
+    tls: Option<(PathBuf, PathBuf)>,
+    node_timeout: Duration,
+    pod_eviction_timeout: Duration,
+    store: Option<Box<dyn Store>>,
+    authorizer: Authorizer,
+    node_ca_file: Option<&Path>,
+) -> Result<()> {
+    // rks forwards requests to the nodes with their admin tokens
+    if !authorizer.authenticates() && !listen.ip().is_loopback() {
+        return Err(anyhow!("--token-file is required to listen on {}", listen));
+    }
+    // the tokens travel with every request
+    if tls.is_none() && !listen.ip().is_loopback() {
+        return Err(anyhow!("--tls-cert and --tls-key are required to listen on {}", listen));
+    }
+    let mut builder = Server::builder();
+    if let Some((cert, key)) = &tls {
+        let read = |path: &PathBuf| fs::read(path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e));
+        let identity = Identity::from_pem(read(cert)?, read(key)?);
+        builder = builder
+            .tls_config(ServerTlsConfig::new().identity(identity))
+            .map_err(|e| anyhow!("Invalid TLS certificate {}: {}", cert.display(), e))?;
+    }
+    if let Some(path) = node_ca_file {
+        let ca = fs::read(path).map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+        let _ = NODE_TLS.set(ClientTlsConfig::new().ca_certificate(Certificate::from_pem(ca)));
+    }
+    let service = Arc::new(SchedulerService::new(node_timeout, pod_eviction_timeout, store, authorizer)?);
+    let monitor = service.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(heartbeat::MONITOR_PERIOD).await;
+            let span = logging::root_span(info_span!("monitor_nodes", trace_id = tracing::field::Empty));
+            monitor.monitor_nodes().instrument(span).await;
+        }
+    });
+    // new and returning nodes get the pods of the daemon sets within an interval
+    let syncer = service.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(DAEMONSET_SYNC_INTERVAL).await;
+            let span = logging::root_span(info_span!("sync_daemonsets", trace_id = tracing::field::Empty));
+            syncer.sync_daemonsets().instrument(span).await;
+        }
+    });
+    info!("rks listening on {}://{}", if tls.is_some() { "https" } else { "http" }, listen);
+    builder
+        .trace_fn(logging::request_span)
+        .add_service(SchedulerServer::from_arc(service))
+        .serve(listen)
+        .await
+        .map_err(|e| anyhow!("Failed to serve on {}: {}", listen, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pod(namespace: &str) -> String {
+        format!("apiVersion: v1\nkind: Pod\nmetadata:\n  name: web\n  namespace: {}\nspec:\n  containers:\n    - name: app\n      image: app:v1\n", namespace)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_pods_of_a_name_in_two_namespaces() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("store");
+        let service = |store| SchedulerService::new(Duration::from_secs(40), Duration::from_secs(300), Some(store), Authorizer::new(None).unwrap()).unwrap();
+        let scheduler = service(store::open(path.to_str().unwrap()).unwrap());
+        scheduler.namespaces.lock().unwrap().insert("team-a".to_string());
+        let node = Node {
+            name: "node-1".to_string(),
+            capacity: Some(Resources { cpu_millis: 4000, memory_bytes: 8 << 30, pods: 110 }),
+            ..Default::default()
+        };
+        scheduler.nodes.lock().unwrap().insert(node.name.clone(), NodeState::new(node, Instant::now()));
+
+        for namespace in [namespace::DEFAULT, "team-a"] {
+            let manifest = pod(namespace);
+            scheduler.place(&PodManifest::parse(&manifest).unwrap(), &manifest, false).unwrap();
+        }
+        let manifest = pod("team-a");
+        let err = scheduler.place(&PodManifest::parse(&manifest).unwrap(), &manifest, false).unwrap_err();
+        assert_eq!(err.code(), tonic::Code::AlreadyExists);
+        assert_eq!(scheduler.scheduled_node("web", "team-a").unwrap().name, "node-1");
+
+        // deleting one leaves the other
+        scheduler.unreserve("node-1", &namespaced_key("team-a", "web"));
+        assert!(scheduler.scheduled_node("web", "team-a").is_err());
+        assert_eq!(scheduler.scheduled_node("web", namespace::DEFAULT).unwrap().name, "node-1");
+        drop(scheduler);
+        let keys: Vec<String> = store::open(path.to_str().unwrap()).unwrap().list(PODS_PREFIX).unwrap().items.into_iter().map(|kv| kv.key).collect();
+        assert_eq!(keys, [format!("{}node-1/default/web", PODS_PREFIX)]);
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use serde::Deserialize;
+use crate::affinity::{LabelSelector, Placed};
+use crate::pb::scheduler::Node;
+
+// Topology spread constraints, like in Kubernetes: the pods a constraint
+// selects, of the namespace of the pod, are counted per domain of its
+// topologyKey, the nodes with the same value of that label. The domains are
+// those of the ready, uncordoned nodes the pod could go onto by its node
+// selector, node affinity and taints. The skew of a node is the count of its
+// domain with the pod placed there, less the smallest count of any domain:
+//   DoNotSchedule   nodes whose skew exceeds maxSkew or that don't have the
+//                   label are filtered out
+//   ScheduleAnyway  every pod the domain of a node has more than the emptiest
+//                   one costs SKEW_PENALTY points of its score
+// Replicas of a deployment share their labels, so a constraint selecting them
+// spreads them over zones with topology.kubernetes.io/zone or over the nodes
+// with kubernetes.io/hostname. rks migrate to a given node only weighs the
+// domain of that node, it doesn't hold the migration back.
+
+const SKEW_PENALTY: i64 = 10;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TopologySpreadConstraint {
+    pub max_skew: i64,
+    pub topology_key: String,
+    // DoNotSchedule when unset
+    #[serde(default)]
+    pub when_unsatisfiable: Option<String>,
+    // no pods when unset
+    #[serde(default)]
+    pub label_selector: Option<LabelSelector>,
+}
+
+impl TopologySpreadConstraint {
+    fn hard(&self) -> bool {
+        self.when_unsatisfiable.as_deref() != Some("ScheduleAnyway")
+    }
+
+    fn selects(&self, labels: &HashMap<String, String>) -> bool {
+        self.label_selector.as_ref().is_some_and(|selector| selector.matches(labels))
+    }
+}
+
+// the pods a constraint counts in every domain
+pub struct Spread<'a> {
+    constraint: &'a TopologySpreadConstraint,
+    counts: HashMap<&'a str, i64>,
+    // whether the pod counts itself where it goes
+    self_match: i64,
+}
+
+impl<'a> Spread<'a> {
+    // the domains of the eligible nodes for a pod of the namespace and labels
+    pub fn new(
+        constraint: &'a TopologySpreadConstraint,
+        namespace: &str,
+        labels: &HashMap<String, String>,
+        eligible: &[&'a Node],
+        placed: &[Placed<'a>],
+    ) -> Self {
+        let mut counts: HashMap<&str, i64> = eligible
+            .iter()
+            .filter_map(|node| node.labels.get(&constraint.topology_key))
+            .map(|domain| (domain.as_str(), 0))
+            .collect();
+        for pod in placed.iter().filter(|pod| pod.namespace == namespace && constraint.selects(pod.labels)) {
+            if let Some(count) = pod.node.labels.get(&constraint.topology_key).and_then(|domain| counts.get_mut(domain.as_str())) {
+                *count += 1;
+            }
+        }
+        Spread { constraint, counts, self_match: i64::from(constraint.selects(labels)) }
+    }
+
+    // how much fuller the domain of the node would be than the emptiest one,
+    // None when the node has no domain
+    fn skew(&self, node: &Node) -> Option<i64> {
+        let count = node.labels.get(&self.constraint.topology_key).and_then(|domain| self.counts.get(domain.as_str()))?;
+        let min = self.counts.values().min().copied().unwrap_or_default();
+        Some(count + self.self_match - min)
+    }
+
+    pub fn violated(&self, node: &Node) -> bool {
+        self.constraint.hard() && self.skew(node).is_none_or(|skew| skew > self.constraint.max_skew)
+    }
+
+    pub fn penalty(&self, node: &Node) -> i64 {
+        if self.constraint.hard() {
+            return 0;
+        }
+        // a node without the label is as bad as the fullest domain
+        let max = self.counts.values().max().copied().unwrap_or_default() + self.self_match;
+        (self.skew(node).unwrap_or(max) - self.self_match).max(0) * SKEW_PENALTY
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(name: &str, zone: &str) -> Node {
+        let mut node = Node { name: name.to_string(), ..Default::default() };
+        node.labels.insert("topology.kubernetes.io/zone".to_string(), zone.to_string());
+        node
+    }
+
+    #[test]
+    fn test_spread() {
+        let constraint: TopologySpreadConstraint = serde_yaml::from_str(
+            "maxSkew: 1\ntopologyKey: topology.kubernetes.io/zone\nlabelSelector: {matchLabels: {app: web}}\n",
+        )
+        .unwrap();
+        let (a1, a2, b1) = (node("a1", "a"), node("a2", "a"), node("b1", "b"));
+        let bare = Node { name: "bare".to_string(), ..Default::default() };
+        let web = HashMap::from([("app".to_string(), "web".to_string())]);
+        let placed = [
+            Placed { node: &a1, namespace: "default", labels: &web, anti_affinity: &[] },
+            Placed { node: &a2, namespace: "other", labels: &web, anti_affinity: &[] },
+        ];
+        let spread = Spread::new(&constraint, "default", &web, &[&a1, &a2, &b1, &bare], &placed);
+        // zone a has one pod of the namespace, b none
+        assert!(spread.violated(&a2));
+        assert!(!spread.violated(&b1));
+        assert!(spread.violated(&bare));
+
+        let soft = TopologySpreadConstraint { when_unsatisfiable: Some("ScheduleAnyway".to_string()), ..constraint };
+        let spread = Spread::new(&soft, "default", &web, &[&a1, &a2, &b1], &placed);
+        assert!(!spread.violated(&a2));
+        assert_eq!(spread.penalty(&a2), SKEW_PENALTY);
+        assert_eq!(spread.penalty(&b1), 0);
+    }
+}
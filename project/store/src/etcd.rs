@@ -0,0 +1,309 @@
+use std::io::{BufRead, BufReader, Read};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use anyhow::{Result, anyhow};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tracing::warn;
+use crate::{Event, EventType, KeyValue, List, Store, StoreError, Watch};
+
+// etcd v3 through the JSON gateway every etcd serves next to gRPC, which keeps
+// the store free of a gRPC client and protoc. Keys and values are base64 in
+// the JSON and int64s are strings. A request goes to the first endpoint that
+// answers. The resourceVersion of a key is its etcd mod_revision, conditional
+// writes are transactions comparing it.
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct EtcdStore {
+    endpoints: Vec<String>,
+    agent: ureq::Agent,
+}
+
+#[derive(Deserialize)]
+struct RawKeyValue {
+    key: String,
+    #[serde(default)]
+    value: String,
+    #[serde(default)]
+    create_revision: Option<String>,
+    #[serde(default)]
+    mod_revision: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+struct Header {
+    #[serde(default)]
+    revision: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RangeResponse {
+    #[serde(default)]
+    header: Header,
+    #[serde(default)]
+    kvs: Vec<RawKeyValue>,
+}
+
+#[derive(Deserialize)]
+struct TxnResponse {
+    #[serde(default)]
+    header: Header,
+    #[serde(default)]
+    succeeded: bool,
+    // the range of the failure branch, holding the current key if any
+    #[serde(default)]
+    responses: Vec<Value>,
+}
+
+#[derive(Deserialize)]
+struct WatchEvent {
+    // PUT is the default and left out
+    #[serde(rename = "type", default)]
+    kind: Option<String>,
+    kv: RawKeyValue,
+    #[serde(default)]
+    prev_kv: Option<RawKeyValue>,
+}
+
+#[derive(Deserialize)]
+struct WatchResult {
+    #[serde(default)]
+    events: Vec<WatchEvent>,
+    #[serde(default)]
+    compact_revision: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct WatchResponse {
+    result: Option<WatchResult>,
+    error: Option<Value>,
+}
+
+fn encode(bytes: &[u8]) -> String {
+    STANDARD.encode(bytes)
+}
+
+fn number(value: &Option<String>) -> u64 {
+    value.as_deref().and_then(|n| n.parse().ok()).unwrap_or(0)
+}
+
+fn decode(kv: &RawKeyValue) -> Result<KeyValue> {
+    let key = String::from_utf8(STANDARD.decode(&kv.key)?).map_err(|e| anyhow!("key is not UTF-8: {}", e))?;
+    Ok(KeyValue { key, value: STANDARD.decode(&kv.value)?, resource_version: number(&kv.mod_revision) })
+}
+
+// the end of the range of the keys with the prefix, as etcd expects it
+fn prefix_end(prefix: &str) -> Vec<u8> {
+    let mut end = prefix.as_bytes().to_vec();
+    while let Some(last) = end.pop() {
+        if last < 0xff {
+            end.push(last + 1);
+            return end;
+        }
+    }
+    // every key
+    vec![0]
+}
+
+impl EtcdStore {
+    pub fn new(endpoints: Vec<String>) -> Result<Self> {
+        if endpoints.is_empty() {
+            return Err(anyhow!("at least one etcd endpoint is required"));
+        }
+        let agent = ureq::AgentBuilder::new().timeout_connect(TIMEOUT).build();
+        Ok(EtcdStore { endpoints, agent })
+    }
+
+    // POST to the first endpoint that answers, returning the response body
+    fn post(&self, path: &str, body: &Value, timeout: Option<Duration>) -> Result<Box<dyn Read + Send + Sync>> {
+        let mut last_error = None;
+        for endpoint in &self.endpoints {
+            let mut request = self.agent.post(&format!("{}{}", endpoint, path));
+            if let Some(timeout) = timeout {
+                request = request.timeout(timeout);
+            }
+            match request.send_string(&body.to_string()) {
+                Ok(response) => return Ok(response.into_reader()),
+                Err(ureq::Error::Status(code, response)) => {
+                    let message = response.into_string().unwrap_or_default();
+                    return Err(anyhow!("etcd {} failed with {}: {}", path, code, message.trim()));
+                }
+                // try the next member
+                Err(e) => last_error = Some(anyhow!("Failed to reach etcd at {}: {}", endpoint, e)),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| anyhow!("no etcd endpoint")))
+    }
+
+    fn call<T: for<'de> Deserialize<'de>>(&self, path: &str, body: Value) -> Result<T> {
+        let mut response = String::new();
+        self.post(path, &body, Some(TIMEOUT))?.read_to_string(&mut response)?;
+        Ok(serde_json::from_str(&response)?)
+    }
+
+    // run the request if the key is at resource_version (0: doesn't exist),
+    // reading the key otherwise to tell why not
+    fn transaction(&self, key: &str, resource_version: u64, request: Value) -> Result<Result<u64, Option<KeyValue>>> {
+        let encoded = encode(key.as_bytes());
+        let compare = if resource_version == 0 {
+            json!({ "key": encoded, "target": "CREATE", "result": "EQUAL", "create_revision": "0" })
+        } else {
+            json!({ "key": encoded, "target": "MOD", "result": "EQUAL", "mod_revision": resource_version.to_string() })
+        };
+        let body = json!({
+            "compare": [compare],
+            "success": [request],
+            "failure": [{ "request_range": { "key": encoded } }],
+        });
+        let response: TxnResponse = self.call("/v3/kv/txn", body)?;
+        if response.succeeded {
+            return Ok(Ok(number(&response.header.revision)));
+        }
+        let current = response
+            .responses
+            .first()
+            .and_then(|r| r.get("response_range"))
+            .and_then(|range| serde_json::from_value::<RangeResponse>(range.clone()).ok())
+            .and_then(|range| range.kvs.first().map(decode))
+            .transpose()?;
+        Ok(Err(current))
+    }
+}
+
+impl Store for EtcdStore {
+    fn get(&self, key: &str) -> Result<Option<KeyValue>> {
+        let response: RangeResponse = self.call("/v3/kv/range", json!({ "key": encode(key.as_bytes()) }))?;
+        response.kvs.first().map(decode).transpose()
+    }
+
+    fn put(&self, key: &str, value: &[u8]) -> Result<u64> {
+        let body = json!({ "key": encode(key.as_bytes()), "value": encode(value) });
+        let response: Value = self.call("/v3/kv/put", body)?;
+        let header: Header = serde_json::from_value(response["header"].clone()).unwrap_or_default();
+        Ok(number(&header.revision))
+    }
+
+    fn create(&self, key: &str, value: &[u8]) -> Result<u64> {
+        let put = json!({ "request_put": { "key": encode(key.as_bytes()), "value": encode(value) } });
+        self.transaction(key, 0, put)?.map_err(|_| StoreError::AlreadyExists(key.to_string()).into())
+    }
+
+    fn update(&self, key: &str, value: &[u8], resource_version: u64) -> Result<u64> {
+        let put = json!({ "request_put": { "key": encode(key.as_bytes()), "value": encode(value) } });
+        self.transaction(key, resource_version, put)?.map_err(|current| match current {
+            Some(kv) => StoreError::Conflict { key: key.to_string(), expected: resource_version, actual: kv.resource_version }.into(),
+            None => StoreError::NotFound(key.to_string()).into(),
+        })
+    }
+
+    fn delete(&self, key: &str, resource_version: Option<u64>) -> Result<bool> {
+        let Some(expected) = resource_version else {
+            let response: Value = self.call("/v3/kv/deleterange", json!({ "key": encode(key.as_bytes()) }))?;
+            return Ok(response["deleted"].as_str().is_some_and(|deleted| deleted != "0"));
+        };
+        let delete = json!({ "request_delete_range": { "key": encode(key.as_bytes()) } });
+        match self.transaction(key, expected, delete)? {
+            Ok(_) => Ok(true),
+            Err(None) => Ok(false),
+            Err(Some(kv)) => Err(StoreError::Conflict { key: key.to_string(), expected, actual: kv.resource_version }.into()),
+        }
+    }
+
+    fn list(&self, prefix: &str) -> Result<List> {
+        let body = json!({
+            "key": encode(prefix.as_bytes()),
+            "range_end": encode(&prefix_end(prefix)),
+            "sort_order": "ASCEND",
+            "sort_target": "KEY",
+        });
+        let response: RangeResponse = self.call("/v3/kv/range", body)?;
+        Ok(List {
+            items: response.kvs.iter().map(decode).collect::<Result<_>>()?,
+            resource_version: number(&response.header.revision),
+        })
+    }
+
+    fn watch(&self, prefix: &str, resource_version: u64) -> Result<Watch> {
+        let mut create = json!({
+            "key": encode(prefix.as_bytes()),
+            "range_end": encode(&prefix_end(prefix)),
+            "prev_kv": true,
+        });
+        if resource_version > 0 {
+            create["start_revision"] = json!((resource_version + 1).to_string());
+        }
+        // the gateway streams one JSON response per line for as long as the watch lasts
+        let stream = self.post("/v3/watch", &json!({ "create_request": create }), None)?;
+
+        let (sender, events) = mpsc::channel();
+        // what the watch logs belongs to the span it was made in
+        let span = tracing::Span::current();
+        thread::spawn(move || {
+            let _span = span.entered();
+            for line in BufReader::new(stream).lines() {
+                let Ok(line) = line else {
+                    return;
+                };
+                let Ok(response) = serde_json::from_str::<WatchResponse>(&line) else {
+                    continue;
+                };
+                if let Some(error) = response.error {
+                    warn!("etcd watch failed: {}", error);
+                    return;
+                }
+                let Some(result) = response.result else {
+                    continue;
+                };
+                let compacted = number(&result.compact_revision);
+                if compacted > 0 {
+                    let _ = sender.send(Err(StoreError::Expired { requested: resource_version, oldest: compacted }));
+                    return;
+                }
+                for event in result.events {
+                    let Ok(mut kv) = decode(&event.kv) else {
+                        continue;
+                    };
+                    let kind = match event.kind.as_deref() {
+                        Some("DELETE") => {
+                            // the last value of a deleted key is its previous one
+                            if let Some(Ok(prev)) = event.prev_kv.as_ref().map(decode) {
+                                kv.value = prev.value;
+                            }
+                            EventType::Deleted
+                        }
+                        _ if number(&event.kv.create_revision) == kv.resource_version => EventType::Added,
+                        _ => EventType::Modified,
+                    };
+                    if sender.send(Ok(Event { kind, kv })).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        Ok(Watch { events })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(prefix_end("/registry/"), b"/registry0".to_vec());
+        assert_eq!(prefix_end(""), vec![0]);
+        let kv: RawKeyValue = serde_json::from_value(json!({
+            "key": encode(b"/registry/pods/web"),
+            "value": encode(b"node-1"),
+            "create_revision": "3",
+            "mod_revision": "7",
+        }))
+        .unwrap();
+        let expected = KeyValue { key: "/registry/pods/web".to_string(), value: b"node-1".to_vec(), resource_version: 7 };
+        assert_eq!(decode(&kv).unwrap(), expected);
+    }
+}
@@ -0,0 +1,321 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use anyhow::{Result, anyhow};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use crate::watch::Watchers;
+use crate::{Event, EventType, KeyValue, List, Store, StoreError, Watch};
+
+// The embedded store keeps every key in memory and appends each change to the
+// file as a JSON line, which opening the store replays. Once most of the lines
+// are superseded the file is rewritten with the current keys only. A torn last
+// line, from a crash while appending, is dropped; any other line that doesn't
+// parse fails the open, rather than losing the keys it held at the next
+// compaction. The most recent changes are
+// kept in memory for watches resuming from an earlier resourceVersion.
+
+// changes appended before the log is compacted, at least
+const MIN_COMPACT_ENTRIES: usize = 1000;
+// changes a watch can resume from
+const HISTORY: usize = 1000;
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+enum Entry {
+    Put {
+        key: String,
+        value: String,
+        // 0 in logs written before revisions, replaying numbers them
+        #[serde(default)]
+        revision: u64,
+    },
+    Delete {
+        key: String,
+        #[serde(default)]
+        revision: u64,
+    },
+    // the revision of the store, recorded by compaction as the revision of
+    // its last change may be one of the dropped deletes
+    Revision { revision: u64 },
+}
+
+struct State {
+    // value and resourceVersion by key
+    data: BTreeMap<String, (Vec<u8>, u64)>,
+    revision: u64,
+    history: VecDeque<Event>,
+    log: File,
+    // lines of the log
+    entries: usize,
+}
+
+impl State {
+    fn key_value(&self, key: &str) -> Option<KeyValue> {
+        self.data
+            .get(key)
+            .map(|(value, resource_version)| KeyValue { key: key.to_string(), value: value.clone(), resource_version: *resource_version })
+    }
+}
+
+pub struct FileStore {
+    path: PathBuf,
+    state: Mutex<State>,
+    watchers: Watchers,
+    // held for as long as the store is open
+    _lock: File,
+}
+
+impl FileStore {
+    pub fn open(path: PathBuf) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let lock_path = path.with_extension("lock");
+        let lock = File::create(&lock_path)?;
+        lock.try_lock()
+            .map_err(|_| anyhow!("Store {} is in use by another process", path.display()))?;
+
+        let mut data = BTreeMap::new();
+        let mut revision = 0;
+        let mut entries = 0;
+        if path.exists() {
+            let log = fs::read_to_string(&path)?;
+            for (number, line) in log.split_inclusive('\n').enumerate() {
+                let entry = match serde_json::from_str::<Entry>(line) {
+                    Ok(entry) => entry,
+                    // only the last line is unterminated
+                    Err(_) if !line.ends_with('\n') => {
+                        warn!("Dropping the torn last entry of store {}", path.display());
+                        continue;
+                    }
+                    Err(e) => return Err(anyhow!("Corrupt entry on line {} of store {}: {}", number + 1, path.display(), e)),
+                };
+                let next = |logged: u64| if logged == 0 { revision + 1 } else { logged.max(revision) };
+                match entry {
+                    Entry::Put { key, value, revision: logged } => {
+                        let value = STANDARD.decode(value).map_err(|e| anyhow!("Corrupt value of {}: {}", key, e))?;
+                        revision = next(logged);
+                        data.insert(key, (value, revision));
+                    }
+                    Entry::Delete { key, revision: logged } => {
+                        revision = next(logged);
+                        data.remove(&key);
+                    }
+                    Entry::Revision { revision: logged } => revision = revision.max(logged),
+                }
+                entries += 1;
+            }
+        }
+
+        let log = OpenOptions::new().create(true).append(true).open(&path)?;
+        let state = State { data, revision, history: VecDeque::new(), log, entries };
+        let store = FileStore { state: Mutex::new(state), path, watchers: Watchers::default(), _lock: lock };
+        // start from a log without superseded or torn entries
+        store.compact(&mut store.state.lock().unwrap())?;
+        Ok(store)
+    }
+
+    // write a change with the next revision and tell the watches
+    fn apply(&self, state: &mut State, key: &str, value: Option<&[u8]>) -> Result<u64> {
+        let revision = state.revision + 1;
+        let entry = match value {
+            Some(value) => Entry::Put { key: key.to_string(), value: STANDARD.encode(value), revision },
+            None => Entry::Delete { key: key.to_string(), revision },
+        };
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        state.log.write_all(line.as_bytes())?;
+        state.log.sync_data()?;
+        state.entries += 1;
+        state.revision = revision;
+
+        let event = match value {
+            Some(value) => {
+                let kind = if state.data.contains_key(key) { EventType::Modified } else { EventType::Added };
+                state.data.insert(key.to_string(), (value.to_vec(), revision));
+                Event { kind, kv: KeyValue { key: key.to_string(), value: value.to_vec(), resource_version: revision } }
+            }
+            None => {
+                let (last, _) = state.data.remove(key).unwrap_or_default();
+                Event { kind: EventType::Deleted, kv: KeyValue { key: key.to_string(), value: last, resource_version: revision } }
+            }
+        };
+        self.watchers.notify(&event);
+        state.history.push_back(event);
+        if state.history.len() > HISTORY {
+            state.history.pop_front();
+        }
+
+        if state.entries > MIN_COMPACT_ENTRIES.max(state.data.len() * 2) {
+            self.compact(state)?;
+        }
+        Ok(revision)
+    }
+
+    // rewrite the log with one entry per key
+    fn compact(&self, state: &mut State) -> Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        let mut file = File::create(&tmp)?;
+        for (key, (value, revision)) in &state.data {
+            let entry = Entry::Put { key: key.clone(), value: STANDARD.encode(value), revision: *revision };
+            writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        }
+        writeln!(file, "{}", serde_json::to_string(&Entry::Revision { revision: state.revision })?)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+        state.log = OpenOptions::new().append(true).open(&self.path)?;
+        state.entries = state.data.len() + 1;
+        Ok(())
+    }
+}
+
+impl Store for FileStore {
+    fn get(&self, key: &str) -> Result<Option<KeyValue>> {
+        Ok(self.state.lock().unwrap().key_value(key))
+    }
+
+    fn put(&self, key: &str, value: &[u8]) -> Result<u64> {
+        let mut state = self.state.lock().unwrap();
+        self.apply(&mut state, key, Some(value))
+    }
+
+    fn create(&self, key: &str, value: &[u8]) -> Result<u64> {
+        let mut state = self.state.lock().unwrap();
+        if state.data.contains_key(key) {
+            return Err(StoreError::AlreadyExists(key.to_string()).into());
+        }
+        self.apply(&mut state, key, Some(value))
+    }
+
+    fn update(&self, key: &str, value: &[u8], resource_version: u64) -> Result<u64> {
+        let mut state = self.state.lock().unwrap();
+        match state.data.get(key) {
+            None => Err(StoreError::NotFound(key.to_string()).into()),
+            Some((_, actual)) if *actual != resource_version => {
+                Err(StoreError::Conflict { key: key.to_string(), expected: resource_version, actual: *actual }.into())
+            }
+            Some(_) => self.apply(&mut state, key, Some(value)),
+        }
+    }
+
+    fn delete(&self, key: &str, resource_version: Option<u64>) -> Result<bool> {
+        let mut state = self.state.lock().unwrap();
+        let Some((_, actual)) = state.data.get(key) else {
+            return Ok(false);
+        };
+        if let Some(expected) = resource_version
+            && expected != *actual
+        {
+            return Err(StoreError::Conflict { key: key.to_string(), expected, actual: *actual }.into());
+        }
+        self.apply(&mut state, key, None)?;
+        Ok(true)
+    }
+
+    fn list(&self, prefix: &str) -> Result<List> {
+        let state = self.state.lock().unwrap();
+        let items = state.data
+            .range(prefix.to_string()..)
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .map(|(key, (value, resource_version))| KeyValue { key: key.clone(), value: value.clone(), resource_version: *resource_version })
+            .collect();
+        Ok(List { items, resource_version: state.revision })
+    }
+
+    fn watch(&self, prefix: &str, resource_version: u64) -> Result<Watch> {
+        // the lock keeps changes from slipping in between the replay and the subscription
+        let state = self.state.lock().unwrap();
+        if resource_version == 0 || resource_version >= state.revision {
+            return Ok(self.watchers.add(prefix, std::iter::empty()));
+        }
+        let oldest = state.history.front().map_or(state.revision + 1, |event| event.kv.resource_version);
+        if resource_version + 1 < oldest {
+            return Err(StoreError::Expired { requested: resource_version, oldest: oldest - 1 }.into());
+        }
+        let past = state.history.iter().filter(|event| event.kv.resource_version > resource_version);
+        Ok(self.watchers.add(prefix, past))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::store_error;
+
+    #[test]
+    fn test_file_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.db");
+        let store = FileStore::open(path.clone()).unwrap();
+        assert!(FileStore::open(path.clone()).is_err());
+
+        let watch = store.watch("/registry/nodes/", 0).unwrap();
+        store.put("/registry/nodes/node-1", b"a").unwrap();
+        store.put("/registry/nodes/node-2", b"b").unwrap();
+        store.put("/registry/pods/web", b"c").unwrap();
+        assert!(store.delete("/registry/nodes/node-2", None).unwrap());
+        assert!(!store.delete("/registry/nodes/node-2", None).unwrap());
+        let list = store.list("/registry/").unwrap();
+        let keys: Vec<&str> = list.items.iter().map(|kv| kv.key.as_str()).collect();
+        assert_eq!(keys, vec!["/registry/nodes/node-1", "/registry/pods/web"]);
+        assert_eq!(list.resource_version, 4);
+
+        let next = || watch.next_timeout(Duration::from_secs(1)).unwrap().unwrap();
+        let event = next();
+        assert_eq!((event.kind, event.kv.key.as_str(), event.kv.resource_version), (EventType::Added, "/registry/nodes/node-1", 1));
+        assert_eq!(next().kv.key, "/registry/nodes/node-2");
+        let event = next();
+        assert_eq!((event.kind, event.kv.value.as_slice(), event.kv.resource_version), (EventType::Deleted, &b"b"[..], 4));
+
+        // the changes survive reopening, a torn last line doesn't break it
+        drop(store);
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(b"{\"op\":\"put\",\"ke").unwrap();
+        let store = FileStore::open(path.clone()).unwrap();
+        let node = store.get("/registry/nodes/node-1").unwrap().unwrap();
+        assert_eq!((node.value.as_slice(), node.resource_version), (&b"a"[..], 1));
+        assert_eq!(store.list("/").unwrap().resource_version, 4);
+        assert_eq!(store.put("/registry/pods/db", b"d").unwrap(), 5);
+
+        // but a corrupt entry in the middle does
+        drop(store);
+        let log = fs::read_to_string(&path).unwrap();
+        fs::write(&path, format!("{{\"op\":\"put\"}}\n{}", log)).unwrap();
+        let err = FileStore::open(path).err().unwrap();
+        assert!(err.to_string().starts_with("Corrupt entry on line 1"), "{}", err);
+    }
+
+    #[test]
+    fn test_resource_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileStore::open(dir.path().join("state.db")).unwrap();
+        let created = store.create("/registry/pods/web", b"v1").unwrap();
+        let error = store.create("/registry/pods/web", b"v1").unwrap_err();
+        assert!(matches!(store_error(&error), Some(StoreError::AlreadyExists(_))));
+
+        // of two writers with the same resourceVersion only the first wins
+        let updated = store.update("/registry/pods/web", b"v2", created).unwrap();
+        let error = store.update("/registry/pods/web", b"v3", created).unwrap_err();
+        assert!(matches!(store_error(&error), Some(StoreError::Conflict { actual, .. }) if *actual == updated));
+        let error = store.delete("/registry/pods/web", Some(created)).unwrap_err();
+        assert!(matches!(store_error(&error), Some(StoreError::Conflict { .. })));
+        let error = store.update("/registry/pods/db", b"v1", 1).unwrap_err();
+        assert!(matches!(store_error(&error), Some(StoreError::NotFound(_))));
+
+        // a watch from a list's resourceVersion sees every later change
+        let list = store.list("/registry/pods/").unwrap();
+        store.put("/registry/pods/web", b"v3").unwrap();
+        assert!(store.delete("/registry/pods/web", None).unwrap());
+        let events: Vec<EventType> = store.watch("/registry/pods/", created).unwrap().take(3).map(|e| e.unwrap().kind).collect();
+        assert_eq!(events, vec![EventType::Modified, EventType::Modified, EventType::Deleted]);
+        let mut watch = store.watch("/registry/pods/", list.resource_version).unwrap();
+        assert_eq!(watch.next().unwrap().unwrap().kv.value, b"v3");
+        store.create("/registry/pods/web", b"v4").unwrap();
+        assert_eq!(watch.nth(1).unwrap().unwrap().kind, EventType::Added);
+    }
+}
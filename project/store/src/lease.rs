@@ -0,0 +1,177 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use crate::{Store, StoreError, store_error};
+
+// Leader election, like the Lease objects of Kubernetes: the candidates race to
+// hold the key of a lease under /registry/leases/, the holder renews it before
+// its duration passes and the others take it over once it wasn't renewed for
+// that long. Every write is conditional on the resourceVersion read, so two
+// candidates never both win a round. A candidate measures the expiry on its
+// own clock from when it saw the lease change last, the clocks of the nodes
+// don't have to agree.
+//
+// Every takeover increments the transitions of the lease, which is the fencing
+// token of a leadership. A holder that couldn't renew stops acting once its
+// leadership is past its deadline, before any other candidate can take over,
+// and what it writes can carry the token for the readers to refuse a deposed
+// leader.
+
+pub const LEASES_PREFIX: &str = "/registry/leases/";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Lease {
+    // empty once released
+    pub holder_identity: String,
+    pub lease_duration_seconds: u64,
+    // unix milliseconds, for people to read
+    pub acquire_time: u64,
+    pub renew_time: u64,
+    pub lease_transitions: u64,
+}
+
+// this candidate holds the lease
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Leadership {
+    // the transitions of the lease when it was acquired
+    pub token: u64,
+    // when the leadership ends unless it is renewed first
+    pub deadline: Instant,
+}
+
+impl Leadership {
+    pub fn valid(&self, now: Instant) -> bool {
+        now < self.deadline
+    }
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_millis() as u64).unwrap_or_default()
+}
+
+// whether a write lost the race for the lease to another candidate
+fn lost(error: &anyhow::Error) -> bool {
+    matches!(store_error(error), Some(StoreError::AlreadyExists(_) | StoreError::Conflict { .. } | StoreError::NotFound(_)))
+}
+
+pub fn key(name: &str) -> String {
+    format!("{}{}", LEASES_PREFIX, name)
+}
+
+// the lease of the name as it is in the store
+pub fn get(store: &dyn Store, name: &str) -> Result<Option<Lease>> {
+    store.get(&key(name))?.map(|kv| Ok(serde_json::from_slice(&kv.value)?)).transpose()
+}
+
+pub struct Elector {
+    key: String,
+    identity: String,
+    duration: Duration,
+    // the resourceVersion of the lease seen last and when it was first seen
+    observed: Option<(u64, Instant)>,
+}
+
+impl Elector {
+    pub fn new(name: &str, identity: &str, duration: Duration) -> Self {
+        Elector { key: key(name), identity: identity.to_string(), duration, observed: None }
+    }
+
+    // one round, started at now: acquire the lease when it is free or expired,
+    // renew it when this candidate holds it; the leadership when it does
+    pub fn try_acquire_or_renew(&mut self, store: &dyn Store, now: Instant) -> Result<Option<Leadership>> {
+        let unix = unix_millis();
+        let fresh = |transitions| Lease {
+            holder_identity: self.identity.clone(),
+            lease_duration_seconds: self.duration.as_secs().max(1),
+            acquire_time: unix,
+            renew_time: unix,
+            lease_transitions: transitions,
+        };
+        let (lease, written) = match store.get(&self.key)? {
+            None => {
+                let lease = fresh(0);
+                (lease.clone(), store.create(&self.key, &serde_json::to_vec(&lease)?))
+            }
+            Some(kv) => {
+                let current: Lease = serde_json::from_slice(&kv.value)?;
+                if self.observed.is_none_or(|(version, _)| version != kv.resource_version) {
+                    self.observed = Some((kv.resource_version, now));
+                }
+                let seen = self.observed.map_or(now, |(_, seen)| seen);
+                let expired = current.holder_identity.is_empty()
+                    || now.duration_since(seen) >= Duration::from_secs(current.lease_duration_seconds);
+                let lease = if current.holder_identity == self.identity {
+                    Lease { renew_time: unix, lease_duration_seconds: self.duration.as_secs().max(1), ..current }
+                } else if expired {
+                    fresh(current.lease_transitions + 1)
+                } else {
+                    return Ok(None);
+                };
+                (lease.clone(), store.update(&self.key, &serde_json::to_vec(&lease)?, kv.resource_version))
+            }
+        };
+        match written {
+            Ok(version) => {
+                self.observed = Some((version, now));
+                Ok(Some(Leadership { token: lease.lease_transitions, deadline: now + self.duration }))
+            }
+            Err(e) if lost(&e) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    // give the lease up, so that another candidate takes over without waiting
+    // for it to expire
+    pub fn release(&mut self, store: &dyn Store) -> Result<()> {
+        let Some(kv) = store.get(&self.key)? else {
+            return Ok(());
+        };
+        let current: Lease = serde_json::from_slice(&kv.value)?;
+        if current.holder_identity != self.identity {
+            return Ok(());
+        }
+        let released = Lease { holder_identity: String::new(), renew_time: unix_millis(), ..current };
+        match store.update(&self.key, &serde_json::to_vec(&released)?, kv.resource_version) {
+            Ok(_) => Ok(()),
+            Err(e) if lost(&e) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::FileStore;
+
+    #[test]
+    fn test_election() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FileStore::open(dir.path().join("state.db")).unwrap();
+        let duration = Duration::from_secs(15);
+        let mut a = Elector::new("controllers", "node-a", duration);
+        let mut b = Elector::new("controllers", "node-b", duration);
+        let start = Instant::now();
+
+        let leadership = a.try_acquire_or_renew(&store, start).unwrap().unwrap();
+        assert_eq!(leadership.token, 0);
+        assert!(leadership.valid(start) && !leadership.valid(start + duration));
+        assert_eq!(b.try_acquire_or_renew(&store, start).unwrap(), None);
+        // renewed, b sees the lease change and waits again
+        assert!(a.try_acquire_or_renew(&store, start + duration / 2).unwrap().is_some());
+        assert_eq!(b.try_acquire_or_renew(&store, start + duration / 2).unwrap(), None);
+        assert_eq!(b.try_acquire_or_renew(&store, start + duration).unwrap(), None);
+
+        // a stopped renewing, b takes over with the next token
+        let later = start + duration / 2 + duration;
+        let leadership = b.try_acquire_or_renew(&store, later).unwrap().unwrap();
+        assert_eq!(leadership.token, 1);
+        assert_eq!(get(&store, "controllers").unwrap().unwrap().holder_identity, "node-b");
+        assert_eq!(a.try_acquire_or_renew(&store, later).unwrap(), None);
+
+        // released, a takes over right away
+        b.release(&store).unwrap();
+        assert_eq!(a.try_acquire_or_renew(&store, later).unwrap().unwrap().token, 2);
+    }
+}
@@ -0,0 +1,170 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::Duration;
+use anyhow::{Result, anyhow};
+
+pub mod etcd;
+pub mod file;
+pub mod lease;
+mod watch;
+
+// Cluster state shared by rks, `rkl daemon` and the CLI: a key value store with
+// prefix listing and watches. Keys are paths like /registry/nodes/<name>, values
+// are opaque bytes. There are two backends:
+//   file   an embedded store, a log of the changes in a single file; only one
+//          process can open it at a time, a second open fails while the
+//          first holds its lock, so rks and `rkl daemon` can't share a file
+//          store and a cluster of several such processes needs etcd
+//   etcd   an etcd v3 cluster, through its JSON gRPC gateway
+// open picks one by url.
+//
+// Every change gets the next revision of the store, the resourceVersion of the
+// key from then on. Updates and deletes can be made conditional on it, so that
+// two writers never silently overwrite each other, and a watch resumes from the
+// resourceVersion of a list so that a controller sees every change after it.
+// Leases on top of that elect a leader among processes, see lease.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyValue {
+    pub key: String,
+    pub value: Vec<u8>,
+    // revision of the last change of the key
+    pub resource_version: u64,
+}
+
+// the keys of a prefix at one revision of the store
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct List {
+    pub items: Vec<KeyValue>,
+    // watch from here to see every later change
+    pub resource_version: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventType {
+    Added,
+    Modified,
+    Deleted,
+}
+
+impl fmt::Display for EventType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EventType::Added => write!(f, "ADDED"),
+            EventType::Modified => write!(f, "MODIFIED"),
+            EventType::Deleted => write!(f, "DELETED"),
+        }
+    }
+}
+
+// a change of a key; a deleted key comes with its last value and the revision
+// of the delete
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub kind: EventType,
+    pub kv: KeyValue,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoreError {
+    AlreadyExists(String),
+    NotFound(String),
+    // the key changed since the resourceVersion the writer had
+    Conflict { key: String, expected: u64, actual: u64 },
+    // the changes after the requested revision are no longer kept
+    Expired { requested: u64, oldest: u64 },
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::AlreadyExists(key) => write!(f, "{} already exists", key),
+            StoreError::NotFound(key) => write!(f, "{} not found", key),
+            StoreError::Conflict { key, expected, actual } => write!(
+                f,
+                "{} was modified: resourceVersion is {}, not {}; read it again and retry",
+                key, actual, expected
+            ),
+            StoreError::Expired { requested, oldest } => write!(
+                f,
+                "too old resource version: {} (the oldest available is {}), list again",
+                requested, oldest
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+// the StoreError behind an error of a store, if any
+pub fn store_error(error: &anyhow::Error) -> Option<&StoreError> {
+    error.downcast_ref()
+}
+
+pub trait Store: Send + Sync {
+    fn get(&self, key: &str) -> Result<Option<KeyValue>>;
+    // set the value whatever it was, returns the new resourceVersion
+    fn put(&self, key: &str, value: &[u8]) -> Result<u64>;
+    // set the value of a key that doesn't exist yet
+    fn create(&self, key: &str, value: &[u8]) -> Result<u64>;
+    // set the value if the key is still at resource_version
+    fn update(&self, key: &str, value: &[u8], resource_version: u64) -> Result<u64>;
+    // remove the key, only if it is still at resource_version when given;
+    // whether the key existed
+    fn delete(&self, key: &str, resource_version: Option<u64>) -> Result<bool>;
+    // every key with the prefix, in key order
+    fn list(&self, prefix: &str) -> Result<List>;
+    // the changes of the keys with the prefix after resource_version, 0 for
+    // the changes from now on
+    fn watch(&self, prefix: &str, resource_version: u64) -> Result<Watch>;
+}
+
+// a stream of changes in revision order, ends when the store is dropped or the
+// connection is lost
+pub struct Watch {
+    events: Receiver<Result<Event, StoreError>>,
+}
+
+impl Watch {
+    // the next change, None when the timeout passed first
+    pub fn next_timeout(&self, timeout: Duration) -> Result<Option<Event>> {
+        match self.events.recv_timeout(timeout) {
+            Ok(event) => Ok(Some(event?)),
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(RecvTimeoutError::Disconnected) => Err(anyhow!("watch ended")),
+        }
+    }
+}
+
+impl Iterator for Watch {
+    type Item = Result<Event, StoreError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.events.recv().ok()
+    }
+}
+
+// open a store by url: etcd://host:port[,host:port...] (https with etcds://) or
+// a file path, optionally as file://<path>
+pub fn open(url: &str) -> Result<Box<dyn Store>> {
+    if let Some(endpoints) = url.strip_prefix("etcd://") {
+        return Ok(Box::new(etcd::EtcdStore::new(endpoints_of("http", endpoints))?));
+    }
+    if let Some(endpoints) = url.strip_prefix("etcds://") {
+        return Ok(Box::new(etcd::EtcdStore::new(endpoints_of("https", endpoints))?));
+    }
+    let path = url.strip_prefix("file://").unwrap_or(url);
+    if path.is_empty() || path.contains("://") {
+        return Err(anyhow!("unsupported store url {}, expected etcd://host:port or a file path", url));
+    }
+    Ok(Box::new(file::FileStore::open(PathBuf::from(path))?))
+}
+
+fn endpoints_of(scheme: &str, endpoints: &str) -> Vec<String> {
+    endpoints
+        .split(',')
+        .filter(|endpoint| !endpoint.is_empty())
+        .map(|endpoint| format!("{}://{}", scheme, endpoint.trim_end_matches('/')))
+        .collect()
+}
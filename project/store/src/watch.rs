@@ -0,0 +1,31 @@
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Sender};
+use crate::{Event, StoreError, Watch};
+
+type Sink = Sender<Result<Event, StoreError>>;
+
+// the watches of a store that notifies them itself, with their prefix
+#[derive(Default)]
+pub struct Watchers {
+    watchers: Mutex<Vec<(String, Sink)>>,
+}
+
+impl Watchers {
+    // a watch that first gets the given past events of its prefix
+    pub fn add<'a>(&self, prefix: &str, past: impl Iterator<Item = &'a Event>) -> Watch {
+        let (sender, events) = mpsc::channel();
+        for event in past.filter(|event| event.kv.key.starts_with(prefix)) {
+            let _ = sender.send(Ok(event.clone()));
+        }
+        self.watchers.lock().unwrap().push((prefix.to_string(), sender));
+        Watch { events }
+    }
+
+    // send the event to every watch of its key, forgetting the dropped ones
+    pub fn notify(&self, event: &Event) {
+        self.watchers
+            .lock()
+            .unwrap()
+            .retain(|(prefix, sender)| !event.kv.key.starts_with(prefix.as_str()) || sender.send(Ok(event.clone())).is_ok());
+    }
+}